@@ -0,0 +1,91 @@
+// Styles a generated document against a generated stylesheet, once matching every rule against
+// every element and once with the selector map, ancestor filter and style sharing cache.
+//
+//     cargo bench --bench selector_matching [rules] [elements]
+//
+// With `--features parallel` the fast path runs in parallel too. Either way it has to come up
+// with exactly the same styles as the serial matching of every rule.
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+use light_browser_engine::css_parser;
+use light_browser_engine::dom;
+use light_browser_engine::media::MediaEnvironment;
+use light_browser_engine::style;
+
+const TAGS: &[&str] = &["div", "p", "span", "a", "li", "ul", "section", "em"];
+
+// A deterministic stand-in for a random number generator.
+fn pseudo_random(seed: usize) -> usize {
+    seed.wrapping_mul(2654435761) % 1000003
+}
+
+fn stylesheet(rules: usize) -> String {
+    let mut source = String::new();
+    for i in 0..rules {
+        let tag = TAGS[pseudo_random(i) % TAGS.len()];
+        let selector = match i % 6 {
+            0 => format!(".c{}", pseudo_random(i) % 500),
+            1 => format!("#id{}", pseudo_random(i) % 2000),
+            2 => format!("{}.c{}", tag, pseudo_random(i) % 500),
+            3 => format!(".c{} {}", pseudo_random(i + 1) % 500, tag),
+            4 => format!(".c{} > .c{}", pseudo_random(i + 1) % 500, pseudo_random(i) % 500),
+            _ => format!("section .c{} {}.c{}", pseudo_random(i + 2) % 500, tag, pseudo_random(i) % 500),
+        };
+        source.push_str(&format!("{} {{ width: {}px; }}\n", selector, i));
+    }
+    source
+}
+
+// A tree about `elements` big, a few levels deep.
+fn document(elements: usize) -> dom::Node {
+    let mut next = 0;
+    build(&mut next, elements, 0)
+}
+
+fn build(next: &mut usize, elements: usize, depth: usize) -> dom::Node {
+    let i = *next;
+    *next += 1;
+    let mut attributes = HashMap::new();
+    attributes.insert("class".to_string(), format!("c{} c{}", pseudo_random(i % 40) % 500, pseudo_random(i % 40 + 7) % 500));
+    //most elements have no id, so the style sharing cache gets a chance
+    if i.is_multiple_of(10) {
+        attributes.insert("id".to_string(), format!("id{}", i));
+    }
+
+    let mut children = vec![];
+    if depth < 6 {
+        for _ in 0..5 {
+            if *next >= elements {
+                break;
+            }
+            children.push(build(next, elements, depth + 1));
+        }
+    }
+    dom::elem(TAGS[pseudo_random(i) % TAGS.len()].to_string(), attributes, children)
+}
+
+fn main() {
+    let args: Vec<usize> = std::env::args().skip(1).filter_map(|arg| arg.parse().ok()).collect();
+    let rules = args.first().copied().unwrap_or(5000);
+    let elements = args.get(1).copied().unwrap_or(20000);
+
+    let stylesheet = css_parser::parse(stylesheet(rules));
+    let root = document(elements);
+    let cascade = style::Cascade::new(&stylesheet, MediaEnvironment::default());
+
+    let start = Instant::now();
+    let linear = style::style_tree_linear(&root, &cascade);
+    let linear_time = start.elapsed();
+
+    let start = Instant::now();
+    let fast = style::style_tree(&root, &cascade);
+    let fast_time = start.elapsed();
+
+    assert_eq!(linear.to_string(), fast.to_string(), "the fast path changed the styles");
+    println!("{} rules, {} elements{}", rules, elements, if cfg!(feature = "parallel") { ", parallel" } else { "" });
+    println!("every rule:    {:>10.1?}", linear_time);
+    println!("fast path:     {:>10.1?}", fast_time);
+    println!("speedup:       {:>9.1}x", linear_time.as_secs_f64() / fast_time.as_secs_f64());
+}
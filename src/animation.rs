@@ -0,0 +1,705 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::css::Color;
+use crate::css::KeyframesRule;
+use crate::css::Unit;
+use crate::css::Value;
+use crate::dom::Node;
+use crate::dom::NodeType;
+use crate::style::Cascade;
+use crate::style::PropertyMap;
+use crate::style::StyledNode;
+
+// A timing function, mapping the progress through an interval to the eased progress.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Easing {
+    CubicBezier(f32, f32, f32, f32),
+    Steps(u32, StepPosition),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum StepPosition {
+    Start,
+    End,
+    None,
+    Both,
+}
+
+impl Easing {
+    pub fn from_value(value: &Value) -> Option<Easing> {
+        let number = |value: &Value| match value {
+            Value::Length(number, Unit::None) => Some(*number),
+            _ => None,
+        };
+        match value {
+            Value::Keyword(keyword) => Some(match &*keyword.to_ascii_lowercase() {
+                "linear" => Easing::CubicBezier(0.0, 0.0, 1.0, 1.0),
+                "ease" => Easing::CubicBezier(0.25, 0.1, 0.25, 1.0),
+                "ease-in" => Easing::CubicBezier(0.42, 0.0, 1.0, 1.0),
+                "ease-out" => Easing::CubicBezier(0.0, 0.0, 0.58, 1.0),
+                "ease-in-out" => Easing::CubicBezier(0.42, 0.0, 0.58, 1.0),
+                "step-start" => Easing::Steps(1, StepPosition::Start),
+                "step-end" => Easing::Steps(1, StepPosition::End),
+                _ => return None,
+            }),
+            Value::Function(name, args) if name == "cubic-bezier" && args.len() == 4 => {
+                let args: Vec<f32> = args.iter().map(number).collect::<Option<_>>()?;
+                //the x coordinates have to stay in [0, 1] so the curve is a function of time
+                if !(0.0..=1.0).contains(&args[0]) || !(0.0..=1.0).contains(&args[2]) {
+                    return None;
+                }
+                Some(Easing::CubicBezier(args[0], args[1], args[2], args[3]))
+            },
+            Value::Function(name, args) if name == "steps" && (1..=2).contains(&args.len()) => {
+                let steps = number(&args[0]).filter(|steps| *steps >= 1.0 && steps.fract() == 0.0)? as u32;
+                let position = match args.get(1) {
+                    None => StepPosition::End,
+                    Some(Value::Keyword(keyword)) => match &*keyword.to_ascii_lowercase() {
+                        "jump-start" | "start" => StepPosition::Start,
+                        "jump-end" | "end" => StepPosition::End,
+                        "jump-none" => StepPosition::None,
+                        "jump-both" => StepPosition::Both,
+                        _ => return None,
+                    },
+                    Some(_) => return None,
+                };
+                if position == StepPosition::None && steps < 2 {
+                    return None;
+                }
+                Some(Easing::Steps(steps, position))
+            },
+            _ => None,
+        }
+    }
+
+    pub fn apply(&self, progress: f32) -> f32 {
+        match *self {
+            Easing::CubicBezier(x1, y1, x2, y2) => {
+                if progress <= 0.0 || progress >= 1.0 {
+                    return progress;
+                }
+                let t = solve_bezier(x1, x2, progress);
+                bezier(y1, y2, t)
+            },
+            Easing::Steps(steps, position) => {
+                let jumps = match position {
+                    StepPosition::Start | StepPosition::End => steps,
+                    StepPosition::None => steps - 1,
+                    StepPosition::Both => steps + 1,
+                } as f32;
+                let mut step = (progress * steps as f32).floor();
+                if position == StepPosition::Start || position == StepPosition::Both {
+                    step += 1.0;
+                }
+                if progress >= 0.0 && step < 0.0 {
+                    step = 0.0;
+                }
+                step.min(jumps) / jumps
+            },
+        }
+    }
+}
+
+// One coordinate of a cubic bezier from (0, 0) to (1, 1) at parameter t.
+fn bezier(p1: f32, p2: f32, t: f32) -> f32 {
+    let u = 1.0 - t;
+    3.0 * u * u * t * p1 + 3.0 * u * t * t * p2 + t * t * t
+}
+
+// The parameter t at which the curve's x coordinate is `x`. Newton's method usually gets
+// there in a few steps, bisection catches the flat spots where it doesn't.
+fn solve_bezier(x1: f32, x2: f32, x: f32) -> f32 {
+    let mut t = x;
+    for _ in 0..8 {
+        let error = bezier(x1, x2, t) - x;
+        if error.abs() < 1e-6 {
+            return t;
+        }
+        let u = 1.0 - t;
+        let slope = 3.0 * u * u * x1 + 6.0 * u * t * (x2 - x1) + 3.0 * t * t * (1.0 - x2);
+        if slope.abs() < 1e-6 {
+            break;
+        }
+        t -= error / slope;
+    }
+
+    let (mut low, mut high) = (0.0, 1.0);
+    t = x;
+    for _ in 0..32 {
+        let value = bezier(x1, x2, t);
+        if (value - x).abs() < 1e-6 {
+            break;
+        }
+        if value < x {
+            low = t;
+        } else {
+            high = t;
+        }
+        t = (low + high) / 2.0;
+    }
+    t
+}
+
+// The value `progress` of the way from `from` to `to`, or None if the two can't be
+// interpolated and the value has to flip from one to the other instead.
+pub fn interpolate(from: &Value, to: &Value, progress: f32) -> Option<Value> {
+    let lerp = |a: f32, b: f32| a + (b - a) * progress;
+    Some(match (from, to) {
+        (Value::Length(a, unit_a), Value::Length(b, unit_b)) => {
+            //a unitless zero is a length in any unit
+            let unit = match (unit_a, unit_b) {
+                (a, b) if a == b => *a,
+                (Unit::None, unit) if *a == 0.0 => *unit,
+                (unit, Unit::None) if *b == 0.0 => *unit,
+                _ => return None,
+            };
+            Value::Length(lerp(*a, *b), unit)
+        },
+        (Value::Percentage(a), Value::Percentage(b)) => Value::Percentage(lerp(*a, *b)),
+        (Value::Time(a), Value::Time(b)) => Value::Time(lerp(*a, *b)),
+        (Value::Angle(a), Value::Angle(b)) => Value::Angle(lerp(*a, *b)),
+        (Value::Function(name_a, args_a), Value::Function(name_b, args_b)) if name_a == name_b && args_a.len() == args_b.len() => {
+            Value::Function(name_a.clone(), interpolate_all(args_a, args_b, progress)?)
+        },
+        (Value::List(a), Value::List(b)) if a.len() == b.len() => Value::List(interpolate_all(a, b, progress)?),
+        (Value::CommaList(a), Value::CommaList(b)) if a.len() == b.len() => Value::CommaList(interpolate_all(a, b, progress)?),
+        //`transform: none` animates like the identity version of the other side's functions
+        (Value::Keyword(keyword), transform) if keyword == "none" && identity_transform(transform).is_some() => {
+            return interpolate(&identity_transform(transform)?, transform, progress);
+        },
+        (transform, Value::Keyword(keyword)) if keyword == "none" && identity_transform(transform).is_some() => {
+            return interpolate(transform, &identity_transform(transform)?, progress);
+        },
+        (a, b) => Value::Color(interpolate_color(&to_color(a)?, &to_color(b)?, progress)),
+    })
+}
+
+fn interpolate_all(from: &[Value], to: &[Value], progress: f32) -> Option<Vec<Value>> {
+    from.iter().zip(to).map(|(from, to)| interpolate(from, to, progress)).collect()
+}
+
+fn to_color(value: &Value) -> Option<Color> {
+    match value {
+        Value::Color(color) => Some(color.clone()),
+        Value::Keyword(keyword) => Color::from_name(keyword),
+        _ => None,
+    }
+}
+
+// Colors are interpolated premultiplied by their alpha, so fading from a transparent color
+// doesn't drag its (invisible) rgb along.
+fn interpolate_color(from: &Color, to: &Color, progress: f32) -> Color {
+    let lerp = |a: f32, b: f32| a + (b - a) * progress;
+    let (alpha_a, alpha_b) = (from.a as f32 / 255.0, to.a as f32 / 255.0);
+    let alpha = lerp(alpha_a, alpha_b);
+    let channel = |a: u8, b: u8| {
+        if alpha <= 0.0 {
+            return 0;
+        }
+        (lerp(a as f32 * alpha_a, b as f32 * alpha_b) / alpha).round().clamp(0.0, 255.0) as u8
+    };
+    Color {
+        r: channel(from.r, to.r),
+        g: channel(from.g, to.g),
+        b: channel(from.b, to.b),
+        a: (alpha * 255.0).round().clamp(0.0, 255.0) as u8,
+    }
+}
+
+// The transform that does nothing, with the same functions as `transform`.
+fn identity_transform(transform: &Value) -> Option<Value> {
+    match transform {
+        Value::Function(name, args) => {
+            let identity = if name.starts_with("scale") { 1.0 } else { 0.0 };
+            let args = args.iter().map(|arg| match arg {
+                Value::Length(_, Unit::None) => Value::Length(identity, Unit::None),
+                Value::Length(_, unit) => Value::Length(0.0, *unit),
+                Value::Percentage(_) => Value::Percentage(0.0),
+                Value::Angle(_) => Value::Angle(0.0),
+                arg => arg.clone(),
+            }).collect();
+            if name == "matrix" {
+                let numbers = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+                return Some(Value::Function(name.clone(), numbers.iter().map(|n| Value::Length(*n, Unit::None)).collect()));
+            }
+            Some(Value::Function(name.clone(), args))
+        },
+        Value::List(functions) => Some(Value::List(functions.iter().map(identity_transform).collect::<Option<_>>()?)),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Direction {
+    Normal,
+    Reverse,
+    Alternate,
+    AlternateReverse,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum FillMode {
+    None,
+    Forwards,
+    Backwards,
+    Both,
+}
+
+// The animation-* longhands for one animation, out of the comma separated lists.
+#[derive(Clone, Debug)]
+struct AnimationSpec {
+    name: String,
+    duration: f32,
+    easing: Easing,
+    delay: f32,
+    iterations: f32,
+    direction: Direction,
+    fill: FillMode,
+    paused: bool,
+}
+
+impl AnimationSpec {
+    // One spec per animation-name. The other lists are repeated as needed to match its length.
+    fn from_style(values: &PropertyMap) -> Vec<AnimationSpec> {
+        let names = match values.get("animation-name") {
+            Some(names) => names.groups(),
+            None => return vec![],
+        };
+        let nth = |property: &str, i: usize| {
+            values.get(property).map(|value| value.groups()).filter(|groups| !groups.is_empty()).map(|groups| &groups[i % groups.len()])
+        };
+        let keyword = |property: &str, i: usize| match nth(property, i) {
+            Some(Value::Keyword(keyword)) => keyword.to_ascii_lowercase(),
+            _ => String::new(),
+        };
+        let time = |property: &str, i: usize| match nth(property, i) {
+            Some(Value::Time(ms)) => *ms,
+            _ => 0.0,
+        };
+
+        names.iter().enumerate().filter_map(|(i, name)| {
+            let name = match name {
+                Value::Keyword(name) if name != "none" => name.clone(),
+                Value::Str(name) => name.clone(),
+                _ => return None,
+            };
+            Some(AnimationSpec {
+                name,
+                duration: time("animation-duration", i).max(0.0),
+                easing: nth("animation-timing-function", i).and_then(Easing::from_value)
+                    .unwrap_or(Easing::CubicBezier(0.25, 0.1, 0.25, 1.0)),
+                delay: time("animation-delay", i),
+                iterations: match nth("animation-iteration-count", i) {
+                    Some(Value::Length(count, Unit::None)) => count.max(0.0),
+                    Some(Value::Keyword(keyword)) if keyword == "infinite" => f32::INFINITY,
+                    _ => 1.0,
+                },
+                direction: match &*keyword("animation-direction", i) {
+                    "reverse" => Direction::Reverse,
+                    "alternate" => Direction::Alternate,
+                    "alternate-reverse" => Direction::AlternateReverse,
+                    _ => Direction::Normal,
+                },
+                fill: match &*keyword("animation-fill-mode", i) {
+                    "forwards" => FillMode::Forwards,
+                    "backwards" => FillMode::Backwards,
+                    "both" => FillMode::Both,
+                    _ => FillMode::None,
+                },
+                paused: keyword("animation-play-state", i) == "paused",
+            })
+        }).collect()
+    }
+
+    // How far through the keyframes the animation is `time` ms after it started, or None if
+    // it doesn't affect the style at that time.
+    fn progress(&self, time: f32) -> Option<f32> {
+        let time = time - self.delay;
+        let active_duration = if self.iterations == 0.0 { 0.0 } else { self.duration * self.iterations };
+
+        let (iteration, progress) = if time < 0.0 {
+            if self.fill != FillMode::Backwards && self.fill != FillMode::Both {
+                return None;
+            }
+            (0.0, 0.0)
+        } else if time >= active_duration {
+            if self.fill != FillMode::Forwards && self.fill != FillMode::Both {
+                return None;
+            }
+            //stop at the end of the last iteration, which may be part way through one
+            let iterations = self.iterations.min(f32::MAX);
+            if iterations == 0.0 {
+                (0.0, 0.0)
+            } else if iterations.fract() == 0.0 {
+                (iterations - 1.0, 1.0)
+            } else {
+                (iterations.floor(), iterations.fract())
+            }
+        } else {
+            let elapsed = time / self.duration;
+            (elapsed.floor(), elapsed.fract())
+        };
+
+        let reversed = match self.direction {
+            Direction::Normal => false,
+            Direction::Reverse => true,
+            Direction::Alternate => iteration % 2.0 == 1.0,
+            Direction::AlternateReverse => iteration % 2.0 == 0.0,
+        };
+        Some(if reversed { 1.0 - progress } else { progress })
+    }
+}
+
+// A @keyframes block resolved to one offset, with its declarations by property.
+#[derive(Debug)]
+struct Keyframe {
+    offset: f32,
+    easing: Option<Easing>,
+    values: PropertyMap,
+}
+
+// Flattens a @keyframes rule into keyframes sorted by offset. Blocks for the same offset are
+// merged, later declarations winning.
+fn resolve_keyframes(rule: &KeyframesRule) -> Vec<Keyframe> {
+    let mut keyframes: Vec<Keyframe> = vec![];
+    for block in &rule.keyframes {
+        for &offset in &block.offsets {
+            let index = match keyframes.iter().position(|keyframe| keyframe.offset == offset) {
+                Some(index) => index,
+                None => {
+                    keyframes.push(Keyframe {offset, easing: None, values: HashMap::new()});
+                    keyframes.len() - 1
+                },
+            };
+            let keyframe = &mut keyframes[index];
+            for declaration in &block.declarations {
+                if declaration.name == "animation-timing-function" {
+                    keyframe.easing = Easing::from_value(&declaration.value);
+                } else {
+                    keyframe.values.insert(declaration.name.clone(), declaration.value.clone());
+                }
+            }
+        }
+    }
+    keyframes.sort_by(|a, b| a.offset.total_cmp(&b.offset));
+    keyframes
+}
+
+struct RunningAnimation {
+    spec: AnimationSpec,
+    keyframes: Vec<Keyframe>,
+    // timeline time the animation started at, moved forward by the time spent paused
+    start_time: f32,
+    paused_at: Option<f32>,
+}
+
+impl RunningAnimation {
+    // The value of `property` at the animation's current point, None if the animation
+    // doesn't animate it or isn't in effect.
+    fn value(&self, now: f32, property: &str, underlying: Option<&Value>) -> Option<Value> {
+        let progress = self.spec.progress(self.paused_at.unwrap_or(now) - self.start_time)?;
+
+        let mut frames: Vec<(f32, &Value, Option<&Easing>)> = self.keyframes.iter()
+            .filter_map(|keyframe| keyframe.values.get(property).map(|value| (keyframe.offset, value, keyframe.easing.as_ref())))
+            .collect();
+        //missing `from` and `to` keyframes are the value the element would have without the animation
+        if let Some(underlying) = underlying {
+            if frames.first().is_none_or(|frame| frame.0 > 0.0) {
+                frames.insert(0, (0.0, underlying, None));
+            }
+            if frames.last().is_none_or(|frame| frame.0 < 1.0) {
+                frames.push((1.0, underlying, None));
+            }
+        }
+        let first = frames.first()?;
+        if progress < first.0 {
+            return Some(first.1.clone());
+        }
+
+        let index = frames.iter().rposition(|frame| frame.0 <= progress)?;
+        let (from_offset, from, easing) = frames[index];
+        let (to_offset, to, _) = match frames.get(index + 1) {
+            Some(frame) => *frame,
+            None => return Some(from.clone()),
+        };
+        let local = (progress - from_offset) / (to_offset - from_offset);
+        let eased = easing.unwrap_or(&self.spec.easing).apply(local);
+        interpolate(from, to, eased).or_else(|| Some(if eased < 0.5 { from.clone() } else { to.clone() }))
+    }
+}
+
+struct Transition {
+    property: String,
+    from: Value,
+    to: Value,
+    start_time: f32,
+    duration: f32,
+    delay: f32,
+    easing: Easing,
+}
+
+impl Transition {
+    fn end_time(&self) -> f32 {
+        self.start_time + self.delay + self.duration
+    }
+
+    fn value(&self, now: f32) -> Value {
+        let progress = ((now - self.start_time - self.delay) / self.duration).clamp(0.0, 1.0);
+        interpolate(&self.from, &self.to, self.easing.apply(progress)).unwrap_or_else(|| self.to.clone())
+    }
+}
+
+#[derive(Default)]
+struct ElementState {
+    animations: Vec<RunningAnimation>,
+    transitions: Vec<Transition>,
+    // the element's style at the last update, transitions start when a value changes from it
+    before_change: Arc<PropertyMap>,
+}
+
+// The animations and transitions running on a document, against a clock the host advances
+// by hand. Call `update` with every new style tree and `sample` to get the style tree as it
+// looks at the current time.
+#[derive(Default)]
+pub struct AnimationTimeline {
+    // in milliseconds
+    now: f32,
+    elements: HashMap<*const Node, ElementState>,
+}
+
+impl AnimationTimeline {
+    pub fn new() -> AnimationTimeline {
+        Default::default()
+    }
+
+    pub fn current_time(&self) -> f32 {
+        self.now
+    }
+
+    pub fn advance(&mut self, ms: f32) {
+        self.now += ms;
+        let now = self.now;
+        for state in self.elements.values_mut() {
+            state.transitions.retain(|transition| transition.end_time() > now);
+        }
+    }
+
+    // Starts, updates and cancels animations and transitions to match a freshly cascaded
+    // style tree.
+    pub fn update(&mut self, root: &StyledNode, cascade: &Cascade) {
+        let mut seen = vec![];
+        self.update_node(root, cascade, &mut seen);
+        //elements that are gone take their animations with them
+        self.elements.retain(|node, _| seen.contains(node));
+    }
+
+    fn update_node(&mut self, styled: &StyledNode, cascade: &Cascade, seen: &mut Vec<*const Node>) {
+        for child in &styled.children {
+            self.update_node(child, cascade, seen);
+        }
+        if !matches!(styled.node.node_type, NodeType::Element(_)) {
+            return;
+        }
+
+        let now = self.now;
+        let key: *const Node = styled.node;
+        seen.push(key);
+        let state = self.elements.entry(key).or_default();
+        let values = &styled.specified_values;
+
+        //animations are matched up with the ones already running by name, so an animation
+        //keeps its start time for as long as its name stays in the list
+        let mut previous = std::mem::take(&mut state.animations);
+        for spec in AnimationSpec::from_style(values) {
+            let keyframes = match cascade.keyframes(&spec.name) {
+                Some(rule) => resolve_keyframes(rule),
+                None => continue,
+            };
+            let mut animation = match previous.iter().position(|animation| animation.spec.name == spec.name) {
+                Some(index) => previous.remove(index),
+                None => RunningAnimation {spec: spec.clone(), keyframes: vec![], start_time: now, paused_at: None},
+            };
+            match (spec.paused, animation.paused_at) {
+                (true, None) => animation.paused_at = Some(now),
+                (false, Some(paused_at)) => {
+                    animation.start_time += now - paused_at;
+                    animation.paused_at = None;
+                },
+                _ => {},
+            }
+            animation.spec = spec;
+            animation.keyframes = keyframes;
+            state.animations.push(animation);
+        }
+
+        //values that changed since the last update transition from whatever is showing now
+        for (property, duration, delay, easing) in transitions_of(values, &state.before_change) {
+            let to = match values.get(&property) {
+                Some(to) => to.clone(),
+                None => continue,
+            };
+            let running = state.transitions.iter().position(|transition| transition.property == property);
+            if let Some(index) = running {
+                if state.transitions[index].to == to {
+                    continue;
+                }
+            } else if state.before_change.get(&property) == Some(&to) {
+                continue;
+            }
+
+            let from = match running {
+                Some(index) => state.transitions.remove(index).value(now),
+                None => match state.before_change.get(&property) {
+                    Some(from) => from.clone(),
+                    None => continue,
+                },
+            };
+            if duration > 0.0 && interpolate(&from, &to, 0.5).is_some() {
+                state.transitions.push(Transition {property, from, to, start_time: now, duration, delay, easing});
+            }
+        }
+
+        state.before_change = values.clone();
+    }
+
+    // The style tree as it looks at the current time. Transitions are applied on top of
+    // animations, which are applied on top of the cascaded values.
+    pub fn sample<'a>(&self, styled: &StyledNode<'a>) -> StyledNode<'a> {
+        let mut specified_values = (*styled.specified_values).clone();
+        if let Some(state) = self.elements.get(&(styled.node as *const Node)) {
+            for animation in &state.animations {
+                let properties: Vec<&String> = animation.keyframes.iter().flat_map(|keyframe| keyframe.values.keys()).collect();
+                for property in properties {
+                    if let Some(value) = animation.value(self.now, property, styled.specified_values.get(property)) {
+                        specified_values.insert(property.clone(), value);
+                    }
+                }
+            }
+            for transition in &state.transitions {
+                specified_values.insert(transition.property.clone(), transition.value(self.now));
+            }
+        }
+
+        //pseudo-elements aren't animated, they share their element's node
+        StyledNode {
+            node: styled.node,
+            pseudo_element: styled.pseudo_element,
+            specified_values: Arc::new(specified_values),
+            children: styled.children.iter().map(|child| self.sample(child)).collect(),
+            pseudo_elements: styled.pseudo_elements.iter().map(|pseudo_element| StyledNode {
+                specified_values: pseudo_element.specified_values.clone(),
+                children: vec![],
+                pseudo_elements: vec![],
+                ..*pseudo_element
+            }).collect(),
+        }
+    }
+}
+
+// (property, duration, delay, easing) for every property the transition-* longhands ask to
+// transition. `all` stands for every property in either style.
+fn transitions_of(values: &PropertyMap, before: &PropertyMap) -> Vec<(String, f32, f32, Easing)> {
+    let properties = match values.get("transition-property") {
+        Some(properties) => properties.groups(),
+        None => return vec![],
+    };
+    let nth = |property: &str, i: usize| {
+        values.get(property).map(|value| value.groups()).filter(|groups| !groups.is_empty()).map(|groups| groups[i % groups.len()].clone())
+    };
+    let time = |property: &str, i: usize| match nth(property, i) {
+        Some(Value::Time(ms)) => ms,
+        _ => 0.0,
+    };
+
+    let mut transitions = vec![];
+    for (i, property) in properties.iter().enumerate() {
+        let duration = time("transition-duration", i);
+        let delay = time("transition-delay", i);
+        let easing = nth("transition-timing-function", i).and_then(|value| Easing::from_value(&value))
+            .unwrap_or(Easing::CubicBezier(0.25, 0.1, 0.25, 1.0));
+        let names: Vec<String> = match property {
+            Value::Keyword(name) if name == "none" => vec![],
+            Value::Keyword(name) if name == "all" => values.keys().chain(before.keys())
+                .filter(|name| !name.starts_with("transition") && !name.starts_with("animation"))
+                .cloned().collect(),
+            Value::Keyword(name) => vec![name.clone()],
+            _ => vec![],
+        };
+        for name in names {
+            //a later entry for the same property wins
+            transitions.retain(|(other, _, _, _): &(String, f32, f32, Easing)| *other != name);
+            transitions.push((name, duration, delay, easing.clone()));
+        }
+    }
+    transitions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::css_parser;
+    use crate::html_parser;
+    use crate::media::MediaEnvironment;
+    use crate::style;
+
+    // The computed values of `name` on the root element at each of `times`, in ms, advancing
+    // the timeline to them in turn.
+    fn snapshots(timeline: &mut AnimationTimeline, root: &StyledNode, name: &str, times: &[f32]) -> Vec<Value> {
+        times.iter().map(|&time| {
+            timeline.advance(time - timeline.current_time());
+            let sampled = timeline.sample(root);
+            style::computed_values(&sampled, None, None)[name].clone()
+        }).collect()
+    }
+
+    #[test]
+    fn keyframes_interpolate_over_time() {
+        let root = html_parser::parse("<div></div>".to_string());
+        let stylesheet = css_parser::parse("@keyframes slide { from { left: 0px; opacity: 0 } to { left: 100px; opacity: 1 } }
+            div { display: block; animation: slide 500ms linear both }".to_string());
+        let cascade = Cascade::new(&stylesheet, MediaEnvironment::default());
+        let styled = style::style_tree(&root, &cascade);
+        let mut timeline = AnimationTimeline::new();
+        timeline.update(&styled, &cascade);
+
+        assert_eq!(snapshots(&mut timeline, &styled, "left", &[0.0, 250.0, 500.0]),
+            [Value::Length(0.0, Unit::Px), Value::Length(50.0, Unit::Px), Value::Length(100.0, Unit::Px)]);
+        //filling forwards, the last keyframe stays
+        assert_eq!(snapshots(&mut timeline, &styled, "opacity", &[750.0]), [Value::Length(1.0, Unit::None)]);
+    }
+
+    #[test]
+    fn timing_function_eases_the_progress() {
+        let root = html_parser::parse("<div></div>".to_string());
+        let stylesheet = css_parser::parse("@keyframes slide { from { left: 0px } to { left: 100px } }
+            div { display: block; animation: slide 500ms ease-out }".to_string());
+        let cascade = Cascade::new(&stylesheet, MediaEnvironment::default());
+        let styled = style::style_tree(&root, &cascade);
+        let mut timeline = AnimationTimeline::new();
+        timeline.update(&styled, &cascade);
+
+        let lefts = snapshots(&mut timeline, &styled, "left", &[0.0, 250.0, 500.0]);
+        assert_eq!(lefts[0], Value::Length(0.0, Unit::Px));
+        //cubic-bezier(0, 0, 0.58, 1) is at about 0.685 halfway through
+        assert!(matches!(lefts[1], Value::Length(left, Unit::Px) if (left - 68.5).abs() < 0.5), "{:?}", lefts[1]);
+        //without a fill mode it's over, back to the cascaded value
+        assert_eq!(lefts[2], Value::Keyword("auto".to_string()));
+    }
+
+    #[test]
+    fn transition_runs_from_the_old_value_to_the_new() {
+        let root = html_parser::parse("<div></div>".to_string());
+        let before = css_parser::parse("div { display: block; width: 100px; transition: width 500ms linear }".to_string());
+        let after = css_parser::parse("div { display: block; width: 200px; transition: width 500ms linear }".to_string());
+        let mut timeline = AnimationTimeline::new();
+        let cascade = Cascade::new(&before, MediaEnvironment::default());
+        timeline.update(&style::style_tree(&root, &cascade), &cascade);
+        let cascade = Cascade::new(&after, MediaEnvironment::default());
+        let styled = style::style_tree(&root, &cascade);
+        timeline.update(&styled, &cascade);
+
+        assert_eq!(snapshots(&mut timeline, &styled, "width", &[0.0, 250.0, 500.0]),
+            [Value::Length(100.0, Unit::Px), Value::Length(150.0, Unit::Px), Value::Length(200.0, Unit::Px)]);
+    }
+}
@@ -0,0 +1,637 @@
+// The Unicode Bidirectional Algorithm (UAX #9): the embedding level of each character of a
+// paragraph, which says which way it runs, and the order the characters of a line are shown
+// in. Characters are sorted into the bidi classes that matter for the scripts we can show;
+// unassigned code points in the Hebrew and Arabic blocks get the class of their block.
+
+pub type Level = u8;
+
+// Deeper embeddings than this are ignored, BD2.
+const MAX_DEPTH: Level = 125;
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Class {
+    // strong: left to right, right to left, Arabic letter
+    L, R, AL,
+    // weak: European number, its separator and terminator, Arabic number, common number
+    // separator, nonspacing mark, boundary neutral
+    EN, ES, ET, AN, CS, NSM, BN,
+    // neutral: paragraph separator, segment separator, white space, other neutral
+    B, S, WS, ON,
+    // explicit formatting: embeddings, overrides, the pop that ends them, isolates and the
+    // pop that ends those
+    LRE, LRO, RLE, RLO, PDF, LRI, RLI, FSI, PDI,
+}
+
+use Class::*;
+
+fn class(c: char) -> Class {
+    match c {
+        '\u{202A}' => LRE,
+        '\u{202B}' => RLE,
+        '\u{202C}' => PDF,
+        '\u{202D}' => LRO,
+        '\u{202E}' => RLO,
+        '\u{2066}' => LRI,
+        '\u{2067}' => RLI,
+        '\u{2068}' => FSI,
+        '\u{2069}' => PDI,
+        '\u{200E}' => L,
+        '\u{200F}' => R,
+        '\u{061C}' => AL,
+        '\n' | '\r' | '\u{1C}'..='\u{1E}' | '\u{85}' | '\u{2029}' => B,
+        '\t' | '\u{0B}' | '\u{1F}' => S,
+        '\u{0C}' | ' ' | '\u{1680}' | '\u{2000}'..='\u{200A}' | '\u{2028}' | '\u{205F}' | '\u{3000}' => WS,
+        '\u{00}'..='\u{08}' | '\u{0E}'..='\u{1B}' | '\u{7F}'..='\u{84}' | '\u{86}'..='\u{9F}' | '\u{AD}' | '\u{180E}'
+        | '\u{200B}'..='\u{200D}' | '\u{2060}'..='\u{2065}' | '\u{206A}'..='\u{206F}' | '\u{FEFF}' => BN,
+        '0'..='9' | '\u{B2}' | '\u{B3}' | '\u{B9}' | '\u{6F0}'..='\u{6F9}' | '\u{2070}' | '\u{2074}'..='\u{2079}'
+        | '\u{2080}'..='\u{2089}' | '\u{2488}'..='\u{249B}' | '\u{FF10}'..='\u{FF19}' | '\u{1D7CE}'..='\u{1D7FF}' => EN,
+        '+' | '-' | '\u{207A}' | '\u{207B}' | '\u{208A}' | '\u{208B}' | '\u{2212}' | '\u{FB29}' | '\u{FE62}' | '\u{FE63}'
+        | '\u{FF0B}' | '\u{FF0D}' => ES,
+        '#' | '$' | '%' | '\u{A2}'..='\u{A5}' | '\u{B0}' | '\u{B1}' | '\u{609}' | '\u{60A}' | '\u{66A}' | '\u{9F2}' | '\u{9F3}'
+        | '\u{AF1}' | '\u{BF9}' | '\u{E3F}' | '\u{17DB}' | '\u{2030}'..='\u{2034}' | '\u{20A0}'..='\u{20CF}' | '\u{212E}'
+        | '\u{2213}' | '\u{A838}' | '\u{A839}' | '\u{FE5F}' | '\u{FE69}' | '\u{FE6A}' | '\u{FF03}'..='\u{FF05}'
+        | '\u{FFE0}' | '\u{FFE1}' | '\u{FFE5}' | '\u{FFE6}' => ET,
+        '\u{600}'..='\u{605}' | '\u{660}'..='\u{669}' | '\u{66B}' | '\u{66C}' | '\u{6DD}' | '\u{890}' | '\u{891}' | '\u{8E2}'
+        | '\u{10E60}'..='\u{10E7E}' => AN,
+        ',' | '.' | '/' | ':' | '\u{A0}' | '\u{60C}' | '\u{202F}' | '\u{2044}' | '\u{FE50}' | '\u{FE52}' | '\u{FE55}'
+        | '\u{FF0C}' | '\u{FF0E}' | '\u{FF0F}' | '\u{FF1A}' => CS,
+        '\u{300}'..='\u{36F}' | '\u{483}'..='\u{489}' | '\u{591}'..='\u{5BD}' | '\u{5BF}' | '\u{5C1}' | '\u{5C2}' | '\u{5C4}'
+        | '\u{5C5}' | '\u{5C7}' | '\u{610}'..='\u{61A}' | '\u{64B}'..='\u{65F}' | '\u{670}' | '\u{6D6}'..='\u{6DC}'
+        | '\u{6DF}'..='\u{6E4}' | '\u{6E7}' | '\u{6E8}' | '\u{6EA}'..='\u{6ED}' | '\u{711}' | '\u{730}'..='\u{74A}'
+        | '\u{1AB0}'..='\u{1AFF}' | '\u{1DC0}'..='\u{1DFF}' | '\u{20D0}'..='\u{20F0}' | '\u{FE00}'..='\u{FE0F}'
+        | '\u{FE20}'..='\u{FE2F}' => NSM,
+        '!' | '"' | '&' | '\'' | '(' | ')' | '*' | ';' | '<' | '=' | '>' | '?' | '@' | '[' | '\\' | ']' | '^' | '_' | '`'
+        | '{' | '|' | '}' | '~' | '\u{A1}' | '\u{A6}'..='\u{A9}' | '\u{AB}' | '\u{AC}' | '\u{AE}' | '\u{AF}' | '\u{B4}'
+        | '\u{B6}'..='\u{B8}' | '\u{BB}'..='\u{BF}' | '\u{D7}' | '\u{F7}' | '\u{2B9}' | '\u{2BA}' | '\u{2C2}'..='\u{2CF}'
+        | '\u{2D2}'..='\u{2DF}' | '\u{2E5}'..='\u{2ED}' | '\u{2EF}'..='\u{2FF}' | '\u{374}' | '\u{375}' | '\u{37E}'
+        | '\u{384}' | '\u{385}' | '\u{387}' | '\u{3F6}' | '\u{58A}' | '\u{58D}' | '\u{58E}' | '\u{606}' | '\u{607}'
+        | '\u{60E}' | '\u{60F}' | '\u{6DE}' | '\u{6E9}' | '\u{2010}'..='\u{2027}' | '\u{2035}'..='\u{2043}'
+        | '\u{2045}'..='\u{205E}' | '\u{207C}'..='\u{207E}' | '\u{208C}'..='\u{208E}' | '\u{2190}'..='\u{2487}'
+        | '\u{249C}'..='\u{2BFF}' | '\u{2E00}'..='\u{2E7F}' | '\u{3001}'..='\u{3004}' | '\u{3008}'..='\u{3020}'
+        | '\u{FE10}'..='\u{FE19}' | '\u{FE30}'..='\u{FE4F}' | '\u{FE51}' | '\u{FE54}' | '\u{FE56}'..='\u{FE5E}'
+        | '\u{FE60}' | '\u{FE61}' | '\u{FE64}'..='\u{FE68}' | '\u{FE6B}' | '\u{FF01}' | '\u{FF02}' | '\u{FF06}'..='\u{FF0A}'
+        | '\u{FF1B}'..='\u{FF20}' | '\u{FF3B}'..='\u{FF40}' | '\u{FF5B}'..='\u{FF65}' | '\u{FFFC}' | '\u{FFFD}' => ON,
+        '\u{590}'..='\u{5FF}' | '\u{7C0}'..='\u{85F}' | '\u{FB1D}'..='\u{FB4F}' | '\u{10800}'..='\u{10CFF}'
+        | '\u{1E800}'..='\u{1EC6F}' => R,
+        '\u{600}'..='\u{7BF}' | '\u{860}'..='\u{8FF}' | '\u{FB50}'..='\u{FDCF}' | '\u{FDF0}'..='\u{FDFF}'
+        | '\u{FE70}'..='\u{FEFF}' | '\u{1EC70}'..='\u{1EFFF}' => AL,
+        _ => L,
+    }
+}
+
+// The bracket `c` pairs with and whether it opens, BD14 and BD15.
+fn bracket(c: char) -> Option<(char, bool)> {
+    const PAIRS: &[(char, char)] = &[
+        ('(', ')'), ('[', ']'), ('{', '}'), ('\u{F3A}', '\u{F3B}'), ('\u{F3C}', '\u{F3D}'), ('\u{169B}', '\u{169C}'),
+        ('\u{2045}', '\u{2046}'), ('\u{207D}', '\u{207E}'), ('\u{208D}', '\u{208E}'), ('\u{2329}', '\u{232A}'),
+        ('\u{2768}', '\u{2769}'), ('\u{276A}', '\u{276B}'), ('\u{27E6}', '\u{27E7}'), ('\u{27E8}', '\u{27E9}'),
+        ('\u{3008}', '\u{3009}'), ('\u{300A}', '\u{300B}'), ('\u{300C}', '\u{300D}'), ('\u{300E}', '\u{300F}'),
+        ('\u{3010}', '\u{3011}'), ('\u{3014}', '\u{3015}'), ('\u{FF08}', '\u{FF09}'), ('\u{FF3B}', '\u{FF3D}'),
+        ('\u{FF5B}', '\u{FF5D}'),
+    ];
+    PAIRS.iter().find_map(|&(open, close)| match c {
+        _ if c == open => Some((close, true)),
+        _ if c == close => Some((open, false)),
+        _ => None,
+    })
+}
+
+// The character shown in place of `c` in right to left text, if it's one of the mirrored ones
+// the font doesn't take care of, L4.
+pub fn mirror(c: char) -> Option<char> {
+    match c {
+        '<' => Some('>'),
+        '>' => Some('<'),
+        '\u{AB}' => Some('\u{BB}'),
+        '\u{BB}' => Some('\u{AB}'),
+        '\u{2039}' => Some('\u{203A}'),
+        '\u{203A}' => Some('\u{2039}'),
+        '\u{2264}' => Some('\u{2265}'),
+        '\u{2265}' => Some('\u{2264}'),
+        _ => bracket(c).map(|(other, _)| other),
+    }
+}
+
+fn is_isolate_initiator(class: Class) -> bool {
+    matches!(class, LRI | RLI | FSI)
+}
+
+// Whether X9 removes characters of the class, which then take no part in the rest of the
+// algorithm.
+fn is_removed(class: Class) -> bool {
+    matches!(class, LRE | RLE | LRO | RLO | PDF | BN)
+}
+
+fn is_neutral(class: Class) -> bool {
+    matches!(class, B | S | WS | ON | LRI | RLI | FSI | PDI)
+}
+
+// The direction a strong or number class counts as for N0 and N1, which is R for numbers.
+fn strong_direction(class: Class) -> Option<Class> {
+    match class {
+        L => Some(L),
+        R | AL | EN | AN => Some(R),
+        _ => None,
+    }
+}
+
+fn direction(level: Level) -> Class {
+    if level.is_multiple_of(2) { L } else { R }
+}
+
+// The level of the paragraph from its first strong character outside isolates, P2 and P3.
+// None if it has no strong characters.
+pub fn paragraph_level(text: &str) -> Option<Level> {
+    let classes: Vec<Class> = text.chars().map(class).collect();
+    first_strong(&classes, 0, classes.len())
+}
+
+fn first_strong(classes: &[Class], start: usize, end: usize) -> Option<Level> {
+    let mut isolates = 0;
+    for &class in &classes[start..end] {
+        match class {
+            LRI | RLI | FSI => isolates += 1,
+            PDI if isolates > 0 => isolates -= 1,
+            B if isolates == 0 => return None,
+            L if isolates == 0 => return Some(0),
+            R | AL if isolates == 0 => return Some(1),
+            _ => {},
+        }
+    }
+    None
+}
+
+// The matching PDI of each isolate initiator, BD9.
+fn matching_pdis(classes: &[Class]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; classes.len()];
+    let mut open: Vec<usize> = vec![];
+    for (i, &class) in classes.iter().enumerate() {
+        match class {
+            LRI | RLI | FSI => open.push(i),
+            PDI => {
+                if let Some(initiator) = open.pop() {
+                    matches[initiator] = Some(i);
+                }
+            },
+            B => open.clear(),
+            _ => {},
+        }
+    }
+    matches
+}
+
+// The embedding level of each byte of `text`, at `base_level` if given or else from its
+// content. Paragraph separators end paragraphs, and each one after the first gets its own
+// level the same way. Line-based rule L1 is only applied to segment and paragraph separators
+// and the white space before them; the white space at the end of a line is up to the line
+// breaker.
+pub fn resolve_levels(text: &str, base_level: Option<Level>) -> Vec<Level> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let mut classes: Vec<Class> = chars.iter().map(|&(_, c)| class(c)).collect();
+    let original = classes.clone();
+    let mut levels = vec![0; chars.len()];
+
+    let mut start = 0;
+    while start < chars.len() {
+        let end = classes[start..].iter().position(|&class| class == B).map_or(chars.len(), |i| start + i + 1);
+        let paragraph_level = base_level.unwrap_or_else(|| first_strong(&classes, start, end).unwrap_or(0));
+        resolve_paragraph(&chars[start..end], &mut classes[start..end], &original[start..end], &mut levels[start..end], paragraph_level);
+        start = end;
+    }
+
+    let mut byte_levels = vec![0; text.len()];
+    for (i, &(offset, c)) in chars.iter().enumerate() {
+        byte_levels[offset..offset + c.len_utf8()].fill(levels[i]);
+    }
+    byte_levels
+}
+
+fn resolve_paragraph(chars: &[(usize, char)], classes: &mut [Class], original: &[Class], levels: &mut [Level], paragraph_level: Level) {
+    let matches = matching_pdis(classes);
+    explicit_levels(classes, levels, &matches, paragraph_level);
+
+    //X9 leaves out the embedding controls and boundary neutrals; they go along with the
+    //character before them
+    let kept: Vec<usize> = (0..classes.len()).filter(|&i| !is_removed(original[i])).collect();
+
+    //X10: runs of the same level, joined across isolates into isolating run sequences
+    let mut runs: Vec<Vec<usize>> = vec![];
+    for &i in &kept {
+        match runs.last_mut() {
+            Some(run) if levels[*run.last().unwrap()] == levels[i] => run.push(i),
+            _ => runs.push(vec![i]),
+        }
+    }
+    let mut sequences: Vec<Vec<usize>> = vec![];
+    let mut continued_by: Vec<Option<usize>> = vec![None; classes.len()];
+    for run in runs {
+        let first = run[0];
+        let target = continued_by[first].and_then(|sequence| {
+            let last = *sequences[sequence].last().unwrap();
+            (matches[last] == Some(first)).then_some(sequence)
+        });
+        let sequence = match target {
+            Some(sequence) => {
+                sequences[sequence].extend(&run);
+                sequence
+            },
+            None => {
+                sequences.push(run);
+                sequences.len() - 1
+            },
+        };
+        let last = *sequences[sequence].last().unwrap();
+        if is_isolate_initiator(original[last]) {
+            if let Some(pdi) = matches[last] {
+                continued_by[pdi] = Some(sequence);
+            }
+        }
+    }
+
+    //sos and eos come from the levels before the implicit rules change them
+    let explicit = levels.to_vec();
+    let level_at = |position: Option<usize>| position.map_or(paragraph_level, |i| explicit[i]);
+    for sequence in &sequences {
+        let first = sequence[0];
+        let last = *sequence.last().unwrap();
+        let level = levels[first];
+        let before = kept.iter().rev().find(|&&i| i < first).copied();
+        //an isolate initiator at the end has no matching PDI, or the sequence would go on
+        let after = kept.iter().find(|&&i| i > last).copied().filter(|_| !is_isolate_initiator(original[last]));
+        let sos = direction(level.max(level_at(before)));
+        let eos = direction(level.max(level_at(after)));
+        resolve_weak(sequence, classes, sos);
+        resolve_brackets(sequence, chars, classes, original, level, sos);
+        resolve_neutrals(sequence, classes, level, sos, eos);
+        for &i in sequence {
+            levels[i] = match (level % 2, classes[i]) {
+                (0, R) => level + 1,
+                (0, AN) | (0, EN) => level + 2,
+                (1, L) | (1, EN) | (1, AN) => level + 1,
+                _ => level,
+            };
+        }
+    }
+
+    //L1 for separators, and the white space and isolate controls before them
+    let mut reset = true;
+    for i in (0..classes.len()).rev() {
+        match original[i] {
+            B | S => {
+                levels[i] = paragraph_level;
+                reset = true;
+            },
+            WS | LRI | RLI | FSI | PDI if reset => levels[i] = paragraph_level,
+            class if is_removed(class) => {},
+            _ => reset = false,
+        }
+    }
+    for i in 0..classes.len() {
+        if is_removed(original[i]) {
+            levels[i] = if i == 0 { paragraph_level } else { levels[i - 1] };
+        }
+    }
+}
+
+// X1 to X8: the levels the embeddings, overrides and isolates put each character at.
+fn explicit_levels(classes: &mut [Class], levels: &mut [Level], matches: &[Option<usize>], paragraph_level: Level) {
+    struct Status {
+        level: Level,
+        override_class: Option<Class>,
+        isolate: bool,
+    }
+    let mut stack = vec![Status {level: paragraph_level, override_class: None, isolate: false}];
+    let (mut overflow_isolates, mut overflow_embeddings, mut valid_isolates) = (0, 0, 0);
+    let next_odd = |level: Level| (level + 1) | 1;
+    let next_even = |level: Level| (level + 2) & !1;
+
+    for i in 0..classes.len() {
+        let top = stack.last().unwrap();
+        let (level, override_class) = (top.level, top.override_class);
+        match classes[i] {
+            RLE | LRE | RLO | LRO => {
+                levels[i] = level;
+                let new_level = if matches!(classes[i], RLE | RLO) { next_odd(level) } else { next_even(level) };
+                if new_level <= MAX_DEPTH && overflow_isolates == 0 && overflow_embeddings == 0 {
+                    let override_class = match classes[i] {
+                        RLO => Some(R),
+                        LRO => Some(L),
+                        _ => None,
+                    };
+                    stack.push(Status {level: new_level, override_class, isolate: false});
+                } else if overflow_isolates == 0 {
+                    overflow_embeddings += 1;
+                }
+            },
+            RLI | LRI | FSI => {
+                levels[i] = level;
+                let rtl = match classes[i] {
+                    RLI => true,
+                    LRI => false,
+                    _ => first_strong(classes, i + 1, matches[i].unwrap_or(classes.len())) == Some(1),
+                };
+                if let Some(class) = override_class {
+                    classes[i] = class;
+                }
+                let new_level = if rtl { next_odd(level) } else { next_even(level) };
+                if new_level <= MAX_DEPTH && overflow_isolates == 0 && overflow_embeddings == 0 {
+                    valid_isolates += 1;
+                    stack.push(Status {level: new_level, override_class: None, isolate: true});
+                } else {
+                    overflow_isolates += 1;
+                }
+            },
+            PDI => {
+                if overflow_isolates > 0 {
+                    overflow_isolates -= 1;
+                } else if valid_isolates > 0 {
+                    overflow_embeddings = 0;
+                    while !stack.last().unwrap().isolate {
+                        stack.pop();
+                    }
+                    stack.pop();
+                    valid_isolates -= 1;
+                }
+                let top = stack.last().unwrap();
+                levels[i] = top.level;
+                if let Some(class) = top.override_class {
+                    classes[i] = class;
+                }
+            },
+            PDF => {
+                levels[i] = level;
+                if overflow_isolates > 0 {
+                } else if overflow_embeddings > 0 {
+                    overflow_embeddings -= 1;
+                } else if !stack.last().unwrap().isolate && stack.len() >= 2 {
+                    stack.pop();
+                }
+            },
+            B => levels[i] = paragraph_level,
+            BN => levels[i] = level,
+            _ => {
+                levels[i] = level;
+                if let Some(class) = override_class {
+                    classes[i] = class;
+                }
+            },
+        }
+    }
+}
+
+// W1 to W7, the weak types.
+fn resolve_weak(sequence: &[usize], classes: &mut [Class], sos: Class) {
+    //W1: marks take the type of what they're on, and are neutral after isolate controls
+    let mut previous = sos;
+    for &i in sequence {
+        if classes[i] == NSM {
+            classes[i] = if matches!(previous, LRI | RLI | FSI | PDI) { ON } else { previous };
+        }
+        previous = classes[i];
+    }
+    //W2 and W3: European numbers after Arabic letters are Arabic numbers, and Arabic
+    //letters are right to left
+    let mut last_strong = sos;
+    for &i in sequence {
+        match classes[i] {
+            L | R => last_strong = classes[i],
+            AL => {
+                last_strong = AL;
+                classes[i] = R;
+            },
+            EN if last_strong == AL => classes[i] = AN,
+            _ => {},
+        }
+    }
+    //W4: a single separator between two numbers of the same kind
+    for k in 1..sequence.len().saturating_sub(1) {
+        let (before, after) = (classes[sequence[k - 1]], classes[sequence[k + 1]]);
+        match classes[sequence[k]] {
+            ES if before == EN && after == EN => classes[sequence[k]] = EN,
+            CS if before == after && matches!(before, EN | AN) => classes[sequence[k]] = before,
+            _ => {},
+        }
+    }
+    //W5: terminators next to European numbers
+    let mut k = 0;
+    while k < sequence.len() {
+        if classes[sequence[k]] != ET {
+            k += 1;
+            continue;
+        }
+        let end = (k..sequence.len()).find(|&j| classes[sequence[j]] != ET).unwrap_or(sequence.len());
+        let next_to_number = (k > 0 && classes[sequence[k - 1]] == EN) || (end < sequence.len() && classes[sequence[end]] == EN);
+        if next_to_number {
+            for &i in &sequence[k..end] {
+                classes[i] = EN;
+            }
+        }
+        k = end;
+    }
+    //W6: the separators and terminators left are neutral
+    for &i in sequence {
+        if matches!(classes[i], ES | ET | CS) {
+            classes[i] = ON;
+        }
+    }
+    //W7: European numbers in left to right text are left to right
+    let mut last_strong = sos;
+    for &i in sequence {
+        match classes[i] {
+            L | R => last_strong = classes[i],
+            EN if last_strong == L => classes[i] = L,
+            _ => {},
+        }
+    }
+}
+
+// N0: matching brackets take the direction of what they enclose, or of the context if that
+// doesn't settle it.
+fn resolve_brackets(sequence: &[usize], chars: &[(usize, char)], classes: &mut [Class], original: &[Class], level: Level, sos: Class) {
+    //BD16: the pairs, by where they open, found with a stack of at most 63 open brackets
+    let mut pairs: Vec<(usize, usize)> = vec![];
+    let mut open: Vec<(char, usize)> = vec![];
+    for (k, &i) in sequence.iter().enumerate() {
+        if classes[i] != ON {
+            continue;
+        }
+        match bracket(chars[i].1) {
+            Some((close, true)) => {
+                if open.len() == 63 {
+                    break;
+                }
+                open.push((close, k));
+            },
+            Some((_, false)) => {
+                if let Some(depth) = open.iter().rposition(|&(close, _)| close == chars[i].1) {
+                    pairs.push((open[depth].1, k));
+                    open.truncate(depth);
+                }
+            },
+            None => {},
+        }
+    }
+    pairs.sort_unstable();
+
+    let embedding = direction(level);
+    for (open, close) in pairs {
+        let inside = sequence[open + 1..close].iter().filter_map(|&i| strong_direction(classes[i]));
+        let mut opposite = false;
+        let mut resolved = None;
+        for strong in inside {
+            if strong == embedding {
+                resolved = Some(embedding);
+                break;
+            }
+            opposite = true;
+        }
+        if resolved.is_none() && opposite {
+            let context = sequence[..open].iter().rev().find_map(|&i| strong_direction(classes[i])).unwrap_or(sos);
+            resolved = Some(if context != embedding { context } else { embedding });
+        }
+        if let Some(resolved) = resolved {
+            for k in [open, close] {
+                classes[sequence[k]] = resolved;
+                //marks on the brackets follow them
+                for &i in sequence[k + 1..].iter().take_while(|&&i| original[i] == NSM) {
+                    classes[i] = resolved;
+                }
+            }
+        }
+    }
+}
+
+// N1 and N2: neutrals between two strong types of the same direction take it, the others
+// the embedding direction.
+fn resolve_neutrals(sequence: &[usize], classes: &mut [Class], level: Level, sos: Class, eos: Class) {
+    let mut k = 0;
+    while k < sequence.len() {
+        if !is_neutral(classes[sequence[k]]) {
+            k += 1;
+            continue;
+        }
+        let end = (k..sequence.len()).find(|&j| !is_neutral(classes[sequence[j]])).unwrap_or(sequence.len());
+        let before = if k == 0 { Some(sos) } else { strong_direction(classes[sequence[k - 1]]) };
+        let after = if end == sequence.len() { Some(eos) } else { strong_direction(classes[sequence[end]]) };
+        let resolved = match (before, after) {
+            (Some(before), Some(after)) if before == after => before,
+            _ => direction(level),
+        };
+        for &i in &sequence[k..end] {
+            classes[i] = resolved;
+        }
+        k = end;
+    }
+}
+
+// L2: the order items at these levels are shown in, left to right, as indices into `levels`.
+// From the highest level down to the lowest odd one, every run at that level or above is
+// reversed.
+pub fn visual_order(levels: &[Level]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..levels.len()).collect();
+    let (lowest, highest) = match (levels.iter().min(), levels.iter().max()) {
+        (Some(&lowest), Some(&highest)) => (lowest, highest),
+        _ => return order,
+    };
+    let lowest_odd = lowest | 1;
+    let mut level = highest;
+    while level >= lowest_odd && level > 0 {
+        let mut k = 0;
+        while k < order.len() {
+            if levels[order[k]] < level {
+                k += 1;
+                continue;
+            }
+            let end = (k..order.len()).find(|&j| levels[order[j]] < level).unwrap_or(order.len());
+            order[k..end].reverse();
+            k = end;
+        }
+        level -= 1;
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The level of each character of `text`.
+    fn char_levels(text: &str, base_level: Option<Level>) -> Vec<Level> {
+        let levels = resolve_levels(text, base_level);
+        text.char_indices().map(|(offset, _)| levels[offset]).collect()
+    }
+
+    // `text` the way it is shown, without the controls and with the mirrored characters of
+    // right to left text swapped.
+    fn visual(text: &str, base_level: Option<Level>) -> String {
+        let chars: Vec<char> = text.chars().collect();
+        let levels = char_levels(text, base_level);
+        visual_order(&levels).into_iter()
+            .filter(|&i| !is_removed(class(chars[i])) && !is_isolate_initiator(class(chars[i])) && class(chars[i]) != PDI)
+            .map(|i| if levels[i] % 2 == 1 { mirror(chars[i]).unwrap_or(chars[i]) } else { chars[i] })
+            .collect()
+    }
+
+    #[test]
+    fn right_to_left_text_is_reversed() {
+        assert_eq!(paragraph_level("אבג"), Some(1));
+        assert_eq!(char_levels("אבג", None), [1, 1, 1]);
+        assert_eq!(visual("אבג", None), "גבא");
+        //neutrals at the ends take the paragraph's direction, and mirror
+        assert_eq!(char_levels("אב <ג>!", None), [1, 1, 1, 1, 1, 1, 1]);
+        assert_eq!(visual("אב <ג>!", None), "!<ג> בא");
+        assert_eq!(paragraph_level("123 !"), None);
+    }
+
+    #[test]
+    fn numbers_in_mixed_text() {
+        //numbers go left to right at the level above right to left text, and the white space
+        //between two right to left runs goes with them, W7 and N1
+        assert_eq!(char_levels("ab אב 12 cd", None), [0, 0, 0, 1, 1, 1, 2, 2, 0, 0, 0]);
+        assert_eq!(visual("ab אב 12 cd", None), "ab 12 בא cd");
+        //a separator between digits and a terminator next to them are part of the number, W4
+        //and W5, and a separator after one isn't, so it follows the paragraph
+        assert_eq!(char_levels("א 1.5 $2, ב", None), [1, 1, 2, 2, 2, 1, 2, 2, 1, 1, 1]);
+        assert_eq!(visual("א 1.5 $2, ב", None), "ב ,$2 1.5 א");
+        //after an Arabic letter European digits are Arabic numbers, W2, and a separator
+        //between Arabic numbers only stays one if it is a common one, W4
+        assert_eq!(char_levels("ا 1,2+3", None), [1, 1, 2, 2, 2, 1, 2]);
+    }
+
+    #[test]
+    fn isolates_and_embeddings() {
+        //the paragraph's direction is that of the first strong character outside isolates
+        assert_eq!(paragraph_level("\u{2067}א\u{2069}b"), Some(0));
+        assert_eq!(paragraph_level("\u{202B}א\u{202C}b"), Some(1));
+
+        //what unicode-bidi: embed and isolate with direction: rtl put around an inline box
+        //raise its text to the next odd level, where left to right text goes a level higher
+        assert_eq!(char_levels("a \u{202B}b c\u{202C} d", None)[3..6], [2, 2, 2]);
+        assert_eq!(char_levels("a \u{2067}b c\u{2069} d", None), [0, 0, 0, 2, 2, 2, 0, 0, 0]);
+        assert_eq!(visual("a \u{2067}b c\u{2069} d", None), "a b c d");
+        assert_eq!(visual("a \u{2067}b אג\u{2069} d", None), "a גא b d");
+
+        //an isolate is a neutral to the text around it, an embedding isn't: the number after
+        //it follows the right to left text inside the embedding, W7
+        assert_eq!(char_levels("a\u{202B}ב\u{202C}1", None)[4], 2);
+        assert_eq!(char_levels("a\u{2067}ב\u{2069}1", None)[4], 0);
+
+        //with an override the characters all go the override's way, bidi-override
+        assert_eq!(visual("\u{202E}abc\u{202C}d", Some(0)), "cbad");
+        assert_eq!(visual("\u{2067}\u{202E}abc\u{202C}\u{2069}d", Some(0)), "cbad");
+    }
+
+    #[test]
+    fn brackets_take_the_direction_of_their_contents() {
+        //brackets around left to right text after left to right text in a right to left
+        //paragraph stay with it, N0, where an unmatched one before right to left text goes
+        //with the paragraph, N1
+        assert_eq!(char_levels("a(b)ג", Some(1)), [2, 2, 2, 2, 1]);
+        assert_eq!(visual("a(b)ג", Some(1)), "גa(b)");
+        assert_eq!(char_levels("a b)ג", Some(1)), [2, 2, 2, 1, 1]);
+        //with the paragraph's direction inside, they take that
+        assert_eq!(char_levels("a(ב)c", Some(1)), [2, 1, 1, 1, 2]);
+        assert_eq!(visual("a(ב)c", Some(1)), "c(ב)a");
+    }
+}
@@ -1,7 +1,8 @@
 
-
 pub struct Stylesheet {
-    pub rules: Vec<Rule>
+    // where it was loaded from, empty for a stylesheet parsed from a string
+    pub url: String,
+    pub rules: Vec<CssRule>
 }
 
 impl std::fmt::Display for Stylesheet {
@@ -10,82 +11,661 @@ impl std::fmt::Display for Stylesheet {
     }
 }
 
+pub enum CssRule {
+    Style(Rule),
+    Media(MediaRule),
+    Import(ImportRule),
+    Supports(SupportsRule),
+    Layer(LayerRule),
+    // @layer a, b.c; only fixes the order of the layers
+    LayerStatement(Vec<LayerName>),
+    FontFace(FontFaceRule),
+    Keyframes(KeyframesRule),
+}
+
+impl std::fmt::Display for CssRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CssRule::Style(rule) => write!(f, "{}", rule),
+            CssRule::Media(rule) => write!(f, "{}", rule),
+            CssRule::Import(rule) => write!(f, "{}", rule),
+            CssRule::Supports(rule) => write!(f, "{}", rule),
+            CssRule::Layer(rule) => write!(f, "{}", rule),
+            CssRule::LayerStatement(names) => {
+                write!(f, "@layer {};", names.iter().map(|name| name.join(".")).collect::<Vec<String>>().join(", "))
+            },
+            CssRule::FontFace(rule) => write!(f, "{}", rule),
+            CssRule::Keyframes(rule) => write!(f, "{}", rule),
+        }
+    }
+}
+
+fn write_block(f: &mut std::fmt::Formatter<'_>, prelude: &str, rules: &[CssRule]) -> std::fmt::Result {
+    let rules = rules.iter().map(|rule| format!("{}", rule)).collect::<Vec<String>>().join("\n");
+    write!(f, "{} {{\n{}\n}}", prelude, rules)
+}
+
 pub struct Rule {
     pub selectors: Vec<Selector>,
-    pub declarations: Vec<Declaration>
+    pub declarations: Vec<Declaration>,
+    // Nested style and conditional rules. Their selectors are already resolved against
+    // this rule's, so they can be used on their own.
+    pub children: Vec<CssRule>,
 }
 
 impl std::fmt::Display for Rule {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         // {
-        let sels = (&self.selectors).iter().map(|selector| {format!("{}",selector)}).collect::<Vec<String>>().join(", ");
+        let sels = self.selectors.iter().map(|selector| {format!("{}",selector)}).collect::<Vec<String>>().join(", ");
 
         let decs = (self.declarations).iter().map(|declaration| {
-            let value = &declaration.value;
-
-            format!("===={}: {}", &declaration.name, match value {
-                Value::Keyword(keyword) => keyword.to_string(),
-                Value::Length(len, unit) => format!("{}{}",&len, match unit {
-                    Unit::Px => "px".to_string(),
-                    Unit::None => "".to_string()
-                }).to_string(),
-                Value::ColorValue(color) => format!("({},{},{},{})",&color.r, &color.g, &color.b, &color.a)
-            })
+            format!("===={}: {}{}", &declaration.name, &declaration.value, if declaration.important { " !important" } else { "" })
         }).collect::<Vec<String>>().join("\n");
         // }
-        write!(f, "{} {{\n{}\n}}",sels, decs)
+        write!(f, "{} {{\n{}\n}}",sels, decs)?;
+        for child in &self.children {
+            write!(f, "\n{}", child)?;
+        }
+        Ok(())
+    }
+}
+
+// @media <queries> { <rules> }
+pub struct MediaRule {
+    pub queries: MediaQueryList,
+    pub rules: Vec<CssRule>,
+}
+
+impl std::fmt::Display for MediaRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_block(f, &format!("@media {}", self.queries), &self.rules)
+    }
+}
+
+// A dotted layer name like `framework.base`, split at the dots.
+pub type LayerName = Vec<String>;
+
+// @import url(...) [layer | layer(name)] [supports(...)] [media queries];
+pub struct ImportRule {
+    pub url: String,
+    // Some(empty name) for an anonymous `layer`
+    pub layer: Option<LayerName>,
+    pub supports: Option<SupportsCondition>,
+    pub queries: MediaQueryList,
+    // filled in by resource::load_stylesheet, stays None if loading failed or the import is cyclic
+    pub stylesheet: Option<Stylesheet>,
+}
+
+impl std::fmt::Display for ImportRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "@import url({})", self.url)?;
+        match &self.layer {
+            Some(name) if name.is_empty() => write!(f, " layer")?,
+            Some(name) => write!(f, " layer({})", name.join("."))?,
+            None => {}
+        }
+        if let Some(condition) = &self.supports {
+            write!(f, " supports({})", condition)?;
+        }
+        if !self.queries.queries.is_empty() {
+            write!(f, " {}", self.queries)?;
+        }
+        write!(f, ";")?;
+        if let Some(stylesheet) = &self.stylesheet {
+            write!(f, "\n{}", stylesheet)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct SupportsRule {
+    pub condition: SupportsCondition,
+    pub rules: Vec<CssRule>,
+}
+
+impl std::fmt::Display for SupportsRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_block(f, &format!("@supports {}", self.condition), &self.rules)
+    }
+}
+
+pub enum SupportsCondition {
+    // (property: value), the value is None if we couldn't even parse it
+    Declaration(String, Option<Value>),
+    // selector(...), true if the selector parsed
+    Selector(String, bool),
+    Not(Box<SupportsCondition>),
+    And(Vec<SupportsCondition>),
+    Or(Vec<SupportsCondition>),
+    // <general-enclosed>, always false
+    Unknown(String),
+}
+
+impl std::fmt::Display for SupportsCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let nested = |condition: &SupportsCondition| match condition {
+            SupportsCondition::And(_) | SupportsCondition::Or(_) | SupportsCondition::Not(_) => format!("({})", condition),
+            _ => format!("{}", condition),
+        };
+        match self {
+            SupportsCondition::Declaration(name, Some(value)) => write!(f, "({}: {})", name, value),
+            SupportsCondition::Declaration(name, None) => write!(f, "({}: <invalid>)", name),
+            SupportsCondition::Selector(selector, _) => write!(f, "selector({})", selector),
+            SupportsCondition::Not(condition) => write!(f, "not {}", nested(condition)),
+            SupportsCondition::And(conditions) => write!(f, "{}", conditions.iter().map(nested).collect::<Vec<String>>().join(" and ")),
+            SupportsCondition::Or(conditions) => write!(f, "{}", conditions.iter().map(nested).collect::<Vec<String>>().join(" or ")),
+            SupportsCondition::Unknown(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+// @layer name { ... }, or an anonymous @layer { ... } when name is None
+pub struct LayerRule {
+    pub name: Option<LayerName>,
+    pub rules: Vec<CssRule>,
+}
+
+impl std::fmt::Display for LayerRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.name {
+            Some(name) => write_block(f, &format!("@layer {}", name.join(".")), &self.rules),
+            None => write_block(f, "@layer", &self.rules),
+        }
+    }
+}
+
+// Descriptors are kept as written, the font subsystem interprets them.
+pub struct FontFaceRule {
+    pub descriptors: Vec<(String, String)>,
+}
+
+impl std::fmt::Display for FontFaceRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let decs = self.descriptors.iter().map(|(name, value)| {
+            format!("===={}: {}", name, value)
+        }).collect::<Vec<String>>().join("\n");
+        write!(f, "@font-face {{\n{}\n}}", decs)
+    }
+}
+
+pub struct KeyframesRule {
+    pub name: String,
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl std::fmt::Display for KeyframesRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let keyframes = self.keyframes.iter().map(|keyframe| format!("{}", keyframe)).collect::<Vec<String>>().join("\n");
+        write!(f, "@keyframes {} {{\n{}\n}}", self.name, keyframes)
+    }
+}
+
+// A block of a @keyframes rule. `from` and `to` are offsets 0 and 1, `50%` is 0.5.
+pub struct Keyframe {
+    pub offsets: Vec<f32>,
+    pub declarations: Vec<Declaration>,
+}
+
+impl std::fmt::Display for Keyframe {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let offsets = self.offsets.iter().map(|offset| format!("{}%", offset * 100.0)).collect::<Vec<String>>().join(", ");
+        let decs = self.declarations.iter().map(|declaration| {
+            format!("===={}: {}", &declaration.name, &declaration.value)
+        }).collect::<Vec<String>>().join("\n");
+        write!(f, "{} {{\n{}\n}}", offsets, decs)
+    }
+}
+
+// An empty list matches every environment, like `@media {}` or a missing media attribute.
+pub struct MediaQueryList {
+    pub queries: Vec<MediaQuery>,
+}
+
+impl std::fmt::Display for MediaQueryList {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.queries.is_empty() {
+            return write!(f, "all");
+        }
+        write!(f, "{}", self.queries.iter().map(|query| format!("{}", query)).collect::<Vec<String>>().join(", "))
+    }
+}
+
+pub struct MediaQuery {
+    pub qualifier: Option<MediaQualifier>,
+    pub media_type: MediaType,
+    pub condition: Option<MediaCondition>,
+}
+
+impl MediaQuery {
+    // What a query that failed to parse turns into, so it can't match but doesn't take the
+    // rest of the list down with it.
+    pub fn not_all() -> MediaQuery {
+        MediaQuery {
+            qualifier: Some(MediaQualifier::Not),
+            media_type: MediaType::All,
+            condition: None,
+        }
+    }
+}
+
+impl std::fmt::Display for MediaQuery {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = vec![];
+        match self.qualifier {
+            Some(MediaQualifier::Not) => parts.push("not".to_string()),
+            Some(MediaQualifier::Only) => parts.push("only".to_string()),
+            None => {}
+        }
+        let type_is_implied = self.qualifier.is_none() && self.media_type == MediaType::All && self.condition.is_some();
+        if !type_is_implied {
+            parts.push(format!("{}", self.media_type));
+        }
+        if let Some(condition) = &self.condition {
+            if !type_is_implied {
+                parts.push("and".to_string());
+            }
+            parts.push(format!("{}", condition));
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MediaQualifier {
+    Not,
+    Only,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum MediaType {
+    All,
+    Screen,
+    Print,
+    // Deprecated and unknown types are kept so they can be serialized, but never match.
+    Other(String),
+}
+
+impl std::fmt::Display for MediaType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MediaType::All => write!(f, "all"),
+            MediaType::Screen => write!(f, "screen"),
+            MediaType::Print => write!(f, "print"),
+            MediaType::Other(name) => write!(f, "{}", name),
+        }
     }
 }
 
+pub enum MediaCondition {
+    Feature(MediaFeature),
+    Not(Box<MediaCondition>),
+    And(Vec<MediaCondition>),
+    Or(Vec<MediaCondition>),
+    // <general-enclosed>: syntactically valid but not understood, evaluates to false.
+    Unknown(String),
+}
+
+impl std::fmt::Display for MediaCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let nested = |condition: &MediaCondition| match condition {
+            MediaCondition::And(_) | MediaCondition::Or(_) | MediaCondition::Not(_) => format!("({})", condition),
+            _ => format!("{}", condition),
+        };
+        match self {
+            MediaCondition::Feature(feature) => write!(f, "({})", feature),
+            MediaCondition::Not(condition) => write!(f, "not {}", nested(condition)),
+            MediaCondition::And(conditions) => write!(f, "{}", conditions.iter().map(nested).collect::<Vec<String>>().join(" and ")),
+            MediaCondition::Or(conditions) => write!(f, "{}", conditions.iter().map(nested).collect::<Vec<String>>().join(" or ")),
+            MediaCondition::Unknown(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+pub enum MediaFeature {
+    // (color)
+    Boolean(String),
+    // (max-width: 600px), the min-/max- prefix is kept in the name
+    Plain(String, MediaValue),
+    // (width >= 600px) and (400px < width <= 700px), stored as `name op value` comparisons
+    Range(String, Vec<(MediaComparison, MediaValue)>),
+}
+
+impl std::fmt::Display for MediaFeature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MediaFeature::Boolean(name) => write!(f, "{}", name),
+            MediaFeature::Plain(name, value) => write!(f, "{}: {}", name, value),
+            MediaFeature::Range(name, comparisons) => {
+                write!(f, "{}", comparisons.iter().map(|(op, value)| {
+                    format!("{} {} {}", name, op, value)
+                }).collect::<Vec<String>>().join(") and ("))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MediaComparison {
+    Lt,
+    Le,
+    Eq,
+    Ge,
+    Gt,
+}
+
+impl MediaComparison {
+    // `600px < width` is the same test as `width > 600px`
+    pub fn flip(self) -> MediaComparison {
+        match self {
+            MediaComparison::Lt => MediaComparison::Gt,
+            MediaComparison::Le => MediaComparison::Ge,
+            MediaComparison::Eq => MediaComparison::Eq,
+            MediaComparison::Ge => MediaComparison::Le,
+            MediaComparison::Gt => MediaComparison::Lt,
+        }
+    }
+}
+
+impl std::fmt::Display for MediaComparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            MediaComparison::Lt => "<",
+            MediaComparison::Le => "<=",
+            MediaComparison::Eq => "=",
+            MediaComparison::Ge => ">=",
+            MediaComparison::Gt => ">",
+        })
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum MediaValue {
+    Length(f32, Unit),
+    Number(f32),
+    Ratio(f32, f32),
+    // always in dppx
+    Resolution(f32),
+    Ident(String),
+}
+
+impl std::fmt::Display for MediaValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MediaValue::Length(len, unit) => write!(f, "{}{}", len, unit),
+            MediaValue::Number(number) => write!(f, "{}", number),
+            MediaValue::Ratio(num, den) => write!(f, "{}/{}", num, den),
+            MediaValue::Resolution(dppx) => write!(f, "{}dppx", dppx),
+            MediaValue::Ident(ident) => write!(f, "{}", ident),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub enum Selector {
-    Simple(SimpleSelector)
+    Simple(SimpleSelector),
+    Complex(ComplexSelector),
 }
 
 impl std::fmt::Display for Selector {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 
         match &self {
-            Selector::Simple(sel) => {
-                let mut tag = "";
-                let mut id = "";
-                let classes = sel.class.join(".");
-                if let Some(tag_name) = &sel.tag_name {
-                    tag = &tag_name;
-                }
-                if let Some(sid) = &sel.id {
-                    id = &sid;
+            Selector::Simple(sel) => write!(f, "{}", sel),
+            Selector::Complex(sel) => {
+                write!(f, "{}", sel.parts[0])?;
+                for (combinator, part) in sel.combinators.iter().zip(&sel.parts[1..]) {
+                    write!(f, "{}{}", combinator, part)?;
                 }
-                write!(f,"{}({})[{}]",tag, id, classes)
+                Ok(())
             }
         }
     }
 }
 
+// Compound selectors joined by combinators, `a > b c` has parts [a, b, c] and combinators
+// [Child, Descendant]. There is always one combinator less than there are parts.
+#[derive(Clone)]
+pub struct ComplexSelector {
+    pub parts: Vec<SimpleSelector>,
+    pub combinators: Vec<Combinator>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Combinator {
+    Descendant,
+    Child,
+    NextSibling,
+    SubsequentSibling,
+}
+
+impl std::fmt::Display for Combinator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Combinator::Descendant => " ",
+            Combinator::Child => " > ",
+            Combinator::NextSibling => " + ",
+            Combinator::SubsequentSibling => " ~ ",
+        })
+    }
+}
+
+#[derive(Clone)]
 pub struct SimpleSelector {
     pub tag_name: Option<String>,
     pub id: Option<String>,
     pub class: Vec<String>,
+    pub pseudo_classes: Vec<PseudoClass>,
+    // only ever on the rightmost compound, which then selects that part of the element
+    pub pseudo_element: Option<PseudoElement>,
 }
 
+impl std::fmt::Display for SimpleSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut tag = "";
+        let mut id = "";
+        let classes = self.class.join(".");
+        if let Some(tag_name) = &self.tag_name {
+            tag = tag_name;
+        }
+        if let Some(sid) = &self.id {
+            id = sid;
+        }
+        write!(f,"{}({})[{}]",tag, id, classes)?;
+        for pseudo_class in &self.pseudo_classes {
+            write!(f, "{}", pseudo_class)?;
+        }
+        if let Some(pseudo_element) = self.pseudo_element {
+            write!(f, "{}", pseudo_element)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub enum PseudoClass {
+    // :is() also stands in for `&` in nested rules, holding the parent rule's selectors
+    Is(Vec<Selector>),
+    Where(Vec<Selector>),
+    Not(Vec<Selector>),
+}
+
+// The boxes an element generates besides its own, CSS Pseudo-Elements 4 §3 and CSS Lists 3 §3.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum PseudoElement {
+    Marker,
+    Before,
+    After,
+}
+
+impl std::fmt::Display for PseudoElement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            PseudoElement::Marker => "::marker",
+            PseudoElement::Before => "::before",
+            PseudoElement::After => "::after",
+        })
+    }
+}
+
+impl std::fmt::Display for PseudoClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let list = |selectors: &[Selector]| selectors.iter().map(|selector| format!("{}", selector)).collect::<Vec<String>>().join(", ");
+        match self {
+            PseudoClass::Is(selectors) => write!(f, ":is({})", list(selectors)),
+            PseudoClass::Where(selectors) => write!(f, ":where({})", list(selectors)),
+            PseudoClass::Not(selectors) => write!(f, ":not({})", list(selectors)),
+        }
+    }
+}
+
+impl Selector {
+    // The selector the way it would be written in a stylesheet, unlike Display which shows
+    // how it was parsed. Nested rules come out with their `&` already replaced by :is().
+    pub fn css_text(&self) -> String {
+        match self {
+            Selector::Simple(sel) => sel.css_text(),
+            Selector::Complex(sel) => {
+                let mut text = sel.parts[0].css_text();
+                for (combinator, part) in sel.combinators.iter().zip(&sel.parts[1..]) {
+                    text += &format!("{}{}", combinator, part.css_text());
+                }
+                text
+            },
+        }
+    }
+}
+
+impl SimpleSelector {
+    pub fn css_text(&self) -> String {
+        let mut text = self.tag_name.clone().unwrap_or_default();
+        if let Some(id) = &self.id {
+            text += &format!("#{}", id);
+        }
+        for class in &self.class {
+            text += &format!(".{}", class);
+        }
+        for pseudo_class in &self.pseudo_classes {
+            let (name, selectors) = match pseudo_class {
+                PseudoClass::Is(selectors) => ("is", selectors),
+                PseudoClass::Where(selectors) => ("where", selectors),
+                PseudoClass::Not(selectors) => ("not", selectors),
+            };
+            let list = selectors.iter().map(Selector::css_text).collect::<Vec<String>>().join(", ");
+            text += &format!(":{}({})", name, list);
+        }
+        if let Some(pseudo_element) = self.pseudo_element {
+            if text.is_empty() {
+                text.push('*');
+            }
+            text += &pseudo_element.to_string();
+        }
+        if text.is_empty() {
+            text.push('*');
+        }
+        text
+    }
+}
+
+#[derive(Clone)]
 pub struct Declaration {
     pub name: String,
     pub value: Value,
+    pub important: bool,
+    pub location: SourceLocation,
 }
 
-#[derive(Clone)]
+// Where in its stylesheet something was written.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SourceLocation {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Clone, PartialEq, Debug)]
 pub enum Value {
     Keyword(String),
+    // a plain number is a length with Unit::None
     Length(f32, Unit),
-    ColorValue(Color),
+    Percentage(f32),
+    // in milliseconds
+    Time(f32),
+    // in degrees
+    Angle(f32),
+    Color(Color),
+    Str(String),
+    // name(args), like translate(10px, 5px) or cubic-bezier(0.1, 0.7, 1, 0.1)
+    Function(String, Vec<Value>),
+    // [name ...], the names of a grid line in a track list
+    LineNames(Vec<String>),
+    // the / between the parts of a value like grid-column: 1 / 3
+    Slash,
+    // space separated components
+    List(Vec<Value>),
+    // comma separated groups, each possibly a List
+    CommaList(Vec<Value>),
 }
 
-#[derive(Clone)]
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let join = |values: &[Value], separator: &str| values.iter().map(|value| format!("{}", value)).collect::<Vec<String>>().join(separator);
+        match self {
+            Value::Keyword(keyword) => write!(f, "{}", keyword),
+            Value::Length(len, unit) => write!(f, "{}{}", len, unit),
+            Value::Percentage(percentage) => write!(f, "{}%", percentage),
+            Value::Time(ms) => write!(f, "{}ms", ms),
+            Value::Angle(deg) => write!(f, "{}deg", deg),
+            Value::Color(color) => write!(f, "({},{},{},{})", color.r, color.g, color.b, color.a),
+            Value::Str(string) => write!(f, "\"{}\"", string),
+            Value::Function(name, args) => write!(f, "{}({})", name, join(args, ", ")),
+            Value::LineNames(names) => write!(f, "[{}]", names.join(" ")),
+            Value::Slash => write!(f, "/"),
+            Value::List(values) => write!(f, "{}", join(values, " ")),
+            Value::CommaList(values) => write!(f, "{}", join(values, ", ")),
+        }
+    }
+}
+
+impl Value {
+    // The comma separated groups of a value, a value without commas is a single group.
+    pub fn groups(&self) -> &[Value] {
+        match self {
+            Value::CommaList(values) => values,
+            value => std::slice::from_ref(value),
+        }
+    }
+
+    // The space separated components of a value.
+    pub fn components(&self) -> &[Value] {
+        match self {
+            Value::List(values) => values,
+            value => std::slice::from_ref(value),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum Unit {
     Px,
+    Em,
+    Rem,
+    // a fraction of the free space in a grid container
+    Fr,
     None,
 }
 
-#[derive(Clone)]
+impl std::fmt::Display for Unit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Unit::Px => "px",
+            Unit::Em => "em",
+            Unit::Rem => "rem",
+            Unit::Fr => "fr",
+            Unit::None => "",
+        })
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
 pub struct Color {
     pub r: u8,
     pub b: u8,
@@ -93,4 +673,170 @@ pub struct Color {
     pub g: u8,
 }
 
-pub type Specificity = (usize, usize, usize);
\ No newline at end of file
+impl Color {
+    pub fn from_name(name: &str) -> Option<Color> {
+        let name = name.to_ascii_lowercase();
+        if name == "transparent" {
+            return Some(Color {r: 0, g: 0, b: 0, a: 0});
+        }
+        NAMED_COLORS.iter().find(|(color_name, _)| *color_name == name).map(|&(_, rgb)| Color {
+            r: (rgb >> 16) as u8,
+            g: (rgb >> 8) as u8,
+            b: rgb as u8,
+            a: 255,
+        })
+    }
+}
+
+const NAMED_COLORS: &[(&str, u32)] = &[
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+pub type Specificity = (usize, usize, usize);
@@ -1,31 +1,86 @@
 
 use std::str::FromStr;
 use crate::css;
+use crate::properties;
 
 pub fn parse(source: String) -> css::Stylesheet {
-    let style = Parser {input: source, pos: 0}.parse_style();
-
-    style
+    Parser::new(source).parse_style()
 }
 
 impl css::Selector {
     pub fn specificity(&self) -> css::Specificity {
-        let css::Selector::Simple(ref simple) = *self;
+        match self {
+            css::Selector::Simple(simple) => simple.specificity(),
+            css::Selector::Complex(complex) => complex.parts.iter().fold((0, 0, 0), |(a, b, c), part| {
+                let (idc, classc, tagc) = part.specificity();
+                (a + idc, b + classc, c + tagc)
+            }),
+        }
+    }
+}
 
-        let idc = simple.id.iter().count();
-        let tagc = simple.tag_name.iter().count();
-        let classc = simple.class.len();
+impl css::SimpleSelector {
+    pub fn specificity(&self) -> css::Specificity {
+        let idc = self.id.iter().count();
+        let classc = self.class.len();
+        //a pseudo-element counts like a tag name
+        let tagc = self.tag_name.iter().count() + self.pseudo_element.iter().count();
 
-        (idc, tagc, classc)
+        //:is() and :not() count as their most specific argument, :where() counts for nothing
+        self.pseudo_classes.iter().fold((idc, classc, tagc), |(a, b, c), pseudo_class| {
+            let (idc, classc, tagc) = match pseudo_class {
+                css::PseudoClass::Is(selectors) | css::PseudoClass::Not(selectors) => {
+                    selectors.iter().map(|selector| selector.specificity()).max().unwrap_or((0, 0, 0))
+                },
+                css::PseudoClass::Where(_) => (0, 0, 0),
+            };
+            (a + idc, b + classc, c + tagc)
+        })
+    }
+}
+
+//the compound that `&` turns into: something matching any of the parent rule's selectors
+fn nesting_selector(parent: &[css::Selector]) -> css::SimpleSelector {
+    css::SimpleSelector {
+        tag_name: None,
+        id: None,
+        class: vec![],
+        pseudo_classes: vec![css::PseudoClass::Is(parent.to_vec())],
+        pseudo_element: None,
     }
 }
 
 struct Parser {
     input: String,
     pos: usize,
+    // a position and its line and column, to count on from for the next location
+    line_cache: (usize, usize, usize),
 }
 
 impl Parser {
+    fn new(input: String) -> Parser {
+        Parser {input, pos: 0, line_cache: (0, 1, 1)}
+    }
+
+    // line and column of the current position, counted in chars from 1
+    fn location(&mut self) -> css::SourceLocation {
+        let (mut pos, mut line, mut column) = self.line_cache;
+        if pos > self.pos {
+            (pos, line, column) = (0, 1, 1);
+        }
+        for c in self.input[pos..self.pos].chars() {
+            if c == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+        pos = self.pos;
+        self.line_cache = (pos, line, column);
+        css::SourceLocation {line, column}
+    }
+
     fn next_char(&self) -> char {
         self.input[self.pos..].chars().next().unwrap()
     }
@@ -61,8 +116,8 @@ impl Parser {
         Ok(())
     }
 
-    fn consume_while<F>(&mut self, test: F) -> String
-        where F: Fn(char) -> bool {
+    fn consume_while<F>(&mut self, mut test: F) -> String
+        where F: FnMut(char) -> bool {
             let mut result = String::new();
             while !self.eof() && test(self.next_char()) {
                 result.push(self.consume_char());
@@ -80,8 +135,16 @@ impl Parser {
         result
     }
 
+    //comments count as whitespace
     fn consume_whitespace(&mut self) {
-        self.consume_while(char::is_whitespace);
+        loop {
+            self.consume_while(char::is_whitespace);
+            if self.consume_string("/*").is_err() {
+                break;
+            }
+            self.consume_till_str("*/");
+            let _ = self.consume_string("*/");
+        }
     }
 
     // fn consume_str_if_starts_with(&mut self, string: &str) {
@@ -92,24 +155,15 @@ impl Parser {
 
     //just consumes any string containing number or dot
     fn consume_number(&mut self) -> String {
-        self.consume_while(|x| match x {
-            '0'..='9'|'.' => true,
-            _ => false
-        })
+        self.consume_while(|x| matches!(x, '0'..='9'|'.'))
     }
 
     fn valid_identifier_char(c: char) -> bool {
-        match c {
-            'a'..='z' | 'A' ..= 'Z' | '0' ..= '9' | '_' | '-' => true,
-            _ => false,
-        }
+        matches!(c, 'a'..='z' | 'A' ..= 'Z' | '0' ..= '9' | '_' | '-')
     }
 
     fn parse_identifier(&mut self) -> Option<String> {
-        let id = self.consume_while(|c| match c {
-            c if Parser::valid_identifier_char(c) => true,
-            _ => false
-        });
+        let id = self.consume_while(Parser::valid_identifier_char);
 
         // self.consume_whitespace();
         // if self.eof() {
@@ -124,124 +178,263 @@ impl Parser {
         // }
     }
 
-    fn parse_simple_selector(&mut self) -> Result<css::SimpleSelector, String> {
+    //a compound selector like `p#id.class:is(...)`, without any whitespace in it
+    fn parse_simple_selector(&mut self, nesting: Option<&[css::Selector]>, used_nesting: &mut bool) -> Result<css::SimpleSelector, ()> {
+        let start = self.pos;
         let mut selector = css::SimpleSelector {
             tag_name: None,
             id: None,
-            class: Vec::new()
+            class: Vec::new(),
+            pseudo_classes: Vec::new(),
+            pseudo_element: None,
         };
 
         while !self.eof() {
-            self.consume_whitespace();
+            //nothing can follow a pseudo-element in its compound
+            if selector.pseudo_element.is_some() {
+                break;
+            }
             match self.next_char() {
                 '#' => {
                     self.consume_char();
-                    selector.id = self.parse_identifier();
-                    //write code to ignore set of ids w invalid identifier
-                    // if selector.id.is_none() {
-                    //     break;
-                    // }
+                    selector.id = self.parse_identifier().filter(|id| !id.is_empty());
+                    if selector.id.is_none() {
+                        return Err(());
+                    }
                 },
                 '.' => {
                     self.consume_char();
-                    let class = self.parse_identifier();
-                    //TODO: ignore if invalid class
-                    let class = class.unwrap();
-                    // println!("Parsed class '{}' :D", class);
+                    let class = self.parse_identifier().unwrap();
+                    if class.is_empty() {
+                        return Err(());
+                    }
                     selector.class.push(class);
                 },
                 '*' => {
                     self.consume_char();
-                }
-                c if Parser::valid_identifier_char(c) => {
-                    let tag_name = self.parse_identifier();
-                    //TODO: ignore invalidds
-                    selector.tag_name = tag_name;
+                },
+                '&' => {
+                    self.consume_char();
+                    let parent = nesting.ok_or(())?;
+                    selector.pseudo_classes.push(css::PseudoClass::Is(parent.to_vec()));
+                    *used_nesting = true;
+                },
+                ':' => {
+                    self.consume_char();
+                    //::before, and the old :before and :after from CSS 2
+                    let double_colon = self.consume_string(":").is_ok();
+                    let name = self.parse_keyword();
+                    let pseudo_element = match &*name {
+                        "marker" if double_colon => Some(css::PseudoElement::Marker),
+                        "before" => Some(css::PseudoElement::Before),
+                        "after" => Some(css::PseudoElement::After),
+                        _ if double_colon => return Err(()),
+                        _ => None,
+                    };
+                    if pseudo_element.is_some() {
+                        selector.pseudo_element = pseudo_element;
+                        continue;
+                    }
+                    let mut parser = Parser::new(self.consume_parenthesized()?);
+                    let selectors = parser.parse_selector_list(nesting, false, used_nesting)?;
+                    parser.consume_whitespace();
+                    if !parser.eof() {
+                        return Err(());
+                    }
+                    selector.pseudo_classes.push(match &*name {
+                        "is" | "matches" => css::PseudoClass::Is(selectors),
+                        "where" => css::PseudoClass::Where(selectors),
+                        "not" => css::PseudoClass::Not(selectors),
+                        _ => return Err(()),
+                    });
+                },
+                c if Parser::valid_identifier_char(c) && self.pos == start => {
+                    selector.tag_name = self.parse_identifier();
                 },
                 _ => break,
             }
         }
 
-        return Ok(selector);
+        if self.pos == start {
+            return Err(());
+        }
+        Ok(selector)
     }
 
-    fn parse_rule(&mut self) -> css::Rule {
-        let mut selectors = self.parse_selectors();
-        selectors.sort_by(|sel1, sel2| sel1.specificity().cmp( &sel2.specificity()));
-        css::Rule {
-            selectors: selectors,
-            declarations: self.parse_declarations(),
-        }
+    fn parse_combinator(&mut self) -> Option<css::Combinator> {
+        let combinator = match self.next_char() {
+            '>' => css::Combinator::Child,
+            '+' => css::Combinator::NextSibling,
+            '~' => css::Combinator::SubsequentSibling,
+            _ => return None,
+        };
+        self.consume_char();
+        Some(combinator)
     }
 
-    fn parse_selectors(&mut self) -> Vec<css::Selector> {
-        let mut selectors = vec![];
+    //`nesting` holds the selectors of the enclosing rule when parsing a nested one. `&` stands
+    //for them, and a `relative` selector without `&` is taken to be relative to them
+    fn parse_complex_selector(&mut self, nesting: Option<&[css::Selector]>, relative: bool, used_nesting: &mut bool) -> Result<css::Selector, ()> {
+        let mut parts = vec![];
+        let mut combinators = vec![];
+        let mut has_nesting = false;
+
+        self.consume_whitespace();
+        if self.eof() {
+            return Err(());
+        }
+        if let (Some(parent), true) = (nesting, relative) {
+            //`> h2` inside a rule means `& > h2`
+            if let Some(combinator) = self.parse_combinator() {
+                parts.push(nesting_selector(parent));
+                combinators.push(combinator);
+                has_nesting = true;
+                self.consume_whitespace();
+            }
+        }
+
         loop {
+            parts.push(self.parse_simple_selector(nesting, &mut has_nesting)?);
+            let before = self.pos;
             self.consume_whitespace();
-            if self.eof() {
+            if self.eof() || matches!(self.next_char(), ',' | '{' | ')') {
                 break;
             }
-            let c = self.next_char();
-            // // println!("next selector starts with {}", c);
+            if let Some(combinator) = self.parse_combinator() {
+                combinators.push(combinator);
+                self.consume_whitespace();
+            } else if self.pos != before {
+                combinators.push(css::Combinator::Descendant);
+            } else {
+                return Err(());
+            }
+        }
+
+        if let (Some(parent), true, false) = (nesting, relative, has_nesting) {
+            parts.insert(0, nesting_selector(parent));
+            combinators.insert(0, css::Combinator::Descendant);
+            has_nesting = true;
+        }
+        *used_nesting |= has_nesting;
+
+        //a pseudo-element is only ever the subject
+        if parts[..parts.len() - 1].iter().any(|part| part.pseudo_element.is_some()) {
+            return Err(());
+        }
+        if parts.len() == 1 {
+            Ok(css::Selector::Simple(parts.pop().unwrap()))
+        } else {
+            Ok(css::Selector::Complex(css::ComplexSelector {parts, combinators}))
+        }
+    }
 
+    fn parse_selector_list(&mut self, nesting: Option<&[css::Selector]>, relative: bool, used_nesting: &mut bool) -> Result<Vec<css::Selector>, ()> {
+        let mut selectors = vec![self.parse_complex_selector(nesting, relative, used_nesting)?];
+        while self.consume_string(",").is_ok() {
+            selectors.push(self.parse_complex_selector(nesting, relative, used_nesting)?);
+        }
+        Ok(selectors)
+    }
+
+    //a style rule, or a nested one when `nesting` has the selectors of the rule it is in.
+    //A rule with an invalid selector is skipped entirely
+    fn parse_rule(&mut self, nesting: Option<&[css::Selector]>) -> Option<css::Rule> {
+        let selectors = self.parse_selector_list(nesting, nesting.is_some(), &mut false);
+        self.consume_whitespace();
+        let mut selectors = match selectors {
+            Ok(selectors) if self.consume_string("{").is_ok() => selectors,
+            _ => {
+                self.consume_while(|c| c != '{' && c != '}');
+                if self.consume_string("{").is_ok() {
+                    self.skip_block();
+                }
+                return None;
+            }
+        };
+        selectors.sort_by_key(|sel1| sel1.specificity());
+        let (declarations, children) = self.parse_block_contents(&selectors);
+        Some(css::Rule {
+            selectors,
+            declarations,
+            children,
+        })
+    }
+
+    //true if what's next is a nested rule rather than a declaration, which is the case when
+    //there's a `{` before the next `;` or `}`
+    fn starts_nested_rule(&self) -> bool {
+        let mut depth = 0;
+        for c in self.input[self.pos..].chars() {
             match c {
-                '{' => {
-                    self.consume_char();
-                    break;
-                },
-                ',' => {self.consume_char();},
-                _ => {
-                    let selector = self.parse_simple_selector();
-                    if let Ok(selector) = selector {
-                        let selector = css::Selector::Simple(selector);
-                        selectors.push(selector);
-                    } else{
-                        self.consume_whitespace();
-                        let vlen = selectors.len();
-                        self.consume_while(|c| c != ','||(vlen == 0 && c!='}')||(vlen != 0 && c!='{')); //move towards next tag or end of selector list or end of content
-                        if self.eof() {
-                            break;
-                        }
-                        if self.next_char() != '{' {
-                            self.consume_char();
-                        }
-                    }
-                },
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                '{' if depth <= 0 => return true,
+                ';' | '}' if depth <= 0 => return false,
+                _ => {}
             }
         }
-
-        selectors
+        false
     }
 
-    fn parse_declarations(&mut self) -> Vec<css::Declaration> {
-        let mut decs = vec![];
+    //the inside of a style rule's block, up to and including its `}`. Declarations that come
+    //after a nested rule are wrapped in a rule of their own so they keep their place in the
+    //source order
+    fn parse_block_contents(&mut self, selectors: &[css::Selector]) -> (Vec<css::Declaration>, Vec<css::CssRule>) {
+        let mut declarations = vec![];
+        let mut children = vec![];
+        let mut trailing = vec![];
+        let flush = |trailing: &mut Vec<css::Declaration>, children: &mut Vec<css::CssRule>| {
+            if !trailing.is_empty() {
+                children.push(css::CssRule::Style(css::Rule {
+                    selectors: selectors.to_vec(),
+                    declarations: std::mem::take(trailing),
+                    children: vec![],
+                }));
+            }
+        };
 
         loop {
+            self.consume_whitespace();
             if self.eof() {
                 break;
             }
-            self.consume_whitespace();
-            let c = self.next_char();
-            match c {
-                c if Parser::valid_identifier_char(c) => {
-                    let declaration = self.parse_declaration();
-                    if let Ok(declaration) = declaration {
-                        decs.push(declaration);
-                    }
+            match self.next_char() {
+                '}' => {
+                    self.consume_char();
+                    break;
                 },
                 ';' => {
                     self.consume_char();
-                    self.consume_whitespace();
-                    self.consume_string("}");
                 },
-                _ => {break;}
+                '@' => {
+                    flush(&mut trailing, &mut children);
+                    if let Some(rule) = self.parse_at_rule(Some(selectors)) {
+                        children.push(rule);
+                    }
+                },
+                c if Parser::valid_identifier_char(c) && !self.starts_nested_rule() => {
+                    match self.parse_declaration() {
+                        Ok(declaration) if children.is_empty() => declarations.extend(properties::expand(declaration)),
+                        Ok(declaration) => trailing.extend(properties::expand(declaration)),
+                        //skip whatever is left of the invalid declaration
+                        Err(_) => { self.consume_while(|c| c != ';' && c != '}'); },
+                    }
+                },
+                _ => {
+                    flush(&mut trailing, &mut children);
+                    if let Some(rule) = self.parse_rule(Some(selectors)) {
+                        children.push(css::CssRule::Style(rule));
+                    }
+                }
             }
         }
+        flush(&mut trailing, &mut children);
 
-        decs
+        (declarations, children)
     }
 
     fn parse_declaration(&mut self) -> Result<css::Declaration, String> {
+        let location = self.location();
         let property = self.parse_identifier();
         if let Some(property) = property {
             // println!("parsing for {}", property);
@@ -255,35 +448,127 @@ impl Parser {
             
             if let Ok(value) = value {
                 // println!("Found decratation: {}", property);
-                return Ok(css::Declaration {
+                let important = self.consume_string("!").is_ok();
+                if important {
+                    self.consume_whitespace();
+                    if self.parse_keyword() != "important" || !self.at_value_end() {
+                        return Err(format!("Expected !important after value for property {}", property));
+                    }
+                }
+                //dropped here rather than when computing, so it doesn't win the cascade over
+                //a valid declaration (§4.2)
+                if !properties::is_supported(&property, &value) {
+                    return Err(format!("Invalid value for property {}", property));
+                }
+                Ok(css::Declaration {
                     name: property,
-                    value: value,
+                    value,
+                    important,
+                    location,
                 })
             } else {
                 // // println!("So the colour is not okay? {}", property);
-                return Err(format!("Couldn't parse value for property {}",property));
+                Err(format!("Couldn't parse value for property {}",property))
             }
         } else {
-            return Err("Couldnt parse property.".to_string());
+            Err("Couldnt parse property.".to_string())
         }
     }
 
     fn parse_value(&mut self) -> Result<css::Value, String> {
+        let mut groups = vec![];
+        let mut components = vec![];
+
+        loop {
+            if self.at_value_end() {
+                break;
+            }
+            if self.eof() {
+                return Err("All declarations must be terminated by ;".to_string());
+            }
+            if self.consume_string(",").is_ok() {
+                if components.is_empty() {
+                    return Err("Expected a value before ,".to_string());
+                }
+                groups.push(Parser::combine_components(std::mem::take(&mut components)));
+                continue;
+            }
+            components.push(self.parse_component()?);
+        }
+        if components.is_empty() {
+            return Err("Expected a value".to_string());
+        }
+        groups.push(Parser::combine_components(components));
+
+        if groups.len() == 1 {
+            Ok(groups.pop().unwrap())
+        } else {
+            Ok(css::Value::CommaList(groups))
+        }
+    }
+
+    fn combine_components(mut components: Vec<css::Value>) -> css::Value {
+        if components.len() == 1 {
+            components.pop().unwrap()
+        } else {
+            css::Value::List(components)
+        }
+    }
+
+    //a value ends at the `;` or at the `}` of a block whose last declaration has no `;`,
+    //or at the `!` of `!important`
+    fn at_value_end(&mut self) -> bool {
+        self.consume_whitespace();
+        !self.eof() && matches!(self.next_char(), ';' | '}' | '!')
+    }
+
+    fn starts_number(&self) -> bool {
+        let mut chars = self.input[self.pos..].chars();
+        match chars.next() {
+            Some('0'..='9') => true,
+            Some('.') => matches!(chars.next(), Some('0'..='9')),
+            Some('-') | Some('+') => matches!(chars.next(), Some('0'..='9') | Some('.')),
+            _ => false,
+        }
+    }
+
+    //a single component of a value: a number with its unit, a color, a string, a function,
+    //a keyword, grid line names in brackets or a slash
+    fn parse_component(&mut self) -> Result<css::Value, String> {
         let c = self.next_char();
 
         match c {
-            '0'..='9' => {
-                let val = f32::from_str(&self.consume_number()).unwrap(); //handle this too?
-                let unit = if self.starts_with("px") {
-                    self.consume_string("px");
-                    css::Unit::Px
-                } else {
-                    css::Unit::None
-                };
-                if self.next_char() == ';'{
-                    Ok(css::Value::Length(val, unit))
-                } else {
-                    Err("All declarations must be terminated by ;".to_string())
+            _ if self.starts_number() => {
+                let negative = self.consume_string("-").is_ok();
+                let _ = self.consume_string("+");
+                let val = f32::from_str(&self.consume_number()).map_err(|_| "Invalid number".to_string())?;
+                self.parse_dimension(if negative { -val } else { val })
+            },
+            '#' => {
+                self.consume_char();
+                let hex = self.parse_identifier().unwrap();
+                Parser::parse_hex_color(&hex).map(css::Value::Color).ok_or(format!("Invalid color #{}", hex))
+            },
+            '"' | '\'' => self.parse_string().map(css::Value::Str).ok_or_else(|| "Unterminated string".to_string()),
+            '/' => {
+                self.consume_char();
+                Ok(css::Value::Slash)
+            },
+            '[' => {
+                self.consume_char();
+                let mut names = vec![];
+                loop {
+                    self.consume_whitespace();
+                    if self.eof() {
+                        return Err("Unclosed line names".to_string());
+                    }
+                    if self.consume_string("]").is_ok() {
+                        return Ok(css::Value::LineNames(names));
+                    }
+                    match self.parse_identifier() {
+                        Some(name) if !name.is_empty() => names.push(name),
+                        _ => return Err(format!("Unexpected {} in line names", self.next_char())),
+                    }
                 }
             },
             _ => {
@@ -292,27 +577,84 @@ impl Parser {
                     if let Err(message) = val {
                         return Err(message.to_string());
                     }
-                    let val = css::Value::ColorValue(val.unwrap());
-                    if self.next_char() == ';'{
-                        Ok(val)
-                    } else {
-                        // println!("Not terminated wth");
-                        Err("All declarations must be terminated by ;".to_string())
-                    }
+                    Ok(css::Value::Color(val.unwrap()))
                 } else {
                     let val = self.parse_identifier().unwrap();
-                    if val.len() > 0 && self.next_char() == ';'{
-                        Ok(css::Value::Keyword(val))
+                    if val.is_empty() {
+                        return Err(format!("Unexpected {} in value", c));
+                    }
+                    if self.starts_with("(") {
+                        let inner = self.consume_parenthesized().map_err(|_| "Unclosed function".to_string())?;
+                        let args = Parser::new(inner).parse_function_args()?;
+                        Ok(css::Value::Function(val.to_ascii_lowercase(), args))
                     } else {
-                        Err("All declarations must be terminated by ;".to_string())
+                        Ok(css::Value::Keyword(val))
                     }
                 }
             }
         }
     }
 
+    //the arguments of a function, separated by commas or whitespace
+    fn parse_function_args(&mut self) -> Result<Vec<css::Value>, String> {
+        let mut args = vec![];
+        loop {
+            self.consume_whitespace();
+            if self.eof() {
+                break;
+            }
+            if self.consume_string(",").is_ok() {
+                continue;
+            }
+            args.push(self.parse_component()?);
+        }
+        Ok(args)
+    }
+
+    fn parse_dimension(&mut self, val: f32) -> Result<css::Value, String> {
+        if self.consume_string("%").is_ok() {
+            return Ok(css::Value::Percentage(val));
+        }
+        let unit = self.consume_while(|c| c.is_ascii_alphabetic());
+        match &*unit.to_ascii_lowercase() {
+            "px" => Ok(css::Value::Length(val, css::Unit::Px)),
+            "em" => Ok(css::Value::Length(val, css::Unit::Em)),
+            "rem" => Ok(css::Value::Length(val, css::Unit::Rem)),
+            "fr" => Ok(css::Value::Length(val, css::Unit::Fr)),
+            "" => Ok(css::Value::Length(val, css::Unit::None)),
+            "ms" => Ok(css::Value::Time(val)),
+            "s" => Ok(css::Value::Time(val * 1000.0)),
+            "deg" => Ok(css::Value::Angle(val)),
+            "grad" => Ok(css::Value::Angle(val * 0.9)),
+            "rad" => Ok(css::Value::Angle(val.to_degrees())),
+            "turn" => Ok(css::Value::Angle(val * 360.0)),
+            _ => Err(format!("Unknown unit {}", unit)),
+        }
+    }
+
+    //#rgb, #rgba, #rrggbb or #rrggbbaa
+    fn parse_hex_color(hex: &str) -> Option<css::Color> {
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let digits: Vec<u8> = match hex.len() {
+            3 | 4 => hex.chars().map(|c| {
+                let digit = c.to_digit(16).unwrap() as u8;
+                digit * 16 + digit
+            }).collect(),
+            6 | 8 => (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect(),
+            _ => return None,
+        };
+        Some(css::Color {
+            r: digits[0],
+            g: digits[1],
+            b: digits[2],
+            a: digits.get(3).copied().unwrap_or(255),
+        })
+    }
+
     fn parse_color(&mut self) -> Result<css::Color, &str>{
-        self.consume_string("rgb");
+        let _ = self.consume_string("rgb");
         let hasalpha = self.next_char() == 'a';
         if hasalpha {
             self.consume_char(); //consume 'a'
@@ -320,7 +662,7 @@ impl Parser {
         self.consume_char(); //consume '('
         self.consume_whitespace();
         let number = u8::from_str(&self.consume_number());
-        if let Err(_) = number {
+        if number.is_err() {
             return Err("Incorrect format for color. Expected u8 values (u8,u8,u8[,u8])");
         }
         let r = number.unwrap();
@@ -332,7 +674,7 @@ impl Parser {
         self.consume_char();
         self.consume_whitespace();
         let number = u8::from_str(&self.consume_number());
-        if let Err(_) = number {
+        if number.is_err() {
             return Err("Incorrect format for color. Expected u8 values (u8,u8,u8[,u8])");
         }
         let g = number.unwrap();
@@ -343,7 +685,7 @@ impl Parser {
         self.consume_char();
         self.consume_whitespace();
         let number = u8::from_str(&self.consume_number());
-        if let Err(_) = number {
+        if number.is_err() {
             return Err("Incorrect format for color. Expected u8 values (u8,u8,u8[,u8])");
         }
         let b = number.unwrap();
@@ -356,7 +698,7 @@ impl Parser {
             self.consume_char();
             self.consume_whitespace();
             let number = u8::from_str(&self.consume_number());
-            if let Err(_) = number {
+            if number.is_err() {
                 return Err("Incorrect format for color. Expected u8 values (u8,u8,u8[,u8])");
             }
             Ok(number.unwrap())
@@ -374,15 +716,700 @@ impl Parser {
     }
 
     fn parse_style(&mut self) -> css::Stylesheet {
+        css::Stylesheet {url: String::new(), rules: self.parse_rule_list(false)}
+    }
+
+    //parses rules till the end of input, or for a nested list till the `}` that closes the block
+    fn parse_rule_list(&mut self, nested: bool) -> Vec<css::CssRule> {
         let mut rules = vec![];
+        //@import is only valid at the top, before everything but @charset and @layer statements
+        let mut imports_allowed = !nested;
 
-        while !self.eof() {
+        loop {
+            self.consume_whitespace();
+            if self.eof() {
+                break;
+            }
+            match self.next_char() {
+                '}' => {
+                    self.consume_char();
+                    if nested {
+                        break;
+                    }
+                },
+                '@' => {
+                    match self.parse_at_rule(None) {
+                        Some(css::CssRule::Import(_)) if !imports_allowed => {},
+                        Some(rule) => {
+                            if !matches!(rule, css::CssRule::Import(_) | css::CssRule::LayerStatement(_)) {
+                                imports_allowed = false;
+                            }
+                            rules.push(rule);
+                        },
+                        None => {},
+                    }
+                },
+                _ => {
+                    imports_allowed = false;
+                    if let Some(ruru) = self.parse_rule(None) {
+                        // println!("Parsed a ruru!!\n{}", ruru);
+                        rules.push(css::CssRule::Style(ruru));
+                    }
+                }
+            }
+        }
+
+        rules
+    }
+
+    //`nesting` has the selectors of the style rule an at-rule is nested in, if it is
+    fn parse_at_rule(&mut self, nesting: Option<&[css::Selector]>) -> Option<css::CssRule> {
+        self.consume_char(); //consume '@'
+        let name = self.parse_keyword();
+        let prelude = self.consume_at_rule_prelude();
+        if self.eof() {
+            return None;
+        }
+        if self.consume_char() == ';' {
+            if nesting.is_some() {
+                return None;
+            }
+            return match &*name {
+                "import" => parse_import_prelude(&prelude).map(css::CssRule::Import),
+                "layer" => parse_layer_names(&prelude).map(css::CssRule::LayerStatement),
+                //@charset and statement at-rules we don't support
+                _ => None,
+            };
+        }
+
+        match &*name {
+            "media" => {
+                let queries = parse_media_query_list(&prelude);
+                let rules = self.parse_conditional_body(nesting);
+                Some(css::CssRule::Media(css::MediaRule {queries, rules}))
+            },
+            "supports" => {
+                let condition = Parser::new(prelude).parse_supports_condition_list();
+                let rules = self.parse_conditional_body(nesting);
+                condition.map(|condition| css::CssRule::Supports(css::SupportsRule {condition, rules}))
+            },
+            "layer" => {
+                let name = if prelude.trim().is_empty() {
+                    None
+                } else {
+                    match parse_layer_names(&prelude) {
+                        Some(mut names) if names.len() == 1 => names.pop(),
+                        _ => {
+                            self.skip_block();
+                            return None;
+                        }
+                    }
+                };
+                let rules = self.parse_conditional_body(nesting);
+                Some(css::CssRule::Layer(css::LayerRule {name, rules}))
+            },
+            "keyframes" | "-webkit-keyframes" if nesting.is_none() => {
+                let name = prelude.trim().trim_matches(|c| c == '"' || c == '\'').to_string();
+                let keyframes = self.parse_keyframe_blocks();
+                if name.is_empty() {
+                    return None;
+                }
+                Some(css::CssRule::Keyframes(css::KeyframesRule {name, keyframes}))
+            },
+            "font-face" if prelude.trim().is_empty() && nesting.is_none() => {
+                Some(css::CssRule::FontFace(css::FontFaceRule {descriptors: self.parse_descriptors()}))
+            },
+            _ => {
+                self.skip_block();
+                None
+            }
+        }
+    }
+
+    //the `from { ... } 50% { ... }` blocks of a @keyframes rule, up to and including its `}`
+    fn parse_keyframe_blocks(&mut self) -> Vec<css::Keyframe> {
+        let mut keyframes = vec![];
+
+        loop {
             self.consume_whitespace();
-            let ruru = self.parse_rule();
-            // println!("Parsed a ruru!!\n{}", ruru);
-            rules.push(ruru);
+            if self.eof() || self.consume_string("}").is_ok() {
+                break;
+            }
+            let selector = self.consume_while(|c| c != '{' && c != '}');
+            if self.consume_string("{").is_err() {
+                continue;
+            }
+            let (mut declarations, _) = self.parse_block_contents(&[]);
+            //these only say how to get to the next keyframe, they don't animate
+            declarations.retain(|declaration| !declaration.name.starts_with("animation") || declaration.name == "animation-timing-function");
+
+            let offsets: Option<Vec<f32>> = selector.split(',').map(|offset| {
+                match &*offset.trim().to_ascii_lowercase() {
+                    "from" => Some(0.0),
+                    "to" => Some(1.0),
+                    offset => offset.strip_suffix('%')
+                        .and_then(|percentage| f32::from_str(percentage).ok())
+                        .filter(|percentage| (0.0..=100.0).contains(percentage))
+                        .map(|percentage| percentage / 100.0),
+                }
+            }).collect();
+            if let Some(offsets) = offsets {
+                keyframes.push(css::Keyframe {offsets, declarations});
+            }
+        }
+
+        keyframes
+    }
+
+    //the block of an @media, @supports or @layer rule. Nested in a style rule it can hold
+    //declarations, which then apply to the style rule's selectors
+    fn parse_conditional_body(&mut self, nesting: Option<&[css::Selector]>) -> Vec<css::CssRule> {
+        match nesting {
+            None => self.parse_rule_list(true),
+            Some(selectors) => {
+                let (declarations, mut rules) = self.parse_block_contents(selectors);
+                if !declarations.is_empty() {
+                    rules.insert(0, css::CssRule::Style(css::Rule {
+                        selectors: selectors.to_vec(),
+                        declarations,
+                        children: vec![],
+                    }));
+                }
+                rules
+            }
         }
+    }
 
-        css::Stylesheet {rules}
+    //everything up to the `{` or `;` ending the prelude, skipping over strings and parentheses
+    fn consume_at_rule_prelude(&mut self) -> String {
+        let mut depth = 0;
+        let mut quote = None;
+        self.consume_while(|c| {
+            match (quote, c) {
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), _) => {},
+                (None, '"') | (None, '\'') => quote = Some(c),
+                (None, '(') => depth += 1,
+                (None, ')') => depth -= 1,
+                (None, '{') | (None, ';') if depth <= 0 => return false,
+                _ => {},
+            }
+            true
+        })
     }
-}
\ No newline at end of file
+
+    //`name: value;` pairs with the value kept as text, for at-rules like @font-face whose
+    //descriptors aren't properties
+    fn parse_descriptors(&mut self) -> Vec<(String, String)> {
+        let mut descriptors = vec![];
+
+        loop {
+            self.consume_whitespace();
+            if self.eof() {
+                break;
+            }
+            if self.consume_string("}").is_ok() {
+                break;
+            }
+            let name = self.parse_keyword();
+            self.consume_whitespace();
+            if name.is_empty() || self.consume_string(":").is_err() {
+                self.consume_while(|c| c != ';' && c != '}');
+                let _ = self.consume_string(";");
+                continue;
+            }
+            let mut quote = None;
+            let value = self.consume_while(|c| {
+                match (quote, c) {
+                    (Some(q), c) if c == q => quote = None,
+                    (Some(_), _) => {},
+                    (None, '"') | (None, '\'') => quote = Some(c),
+                    (None, ';') | (None, '}') => return false,
+                    _ => {},
+                }
+                true
+            });
+            let _ = self.consume_string(";");
+            descriptors.push((name, value.trim().to_string()));
+        }
+
+        descriptors
+    }
+
+    //the condition of @supports, or the inside of an @import's supports()
+    fn parse_supports_condition_list(&mut self) -> Option<css::SupportsCondition> {
+        let condition = self.parse_supports_condition().ok()?;
+        self.consume_whitespace();
+        if self.eof() {
+            Some(condition)
+        } else {
+            None
+        }
+    }
+
+    fn parse_supports_condition(&mut self) -> Result<css::SupportsCondition, ()> {
+        self.consume_whitespace();
+        if self.starts_with_not_condition() {
+            self.parse_keyword();
+            return Ok(css::SupportsCondition::Not(Box::new(self.parse_supports_in_parens()?)));
+        }
+
+        let mut conditions = vec![self.parse_supports_in_parens()?];
+        let mut combinator = String::new();
+        loop {
+            self.consume_whitespace();
+            if self.eof() || self.next_char() == ')' {
+                break;
+            }
+            let keyword = self.parse_keyword();
+            if (keyword != "and" && keyword != "or") || (!combinator.is_empty() && combinator != keyword) {
+                return Err(());
+            }
+            combinator = keyword;
+            conditions.push(self.parse_supports_in_parens()?);
+        }
+
+        Ok(match &*combinator {
+            "and" => css::SupportsCondition::And(conditions),
+            "or" => css::SupportsCondition::Or(conditions),
+            _ => conditions.remove(0),
+        })
+    }
+
+    fn parse_supports_in_parens(&mut self) -> Result<css::SupportsCondition, ()> {
+        self.consume_whitespace();
+        let function = self.parse_keyword();
+        if self.eof() || self.next_char() != '(' {
+            return Err(());
+        }
+        let inner = self.consume_parenthesized()?;
+
+        if function == "selector" {
+            let mut parser = Parser::new(inner.clone());
+            let selector = parser.parse_complex_selector(None, false, &mut false);
+            parser.consume_whitespace();
+            let valid = selector.is_ok() && parser.eof();
+            return Ok(css::SupportsCondition::Selector(inner.trim().to_string(), valid));
+        }
+        if !function.is_empty() {
+            return Ok(css::SupportsCondition::Unknown(format!("{}({})", function, inner)));
+        }
+
+        let mut parser = Parser::new(inner.clone());
+        parser.consume_whitespace();
+        if parser.eof() {
+            return Ok(css::SupportsCondition::Unknown(format!("({})", inner)));
+        }
+        if parser.next_char() == '(' || parser.starts_with_not_condition() {
+            return Ok(parser.parse_supports_condition_list()
+                .unwrap_or_else(|| css::SupportsCondition::Unknown(format!("({})", inner))));
+        }
+
+        let name = parser.parse_keyword();
+        parser.consume_whitespace();
+        if name.is_empty() || parser.consume_string(":").is_err() {
+            return Ok(css::SupportsCondition::Unknown(format!("({})", inner)));
+        }
+        parser.consume_whitespace();
+        //the value has to be all that's left, so terminate it like a declaration would be
+        let mut parser = Parser::new(format!("{};", &parser.input[parser.pos..].trim_end()));
+        let value = parser.parse_value().ok().filter(|_| parser.consume_string(";").is_ok() && parser.eof());
+        Ok(css::SupportsCondition::Declaration(name, value))
+    }
+
+    //consumes `( ... )` and returns what's inside the parentheses
+    fn consume_parenthesized(&mut self) -> Result<String, ()> {
+        if self.consume_string("(").is_err() {
+            return Err(());
+        }
+        let mut depth = 1;
+        let inner = self.consume_while(|c| {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                _ => {}
+            }
+            depth > 0
+        });
+        if self.consume_string(")").is_err() {
+            return Err(());
+        }
+        Ok(inner)
+    }
+
+    //skips the contents of a block whose `{` was already consumed, along with its `}`
+    fn skip_block(&mut self) {
+        let mut depth = 1;
+        while !self.eof() && depth > 0 {
+            match self.consume_char() {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+
+    fn parse_media_query(&mut self) -> Result<css::MediaQuery, ()> {
+        self.consume_whitespace();
+        if self.eof() {
+            return Err(());
+        }
+
+        let query = if self.next_char() == '(' || self.starts_with_not_condition() {
+            css::MediaQuery {
+                qualifier: None,
+                media_type: css::MediaType::All,
+                condition: Some(self.parse_media_condition(true)?),
+            }
+        } else {
+            let mut ident = self.parse_keyword();
+            let qualifier = match &*ident {
+                "not" => Some(css::MediaQualifier::Not),
+                "only" => Some(css::MediaQualifier::Only),
+                _ => None,
+            };
+            if qualifier.is_some() {
+                self.consume_whitespace();
+                ident = self.parse_keyword();
+            }
+            let media_type = match &*ident {
+                "" | "not" | "only" | "and" | "or" | "layer" => return Err(()),
+                "all" => css::MediaType::All,
+                "screen" => css::MediaType::Screen,
+                "print" => css::MediaType::Print,
+                _ => css::MediaType::Other(ident),
+            };
+            self.consume_whitespace();
+            let condition = if self.eof() {
+                None
+            } else {
+                if self.parse_keyword() != "and" {
+                    return Err(());
+                }
+                Some(self.parse_media_condition(false)?)
+            };
+            css::MediaQuery {qualifier, media_type, condition}
+        };
+
+        self.consume_whitespace();
+        if !self.eof() {
+            return Err(());
+        }
+        Ok(query)
+    }
+
+    fn starts_with_not_condition(&self) -> bool {
+        let rest = &self.input[self.pos..];
+        //a non-ASCII character may start in the first three bytes, where slicing would panic
+        rest.get(..3).is_some_and(|not| not.eq_ignore_ascii_case("not")) && rest[3..].trim_start().starts_with('(')
+    }
+
+    //url(...), url("..."), or a bare string as allowed in @import
+    fn parse_url(&mut self) -> Option<String> {
+        if self.input[self.pos..].to_ascii_lowercase().starts_with("url(") {
+            self.consume_while(|c| c != '(');
+            let inner = self.consume_parenthesized().ok()?;
+            let inner = inner.trim();
+            let unquoted = inner.trim_matches(|c| c == '"' || c == '\'');
+            Some(unquoted.to_string())
+        } else {
+            self.parse_string()
+        }
+    }
+
+    fn parse_string(&mut self) -> Option<String> {
+        if self.eof() {
+            return None;
+        }
+        let quote = self.next_char();
+        if quote != '"' && quote != '\'' {
+            return None;
+        }
+        self.consume_char();
+        let string = self.consume_while(|c| c != quote);
+        self.consume_string(&quote.to_string()).ok()?;
+        Some(string)
+    }
+
+    fn parse_keyword(&mut self) -> String {
+        self.parse_identifier().unwrap().to_ascii_lowercase()
+    }
+
+    //<media-condition>, or <media-condition-without-or> after a media type
+    fn parse_media_condition(&mut self, allow_or: bool) -> Result<css::MediaCondition, ()> {
+        self.consume_whitespace();
+        if self.starts_with_not_condition() {
+            self.parse_keyword();
+            self.consume_whitespace();
+            return Ok(css::MediaCondition::Not(Box::new(self.parse_media_in_parens()?)));
+        }
+
+        let mut conditions = vec![self.parse_media_in_parens()?];
+        let mut combinator = String::new();
+        loop {
+            self.consume_whitespace();
+            if self.eof() || self.next_char() == ')' {
+                break;
+            }
+            let keyword = self.parse_keyword();
+            let allowed = match &*keyword {
+                "and" => combinator.is_empty() || combinator == "and",
+                "or" => allow_or && (combinator.is_empty() || combinator == "or"),
+                _ => false,
+            };
+            if !allowed {
+                return Err(());
+            }
+            combinator = keyword;
+            conditions.push(self.parse_media_in_parens()?);
+        }
+
+        Ok(match &*combinator {
+            "and" => css::MediaCondition::And(conditions),
+            "or" => css::MediaCondition::Or(conditions),
+            _ => conditions.remove(0),
+        })
+    }
+
+    fn parse_media_in_parens(&mut self) -> Result<css::MediaCondition, ()> {
+        self.consume_whitespace();
+        let inner = self.consume_parenthesized()?;
+
+        let mut parser = Parser::new(inner.clone());
+        parser.consume_whitespace();
+        let nested = if parser.eof() {
+            Err(())
+        } else if parser.next_char() == '(' || parser.starts_with_not_condition() {
+            parser.parse_media_condition(true)
+        } else {
+            parser.parse_media_feature().map(css::MediaCondition::Feature)
+        };
+        parser.consume_whitespace();
+        match nested {
+            Ok(condition) if parser.eof() => Ok(condition),
+            _ => Ok(css::MediaCondition::Unknown(format!("({})", inner))),
+        }
+    }
+
+    fn parse_media_feature(&mut self) -> Result<css::MediaFeature, ()> {
+        let c = self.next_char();
+        if c.is_ascii_digit() || c == '.' || c == '-' && self.input[self.pos + 1..].starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+            //<mf-value> <op> <mf-name> [<op> <mf-value>]
+            let low = self.parse_media_value()?;
+            self.consume_whitespace();
+            let first = self.parse_media_comparison().ok_or(())?;
+            self.consume_whitespace();
+            let name = self.parse_feature_name()?;
+            let mut comparisons = vec![(first.flip(), low)];
+            self.consume_whitespace();
+            if let Some(second) = self.parse_media_comparison() {
+                let ascending = |op| op == css::MediaComparison::Lt || op == css::MediaComparison::Le;
+                let descending = |op| op == css::MediaComparison::Gt || op == css::MediaComparison::Ge;
+                if !(ascending(first) && ascending(second) || descending(first) && descending(second)) {
+                    return Err(());
+                }
+                self.consume_whitespace();
+                comparisons.push((second, self.parse_media_value()?));
+            }
+            return Ok(css::MediaFeature::Range(name, comparisons));
+        }
+
+        let name = self.parse_feature_name()?;
+        self.consume_whitespace();
+        if self.eof() {
+            return Ok(css::MediaFeature::Boolean(name));
+        }
+        if self.next_char() == ':' {
+            self.consume_char();
+            self.consume_whitespace();
+            return Ok(css::MediaFeature::Plain(name, self.parse_media_value()?));
+        }
+        let op = self.parse_media_comparison().ok_or(())?;
+        if name.starts_with("min-") || name.starts_with("max-") {
+            return Err(());
+        }
+        self.consume_whitespace();
+        Ok(css::MediaFeature::Range(name, vec![(op, self.parse_media_value()?)]))
+    }
+
+    fn parse_feature_name(&mut self) -> Result<String, ()> {
+        let name = self.parse_keyword();
+        if name.is_empty() {
+            Err(())
+        } else {
+            Ok(name)
+        }
+    }
+
+    fn parse_media_comparison(&mut self) -> Option<css::MediaComparison> {
+        let ops = [
+            ("<=", css::MediaComparison::Le),
+            (">=", css::MediaComparison::Ge),
+            ("<", css::MediaComparison::Lt),
+            (">", css::MediaComparison::Gt),
+            ("=", css::MediaComparison::Eq),
+        ];
+        for (text, op) in ops.iter() {
+            if self.consume_string(text).is_ok() {
+                return Some(*op);
+            }
+        }
+        None
+    }
+
+    fn parse_media_value(&mut self) -> Result<css::MediaValue, ()> {
+        if self.eof() {
+            return Err(());
+        }
+        if self.next_char().is_ascii_alphabetic() {
+            return Ok(css::MediaValue::Ident(self.parse_keyword()));
+        }
+
+        let negative = self.consume_string("-").is_ok();
+        let number = f32::from_str(&self.consume_number()).map_err(|_| ())?;
+        let number = if negative { -number } else { number };
+
+        let rest = self.input[self.pos..].trim_start();
+        if rest.starts_with('/') {
+            self.consume_whitespace();
+            self.consume_char(); //consume '/'
+            self.consume_whitespace();
+            let denominator = f32::from_str(&self.consume_number()).map_err(|_| ())?;
+            return Ok(css::MediaValue::Ratio(number, denominator));
+        }
+
+        let unit = self.consume_while(|c| c.is_ascii_alphabetic()).to_ascii_lowercase();
+        Ok(match &*unit {
+            "" => css::MediaValue::Number(number),
+            "px" => css::MediaValue::Length(number, css::Unit::Px),
+            "em" => css::MediaValue::Length(number, css::Unit::Em),
+            "rem" => css::MediaValue::Length(number, css::Unit::Rem),
+            "cm" => css::MediaValue::Length(number * 96.0 / 2.54, css::Unit::Px),
+            "mm" => css::MediaValue::Length(number * 96.0 / 25.4, css::Unit::Px),
+            "q" => css::MediaValue::Length(number * 96.0 / 101.6, css::Unit::Px),
+            "in" => css::MediaValue::Length(number * 96.0, css::Unit::Px),
+            "pt" => css::MediaValue::Length(number * 96.0 / 72.0, css::Unit::Px),
+            "pc" => css::MediaValue::Length(number * 16.0, css::Unit::Px),
+            "dppx" | "x" => css::MediaValue::Resolution(number),
+            "dpi" => css::MediaValue::Resolution(number / 96.0),
+            "dpcm" => css::MediaValue::Resolution(number * 2.54 / 96.0),
+            _ => return Err(()),
+        })
+    }
+}
+
+//parses a value on its own, like the part of a declaration after the colon
+pub fn parse_value(source: &str) -> Option<css::Value> {
+    let mut parser = Parser::new(format!("{};", source.trim()));
+    let value = parser.parse_value().ok()?;
+    if parser.consume_string(";").is_ok() && parser.eof() {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+//splits the prelude of an @media rule at its top level commas; a query that fails to parse
+//becomes `not all` without affecting the others
+pub fn parse_media_query_list(source: &str) -> css::MediaQueryList {
+    if source.trim().is_empty() {
+        return css::MediaQueryList {queries: vec![]};
+    }
+
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in source.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&source[start..i]);
+                start = i + 1;
+            },
+            _ => {}
+        }
+    }
+    parts.push(&source[start..]);
+
+    let queries = parts.into_iter().map(|part| {
+        Parser::new(part.to_string()).parse_media_query().unwrap_or_else(|_| css::MediaQuery::not_all())
+    }).collect();
+
+    css::MediaQueryList {queries}
+}
+
+//the part of an @import after the url, in the order url, layer, supports(), media queries
+fn parse_import_prelude(source: &str) -> Option<css::ImportRule> {
+    let mut parser = Parser::new(source.to_string());
+    parser.consume_whitespace();
+    let url = parser.parse_url()?;
+
+    parser.consume_whitespace();
+    let mut layer = None;
+    if parser.input[parser.pos..].to_ascii_lowercase().starts_with("layer") {
+        let start = parser.pos;
+        parser.parse_keyword();
+        if parser.starts_with("(") {
+            let name = parser.consume_parenthesized().ok()?;
+            layer = Some(parse_layer_names(&name).filter(|names| names.len() == 1)?.remove(0));
+        } else if parser.eof() || parser.next_char().is_whitespace() {
+            layer = Some(vec![]);
+        } else {
+            parser.pos = start;
+        }
+    }
+
+    parser.consume_whitespace();
+    let mut supports = None;
+    if parser.input[parser.pos..].to_ascii_lowercase().starts_with("supports(") {
+        parser.parse_keyword();
+        let inner = parser.consume_parenthesized().ok()?;
+        let mut condition_parser = Parser::new(inner.clone());
+        //supports() also takes a bare declaration, without the parentheses around it
+        supports = Some(condition_parser.parse_supports_condition_list().or_else(|| {
+            Parser::new(format!("({})", inner)).parse_supports_condition_list()
+        })?);
+    }
+
+    let queries = parse_media_query_list(&parser.input[parser.pos..]);
+    Some(css::ImportRule {url, layer, supports, queries, stylesheet: None})
+}
+
+//a comma separated list of dotted layer names
+fn parse_layer_names(source: &str) -> Option<Vec<css::LayerName>> {
+    source.split(',').map(|name| {
+        let parts: Vec<String> = name.trim().split('.').map(|part| part.to_string()).collect();
+        let valid = parts.iter().all(|part| !part.is_empty() && part.chars().all(Parser::valid_identifier_char));
+        if valid {
+            Some(parts)
+        } else {
+            None
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_ascii_preludes_dont_panic() {
+        for source in [
+            "@media ññ {}",
+            "@media ñot (width) {}",
+            "@media nñ {} p { color: red }",
+            "@supports ññ {}",
+            "@supports nñ(display: block) {}",
+            "@import url(a.css) ññ;",
+            "@import url(a.css) supports(ññ) ñ;",
+            "@media (ñ) and ññ, ñ {}",
+        ] {
+            parse(source.to_string());
+        }
+        //the rules after a prelude that doesn't parse are still read
+        let sheet = parse("@media ññ { p { color: red } } p { color: blue }".to_string());
+        assert!(matches!(sheet.rules.last(), Some(css::CssRule::Style(_))));
+        parse_media_query_list("ñot ñ, ññ");
+    }
+}
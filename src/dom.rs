@@ -45,7 +45,7 @@ pub fn text(data: String) -> Node {
 
 pub fn elem(name: String, attrs: AttrMap, children: Vec<Node>) -> Node {
     Node {
-        children: children,
+        children,
         node_type: NodeType::Element(ElementData {
             tag_name: name,
             attributes: attrs,
@@ -63,14 +63,11 @@ impl fmt::Display for Node {
             NodeType::Element(elem_data) => {
                 write!(f, "<{}{}>\n{}\n</{}>",
                             elem_data.tag_name,
-                            match elem_data.attributes.iter().map(|(key, val)| {
+                            elem_data.attributes.iter().map(|(key, val)| {
                                 format!(" {}='{}'", key, val)
                             }).reduce(|a, b| {
                                 a + &b
-                            }) {
-                                Some(text)=> text,
-                                None => String::new()
-                            },
+                            }).unwrap_or_default(),
                             self.children.iter()
                                 .map(|x| format!("{}",x))
                                 .collect::<Vec<String>>().join("\n"),
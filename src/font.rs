@@ -0,0 +1,444 @@
+// Fonts for text layout: a database of the faces that can be used, matched against
+// font-family, font-weight and font-style with fallback for each character, and shaping text
+// into positioned glyphs. Fonts come from files and @font-face rules, and a fallback font is
+// bundled so text always has one; no system font service is asked.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+mod sfnt;
+mod shaping;
+
+pub use sfnt::Face;
+
+use crate::bidi;
+use crate::css::CssRule;
+use crate::css::Stylesheet;
+use crate::css::Unit;
+use crate::css::Value;
+use crate::properties;
+use crate::resource;
+use crate::resource::ResourceLoader;
+use crate::style::PropertyMap;
+
+// DejaVu Sans, see fonts/LICENSE-DejaVu.txt.
+const FALLBACK_FONT: &[u8] = include_bytes!("../fonts/DejaVuSans.ttf");
+const FALLBACK_FAMILY: &str = "DejaVu Sans";
+
+const GENERIC_FAMILIES: &[&str] = &["serif", "sans-serif", "monospace", "cursive", "fantasy", "system-ui"];
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct FaceId(usize);
+
+impl fmt::Display for FaceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Family {
+    Name(String),
+    // one of GENERIC_FAMILIES
+    Generic(String),
+}
+
+// The font a piece of text asks for, from its computed style.
+#[derive(Clone, PartialEq, Debug)]
+pub struct FontQuery {
+    pub families: Vec<Family>,
+    pub weight: u16,
+    pub italic: bool,
+}
+
+impl FontQuery {
+    pub fn from_style(style: &PropertyMap) -> FontQuery {
+        let family = |value: &Value| match value {
+            Value::Keyword(keyword) if GENERIC_FAMILIES.contains(&&*keyword.to_ascii_lowercase()) => Some(Family::Generic(keyword.to_ascii_lowercase())),
+            Value::Keyword(keyword) | Value::Str(keyword) => Some(Family::Name(keyword.clone())),
+            //unquoted names of several words
+            Value::List(words) => words.iter()
+                .map(|word| match word {
+                    Value::Keyword(word) => Some(word.as_str()),
+                    _ => None,
+                })
+                .collect::<Option<Vec<&str>>>()
+                .map(|words| Family::Name(words.join(" "))),
+            _ => None,
+        };
+        let families = match style.get("font-family") {
+            Some(Value::CommaList(values)) => values.iter().filter_map(family).collect(),
+            Some(value) => family(value).into_iter().collect(),
+            None => vec![],
+        };
+        let weight = match style.get("font-weight") {
+            Some(Value::Length(weight, Unit::None)) => weight.clamp(1.0, 1000.0) as u16,
+            _ => 400,
+        };
+        let italic = matches!(style.get("font-style"), Some(Value::Keyword(keyword)) if keyword == "italic" || keyword == "oblique");
+        FontQuery {families, weight, italic}
+    }
+}
+
+// The vertical metrics of a font at some size, in px. The descent is below the baseline and
+// positive.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FontMetrics {
+    pub size: f32,
+    pub ascent: f32,
+    pub descent: f32,
+    pub line_gap: f32,
+    pub x_height: f32,
+}
+
+// A glyph placed on a line, `x` being where its origin is relative to the start of the
+// shaped text. `cluster` is the byte offset in the text of the first character it shows.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ShapedGlyph {
+    pub face: FaceId,
+    pub glyph: u16,
+    pub cluster: usize,
+    pub x: f32,
+    pub advance: f32,
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct ShapedText {
+    pub glyphs: Vec<ShapedGlyph>,
+    pub width: f32,
+}
+
+pub struct FontDatabase {
+    faces: Vec<Face>,
+    // the family each generic family stands for
+    generic_families: HashMap<String, String>,
+}
+
+impl Default for FontDatabase {
+    fn default() -> FontDatabase {
+        FontDatabase::new()
+    }
+}
+
+impl FontDatabase {
+    // A database with only the bundled font, which every generic family is.
+    pub fn new() -> FontDatabase {
+        let mut fonts = FontDatabase {faces: vec![], generic_families: HashMap::new()};
+        fonts.add_font(FALLBACK_FONT.to_vec()).expect("the bundled font is a valid font");
+        for generic in GENERIC_FAMILIES {
+            fonts.set_generic_family(generic, FALLBACK_FAMILY);
+        }
+        fonts
+    }
+
+    // Adds every face of a TrueType or OpenType font, or of a collection of them.
+    pub fn add_font(&mut self, data: Vec<u8>) -> Result<Vec<FaceId>, String> {
+        let data: Arc<[u8]> = data.into();
+        let faces = (0..Face::count(&data)).map(|index| Face::parse(data.clone(), index)).collect::<Result<Vec<Face>, String>>()?;
+        Ok(faces.into_iter().map(|face| self.add_face(face)).collect())
+    }
+
+    fn add_face(&mut self, face: Face) -> FaceId {
+        self.faces.push(face);
+        FaceId(self.faces.len() - 1)
+    }
+
+    pub fn load_file(&mut self, path: &Path) -> Result<Vec<FaceId>, String> {
+        let data = fs::read(path).map_err(|err| format!("Couldn't load {}: {}", path.display(), err))?;
+        self.add_font(data).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    // Adds the fonts in a directory and the ones under it, skipping files that aren't fonts.
+    // Returns how many faces were added.
+    pub fn load_directory(&mut self, dir: &Path) -> usize {
+        let mut entries: Vec<_> = match fs::read_dir(dir) {
+            Ok(entries) => entries.filter_map(Result::ok).map(|entry| entry.path()).collect(),
+            Err(_) => return 0,
+        };
+        //so the same fonts always end up in the same order
+        entries.sort();
+        entries.iter().map(|path| {
+            let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("").to_ascii_lowercase();
+            if path.is_dir() {
+                self.load_directory(path)
+            } else if matches!(&*extension, "ttf" | "otf" | "ttc" | "otc") {
+                self.load_file(path).map_or(0, |faces| faces.len())
+            } else {
+                0
+            }
+        }).sum()
+    }
+
+    // Adds the fonts in the directories they're usually installed in on Linux, macOS and
+    // Windows, by reading the directories.
+    pub fn load_system_fonts(&mut self) -> usize {
+        let mut dirs = vec![
+            "/usr/share/fonts".to_string(),
+            "/usr/local/share/fonts".to_string(),
+            "/System/Library/Fonts".to_string(),
+            "/Library/Fonts".to_string(),
+            "C:\\Windows\\Fonts".to_string(),
+        ];
+        if let Ok(home) = std::env::var("HOME") {
+            dirs.push(format!("{}/.fonts", home));
+            dirs.push(format!("{}/.local/share/fonts", home));
+            dirs.push(format!("{}/Library/Fonts", home));
+        }
+        dirs.iter().map(|dir| self.load_directory(Path::new(dir))).sum()
+    }
+
+    pub fn set_generic_family(&mut self, generic: &str, family: &str) {
+        self.generic_families.insert(generic.to_ascii_lowercase(), family.to_string());
+    }
+
+    // Adds the faces of the @font-face rules in a stylesheet and those it imports, under the
+    // family, weight and style the rules give them. The first source of each that loads is
+    // used. @font-face rules inside @media are left out, since fonts aren't reloaded when the
+    // media changes. Returns why the faces that couldn't be added couldn't be.
+    pub fn load_font_faces(&mut self, stylesheet: &Stylesheet, loader: &dyn ResourceLoader) -> Vec<String> {
+        let mut errors = vec![];
+        self.load_font_face_rules(&stylesheet.rules, &stylesheet.url, loader, &mut errors);
+        errors
+    }
+
+    fn load_font_face_rules(&mut self, rules: &[CssRule], url: &str, loader: &dyn ResourceLoader, errors: &mut Vec<String>) {
+        for rule in rules {
+            match rule {
+                CssRule::FontFace(rule) => {
+                    if let Err(err) = self.load_font_face(&rule.descriptors, url, loader) {
+                        errors.push(err);
+                    }
+                },
+                CssRule::Import(import) => {
+                    if let Some(stylesheet) = &import.stylesheet {
+                        self.load_font_face_rules(&stylesheet.rules, &stylesheet.url, loader, errors);
+                    }
+                },
+                CssRule::Layer(layer) => self.load_font_face_rules(&layer.rules, url, loader, errors),
+                CssRule::Supports(supports) if properties::supports(&supports.condition) => {
+                    self.load_font_face_rules(&supports.rules, url, loader, errors);
+                },
+                _ => {},
+            }
+        }
+    }
+
+    fn load_font_face(&mut self, descriptors: &[(String, String)], url: &str, loader: &dyn ResourceLoader) -> Result<(), String> {
+        let descriptor = |name: &str| descriptors.iter().rev().find(|(descriptor, _)| descriptor == name).map(|(_, value)| value.as_str());
+        let family = descriptor("font-family").map(unquote).ok_or("@font-face without a font-family")?;
+        let src = descriptor("src").ok_or_else(|| format!("@font-face for {} without a src", family))?;
+
+        let mut face = None;
+        for source in split_outside_parens(src) {
+            let (function, argument) = match source.split_once('(') {
+                Some((function, rest)) => (function.trim().to_ascii_lowercase(), unquote(rest.split(')').next().unwrap_or(""))),
+                None => continue,
+            };
+            //only plain font files can be read, not WOFF or the like
+            let format = source.to_ascii_lowercase().split("format(").nth(1).map(|format| unquote(format.split(')').next().unwrap_or("")));
+            if format.as_ref().is_some_and(|format| !matches!(&**format, "truetype" | "opentype" | "collection")) {
+                continue;
+            }
+            face = match &*function {
+                "url" => loader.load(&resource::resolve_url(url, &argument)).ok()
+                    .and_then(|data| Face::parse(data.into(), 0).ok()),
+                "local" => self.faces.iter()
+                    .find(|face| face.full_name.eq_ignore_ascii_case(&argument) || face.postscript_name.eq_ignore_ascii_case(&argument))
+                    .cloned(),
+                _ => None,
+            };
+            if face.is_some() {
+                break;
+            }
+        }
+        let mut face = face.ok_or_else(|| format!("None of the sources of @font-face for {} could be loaded", family))?;
+
+        face.family = family;
+        if let Some(weight) = descriptor("font-weight") {
+            //of a range, the start
+            face.weight = match weight.split_whitespace().next() {
+                Some("normal") => 400,
+                Some("bold") => 700,
+                Some(weight) => weight.parse::<f32>().map_or(face.weight, |weight| weight.clamp(1.0, 1000.0) as u16),
+                None => face.weight,
+            };
+        }
+        if let Some(style) = descriptor("font-style") {
+            face.italic = style.starts_with("italic") || style.starts_with("oblique");
+        }
+        self.add_face(face);
+        Ok(())
+    }
+
+    pub fn face(&self, id: FaceId) -> &Face {
+        &self.faces[id.0]
+    }
+
+    pub fn faces(&self) -> impl Iterator<Item = (FaceId, &Face)> {
+        self.faces.iter().enumerate().map(|(i, face)| (FaceId(i), face))
+    }
+
+    // The face of `family` that best matches the weight and style, CSS Fonts 4 §5.2.
+    fn match_family(&self, family: &str, query: &FontQuery) -> Option<FaceId> {
+        self.faces().filter(|(_, face)| face.family.eq_ignore_ascii_case(family))
+            .min_by_key(|(_, face)| (face.italic != query.italic, weight_rank(face.weight, query.weight)))
+            .map(|(id, _)| id)
+    }
+
+    // The faces to look for each character in, best first: the families asked for, the
+    // fallback font, then every other face.
+    fn candidates(&self, query: &FontQuery) -> Vec<FaceId> {
+        let mut candidates = vec![];
+        for family in &query.families {
+            let name = match family {
+                Family::Name(name) => name,
+                Family::Generic(generic) => match self.generic_families.get(generic) {
+                    Some(name) => name,
+                    None => continue,
+                },
+            };
+            candidates.extend(self.match_family(name, query));
+        }
+        candidates.extend(self.match_family(FALLBACK_FAMILY, query));
+        candidates.extend(self.faces().map(|(id, _)| id));
+        let mut seen = std::collections::HashSet::new();
+        candidates.retain(|id| seen.insert(*id));
+        candidates
+    }
+
+    // The metrics of the first available font, which are what the line height and baseline
+    // positions of text come from.
+    pub fn metrics(&self, query: &FontQuery, size: f32) -> FontMetrics {
+        let face = self.face(self.candidates(query)[0]);
+        let scale = size / face.units_per_em as f32;
+        FontMetrics {
+            size,
+            ascent: face.ascent as f32 * scale,
+            descent: -face.descent as f32 * scale,
+            line_gap: face.line_gap as f32 * scale,
+            x_height: face.x_height.map_or(0.5 * size, |x_height| x_height as f32 * scale),
+        }
+    }
+
+    // Shapes `text` at `size` px. Each character is shown in the first candidate face that has
+    // a glyph for it, or missing in the first available font if none does; marks and joiners
+    // stay in the face of the character they go with.
+    pub fn shape(&self, text: &str, query: &FontQuery, size: f32) -> ShapedText {
+        let candidates = self.candidates(query);
+        let mut runs: Vec<(FaceId, usize)> = vec![];
+        for (offset, c) in text.char_indices() {
+            let attaches = matches!(c, '\u{300}'..='\u{36F}' | '\u{200C}' | '\u{200D}' | '\u{FE00}'..='\u{FE0F}') || c == '\t';
+            let face = match runs.last() {
+                Some(&(face, _)) if attaches => face,
+                _ => candidates.iter().copied()
+                    .find(|&id| self.face(id).glyph_index(c).is_some())
+                    .unwrap_or(candidates[0]),
+            };
+            if runs.last().is_none_or(|&(last, _)| last != face) {
+                runs.push((face, offset));
+            }
+        }
+
+        let mut shaped = ShapedText::default();
+        for (i, &(face, start)) in runs.iter().enumerate() {
+            let end = runs.get(i + 1).map_or(text.len(), |&(_, end)| end);
+            shaping::shape_run(self.face(face), face, &text[start..end], start, size, &mut shaped.glyphs, &mut shaped.width);
+        }
+        shaped
+    }
+
+    // Shapes right to left text: the mirrored characters are swapped for their mirror images
+    // and the glyphs come out in visual order, from the end of the text to its start.
+    pub fn shape_right_to_left(&self, text: &str, query: &FontQuery, size: f32) -> ShapedText {
+        //mirror images have the same length in UTF-8, so the clusters still match `text`
+        let mirrored: String = text.chars().map(|c| bidi::mirror(c).unwrap_or(c)).collect();
+        let mut shaped = self.shape(&mirrored, query, size);
+        shaped.glyphs.reverse();
+        let mut x = 0.0;
+        for glyph in &mut shaped.glyphs {
+            glyph.x = x;
+            x += glyph.advance;
+        }
+        shaped
+    }
+}
+
+// Orders weights by how good a stand in they are for `desired`: between it and 500 first if
+// it's 400 to 500, then lighter ones nearest first, then heavier ones; below 400 lighter ones
+// first, above 500 heavier ones.
+fn weight_rank(available: u16, desired: u16) -> (u8, u16) {
+    let lighter = (1, desired.saturating_sub(available));
+    let heavier = (2, available.saturating_sub(desired));
+    match desired {
+        400..=500 if available >= desired && available <= 500 => (0, available - desired),
+        400..=500 if available < desired => lighter,
+        400..=500 => heavier,
+        _ if desired < 400 && available <= desired => (0, desired - available),
+        _ if desired < 400 => heavier,
+        _ if available >= desired => (0, available - desired),
+        _ => lighter,
+    }
+}
+
+fn unquote(text: &str) -> String {
+    text.trim().trim_matches(|c| c == '"' || c == '\'').to_string()
+}
+
+// Splits a descriptor value at the commas that aren't inside parentheses or quotes.
+fn split_outside_parens(text: &str) -> Vec<&str> {
+    let (mut parts, mut start, mut depth, mut quote) = (vec![], 0, 0, None);
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {},
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                parts.push(text[start..i].trim());
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    parts.push(text[start..].trim());
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query() -> FontQuery {
+        FontQuery {families: vec![], weight: 400, italic: false}
+    }
+
+    #[test]
+    fn ligatures_replace_their_components() {
+        let fonts = FontDatabase::new();
+        let shaped = fonts.shape("fi", &query(), 16.0);
+        assert_eq!(shaped.glyphs.len(), 1);
+        let (glyph, face) = (shaped.glyphs[0], fonts.face(shaped.glyphs[0].face));
+        assert_ne!(Some(glyph.glyph), face.glyph_index('f'));
+        assert_eq!(glyph.cluster, 0);
+
+        //a tab between them keeps them apart
+        assert_eq!(fonts.shape("f\ti", &query(), 16.0).glyphs.len(), 3);
+    }
+
+    #[test]
+    fn pairs_are_kerned() {
+        let fonts = FontDatabase::new();
+        let shaped = fonts.shape("AV", &query(), 16.0);
+        let face = fonts.face(shaped.glyphs[0].face);
+        let (a, v) = (face.glyph_index('A').unwrap(), face.glyph_index('V').unwrap());
+        assert!(face.kerning(a, v) < 0);
+        let scale = 16.0 / face.units_per_em as f32;
+        assert_eq!(shaped.glyphs[0].advance, (face.advance(a) as i16 + face.kerning(a, v)) as f32 * scale);
+        assert_eq!(shaped.glyphs[1].x, shaped.glyphs[0].advance);
+        assert!(shaped.width < fonts.shape("A", &query(), 16.0).width + fonts.shape("V", &query(), 16.0).width);
+    }
+}
@@ -0,0 +1,687 @@
+// Reading TrueType and OpenType fonts, https://learn.microsoft.com/typography/opentype/spec/:
+// the tables that map characters to glyphs, give their advances and the font's vertical
+// metrics, the ligatures, Arabic joining forms and pair kerning of GSUB and GPOS, and the
+// TrueType outlines of glyphs. The CFF outlines of OpenType fonts aren't read.
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+// Big endian reads that give None past the end of the data, so a truncated or corrupt font
+// fails to load instead of panicking.
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn i16_at(data: &[u8], offset: usize) -> Option<i16> {
+    u16_at(data, offset).map(|value| value as i16)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn tag_at(data: &[u8], offset: usize) -> Option<[u8; 4]> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+// One font, which is all of a font file unless the file is a collection.
+#[derive(Clone)]
+pub struct Face {
+    data: Arc<[u8]>,
+    tables: HashMap<[u8; 4], Range<usize>>,
+    pub family: String,
+    // the full and PostScript names, which local() in @font-face looks fonts up by
+    pub full_name: String,
+    pub postscript_name: String,
+    pub weight: u16,
+    pub italic: bool,
+    pub units_per_em: u16,
+    // in font units, with y going up from the baseline so the descent is negative
+    pub ascent: i16,
+    pub descent: i16,
+    pub line_gap: i16,
+    pub x_height: Option<i16>,
+    // the cmap subtable in use, and its format
+    cmap: Option<(usize, u16)>,
+    num_h_metrics: u16,
+    ligatures: Vec<LigatureLookup>,
+    // the isolated, final, medial and initial forms of Arabic letters
+    joining_forms: [SingleSubstitution; 4],
+    kerning: Vec<KerningLookup>,
+}
+
+// A substitution of a glyph and the ones after it by a single glyph.
+#[derive(Clone)]
+pub(super) struct Ligature {
+    // the glyphs after the first
+    pub components: Vec<u16>,
+    pub glyph: u16,
+}
+
+// The ligatures of one GSUB lookup, by their first glyph.
+pub(super) type LigatureLookup = HashMap<u16, Vec<Ligature>>;
+
+// The glyphs a single substitution feature replaces and what it replaces them with.
+pub(super) type SingleSubstitution = HashMap<u16, u16>;
+
+// The subtables of one GPOS pair adjustment lookup. Within a lookup the first subtable that
+// applies to a pair decides its kerning.
+type KerningLookup = Vec<PairSubtable>;
+
+#[derive(Clone)]
+enum PairSubtable {
+    // kerning for each pair of glyphs, in font units
+    Glyphs(HashMap<(u16, u16), i16>),
+    // kerning for each pair of glyph classes, for first glyphs that are in `coverage`
+    Classes {
+        coverage: Vec<u16>,
+        first: ClassDef,
+        second: ClassDef,
+        second_count: usize,
+        values: Vec<i16>,
+    },
+}
+
+impl PairSubtable {
+    fn kerning(&self, left: u16, right: u16) -> Option<i16> {
+        match self {
+            PairSubtable::Glyphs(pairs) => pairs.get(&(left, right)).copied(),
+            PairSubtable::Classes {coverage, first, second, second_count, values} => {
+                coverage.binary_search(&left).ok()?;
+                values.get(first.class(left) * second_count + second.class(right)).copied()
+            },
+        }
+    }
+}
+
+// Glyph classes as ranges of glyphs, sorted. Glyphs in none of them are class 0.
+#[derive(Clone)]
+struct ClassDef(Vec<(u16, u16, u16)>);
+
+impl ClassDef {
+    fn class(&self, glyph: u16) -> usize {
+        let range = self.0.partition_point(|&(_, end, _)| end < glyph);
+        match self.0.get(range) {
+            Some(&(start, _, class)) if start <= glyph => class as usize,
+            _ => 0,
+        }
+    }
+}
+
+impl Face {
+    // How many faces the font file holds: more than one only for a collection.
+    pub fn count(data: &[u8]) -> usize {
+        match tag_at(data, 0) {
+            Some(tag) if &tag == b"ttcf" => u32_at(data, 8).unwrap_or(0) as usize,
+            _ => 1,
+        }
+    }
+
+    // Reads face `index` of a font file.
+    pub fn parse(data: Arc<[u8]>, index: usize) -> Result<Face, String> {
+        Face::parse_tables(data, index).ok_or_else(|| "Not a TrueType or OpenType font, or a damaged one".to_string())
+    }
+
+    fn parse_tables(data: Arc<[u8]>, index: usize) -> Option<Face> {
+        let start = match &tag_at(&data, 0)? {
+            b"ttcf" => u32_at(&data, 12 + 4 * index)? as usize,
+            _ if index == 0 => 0,
+            _ => return None,
+        };
+        match &tag_at(&data, start)? {
+            [0, 1, 0, 0] | b"OTTO" | b"true" => {},
+            _ => return None,
+        }
+        let mut tables = HashMap::new();
+        for i in 0..u16_at(&data, start + 4)? as usize {
+            let record = start + 12 + 16 * i;
+            let offset = u32_at(&data, record + 8)? as usize;
+            let length = u32_at(&data, record + 12)? as usize;
+            if offset.checked_add(length)? > data.len() {
+                return None;
+            }
+            tables.insert(tag_at(&data, record)?, offset..offset + length);
+        }
+        let table = |tag: &[u8; 4]| tables.get(tag).map(|range| &data[range.clone()]);
+
+        let head = table(b"head")?;
+        let hhea = table(b"hhea")?;
+        let os2 = table(b"OS/2");
+        let units_per_em = u16_at(head, 18)?;
+        let mac_style = u16_at(head, 44)?;
+        let fs_selection = os2.and_then(|os2| u16_at(os2, 62)).unwrap_or(0);
+        let weight = os2.and_then(|os2| u16_at(os2, 4))
+            .unwrap_or(if mac_style & 1 != 0 { 700 } else { 400 });
+        let italic = if os2.is_some() { fs_selection & 0x201 != 0 } else { mac_style & 2 != 0 };
+
+        //hhea's metrics are what most fonts are made for, unless they ask for the typographic
+        //ones in OS/2 or leave hhea's empty
+        let typo = os2.and_then(|os2| Some((i16_at(os2, 68)?, i16_at(os2, 70)?, i16_at(os2, 72)?)));
+        let hhea_metrics = (i16_at(hhea, 4)?, i16_at(hhea, 6)?, i16_at(hhea, 8)?);
+        let (ascent, descent, line_gap) = match typo {
+            Some(typo) if fs_selection & 0x80 != 0 || (hhea_metrics.0 == 0 && hhea_metrics.1 == 0) => typo,
+            _ => hhea_metrics,
+        };
+        let x_height = os2.filter(|os2| u16_at(os2, 0).is_some_and(|version| version >= 2))
+            .and_then(|os2| i16_at(os2, 86))
+            .filter(|&x_height| x_height > 0);
+
+        let name = |ids: &[u16]| table(b"name").and_then(|name| ids.iter().find_map(|&id| read_name(name, id)));
+        let family = name(&[16, 1])?;
+        let full_name = name(&[4]).unwrap_or_else(|| family.clone());
+        let postscript_name = name(&[6]).unwrap_or_default();
+
+        let cmap = table(b"cmap").and_then(choose_cmap)
+            .map(|(offset, format)| (tables[b"cmap"].start + offset, format));
+
+        let ligatures = table(b"GSUB").and_then(read_ligatures).unwrap_or_default();
+        let joining_forms = [b"isol", b"fina", b"medi", b"init"]
+            .map(|feature| table(b"GSUB").and_then(|gsub| read_single_substitutions(gsub, feature)).unwrap_or_default());
+        let mut kerning = table(b"GPOS").and_then(read_pair_kerning).unwrap_or_default();
+        if kerning.is_empty() {
+            kerning = table(b"kern").and_then(read_kern_table).unwrap_or_default();
+        }
+
+        Some(Face {
+            num_h_metrics: u16_at(hhea, 34)?,
+            data,
+            tables,
+            family,
+            full_name,
+            postscript_name,
+            weight,
+            italic,
+            units_per_em: units_per_em.max(1),
+            ascent,
+            descent,
+            line_gap,
+            x_height,
+            cmap,
+            ligatures,
+            joining_forms,
+            kerning,
+        })
+    }
+
+    fn table(&self, tag: &[u8; 4]) -> Option<&[u8]> {
+        self.tables.get(tag).map(|range| &self.data[range.clone()])
+    }
+
+    // The glyph the font has for `c`, None if it has none.
+    pub fn glyph_index(&self, c: char) -> Option<u16> {
+        let (offset, format) = self.cmap?;
+        let subtable = &self.data[offset..];
+        let c = c as u32;
+        let glyph = match format {
+            4 => {
+                if c > 0xFFFF {
+                    return None;
+                }
+                let segments = u16_at(subtable, 6)? as usize / 2;
+                let end_codes = 14;
+                let start_codes = end_codes + 2 * segments + 2;
+                let deltas = start_codes + 2 * segments;
+                let range_offsets = deltas + 2 * segments;
+                let segment = partition_point(segments, |i| u16_at(subtable, end_codes + 2 * i).is_some_and(|end| (end as u32) < c));
+                if segment == segments || (u16_at(subtable, start_codes + 2 * segment)? as u32) > c {
+                    return None;
+                }
+                let delta = u16_at(subtable, deltas + 2 * segment)?;
+                let range_offset = u16_at(subtable, range_offsets + 2 * segment)? as usize;
+                if range_offset == 0 {
+                    (c as u16).wrapping_add(delta)
+                } else {
+                    let start = u16_at(subtable, start_codes + 2 * segment)? as u32;
+                    let address = range_offsets + 2 * segment + range_offset + 2 * (c - start) as usize;
+                    match u16_at(subtable, address)? {
+                        0 => 0,
+                        glyph => glyph.wrapping_add(delta),
+                    }
+                }
+            },
+            12 => {
+                let groups = u32_at(subtable, 12)? as usize;
+                let group = partition_point(groups, |i| u32_at(subtable, 16 + 12 * i + 4).is_some_and(|end| end < c));
+                let record = 16 + 12 * group;
+                if group == groups || u32_at(subtable, record)? > c {
+                    return None;
+                }
+                (u32_at(subtable, record + 8)? + c - u32_at(subtable, record)?) as u16
+            },
+            _ => return None,
+        };
+        Some(glyph).filter(|&glyph| glyph != 0)
+    }
+
+    // How far the glyph moves the pen, in font units.
+    pub fn advance(&self, glyph: u16) -> u16 {
+        let metric = glyph.min(self.num_h_metrics.saturating_sub(1)) as usize;
+        self.table(b"hmtx").and_then(|hmtx| u16_at(hmtx, 4 * metric)).unwrap_or(0)
+    }
+
+    // The adjustment to the advance of `left` when `right` follows it, in font units.
+    pub fn kerning(&self, left: u16, right: u16) -> i16 {
+        self.kerning.iter()
+            .filter_map(|lookup| lookup.iter().find_map(|subtable| subtable.kerning(left, right)))
+            .fold(0, i16::saturating_add)
+    }
+
+    // The outline of a glyph, in font units with y going up: its contours, each as its points
+    // and whether they are on the curve, which runs between them in straight lines and
+    // quadratic Béziers with the off curve points as control points. Empty for a glyph without
+    // an outline, like the space, and for fonts with CFF outlines.
+    pub fn outline(&self, glyph: u16) -> Vec<Vec<(f32, f32, bool)>> {
+        let mut contours = vec![];
+        self.read_outline(glyph, [1.0, 0.0, 0.0, 1.0, 0.0, 0.0], 0, &mut contours);
+        contours
+    }
+
+    // Adds the contours of a glyph to `contours`, transformed by `matrix` like a transform in
+    // CSS. Composite glyphs nest no deeper than the maxp table allows for most fonts.
+    fn read_outline(&self, glyph: u16, matrix: [f32; 6], depth: usize, contours: &mut Vec<Vec<(f32, f32, bool)>>) -> Option<()> {
+        let (head, loca, glyf) = (self.table(b"head")?, self.table(b"loca")?, self.table(b"glyf")?);
+        let (start, end) = match i16_at(head, 50)? {
+            0 => (2 * u16_at(loca, 2 * glyph as usize)? as usize, 2 * u16_at(loca, 2 * glyph as usize + 2)? as usize),
+            _ => (u32_at(loca, 4 * glyph as usize)? as usize, u32_at(loca, 4 * glyph as usize + 4)? as usize),
+        };
+        let data = glyf.get(start..end).filter(|data| !data.is_empty())?;
+        let transform = |x: f32, y: f32| (matrix[0] * x + matrix[2] * y + matrix[4], matrix[1] * x + matrix[3] * y + matrix[5]);
+        let count = i16_at(data, 0)?;
+        if count < 0 {
+            return self.read_composite(data, matrix, depth, contours);
+        }
+        let ends = (0..count as usize).map(|i| u16_at(data, 10 + 2 * i)).collect::<Option<Vec<u16>>>()?;
+        let point_count = ends.last().map_or(0, |&end| end as usize + 1);
+        let instructions = 10 + 2 * count as usize;
+        let mut offset = instructions + 2 + u16_at(data, instructions)? as usize;
+
+        //flags, each of which can say it is repeated
+        let mut flags = Vec::with_capacity(point_count);
+        while flags.len() < point_count {
+            let flag = *data.get(offset)?;
+            offset += 1;
+            let repeats = match flag & 0x08 {
+                0 => 1,
+                _ => {
+                    offset += 1;
+                    1 + *data.get(offset - 1)? as usize
+                },
+            };
+            flags.extend(std::iter::repeat_n(flag, repeats));
+        }
+        flags.truncate(point_count);
+
+        //then the x and y coordinates, as deltas a byte or two long
+        let mut read_coordinates = |short: u8, same_or_positive: u8| -> Option<Vec<i32>> {
+            let mut value = 0i32;
+            flags.iter().map(|&flag| {
+                if flag & short != 0 {
+                    let delta = *data.get(offset)? as i32;
+                    offset += 1;
+                    value += if flag & same_or_positive != 0 { delta } else { -delta };
+                } else if flag & same_or_positive == 0 {
+                    value += i16_at(data, offset)? as i32;
+                    offset += 2;
+                }
+                Some(value)
+            }).collect()
+        };
+        let xs = read_coordinates(0x02, 0x10)?;
+        let ys = read_coordinates(0x04, 0x20)?;
+
+        let mut first = 0;
+        for end in ends {
+            let end = end as usize + 1;
+            if end <= first || end > point_count {
+                return None;
+            }
+            contours.push((first..end).map(|i| {
+                let (x, y) = transform(xs[i] as f32, ys[i] as f32);
+                (x, y, flags[i] & 0x01 != 0)
+            }).collect());
+            first = end;
+        }
+        Some(())
+    }
+
+    // The components of a composite glyph, each another glyph moved and possibly scaled.
+    // Components placed by matching points rather than by an offset are left where they are.
+    fn read_composite(&self, data: &[u8], matrix: [f32; 6], depth: usize, contours: &mut Vec<Vec<(f32, f32, bool)>>) -> Option<()> {
+        if depth > 8 {
+            return None;
+        }
+        let f2dot14 = |offset: usize| i16_at(data, offset).map(|value| value as f32 / 16384.0);
+        let mut offset = 10;
+        loop {
+            let flags = u16_at(data, offset)?;
+            let glyph = u16_at(data, offset + 2)?;
+            offset += 4;
+            let (dx, dy) = match flags & 0x01 {
+                0 => (data.get(offset).copied()? as i8 as f32, data.get(offset + 1).copied()? as i8 as f32),
+                _ => (i16_at(data, offset)? as f32, i16_at(data, offset + 2)? as f32),
+            };
+            offset += if flags & 0x01 != 0 { 4 } else { 2 };
+            let (dx, dy) = if flags & 0x02 != 0 { (dx, dy) } else { (0.0, 0.0) };
+            let (a, b, c, d) = if flags & 0x08 != 0 {
+                offset += 2;
+                (f2dot14(offset - 2)?, 0.0, 0.0, f2dot14(offset - 2)?)
+            } else if flags & 0x40 != 0 {
+                offset += 4;
+                (f2dot14(offset - 4)?, 0.0, 0.0, f2dot14(offset - 2)?)
+            } else if flags & 0x80 != 0 {
+                offset += 8;
+                (f2dot14(offset - 8)?, f2dot14(offset - 6)?, f2dot14(offset - 4)?, f2dot14(offset - 2)?)
+            } else {
+                (1.0, 0.0, 0.0, 1.0)
+            };
+            let [ma, mb, mc, md, me, mf] = matrix;
+            let component = [
+                ma * a + mc * b, mb * a + md * b,
+                ma * c + mc * d, mb * c + md * d,
+                ma * dx + mc * dy + me, mb * dx + md * dy + mf,
+            ];
+            self.read_outline(glyph, component, depth + 1, contours);
+            if flags & 0x20 == 0 {
+                return Some(());
+            }
+        }
+    }
+
+    pub(super) fn ligature_lookups(&self) -> &[LigatureLookup] {
+        &self.ligatures
+    }
+
+    // The substitutions of the isol, fina, medi and init features, in that order.
+    pub(super) fn joining_forms(&self) -> &[SingleSubstitution; 4] {
+        &self.joining_forms
+    }
+}
+
+// The index of the first of `len` items for which `before` is false, `before` being true for
+// a prefix of them.
+fn partition_point(len: usize, before: impl Fn(usize) -> bool) -> usize {
+    let (mut low, mut high) = (0, len);
+    while low < high {
+        let middle = (low + high) / 2;
+        if before(middle) {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+    low
+}
+
+// A name from the name table, preferring US English Windows names. Mac Roman names are read
+// as Latin-1, which only differs for characters that don't turn up in font names much.
+fn read_name(name: &[u8], id: u16) -> Option<String> {
+    let count = u16_at(name, 2)? as usize;
+    let strings = u16_at(name, 4)? as usize;
+    let mut best: Option<(u8, String)> = None;
+    for i in 0..count {
+        let record = 6 + 12 * i;
+        if u16_at(name, record + 6)? != id {
+            continue;
+        }
+        let platform = u16_at(name, record)?;
+        let language = u16_at(name, record + 4)?;
+        let length = u16_at(name, record + 8)? as usize;
+        let offset = strings + u16_at(name, record + 10)? as usize;
+        let bytes = name.get(offset..offset + length)?;
+        let (rank, text) = match platform {
+            0 | 3 => {
+                let units: Vec<u16> = bytes.chunks_exact(2).map(|unit| u16::from_be_bytes([unit[0], unit[1]])).collect();
+                (if platform == 3 && language == 0x409 { 0 } else { 1 }, String::from_utf16_lossy(&units))
+            },
+            1 => (2, bytes.iter().map(|&byte| byte as char).collect()),
+            _ => continue,
+        };
+        if best.as_ref().is_none_or(|(best_rank, _)| rank < *best_rank) {
+            best = Some((rank, text));
+        }
+    }
+    best.map(|(_, text)| text).filter(|text| !text.is_empty())
+}
+
+// The Unicode cmap subtable to use, relative to the table, and its format: a full repertoire
+// one if there is one, otherwise one for the basic multilingual plane.
+fn choose_cmap(cmap: &[u8]) -> Option<(usize, u16)> {
+    let mut best: Option<(u8, usize, u16)> = None;
+    for i in 0..u16_at(cmap, 2)? as usize {
+        let record = 4 + 8 * i;
+        let platform = u16_at(cmap, record)?;
+        let encoding = u16_at(cmap, record + 2)?;
+        let offset = u32_at(cmap, record + 4)? as usize;
+        let format = u16_at(cmap, offset)?;
+        let rank = match (platform, encoding, format) {
+            (3, 10, 12) | (0, 4, 12) | (0, 6, 12) => 0,
+            (3, 1, 4) | (0, _, 4) => 1,
+            _ => continue,
+        };
+        if best.is_none_or(|(best_rank, _, _)| rank < best_rank) {
+            best = Some((rank, offset, format));
+        }
+    }
+    best.map(|(_, offset, format)| (offset, format))
+}
+
+// The glyphs of a coverage table, in coverage index order.
+fn read_coverage(data: &[u8], offset: usize) -> Option<Vec<u16>> {
+    let count = u16_at(data, offset + 2)? as usize;
+    match u16_at(data, offset)? {
+        1 => (0..count).map(|i| u16_at(data, offset + 4 + 2 * i)).collect(),
+        2 => {
+            let mut glyphs = vec![];
+            for i in 0..count {
+                let record = offset + 4 + 6 * i;
+                glyphs.extend(u16_at(data, record)?..=u16_at(data, record + 2)?);
+            }
+            Some(glyphs)
+        },
+        _ => None,
+    }
+}
+
+fn read_class_def(data: &[u8], offset: usize) -> Option<ClassDef> {
+    let mut ranges = vec![];
+    match u16_at(data, offset)? {
+        1 => {
+            let start = u16_at(data, offset + 2)?;
+            for i in 0..u16_at(data, offset + 4)? {
+                let class = u16_at(data, offset + 6 + 2 * i as usize)?;
+                ranges.push((start + i, start + i, class));
+            }
+        },
+        2 => {
+            for i in 0..u16_at(data, offset + 2)? as usize {
+                let record = offset + 4 + 6 * i;
+                ranges.push((u16_at(data, record)?, u16_at(data, record + 2)?, u16_at(data, record + 4)?));
+            }
+        },
+        _ => return None,
+    }
+    ranges.sort_unstable();
+    Some(ClassDef(ranges))
+}
+
+// The lookups of the features tagged `feature` in a GSUB or GPOS table, in lookup list order,
+// as their type and the offsets of their subtables. Every script and language system gets the
+// same features, which is close enough for the scripts we shape. Extension lookups, of type
+// `extension_type`, are replaced by the lookups they wrap.
+fn feature_lookups(table: &[u8], feature: &[u8; 4], extension_type: u16) -> Option<Vec<(u16, Vec<usize>)>> {
+    let feature_list = u16_at(table, 6)? as usize;
+    let lookup_list = u16_at(table, 8)? as usize;
+    let mut indices = vec![];
+    for i in 0..u16_at(table, feature_list)? as usize {
+        let record = feature_list + 2 + 6 * i;
+        if &tag_at(table, record)? != feature {
+            continue;
+        }
+        let feature_table = feature_list + u16_at(table, record + 4)? as usize;
+        for j in 0..u16_at(table, feature_table + 2)? as usize {
+            indices.push(u16_at(table, feature_table + 4 + 2 * j)?);
+        }
+    }
+    indices.sort_unstable();
+    indices.dedup();
+
+    indices.into_iter().map(|index| {
+        let lookup = lookup_list + u16_at(table, lookup_list + 2 + 2 * index as usize)? as usize;
+        let mut lookup_type = u16_at(table, lookup)?;
+        let mut subtables = vec![];
+        for i in 0..u16_at(table, lookup + 4)? as usize {
+            let mut subtable = lookup + u16_at(table, lookup + 6 + 2 * i)? as usize;
+            if u16_at(table, lookup)? == extension_type {
+                lookup_type = u16_at(table, subtable + 2)?;
+                subtable += u32_at(table, subtable + 4)? as usize;
+            }
+            subtables.push(subtable);
+        }
+        Some((lookup_type, subtables))
+    }).collect()
+}
+
+// The single substitution lookups of a feature, merged into one since the joining forms
+// don't overlap.
+fn read_single_substitutions(gsub: &[u8], feature: &[u8; 4]) -> Option<SingleSubstitution> {
+    let mut substitutions = HashMap::new();
+    for (lookup_type, subtables) in feature_lookups(gsub, feature, 7)? {
+        if lookup_type != 1 {
+            continue;
+        }
+        for subtable in subtables {
+            let coverage = read_coverage(gsub, subtable + u16_at(gsub, subtable + 2)? as usize)?;
+            for (i, &glyph) in coverage.iter().enumerate() {
+                let substitute = match u16_at(gsub, subtable)? {
+                    //a delta added to the glyph id
+                    1 => glyph.wrapping_add(u16_at(gsub, subtable + 4)?),
+                    2 if i < u16_at(gsub, subtable + 4)? as usize => u16_at(gsub, subtable + 6 + 2 * i)?,
+                    _ => continue,
+                };
+                substitutions.entry(glyph).or_insert(substitute);
+            }
+        }
+    }
+    Some(substitutions)
+}
+
+// The standard and required ligatures, from the ligature substitution lookups of the liga and
+// rlig features.
+fn read_ligatures(gsub: &[u8]) -> Option<Vec<LigatureLookup>> {
+    let mut lookups = vec![];
+    let mut features = feature_lookups(gsub, b"rlig", 7).unwrap_or_default();
+    features.extend(feature_lookups(gsub, b"liga", 7)?);
+    for (lookup_type, subtables) in features {
+        if lookup_type != 4 {
+            continue;
+        }
+        let mut ligatures: LigatureLookup = HashMap::new();
+        for subtable in subtables {
+            if u16_at(gsub, subtable)? != 1 {
+                continue;
+            }
+            let coverage = read_coverage(gsub, subtable + u16_at(gsub, subtable + 2)? as usize)?;
+            let set_count = u16_at(gsub, subtable + 4)? as usize;
+            for (i, &first) in coverage.iter().enumerate().take(set_count) {
+                let set = subtable + u16_at(gsub, subtable + 6 + 2 * i)? as usize;
+                for j in 0..u16_at(gsub, set)? as usize {
+                    let ligature = set + u16_at(gsub, set + 2 + 2 * j)? as usize;
+                    let glyph = u16_at(gsub, ligature)?;
+                    let count = u16_at(gsub, ligature + 2)? as usize;
+                    let components = (1..count).map(|k| u16_at(gsub, ligature + 2 + 2 * k)).collect::<Option<Vec<u16>>>()?;
+                    //an earlier subtable's ligature takes precedence
+                    ligatures.entry(first).or_default().push(Ligature {components, glyph});
+                }
+            }
+        }
+        lookups.push(ligatures);
+    }
+    Some(lookups)
+}
+
+// The size of a value record with the given value format.
+fn value_record_size(format: u16) -> usize {
+    2 * (format & 0xFF).count_ones() as usize
+}
+
+// Horizontal kerning, from the pair adjustment lookups of the kern feature. Only the x advance
+// of the first glyph is used, which is how fonts kern horizontal text.
+fn read_pair_kerning(gpos: &[u8]) -> Option<Vec<KerningLookup>> {
+    let mut lookups = vec![];
+    for (lookup_type, subtables) in feature_lookups(gpos, b"kern", 9)? {
+        if lookup_type != 2 {
+            continue;
+        }
+        let lookup = subtables.into_iter()
+            .map(|subtable| read_pair_subtable(gpos, subtable))
+            .collect::<Option<Vec<PairSubtable>>>()?;
+        lookups.push(lookup);
+    }
+    Some(lookups)
+}
+
+fn read_pair_subtable(gpos: &[u8], offset: usize) -> Option<PairSubtable> {
+    let coverage = read_coverage(gpos, offset + u16_at(gpos, offset + 2)? as usize)?;
+    let (format1, format2) = (u16_at(gpos, offset + 4)?, u16_at(gpos, offset + 6)?);
+    let value_size = value_record_size(format1) + value_record_size(format2);
+    //where in the first value record its x advance is, if it has one
+    let x_advance = Some(value_record_size(format1 & 0x3)).filter(|_| format1 & 0x4 != 0);
+    let read_value = |record: usize| match x_advance {
+        Some(at) => i16_at(gpos, record + at),
+        None => Some(0),
+    };
+
+    match u16_at(gpos, offset)? {
+        1 => {
+            let mut pairs = HashMap::new();
+            let set_count = u16_at(gpos, offset + 8)? as usize;
+            for (i, &first) in coverage.iter().enumerate().take(set_count) {
+                let set = offset + u16_at(gpos, offset + 10 + 2 * i)? as usize;
+                for j in 0..u16_at(gpos, set)? as usize {
+                    let record = set + 2 + j * (2 + value_size);
+                    pairs.entry((first, u16_at(gpos, record)?)).or_insert(read_value(record + 2)?);
+                }
+            }
+            Some(PairSubtable::Glyphs(pairs))
+        },
+        2 => {
+            let first = read_class_def(gpos, offset + u16_at(gpos, offset + 8)? as usize)?;
+            let second = read_class_def(gpos, offset + u16_at(gpos, offset + 10)? as usize)?;
+            let first_count = u16_at(gpos, offset + 12)? as usize;
+            let second_count = u16_at(gpos, offset + 14)? as usize;
+            let values = (0..first_count * second_count)
+                .map(|i| read_value(offset + 16 + i * value_size))
+                .collect::<Option<Vec<i16>>>()?;
+            let mut coverage = coverage;
+            coverage.sort_unstable();
+            Some(PairSubtable::Classes {coverage, first, second, second_count, values})
+        },
+        _ => None,
+    }
+}
+
+// The pairs of the old kern table, for fonts without kerning in GPOS. Only horizontal format 0
+// subtables are read; Apple's version of the table isn't.
+fn read_kern_table(kern: &[u8]) -> Option<Vec<KerningLookup>> {
+    if u16_at(kern, 0)? != 0 {
+        return None;
+    }
+    let mut pairs = HashMap::new();
+    let mut subtable = 4;
+    for _ in 0..u16_at(kern, 2)? {
+        let length = u16_at(kern, subtable + 2)? as usize;
+        let coverage = u16_at(kern, subtable + 4)?;
+        if coverage >> 8 == 0 && coverage & 0x7 == 1 {
+            for i in 0..u16_at(kern, subtable + 6)? as usize {
+                let pair = subtable + 14 + 6 * i;
+                pairs.entry((u16_at(kern, pair)?, u16_at(kern, pair + 2)?)).or_insert(i16_at(kern, pair + 4)?);
+            }
+        }
+        subtable += length;
+    }
+    Some(vec![vec![PairSubtable::Glyphs(pairs)]])
+}
@@ -0,0 +1,118 @@
+// Shaping text in a single face: characters are mapped to glyphs through the cmap, Arabic
+// letters take the form for how they join their neighbours, ligatures are substituted and pair
+// kerning is applied to the advances. There is no reordering or mark positioning, which is
+// enough for the simple scripts and unvowelled Arabic.
+
+use super::sfnt::Face;
+use super::FaceId;
+use super::ShapedGlyph;
+
+// Characters that take up no room and have no glyph of their own.
+fn is_default_ignorable(c: char) -> bool {
+    matches!(c, '\u{AD}' | '\u{200B}'..='\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2060}'..='\u{2064}' | '\u{FE00}'..='\u{FE0F}' | '\u{FEFF}')
+}
+
+// How a character joins the ones next to it in cursive scripts, from the Unicode
+// ArabicShaping data for the Arabic block.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Joining {
+    // joins on both sides
+    Dual,
+    // only joins the character before it, which is on its right
+    Right,
+    // doesn't break the joining of the characters around it, like a mark
+    Transparent,
+    // joins whatever is next to it without changing shape, like the zero width joiner
+    Causing,
+    None,
+}
+
+fn joining(c: char) -> Joining {
+    match c {
+        '\u{622}'..='\u{625}' | '\u{627}' | '\u{629}' | '\u{62F}'..='\u{632}' | '\u{648}' | '\u{671}'..='\u{673}'
+        | '\u{675}'..='\u{677}' | '\u{688}'..='\u{699}' | '\u{6C0}' | '\u{6C3}'..='\u{6CB}' | '\u{6CD}' | '\u{6CF}'
+        | '\u{6D2}' | '\u{6D3}' | '\u{6D5}' | '\u{6EE}' | '\u{6EF}' => Joining::Right,
+        '\u{620}' | '\u{626}' | '\u{628}' | '\u{62A}'..='\u{62E}' | '\u{633}'..='\u{63F}' | '\u{641}'..='\u{647}'
+        | '\u{649}' | '\u{64A}' | '\u{66E}' | '\u{66F}' | '\u{678}'..='\u{687}' | '\u{69A}'..='\u{6BF}' | '\u{6C1}'
+        | '\u{6C2}' | '\u{6CC}' | '\u{6CE}' | '\u{6D0}' | '\u{6D1}' | '\u{6FA}'..='\u{6FC}' | '\u{6FF}' => Joining::Dual,
+        '\u{610}'..='\u{61A}' | '\u{64B}'..='\u{65F}' | '\u{670}' | '\u{6D6}'..='\u{6DC}' | '\u{6DF}'..='\u{6E4}'
+        | '\u{6E7}' | '\u{6E8}' | '\u{6EA}'..='\u{6ED}' => Joining::Transparent,
+        '\u{640}' | '\u{200D}' => Joining::Causing,
+        _ => Joining::None,
+    }
+}
+
+// Which joining form, as an index into Face::joining_forms, each character of `text` takes:
+// isolated, final, medial or initial. None for those that don't change shape.
+fn joining_forms(text: &str) -> Vec<Option<usize>> {
+    let joinings: Vec<Joining> = text.chars().map(joining).collect();
+    let not_transparent = |joining: &&Joining| **joining != Joining::Transparent;
+    (0..joinings.len()).map(|i| {
+        if !matches!(joinings[i], Joining::Dual | Joining::Right) {
+            return None;
+        }
+        let before = joinings[..i].iter().rev().find(not_transparent);
+        let after = joinings[i + 1..].iter().find(not_transparent);
+        let joins_before = matches!(before, Some(Joining::Dual | Joining::Causing));
+        let joins_after = joinings[i] == Joining::Dual && matches!(after, Some(Joining::Dual | Joining::Right | Joining::Causing));
+        Some(match (joins_before, joins_after) {
+            (false, false) => 0,
+            (true, false) => 1,
+            (true, true) => 2,
+            (false, true) => 3,
+        })
+    }).collect()
+}
+
+struct Glyph {
+    id: u16,
+    // byte offset of the first character the glyph is for
+    cluster: usize,
+    tab: bool,
+}
+
+// Appends the glyphs of `text`, which starts `base` bytes into the text being shaped, to
+// `glyphs`, starting at `pen` and moving it along.
+pub(super) fn shape_run(face: &Face, face_id: FaceId, text: &str, base: usize, size: f32, glyphs: &mut Vec<ShapedGlyph>, pen: &mut f32) {
+    let space = face.glyph_index(' ').unwrap_or(0);
+    let forms = joining_forms(text);
+    let mut run: Vec<Glyph> = text.char_indices().zip(forms)
+        .filter(|&((_, c), _)| !is_default_ignorable(c))
+        .map(|((offset, c), form)| match c {
+            '\t' => Glyph {id: space, cluster: base + offset, tab: true},
+            _ => {
+                let id = face.glyph_index(c).unwrap_or(0);
+                let id = form.and_then(|form| face.joining_forms()[form].get(&id).copied()).unwrap_or(id);
+                Glyph {id, cluster: base + offset, tab: false}
+            },
+        })
+        .collect();
+
+    //each ligature lookup goes over the whole run before the next one
+    for lookup in face.ligature_lookups() {
+        let mut i = 0;
+        while i < run.len() {
+            let ligature = lookup.get(&run[i].id).and_then(|ligatures| ligatures.iter().find(|ligature| {
+                let following = &run[i + 1..];
+                following.len() >= ligature.components.len()
+                    && ligature.components.iter().zip(following).all(|(&component, glyph)| component == glyph.id && !glyph.tab)
+            }));
+            if let Some(ligature) = ligature {
+                run[i].id = ligature.glyph;
+                run.drain(i + 1..i + 1 + ligature.components.len());
+            }
+            i += 1;
+        }
+    }
+
+    let scale = size / face.units_per_em as f32;
+    for (i, glyph) in run.iter().enumerate() {
+        //a tab is 8 spaces wide, the initial tab-size
+        let mut advance = face.advance(glyph.id) as f32 * if glyph.tab { 8.0 } else { 1.0 };
+        if let Some(next) = run.get(i + 1).filter(|_| !glyph.tab) {
+            advance += face.kerning(glyph.id, next.id) as f32;
+        }
+        glyphs.push(ShapedGlyph {face: face_id, glyph: glyph.id, cluster: glyph.cluster, x: *pen, advance: advance * scale});
+        *pen += advance * scale;
+    }
+}
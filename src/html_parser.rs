@@ -1,9 +1,17 @@
 
 use crate::dom;
 
+// Elements that never have content or an end tag.
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track", "wbr",
+];
+
+// The start tags that make up the structure of a table.
+const TABLE_STRUCTURE: &[&str] = &["caption", "col", "colgroup", "tbody", "thead", "tfoot", "tr", "td", "th", "table"];
 
 pub fn parse(source: String) -> dom::Node {
-    let mut nodes = Parser {input: source, pos: 0}.parse_nodes();
+    let mut parser = Parser {input: source, pos: 0, open: vec![], foster: vec![], fostered: vec![]};
+    let mut nodes = parser.parse_nodes();
 
     if nodes.len() == 1 {
         nodes.swap_remove(0)
@@ -15,6 +23,39 @@ pub fn parse(source: String) -> dom::Node {
 struct Parser {
     input: String,
     pos: usize,
+    // the names of the elements whose content is being parsed, innermost last
+    open: Vec<String>,
+    // for each open table, the content that turned up in it where it can't go and so goes
+    // right before the table instead, innermost last
+    foster: Vec<Vec<dom::Node>>,
+    // what was fostered out of the table that was parsed last
+    fostered: Vec<dom::Node>,
+}
+
+// Which part of a table the parser is in, going by the closest table element that is open,
+// from the insertion modes of the HTML tree construction stage. Outside of tables it's
+// always in body.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum InsertionMode {
+    Body,
+    Table,
+    Caption,
+    ColumnGroup,
+    TableBody,
+    Row,
+    Cell,
+}
+
+// What the parser does with the next node, given the insertion mode.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Action {
+    Insert,
+    // the element being parsed ends right here, without an end tag
+    Close,
+    // the node goes in an element with this name that has no start tag of its own
+    Imply(&'static str),
+    // the node goes before the table, since nothing but table structure can go in it
+    Foster,
 }
 
 impl Parser {
@@ -72,6 +113,12 @@ impl Parser {
         result
     }
 
+    // The name of the tag that starts `skip` bytes on, in lowercase.
+    fn peek_name(&self, skip: usize) -> String {
+        let rest = self.input.get(self.pos + skip..).unwrap_or("");
+        rest.chars().take_while(char::is_ascii_alphanumeric).collect::<String>().to_ascii_lowercase()
+    }
+
     fn consume_whitespace(&mut self) {
         self.consume_while(char::is_whitespace);
     }
@@ -83,10 +130,7 @@ impl Parser {
     // }
 
     fn parse_name(&mut self) -> String {
-        self.consume_while(|c| match c {
-            'a'..='z' | 'A' ..= 'Z' | '0' ..= '9' => true,
-            _ => false
-        })
+        self.consume_while(|c| matches!(c, 'a'..='z' | 'A' ..= 'Z' | '0' ..= '9'))
     }
 
     fn parse_node(&mut self) -> dom::Node {
@@ -112,13 +156,23 @@ impl Parser {
         let tag_name = self.parse_name();
         let attrs = self.parse_attributes();
         // assert_eq!('>', self.consume_char());
-        if let Ok(_) = self.consume_string("/>") {
+        if self.consume_string("/>").is_ok() {
             return dom::elem(tag_name, attrs, vec![]);
         }
 
         self.consume_char(); //consuming >
 
+        let name = tag_name.to_ascii_lowercase();
+        if VOID_ELEMENTS.contains(&&*name) {
+            return dom::elem(tag_name, attrs, vec![]);
+        }
+        let table = name == "table";
+        if table {
+            self.foster.push(vec![]);
+        }
+        self.open.push(name);
         let children = self.parse_nodes();
+        self.open.pop();
 
         // assert_eq!(self.consume_char(),'<');
         // assert_eq!(self.consume_char(), '/');
@@ -129,9 +183,21 @@ impl Parser {
         let closing_tag = format!("</{}>", tag_name);
         let _ = self.consume_string(&closing_tag);
 
+        if table {
+            self.fostered = self.foster.pop().unwrap_or_default();
+        }
         dom::elem(tag_name, attrs, children)
     }
 
+    // An element the markup leaves out, like the tbody around rows put right in a table.
+    fn parse_implied(&mut self, name: &str) -> dom::Node {
+        self.open.push(name.to_string());
+        let children = self.parse_nodes();
+        self.open.pop();
+        let _ = self.consume_string(&format!("</{}>", name));
+        dom::elem(name.to_string(), dom::AttrMap::new(), children)
+    }
+
     fn parse_comment(&mut self) -> dom::Node {
         // assert_eq!(self.consume_char(), '!');
         // assert_eq!(self.consume_char(), '-');
@@ -189,15 +255,97 @@ impl Parser {
         attributes
     }
 
+    fn insertion_mode(&self) -> InsertionMode {
+        self.open.iter().rev().find_map(|name| match &**name {
+            "td" | "th" => Some(InsertionMode::Cell),
+            "tr" => Some(InsertionMode::Row),
+            "tbody" | "thead" | "tfoot" => Some(InsertionMode::TableBody),
+            "caption" => Some(InsertionMode::Caption),
+            "colgroup" => Some(InsertionMode::ColumnGroup),
+            "table" => Some(InsertionMode::Table),
+            _ => None,
+        }).unwrap_or(InsertionMode::Body)
+    }
+
+    // What to do with the next node, whose tag name is `tag` if it's an element. This is
+    // the part of the tree construction rules that deals with tables: table structure in
+    // the wrong place ends the elements in the way or implies the ones that are missing, and
+    // anything else in a table is foster parented.
+    fn action(&self, tag: Option<&str>) -> Action {
+        let mode = self.insertion_mode();
+        let structure = tag.filter(|tag| TABLE_STRUCTURE.contains(tag));
+        let current = self.open.last().map_or("", |name| &**name);
+        let is_text = !self.starts_with("<");
+        let in_place = match mode {
+            InsertionMode::Table => current == "table",
+            InsertionMode::TableBody => matches!(current, "tbody" | "thead" | "tfoot"),
+            InsertionMode::Row => current == "tr",
+            _ => true,
+        };
+        match (mode, structure) {
+            (InsertionMode::Body, _) => Action::Insert,
+            (InsertionMode::Cell, Some("table")) | (InsertionMode::Caption, Some("table")) => Action::Insert,
+            (InsertionMode::Cell, Some(_)) | (InsertionMode::Caption, Some(_)) => Action::Close,
+            (InsertionMode::Cell, None) | (InsertionMode::Caption, None) => Action::Insert,
+            (InsertionMode::ColumnGroup, Some("col")) => Action::Insert,
+            (InsertionMode::ColumnGroup, _) if tag.is_none() && !is_text => Action::Insert,
+            (InsertionMode::ColumnGroup, _) => Action::Close,
+            //content that was fostered out of the table has the structure end it
+            (_, Some(_)) if !in_place => Action::Close,
+            (_, None) if !in_place => Action::Insert,
+            (InsertionMode::Table, Some("caption" | "colgroup" | "tbody" | "thead" | "tfoot")) => Action::Insert,
+            (InsertionMode::Table, Some("col")) => Action::Imply("colgroup"),
+            (InsertionMode::Table, Some("tr" | "td" | "th")) => Action::Imply("tbody"),
+            (InsertionMode::TableBody, Some("tr")) => Action::Insert,
+            (InsertionMode::TableBody, Some("td" | "th")) => Action::Imply("tr"),
+            (InsertionMode::Row, Some("td" | "th")) => Action::Insert,
+            (_, Some(_)) => Action::Close,
+            (_, None) if matches!(tag, Some("style" | "script" | "template")) => Action::Insert,
+            //comments stay where they are
+            (_, None) if tag.is_none() && !is_text => Action::Insert,
+            (_, None) => Action::Foster,
+        }
+    }
+
     fn parse_nodes(&mut self) -> Vec<dom::Node> {
         let mut nodes = Vec::new();
 
         loop {
             self.consume_whitespace();
-            if self.eof() || self.starts_with("</") {
+            if self.eof() {
                 break;
             }
-            nodes.push(self.parse_node());
+            //an end tag ends the element it belongs to along with any inside that are still
+            //open, unless there is no such element, in which case it's ignored
+            if self.starts_with("</") {
+                let name = self.peek_name(2);
+                if self.open.contains(&name) {
+                    break;
+                }
+                self.consume_till_str(">");
+                let _ = self.consume_string(">");
+                continue;
+            }
+            let tag = match self.starts_with("<!--") {
+                true => None,
+                false => Some(self.peek_name(1)).filter(|_| self.starts_with("<")),
+            };
+            match self.action(tag.as_deref()) {
+                Action::Insert => {
+                    let node = self.parse_node();
+                    nodes.append(&mut self.fostered);
+                    nodes.push(node);
+                },
+                Action::Close => break,
+                Action::Imply(name) => nodes.push(self.parse_implied(name)),
+                Action::Foster => {
+                    let node = self.parse_node();
+                    match self.foster.last_mut() {
+                        Some(fostered) => fostered.push(node),
+                        None => nodes.push(node),
+                    }
+                },
+            }
         }
         nodes
     }
@@ -0,0 +1,127 @@
+// Images for replaced elements: decoding PNG, JPEG, GIF, WebP and BMP into RGBA pixels, and
+// a store of the images a document's <img> elements load through the resource layer. An image
+// that can't be loaded or decoded leaves its element showing its alt text instead. Rendered
+// pages are encoded as PNG.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+mod bmp;
+mod deflate;
+mod gif;
+mod inflate;
+mod jpeg;
+mod png;
+mod vp8;
+mod webp;
+
+use crate::dom;
+use crate::dom::NodeType;
+use crate::resource;
+use crate::resource::ResourceLoader;
+
+// No image may have more pixels than this, so a corrupt header can't ask for all memory.
+const MAX_PIXELS: usize = 1 << 28;
+
+// Decoded pixels, four bytes each of red, green, blue and alpha, row by row from the top left,
+// not premultiplied.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    // A transparent image.
+    pub fn new(width: u32, height: u32) -> Image {
+        Image {width, height, pixels: vec![0; width as usize * height as usize * 4]}
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        let i = (y * self.width as usize + x) * 4;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2], self.pixels[i + 3]]
+    }
+
+    fn set(&mut self, x: usize, y: usize, rgba: [u8; 4]) {
+        let i = (y * self.width as usize + x) * 4;
+        self.pixels[i..i + 4].copy_from_slice(&rgba);
+    }
+}
+
+// Decodes an image in any of the supported formats, telling them apart by their first bytes
+// rather than by a file extension or content type.
+pub fn decode(data: &[u8]) -> Result<Image, String> {
+    if data.starts_with(png::SIGNATURE) {
+        png::decode(data)
+    } else if data.starts_with(&[0xff, 0xd8, 0xff]) {
+        jpeg::decode(data)
+    } else if data.starts_with(b"GIF8") {
+        gif::decode(data)
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+        webp::decode(data)
+    } else if data.starts_with(b"BM") {
+        bmp::decode(data)
+    } else {
+        Err("Unknown image format".to_string())
+    }
+}
+
+// Encodes an image as a PNG file. The same pixels always give the same bytes.
+pub fn encode_png(image: &Image) -> Vec<u8> {
+    png::encode(image)
+}
+
+// The decoded images of a document, by the src of the <img> elements that use them.
+#[derive(Default)]
+pub struct ImageStore {
+    images: HashMap<String, Arc<Image>>,
+}
+
+impl ImageStore {
+    pub fn new() -> ImageStore {
+        Default::default()
+    }
+
+    pub fn get(&self, src: &str) -> Option<&Arc<Image>> {
+        self.images.get(src)
+    }
+
+    // Loads and decodes the image of every <img> in the document at `url`, each src once.
+    // An <img> whose image fails gets its alt text as its content, which it then shows as an
+    // inline box instead of being replaced, HTML §15.4.4. Returns what went wrong.
+    pub fn load_document_images(&mut self, root: &mut dom::Node, url: &str, loader: &dyn ResourceLoader) -> Vec<String> {
+        let mut errors = vec![];
+        let mut failed = vec![];
+        self.load_images_in(root, url, loader, &mut errors, &mut failed);
+        errors
+    }
+
+    fn load_images_in(&mut self, node: &mut dom::Node, url: &str, loader: &dyn ResourceLoader, errors: &mut Vec<String>, failed: &mut Vec<String>) {
+        if let NodeType::Element(elem) = &node.node_type {
+            if elem.tag_name == "img" {
+                let src = elem.attributes.get("src").cloned().unwrap_or_default();
+                if !src.is_empty() && !self.images.contains_key(&src) && !failed.contains(&src) {
+                    let image_url = resource::resolve_url(url, &src);
+                    match loader.load(&image_url).and_then(|data| decode(&data)) {
+                        Ok(image) => {
+                            self.images.insert(src.clone(), Arc::new(image));
+                        },
+                        Err(err) => {
+                            errors.push(format!("Couldn't show image {}: {}", image_url, err));
+                            failed.push(src.clone());
+                        },
+                    }
+                }
+                if !self.images.contains_key(&src) {
+                    let alt = elem.attributes.get("alt").cloned().unwrap_or_default();
+                    node.children = if alt.is_empty() { vec![] } else { vec![dom::text(alt)] };
+                }
+                return;
+            }
+        }
+        for child in &mut node.children {
+            self.load_images_in(child, url, loader, errors, failed);
+        }
+    }
+}
@@ -0,0 +1,90 @@
+// BMP with any of the Windows info headers: 1, 4 and 8 bit palettes, 16, 24 and 32 bit pixels
+// with or without bit field masks, bottom-up or top-down. Run length coding isn't supported.
+
+use super::Image;
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as u32)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+// A channel picked out of a pixel by its bit mask and scaled to eight bits.
+fn channel(pixel: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+    let value = (pixel & mask) >> mask.trailing_zeros();
+    let max = mask >> mask.trailing_zeros();
+    (value * 255 / max) as u8
+}
+
+pub(super) fn decode(data: &[u8]) -> Result<Image, String> {
+    let truncated = || "Truncated BMP image".to_string();
+    if !data.starts_with(b"BM") {
+        return Err("Not a BMP image".to_string());
+    }
+    let pixels_offset = u32_at(data, 10).ok_or_else(truncated)? as usize;
+    let header_size = u32_at(data, 14).ok_or_else(truncated)? as usize;
+    let (width, height, depth, compression) = if header_size == 12 {
+        //the old OS/2 header, with 16 bit sizes
+        (u16_at(data, 18).ok_or_else(truncated)? as i32, u16_at(data, 20).ok_or_else(truncated)? as i16 as i32, u16_at(data, 24).ok_or_else(truncated)?, 0)
+    } else {
+        (u32_at(data, 18).ok_or_else(truncated)? as i32, u32_at(data, 22).ok_or_else(truncated)? as i32, u16_at(data, 28).ok_or_else(truncated)?, u32_at(data, 30).ok_or_else(truncated)?)
+    };
+    //a negative height means the rows go top to bottom
+    let (top_down, width, height) = (height < 0, width.unsigned_abs() as usize, height.unsigned_abs() as usize);
+    if width == 0 || height == 0 || width * height > super::MAX_PIXELS {
+        return Err("Unsupported BMP image".to_string());
+    }
+
+    let masks = match (compression, depth) {
+        //bit fields, after the info header or inside the larger ones
+        (3, _) | (6, _) => {
+            let at = if header_size >= 52 { 54 } else { 14 + header_size };
+            let mask = |i: usize| u32_at(data, at + i * 4).ok_or_else(truncated);
+            let alpha = if compression == 6 || header_size >= 56 { mask(3)? } else { 0 };
+            [mask(0)?, mask(1)?, mask(2)?, alpha]
+        },
+        (0, 16) => [0x7c00, 0x03e0, 0x001f, 0],
+        (0, 24) | (0, 32) => [0xff0000, 0xff00, 0xff, 0],
+        (0, 1) | (0, 4) | (0, 8) => [0; 4],
+        _ => return Err("Unsupported BMP compression".to_string()),
+    };
+    let palette_entry = if header_size == 12 { 3 } else { 4 };
+    let palette = data.get(14 + header_size..pixels_offset).unwrap_or(&[]);
+
+    let mut image = Image::new(width as u32, height as u32);
+    let stride = (width * depth as usize).div_ceil(32) * 4;
+    //an alpha channel of all zeros is taken to be unused
+    let mut any_alpha = false;
+    for row in 0..height {
+        let y = if top_down { row } else { height - 1 - row };
+        let line = data.get(pixels_offset + row * stride..pixels_offset + (row + 1) * stride).ok_or_else(truncated)?;
+        for x in 0..width {
+            let rgba = match depth {
+                1 | 4 | 8 => {
+                    let depth = depth as usize;
+                    let bit = x * depth;
+                    let index = ((line[bit / 8] >> (8 - depth - bit % 8)) & ((1u16 << depth) - 1) as u8) as usize;
+                    let entry = palette.get(index * palette_entry..index * palette_entry + 3).ok_or("BMP palette index out of range")?;
+                    [entry[2], entry[1], entry[0], 255]
+                },
+                _ => {
+                    let bytes = depth as usize / 8;
+                    let pixel = line[x * bytes..(x + 1) * bytes].iter().rev().fold(0u32, |pixel, &byte| pixel << 8 | byte as u32);
+                    let alpha = channel(pixel, masks[3]);
+                    any_alpha |= alpha != 0;
+                    [channel(pixel, masks[0]), channel(pixel, masks[1]), channel(pixel, masks[2]), if masks[3] == 0 { 255 } else { alpha }]
+                },
+            };
+            image.set(x, y, rgba);
+        }
+    }
+    if masks[3] != 0 && !any_alpha {
+        image.pixels.chunks_mut(4).for_each(|pixel| pixel[3] = 255);
+    }
+    Ok(image)
+}
@@ -0,0 +1,148 @@
+// Compressing with DEFLATE, RFC 1951, in a zlib wrapper, RFC 1950, for the PNG images we
+// write. Repeats are found with a hash of the next three bytes and coded with the fixed
+// Huffman codes, which costs some size but makes the output depend on nothing but the input.
+
+use super::inflate::DISTANCE_BASES;
+use super::inflate::DISTANCE_EXTRA;
+use super::inflate::LENGTH_BASES;
+use super::inflate::LENGTH_EXTRA;
+
+const WINDOW: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+// how many earlier places with the same hash are tried for a repeat
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+
+// Writes bits least significant bit first into bytes.
+struct BitWriter {
+    output: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    // Writes the low `n` bits of `bits`, up to 32.
+    fn bits(&mut self, bits: u32, n: u32) {
+        self.buffer |= (bits as u64) << self.count;
+        self.count += n;
+        while self.count >= 8 {
+            self.output.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Writes a Huffman code of `length` bits, which go first bit first.
+    fn code(&mut self, code: u32, length: u32) {
+        self.bits(code.reverse_bits() >> (32 - length), length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.output.push(self.buffer as u8);
+        }
+        self.output
+    }
+
+    // A literal or length symbol in the fixed code, §3.2.6.
+    fn literal(&mut self, symbol: usize) {
+        let symbol = symbol as u32;
+        match symbol {
+            0..=143 => self.code(0x30 + symbol, 8),
+            144..=255 => self.code(0x190 + symbol - 144, 9),
+            256..=279 => self.code(symbol - 256, 7),
+            _ => self.code(0xc0 + symbol - 280, 8),
+        }
+    }
+
+    // A repeat of `length` bytes from `distance` back, each as its symbol and extra bits.
+    fn repeat(&mut self, length: usize, distance: usize) {
+        let index = LENGTH_BASES.partition_point(|&base| base as usize <= length) - 1;
+        self.literal(257 + index);
+        self.bits((length - LENGTH_BASES[index] as usize) as u32, LENGTH_EXTRA[index] as u32);
+        let index = DISTANCE_BASES.partition_point(|&base| base as usize <= distance) - 1;
+        self.code(index as u32, 5);
+        self.bits((distance - DISTANCE_BASES[index] as usize) as u32, DISTANCE_EXTRA[index] as u32);
+    }
+}
+
+fn hash(data: &[u8], at: usize) -> usize {
+    let value = (data[at] as u32) << 16 | (data[at + 1] as u32) << 8 | data[at + 2] as u32;
+    (value.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+}
+
+// Compresses data as one block of DEFLATE data with the fixed codes.
+pub(super) fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter {output: vec![], buffer: 0, count: 0};
+    //the last block, coded with the fixed codes
+    writer.bits(1, 1);
+    writer.bits(1, 2);
+
+    //the last place each hash was seen, and the place before that with the same hash
+    let mut heads = vec![usize::MAX; 1 << HASH_BITS];
+    let mut previous = vec![usize::MAX; WINDOW];
+    let mut at = 0;
+    while at < data.len() {
+        let (mut best_length, mut best_distance) = (0, 0);
+        if at + MIN_MATCH <= data.len() {
+            let mut candidate = heads[hash(data, at)];
+            let mut tries = 0;
+            while candidate != usize::MAX && at - candidate <= WINDOW && tries < MAX_CHAIN {
+                let limit = (data.len() - at).min(MAX_MATCH);
+                let length = (0..limit).take_while(|&i| data[candidate + i] == data[at + i]).count();
+                if length > best_length {
+                    (best_length, best_distance) = (length, at - candidate);
+                    if length == limit {
+                        break;
+                    }
+                }
+                //a place from more than a window back has been written over
+                let next = previous[candidate % WINDOW];
+                if next == usize::MAX || next >= candidate {
+                    break;
+                }
+                candidate = next;
+                tries += 1;
+            }
+        }
+        if best_length >= MIN_MATCH {
+            writer.repeat(best_length, best_distance);
+            for i in at..at + best_length {
+                insert(data, i, &mut heads, &mut previous);
+            }
+            at += best_length;
+        } else {
+            writer.literal(data[at] as usize);
+            insert(data, at, &mut heads, &mut previous);
+            at += 1;
+        }
+    }
+    writer.literal(256);
+    writer.finish()
+}
+
+fn insert(data: &[u8], at: usize, heads: &mut [usize], previous: &mut [usize]) {
+    if at + MIN_MATCH <= data.len() {
+        let hash = hash(data, at);
+        previous[at % WINDOW] = heads[hash];
+        heads[hash] = at;
+    }
+}
+
+// Compresses data into zlib data: a header, the DEFLATE data and an Adler-32 checksum.
+pub(super) fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    let mut output = vec![0x78, 0x01];
+    output.extend(deflate(data));
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    output.extend_from_slice(&(b << 16 | a).to_be_bytes());
+    output
+}
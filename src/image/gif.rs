@@ -0,0 +1,177 @@
+// GIF, https://www.w3.org/Graphics/GIF/spec-gif89a.txt. Only the first frame is decoded, drawn
+// over a transparent canvas the size of the logical screen.
+
+use super::Image;
+
+// Skips a run of data sub-blocks, each a length byte and that many bytes, ended by an empty
+// one. Returns the offset after them and their bytes joined.
+fn sub_blocks(data: &[u8], mut offset: usize) -> Result<(usize, Vec<u8>), String> {
+    let mut joined = vec![];
+    loop {
+        let length = *data.get(offset).ok_or("Truncated GIF image")? as usize;
+        offset += 1;
+        if length == 0 {
+            return Ok((offset, joined));
+        }
+        joined.extend_from_slice(data.get(offset..offset + length).ok_or("Truncated GIF image")?);
+        offset += length;
+    }
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<usize, String> {
+    data.get(offset..offset + 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as usize).ok_or_else(|| "Truncated GIF image".to_string())
+}
+
+pub(super) fn decode(data: &[u8]) -> Result<Image, String> {
+    if !data.starts_with(b"GIF87a") && !data.starts_with(b"GIF89a") {
+        return Err("Not a GIF image".to_string());
+    }
+    let (width, height) = (u16_at(data, 6)?, u16_at(data, 8)?);
+    let flags = *data.get(10).ok_or("Truncated GIF image")?;
+    let mut offset = 13;
+    let mut global_palette: &[u8] = &[];
+    if flags & 0x80 != 0 {
+        let size = 3 << ((flags & 7) + 1);
+        global_palette = data.get(offset..offset + size).ok_or("Truncated GIF palette")?;
+        offset += size;
+    }
+    let mut transparent = None;
+    loop {
+        match data.get(offset) {
+            //an extension, where only the graphic control one before the frame matters
+            Some(0x21) => {
+                let label = *data.get(offset + 1).ok_or("Truncated GIF image")?;
+                let (next, body) = sub_blocks(data, offset + 2)?;
+                if label == 0xf9 && body.len() >= 4 && body[0] & 1 != 0 {
+                    transparent = Some(body[3]);
+                }
+                offset = next;
+            },
+            Some(0x2c) => break,
+            _ => return Err("GIF image without a frame".to_string()),
+        }
+    }
+
+    let descriptor = data.get(offset..offset + 10).ok_or("Truncated GIF image")?;
+    let (left, top) = (u16_at(descriptor, 1)?, u16_at(descriptor, 3)?);
+    let (frame_width, frame_height) = (u16_at(descriptor, 5)?, u16_at(descriptor, 7)?);
+    let flags = descriptor[9];
+    offset += 10;
+    let mut palette = global_palette;
+    if flags & 0x80 != 0 {
+        let size = 3 << ((flags & 7) + 1);
+        palette = data.get(offset..offset + size).ok_or("Truncated GIF palette")?;
+        offset += size;
+    }
+    let interlaced = flags & 0x40 != 0;
+    let minimum_code_size = *data.get(offset).ok_or("Truncated GIF image")? as u32;
+    let (_, compressed) = sub_blocks(data, offset + 1)?;
+    let indices = lzw_decode(&compressed, minimum_code_size, frame_width * frame_height)?;
+
+    //some images have frames larger than their logical screen
+    let (width, height) = (width.max(left + frame_width), height.max(top + frame_height));
+    if width == 0 || height == 0 || width * height > super::MAX_PIXELS {
+        return Err("Unsupported GIF image".to_string());
+    }
+    let mut image = Image::new(width as u32, height as u32);
+    let rows: Vec<usize> = if interlaced {
+        [(0, 8), (4, 8), (2, 4), (1, 2)].iter().flat_map(|&(start, step)| (start..frame_height).step_by(step)).collect()
+    } else {
+        (0..frame_height).collect()
+    };
+    for (i, &index) in indices.iter().enumerate() {
+        let (row, column) = (rows[i / frame_width], i % frame_width);
+        if Some(index) == transparent {
+            continue;
+        }
+        let i = index as usize * 3;
+        if let Some(color) = palette.get(i..i + 3) {
+            image.set(left + column, top + row, [color[0], color[1], color[2], 255]);
+        }
+    }
+    Ok(image)
+}
+
+// Decodes variable length LZW codes, least significant bit first, into `count` palette indices.
+// Images that stop short leave the rest of the frame blank.
+fn lzw_decode(data: &[u8], minimum_code_size: u32, count: usize) -> Result<Vec<u8>, String> {
+    if !(1..=11).contains(&minimum_code_size) {
+        return Err("Invalid GIF code size".to_string());
+    }
+    let clear = 1u16 << minimum_code_size;
+    let end = clear + 1;
+    //each code is a previous code plus a byte, with its first byte and its length
+    let mut prefixes = vec![0u16; 4096];
+    let mut suffixes = vec![0u8; 4096];
+    let mut firsts = vec![0u8; 4096];
+    let mut lengths = vec![0usize; 4096];
+    for code in 0..clear {
+        suffixes[code as usize] = code as u8;
+        firsts[code as usize] = code as u8;
+        lengths[code as usize] = 1;
+    }
+    let mut output = Vec::with_capacity(count);
+    let mut size = minimum_code_size + 1;
+    let mut next = end + 1;
+    let mut previous: Option<u16> = None;
+    let (mut buffer, mut bits) = (0u32, 0u32);
+    let mut bytes = data.iter();
+    while output.len() < count {
+        while bits < size {
+            match bytes.next() {
+                Some(&byte) => buffer |= (byte as u32) << bits,
+                None => {
+                    output.resize(count, 0);
+                    return Ok(output);
+                },
+            }
+            bits += 8;
+        }
+        let code = (buffer & ((1 << size) - 1)) as u16;
+        buffer >>= size;
+        bits -= size;
+        if code == clear {
+            size = minimum_code_size + 1;
+            next = end + 1;
+            previous = None;
+            continue;
+        }
+        if code == end {
+            break;
+        }
+        let emitted = match previous {
+            None => code,
+            Some(previous) => {
+                //a code not yet made is the previous one plus its own first byte
+                let first = if code < next { firsts[code as usize] } else if code == next { firsts[previous as usize] } else {
+                    return Err("Invalid GIF code".to_string());
+                };
+                if next < 4096 {
+                    prefixes[next as usize] = previous;
+                    suffixes[next as usize] = first;
+                    firsts[next as usize] = firsts[previous as usize];
+                    lengths[next as usize] = lengths[previous as usize] + 1;
+                    next += 1;
+                    if next == 1 << size && size < 12 {
+                        size += 1;
+                    }
+                }
+                code
+            },
+        };
+        if emitted >= next && previous.is_none() {
+            return Err("Invalid GIF code".to_string());
+        }
+        let length = lengths[emitted as usize];
+        let start = output.len();
+        output.resize(start + length, 0);
+        let mut walk = emitted;
+        for i in (0..length).rev() {
+            output[start + i] = suffixes[walk as usize];
+            walk = prefixes[walk as usize];
+        }
+        previous = Some(code);
+    }
+    output.resize(count, 0);
+    Ok(output)
+}
@@ -0,0 +1,253 @@
+// DEFLATE, RFC 1951, and the zlib wrapper around it, RFC 1950, which PNG compresses its pixels
+// with. The canonical Huffman codes and the least significant bit first reading are shared
+// with lossless WebP, which codes its pixels the same way.
+
+// Reads a byte stream as bits, least significant bit of each byte first.
+pub(super) struct BitReader<'d> {
+    data: &'d [u8],
+    position: usize,
+    buffer: u64,
+    count: u32,
+}
+
+impl<'d> BitReader<'d> {
+    pub(super) fn new(data: &'d [u8]) -> BitReader<'d> {
+        BitReader {data, position: 0, buffer: 0, count: 0}
+    }
+
+    // The next `n` bits, up to 32, as a number with the first one read lowest.
+    pub(super) fn bits(&mut self, n: u32) -> Result<u32, String> {
+        while self.count < n {
+            let byte = *self.data.get(self.position).ok_or("Unexpected end of compressed data")?;
+            self.buffer |= (byte as u64) << self.count;
+            self.position += 1;
+            self.count += 8;
+        }
+        let value = (self.buffer & ((1u64 << n) - 1)) as u32;
+        self.buffer >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    pub(super) fn bit(&mut self) -> Result<bool, String> {
+        Ok(self.bits(1)? == 1)
+    }
+
+    // Skips to the next byte boundary.
+    fn align(&mut self) {
+        let skip = self.count % 8;
+        self.buffer >>= skip;
+        self.count -= skip;
+    }
+}
+
+// A canonical Huffman code, from the length of each symbol's code: shorter codes come first,
+// and codes of the same length go in symbol order. A length of 0 means the symbol is unused.
+pub(super) struct Huffman {
+    // how many codes there are of each length
+    counts: [u16; 16],
+    // the symbols in code order
+    symbols: Vec<u16>,
+    // whether a code of one symbol takes no bits to read, as in lossless WebP but not DEFLATE,
+    // where it still takes one, RFC 1951 §3.2.7
+    free_single_symbol: bool,
+}
+
+impl Huffman {
+    pub(super) fn new(lengths: &[u8]) -> Result<Huffman, String> {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        //more codes of a length than there is room for can't be decoded
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = left * 2 - count as i32;
+            if left < 0 {
+                return Err("Invalid Huffman code".to_string());
+            }
+        }
+        let mut offsets = [0u16; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.iter().filter(|&&length| length > 0).count()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length > 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Ok(Huffman {counts, symbols, free_single_symbol: false})
+    }
+
+    // A code of lossless WebP, where the only symbol of a code that has just one is read
+    // without reading any bits, §3.7.2.1.
+    pub(super) fn new_lossless_webp(lengths: &[u8]) -> Result<Huffman, String> {
+        Ok(Huffman {free_single_symbol: true, ..Huffman::new(lengths)?})
+    }
+
+    // Reads one symbol a bit at a time, the code's first bit first.
+    pub(super) fn decode(&self, reader: &mut BitReader) -> Result<u16, String> {
+        if self.free_single_symbol && self.symbols.len() == 1 {
+            return Ok(self.symbols[0]);
+        }
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.counts[1..] {
+            code |= reader.bits(1)? as i32;
+            let count = count as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("Invalid Huffman code in compressed data".to_string())
+    }
+}
+
+// The base lengths and distances of the length and distance symbols, and how many extra bits
+// follow each, §3.2.5.
+pub(super) const LENGTH_BASES: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+pub(super) const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+pub(super) const DISTANCE_BASES: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+pub(super) const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+const TOO_LONG: &str = "Compressed data longer than it can be";
+
+// The order the lengths of the code length code come in, §3.2.7.
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+// Decompresses zlib data: a two byte header, DEFLATE data and a checksum, which isn't checked.
+// Data that decompresses to more than `limit` bytes is an error, so that a little of it can't
+// take up all memory.
+pub(super) fn zlib_decompress(data: &[u8], limit: usize) -> Result<Vec<u8>, String> {
+    match data {
+        [method, flags, ..] if method & 0x0f == 8 && (*method as u16 * 256 + *flags as u16).is_multiple_of(31) && flags & 0x20 == 0 => inflate(&data[2..], limit),
+        _ => Err("Not zlib data".to_string()),
+    }
+}
+
+// Decompresses DEFLATE data, to at most `limit` bytes.
+pub(super) fn inflate(data: &[u8], limit: usize) -> Result<Vec<u8>, String> {
+    let mut reader = BitReader::new(data);
+    let mut output = vec![];
+    loop {
+        let last = reader.bit()?;
+        match reader.bits(2)? {
+            0 => {
+                reader.align();
+                let length = reader.bits(16)?;
+                let complement = reader.bits(16)?;
+                if length != !complement & 0xffff {
+                    return Err("Corrupt stored block".to_string());
+                }
+                if output.len() + length as usize > limit {
+                    return Err(TOO_LONG.to_string());
+                }
+                for _ in 0..length {
+                    output.push(reader.bits(8)? as u8);
+                }
+            },
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                let literals = Huffman::new(&lengths)?;
+                let distances = Huffman::new(&[5; 30])?;
+                inflate_block(&mut reader, &literals, &distances, &mut output, limit)?;
+            },
+            2 => {
+                let (literals, distances) = read_dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &literals, &distances, &mut output, limit)?;
+            },
+            _ => return Err("Invalid block type in compressed data".to_string()),
+        }
+        if last {
+            return Ok(output);
+        }
+    }
+}
+
+fn read_dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), String> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+    let mut code_lengths = [0u8; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[symbol] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths)?;
+
+    //the lengths of both codes come as one sequence, with runs of repeats
+    let mut lengths = vec![];
+    while lengths.len() < literal_count + distance_count {
+        let (length, repeat) = match code_length_code.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last().ok_or("Repeat of no code length")?, 3 + reader.bits(2)?),
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(length, repeat as usize));
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err("Too many code lengths".to_string());
+    }
+    Ok((Huffman::new(&lengths[..literal_count])?, Huffman::new(&lengths[literal_count..])?))
+}
+
+fn inflate_block(reader: &mut BitReader, literals: &Huffman, distances: &Huffman, output: &mut Vec<u8>, limit: usize) -> Result<(), String> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        if symbol != 256 && output.len() >= limit {
+            return Err(TOO_LONG.to_string());
+        }
+        match symbol {
+            0..=255 => output.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let index = symbol - 257;
+                let (&base, &extra) = LENGTH_BASES.get(index).zip(LENGTH_EXTRA.get(index)).ok_or("Invalid length in compressed data")?;
+                let length = base as usize + reader.bits(extra as u32)? as usize;
+                let index = distances.decode(reader)? as usize;
+                let (&base, &extra) = DISTANCE_BASES.get(index).zip(DISTANCE_EXTRA.get(index)).ok_or("Invalid distance in compressed data")?;
+                let distance = base as usize + reader.bits(extra as u32)? as usize;
+                if output.len() + length > limit {
+                    return Err(TOO_LONG.to_string());
+                }
+                if distance > output.len() {
+                    return Err("Distance too far back in compressed data".to_string());
+                }
+                //the copy may overlap what it is copying
+                let start = output.len() - distance;
+                for i in 0..length {
+                    output.push(output[start + i]);
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_distance_code_takes_a_bit() {
+        //a dynamic block whose only distance code is for a distance of 1, with "a" and a
+        //repeat of it three long
+        let data = [0x78, 0x01, 0x0d, 0xc0, 0x81, 0x00, 0x00, 0x00, 0x00, 0x80, 0x20, 0xd6, 0xfc, 0x25, 0x3e, 0x0b, 0x03, 0xce, 0x01, 0x85];
+        assert_eq!(zlib_decompress(&data, 4).unwrap(), b"aaaa");
+    }
+
+    #[test]
+    fn output_is_limited() {
+        let data = super::super::deflate::zlib_compress(&[0; 100_000]);
+        assert_eq!(zlib_decompress(&data, 100_000).unwrap().len(), 100_000);
+        assert!(zlib_decompress(&data, 99_999).is_err());
+    }
+}
@@ -0,0 +1,550 @@
+// JPEG, ITU T.81: baseline and progressive Huffman coding, any sampling factors, restart
+// intervals, and grayscale, YCbCr, RGB and Adobe CMYK and YCCK color.
+
+use std::f32::consts::PI;
+
+use super::Image;
+
+// The natural order positions of the coefficients in zigzag order, figure A.6.
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20, 13, 6, 7, 14, 21, 28,
+    35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+// A Huffman table from a DHT segment, Annex C.
+#[derive(Clone, Default)]
+struct Huffman {
+    // how many codes there are of each length from 1 to 16
+    counts: [u8; 16],
+    // the symbols in code order
+    symbols: Vec<u8>,
+}
+
+// Reads entropy coded data most significant bit first, dropping the zero byte stuffed after each
+// 0xff. At a marker it reads zeros without going past it.
+struct BitReader<'d> {
+    data: &'d [u8],
+    position: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl<'d> BitReader<'d> {
+    fn bit(&mut self) -> u32 {
+        if self.count == 0 {
+            let byte = match self.data.get(self.position..self.position + 2) {
+                Some([0xff, 0x00]) => {
+                    self.position += 2;
+                    0xff
+                },
+                Some([0xff, _]) => 0,
+                _ => match self.data.get(self.position) {
+                    Some(&byte) => {
+                        self.position += 1;
+                        byte
+                    },
+                    None => 0,
+                },
+            };
+            self.buffer = byte as u32;
+            self.count = 8;
+        }
+        self.count -= 1;
+        (self.buffer >> self.count) & 1
+    }
+
+    fn bits(&mut self, n: u8) -> u32 {
+        (0..n).fold(0, |value, _| value << 1 | self.bit())
+    }
+
+    // `n` bits read as a signed magnitude, F.2.2.1: with the first bit clear the value is
+    // negative.
+    fn receive_extend(&mut self, n: u8) -> i32 {
+        if n == 0 {
+            return 0;
+        }
+        //no real coefficient is more than 16 bits long, but a corrupt table can say one is
+        let n = n.min(16);
+        let value = self.bits(n) as i32;
+        if value < 1 << (n - 1) { value - (1 << n) + 1 } else { value }
+    }
+
+    fn decode(&mut self, table: &Huffman) -> Result<u8, String> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &table.counts {
+            code |= self.bit() as i32;
+            let count = count as i32;
+            if code - first < count {
+                return table.symbols.get((index + code - first) as usize).copied().ok_or_else(|| "Invalid JPEG Huffman table".to_string());
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("Invalid Huffman code in JPEG data".to_string())
+    }
+
+    // Skips past the restart marker expected at a restart interval, F.2.2.5.
+    fn restart(&mut self) {
+        self.count = 0;
+        while let Some(&[0xff, marker]) = self.data.get(self.position..self.position + 2) {
+            self.position += if marker == 0xff { 1 } else { 2 };
+            if (0xd0..=0xd7).contains(&marker) {
+                break;
+            }
+        }
+    }
+}
+
+struct Component {
+    id: u8,
+    h: usize,
+    v: usize,
+    quantization: usize,
+    // its blocks across and down, padded out to whole MCUs
+    blocks_across: usize,
+    blocks_down: usize,
+    // its blocks across and down covering the image, the ones a scan of it alone codes
+    coded_across: usize,
+    coded_down: usize,
+    // the coefficients of each block in natural order
+    coefficients: Vec<i32>,
+    dc_table: usize,
+    ac_table: usize,
+    dc_prediction: i32,
+}
+
+// What one scan codes, from its SOS header.
+struct Scan {
+    components: Vec<usize>,
+    spectral_start: usize,
+    spectral_end: usize,
+    approximation_high: u8,
+    approximation_low: u8,
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<usize, String> {
+    data.get(offset..offset + 2).map(|b| u16::from_be_bytes([b[0], b[1]]) as usize).ok_or_else(|| "Truncated JPEG image".to_string())
+}
+
+pub(super) fn decode(data: &[u8]) -> Result<Image, String> {
+    if !data.starts_with(&[0xff, 0xd8]) {
+        return Err("Not a JPEG image".to_string());
+    }
+    let mut quantization = [[1u16; 64]; 4];
+    let mut dc_tables = vec![Huffman::default(); 4];
+    let mut ac_tables = vec![Huffman::default(); 4];
+    let mut components: Vec<Component> = vec![];
+    let (mut width, mut height) = (0, 0);
+    let (mut max_h, mut max_v) = (1, 1);
+    let mut progressive = false;
+    let mut restart_interval = 0;
+    let mut adobe_transform = None;
+    let mut offset = 2;
+    loop {
+        //markers may be padded with any number of 0xff
+        while data.get(offset) == Some(&0xff) && data.get(offset + 1) == Some(&0xff) {
+            offset += 1;
+        }
+        let marker = match data.get(offset..offset + 2) {
+            Some(&[0xff, marker]) => marker,
+            _ => break,
+        };
+        offset += 2;
+        if marker == 0xd9 {
+            break;
+        }
+        if (0xd0..=0xd7).contains(&marker) || marker == 0x01 {
+            continue;
+        }
+        let length = u16_at(data, offset)?;
+        let segment = data.get(offset + 2..offset + length).ok_or("Truncated JPEG segment")?;
+        offset += length;
+        match marker {
+            0xdb => {
+                let mut rest = segment;
+                while let [info, ..] = *rest {
+                    let sixteen_bit = info >> 4 == 1;
+                    let size = if sixteen_bit { 129 } else { 65 };
+                    let table = rest.get(1..size).ok_or("Truncated JPEG quantization table")?;
+                    let target = quantization.get_mut(info as usize & 3).unwrap();
+                    for (i, &position) in ZIGZAG.iter().enumerate() {
+                        target[position] = if sixteen_bit { u16::from_be_bytes([table[i * 2], table[i * 2 + 1]]) } else { table[i] as u16 };
+                    }
+                    rest = &rest[size..];
+                }
+            },
+            0xc4 => {
+                let mut rest = segment;
+                while let [info, ..] = *rest {
+                    let counts = rest.get(1..17).ok_or("Truncated JPEG Huffman table")?;
+                    let total = counts.iter().map(|&count| count as usize).sum::<usize>();
+                    let symbols = rest.get(17..17 + total).ok_or("Truncated JPEG Huffman table")?;
+                    let mut table = Huffman {counts: [0; 16], symbols: symbols.to_vec()};
+                    table.counts.copy_from_slice(counts);
+                    let tables = if info >> 4 == 0 { &mut dc_tables } else { &mut ac_tables };
+                    tables[info as usize & 3] = table;
+                    rest = &rest[17 + total..];
+                }
+            },
+            0xdd => restart_interval = u16_at(segment, 0)?,
+            0xee if segment.starts_with(b"Adobe") && segment.len() >= 12 => adobe_transform = Some(segment[11]),
+            0xc0..=0xc2 => {
+                //scans index the components of the one frame
+                if !components.is_empty() {
+                    return Err("JPEG image with more than one frame".to_string());
+                }
+                progressive = marker == 0xc2;
+                height = u16_at(segment, 1)?;
+                width = u16_at(segment, 3)?;
+                let count = *segment.get(5).ok_or("Truncated JPEG frame header")? as usize;
+                for i in 0..count {
+                    let spec = segment.get(6 + i * 3..9 + i * 3).ok_or("Truncated JPEG frame header")?;
+                    let (h, v) = ((spec[1] >> 4) as usize, (spec[1] & 15) as usize);
+                    if !(1..=4).contains(&h) || !(1..=4).contains(&v) {
+                        return Err("Invalid JPEG sampling factors".to_string());
+                    }
+                    components.push(Component {
+                        id: spec[0],
+                        h,
+                        v,
+                        quantization: spec[2] as usize & 3,
+                        blocks_across: 0,
+                        blocks_down: 0,
+                        coded_across: 0,
+                        coded_down: 0,
+                        coefficients: vec![],
+                        dc_table: 0,
+                        ac_table: 0,
+                        dc_prediction: 0,
+                    });
+                }
+                if width == 0 || height == 0 || width * height > super::MAX_PIXELS || components.is_empty() {
+                    return Err("Unsupported JPEG image".to_string());
+                }
+                max_h = components.iter().map(|component| component.h).max().unwrap();
+                max_v = components.iter().map(|component| component.v).max().unwrap();
+                let (mcus_across, mcus_down) = (width.div_ceil(8 * max_h), height.div_ceil(8 * max_v));
+                for component in &mut components {
+                    component.blocks_across = mcus_across * component.h;
+                    component.blocks_down = mcus_down * component.v;
+                    component.coded_across = (width * component.h).div_ceil(max_h).div_ceil(8);
+                    component.coded_down = (height * component.v).div_ceil(max_v).div_ceil(8);
+                    component.coefficients = vec![0; component.blocks_across * component.blocks_down * 64];
+                }
+            },
+            0xc3 | 0xc5..=0xc7 | 0xc9..=0xcb | 0xcd..=0xcf => return Err("Unsupported JPEG coding".to_string()),
+            0xda => {
+                let count = *segment.first().ok_or("Truncated JPEG scan header")? as usize;
+                if !(1..=4).contains(&count) {
+                    return Err("Invalid JPEG scan header".to_string());
+                }
+                let mut scan_components = vec![];
+                for i in 0..count {
+                    let spec = segment.get(1 + i * 2..3 + i * 2).ok_or("Truncated JPEG scan header")?;
+                    let index = components.iter().position(|component| component.id == spec[0]).ok_or("JPEG scan of an unknown component")?;
+                    components[index].dc_table = (spec[1] >> 4) as usize & 3;
+                    components[index].ac_table = (spec[1] & 15) as usize & 3;
+                    scan_components.push(index);
+                }
+                let spectral = segment.get(1 + count * 2..4 + count * 2).ok_or("Truncated JPEG scan header")?;
+                let scan = Scan {
+                    components: scan_components,
+                    spectral_start: spectral[0] as usize,
+                    spectral_end: (spectral[1] as usize).min(63),
+                    approximation_high: spectral[2] >> 4,
+                    approximation_low: spectral[2] & 15,
+                };
+                //a sequential scan codes all the coefficients
+                let scan = if progressive { scan } else { Scan {spectral_start: 0, spectral_end: 63, approximation_high: 0, approximation_low: 0, ..scan} };
+                offset = decode_scan(data, offset, &scan, &mut components, (&dc_tables, &ac_tables), restart_interval)?;
+            },
+            _ => {},
+        }
+    }
+    if components.is_empty() {
+        return Err("JPEG image without a frame".to_string());
+    }
+
+    //back to samples, each component at its own resolution
+    let cosines = dct_cosines();
+    let planes: Vec<(Vec<u8>, usize)> = components.iter().map(|component| {
+        let plane_width = component.blocks_across * 8;
+        let mut plane = vec![0u8; plane_width * component.blocks_down * 8];
+        let table = &quantization[component.quantization];
+        for (i, block) in component.coefficients.chunks(64).enumerate() {
+            let (block_x, block_y) = (i % component.blocks_across, i / component.blocks_across);
+            let samples = inverse_dct(block, table, &cosines);
+            for (row, samples) in samples.chunks(8).enumerate() {
+                let start = (block_y * 8 + row) * plane_width + block_x * 8;
+                plane[start..start + 8].copy_from_slice(samples);
+            }
+        }
+        (plane, plane_width)
+    }).collect();
+
+    let mut image = Image::new(width as u32, height as u32);
+    for y in 0..height {
+        for x in 0..width {
+            let sample = |i: usize| {
+                let (plane, plane_width) = &planes[i];
+                let component = &components[i];
+                plane[(y * component.v / max_v) * plane_width + x * component.h / max_h] as f32
+            };
+            let rgb = match planes.len() {
+                1 | 2 => [sample(0); 3],
+                3 if adobe_transform == Some(0) => [sample(0), sample(1), sample(2)],
+                3 => ycbcr_to_rgb(sample(0), sample(1), sample(2)),
+                _ => {
+                    //Adobe stores CMYK inverted, so each is how much of a color there is
+                    let cmy = if adobe_transform == Some(2) { ycbcr_to_rgb(sample(0), sample(1), sample(2)) } else { [sample(0), sample(1), sample(2)] };
+                    let k = sample(3);
+                    [cmy[0] * k / 255.0, cmy[1] * k / 255.0, cmy[2] * k / 255.0]
+                },
+            };
+            let clamp = |value: f32| value.round().clamp(0.0, 255.0) as u8;
+            image.set(x, y, [clamp(rgb[0]), clamp(rgb[1]), clamp(rgb[2]), 255]);
+        }
+    }
+    Ok(image)
+}
+
+fn ycbcr_to_rgb(y: f32, cb: f32, cr: f32) -> [f32; 3] {
+    let (cb, cr) = (cb - 128.0, cr - 128.0);
+    [y + 1.402 * cr, y - 0.344136 * cb - 0.714136 * cr, y + 1.772 * cb]
+}
+
+// Decodes one scan's entropy coded data starting at `offset` into the components' coefficients.
+// Returns where the data ends, at the next marker that isn't a restart.
+fn decode_scan(data: &[u8], offset: usize, scan: &Scan, components: &mut [Component], tables: (&[Huffman], &[Huffman]), restart_interval: usize) -> Result<usize, String> {
+    let mut reader = BitReader {data, position: offset, buffer: 0, count: 0};
+    for &index in &scan.components {
+        components[index].dc_prediction = 0;
+    }
+    let mut end_of_bands = 0;
+
+    //the blocks of the scan: a lone component's cover just the image, several components'
+    //go by MCU
+    let mut blocks: Vec<(usize, usize)> = vec![];
+    if let [index] = scan.components[..] {
+        let component = &components[index];
+        for y in 0..component.coded_down {
+            for x in 0..component.coded_across {
+                blocks.push((index, y * component.blocks_across + x));
+            }
+        }
+    } else {
+        let first = &components[scan.components[0]];
+        let (mcus_across, mcus_down) = (first.blocks_across / first.h, first.blocks_down / first.v);
+        for mcu_y in 0..mcus_down {
+            for mcu_x in 0..mcus_across {
+                for &index in &scan.components {
+                    let component = &components[index];
+                    for v in 0..component.v {
+                        for h in 0..component.h {
+                            blocks.push((index, (mcu_y * component.v + v) * component.blocks_across + mcu_x * component.h + h));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    let blocks_per_mcu = if scan.components.len() == 1 { 1 } else { scan.components.iter().map(|&index| components[index].h * components[index].v).sum() };
+
+    for (i, &(index, block)) in blocks.iter().enumerate() {
+        if restart_interval > 0 && i > 0 && i % (restart_interval * blocks_per_mcu) == 0 {
+            reader.restart();
+            for &index in &scan.components {
+                components[index].dc_prediction = 0;
+            }
+            end_of_bands = 0;
+        }
+        let component = &mut components[index];
+        let (dc_table, ac_table) = (&tables.0[component.dc_table], &tables.1[component.ac_table]);
+        let coefficients = &mut component.coefficients[block * 64..block * 64 + 64];
+        let (low, high) = (scan.approximation_low, scan.approximation_high);
+        if scan.spectral_start == 0 {
+            //the DC coefficient, coded as the difference from the previous block's
+            if high == 0 {
+                let size = reader.decode(dc_table)?;
+                //corrupt data can run the prediction past any real one, which mustn't panic
+                component.dc_prediction = component.dc_prediction.wrapping_add(reader.receive_extend(size));
+                coefficients[0] = component.dc_prediction.wrapping_mul(1 << low);
+            } else if reader.bit() == 1 {
+                coefficients[0] |= 1 << low;
+            }
+            if scan.spectral_end == 0 {
+                continue;
+            }
+        }
+        let start = scan.spectral_start.max(1);
+        if high == 0 {
+            decode_ac_first(&mut reader, ac_table, coefficients, start, scan.spectral_end, low, &mut end_of_bands)?;
+        } else {
+            decode_ac_refine(&mut reader, ac_table, coefficients, start, scan.spectral_end, low, &mut end_of_bands)?;
+        }
+    }
+
+    //find the marker after the data
+    let mut position = reader.position;
+    while let Some(pair) = data.get(position..position + 2) {
+        if pair[0] == 0xff && pair[1] != 0 && !(0xd0..=0xd7).contains(&pair[1]) {
+            break;
+        }
+        position += 1;
+    }
+    Ok(position)
+}
+
+// The AC coefficients from `start` to `end` the first time they are coded, G.1.2.2: runs of
+// zeros and values, where a run of whole blocks can be ended at once. A sequential scan is the
+// same with all of them and no end of band runs.
+fn decode_ac_first(reader: &mut BitReader, table: &Huffman, coefficients: &mut [i32], start: usize, end: usize, low: u8, end_of_bands: &mut u32) -> Result<(), String> {
+    if *end_of_bands > 0 {
+        *end_of_bands -= 1;
+        return Ok(());
+    }
+    let mut k = start;
+    while k <= end {
+        let symbol = reader.decode(table)?;
+        let (run, size) = ((symbol >> 4) as usize, symbol & 15);
+        if size == 0 {
+            if run < 15 {
+                *end_of_bands = (1 << run) - 1 + reader.bits(run as u8);
+                return Ok(());
+            }
+            k += 16;
+            continue;
+        }
+        k += run;
+        if k > 63 {
+            return Err("Invalid JPEG coefficient run".to_string());
+        }
+        coefficients[ZIGZAG[k]] = reader.receive_extend(size).wrapping_mul(1 << low);
+        k += 1;
+    }
+    Ok(())
+}
+
+// One more bit of each of the AC coefficients from `start` to `end`, G.1.2.3: new ones come
+// with the runs of zeros before them, and ones already set get a correction bit each.
+fn decode_ac_refine(reader: &mut BitReader, table: &Huffman, coefficients: &mut [i32], start: usize, end: usize, low: u8, end_of_bands: &mut u32) -> Result<(), String> {
+    let (plus, minus) = (1i32 << low, -1i32 << low);
+    let refine = |reader: &mut BitReader, coefficient: &mut i32| {
+        if reader.bit() == 1 && *coefficient & plus == 0 {
+            *coefficient = coefficient.wrapping_add(if *coefficient >= 0 { plus } else { minus });
+        }
+    };
+    let mut k = start;
+    if *end_of_bands == 0 {
+        while k <= end {
+            let symbol = reader.decode(table)?;
+            let (mut run, size) = ((symbol >> 4) as i32, symbol & 15);
+            let mut value = 0;
+            if size != 0 {
+                value = if reader.bit() == 1 { plus } else { minus };
+            } else if run != 15 {
+                *end_of_bands = (1 << run) + reader.bits(run as u8);
+                break;
+            }
+            //skip the run of zeros, refining the nonzero coefficients on the way
+            while k <= end {
+                let coefficient = &mut coefficients[ZIGZAG[k]];
+                if *coefficient != 0 {
+                    refine(reader, coefficient);
+                } else {
+                    run -= 1;
+                    if run < 0 {
+                        break;
+                    }
+                }
+                k += 1;
+            }
+            if value != 0 && k <= end {
+                coefficients[ZIGZAG[k]] = value;
+            }
+            k += 1;
+        }
+    }
+    if *end_of_bands > 0 {
+        while k <= end {
+            let coefficient = &mut coefficients[ZIGZAG[k]];
+            if *coefficient != 0 {
+                refine(reader, coefficient);
+            }
+            k += 1;
+        }
+        *end_of_bands -= 1;
+    }
+    Ok(())
+}
+
+// The basis of the inverse DCT, the weight of frequency `u` at sample `x` at [x * 8 + u].
+fn dct_cosines() -> [f32; 64] {
+    let mut cosines = [0f32; 64];
+    for (i, cosine) in cosines.iter_mut().enumerate() {
+        let (x, u) = (i / 8, i % 8);
+        let scale = if u == 0 { 1.0 / 2f32.sqrt() } else { 1.0 };
+        *cosine = scale * ((2 * x + 1) as f32 * u as f32 * PI / 16.0).cos() / 2.0;
+    }
+    cosines
+}
+
+// Dequantizes a block's coefficients and turns them back into samples, A.3.3.
+fn inverse_dct(coefficients: &[i32], quantization: &[u16; 64], cosines: &[f32; 64]) -> [u8; 64] {
+    let mut values = [0f32; 64];
+    for i in 0..64 {
+        values[i] = coefficients[i] as f32 * quantization[i] as f32;
+    }
+    //rows, then columns
+    let mut rows = [0f32; 64];
+    for y in 0..8 {
+        for x in 0..8 {
+            rows[y * 8 + x] = (0..8).map(|u| cosines[x * 8 + u] * values[y * 8 + u]).sum();
+        }
+    }
+    let mut samples = [0u8; 64];
+    for x in 0..8 {
+        for y in 0..8 {
+            let value: f32 = (0..8).map(|v| cosines[y * 8 + v] * rows[v * 8 + x]).sum();
+            samples[y * 8 + x] = (value + 128.0).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A start of image and a baseline frame header for a 1x1 grayscale image.
+    const FRAME: &[u8] = &[0xff, 0xd8, 0xff, 0xc0, 0, 11, 8, 0, 1, 0, 1, 1, 1, 0x11, 0];
+
+    #[test]
+    fn scan_without_components_is_an_error() {
+        let mut data = FRAME.to_vec();
+        data.extend_from_slice(&[0xff, 0xda, 0, 6, 0, 0, 63, 0, 0, 0]);
+        assert!(decode(&data).is_err());
+        let mut data = FRAME.to_vec();
+        data.extend_from_slice(&[0xff, 0xda, 0, 16, 5, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 0, 63, 0]);
+        assert!(decode(&data).is_err());
+    }
+
+    #[test]
+    fn second_frame_is_an_error() {
+        let mut data = FRAME.to_vec();
+        data.extend_from_slice(&FRAME[2..]);
+        assert!(decode(&data).is_err());
+    }
+
+    #[test]
+    fn corrupt_data_doesnt_panic() {
+        let mut data = FRAME.to_vec();
+        data.extend_from_slice(&[0xff, 0xda, 0, 8, 1, 1, 0, 0, 63, 0]);
+        for seed in 0..64u32 {
+            let mut corrupt = data.clone();
+            corrupt.extend((0..64).map(|i| (seed.wrapping_mul(2_654_435_761).wrapping_add(i * 40_503) >> 8) as u8));
+            let _ = decode(&corrupt);
+        }
+    }
+}
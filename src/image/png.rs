@@ -0,0 +1,249 @@
+// PNG, https://www.w3.org/TR/png/: every color type and bit depth, Adam7 interlacing and
+// tRNS transparency. Sixteen bit samples are cut down to their high byte. Images are written
+// as eight bit RGBA.
+
+use super::deflate;
+use super::inflate;
+use super::Image;
+
+pub(super) const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+// The starting column and row and the column and row steps of the seven Adam7 passes.
+const ADAM7: [(usize, usize, usize, usize); 7] = [(0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)];
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+pub(super) fn decode(data: &[u8]) -> Result<Image, String> {
+    if !data.starts_with(SIGNATURE) {
+        return Err("Not a PNG image".to_string());
+    }
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut transparency: &[u8] = &[];
+    let mut compressed = vec![];
+    let mut offset = SIGNATURE.len();
+    while let Some(length) = u32_at(data, offset) {
+        let kind = data.get(offset + 4..offset + 8).ok_or("Truncated PNG chunk")?;
+        let body = data.get(offset + 8..offset + 8 + length as usize).ok_or("Truncated PNG chunk")?;
+        match kind {
+            b"IHDR" if body.len() >= 13 => header = Some(body),
+            b"PLTE" => palette = body,
+            b"tRNS" => transparency = body,
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {},
+        }
+        //past the length, type, body and checksum
+        offset += 12 + length as usize;
+    }
+    let header = header.ok_or("PNG image without a header")?;
+    let width = u32_at(header, 0).unwrap() as usize;
+    let height = u32_at(header, 4).unwrap() as usize;
+    let (depth, color_type, interlaced) = (header[8] as usize, header[9], header[12] == 1);
+    let channels = match color_type {
+        0 | 3 => 1,
+        2 => 3,
+        4 => 2,
+        6 => 4,
+        _ => return Err(format!("Unknown PNG color type {}", color_type)),
+    };
+    if ![1, 2, 4, 8, 16].contains(&depth) || width == 0 || height == 0 || width * height > super::MAX_PIXELS {
+        return Err("Unsupported PNG image".to_string());
+    }
+    let bits_per_pixel = depth * channels;
+    //filters look back at the pixel before, at least a byte back
+    let filter_step = (bits_per_pixel / 8).max(1);
+    let passes: &[(usize, usize, usize, usize)] = if interlaced { &ADAM7 } else { &[(0, 0, 1, 1)] };
+    //the size of the filtered rows of every pass, past which the data is no use
+    let size: usize = passes.iter().map(|&(x0, y0, dx, dy)| {
+        let (columns, rows) = ((width + dx - 1 - x0) / dx, (height + dy - 1 - y0) / dy);
+        if columns == 0 { 0 } else { rows * (1 + (columns * bits_per_pixel).div_ceil(8)) }
+    }).sum();
+    let raw = inflate::zlib_decompress(&compressed, size)?;
+
+    let mut image = Image::new(width as u32, height as u32);
+    let mut offset = 0;
+    for &(x0, y0, dx, dy) in passes {
+        let columns = (width + dx - 1 - x0) / dx;
+        let rows = (height + dy - 1 - y0) / dy;
+        if columns == 0 || rows == 0 {
+            continue;
+        }
+        let stride = (columns * bits_per_pixel).div_ceil(8);
+        let mut previous = vec![0u8; stride];
+        for row in 0..rows {
+            let line = raw.get(offset..offset + 1 + stride).ok_or("Truncated PNG image data")?;
+            offset += 1 + stride;
+            let mut current = line[1..].to_vec();
+            unfilter(line[0], &mut current, &previous, filter_step)?;
+            for column in 0..columns {
+                let sample = |channel: usize| read_sample(&current, (column * channels + channel) * depth, depth);
+                let rgba = match color_type {
+                    3 => {
+                        let index = sample(0) as usize;
+                        let color = palette.get(index * 3..index * 3 + 3).ok_or("PNG palette index out of range")?;
+                        [color[0], color[1], color[2], transparency.get(index).copied().unwrap_or(255)]
+                    },
+                    0 => {
+                        let gray = scale(sample(0), depth);
+                        let key = u16_at(transparency, 0);
+                        [gray, gray, gray, if key == Some(sample(0)) { 0 } else { 255 }]
+                    },
+                    2 => {
+                        let key = [u16_at(transparency, 0), u16_at(transparency, 2), u16_at(transparency, 4)];
+                        let opaque = key.iter().enumerate().any(|(channel, key)| *key != Some(sample(channel)));
+                        [scale(sample(0), depth), scale(sample(1), depth), scale(sample(2), depth), if opaque { 255 } else { 0 }]
+                    },
+                    4 => {
+                        let gray = scale(sample(0), depth);
+                        [gray, gray, gray, scale(sample(1), depth)]
+                    },
+                    _ => [scale(sample(0), depth), scale(sample(1), depth), scale(sample(2), depth), scale(sample(3), depth)],
+                };
+                image.set(x0 + column * dx, y0 + row * dy, rgba);
+            }
+            previous = current;
+        }
+    }
+    Ok(image)
+}
+
+// Encodes an image as a PNG file, each row with whichever filter leaves the smallest
+// differences, §12.8, so that it compresses well.
+pub(super) fn encode(image: &Image) -> Vec<u8> {
+    let stride = image.width as usize * 4;
+    let mut raw = Vec::with_capacity((stride + 1) * image.height as usize);
+    let mut previous = vec![0u8; stride];
+    for row in image.pixels.chunks_exact(stride.max(1)).take(image.height as usize) {
+        let filtered = (0..5).map(|filter| (filter, apply_filter(filter, row, &previous)))
+            .min_by_key(|(_, filtered)| filtered.iter().map(|&byte| (byte as i8).unsigned_abs() as u32).sum::<u32>())
+            .unwrap();
+        raw.push(filtered.0);
+        raw.extend(filtered.1);
+        previous.copy_from_slice(row);
+    }
+
+    let mut header = vec![];
+    header.extend_from_slice(&image.width.to_be_bytes());
+    header.extend_from_slice(&image.height.to_be_bytes());
+    //eight bits, RGBA, deflate, adaptive filtering, not interlaced
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    let mut data = SIGNATURE.to_vec();
+    write_chunk(&mut data, b"IHDR", &header);
+    write_chunk(&mut data, b"IDAT", &deflate::zlib_compress(&raw));
+    write_chunk(&mut data, b"IEND", &[]);
+    data
+}
+
+fn write_chunk(data: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]) {
+    data.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    data.extend_from_slice(kind);
+    data.extend_from_slice(body);
+    let crc = crc32(kind.iter().chain(body));
+    data.extend_from_slice(&crc.to_be_bytes());
+}
+
+// The CRC-32 of the chunk type and body that ends each chunk, §5.5.
+fn crc32<'d>(bytes: impl Iterator<Item = &'d u8>) -> u32 {
+    !bytes.fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| if crc & 1 != 0 { 0xedb8_8320 ^ (crc >> 1) } else { crc >> 1 })
+    })
+}
+
+// A row filtered for the row above it, the reverse of unfilter.
+fn apply_filter(filter: u8, row: &[u8], previous: &[u8]) -> Vec<u8> {
+    (0..row.len()).map(|i| {
+        let left = if i >= 4 { row[i - 4] } else { 0 };
+        let up = previous[i];
+        let up_left = if i >= 4 { previous[i - 4] } else { 0 };
+        let predicted = match filter {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((left as u16 + up as u16) / 2) as u8,
+            _ => paeth(left, up, up_left),
+        };
+        row[i].wrapping_sub(predicted)
+    }).collect()
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+}
+
+// The sample of `depth` bits starting `bit` bits into a row, most significant bit first.
+fn read_sample(row: &[u8], bit: usize, depth: usize) -> u16 {
+    match depth {
+        16 => u16::from_be_bytes([row[bit / 8], row[bit / 8 + 1]]),
+        8 => row[bit / 8] as u16,
+        _ => ((row[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1) as u8) as u16,
+    }
+}
+
+// A sample scaled to eight bits.
+fn scale(sample: u16, depth: usize) -> u8 {
+    match depth {
+        16 => (sample >> 8) as u8,
+        8 => sample as u8,
+        _ => (sample as u32 * 255 / ((1 << depth) - 1)) as u8,
+    }
+}
+
+// Undoes the filter a row was coded with, given the row above it unfiltered, §7.3.
+fn unfilter(filter: u8, row: &mut [u8], previous: &[u8], step: usize) -> Result<(), String> {
+    for i in 0..row.len() {
+        let left = if i >= step { row[i - step] } else { 0 };
+        let up = previous[i];
+        let up_left = if i >= step { previous[i - step] } else { 0 };
+        let predicted = match filter {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((left as u16 + up as u16) / 2) as u8,
+            4 => paeth(left, up, up_left),
+            _ => return Err(format!("Unknown PNG filter {}", filter)),
+        };
+        row[i] = row[i].wrapping_add(predicted);
+    }
+    Ok(())
+}
+
+// Whichever of left, up and up left is nearest to left + up - up left, in that order on ties.
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let (to_left, to_up, to_up_left) = ((estimate - left as i16).abs(), (estimate - up as i16).abs(), (estimate - up_left as i16).abs());
+    if to_left <= to_up && to_left <= to_up_left {
+        left
+    } else if to_up <= to_up_left {
+        up
+    } else {
+        up_left
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoded_image_decodes_to_the_same_pixels() {
+        //a gradient with repeats for the compressor to find and some translucent pixels
+        let mut image = Image::new(67, 40);
+        for (i, pixel) in image.pixels.chunks_mut(4).enumerate() {
+            let (x, y) = (i % 67, i / 67);
+            pixel.copy_from_slice(&[(x * 3) as u8, (y * 6) as u8, ((x / 8) * 30) as u8, if y % 10 == 0 { 128 } else { 255 }]);
+        }
+        let encoded = encode(&image);
+        assert!(encoded.starts_with(SIGNATURE));
+        let decoded = decode(&encoded).unwrap();
+        assert_eq!((decoded.width, decoded.height), (67, 40));
+        assert_eq!(decoded.pixels, image.pixels);
+
+        //and so does a transparent one
+        let decoded = decode(&encode(&Image::new(1, 1))).unwrap();
+        assert_eq!(decoded.pixels, [0, 0, 0, 0]);
+    }
+}
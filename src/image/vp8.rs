@@ -0,0 +1,906 @@
+// VP8 key frames, RFC 6386, the lossy coding of WebP: a boolean entropy coder, intra
+// prediction of 16x16 and 4x4 luma and 8x8 chroma blocks, the DCT and WHT residuals, the loop
+// filters and conversion of the 4:2:0 YUV planes to RGB.
+
+use super::Image;
+
+// The quantizer step of each quantizer index, §14.1.
+const DC_QUANT: [u16; 128] = [
+    4, 5, 6, 7, 8, 9, 10, 10, 11, 12, 13, 14, 15, 16, 17, 17, 18, 19, 20, 20, 21, 21, 22, 22, 23, 23, 24, 25, 25, 26, 27, 28,
+    29, 30, 31, 32, 33, 34, 35, 36, 37, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 46, 47, 48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58,
+    59, 60, 61, 62, 63, 64, 65, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76, 76, 77, 78, 79, 80, 81, 82, 83, 84, 85, 86, 87, 88, 89,
+    91, 93, 95, 96, 98, 100, 101, 102, 104, 106, 108, 110, 112, 114, 116, 118, 122, 124, 126, 128, 130, 132, 134, 136, 138, 140, 143, 145, 148, 151, 154, 157,
+];
+const AC_QUANT: [u16; 128] = [
+    4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35,
+    36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47, 48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 60, 62, 64, 66, 68, 70, 72, 74, 76,
+    78, 80, 82, 84, 86, 88, 90, 92, 94, 96, 98, 100, 102, 104, 106, 108, 110, 112, 114, 116, 119, 122, 125, 128, 131, 134, 137, 140, 143, 146, 149, 152,
+    155, 158, 161, 164, 167, 170, 173, 177, 181, 185, 189, 193, 197, 201, 205, 209, 213, 217, 221, 225, 229, 234, 239, 245, 249, 254, 259, 264, 269, 274, 279, 284,
+];
+
+// The position in the block of each coefficient in coding order, and the band each coding
+// position takes its probabilities from, §13.
+const ZIGZAG: [usize; 16] = [0, 1, 4, 8, 5, 2, 3, 6, 9, 12, 13, 10, 7, 11, 14, 15];
+const BANDS: [usize; 17] = [0, 1, 2, 3, 6, 4, 5, 6, 6, 6, 6, 6, 6, 6, 6, 7, 0];
+
+// The probabilities of the extra bits of the larger coefficient categories, §13.2.
+const CATEGORY_PROBABILITIES: [&[u8]; 4] = [&[173, 148, 140], &[176, 155, 140, 135], &[180, 157, 141, 134, 130], &[254, 254, 243, 230, 196, 177, 153, 140, 133, 130, 129]];
+
+// The 4x4 luma prediction modes, numbered so the 16x16 modes share the numbers of the 4x4
+// modes they match.
+const B_DC: u8 = 0;
+const B_TM: u8 = 1;
+const B_VE: u8 = 2;
+const B_HE: u8 = 3;
+const B_RD: u8 = 4;
+const B_VR: u8 = 5;
+const B_LD: u8 = 6;
+const B_VL: u8 = 7;
+const B_HD: u8 = 8;
+const B_HU: u8 = 9;
+// the 16x16 luma mode of a macroblock coded as 4x4 blocks
+const B_PRED: u8 = 10;
+
+// The tree the 4x4 modes are coded with, §11.2: positive entries index further into it and
+// the others are modes negated.
+const B_MODE_TREE: [i8; 18] = [
+    -(B_DC as i8), 1, -(B_TM as i8), 2, -(B_VE as i8), 3, 4, 6, -(B_HE as i8), 5, -(B_RD as i8), -(B_VR as i8), -(B_LD as i8), 7, -(B_VL as i8), 8, -(B_HD as i8), -(B_HU as i8),
+];
+
+// The probabilities of each 4x4 mode in a key frame given the modes of the blocks above and to
+// the left, §11.5.
+const B_MODE_PROBABILITIES: [[[u8; 9]; 10]; 10] = [
+    [[231, 120, 48, 89, 115, 113, 120, 152, 112], [152, 179, 64, 126, 170, 118, 46, 70, 95], [175, 69, 143, 80, 85, 82, 72, 155, 103], [56, 58, 10, 171, 218, 189, 17, 13, 152], [114, 26, 17, 163, 44, 195, 21, 10, 173],
+     [121, 24, 80, 195, 26, 62, 44, 64, 85], [144, 71, 10, 38, 171, 213, 144, 34, 26], [170, 46, 55, 19, 136, 160, 33, 206, 71], [63, 20, 8, 114, 114, 208, 12, 9, 226], [81, 40, 11, 96, 182, 84, 29, 16, 36]],
+    [[134, 183, 89, 137, 98, 101, 106, 165, 148], [72, 187, 100, 130, 157, 111, 32, 75, 80], [66, 102, 167, 99, 74, 62, 40, 234, 128], [41, 53, 9, 178, 241, 141, 26, 8, 107], [74, 43, 26, 146, 73, 166, 49, 23, 157],
+     [65, 38, 105, 160, 51, 52, 31, 115, 128], [104, 79, 12, 27, 217, 255, 87, 17, 7], [87, 68, 71, 44, 114, 51, 15, 186, 23], [47, 41, 14, 110, 182, 183, 21, 17, 194], [66, 45, 25, 102, 197, 189, 23, 18, 22]],
+    [[88, 88, 147, 150, 42, 46, 45, 196, 205], [43, 97, 183, 117, 85, 38, 35, 179, 61], [39, 53, 200, 87, 26, 21, 43, 232, 171], [56, 34, 51, 104, 114, 102, 29, 93, 77], [39, 28, 85, 171, 58, 165, 90, 98, 64],
+     [34, 22, 116, 206, 23, 34, 43, 166, 73], [107, 54, 32, 26, 51, 1, 81, 43, 31], [68, 25, 106, 22, 64, 171, 36, 225, 114], [34, 19, 21, 102, 132, 188, 16, 76, 124], [62, 18, 78, 95, 85, 57, 50, 48, 51]],
+    [[193, 101, 35, 159, 215, 111, 89, 46, 111], [60, 148, 31, 172, 219, 228, 21, 18, 111], [112, 113, 77, 85, 179, 255, 38, 120, 114], [40, 42, 1, 196, 245, 209, 10, 25, 109], [88, 43, 29, 140, 166, 213, 37, 43, 154],
+     [61, 63, 30, 155, 67, 45, 68, 1, 209], [100, 80, 8, 43, 154, 1, 51, 26, 71], [142, 78, 78, 16, 255, 128, 34, 197, 171], [41, 40, 5, 102, 211, 183, 4, 1, 221], [51, 50, 17, 168, 209, 192, 23, 25, 82]],
+    [[138, 31, 36, 171, 27, 166, 38, 44, 229], [67, 87, 58, 169, 82, 115, 26, 59, 179], [63, 59, 90, 180, 59, 166, 93, 73, 154], [40, 40, 21, 116, 143, 209, 34, 39, 175], [47, 15, 16, 183, 34, 223, 49, 45, 183],
+     [46, 17, 33, 183, 6, 98, 15, 32, 183], [57, 46, 22, 24, 128, 1, 54, 17, 37], [65, 32, 73, 115, 28, 128, 23, 128, 205], [40, 3, 9, 115, 51, 192, 18, 6, 223], [87, 37, 9, 115, 59, 77, 64, 21, 47]],
+    [[104, 55, 44, 218, 9, 54, 53, 130, 226], [64, 90, 70, 205, 40, 41, 23, 26, 57], [54, 57, 112, 184, 5, 41, 38, 166, 213], [30, 34, 26, 133, 152, 116, 10, 32, 134], [39, 19, 53, 221, 26, 114, 32, 73, 255],
+     [31, 9, 65, 234, 2, 15, 1, 118, 73], [75, 32, 12, 51, 192, 255, 160, 43, 51], [88, 31, 35, 67, 102, 85, 55, 186, 85], [56, 21, 23, 111, 59, 205, 45, 37, 192], [55, 38, 70, 124, 73, 102, 1, 34, 98]],
+    [[125, 98, 42, 88, 104, 85, 117, 175, 82], [95, 84, 53, 89, 128, 100, 113, 101, 45], [75, 79, 123, 47, 51, 128, 81, 171, 1], [57, 17, 5, 71, 102, 57, 53, 41, 49], [38, 33, 13, 121, 57, 73, 26, 1, 85],
+     [41, 10, 67, 138, 77, 110, 90, 47, 114], [115, 21, 2, 10, 102, 255, 166, 23, 6], [101, 29, 16, 10, 85, 128, 101, 196, 26], [57, 18, 10, 102, 102, 213, 34, 20, 43], [117, 20, 15, 36, 163, 128, 68, 1, 26]],
+    [[102, 61, 71, 37, 34, 53, 31, 243, 192], [69, 60, 71, 38, 73, 119, 28, 222, 37], [68, 45, 128, 34, 1, 47, 11, 245, 171], [62, 17, 19, 70, 146, 85, 55, 62, 70], [37, 43, 37, 154, 100, 163, 85, 160, 1],
+     [63, 9, 92, 136, 28, 64, 32, 201, 85], [75, 15, 9, 9, 64, 255, 184, 119, 16], [86, 6, 28, 5, 64, 255, 25, 248, 1], [56, 8, 17, 132, 137, 255, 55, 116, 128], [58, 15, 20, 82, 135, 57, 26, 121, 40]],
+    [[164, 50, 31, 137, 154, 133, 25, 35, 218], [51, 103, 44, 131, 131, 123, 31, 6, 158], [86, 40, 64, 135, 148, 224, 45, 183, 128], [22, 26, 17, 131, 240, 154, 14, 1, 209], [45, 16, 21, 91, 64, 222, 7, 1, 197],
+     [56, 21, 39, 155, 60, 138, 23, 102, 213], [83, 12, 13, 54, 192, 255, 68, 47, 28], [85, 26, 85, 85, 128, 128, 32, 146, 171], [18, 11, 7, 63, 144, 171, 4, 4, 246], [35, 27, 10, 146, 174, 171, 12, 26, 128]],
+    [[190, 80, 35, 99, 180, 80, 126, 54, 45], [85, 126, 47, 87, 176, 51, 41, 20, 32], [101, 75, 128, 139, 118, 146, 116, 128, 85], [56, 41, 15, 176, 236, 85, 37, 9, 62], [71, 30, 17, 119, 118, 255, 17, 18, 138],
+     [101, 38, 60, 138, 55, 70, 43, 26, 142], [146, 36, 19, 30, 171, 255, 97, 27, 20], [138, 45, 61, 62, 219, 1, 81, 188, 64], [32, 41, 20, 117, 151, 142, 20, 21, 163], [112, 19, 12, 61, 195, 128, 48, 4, 24]],
+];
+
+// The coefficient probabilities a key frame starts from, by block type, band, context and
+// token tree node, §13.5.
+type CoefficientProbabilities = [[[[u8; 11]; 3]; 8]; 4];
+
+const DEFAULT_COEFFICIENT_PROBABILITIES: CoefficientProbabilities = [
+    [
+        [[128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128], [128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128], [128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128]],
+        [[253, 136, 254, 255, 228, 219, 128, 128, 128, 128, 128], [189, 129, 242, 255, 227, 213, 255, 219, 128, 128, 128], [106, 126, 227, 252, 214, 209, 255, 255, 128, 128, 128]],
+        [[1, 98, 248, 255, 236, 226, 255, 255, 128, 128, 128], [181, 133, 238, 254, 221, 234, 255, 154, 128, 128, 128], [78, 134, 202, 247, 198, 180, 255, 219, 128, 128, 128]],
+        [[1, 185, 249, 255, 243, 255, 128, 128, 128, 128, 128], [184, 150, 247, 255, 236, 224, 128, 128, 128, 128, 128], [77, 110, 216, 255, 236, 230, 128, 128, 128, 128, 128]],
+        [[1, 101, 251, 255, 241, 255, 128, 128, 128, 128, 128], [170, 139, 241, 252, 236, 209, 255, 255, 128, 128, 128], [37, 116, 196, 243, 228, 255, 255, 255, 128, 128, 128]],
+        [[1, 204, 254, 255, 245, 255, 128, 128, 128, 128, 128], [207, 160, 250, 255, 238, 128, 128, 128, 128, 128, 128], [102, 103, 231, 255, 211, 171, 128, 128, 128, 128, 128]],
+        [[1, 152, 252, 255, 240, 255, 128, 128, 128, 128, 128], [177, 135, 243, 255, 234, 225, 128, 128, 128, 128, 128], [80, 129, 211, 255, 194, 224, 128, 128, 128, 128, 128]],
+        [[1, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128], [246, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128], [255, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128]],
+    ],
+    [
+        [[198, 35, 237, 223, 193, 187, 162, 160, 145, 155, 62], [131, 45, 198, 221, 172, 176, 220, 157, 252, 221, 1], [68, 47, 146, 208, 149, 167, 221, 162, 255, 223, 128]],
+        [[1, 149, 241, 255, 221, 224, 255, 255, 128, 128, 128], [184, 141, 234, 253, 222, 220, 255, 199, 128, 128, 128], [81, 99, 181, 242, 176, 190, 249, 202, 255, 255, 128]],
+        [[1, 129, 232, 253, 214, 197, 242, 196, 255, 255, 128], [99, 121, 210, 250, 201, 198, 255, 202, 128, 128, 128], [23, 91, 163, 242, 170, 187, 247, 210, 255, 255, 128]],
+        [[1, 200, 246, 255, 234, 255, 128, 128, 128, 128, 128], [109, 178, 241, 255, 231, 245, 255, 255, 128, 128, 128], [44, 130, 201, 253, 205, 192, 255, 255, 128, 128, 128]],
+        [[1, 132, 239, 251, 219, 209, 255, 165, 128, 128, 128], [94, 136, 225, 251, 218, 190, 255, 255, 128, 128, 128], [22, 100, 174, 245, 186, 161, 255, 199, 128, 128, 128]],
+        [[1, 182, 249, 255, 232, 235, 128, 128, 128, 128, 128], [124, 143, 241, 255, 227, 234, 128, 128, 128, 128, 128], [35, 77, 181, 251, 193, 211, 255, 205, 128, 128, 128]],
+        [[1, 157, 247, 255, 236, 231, 255, 255, 128, 128, 128], [121, 141, 235, 255, 225, 227, 255, 255, 128, 128, 128], [45, 99, 188, 251, 195, 217, 255, 224, 128, 128, 128]],
+        [[1, 1, 251, 255, 213, 255, 128, 128, 128, 128, 128], [203, 1, 248, 255, 255, 128, 128, 128, 128, 128, 128], [137, 1, 177, 255, 224, 255, 128, 128, 128, 128, 128]],
+    ],
+    [
+        [[253, 9, 248, 251, 207, 208, 255, 192, 128, 128, 128], [175, 13, 224, 243, 193, 185, 249, 198, 255, 255, 128], [73, 17, 171, 221, 161, 179, 236, 167, 255, 234, 128]],
+        [[1, 95, 247, 253, 212, 183, 255, 255, 128, 128, 128], [239, 90, 244, 250, 211, 209, 255, 255, 128, 128, 128], [155, 77, 195, 248, 188, 195, 255, 255, 128, 128, 128]],
+        [[1, 24, 239, 251, 218, 219, 255, 205, 128, 128, 128], [201, 51, 219, 255, 196, 186, 128, 128, 128, 128, 128], [69, 46, 190, 239, 201, 218, 255, 228, 128, 128, 128]],
+        [[1, 191, 251, 255, 255, 128, 128, 128, 128, 128, 128], [223, 165, 249, 255, 213, 255, 128, 128, 128, 128, 128], [141, 124, 248, 255, 255, 128, 128, 128, 128, 128, 128]],
+        [[1, 16, 248, 255, 255, 128, 128, 128, 128, 128, 128], [190, 36, 230, 255, 236, 255, 128, 128, 128, 128, 128], [149, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128]],
+        [[1, 226, 255, 128, 128, 128, 128, 128, 128, 128, 128], [247, 192, 255, 128, 128, 128, 128, 128, 128, 128, 128], [240, 128, 255, 128, 128, 128, 128, 128, 128, 128, 128]],
+        [[1, 134, 252, 255, 255, 128, 128, 128, 128, 128, 128], [213, 62, 250, 255, 255, 128, 128, 128, 128, 128, 128], [55, 93, 255, 128, 128, 128, 128, 128, 128, 128, 128]],
+        [[128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128], [128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128], [128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128]],
+    ],
+    [
+        [[202, 24, 213, 235, 186, 191, 220, 160, 240, 175, 255], [126, 38, 182, 232, 169, 184, 228, 174, 255, 187, 128], [61, 46, 138, 219, 151, 178, 240, 170, 255, 216, 128]],
+        [[1, 112, 230, 250, 199, 191, 247, 159, 255, 255, 128], [166, 109, 228, 252, 211, 215, 255, 174, 128, 128, 128], [39, 77, 162, 232, 172, 180, 245, 178, 255, 255, 128]],
+        [[1, 52, 220, 246, 198, 199, 249, 220, 255, 255, 128], [124, 74, 191, 243, 183, 193, 250, 221, 255, 255, 128], [24, 71, 130, 219, 154, 170, 243, 182, 255, 255, 128]],
+        [[1, 182, 225, 249, 219, 240, 255, 224, 128, 128, 128], [149, 150, 226, 252, 216, 205, 255, 171, 128, 128, 128], [28, 108, 170, 242, 183, 194, 254, 223, 255, 255, 128]],
+        [[1, 81, 230, 252, 204, 203, 255, 192, 128, 128, 128], [123, 102, 209, 247, 188, 196, 255, 233, 128, 128, 128], [20, 95, 153, 243, 164, 173, 255, 203, 128, 128, 128]],
+        [[1, 222, 248, 255, 216, 213, 128, 128, 128, 128, 128], [168, 175, 246, 252, 235, 205, 255, 255, 128, 128, 128], [47, 116, 215, 255, 211, 212, 255, 255, 128, 128, 128]],
+        [[1, 121, 236, 253, 212, 214, 255, 255, 128, 128, 128], [141, 84, 213, 252, 201, 202, 255, 219, 128, 128, 128], [42, 80, 160, 240, 162, 185, 255, 205, 128, 128, 128]],
+        [[1, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128], [244, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128], [238, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128]],
+    ],
+];
+
+// The probability that each coefficient probability is updated in the frame header, §13.4.
+const COEFFICIENT_UPDATE_PROBABILITIES: CoefficientProbabilities = [
+    [
+        [[255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255], [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255], [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+        [[176, 246, 255, 255, 255, 255, 255, 255, 255, 255, 255], [223, 241, 252, 255, 255, 255, 255, 255, 255, 255, 255], [249, 253, 253, 255, 255, 255, 255, 255, 255, 255, 255]],
+        [[255, 244, 252, 255, 255, 255, 255, 255, 255, 255, 255], [234, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255], [253, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+        [[255, 246, 254, 255, 255, 255, 255, 255, 255, 255, 255], [239, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255], [254, 255, 254, 255, 255, 255, 255, 255, 255, 255, 255]],
+        [[255, 248, 254, 255, 255, 255, 255, 255, 255, 255, 255], [251, 255, 254, 255, 255, 255, 255, 255, 255, 255, 255], [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+        [[255, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255], [251, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255], [254, 255, 254, 255, 255, 255, 255, 255, 255, 255, 255]],
+        [[255, 254, 253, 255, 254, 255, 255, 255, 255, 255, 255], [250, 255, 254, 255, 254, 255, 255, 255, 255, 255, 255], [254, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+        [[255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255], [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255], [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+    ],
+    [
+        [[217, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255], [225, 252, 241, 253, 255, 255, 254, 255, 255, 255, 255], [234, 250, 241, 250, 253, 255, 253, 254, 255, 255, 255]],
+        [[255, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255], [223, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255], [238, 253, 254, 254, 255, 255, 255, 255, 255, 255, 255]],
+        [[255, 248, 254, 255, 255, 255, 255, 255, 255, 255, 255], [249, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255], [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+        [[255, 253, 255, 255, 255, 255, 255, 255, 255, 255, 255], [247, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255], [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+        [[255, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255], [252, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255], [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+        [[255, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255], [253, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255], [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+        [[255, 254, 253, 255, 255, 255, 255, 255, 255, 255, 255], [250, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255], [254, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+        [[255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255], [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255], [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+    ],
+    [
+        [[186, 251, 250, 255, 255, 255, 255, 255, 255, 255, 255], [234, 251, 244, 254, 255, 255, 255, 255, 255, 255, 255], [251, 251, 243, 253, 254, 255, 254, 255, 255, 255, 255]],
+        [[255, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255], [236, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255], [251, 253, 253, 254, 254, 255, 255, 255, 255, 255, 255]],
+        [[255, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255], [254, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255], [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+        [[255, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255], [254, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255], [254, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+        [[255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255], [254, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255], [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+        [[255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255], [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255], [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+        [[255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255], [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255], [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+        [[255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255], [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255], [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+    ],
+    [
+        [[248, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255], [250, 254, 252, 254, 255, 255, 255, 255, 255, 255, 255], [248, 254, 249, 253, 255, 255, 255, 255, 255, 255, 255]],
+        [[255, 253, 253, 255, 255, 255, 255, 255, 255, 255, 255], [246, 253, 253, 255, 255, 255, 255, 255, 255, 255, 255], [252, 254, 251, 254, 254, 255, 255, 255, 255, 255, 255]],
+        [[255, 254, 252, 255, 255, 255, 255, 255, 255, 255, 255], [248, 254, 253, 255, 255, 255, 255, 255, 255, 255, 255], [253, 255, 254, 254, 255, 255, 255, 255, 255, 255, 255]],
+        [[255, 251, 254, 255, 255, 255, 255, 255, 255, 255, 255], [245, 251, 254, 255, 255, 255, 255, 255, 255, 255, 255], [253, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255]],
+        [[255, 251, 253, 255, 255, 255, 255, 255, 255, 255, 255], [252, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255], [255, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+        [[255, 252, 255, 255, 255, 255, 255, 255, 255, 255, 255], [249, 255, 254, 255, 255, 255, 255, 255, 255, 255, 255], [255, 255, 254, 255, 255, 255, 255, 255, 255, 255, 255]],
+        [[255, 255, 253, 255, 255, 255, 255, 255, 255, 255, 255], [250, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255], [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+        [[255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255], [254, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255], [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]],
+    ],
+];
+
+// The boolean entropy decoder, §7: each call decodes one bit that is 0 with probability
+// `probability / 256`.
+struct BoolDecoder<'d> {
+    data: &'d [u8],
+    position: usize,
+    value: u32,
+    range: u32,
+    bit_count: u32,
+}
+
+impl<'d> BoolDecoder<'d> {
+    fn new(data: &'d [u8]) -> BoolDecoder<'d> {
+        let mut decoder = BoolDecoder {data, position: 0, value: 0, range: 255, bit_count: 0};
+        decoder.value = decoder.next_byte() << 8 | decoder.next_byte();
+        decoder
+    }
+
+    //past the end of the data it reads zeros
+    fn next_byte(&mut self) -> u32 {
+        let byte = self.data.get(self.position).copied().unwrap_or(0);
+        self.position += 1;
+        byte as u32
+    }
+
+    fn bool(&mut self, probability: u8) -> bool {
+        let split = 1 + (((self.range - 1) * probability as u32) >> 8);
+        let big_split = split << 8;
+        let bit = if self.value >= big_split {
+            self.range -= split;
+            self.value -= big_split;
+            true
+        } else {
+            self.range = split;
+            false
+        };
+        while self.range < 128 {
+            self.value <<= 1;
+            self.range <<= 1;
+            self.bit_count += 1;
+            if self.bit_count == 8 {
+                self.bit_count = 0;
+                self.value |= self.next_byte();
+            }
+        }
+        bit
+    }
+
+    fn literal(&mut self, bits: u32) -> i32 {
+        (0..bits).fold(0, |value, _| value << 1 | self.bool(128) as i32)
+    }
+
+    fn signed(&mut self, bits: u32) -> i32 {
+        let value = self.literal(bits);
+        if self.bool(128) { -value } else { value }
+    }
+
+    // A flag and, if it is set, a signed value.
+    fn optional_signed(&mut self, bits: u32) -> i32 {
+        if self.bool(128) { self.signed(bits) } else { 0 }
+    }
+}
+
+// The dequantization factors of a segment for the DC and AC coefficients of each kind of block.
+#[derive(Clone, Copy, Default)]
+struct Quantizers {
+    y: [i32; 2],
+    y2: [i32; 2],
+    uv: [i32; 2],
+}
+
+// How a macroblock's edges are loop filtered, §15.
+#[derive(Clone, Copy, Default)]
+struct FilterStrength {
+    limit: i32,
+    interior: i32,
+    hev_threshold: i32,
+    // whether the edges between its blocks are filtered too
+    inner: bool,
+}
+
+struct Header {
+    segment_map: Option<[u8; 3]>,
+    quantizers: [Quantizers; 4],
+    // the filter level of each segment, then how it changes for 4x4 coded macroblocks, if it
+    // does
+    filter_levels: [i32; 4],
+    b_pred_filter_delta: i32,
+    simple_filter: bool,
+    sharpness: i32,
+    coefficient_probabilities: CoefficientProbabilities,
+    skip_probability: Option<u8>,
+}
+
+fn read_header(decoder: &mut BoolDecoder) -> (Header, usize) {
+    let _color_space = decoder.literal(1);
+    let _clamping = decoder.literal(1);
+    let mut segment_map = None;
+    //without segmentation every segment is the same, with no adjustments
+    let mut absolute = false;
+    let (mut segment_quantizers, mut segment_filters) = ([0; 4], [0; 4]);
+    if decoder.bool(128) {
+        let update_map = decoder.bool(128);
+        if decoder.bool(128) {
+            absolute = decoder.bool(128);
+            segment_quantizers = [(); 4].map(|_| decoder.optional_signed(7));
+            segment_filters = [(); 4].map(|_| decoder.optional_signed(6));
+        }
+        if update_map {
+            segment_map = Some([(); 3].map(|_| if decoder.bool(128) { decoder.literal(8) as u8 } else { 255 }));
+        }
+    }
+    let simple_filter = decoder.bool(128);
+    let filter_level = decoder.literal(6);
+    let sharpness = decoder.literal(3);
+    let (mut reference_delta, mut b_pred_delta) = (0, 0);
+    if decoder.bool(128) && decoder.bool(128) {
+        let reference_deltas = [(); 4].map(|_| decoder.optional_signed(6));
+        let mode_deltas = [(); 4].map(|_| decoder.optional_signed(6));
+        //a key frame only has intra macroblocks, and only 4x4 prediction has a mode delta
+        reference_delta = reference_deltas[0];
+        b_pred_delta = mode_deltas[0];
+    }
+    let partitions = 1 << decoder.literal(2);
+
+    let base = decoder.literal(7);
+    let deltas = [(); 5].map(|_| decoder.optional_signed(4));
+    let mut quantizers = [Quantizers::default(); 4];
+    let mut filter_levels = [0; 4];
+    for segment in 0..4 {
+        let index = if absolute { segment_quantizers[segment] } else { base + segment_quantizers[segment] };
+        let q = |delta: i32, table: &[u16; 128]| table[(index + delta).clamp(0, 127) as usize] as i32;
+        quantizers[segment] = Quantizers {
+            y: [q(deltas[0], &DC_QUANT), q(0, &AC_QUANT)],
+            y2: [q(deltas[1], &DC_QUANT) * 2, ((q(deltas[2], &AC_QUANT) * 101581) >> 16).max(8)],
+            uv: [q(deltas[3], &DC_QUANT).min(132), q(deltas[4], &AC_QUANT)],
+        };
+        let level = if absolute { segment_filters[segment] } else { filter_level + segment_filters[segment] };
+        filter_levels[segment] = level + reference_delta;
+    }
+
+    let _refresh_entropy = decoder.literal(1);
+    let mut coefficient_probabilities = DEFAULT_COEFFICIENT_PROBABILITIES;
+    for (i, types) in COEFFICIENT_UPDATE_PROBABILITIES.iter().enumerate() {
+        for (j, bands) in types.iter().enumerate() {
+            for (k, contexts) in bands.iter().enumerate() {
+                for (l, &update) in contexts.iter().enumerate() {
+                    if decoder.bool(update) {
+                        coefficient_probabilities[i][j][k][l] = decoder.literal(8) as u8;
+                    }
+                }
+            }
+        }
+    }
+    let skip_probability = if decoder.bool(128) { Some(decoder.literal(8) as u8) } else { None };
+    let header = Header {
+        segment_map,
+        quantizers,
+        filter_levels,
+        b_pred_filter_delta: b_pred_delta,
+        simple_filter,
+        sharpness,
+        coefficient_probabilities,
+        skip_probability,
+    };
+    (header, partitions)
+}
+
+// The luma, U and V planes of a frame, each whole macroblocks in size.
+struct Planes {
+    y: Vec<u8>,
+    u: Vec<u8>,
+    v: Vec<u8>,
+    // the width of the luma plane, twice that of the chroma planes
+    stride: usize,
+}
+
+pub(super) fn decode(data: &[u8]) -> Result<Image, String> {
+    let tag = data.get(..10).ok_or("Truncated VP8 frame")?;
+    if tag[0] & 1 != 0 || tag[3..6] != [0x9d, 0x01, 0x2a] {
+        return Err("Not a VP8 key frame".to_string());
+    }
+    let first_partition_size = (tag[0] as usize | (tag[1] as usize) << 8 | (tag[2] as usize) << 16) >> 5;
+    let width = (u16::from_le_bytes([tag[6], tag[7]]) & 0x3fff) as usize;
+    let height = (u16::from_le_bytes([tag[8], tag[9]]) & 0x3fff) as usize;
+    if width == 0 || height == 0 || width * height > super::MAX_PIXELS {
+        return Err("Unsupported VP8 frame".to_string());
+    }
+    let first_partition = data.get(10..10 + first_partition_size).ok_or("Truncated VP8 frame")?;
+    let mut decoder = BoolDecoder::new(first_partition);
+    let (header, partition_count) = read_header(&mut decoder);
+
+    //the token partitions, their sizes first except for the last
+    let mut offset = 10 + first_partition_size + 3 * (partition_count - 1);
+    let mut partitions = vec![];
+    for i in 0..partition_count {
+        let size = if i + 1 < partition_count {
+            let at = 10 + first_partition_size + 3 * i;
+            let size = data.get(at..at + 3).ok_or("Truncated VP8 frame")?;
+            size[0] as usize | (size[1] as usize) << 8 | (size[2] as usize) << 16
+        } else {
+            data.len().saturating_sub(offset)
+        };
+        partitions.push(BoolDecoder::new(data.get(offset..offset + size).unwrap_or(&[])));
+        offset += size;
+    }
+
+    let (mbs_across, mbs_down) = (width.div_ceil(16), height.div_ceil(16));
+    let stride = mbs_across * 16;
+    let mut planes = Planes {y: vec![0; stride * mbs_down * 16], u: vec![0; stride / 2 * mbs_down * 8], v: vec![0; stride / 2 * mbs_down * 8], stride};
+    let mut strengths = vec![FilterStrength::default(); mbs_across * mbs_down];
+
+    //the contexts carried from the macroblocks above and to the left: the 4x4 modes along the
+    //edge, and whether each block along it had coefficients, four luma, two U, two V and the
+    //Y2 block
+    let mut modes_above = vec![B_DC; mbs_across * 4];
+    let mut nonzero_above = vec![[false; 9]; mbs_across];
+    for mb_y in 0..mbs_down {
+        let mut modes_left = [B_DC; 4];
+        let mut nonzero_left = [false; 9];
+        let tokens = &mut partitions[mb_y % partition_count];
+        for mb_x in 0..mbs_across {
+            let segment = match header.segment_map {
+                Some(p) => if decoder.bool(p[0]) { 2 + decoder.bool(p[2]) as usize } else { decoder.bool(p[1]) as usize },
+                None => 0,
+            };
+            let skip = header.skip_probability.is_some_and(|p| decoder.bool(p));
+            let y_mode = if !decoder.bool(145) {
+                B_PRED
+            } else if decoder.bool(156) {
+                if decoder.bool(128) { B_TM } else { B_HE }
+            } else if decoder.bool(163) {
+                B_VE
+            } else {
+                B_DC
+            };
+            let mut sub_modes = [y_mode; 16];
+            if y_mode == B_PRED {
+                for i in 0..16 {
+                    let above = if i < 4 { modes_above[mb_x * 4 + i] } else { sub_modes[i - 4] };
+                    let left = if i % 4 == 0 { modes_left[i / 4] } else { sub_modes[i - 1] };
+                    let probabilities = &B_MODE_PROBABILITIES[above as usize][left as usize];
+                    let mut node = B_MODE_TREE[decoder.bool(probabilities[0]) as usize];
+                    while node > 0 {
+                        node = B_MODE_TREE[2 * node as usize + decoder.bool(probabilities[node as usize]) as usize];
+                    }
+                    sub_modes[i] = -node as u8;
+                }
+            }
+            for i in 0..4 {
+                modes_above[mb_x * 4 + i] = sub_modes[12 + i];
+                modes_left[i] = sub_modes[i * 4 + 3];
+            }
+            let uv_mode = if !decoder.bool(142) {
+                B_DC
+            } else if !decoder.bool(114) {
+                B_VE
+            } else if decoder.bool(183) {
+                B_TM
+            } else {
+                B_HE
+            };
+
+            //the residuals, in coefficient order
+            let mut coefficients = [[0i32; 16]; 25];
+            let mut has_coefficients = false;
+            if skip {
+                let keep_y2 = y_mode == B_PRED;
+                nonzero_above[mb_x] = [false, false, false, false, false, false, false, false, keep_y2 && nonzero_above[mb_x][8]];
+                nonzero_left = [false, false, false, false, false, false, false, false, keep_y2 && nonzero_left[8]];
+            } else {
+                has_coefficients = read_residuals(tokens, &header, segment, y_mode == B_PRED, &mut coefficients, &mut nonzero_above[mb_x], &mut nonzero_left);
+            }
+
+            reconstruct(&mut planes, mb_x, mb_y, mbs_across, y_mode, &sub_modes, uv_mode, &coefficients);
+            strengths[mb_y * mbs_across + mb_x] = filter_strength(&header, segment, y_mode == B_PRED, has_coefficients);
+        }
+    }
+
+    for mb_y in 0..mbs_down {
+        for mb_x in 0..mbs_across {
+            loop_filter(&mut planes, mb_x, mb_y, strengths[mb_y * mbs_across + mb_x], header.simple_filter);
+        }
+    }
+
+    let mut image = Image::new(width as u32, height as u32);
+    let chroma_stride = stride / 2;
+    let chroma_height = mbs_down * 8;
+    for y in 0..height {
+        for x in 0..width {
+            //chroma is upsampled from its four nearest samples, 9:3:3:1 by distance
+            let (cx, cy) = (x / 2, y / 2);
+            let nx = if x % 2 == 0 { cx.saturating_sub(1) } else { (cx + 1).min(chroma_stride - 1) };
+            let ny = if y % 2 == 0 { cy.saturating_sub(1) } else { (cy + 1).min(chroma_height - 1) };
+            let sample = |plane: &[u8]| {
+                let at = |x: usize, y: usize| plane[y * chroma_stride + x] as i32;
+                (9 * at(cx, cy) + 3 * at(nx, cy) + 3 * at(cx, ny) + at(nx, ny) + 8) >> 4
+            };
+            let luma = planes.y[y * stride + x] as i32;
+            let (u, v) = (sample(&planes.u), sample(&planes.v));
+            image.set(x, y, yuv_to_rgb(luma, u, v));
+        }
+    }
+    Ok(image)
+}
+
+// BT.601 with studio swing luma, which VP8 uses.
+fn yuv_to_rgb(y: i32, u: i32, v: i32) -> [u8; 4] {
+    let (y, u, v) = (1.164 * (y - 16) as f32, (u - 128) as f32, (v - 128) as f32);
+    let clamp = |value: f32| value.round().clamp(0.0, 255.0) as u8;
+    [clamp(y + 1.596 * v), clamp(y - 0.391 * u - 0.813 * v), clamp(y + 2.018 * u), 255]
+}
+
+// Reads a macroblock's coefficients, §13: a Y2 block of the luma DCs unless it is coded as 4x4
+// blocks, sixteen luma blocks, four U and four V. Returns whether any are nonzero.
+fn read_residuals(decoder: &mut BoolDecoder, header: &Header, segment: usize, b_pred: bool, coefficients: &mut [[i32; 16]; 25], above: &mut [bool; 9], left: &mut [bool; 9]) -> bool {
+    let quantizers = header.quantizers[segment];
+    let probabilities = &header.coefficient_probabilities;
+    let mut any = false;
+    let first = if b_pred {
+        0
+    } else {
+        let context = above[8] as usize + left[8] as usize;
+        let end = read_coefficients(decoder, &probabilities[1], context, quantizers.y2, 0, &mut coefficients[24]);
+        above[8] = end > 0;
+        left[8] = end > 0;
+        //the Y2 block's inverse WHT gives the DC of each luma block
+        let dcs = inverse_wht(&coefficients[24]);
+        for (block, dc) in coefficients.iter_mut().zip(dcs.iter()) {
+            block[0] = *dc;
+            any |= *dc != 0;
+        }
+        1
+    };
+    let luma_probabilities = if b_pred { &probabilities[3] } else { &probabilities[0] };
+    for y in 0..4 {
+        for x in 0..4 {
+            let context = above[x] as usize + left[y] as usize;
+            let end = read_coefficients(decoder, luma_probabilities, context, quantizers.y, first, &mut coefficients[y * 4 + x]);
+            above[x] = end > first;
+            left[y] = end > first;
+            any |= end > first;
+        }
+    }
+    for plane in 0..2 {
+        for y in 0..2 {
+            for x in 0..2 {
+                let (a, l) = (4 + plane * 2 + x, 4 + plane * 2 + y);
+                let context = above[a] as usize + left[l] as usize;
+                let end = read_coefficients(decoder, &probabilities[2], context, quantizers.uv, 0, &mut coefficients[16 + plane * 4 + y * 2 + x]);
+                above[a] = end > 0;
+                left[l] = end > 0;
+                any |= end > 0;
+            }
+        }
+    }
+    any
+}
+
+// Reads one block's coefficients from position `first` with the token tree, §13.2, and
+// dequantizes them. Returns the position after the last one read.
+fn read_coefficients(decoder: &mut BoolDecoder, probabilities: &[[[u8; 11]; 3]; 8], context: usize, quantizers: [i32; 2], first: usize, block: &mut [i32; 16]) -> usize {
+    let mut n = first;
+    let mut p = &probabilities[BANDS[n]][context];
+    while n < 16 {
+        //end of block
+        if !decoder.bool(p[0]) {
+            return n;
+        }
+        //runs of zeros
+        while !decoder.bool(p[1]) {
+            n += 1;
+            if n == 16 {
+                return 16;
+            }
+            p = &probabilities[BANDS[n]][0];
+        }
+        let next = &probabilities[BANDS[n + 1]];
+        let value = if !decoder.bool(p[2]) {
+            p = &next[1];
+            1
+        } else {
+            let value = if !decoder.bool(p[3]) {
+                if !decoder.bool(p[4]) { 2 } else { 3 + decoder.bool(p[5]) as i32 }
+            } else if !decoder.bool(p[6]) {
+                if !decoder.bool(p[7]) {
+                    5 + decoder.bool(159) as i32
+                } else {
+                    7 + 2 * decoder.bool(165) as i32 + decoder.bool(145) as i32
+                }
+            } else {
+                let high = decoder.bool(p[8]) as usize;
+                let low = decoder.bool(p[9 + high]) as usize;
+                let category = 2 * high + low;
+                let extra = CATEGORY_PROBABILITIES[category].iter().fold(0, |value, &probability| value * 2 + decoder.bool(probability) as i32);
+                extra + 3 + (8 << category)
+            };
+            p = &next[2];
+            value
+        };
+        let value = if decoder.bool(128) { -value } else { value };
+        block[ZIGZAG[n]] = value * quantizers[(n > 0) as usize];
+        n += 1;
+    }
+    16
+}
+
+// The inverse Walsh-Hadamard transform of the Y2 block, §14.3.
+fn inverse_wht(input: &[i32; 16]) -> [i32; 16] {
+    let mut temp = [0; 16];
+    for i in 0..4 {
+        let a0 = input[i] + input[12 + i];
+        let a1 = input[4 + i] + input[8 + i];
+        let a2 = input[4 + i] - input[8 + i];
+        let a3 = input[i] - input[12 + i];
+        temp[i] = a0 + a1;
+        temp[8 + i] = a0 - a1;
+        temp[4 + i] = a3 + a2;
+        temp[12 + i] = a3 - a2;
+    }
+    let mut output = [0; 16];
+    for i in 0..4 {
+        let dc = temp[i * 4] + 3;
+        let a0 = dc + temp[3 + i * 4];
+        let a1 = temp[1 + i * 4] + temp[2 + i * 4];
+        let a2 = temp[1 + i * 4] - temp[2 + i * 4];
+        let a3 = dc - temp[3 + i * 4];
+        output[i * 4] = (a0 + a1) >> 3;
+        output[i * 4 + 1] = (a3 + a2) >> 3;
+        output[i * 4 + 2] = (a0 - a1) >> 3;
+        output[i * 4 + 3] = (a3 - a2) >> 3;
+    }
+    output
+}
+
+// Adds the inverse DCT of a block's coefficients to the 4x4 prediction at `at` in `plane`,
+// §14.4.
+fn add_inverse_dct(block: &[i32; 16], plane: &mut [u8], at: usize, stride: usize) {
+    if block.iter().all(|&coefficient| coefficient == 0) {
+        return;
+    }
+    let mul1 = |a: i32| ((a * 20091) >> 16) + a;
+    let mul2 = |a: i32| (a * 35468) >> 16;
+    let mut temp = [0; 16];
+    for i in 0..4 {
+        let a = block[i] + block[8 + i];
+        let b = block[i] - block[8 + i];
+        let c = mul2(block[4 + i]) - mul1(block[12 + i]);
+        let d = mul1(block[4 + i]) + mul2(block[12 + i]);
+        temp[i * 4] = a + d;
+        temp[i * 4 + 1] = b + c;
+        temp[i * 4 + 2] = b - c;
+        temp[i * 4 + 3] = a - d;
+    }
+    for i in 0..4 {
+        let dc = temp[i] + 4;
+        let a = dc + temp[8 + i];
+        let b = dc - temp[8 + i];
+        let c = mul2(temp[4 + i]) - mul1(temp[12 + i]);
+        let d = mul1(temp[4 + i]) + mul2(temp[12 + i]);
+        let row = at + i * stride;
+        for (x, value) in [a + d, b + c, b - c, a - d].iter().enumerate() {
+            plane[row + x] = (plane[row + x] as i32 + (value >> 3)).clamp(0, 255) as u8;
+        }
+    }
+}
+
+// The edges a block is predicted from: the row above with the top left corner first, and the
+// column to the left. Outside the frame the row above is 127 and the column to the left 129.
+fn edges(plane: &[u8], stride: usize, x: usize, y: usize, size: usize, above_length: usize) -> (Vec<u8>, Vec<u8>) {
+    let mut above = vec![127u8; above_length + 1];
+    if y > 0 {
+        above[0] = if x > 0 { plane[(y - 1) * stride + x - 1] } else { 129 };
+        for i in 0..above_length {
+            above[1 + i] = plane[(y - 1) * stride + (x + i).min(stride - 1)];
+        }
+    }
+    let left = (0..size).map(|i| if x > 0 { plane[(y + i) * stride + x - 1] } else { 129 }).collect();
+    (above, left)
+}
+
+// Fills a square block with one of the whole block predictions, §12.2: DC, vertical,
+// horizontal or TrueMotion. DC averages only the edges inside the frame.
+fn predict_block(plane: &mut [u8], stride: usize, x: usize, y: usize, size: usize, mode: u8) {
+    let (above, left) = edges(plane, stride, x, y, size, size);
+    let shift = size.trailing_zeros();
+    for row in 0..size {
+        for column in 0..size {
+            let value = match mode {
+                B_VE => above[1 + column] as i32,
+                B_HE => left[row] as i32,
+                B_TM => left[row] as i32 + above[1 + column] as i32 - above[0] as i32,
+                _ => {
+                    let sum_above: i32 = above[1..].iter().map(|&a| a as i32).sum();
+                    let sum_left: i32 = left.iter().map(|&l| l as i32).sum();
+                    match (x > 0, y > 0) {
+                        (true, true) => (sum_above + sum_left + size as i32) >> (shift + 1),
+                        (false, true) => (sum_above + (size as i32 >> 1)) >> shift,
+                        (true, false) => (sum_left + (size as i32 >> 1)) >> shift,
+                        (false, false) => 128,
+                    }
+                },
+            };
+            plane[(y + row) * stride + x + column] = value.clamp(0, 255) as u8;
+        }
+    }
+}
+
+// Fills a 4x4 luma block with its prediction, §12.3, given the eight pixels above it with the
+// top left corner first, and the four to its left.
+fn predict_subblock(plane: &mut [u8], stride: usize, at: usize, mode: u8, above: &[i32; 9], left: &[i32; 4]) {
+    let average3 = |a: i32, b: i32, c: i32| (a + 2 * b + c + 2) >> 2;
+    let average2 = |a: i32, b: i32| (a + b + 1) >> 1;
+    let p = above[0];
+    let a = |i: usize| above[1 + i];
+    let l = |i: usize| left[i];
+    //the edge running from the bottom left up and along the top, for the diagonal modes
+    let edge = [l(3), l(2), l(1), l(0), p, a(0), a(1), a(2), a(3)];
+    let mut block = [[0i32; 4]; 4];
+    for (y, row) in block.iter_mut().enumerate() {
+        for (x, value) in row.iter_mut().enumerate() {
+            *value = match mode {
+                B_DC => ((0..4).map(|i| a(i) + l(i)).sum::<i32>() + 4) >> 3,
+                B_TM => l(y) + a(x) - p,
+                B_VE => average3(above[x], above[x + 1], above[x + 2]),
+                B_HE => match y {
+                    0 => average3(p, l(0), l(1)),
+                    3 => average3(l(2), l(3), l(3)),
+                    _ => average3(l(y - 1), l(y), l(y + 1)),
+                },
+                B_LD => {
+                    let i = x + y;
+                    if i < 6 { average3(a(i), a(i + 1), a(i + 2)) } else { average3(a(6), a(7), a(7)) }
+                },
+                B_RD => average3(edge[3 + x - y], edge[4 + x - y], edge[5 + x - y]),
+                B_VR => match (x, y) {
+                    (0, 3) => average3(l(2), l(1), l(0)),
+                    (0, 2) => average3(l(1), l(0), p),
+                    (_, 0) => average2(edge[4 + x], edge[5 + x]),
+                    (_, 1) => average3(edge[3 + x], edge[4 + x], edge[5 + x]),
+                    (_, 2) => average2(edge[3 + x], edge[4 + x]),
+                    _ => average3(edge[2 + x], edge[3 + x], edge[4 + x]),
+                },
+                B_VL => match (x, y) {
+                    (3, 2) => average3(a(4), a(5), a(6)),
+                    (3, 3) => average3(a(5), a(6), a(7)),
+                    (_, 0) => average2(a(x), a(x + 1)),
+                    (_, 1) => average3(a(x), a(x + 1), a(x + 2)),
+                    (_, 2) => average2(a(x + 1), a(x + 2)),
+                    _ => average3(a(x + 1), a(x + 2), a(x + 3)),
+                },
+                B_HD => match (x, y) {
+                    (3, 0) => average3(a(0), a(1), a(2)),
+                    (2, 0) => average3(p, a(0), a(1)),
+                    //down the left edge, moving two columns each row
+                    _ if x % 2 == 0 => average2(edge[3 + x / 2 - y], edge[4 + x / 2 - y]),
+                    _ => average3(edge[3 + x / 2 - y], edge[4 + x / 2 - y], edge[5 + x / 2 - y]),
+                },
+                _ => match 2 * y + x {
+                    0 => average2(l(0), l(1)),
+                    1 => average3(l(0), l(1), l(2)),
+                    2 => average2(l(1), l(2)),
+                    3 => average3(l(1), l(2), l(3)),
+                    4 => average2(l(2), l(3)),
+                    5 => average3(l(2), l(3), l(3)),
+                    _ => l(3),
+                },
+            };
+        }
+    }
+    for (y, row) in block.iter().enumerate() {
+        for (x, value) in row.iter().enumerate() {
+            plane[at + y * stride + x] = (*value).clamp(0, 255) as u8;
+        }
+    }
+}
+
+// Predicts a macroblock and adds its residuals, §12 and §14.
+#[allow(clippy::too_many_arguments)]
+fn reconstruct(planes: &mut Planes, mb_x: usize, mb_y: usize, mbs_across: usize, y_mode: u8, sub_modes: &[u8; 16], uv_mode: u8, coefficients: &[[i32; 16]; 25]) {
+    let stride = planes.stride;
+    let (x0, y0) = (mb_x * 16, mb_y * 16);
+    if y_mode == B_PRED {
+        //the pixels above and to the right of the macroblock, which the blocks along its right
+        //edge all use
+        let above_right: [i32; 4] = if mb_y == 0 {
+            [127; 4]
+        } else if mb_x + 1 == mbs_across {
+            [planes.y[(y0 - 1) * stride + x0 + 15] as i32; 4]
+        } else {
+            [0, 1, 2, 3].map(|i| planes.y[(y0 - 1) * stride + x0 + 16 + i] as i32)
+        };
+        for i in 0..16 {
+            let (x, y) = (x0 + i % 4 * 4, y0 + i / 4 * 4);
+            let (edge_above, edge_left) = edges(&planes.y, stride, x, y, 4, 4);
+            let mut above = [0i32; 9];
+            for (j, value) in edge_above.iter().enumerate() {
+                above[j] = *value as i32;
+            }
+            //the blocks along the right edge take the macroblock's above right, the others what
+            //is above and right of them already
+            for j in 0..4 {
+                above[5 + j] = if i % 4 == 3 {
+                    above_right[j]
+                } else if y == 0 {
+                    127
+                } else {
+                    planes.y[(y - 1) * stride + x + 4 + j] as i32
+                };
+            }
+            let left = [0, 1, 2, 3].map(|j| edge_left[j] as i32);
+            let at = y * stride + x;
+            predict_subblock(&mut planes.y, stride, at, sub_modes[i], &above, &left);
+            add_inverse_dct(&coefficients[i], &mut planes.y, at, stride);
+        }
+    } else {
+        predict_block(&mut planes.y, stride, x0, y0, 16, y_mode);
+        for (i, block) in coefficients[..16].iter().enumerate() {
+            add_inverse_dct(block, &mut planes.y, (y0 + i / 4 * 4) * stride + x0 + i % 4 * 4, stride);
+        }
+    }
+    let chroma_stride = stride / 2;
+    let (x0, y0) = (mb_x * 8, mb_y * 8);
+    for (plane, blocks) in [(&mut planes.u, &coefficients[16..20]), (&mut planes.v, &coefficients[20..24])] {
+        predict_block(plane, chroma_stride, x0, y0, 8, uv_mode);
+        for (i, block) in blocks.iter().enumerate() {
+            add_inverse_dct(block, plane, (y0 + i / 2 * 4) * chroma_stride + x0 + i % 2 * 4, chroma_stride);
+        }
+    }
+}
+
+// How strongly a macroblock's edges are filtered, §15.1 and §9.6.
+fn filter_strength(header: &Header, segment: usize, b_pred: bool, has_coefficients: bool) -> FilterStrength {
+    let level = (header.filter_levels[segment] + if b_pred { header.b_pred_filter_delta } else { 0 }).clamp(0, 63);
+    if level == 0 {
+        return FilterStrength::default();
+    }
+    let mut interior = level;
+    if header.sharpness > 0 {
+        interior >>= if header.sharpness > 4 { 2 } else { 1 };
+        interior = interior.min(9 - header.sharpness);
+    }
+    let interior = interior.max(1);
+    FilterStrength {
+        limit: 2 * level + interior,
+        interior,
+        hev_threshold: if level >= 40 { 2 } else if level >= 15 { 1 } else { 0 },
+        //macroblocks with no coefficients predicted whole have no edges inside to smooth
+        inner: b_pred || has_coefficients,
+    }
+}
+
+// Filters the edges of a macroblock: its left edge, the edges between its columns of blocks,
+// its top edge and the edges between its rows, §15.
+fn loop_filter(planes: &mut Planes, mb_x: usize, mb_y: usize, strength: FilterStrength, simple: bool) {
+    if strength.limit == 0 {
+        return;
+    }
+    let stride = planes.stride;
+    let chroma_stride = stride / 2;
+    //the left and inner vertical edges come before the top and inner horizontal ones, each
+    //edge as its first pixel past it, the step across it and the step along it
+    for &(vertical, outer) in &[(true, mb_x > 0), (false, mb_y > 0)] {
+        let (across, along, chroma_across, chroma_along) = if vertical { (1, stride, 1, chroma_stride) } else { (stride, 1, chroma_stride, 1) };
+        let luma_at = |offset: usize| if vertical { mb_y * 16 * stride + mb_x * 16 + offset } else { (mb_y * 16 + offset) * stride + mb_x * 16 };
+        let chroma_at = |offset: usize| if vertical { mb_y * 8 * chroma_stride + mb_x * 8 + offset } else { (mb_y * 8 + offset) * chroma_stride + mb_x * 8 };
+        let mut luma_edges = vec![];
+        let mut chroma_edges = vec![];
+        if outer {
+            luma_edges.push((luma_at(0), true));
+            chroma_edges.push((chroma_at(0), true));
+        }
+        if strength.inner {
+            luma_edges.extend([4, 8, 12].iter().map(|&offset| (luma_at(offset), false)));
+            chroma_edges.push((chroma_at(4), false));
+        }
+        for (at, outer) in luma_edges {
+            filter_edge(&mut planes.y, at, across, along, 16, outer, strength, simple);
+        }
+        //the simple filter leaves chroma alone
+        if !simple {
+            for (at, outer) in chroma_edges {
+                filter_edge(&mut planes.u, at, chroma_across, chroma_along, 8, outer, strength, simple);
+                filter_edge(&mut planes.v, at, chroma_across, chroma_along, 8, outer, strength, simple);
+            }
+        }
+    }
+}
+
+// Filters `length` pixels along one edge, each with the three pixels before it and the four
+// from it across the edge.
+#[allow(clippy::too_many_arguments)]
+fn filter_edge(plane: &mut [u8], at: usize, across: usize, along: usize, length: usize, outer: bool, strength: FilterStrength, simple: bool) {
+    let limit = if outer { strength.limit + 4 } else { strength.limit };
+    let clamp = |value: i32| value.clamp(-128, 127);
+    for i in 0..length {
+        let center = at + i * along;
+        //pixels as signed values around zero, p before the edge and q after it
+        let get = |plane: &[u8], offset: isize| plane[(center as isize + offset * across as isize) as usize] as i32 - 128;
+        let [p3, p2, p1, p0, q0, q1, q2, q3] = [-4, -3, -2, -1, 0, 1, 2, 3].map(|offset| get(plane, offset));
+        if (p0 - q0).abs() * 2 + ((p1 - q1).abs() >> 1) > limit {
+            continue;
+        }
+        let mut set = |offset: isize, value: i32| plane[(center as isize + offset * across as isize) as usize] = (clamp(value) + 128) as u8;
+        //adjusts the two pixels at the edge, returning how much q0 moved
+        let common_adjust = |use_outer_taps: bool, set: &mut dyn FnMut(isize, i32)| {
+            let a = clamp((if use_outer_taps { clamp(p1 - q1) } else { 0 }) + 3 * (q0 - p0));
+            let b = clamp(a + 3) >> 3;
+            let a = clamp(a + 4) >> 3;
+            set(0, q0 - a);
+            set(-1, p0 + b);
+            a
+        };
+        if simple {
+            common_adjust(true, &mut set);
+            continue;
+        }
+        let interior = strength.interior;
+        if [(p3, p2), (p2, p1), (p1, p0), (q3, q2), (q2, q1), (q1, q0)].iter().any(|(a, b)| (a - b).abs() > interior) {
+            continue;
+        }
+        let high_edge_variance = (p1 - p0).abs() > strength.hev_threshold || (q1 - q0).abs() > strength.hev_threshold;
+        if outer {
+            if high_edge_variance {
+                common_adjust(true, &mut set);
+            } else {
+                let w = clamp(clamp(p1 - q1) + 3 * (q0 - p0));
+                for (weight, before, after, p, q) in [(27, -1, 0, p0, q0), (18, -2, 1, p1, q1), (9, -3, 2, p2, q2)] {
+                    let a = clamp((weight * w + 63) >> 7);
+                    set(after, q - a);
+                    set(before, p + a);
+                }
+            }
+        } else {
+            let a = (common_adjust(high_edge_variance, &mut set) + 1) >> 1;
+            if !high_edge_variance {
+                set(1, q1 - a);
+                set(-2, p1 + a);
+            }
+        }
+    }
+}
@@ -0,0 +1,427 @@
+// WebP, https://developers.google.com/speed/webp/docs/riff_container: the RIFF container with
+// its simple and extended forms, separate alpha in ALPH chunks, the first frame of an animation,
+// and the lossless VP8L format. Lossy frames are decoded by vp8.rs.
+
+use super::inflate::BitReader;
+use super::inflate::Huffman;
+use super::vp8;
+use super::Image;
+
+fn u24_at(data: &[u8], offset: usize) -> Option<usize> {
+    data.get(offset..offset + 3).map(|b| b[0] as usize | (b[1] as usize) << 8 | (b[2] as usize) << 16)
+}
+
+// The chunks in `data`, each its four byte kind and its body.
+fn chunks(data: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut chunks = vec![];
+    let mut offset = 0;
+    while let Some(header) = data.get(offset..offset + 8) {
+        let length = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let body = &data[offset + 8..(offset + 8).saturating_add(length).min(data.len())];
+        chunks.push((&header[..4], body));
+        //bodies are padded to an even length
+        offset += 8 + length + (length & 1);
+    }
+    chunks
+}
+
+pub(super) fn decode(data: &[u8]) -> Result<Image, String> {
+    let chunks = chunks(&data[12..]);
+    let frame = match chunks.iter().find(|(kind, _)| *kind == b"ANMF") {
+        //an animation shows its first frame on a transparent canvas
+        Some((_, body)) => {
+            let (width, height) = match chunks.iter().find(|(kind, _)| *kind == b"VP8X") {
+                Some((_, header)) => (u24_at(header, 4).ok_or("Truncated WebP header")? + 1, u24_at(header, 7).ok_or("Truncated WebP header")? + 1),
+                None => return Err("WebP animation without a canvas".to_string()),
+            };
+            let (x, y) = (u24_at(body, 0).ok_or("Truncated WebP frame")? * 2, u24_at(body, 3).ok_or("Truncated WebP frame")? * 2);
+            let frame = decode_frame(&self::chunks(body.get(16..).unwrap_or(&[])))?;
+            if width * height > super::MAX_PIXELS {
+                return Err("Unsupported WebP image".to_string());
+            }
+            let mut canvas = Image::new(width as u32, height as u32);
+            for row in 0..(frame.height as usize).min(height.saturating_sub(y)) {
+                for column in 0..(frame.width as usize).min(width.saturating_sub(x)) {
+                    canvas.set(x + column, y + row, frame.pixel(column, row));
+                }
+            }
+            canvas
+        },
+        None => decode_frame(&chunks)?,
+    };
+    Ok(frame)
+}
+
+// Decodes the image in a frame's chunks, lossy with optional alpha or lossless.
+fn decode_frame(chunks: &[(&[u8], &[u8])]) -> Result<Image, String> {
+    let chunk = |name: &[u8]| chunks.iter().find(|(kind, _)| *kind == name).map(|(_, body)| *body);
+    if let Some(body) = chunk(b"VP8L") {
+        return decode_lossless(body);
+    }
+    let mut image = vp8::decode(chunk(b"VP8 ").ok_or("WebP image without a frame")?)?;
+    if let Some(alpha) = chunk(b"ALPH") {
+        let alpha = decode_alpha(alpha, image.width as usize, image.height as usize)?;
+        for (pixel, alpha) in image.pixels.chunks_mut(4).zip(alpha) {
+            pixel[3] = alpha;
+        }
+    }
+    Ok(image)
+}
+
+// The alpha of a lossy frame, raw or coded as the green of a lossless image, then unfiltered.
+fn decode_alpha(chunk: &[u8], width: usize, height: usize) -> Result<Vec<u8>, String> {
+    let header = *chunk.first().ok_or("Empty WebP alpha")?;
+    let mut alpha = match header & 3 {
+        0 => chunk[1..].to_vec(),
+        1 => {
+            let mut reader = BitReader::new(&chunk[1..]);
+            decode_image_stream(&mut reader, width, height)?.iter().map(|argb| (argb >> 8) as u8).collect()
+        },
+        _ => return Err("Unknown WebP alpha compression".to_string()),
+    };
+    if alpha.len() < width * height {
+        return Err("Truncated WebP alpha".to_string());
+    }
+    //each value was predicted from the ones left of, above or both, and only the difference kept
+    let filter = (header >> 2) & 3;
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            let predicted = match (filter, x, y) {
+                (0, _, _) | (_, 0, 0) => 0,
+                (1, 0, _) | (3, 0, _) => alpha[i - width],
+                (2, _, 0) | (3, _, 0) | (1, _, _) => alpha[i - 1],
+                (2, _, _) => alpha[i - width],
+                _ => (alpha[i - 1] as i32 + alpha[i - width] as i32 - alpha[i - width - 1] as i32).clamp(0, 255) as u8,
+            };
+            alpha[i] = alpha[i].wrapping_add(predicted);
+        }
+    }
+    alpha.truncate(width * height);
+    Ok(alpha)
+}
+
+// The distances of the 120 nearest pixels back, coded as their row above (high nibble) and
+// eight minus their column offset (low nibble), §4.2.2 of the lossless specification.
+const DISTANCE_MAP: [u8; 120] = [
+    0x18, 0x07, 0x17, 0x19, 0x28, 0x06, 0x27, 0x29, 0x16, 0x1a, 0x26, 0x2a, 0x38, 0x05, 0x37, 0x39, 0x15, 0x1b, 0x36, 0x3a,
+    0x25, 0x2b, 0x48, 0x04, 0x47, 0x49, 0x14, 0x1c, 0x35, 0x3b, 0x46, 0x4a, 0x24, 0x2c, 0x58, 0x45, 0x4b, 0x34, 0x3c, 0x03,
+    0x57, 0x59, 0x13, 0x1d, 0x56, 0x5a, 0x23, 0x2d, 0x44, 0x4c, 0x55, 0x5b, 0x33, 0x3d, 0x68, 0x02, 0x67, 0x69, 0x12, 0x1e,
+    0x66, 0x6a, 0x22, 0x2e, 0x54, 0x5c, 0x43, 0x4d, 0x65, 0x6b, 0x32, 0x3e, 0x78, 0x01, 0x77, 0x79, 0x53, 0x5d, 0x11, 0x1f,
+    0x64, 0x6c, 0x42, 0x4e, 0x76, 0x7a, 0x21, 0x2f, 0x75, 0x7b, 0x31, 0x3f, 0x63, 0x6d, 0x52, 0x5e, 0x00, 0x74, 0x7c, 0x41,
+    0x4f, 0x10, 0x20, 0x62, 0x6e, 0x30, 0x73, 0x7d, 0x51, 0x5f, 0x40, 0x72, 0x7e, 0x61, 0x6f, 0x50, 0x71, 0x7f, 0x60, 0x70,
+];
+
+// The order the lengths of the code length code come in, §3.7.2.1.2.
+const CODE_LENGTH_ORDER: [usize; 19] = [17, 18, 0, 1, 2, 3, 4, 5, 16, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+
+enum Transform {
+    // the size of the blocks as a power of two, and each block's prediction mode or color
+    // transform
+    Predictor(u32, Vec<u32>),
+    Color(u32, Vec<u32>),
+    SubtractGreen,
+    // how many pixels are packed into one as a power of two, and the colors
+    ColorIndexing(u32, Vec<u32>),
+}
+
+fn decode_lossless(data: &[u8]) -> Result<Image, String> {
+    let mut reader = BitReader::new(data);
+    if reader.bits(8)? != 0x2f {
+        return Err("Not a lossless WebP image".to_string());
+    }
+    let width = reader.bits(14)? as usize + 1;
+    let height = reader.bits(14)? as usize + 1;
+    let _alpha_used = reader.bit()?;
+    if reader.bits(3)? != 0 {
+        return Err("Unknown lossless WebP version".to_string());
+    }
+    if width * height > super::MAX_PIXELS {
+        return Err("Unsupported WebP image".to_string());
+    }
+    let argb = decode_image_stream(&mut reader, width, height)?;
+    let mut image = Image::new(width as u32, height as u32);
+    for (pixel, argb) in image.pixels.chunks_mut(4).zip(argb) {
+        pixel.copy_from_slice(&[(argb >> 16) as u8, (argb >> 8) as u8, argb as u8, (argb >> 24) as u8]);
+    }
+    Ok(image)
+}
+
+// How many blocks of 2^bits cover `size`.
+fn blocks(size: usize, bits: u32) -> usize {
+    (size + (1 << bits) - 1) >> bits
+}
+
+// The transforms and then the pixels of an image, with the transforms undone, §4 and §5.
+fn decode_image_stream(reader: &mut BitReader, width: usize, height: usize) -> Result<Vec<u32>, String> {
+    let mut transforms = vec![];
+    //color indexing packs several pixels into one, narrowing what comes after it
+    let mut coded_width = width;
+    while reader.bit()? {
+        if transforms.len() == 4 {
+            return Err("Too many lossless WebP transforms".to_string());
+        }
+        let width_before = coded_width;
+        let transform = match reader.bits(2)? {
+            kind @ 0..=1 => {
+                let bits = reader.bits(3)? + 2;
+                let data = decode_entropy_image(reader, blocks(coded_width, bits), blocks(height, bits), false)?;
+                if kind == 0 { Transform::Predictor(bits, data) } else { Transform::Color(bits, data) }
+            },
+            2 => Transform::SubtractGreen,
+            _ => {
+                let size = reader.bits(8)? as usize + 1;
+                let mut colors = decode_entropy_image(reader, size, 1, false)?;
+                //each color is stored as the difference from the one before it
+                for i in 1..colors.len() {
+                    colors[i] = add_pixels(colors[i], colors[i - 1]);
+                }
+                let bits = match size {
+                    0..=2 => 3,
+                    3..=4 => 2,
+                    5..=16 => 1,
+                    _ => 0,
+                };
+                coded_width = blocks(coded_width, bits);
+                Transform::ColorIndexing(bits, colors)
+            },
+        };
+        //each is undone at the width it was read at
+        transforms.push((transform, width_before));
+    }
+
+    let mut pixels = decode_entropy_image(reader, coded_width, height, true)?;
+    for (transform, width) in transforms.iter().rev() {
+        let width = *width;
+        match transform {
+            Transform::Predictor(bits, modes) => undo_prediction(&mut pixels, width, height, *bits, modes),
+            Transform::Color(bits, elements) => {
+                let blocks_across = blocks(width, *bits);
+                for (i, pixel) in pixels.iter_mut().enumerate() {
+                    let (x, y) = (i % width, i / width);
+                    let element = elements[(y >> bits) * blocks_across + (x >> bits)];
+                    let delta = |factor: u32, channel: u32| ((factor as u8 as i8 as i32 * channel as u8 as i8 as i32) >> 5) as u32;
+                    let (green, mut red, mut blue) = ((*pixel >> 8) & 0xff, (*pixel >> 16) & 0xff, *pixel & 0xff);
+                    red = (red + delta(element, green)) & 0xff;
+                    blue = (blue + delta(element >> 8, green)) & 0xff;
+                    blue = (blue + delta(element >> 16, red)) & 0xff;
+                    *pixel = (*pixel & 0xff00ff00) | red << 16 | blue;
+                }
+            },
+            Transform::SubtractGreen => {
+                for pixel in &mut pixels {
+                    let green = (*pixel >> 8) & 0xff;
+                    *pixel = add_pixels(*pixel, green << 16 | green);
+                }
+            },
+            Transform::ColorIndexing(bits, colors) => {
+                let packed_width = blocks(width, *bits);
+                let pixels_before = pixels;
+                let per_pixel = 8 >> bits;
+                pixels = (0..width * height).map(|i| {
+                    let (x, y) = (i % width, i / width);
+                    let packed = (pixels_before[y * packed_width + (x >> bits)] >> 8) & 0xff;
+                    let index = (packed >> ((x & ((1 << bits) - 1)) * per_pixel)) & ((1 << per_pixel) - 1);
+                    //an index past the end of the colors is transparent black
+                    colors.get(index as usize).copied().unwrap_or(0)
+                }).collect();
+            },
+        }
+    }
+    Ok(pixels)
+}
+
+// Adds each pixel's prediction back, from the pixels left of and above it with the mode of its
+// block, §4.1.
+fn undo_prediction(pixels: &mut [u32], width: usize, height: usize, bits: u32, modes: &[u32]) {
+    let blocks_across = blocks(width, bits);
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            let predicted = if y == 0 {
+                if x == 0 { 0xff000000 } else { pixels[i - 1] }
+            } else if x == 0 {
+                pixels[i - width]
+            } else {
+                let (left, top, top_left) = (pixels[i - 1], pixels[i - width], pixels[i - width - 1]);
+                //the last column's top right is the first pixel of its own row
+                let top_right = pixels[i - width + 1];
+                match (modes[(y >> bits) * blocks_across + (x >> bits)] >> 8) & 0xf {
+                    0 => 0xff000000,
+                    1 => left,
+                    2 => top,
+                    3 => top_right,
+                    4 => top_left,
+                    5 => average(average(left, top_right), top),
+                    6 => average(left, top_left),
+                    7 => average(left, top),
+                    8 => average(top_left, top),
+                    9 => average(top, top_right),
+                    10 => average(average(left, top_left), average(top, top_right)),
+                    11 => select(left, top, top_left),
+                    12 => per_channel(left, top, top_left, |l, t, tl| l + t - tl),
+                    13 => per_channel(average(left, top), top_left, 0, |a, tl, _| a + (a - tl) / 2),
+                    _ => 0,
+                }
+            };
+            pixels[i] = add_pixels(pixels[i], predicted);
+        }
+    }
+}
+
+fn channels(pixel: u32) -> [i32; 4] {
+    [(pixel >> 24) as i32, (pixel >> 16 & 0xff) as i32, (pixel >> 8 & 0xff) as i32, (pixel & 0xff) as i32]
+}
+
+fn per_channel(a: u32, b: u32, c: u32, f: impl Fn(i32, i32, i32) -> i32) -> u32 {
+    let (a, b, c) = (channels(a), channels(b), channels(c));
+    (0..4).fold(0, |pixel, i| pixel << 8 | f(a[i], b[i], c[i]).clamp(0, 255) as u32)
+}
+
+fn average(a: u32, b: u32) -> u32 {
+    per_channel(a, b, 0, |a, b, _| (a + b) / 2)
+}
+
+// Left or top, whichever is nearer to left + top - top left.
+fn select(left: u32, top: u32, top_left: u32) -> u32 {
+    let (l, t, tl) = (channels(left), channels(top), channels(top_left));
+    let to_left: i32 = (0..4).map(|i| (t[i] - tl[i]).abs()).sum();
+    let to_top: i32 = (0..4).map(|i| (l[i] - tl[i]).abs()).sum();
+    if to_left < to_top { left } else { top }
+}
+
+fn add_pixels(a: u32, b: u32) -> u32 {
+    let alpha_green = (a & 0xff00ff00).wrapping_add(b & 0xff00ff00) & 0xff00ff00;
+    let red_blue = (a & 0x00ff00ff).wrapping_add(b & 0x00ff00ff) & 0x00ff00ff;
+    alpha_green | red_blue
+}
+
+// Reads one prefix code, either a simple one of one or two symbols or one given by the lengths
+// of its codes, §3.7.2.1.
+fn read_code(reader: &mut BitReader, alphabet: usize) -> Result<Huffman, String> {
+    let mut lengths = vec![0u8; alphabet];
+    if reader.bit()? {
+        let count = reader.bits(1)? + 1;
+        let first_bits = if reader.bit()? { 8 } else { 1 };
+        let symbols = [reader.bits(first_bits)? as usize, if count == 2 { reader.bits(8)? as usize } else { 0 }];
+        for &symbol in &symbols[..count as usize] {
+            *lengths.get_mut(symbol).ok_or("Invalid lossless WebP code")? = 1;
+        }
+        return Huffman::new_lossless_webp(&lengths);
+    }
+    let mut code_lengths = [0u8; 19];
+    let count = reader.bits(4)? as usize + 4;
+    for &symbol in CODE_LENGTH_ORDER.iter().take(count) {
+        code_lengths[symbol] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new_lossless_webp(&code_lengths)?;
+    let mut max_symbols = if reader.bit()? {
+        let bits = 2 + 2 * reader.bits(3)?;
+        2 + reader.bits(bits)? as usize
+    } else {
+        alphabet
+    };
+    let (mut symbol, mut previous) = (0, 8);
+    while symbol < alphabet && max_symbols > 0 {
+        max_symbols -= 1;
+        let (length, repeat) = match code_length_code.decode(reader)? {
+            length @ 0..=15 => {
+                if length != 0 {
+                    previous = length as u8;
+                }
+                (length as u8, 1)
+            },
+            16 => (previous, 3 + reader.bits(2)? as usize),
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if symbol + repeat > alphabet {
+            return Err("Invalid lossless WebP code lengths".to_string());
+        }
+        lengths[symbol..symbol + repeat].fill(length);
+        symbol += repeat;
+    }
+    Huffman::new_lossless_webp(&lengths)
+}
+
+// The length or distance of a backward reference from its prefix symbol and extra bits, §5.2.2.
+fn prefix_value(reader: &mut BitReader, prefix: u32) -> Result<usize, String> {
+    if prefix < 4 {
+        return Ok(prefix as usize + 1);
+    }
+    let extra = (prefix - 2) >> 1;
+    let offset = (2 + (prefix & 1)) << extra;
+    Ok((offset + reader.bits(extra)?) as usize + 1)
+}
+
+// Decodes an entropy coded image, §5: literal pixels, backward references to earlier ones and
+// recently used colors. The main image may use different codes for different blocks of it.
+fn decode_entropy_image(reader: &mut BitReader, width: usize, height: usize, main: bool) -> Result<Vec<u32>, String> {
+    let cache_bits = if reader.bit()? { reader.bits(4)? } else { 0 };
+    if cache_bits > 11 {
+        return Err("Invalid lossless WebP color cache".to_string());
+    }
+    let cache_size = if cache_bits > 0 { 1 << cache_bits } else { 0 };
+    let mut cache = vec![0u32; cache_size];
+
+    let mut meta = None;
+    if main && reader.bit()? {
+        let bits = reader.bits(3)? + 2;
+        let codes = decode_entropy_image(reader, blocks(width, bits), blocks(height, bits), false)?;
+        meta = Some((bits, codes.iter().map(|code| (code >> 8) & 0xffff).collect::<Vec<_>>()));
+    }
+    let group_count = meta.as_ref().map_or(1, |(_, codes)| codes.iter().max().map_or(1, |max| *max as usize + 1));
+    let mut groups = vec![];
+    for _ in 0..group_count {
+        groups.push([
+            read_code(reader, 256 + 24 + cache_size)?,
+            read_code(reader, 256)?,
+            read_code(reader, 256)?,
+            read_code(reader, 256)?,
+            read_code(reader, 40)?,
+        ]);
+    }
+
+    let count = width * height;
+    let mut pixels: Vec<u32> = Vec::with_capacity(count);
+    let mut cached = 0;
+    while pixels.len() < count {
+        let i = pixels.len();
+        let group = &groups[meta.as_ref().map_or(0, |(bits, codes)| {
+            let (x, y) = (i % width, i / width);
+            codes[(y >> bits) * blocks(width, *bits) + (x >> bits)] as usize
+        })];
+        let symbol = group[0].decode(reader)? as u32;
+        if symbol < 256 {
+            let red = group[1].decode(reader)? as u32;
+            let blue = group[2].decode(reader)? as u32;
+            let alpha = group[3].decode(reader)? as u32;
+            pixels.push(alpha << 24 | red << 16 | symbol << 8 | blue);
+        } else if symbol < 256 + 24 {
+            let length = prefix_value(reader, symbol - 256)?;
+            let distance_prefix = group[4].decode(reader)? as u32;
+            let code = prefix_value(reader, distance_prefix)?;
+            let distance = if code > 120 {
+                code - 120
+            } else {
+                let mapped = DISTANCE_MAP[code - 1] as usize;
+                ((mapped >> 4) * width + 8).saturating_sub(mapped & 0xf).max(1)
+            };
+            if distance > i || i + length > count {
+                return Err("Invalid lossless WebP backward reference".to_string());
+            }
+            for j in i..i + length {
+                pixels.push(pixels[j - distance]);
+            }
+        } else {
+            let index = (symbol - 256 - 24) as usize;
+            //the cache holds every pixel so far, so it has to catch up first
+            for &pixel in &pixels[cached..] {
+                cache[(0x1e35a7bd_u32.wrapping_mul(pixel) >> (32 - cache_bits)) as usize] = pixel;
+            }
+            cached = pixels.len();
+            pixels.push(*cache.get(index).ok_or("Invalid lossless WebP color cache index")?);
+        }
+    }
+    Ok(pixels)
+}
@@ -0,0 +1,1016 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+mod flex;
+mod float;
+mod generated;
+mod grid;
+mod inline;
+mod overflow;
+mod position;
+mod replaced;
+mod table;
+mod vertical;
+
+use crate::css::PseudoElement;
+use crate::css::Unit;
+use crate::css::Value;
+use crate::dom::Node;
+use crate::dom::NodeType;
+use crate::font::FontDatabase;
+use crate::font::ShapedGlyph;
+use crate::image::Image;
+use crate::image::ImageStore;
+use crate::properties;
+use crate::style;
+use crate::style::Display;
+use crate::style::PropertyMap;
+use crate::style::StyledNode;
+use float::FloatContext;
+use generated::Counters;
+use position::ContainingBlocks;
+
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Rect {
+    pub fn expanded_by(self, edge: EdgeSizes) -> Rect {
+        Rect {
+            x: self.x - edge.left,
+            y: self.y - edge.top,
+            width: self.width + edge.left + edge.right,
+            height: self.height + edge.top + edge.bottom,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct EdgeSizes {
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub left: f32,
+}
+
+impl fmt::Display for EdgeSizes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {} {}", self.top, self.right, self.bottom, self.left)
+    }
+}
+
+// The CSS box model of a box. The content rect is in document coordinates, the edges are
+// measured out from it.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Dimensions {
+    pub content: Rect,
+    pub padding: EdgeSizes,
+    pub border: EdgeSizes,
+    pub margin: EdgeSizes,
+}
+
+impl Dimensions {
+    pub fn padding_box(&self) -> Rect {
+        self.content.expanded_by(self.padding)
+    }
+
+    pub fn border_box(&self) -> Rect {
+        self.padding_box().expanded_by(self.border)
+    }
+
+    pub fn margin_box(&self) -> Rect {
+        self.border_box().expanded_by(self.margin)
+    }
+}
+
+#[derive(Clone)]
+pub enum BoxType<'a> {
+    BlockNode(&'a StyledNode<'a>),
+    // an inline element or text node, and once laid out the part of one on a single line
+    InlineNode(&'a StyledNode<'a>),
+    // a block container that sits on a line like a single big character
+    InlineBlockNode(&'a StyledNode<'a>),
+    // wraps the inline boxes among the block children of a block box, CSS 2.1 §9.2.1.1
+    AnonymousBlock,
+    // one line of an inline formatting context, `baseline` is the y its root inline box's
+    // baseline is at, or the x in a vertical writing mode
+    LineBox { baseline: f32 },
+    // the part of a text node on one line, after white space processing, and its glyphs in
+    // visual order with x relative to the left of the content box, or its top in a vertical
+    // writing mode. `justification` is the extra space text-align: justify adds to each
+    // space, which the glyphs already include.
+    TextRun { node: &'a StyledNode<'a>, text: String, justification: f32, glyphs: Vec<ShapedGlyph> },
+    // the text the content of a pseudo-element makes, which is laid out like a text node
+    GeneratedText { node: &'a StyledNode<'a>, text: String },
+}
+
+#[derive(Clone)]
+pub struct LayoutBox<'a> {
+    pub dimensions: Dimensions,
+    pub box_type: BoxType<'a>,
+    // computed values, an anonymous box only has the ones it inherits
+    pub style: PropertyMap,
+    pub children: Vec<LayoutBox<'a>>,
+    // the image of a replaced element, which it shows instead of any children
+    pub image: Option<Arc<Image>>,
+    // how far the content of a scroll container is scrolled left and up, which painting moves
+    // it back by; the boxes inside are where they would be unscrolled
+    pub scroll_offset: (f32, f32),
+}
+
+impl fmt::Display for LayoutBox<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_indented(f, 0)
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    fn new(box_type: BoxType<'a>, style: PropertyMap) -> LayoutBox<'a> {
+        LayoutBox {dimensions: Default::default(), box_type, style, children: vec![], image: None, scroll_offset: (0.0, 0.0)}
+    }
+
+    fn write_indented(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        let label = |node: &StyledNode| match &node.node.node_type {
+            NodeType::Element(elem) => match node.pseudo_element {
+                Some(pseudo_element) => format!("<{}>{}", elem.tag_name, pseudo_element),
+                None => format!("<{}>", elem.tag_name),
+            },
+            NodeType::Text(_) => "#text".to_string(),
+            NodeType::Comment(_) => "#comment".to_string(),
+        };
+        let label = match &self.box_type {
+            BoxType::BlockNode(node) => format!("{} block", label(node)),
+            BoxType::InlineNode(node) => format!("{} inline", label(node)),
+            BoxType::InlineBlockNode(node) => format!("{} inline-block", label(node)),
+            BoxType::AnonymousBlock => "anonymous block".to_string(),
+            BoxType::LineBox {baseline} => format!("line baseline {}", baseline),
+            BoxType::TextRun {text, ..} | BoxType::GeneratedText {text, ..} => format!("{:?}", text),
+        };
+        let d = &self.dimensions;
+        write!(f, "{}{} ({}, {}) {}x{}", "  ".repeat(depth), label, d.content.x, d.content.y, d.content.width, d.content.height)?;
+        for (name, edges) in [("padding", d.padding), ("border", d.border), ("margin", d.margin)] {
+            if edges != EdgeSizes::default() {
+                write!(f, " {} {}", name, edges)?;
+            }
+        }
+        writeln!(f)?;
+        for child in &self.children {
+            child.write_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    fn is_block_level(&self) -> bool {
+        matches!(self.box_type, BoxType::BlockNode(_) | BoxType::AnonymousBlock)
+    }
+
+    // Whether the box is taken out of the flow it's in, by floating or being absolutely positioned.
+    fn is_out_of_flow(&self) -> bool {
+        self.is_float() || self.is_absolutely_positioned()
+    }
+
+    // Whether the box is the ::marker of a list item with list-style-position: outside, which
+    // goes beside the list item's first line rather than in it, CSS Lists 3 §3.2.
+    fn is_outside_marker(&self) -> bool {
+        matches!(self.box_type, BoxType::InlineBlockNode(node) if node.pseudo_element == Some(PseudoElement::Marker))
+    }
+
+    fn is_block_container(&self) -> bool {
+        matches!(self.box_type, BoxType::BlockNode(_) | BoxType::InlineBlockNode(_) | BoxType::AnonymousBlock)
+    }
+
+    // Whether the box lays out its children as flex items rather than in flow. A flex
+    // container is a block or inline-block box as far as its parent is concerned.
+    fn is_flex_container(&self) -> bool {
+        matches!(keyword(&self.style, "display"), "flex" | "inline-flex")
+    }
+
+    // Whether the box lays out its children as grid items, likewise.
+    fn is_grid_container(&self) -> bool {
+        matches!(keyword(&self.style, "display"), "grid" | "inline-grid")
+    }
+
+    // Whether the box is a table, which lays out its rows and cells in a table grid.
+    fn is_table(&self) -> bool {
+        matches!(keyword(&self.style, "display"), "table" | "inline-table")
+    }
+
+    // Moves the box and everything in it.
+    fn translate(&mut self, dx: f32, dy: f32) {
+        self.dimensions.content.x += dx;
+        self.dimensions.content.y += dy;
+        if let BoxType::LineBox {baseline} = &mut self.box_type {
+            *baseline += dy;
+        }
+        for child in &mut self.children {
+            child.translate(dx, dy);
+        }
+    }
+
+    // Lays out a block container and its descendants at `position` in the block formatting
+    // context of its parent, returning where the box after it goes. The containing block is
+    // the content box of the parent, whose height is only given if it doesn't depend on the
+    // box's own. `floats` are those of the block formatting context it is in, None if the box
+    // has to be the root of one of its own. A formatting root keeps its floats inside and its
+    // margins never collapse with its children's.
+    fn layout_block(&mut self, x: f32, position: FlowPosition, containing_width: f32, containing_height: Option<f32>, floats: Option<&mut FloatContext>, fonts: &FontDatabase) -> Placement {
+        let mut own_floats = FloatContext::default();
+        let (floats, formatting_root) = match floats {
+            Some(floats) if !self.establishes_formatting_context() => (floats, false),
+            _ => (&mut own_floats, true),
+        };
+        self.calculate_block_width(containing_width, containing_height, fonts);
+        self.calculate_vertical_edges(containing_width);
+        self.dimensions.content.x = x + self.dimensions.margin.left + self.dimensions.border.left + self.dimensions.padding.left;
+        let (height, min_height, max_height) = self.specified_heights(containing_height);
+        let clamp = |height: f32| max_height.map_or(height, |max| height.min(max)).max(min_height);
+        let height = match self.is_replaced() {
+            true => Some(self.replaced_size(Some(containing_width), containing_height).1),
+            false => height.map(clamp),
+        };
+
+        //the top margin collapses with the first child's, unless a border or padding is in
+        //between, in which case the top of the content box is known right away
+        let d = self.dimensions;
+        let margins = position.margins.adjoin(d.margin.top);
+        let collapse_top = !formatting_root && d.border.top == 0.0 && d.padding.top == 0.0;
+        let start = if collapse_top {
+            FlowPosition {y: position.y, margins}
+        } else {
+            FlowPosition {y: position.y + margins.collapse() + d.border.top + d.padding.top, margins: Default::default()}
+        };
+        self.dimensions.content.y = start.y;
+
+        let marker = match self.children.first() {
+            Some(first) if first.is_outside_marker() => Some(self.children.remove(0)),
+            _ => None,
+        };
+
+        //absolutely positioned children aren't flex or grid items
+        let mut positioned = vec![];
+        if self.is_flex_container() || self.is_grid_container() {
+            let children;
+            (positioned, children) = std::mem::take(&mut self.children).into_iter().partition(LayoutBox::is_absolutely_positioned);
+            self.children = children;
+        }
+        let (end, first_top) = if self.is_flex_container() {
+            self.layout_flex_items(start, height, clamp, fonts)
+        } else if self.is_grid_container() {
+            self.layout_grid_items(start, height, clamp, fonts)
+        } else if self.is_table() {
+            self.layout_table(start, height, fonts)
+        } else if self.children.iter().any(|child| !child.is_block_level()) {
+            self.layout_inline_children(start, floats, fonts)
+        } else {
+            self.layout_block_children(start, height, floats, fonts)
+        };
+        for mut child in positioned {
+            child.set_static_position(self.dimensions.content.x, start.y);
+            self.children.push(child);
+        }
+
+        //a table's height is only the least it gets, its rows can make it taller, CSS 2.1 §17.5.3
+        let height = height.filter(|_| !self.is_table());
+
+        //likewise the last child's bottom margin collapses with this one if nothing keeps
+        //them apart, including a height that doesn't come from the content
+        let collapse_bottom = !formatting_root && height.is_none() && min_height == 0.0 && d.border.bottom == 0.0 && d.padding.bottom == 0.0;
+        let top = match (collapse_top, first_top) {
+            (false, _) => start.y,
+            (true, Some(top)) => top,
+            //no border, padding, content or height, so the margins collapse right through
+            (true, None) if collapse_bottom => {
+                self.dimensions.content.y = position.y + end.margins.collapse();
+                self.dimensions.content.height = 0.0;
+                self.place_outside_marker(marker, fonts);
+                let next = FlowPosition {y: position.y, margins: end.margins.adjoin(d.margin.bottom)};
+                return Placement {next, collapsed_through: true};
+            },
+            //empty, but with a height, so the children's margins end up above it
+            (true, None) => position.y + end.margins.collapse(),
+        };
+        self.dimensions.content.y = top;
+        self.place_outside_marker(marker, fonts);
+
+        let content_bottom = match (collapse_bottom, first_top.is_some() || !collapse_top) {
+            (true, _) => end.y,
+            (false, true) => end.y + end.margins.collapse(),
+            (false, false) => top,
+        };
+        //a formatting root's auto height takes in its floats, CSS 2.1 §10.6.7
+        let content_bottom = match floats.bottom() {
+            Some(bottom) if formatting_root => content_bottom.max(bottom),
+            _ => content_bottom,
+        };
+        self.dimensions.content.height = height.unwrap_or_else(|| clamp(content_bottom - top));
+
+        let bottom = top + self.dimensions.content.height + d.padding.bottom + d.border.bottom;
+        let margins = if collapse_bottom { end.margins } else { Default::default() };
+        Placement {next: FlowPosition {y: bottom, margins: margins.adjoin(d.margin.bottom)}, collapsed_through: false}
+    }
+
+    // Puts the outside marker of a list item back among its children once the rest of it is
+    // laid out, with its margin box against the start of the list item's border box and its
+    // baseline on that of the first line, or at the top of the content box if there is none.
+    fn place_outside_marker(&mut self, marker: Option<LayoutBox<'a>>, fonts: &FontDatabase) {
+        let mut marker = match marker {
+            Some(marker) => marker,
+            None => return,
+        };
+        marker.layout_block(0.0, Default::default(), self.dimensions.content.width, None, None, fonts);
+        let margin_box = marker.dimensions.margin_box();
+        let border_box = self.dimensions.border_box();
+        let x = match keyword(&self.style, "direction") {
+            "rtl" => border_box.x + border_box.width,
+            _ => border_box.x - margin_box.width,
+        };
+        let y = match (self.first_baseline(), inline::atomic_baseline(&marker)) {
+            (Some(baseline), marker_baseline) => baseline - (marker_baseline - margin_box.y),
+            (None, _) => self.dimensions.content.y,
+        };
+        marker.translate(x - margin_box.x, y - margin_box.y);
+        self.children.insert(0, marker);
+    }
+
+    // CSS 2.1 §10.3.3, the width and horizontal margins of a block-level box in normal flow,
+    // kept between min-width and max-width as §10.4 says. A replaced box's auto width comes
+    // from its image, §10.3.4.
+    fn calculate_block_width(&mut self, containing_width: f32, containing_height: Option<f32>, fonts: &FontDatabase) {
+        let size = |name| to_px(self.style.get(name), containing_width);
+        let border_left = size("border-left-width").unwrap_or(0.0);
+        let border_right = size("border-right-width").unwrap_or(0.0);
+        let padding_left = size("padding-left").unwrap_or(0.0);
+        let padding_right = size("padding-right").unwrap_or(0.0);
+        let edges = border_left + padding_left + padding_right + border_right;
+        let content_size = |name| size(name).map(|size| if self.border_box_sizing() { (size - edges).max(0.0) } else { size });
+
+        let solve = |width: Option<f32>| match self.box_type {
+            //a table is as wide as its columns want to be if there's room, but never narrower
+            //than they need, CSS 2.1 §17.5.2
+            _ if self.is_table() => {
+                let (min_content, max_content) = self.intrinsic_widths(fonts);
+                //an anonymous table has no margins at all
+                let margin = |name| self.style.get(name).map_or(Some(0.0), |margin| to_px(Some(margin), containing_width));
+                let (margin_left, margin_right) = (margin("margin-left"), margin("margin-right"));
+                let room = containing_width - margin_left.unwrap_or(0.0) - edges - margin_right.unwrap_or(0.0);
+                let width = width.unwrap_or_else(|| max_content.min(room)).max(min_content);
+                match self.box_type {
+                    BoxType::InlineBlockNode(_) => (width, margin_left.unwrap_or(0.0), margin_right.unwrap_or(0.0)),
+                    _ if self.is_float() => (width, margin_left.unwrap_or(0.0), margin_right.unwrap_or(0.0)),
+                    _ => solve_block_width(Some(width), margin_left, margin_right, edges, containing_width),
+                }
+            },
+            //inline-blocks and floats shrink to fit their content, and auto margins are 0,
+            //§10.3.9 and §10.3.5
+            _ if self.is_float() || matches!(self.box_type, BoxType::InlineBlockNode(_)) => {
+                let (margin_left, margin_right) = (size("margin-left").unwrap_or(0.0), size("margin-right").unwrap_or(0.0));
+                let width = width.unwrap_or_else(|| {
+                    let (min_content, max_content) = self.intrinsic_widths(fonts);
+                    min_content.max(containing_width - margin_left - edges - margin_right).min(max_content)
+                });
+                (width, margin_left, margin_right)
+            },
+            _ => solve_block_width(width, size("margin-left"), size("margin-right"), edges, containing_width),
+        };
+        let width = match self.is_replaced() {
+            true => Some(self.replaced_size(Some(containing_width), containing_height).0),
+            false => content_size("width"),
+        };
+        let (mut width, mut margin_left, mut margin_right) = solve(width);
+        if let Some(max_width) = content_size("max-width").filter(|max_width| width > *max_width) {
+            (width, margin_left, margin_right) = solve(Some(max_width));
+        }
+        if let Some(min_width) = content_size("min-width").filter(|min_width| width < *min_width) {
+            (width, margin_left, margin_right) = solve(Some(min_width));
+        }
+
+        let d = &mut self.dimensions;
+        d.content.width = width;
+        d.padding.left = padding_left;
+        d.padding.right = padding_right;
+        d.border.left = border_left;
+        d.border.right = border_right;
+        d.margin.left = margin_left;
+        d.margin.right = margin_right;
+    }
+
+    // The vertical margins, borders and paddings, auto margins are 0 (§10.6.3).
+    fn calculate_vertical_edges(&mut self, containing_width: f32) {
+        //vertical margins and padding are percentages of the width too
+        let size = |name| to_px(self.style.get(name), containing_width).unwrap_or(0.0);
+        let (margin_top, margin_bottom) = (size("margin-top"), size("margin-bottom"));
+        let (border_top, border_bottom) = (size("border-top-width"), size("border-bottom-width"));
+        let (padding_top, padding_bottom) = (size("padding-top"), size("padding-bottom"));
+
+        let d = &mut self.dimensions;
+        d.margin.top = margin_top;
+        d.margin.bottom = margin_bottom;
+        d.border.top = border_top;
+        d.border.bottom = border_bottom;
+        d.padding.top = padding_top;
+        d.padding.bottom = padding_bottom;
+    }
+
+    // The content heights height, min-height and max-height ask for, CSS 2.1 §10.5 and §10.7.
+    // A percentage only counts when the containing block's height doesn't depend on this box,
+    // otherwise height and max-height are none and min-height is 0.
+    fn specified_heights(&self, containing_height: Option<f32>) -> (Option<f32>, f32, Option<f32>) {
+        let d = &self.dimensions;
+        let edges = d.border.top + d.padding.top + d.padding.bottom + d.border.bottom;
+        let size = |name| {
+            let size = match self.style.get(name) {
+                Some(Value::Percentage(percentage)) => containing_height.map(|height| percentage / 100.0 * height),
+                value => to_px(value, 0.0),
+            };
+            size.map(|size| if self.border_box_sizing() { (size - edges).max(0.0) } else { size })
+        };
+        (size("height"), size("min-height").unwrap_or(0.0), size("max-height"))
+    }
+
+    fn border_box_sizing(&self) -> bool {
+        matches!(self.style.get("box-sizing"), Some(Value::Keyword(keyword)) if keyword == "border-box")
+    }
+
+    // The min-content and max-content widths of the content box: as narrow as it gets by
+    // breaking every line it can, and as wide as it gets without breaking any.
+    fn intrinsic_widths(&self, fonts: &FontDatabase) -> (f32, f32) {
+        if self.is_replaced() {
+            let width = self.replaced_size(None, None).0;
+            return (width, width);
+        }
+        if self.is_flex_container() {
+            return self.flex_intrinsic_widths(fonts);
+        }
+        if self.is_grid_container() {
+            return self.grid_intrinsic_widths(fonts);
+        }
+        if self.is_table() {
+            return self.table_intrinsic_widths(fonts);
+        }
+        let children = match self.children.first() {
+            Some(first) if first.is_outside_marker() => &self.children[1..],
+            _ => &self.children[..],
+        };
+        if children.iter().any(|child| !child.is_block_level()) {
+            return inline::intrinsic_inline_widths(children, &self.style, fonts);
+        }
+        children.iter().filter(|child| !child.is_absolutely_positioned()).map(|child| child.intrinsic_contribution(fonts))
+            .fold((0.0, 0.0), |(min, max), (child_min, child_max)| (f32::max(min, child_min), f32::max(max, child_max)))
+    }
+
+    // How wide the margin box wants to be in a parent that is sized to its content. Percentages
+    // depend on that size, so they count as auto.
+    fn intrinsic_contribution(&self, fonts: &FontDatabase) -> (f32, f32) {
+        let size = |name: &str| match self.style.get(name) {
+            Some(Value::Percentage(_)) => None,
+            value => to_px(value, 0.0),
+        };
+        let edges = ["border-left-width", "padding-left", "padding-right", "border-right-width"].iter().filter_map(|name| size(name)).sum::<f32>();
+        let margins = size("margin-left").unwrap_or(0.0) + size("margin-right").unwrap_or(0.0);
+        let (min, max) = match size("width") {
+            Some(width) if self.border_box_sizing() => (width.max(edges), width.max(edges)),
+            Some(width) => (width + edges, width + edges),
+            None => {
+                let (min, max) = self.intrinsic_widths(fonts);
+                (min + edges, max + edges)
+            },
+        };
+        (min + margins, max + margins)
+    }
+
+    // Stacks the children top to bottom in the content box, starting at `start`, with floats
+    // taken out of the flow and put to the side. Returns where a box after the last child would
+    // go, and the top border edge of the first child that doesn't let the margins collapse
+    // through it.
+    fn layout_block_children(&mut self, start: FlowPosition, height: Option<f32>, floats: &mut FloatContext, fonts: &FontDatabase) -> (FlowPosition, Option<f32>) {
+        let content = self.dimensions.content;
+        let style = &self.style;
+        let layout_in_flow = |child: &mut LayoutBox, position: FlowPosition, floats: &mut FloatContext| {
+            if child.is_orthogonal_to(style) {
+                child.layout_orthogonal(content.x, position, content.width, height, fonts)
+            } else if child.establishes_formatting_context() {
+                child.layout_beside_floats(content, position, height, floats, fonts)
+            } else {
+                child.layout_block(content.x, position, content.width, height, Some(floats), fonts)
+            }
+        };
+        let mut position = start;
+        let mut first_top = None;
+        //floats whose top waits on the margins after them, which they go below too, and the
+        //floats there were before them
+        let mut pending = vec![];
+        let mut before_pending = None;
+        for i in 0..self.children.len() {
+            let child = &mut self.children[i];
+            if child.is_absolutely_positioned() {
+                child.set_static_position(content.x, position.y + position.margins.collapse());
+                continue;
+            }
+            if child.is_float() {
+                //a float goes no higher than where the next box in flow does
+                child.layout_block(0.0, Default::default(), content.width, height, None, fonts);
+                before_pending.get_or_insert_with(|| floats.clone());
+                let y = position.y + position.margins.collapse();
+                child.place_float(content, y, floats);
+                pending.push((i, y));
+                continue;
+            }
+            //a box that clears floats goes below them where they are
+            if !matches!(keyword(&child.style, "clear"), "none" | "") {
+                pending.clear();
+                before_pending = None;
+            }
+            if let Some(cleared) = child.clear_floats(position, content.width, floats) {
+                //the box's margins no longer collapse with the parent's either
+                first_top.get_or_insert(position.y + position.margins.collapse());
+                position = cleared;
+            }
+            let unplaced = (!pending.is_empty()).then(|| child.clone());
+            let mut placement = layout_in_flow(child, position, floats);
+            if placement.collapsed_through {
+                //floats inside an empty box are placed for good, along with those before them
+                if before_pending.as_ref().is_some_and(|before| floats.count() != before.count() + pending.len()) {
+                    pending.clear();
+                    before_pending = None;
+                }
+                position = placement.next;
+                continue;
+            }
+            //the margins end where the box's border box starts, unless it had to move down
+            //past floats, where only its own top margin adjoins them
+            let top = match child.establishes_formatting_context() {
+                true => position.y + position.margins.adjoin(child.dimensions.margin.top).collapse(),
+                false => child.dimensions.border_box().y,
+            };
+            if let (Some(unplaced), Some(before)) = (unplaced, before_pending.take()) {
+                if pending.iter().any(|&(_, y)| y < top) {
+                    *floats = before;
+                    for &(float, _) in &pending {
+                        self.children[float].place_float(content, top, floats);
+                    }
+                    self.children[i] = unplaced;
+                    placement = layout_in_flow(&mut self.children[i], position, floats);
+                }
+            }
+            pending.clear();
+            first_top.get_or_insert(self.children[i].dimensions.border_box().y);
+            position = placement.next;
+        }
+        (position, first_top)
+    }
+}
+
+// The width and horizontal margins of a block-level box that fill its containing block,
+// given the width (None for auto) and margins it asks for and its padding and borders.
+fn solve_block_width(mut width: Option<f32>, mut margin_left: Option<f32>, mut margin_right: Option<f32>, edges: f32, containing_width: f32) -> (f32, f32, f32) {
+    let total = margin_left.unwrap_or(0.0) + width.unwrap_or(0.0) + edges + margin_right.unwrap_or(0.0);
+
+    //a box wider than its containing block already has no room for auto margins
+    if width.is_some() && total > containing_width {
+        margin_left.get_or_insert(0.0);
+        margin_right.get_or_insert(0.0);
+    }
+
+    let underflow = containing_width - total;
+    match (width, margin_left, margin_right) {
+        //overconstrained, the right margin gives way
+        (Some(_), Some(_), Some(right)) => margin_right = Some(right + underflow),
+        (Some(_), None, Some(_)) => margin_left = Some(underflow),
+        (Some(_), Some(_), None) => margin_right = Some(underflow),
+        //auto on both sides centers the box
+        (Some(_), None, None) => {
+            margin_left = Some(underflow / 2.0);
+            margin_right = Some(underflow / 2.0);
+        },
+        //an auto width takes up whatever is left, auto margins become 0
+        (None, _, _) => {
+            margin_left.get_or_insert(0.0);
+            if underflow >= 0.0 {
+                width = Some(underflow);
+                margin_right.get_or_insert(0.0);
+            } else {
+                width = Some(0.0);
+                margin_right = Some(margin_right.unwrap_or(0.0) + underflow);
+            }
+        },
+    }
+    (width.unwrap_or(0.0), margin_left.unwrap_or(0.0), margin_right.unwrap_or(0.0))
+}
+
+// Margins that adjoin, CSS 2.1 §8.3.1, which collapse into the largest positive one plus the
+// most negative one.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+struct CollapsibleMargins {
+    positive: f32,
+    negative: f32,
+}
+
+impl CollapsibleMargins {
+    fn adjoin(self, margin: f32) -> CollapsibleMargins {
+        CollapsibleMargins {positive: self.positive.max(margin), negative: self.negative.min(margin)}
+    }
+
+    fn collapse(self) -> f32 {
+        self.positive + self.negative
+    }
+}
+
+// Where the next block-level box of a block formatting context goes: at `y`, the bottom border
+// edge of the box before it, plus whatever the margins adjoining there collapse to.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+struct FlowPosition {
+    y: f32,
+    margins: CollapsibleMargins,
+}
+
+struct Placement {
+    next: FlowPosition,
+    // the box has no height or content, so its top and bottom margins collapse together
+    collapsed_through: bool,
+}
+
+// The keyword a property computed to, or "" if it isn't one.
+fn keyword<'s>(style: &'s PropertyMap, name: &str) -> &'s str {
+    match style.get(name) {
+        Some(Value::Keyword(keyword)) => keyword,
+        _ => "",
+    }
+}
+
+// A computed length or percentage in px, None for auto and anything else that isn't one.
+fn to_px(value: Option<&Value>, reference: f32) -> Option<f32> {
+    match value? {
+        Value::Length(len, Unit::Px) => Some(*len),
+        Value::Percentage(percentage) => Some(percentage / 100.0 * reference),
+        _ => None,
+    }
+}
+
+// Lays out the style tree for a viewport of the given size, which is the containing block of
+// the root element, with text in `fonts` and the images of <img> elements in `images`. Returns
+// the root element's box; if it is display: none there is nothing to show and it's an empty
+// anonymous block instead.
+pub fn layout_tree<'a>(root: &'a StyledNode<'a>, viewport_width: f32, viewport_height: f32, fonts: &FontDatabase, images: &ImageStore) -> LayoutBox<'a> {
+    layout_tree_scrolled(root, viewport_width, viewport_height, &ScrollOffsets::new(), fonts, images)
+}
+
+// Where the host has scrolled the viewport and the scroll containers in the document to.
+#[derive(Clone, Default, Debug)]
+pub struct ScrollOffsets {
+    // the x and y of the document at the top left of the viewport
+    pub viewport: (f32, f32),
+    // how far the content of the scroll container of each element is scrolled left and up
+    elements: HashMap<*const Node, (f32, f32)>,
+}
+
+impl ScrollOffsets {
+    pub fn new() -> ScrollOffsets {
+        Default::default()
+    }
+
+    // Scrolls the scroll container of the element `node` so that its scrollport shows its
+    // content from `x` and `y` in from the top left. Layout keeps it within the content.
+    pub fn set(&mut self, node: &Node, x: f32, y: f32) {
+        self.elements.insert(node, (x, y));
+    }
+
+    pub fn get(&self, node: &Node) -> (f32, f32) {
+        self.elements.get(&(node as *const Node)).copied().unwrap_or((0.0, 0.0))
+    }
+}
+
+// Lays out the style tree like layout_tree, with the viewport and scroll containers scrolled
+// to where `scroll` has them. Fixed boxes stay in the viewport and sticky boxes stick to their
+// scrollport, everything else is where it would be unscrolled.
+pub fn layout_tree_scrolled<'a>(root: &'a StyledNode<'a>, viewport_width: f32, viewport_height: f32, scroll: &ScrollOffsets, fonts: &FontDatabase, images: &ImageStore) -> LayoutBox<'a> {
+    let style = style::computed_values(root, None, None);
+    let root_font_size = style.get("font-size").and_then(|value| to_px(Some(value), 0.0));
+    let mut root_box = build_layout_tree(root, style, root_font_size, true, images, &mut Counters::new())
+        .unwrap_or_else(|| LayoutBox::new(BoxType::AnonymousBlock, PropertyMap::new()));
+    if root_box.is_orthogonal_to(&PropertyMap::new()) {
+        root_box.layout_orthogonal(0.0, Default::default(), viewport_width, Some(viewport_height), fonts);
+    } else {
+        root_box.layout_block(0.0, Default::default(), viewport_width, Some(viewport_height), None, fonts);
+    }
+    let initial_containing_block = Rect {x: 0.0, y: 0.0, width: viewport_width, height: viewport_height};
+    let viewport = Rect {x: scroll.viewport.0, y: scroll.viewport.1, ..initial_containing_block};
+    root_box.layout_positioned(ContainingBlocks {
+        block: initial_containing_block,
+        absolute: initial_containing_block,
+        fixed: viewport,
+        scrollport: viewport,
+    }, scroll, fonts);
+    root_box
+}
+
+// The box `node` generates along with those of its descendants, with `style` its computed values.
+// The root element, floats, absolutely positioned boxes and flex and grid items are blockified,
+// so an element that would be inline-level or a part of a table is block-level instead, CSS
+// Display 3 §2.7. An <img> whose image is in `images` is a replaced element, which is an atomic
+// inline when it is inline-level. The boxes are built in document order, following the
+// `counters` in scope, and the ::marker, ::before and ::after of an element come before and
+// after its children, where their generated content goes.
+fn build_layout_tree<'a>(node: &'a StyledNode<'a>, mut style: PropertyMap, root_font_size: Option<f32>, blockify: bool, images: &ImageStore, counters: &mut Counters) -> Option<LayoutBox<'a>> {
+    //flex and grid items don't float, nor do absolutely positioned boxes, CSS 2.1 §9.7
+    let absolute = matches!(keyword(&style, "position"), "absolute" | "fixed");
+    if blockify || absolute {
+        style.insert("float".to_string(), Value::Keyword("none".to_string()));
+    }
+    let blockify = blockify || absolute || matches!(keyword(&style, "float"), "left" | "right");
+    let display = match &node.node.node_type {
+        //a marker is inline, or outside the list item's principal box like an inline-block
+        NodeType::Element(_) if node.pseudo_element == Some(PseudoElement::Marker) => match keyword(&style, "list-style-position") {
+            "inside" => Display::Inline,
+            _ => Display::InlineBlock,
+        },
+        NodeType::Element(_) if blockify => match node.display() {
+            Display::InlineFlex => Display::Flex,
+            Display::InlineGrid => Display::Grid,
+            Display::InlineTable => Display::Table,
+            Display::None => Display::None,
+            display @ (Display::Block | Display::FlowRoot | Display::ListItem | Display::Flex | Display::Grid | Display::Table) => display,
+            _ => {
+                style.insert("display".to_string(), Value::Keyword("block".to_string()));
+                Display::Block
+            },
+        },
+        NodeType::Element(_) => node.display(),
+        //whitespace between tags collapses away to nothing
+        NodeType::Text(text) if text.trim().is_empty() => return None,
+        NodeType::Text(_) => Display::Inline,
+        NodeType::Comment(_) => return None,
+    };
+    let image = match &node.node.node_type {
+        NodeType::Element(elem) if elem.tag_name == "img" && node.pseudo_element.is_none() => {
+            elem.attributes.get("src").and_then(|src| images.get(src)).cloned()
+        },
+        _ => None,
+    };
+    let box_type = match display {
+        Display::Inline if image.is_some() => BoxType::InlineBlockNode(node),
+        Display::Inline => BoxType::InlineNode(node),
+        Display::InlineBlock | Display::InlineFlex | Display::InlineGrid | Display::InlineTable => BoxType::InlineBlockNode(node),
+        Display::None => return None,
+        _ => BoxType::BlockNode(node),
+    };
+    let has_items = matches!(display, Display::Flex | Display::InlineFlex | Display::Grid | Display::InlineGrid);
+    if let NodeType::Element(_) = node.node.node_type {
+        counters.update(node, &style);
+    }
+
+    let children: Vec<LayoutBox> = match node.pseudo_element {
+        Some(_) => {
+            let text = counters.content(node, &style)?;
+            vec![LayoutBox::new(BoxType::GeneratedText {node, text}, inherited_values(&style))]
+        },
+        None => {
+            counters.enter();
+            let pseudo_element = |pseudo_element: PseudoElement| node.pseudo_elements.iter().find(|child| child.pseudo_element == Some(pseudo_element));
+            //only list items have markers
+            let marker = pseudo_element(PseudoElement::Marker).filter(|_| display == Display::ListItem);
+            let children = marker.into_iter()
+                .chain(pseudo_element(PseudoElement::Before))
+                .chain(node.children.iter().filter(|_| image.is_none()))
+                .chain(pseudo_element(PseudoElement::After))
+                .filter_map(|child| {
+                    let child_style = style::computed_values(child, Some(&style), root_font_size);
+                    build_layout_tree(child, child_style, root_font_size, has_items, images, counters)
+                })
+                .collect();
+            counters.leave();
+            children
+        },
+    };
+
+    let mut layout_box = LayoutBox::new(box_type, style);
+    layout_box.image = image;
+    //an outside marker stays out of the flow of the list item's content
+    let (marker, children): (Vec<LayoutBox>, Vec<LayoutBox>) = children.into_iter().partition(LayoutBox::is_outside_marker);
+    let children = table::fix_up_table_parts(&layout_box.style, children);
+    layout_box.adopt_children(children, has_items);
+    layout_box.children.splice(0..0, marker);
+    if layout_box.is_table() {
+        layout_box.prepare_table();
+    }
+    Some(layout_box)
+}
+
+impl<'a> LayoutBox<'a> {
+    // Makes `children` the children of the box. A block box holds either only block-level or
+    // only inline-level boxes, so runs of inline boxes next to blocks go into anonymous blocks.
+    // Blocks inside inline boxes aren't split out of them yet. With `wrap_all_inlines`, as
+    // in a flex or grid container, every run of text goes in one. Floats and absolutely
+    // positioned boxes go along with the inline boxes around them, so that they end up next
+    // to their lines.
+    fn adopt_children(&mut self, children: Vec<LayoutBox<'a>>, wrap_all_inlines: bool) {
+        let in_flow_block = |child: &LayoutBox| child.is_block_level() && !child.is_out_of_flow();
+        let wrap_inlines = children.iter().any(in_flow_block) || wrap_all_inlines;
+        if !self.is_block_container() || !wrap_inlines || children.iter().all(LayoutBox::is_block_level) {
+            self.children = children;
+            return;
+        }
+        for child in children {
+            if in_flow_block(&child) {
+                self.children.push(child);
+                continue;
+            }
+            if !self.children.last().is_some_and(|last| matches!(last.box_type, BoxType::AnonymousBlock) && !last.style.contains_key("display")) {
+                self.children.push(anonymous_box(&self.style, "block"));
+            }
+            self.children.last_mut().unwrap().children.push(child);
+        }
+    }
+}
+
+// An anonymous box in a box with `style`, whose inherited properties it takes on. A display
+// other than block makes it stand in for a part of a table that is missing, CSS 2.1 §17.2.1.
+fn anonymous_box<'a>(style: &PropertyMap, display: &str) -> LayoutBox<'a> {
+    let mut inherited = inherited_values(style);
+    if display != "block" {
+        inherited.insert("display".to_string(), Value::Keyword(display.to_string()));
+    }
+    LayoutBox::new(BoxType::AnonymousBlock, inherited)
+}
+
+// The values of the properties in `style` that are inherited.
+fn inherited_values(style: &PropertyMap) -> PropertyMap {
+    style.iter()
+        .filter(|(name, _)| properties::is_inherited(name))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::css_parser;
+    use crate::html_parser;
+    use crate::media::MediaEnvironment;
+
+    // Lays out `html` styled by `css` in an 800x600 viewport, and returns the dimensions of the
+    // elements with an id, by id, and of the line boxes, as "line", in tree order.
+    pub(super) fn layout(html: &str, css: &str) -> Vec<(String, Dimensions)> {
+        layout_scrolled(html, css, (0.0, 0.0))
+    }
+
+    // Like layout, with the viewport scrolled to `viewport`.
+    pub(super) fn layout_scrolled(html: &str, css: &str, viewport: (f32, f32)) -> Vec<(String, Dimensions)> {
+        let root = html_parser::parse(html.to_string());
+        let stylesheet = css_parser::parse(format!("html, body, div, p {{ display: block }} {}", css));
+        let cascade = style::Cascade::new(&stylesheet, MediaEnvironment::default());
+        let styled = style::style_tree(&root, &cascade);
+        let scroll = ScrollOffsets {viewport, ..ScrollOffsets::new()};
+        let root_box = layout_tree_scrolled(&styled, 800.0, 600.0, &scroll, &FontDatabase::new(), &ImageStore::new());
+
+        fn collect(layout_box: &LayoutBox, boxes: &mut Vec<(String, Dimensions)>) {
+            let label = match &layout_box.box_type {
+                BoxType::BlockNode(node) | BoxType::InlineNode(node) | BoxType::InlineBlockNode(node) => match &node.node.node_type {
+                    NodeType::Element(elem) if node.pseudo_element.is_none() => elem.id().cloned(),
+                    _ => None,
+                },
+                BoxType::LineBox {..} => Some("line".to_string()),
+                _ => None,
+            };
+            if let Some(label) = label {
+                boxes.push((label, layout_box.dimensions));
+            }
+            for child in &layout_box.children {
+                collect(child, boxes);
+            }
+        }
+        let mut boxes = vec![];
+        collect(&root_box, &mut boxes);
+        boxes
+    }
+
+    // The content box of the element with `id`.
+    pub(super) fn content(boxes: &[(String, Dimensions)], id: &str) -> Rect {
+        boxes.iter().find(|(label, _)| label == id).unwrap_or_else(|| panic!("no box for #{}", id)).1.content
+    }
+
+    // The content boxes of the line boxes.
+    pub(super) fn lines(boxes: &[(String, Dimensions)]) -> Vec<Rect> {
+        boxes.iter().filter(|(label, _)| label == "line").map(|(_, d)| d.content).collect()
+    }
+
+    #[test]
+    fn adjacent_sibling_margins_collapse() {
+        let boxes = layout("<html><body><div id=a></div><div id=b></div></body></html>",
+            "#a { height: 10px; margin-bottom: 20px } #b { height: 10px; margin-top: 30px }");
+        assert_eq!(content(&boxes, "b").y, 40.0);
+    }
+
+    #[test]
+    fn negative_margins_collapse() {
+        //the largest positive margin plus the most negative one
+        let boxes = layout("<html><body><div id=a></div><div id=b></div></body></html>",
+            "#a { height: 10px; margin-bottom: 20px } #b { height: 10px; margin-top: -5px }");
+        assert_eq!(content(&boxes, "b").y, 25.0);
+
+        let boxes = layout("<html><body><div id=a></div><div id=b></div></body></html>",
+            "#a { height: 10px; margin-bottom: -10px } #b { height: 10px; margin-top: -20px }");
+        assert_eq!(content(&boxes, "b").y, -10.0);
+    }
+
+    #[test]
+    fn parent_and_first_child_margins_collapse() {
+        let boxes = layout("<html><body><div id=parent><div id=child></div></div></body></html>",
+            "#parent { margin-top: 10px } #child { height: 10px; margin-top: 30px }");
+        assert_eq!(content(&boxes, "parent").y, 30.0);
+        assert_eq!(content(&boxes, "child").y, 30.0);
+
+        //padding keeps them apart
+        let boxes = layout("<html><body><div id=parent><div id=child></div></div></body></html>",
+            "#parent { margin-top: 10px; padding-top: 1px } #child { height: 10px; margin-top: 30px }");
+        assert_eq!(content(&boxes, "parent").y, 11.0);
+        assert_eq!(content(&boxes, "child").y, 41.0);
+    }
+
+    #[test]
+    fn parent_and_last_child_margins_collapse() {
+        let boxes = layout("<html><body><div id=parent><div id=child></div></div><div id=after></div></body></html>",
+            "#parent { margin-bottom: 10px } #child { height: 10px; margin-bottom: 30px } #after { height: 10px }");
+        assert_eq!(content(&boxes, "parent").height, 10.0);
+        assert_eq!(content(&boxes, "after").y, 40.0);
+
+        //a height keeps them apart
+        let boxes = layout("<html><body><div id=parent><div id=child></div></div><div id=after></div></body></html>",
+            "#parent { height: 20px; margin-bottom: 10px } #child { height: 10px; margin-bottom: 30px } #after { height: 10px }");
+        assert_eq!(content(&boxes, "after").y, 30.0);
+    }
+
+    #[test]
+    fn margins_collapse_through_an_empty_box() {
+        let boxes = layout("<html><body><div id=a></div><div id=empty></div><div id=b></div></body></html>",
+            "#a { height: 10px; margin-bottom: 10px } #empty { margin-top: 20px; margin-bottom: 15px } #b { height: 10px; margin-top: 5px }");
+        assert_eq!(content(&boxes, "empty").height, 0.0);
+        assert_eq!(content(&boxes, "b").y, 30.0);
+    }
+
+    #[test]
+    fn clearance_moves_a_box_below_floats() {
+        let boxes = layout("<html><body><div id=float></div><div id=cleared></div></body></html>",
+            "#float { float: left; width: 50px; height: 100px } #cleared { clear: left; height: 10px }");
+        assert_eq!(content(&boxes, "cleared").y, 100.0);
+
+        //the clearance is what the margin doesn't already do
+        let boxes = layout("<html><body><div id=box><div id=float></div><div id=cleared></div></div></body></html>",
+            "#box { padding-top: 10px } #float { float: left; width: 50px; height: 100px } #cleared { clear: left; margin-top: 30px; height: 10px }");
+        assert_eq!(content(&boxes, "cleared").y, 110.0);
+
+        //and none is needed if the margin already clears the float
+        let boxes = layout("<html><body><div id=box><div id=float></div><div id=cleared></div></div></body></html>",
+            "#box { padding-top: 10px } #float { float: left; width: 50px; height: 10px } #cleared { clear: left; margin-top: 30px; height: 10px }");
+        assert_eq!(content(&boxes, "cleared").y, 40.0);
+    }
+
+    #[test]
+    fn widths_stay_between_min_width_and_max_width() {
+        let boxes = layout("<html><body><div id=a></div><div id=b></div><div id=c></div></body></html>",
+            "#a { max-width: 300px } #b { width: 100px; min-width: 200px } #c { width: 500px; min-width: 400px; max-width: 300px }");
+        assert_eq!(content(&boxes, "a").width, 300.0);
+        assert_eq!(content(&boxes, "b").width, 200.0);
+        //min-width wins when they conflict
+        assert_eq!(content(&boxes, "c").width, 400.0);
+
+        //auto margins center the box max-width narrows
+        let boxes = layout("<html><body><div id=a></div></body></html>",
+            "#a { max-width: 300px; margin-left: auto; margin-right: auto }");
+        assert_eq!(content(&boxes, "a").x, 250.0);
+    }
+
+    #[test]
+    fn heights_stay_between_min_height_and_max_height() {
+        let boxes = layout("<html><body><div id=a></div><div id=b></div><div id=c></div></body></html>",
+            "#a { height: 500px; max-height: 100px } #b { min-height: 50px } #c { min-height: 80px; max-height: 40px }");
+        assert_eq!(content(&boxes, "a").height, 100.0);
+        assert_eq!(content(&boxes, "b").height, 50.0);
+        //min-height wins when they conflict
+        assert_eq!(content(&boxes, "c").height, 80.0);
+
+        //a percentage of a height that depends on the content is none
+        let boxes = layout("<html><body><div id=parent><div id=a></div></div></body></html>",
+            "#a { height: 100px; max-height: 50% }");
+        assert_eq!(content(&boxes, "a").height, 100.0);
+    }
+
+    #[test]
+    fn border_box_sizing_includes_padding_and_border() {
+        let css = "#a { width: 200px; height: 100px; padding: 10px; border-width: 5px; border-style: solid }";
+        let boxes = layout("<html><body><div id=a></div></body></html>", css);
+        assert_eq!((content(&boxes, "a").width, content(&boxes, "a").height), (200.0, 100.0));
+
+        let boxes = layout("<html><body><div id=a></div></body></html>", &format!("{} #a {{ box-sizing: border-box }}", css));
+        assert_eq!((content(&boxes, "a").width, content(&boxes, "a").height), (170.0, 70.0));
+
+        //and so do min and max sizes
+        let boxes = layout("<html><body><div id=a></div></body></html>",
+            &format!("{} #a {{ box-sizing: border-box; min-width: 300px; max-height: 50px }}", css));
+        assert_eq!((content(&boxes, "a").width, content(&boxes, "a").height), (270.0, 20.0));
+    }
+}
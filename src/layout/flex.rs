@@ -0,0 +1,610 @@
+// Flex layout, CSS Flexible Box Layout 1 §9: the children of a flex container are its flex
+// items, laid out along the main axis in flex lines, grown or shrunk to fill them and then
+// aligned along both axes. Each item is a block container that gets laid out with
+// layout_block once its sizes are known, as if its style asked for exactly those sizes.
+//
+// The flex-relative start of an axis is where the items or lines begin, which is on the
+// physical end side for the reverse directions, wrap-reverse and rtl rows. Items are placed
+// measuring from the start and mirrored onto the physical side afterwards.
+
+use std::ops::Range;
+
+use super::keyword;
+use super::to_px;
+use super::BoxType;
+use super::EdgeSizes;
+use super::FlowPosition;
+use super::LayoutBox;
+use crate::css::Unit;
+use crate::css::Value;
+use crate::font::FontDatabase;
+use crate::style::PropertyMap;
+
+const SIDES: [&str; 4] = ["top", "right", "bottom", "left"];
+
+// Where an item or line goes in the space left over along an axis, CSS Box Alignment 3.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(super) enum Alignment {
+    Start,
+    End,
+    Center,
+    SpaceBetween,
+    SpaceAround,
+    SpaceEvenly,
+    Stretch,
+    Baseline,
+}
+
+// The alignment a justify-content, align-content or align-self keyword asks for along an
+// axis. `reversed` says whether the writing mode's start is at the flex-relative end, and
+// `left_is_start` whether the physical left is at the flex-relative start.
+pub(super) fn alignment(keyword: &str, reversed: bool, left_is_start: bool) -> Alignment {
+    let start_if = |at_start: bool| if at_start { Alignment::Start } else { Alignment::End };
+    match keyword {
+        "flex-end" => Alignment::End,
+        "start" | "self-start" => start_if(!reversed),
+        "end" | "self-end" => start_if(reversed),
+        "left" => start_if(left_is_start),
+        "right" => start_if(!left_is_start),
+        "center" => Alignment::Center,
+        "space-between" => Alignment::SpaceBetween,
+        "space-around" => Alignment::SpaceAround,
+        "space-evenly" => Alignment::SpaceEvenly,
+        "stretch" => Alignment::Stretch,
+        "baseline" => Alignment::Baseline,
+        //normal and flex-start
+        _ => Alignment::Start,
+    }
+}
+
+// Where the first of `count` things goes in `free` space and how much extra goes between
+// them. The space distributing alignments fall back to the start or center when there is
+// nothing to distribute, so the things never end up before the start.
+pub(super) fn distribute(alignment: Alignment, free: f32, count: usize) -> (f32, f32) {
+    let count = count as f32;
+    match alignment {
+        Alignment::End => (free, 0.0),
+        Alignment::Center => (free / 2.0, 0.0),
+        Alignment::SpaceBetween if free > 0.0 && count > 1.0 => (0.0, free / (count - 1.0)),
+        Alignment::SpaceAround if free > 0.0 => (free / count / 2.0, free / count),
+        Alignment::SpaceEvenly if free > 0.0 => (free / (count + 1.0), free / (count + 1.0)),
+        Alignment::SpaceAround | Alignment::SpaceEvenly => (free / 2.0, 0.0),
+        _ => (0.0, 0.0),
+    }
+}
+
+pub(super) fn number(style: &PropertyMap, name: &str, default: f32) -> f32 {
+    match style.get(name) {
+        Some(Value::Length(number, Unit::None)) => *number,
+        _ => default,
+    }
+}
+
+// A row-gap or column-gap, where normal is none and a percentage of a size that isn't known
+// yet is too.
+pub(super) fn gap(style: &PropertyMap, name: &str, reference: Option<f32>) -> f32 {
+    match style.get(name) {
+        Some(Value::Percentage(percentage)) => reference.map_or(0.0, |reference| percentage / 100.0 * reference),
+        value => to_px(value, 0.0).unwrap_or(0.0),
+    }
+}
+
+// The sides at the start and end of the horizontal or vertical axis, as indices into SIDES.
+pub(super) fn axis_sides(horizontal: bool) -> [usize; 2] {
+    if horizontal { [3, 1] } else { [0, 2] }
+}
+
+pub(super) fn side(edges: &mut EdgeSizes, side: usize) -> &mut f32 {
+    match side {
+        0 => &mut edges.top,
+        1 => &mut edges.right,
+        2 => &mut edges.bottom,
+        _ => &mut edges.left,
+    }
+}
+
+pub(super) fn axis_sum(mut edges: EdgeSizes, horizontal: bool) -> f32 {
+    axis_sides(horizontal).iter().map(|&i| *side(&mut edges, i)).sum()
+}
+
+// A flex item while its container is being laid out. Sizes are of the content box along the
+// main axis unless they say otherwise.
+struct FlexItem {
+    grow: f32,
+    shrink: f32,
+    // §9.2.3
+    base_size: f32,
+    hypothetical_size: f32,
+    min_size: f32,
+    max_size: f32,
+    // the size once its line has been flexed, §9.7
+    target_size: f32,
+    frozen: bool,
+    // auto margins are 0 until the free space is handed out
+    margin: EdgeSizes,
+    auto_margins: [bool; 4],
+    // the borders and padding together
+    edges: EdgeSizes,
+    border_box: bool,
+    alignment: Alignment,
+    // whether its cross size is the line's, which goes for stretched items with an auto cross
+    // size and no auto margins across
+    stretch: bool,
+    // the width it's laid out at in a column container when it neither stretches nor has a
+    // width of its own
+    column_width: Option<Value>,
+    // how far its baseline is below the top of its margin box, for items aligned by baseline
+    baseline: Option<f32>,
+}
+
+impl FlexItem {
+    // The size of the margin box along an axis for a content box `size` across.
+    fn outer(&self, size: f32, horizontal: bool) -> f32 {
+        size + axis_sum(self.margin, horizontal) + axis_sum(self.edges, horizontal)
+    }
+
+    // A content box size in px as the item's width or height says it, going by its box-sizing.
+    fn size_value(&self, size: f32, horizontal: bool) -> Value {
+        let edges = if self.border_box { axis_sum(self.edges, horizontal) } else { 0.0 };
+        Value::Length(size + edges, Unit::Px)
+    }
+
+    fn auto_margin_count(&self, horizontal: bool) -> usize {
+        axis_sides(horizontal).iter().filter(|&&i| self.auto_margins[i]).count()
+    }
+
+    // Hands `free` space out evenly to the auto margins along an axis.
+    fn fill_auto_margins(&mut self, free: f32, horizontal: bool) {
+        let count = self.auto_margin_count(horizontal);
+        for i in axis_sides(horizontal) {
+            if self.auto_margins[i] {
+                *side(&mut self.margin, i) = free / count as f32;
+            }
+        }
+    }
+}
+
+// Grows or shrinks the items of a flex line to fill `space`, §9.7. Items that would go past
+// their min or max size are frozen at it and the free space is shared out again among the
+// others, until none are left to flex.
+fn resolve_flexible_lengths(items: &mut [FlexItem], space: f32, gap: f32, row: bool) {
+    let gaps = gap * items.len().saturating_sub(1) as f32;
+    let used: f32 = items.iter().map(|item| item.outer(item.hypothetical_size, row)).sum::<f32>() + gaps;
+    let growing = used < space;
+    for item in items.iter_mut() {
+        let factor = if growing { item.grow } else { item.shrink };
+        item.target_size = item.hypothetical_size;
+        item.frozen = factor == 0.0
+            || (growing && item.base_size > item.hypothetical_size)
+            || (!growing && item.base_size < item.hypothetical_size);
+    }
+
+    let free_space = |items: &[FlexItem]| {
+        let sizes = items.iter().map(|item| item.outer(if item.frozen { item.target_size } else { item.base_size }, row));
+        space - gaps - sizes.sum::<f32>()
+    };
+    let initial_free_space = free_space(items);
+    while items.iter().any(|item| !item.frozen) {
+        let unfrozen = || items.iter().filter(|item| !item.frozen);
+        let factors: f32 = unfrozen().map(|item| if growing { item.grow } else { item.shrink }).sum();
+        let mut remaining = free_space(items);
+        //factors that add up to less than 1 only take that fraction of the space
+        if factors < 1.0 && (initial_free_space * factors).abs() < remaining.abs() {
+            remaining = initial_free_space * factors;
+        }
+        //items shrink in proportion to their base size as well
+        let scaled_shrink: f32 = unfrozen().map(|item| item.shrink * item.base_size).sum();
+
+        let mut violations = vec![0.0; items.len()];
+        for (item, violation) in items.iter_mut().zip(&mut violations) {
+            if item.frozen {
+                continue;
+            }
+            let size = match growing {
+                true => item.base_size + remaining * item.grow / factors,
+                false if scaled_shrink > 0.0 => item.base_size + remaining * item.shrink * item.base_size / scaled_shrink,
+                false => item.base_size,
+            };
+            item.target_size = size.min(item.max_size).max(item.min_size).max(0.0);
+            *violation = item.target_size - size;
+        }
+
+        let total: f32 = violations.iter().sum();
+        for (item, violation) in items.iter_mut().zip(violations) {
+            item.frozen |= total == 0.0 || (total > 0.0 && violation > 0.0) || (total < 0.0 && violation < 0.0);
+        }
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    // Lays out the children of a flex container as flex items in its content box, which
+    // starts at `start` and is `height` high if that is known, with `clamp_height` keeping a
+    // height within its min-height and max-height. Returns where the content box ends.
+    pub(super) fn layout_flex_items(&mut self, start: FlowPosition, height: Option<f32>, clamp_height: impl Fn(f32) -> f32, fonts: &FontDatabase) -> (FlowPosition, Option<f32>) {
+        let content = self.dimensions.content;
+        let direction = keyword(&self.style, "flex-direction");
+        let row = !direction.starts_with("column");
+        let reverse = direction.ends_with("-reverse");
+        let wraps = keyword(&self.style, "flex-wrap").starts_with("wrap");
+        let wrap_reverse = keyword(&self.style, "flex-wrap") == "wrap-reverse";
+        let rtl = keyword(&self.style, "direction") == "rtl";
+        let main_mirrored = reverse != (row && rtl);
+        let cross_mirrored = wrap_reverse != (!row && rtl);
+
+        let (main_size, cross_size) = if row { (Some(content.width), height) } else { (height, Some(content.width)) };
+        let (column_gap, row_gap) = (gap(&self.style, "column-gap", Some(content.width)), gap(&self.style, "row-gap", height));
+        let (main_gap, cross_gap) = if row { (column_gap, row_gap) } else { (row_gap, column_gap) };
+        let justify = alignment(keyword(&self.style, "justify-content"), reverse, !row || !main_mirrored);
+        let align_content = match keyword(&self.style, "align-content") {
+            "normal" | "" => Alignment::Stretch,
+            value => alignment(value, wrap_reverse, row || !cross_mirrored),
+        };
+        let align_items = keyword(&self.style, "align-items").to_string();
+
+        //items go in order-modified document order, §5.4
+        self.children.sort_by_key(|child| number(&child.style, "order", 0.0) as i32);
+        //an item's line boxes replace its inline children, so every layout starts over from
+        //a copy of it as it was
+        let unlaid = self.children.clone();
+        let mut items: Vec<FlexItem> = self.children.iter_mut().map(|child| {
+            let align_self = match keyword(&child.style, "align-self") {
+                "auto" | "" => &*align_items,
+                value => value,
+            };
+            let alignment = match align_self {
+                "normal" | "" => Alignment::Stretch,
+                //there is no baseline to line up across a column
+                "baseline" if !row => Alignment::Start,
+                value => alignment(value, wrap_reverse, row || !cross_mirrored),
+            };
+            child.flex_item(row, alignment, content.width, height, main_size, fonts)
+        }).collect();
+
+        //break the items into lines where they no longer fit, §9.3
+        let line_limit = main_size.unwrap_or_else(|| clamp_height(f32::INFINITY));
+        let mut lines: Vec<Range<usize>> = vec![];
+        let mut used = 0.0;
+        for (i, item) in items.iter().enumerate() {
+            let size = item.outer(item.hypothetical_size, row);
+            match lines.last_mut() {
+                Some(line) if !wraps || used + main_gap + size <= line_limit => {
+                    line.end = i + 1;
+                    used += main_gap + size;
+                },
+                _ => {
+                    lines.push(i..i + 1);
+                    used = size;
+                },
+            }
+        }
+
+        //a column without a height is as tall as its longest line
+        let main = main_size.unwrap_or_else(|| {
+            let line_sizes = lines.iter().map(|line| {
+                let sizes = items[line.clone()].iter().map(|item| item.outer(item.hypothetical_size, row));
+                sizes.sum::<f32>() + main_gap * (line.len() - 1) as f32
+            });
+            clamp_height(line_sizes.fold(0.0, f32::max))
+        });
+        for line in &lines {
+            resolve_flexible_lengths(&mut items[line.clone()], main, main_gap, row);
+        }
+
+        //lay the items out at their main size to find their cross size, §9.4
+        for ((item, child), unlaid) in items.iter_mut().zip(&mut self.children).zip(&unlaid) {
+            let main_size = item.size_value(item.target_size, row);
+            let mut overrides = vec![(if row { "width" } else { "height" }, main_size)];
+            if !row {
+                overrides.extend(item.column_width.clone().map(|width| ("width", width)));
+            }
+            *child = unlaid.clone();
+            child.layout_overriding(&overrides, content.width, height, fonts);
+            if item.alignment == Alignment::Baseline && item.auto_margin_count(!row) == 0 {
+                let margin_box = child.dimensions.margin_box();
+                let baseline = child.first_baseline().unwrap_or(margin_box.y + margin_box.height - child.dimensions.margin.bottom);
+                item.baseline = Some(baseline - margin_box.y);
+            }
+        }
+        let outer_cross = |item: &FlexItem, child: &LayoutBox| {
+            let content = child.dimensions.content;
+            item.outer(if row { content.height } else { content.width }, !row)
+        };
+
+        //each line is as thick as its items, those aligned by baseline lined up first, or a
+        //single line as the container if it can be
+        let (mut line_sizes, line_baselines): (Vec<f32>, Vec<f32>) = lines.iter().map(|line| {
+            let (mut above, mut below, mut largest) = (0.0f32, 0.0f32, 0.0f32);
+            for (item, child) in items[line.clone()].iter().zip(&self.children[line.clone()]) {
+                let extent = outer_cross(item, child);
+                match item.baseline {
+                    Some(baseline) => {
+                        above = above.max(baseline);
+                        below = below.max(extent - baseline);
+                    },
+                    None => largest = largest.max(extent),
+                }
+            }
+            (largest.max(above + below), above)
+        }).unzip();
+        if !wraps && !line_sizes.is_empty() {
+            line_sizes[0] = cross_size.unwrap_or_else(|| clamp_height(line_sizes[0]));
+        }
+        let cross_gaps = cross_gap * lines.len().saturating_sub(1) as f32;
+        let cross = cross_size.unwrap_or_else(|| clamp_height(line_sizes.iter().sum::<f32>() + cross_gaps));
+
+        //lines share out what's left across the container with align-content, §9.4.15
+        let mut free = cross - line_sizes.iter().sum::<f32>() - cross_gaps;
+        if wraps && align_content == Alignment::Stretch && free > 0.0 {
+            for line_size in &mut line_sizes {
+                *line_size += free / lines.len() as f32;
+            }
+            free = 0.0;
+        }
+        let (mut line_offset, line_spacing) = if wraps { distribute(align_content, free, lines.len()) } else { (0.0, 0.0) };
+
+        for ((line, &line_size), &line_above) in lines.iter().zip(&line_sizes).zip(&line_baselines) {
+            //stretched items take the line's cross size, §9.4.11
+            for ((item, child), unlaid) in items[line.clone()].iter().zip(&mut self.children[line.clone()]).zip(&unlaid[line.clone()]) {
+                if !item.stretch {
+                    continue;
+                }
+                let cross_size = item.size_value((line_size - item.outer(0.0, !row)).max(0.0), !row);
+                let main_size = item.size_value(item.target_size, row);
+                let sizes = if row { [("width", main_size), ("height", cross_size)] } else { [("width", cross_size), ("height", main_size)] };
+                *child = unlaid.clone();
+                child.layout_overriding(&sizes, content.width, height, fonts);
+            }
+
+            //auto margins take up the free space along the main axis first, then
+            //justify-content spreads what's left, §9.5
+            let line_items = &mut items[line.clone()];
+            let used: f32 = line_items.iter().map(|item| item.outer(item.target_size, row)).sum::<f32>() + main_gap * (line.len() - 1) as f32;
+            let auto_margins: usize = line_items.iter().map(|item| item.auto_margin_count(row)).sum();
+            let (mut position, spacing) = match auto_margins {
+                0 => distribute(justify, main - used, line.len()),
+                _ => {
+                    for item in line_items.iter_mut() {
+                        item.fill_auto_margins((main - used).max(0.0) / auto_margins as f32 * item.auto_margin_count(row) as f32, row);
+                    }
+                    (0.0, 0.0)
+                },
+            };
+
+            for (item, child) in items[line.clone()].iter_mut().zip(&mut self.children[line.clone()]) {
+                //and then each item is aligned within the line, §9.6
+                let free = line_size - outer_cross(item, child);
+                let offset = match item.auto_margin_count(!row) {
+                    0 => match item.alignment {
+                        Alignment::End => free,
+                        Alignment::Center => free / 2.0,
+                        Alignment::Baseline => line_above - item.baseline.unwrap_or(line_above),
+                        _ => 0.0,
+                    },
+                    _ => {
+                        item.fill_auto_margins(free.max(0.0), !row);
+                        0.0
+                    },
+                };
+
+                let main_extent = item.outer(item.target_size, row);
+                let cross_extent = outer_cross(item, child);
+                let main_start = if main_mirrored { main - position - main_extent } else { position };
+                let cross_start = line_offset + offset;
+                let cross_start = if cross_mirrored { cross - cross_start - cross_extent } else { cross_start };
+                let (x, y) = if row { (main_start, cross_start) } else { (cross_start, main_start) };
+
+                child.dimensions.margin = item.margin;
+                let margin_box = child.dimensions.margin_box();
+                child.translate(content.x + x - margin_box.x, start.y + y - margin_box.y);
+                position += main_extent + main_gap + spacing;
+            }
+            line_offset += line_size + cross_gap + line_spacing;
+        }
+
+        let height = if row { cross } else { main };
+        (FlowPosition {y: start.y + height, margins: Default::default()}, None)
+    }
+
+    // Gathers what the flex layout needs to know about a child of a flex container before
+    // its lines are flexed, §9.2. In a column it gets laid out once at its cross size to find
+    // its content height.
+    fn flex_item(&mut self, row: bool, alignment: Alignment, containing_width: f32, containing_height: Option<f32>, main_size: Option<f32>, fonts: &FontDatabase) -> FlexItem {
+        let (margin, auto_margins, edges) = self.item_edges(containing_width);
+        let border_box = self.border_box_sizing();
+        let (main_name, cross_name) = if row { ("width", "height") } else { ("height", "width") };
+        let main_edges = axis_sum(edges, row);
+        //a size the main size properties ask for, if it doesn't depend on the content
+        let definite = |value: Option<&Value>| {
+            let size = match value {
+                Some(Value::Percentage(percentage)) => main_size.map(|main_size| percentage / 100.0 * main_size),
+                value => to_px(value, 0.0),
+            };
+            size.map(|size| if border_box { (size - main_edges).max(0.0) } else { size })
+        };
+        let stretch = alignment == Alignment::Stretch
+            && to_px(self.style.get(cross_name), 0.0).is_none()
+            && !axis_sides(!row).iter().any(|&i| auto_margins[i]);
+
+        //in a column the item is as wide as the container when it stretches and as its
+        //content otherwise, and its content height is what it is at that width
+        let mut item = FlexItem {
+            grow: number(&self.style, "flex-grow", 0.0),
+            shrink: number(&self.style, "flex-shrink", 1.0),
+            base_size: 0.0,
+            hypothetical_size: 0.0,
+            min_size: 0.0,
+            max_size: f32::INFINITY,
+            target_size: 0.0,
+            frozen: false,
+            margin,
+            auto_margins,
+            edges,
+            border_box,
+            alignment,
+            stretch,
+            column_width: None,
+            baseline: None,
+        };
+        let (min_content, max_content) = match row {
+            true => self.intrinsic_widths(fonts),
+            false => {
+                if !stretch && to_px(self.style.get("width"), 0.0).is_none() {
+                    let (min_content, max_content) = self.intrinsic_widths(fonts);
+                    let available = containing_width - axis_sum(margin, true) - axis_sum(edges, true);
+                    item.column_width = Some(item.size_value(max_content.min(available.max(min_content)), true));
+                }
+                let mut overrides = vec![("height", Value::Keyword("auto".to_string()))];
+                overrides.extend(item.column_width.clone().map(|width| ("width", width)));
+                self.layout_overriding(&overrides, containing_width, containing_height, fonts);
+                (self.dimensions.content.height, self.dimensions.content.height)
+            },
+        };
+
+        let main_size_property = definite(self.style.get(main_name));
+        let basis = match self.style.get("flex-basis") {
+            Some(Value::Keyword(keyword)) if keyword == "content" => None,
+            Some(Value::Keyword(_)) | None => main_size_property,
+            value => definite(value),
+        };
+        item.base_size = basis.unwrap_or(max_content);
+        item.max_size = definite(self.style.get(&format!("max-{}", main_name))).unwrap_or(f32::INFINITY);
+        //min-width: auto keeps an item from shrinking past its content, or past its size if
+        //that is smaller, §4.5
+        item.min_size = match self.style.get(&format!("min-{}", main_name)) {
+            Some(Value::Keyword(_)) | None => main_size_property.map_or(min_content, |size| size.min(min_content)).min(item.max_size),
+            value => definite(value).unwrap_or(0.0),
+        };
+        item.hypothetical_size = item.base_size.min(item.max_size).max(item.min_size);
+        item
+    }
+
+    // The margins of a flex or grid item, with auto ones as 0, which of them are auto in the
+    // order of SIDES, and its borders and padding together.
+    pub(super) fn item_edges(&self, containing_width: f32) -> (EdgeSizes, [bool; 4], EdgeSizes) {
+        let size = |name: String| to_px(self.style.get(&name), containing_width).unwrap_or(0.0);
+        let mut margin = EdgeSizes::default();
+        let mut edges = EdgeSizes::default();
+        let mut auto_margins = [false; 4];
+        for (i, name) in SIDES.iter().enumerate() {
+            *side(&mut margin, i) = size(format!("margin-{}", name));
+            *side(&mut edges, i) = size(format!("border-{}-width", name)) + size(format!("padding-{}", name));
+            auto_margins[i] = keyword(&self.style, &format!("margin-{}", name)) == "auto";
+        }
+        (margin, auto_margins, edges)
+    }
+
+    // Lays out a flex or grid item or a table cell at the origin like layout_block, as if its
+    // style had the given values instead of its own. Items and cells are the roots of their
+    // own formatting contexts.
+    pub(super) fn layout_overriding(&mut self, overrides: &[(&str, Value)], containing_width: f32, containing_height: Option<f32>, fonts: &FontDatabase) {
+        let saved: Vec<(&str, Option<Value>)> = overrides.iter()
+            .map(|(name, value)| (*name, self.style.insert(name.to_string(), value.clone())))
+            .collect();
+        self.layout_block(0.0, Default::default(), containing_width, containing_height, None, fonts);
+        for (name, value) in saved {
+            match value {
+                Some(value) => self.style.insert(name.to_string(), value),
+                None => self.style.remove(name),
+            };
+        }
+    }
+
+    // The baseline of the first line box in the box, if it has any.
+    pub(super) fn first_baseline(&self) -> Option<f32> {
+        self.children.iter().find_map(|child| match child.box_type {
+            BoxType::LineBox {baseline} => Some(baseline),
+            BoxType::BlockNode(_) | BoxType::AnonymousBlock => child.first_baseline(),
+            _ => None,
+        })
+    }
+
+    // The min-content and max-content widths of a flex container's content box, §9.9.1. Items
+    // in a row sit side by side unless they wrap, those in a column are stacked.
+    pub(super) fn flex_intrinsic_widths(&self, fonts: &FontDatabase) -> (f32, f32) {
+        let contributions: Vec<(f32, f32)> = self.children.iter()
+            .filter(|child| !child.is_absolutely_positioned())
+            .map(|child| child.intrinsic_contribution(fonts))
+            .collect();
+        let largest = contributions.iter().fold((0.0, 0.0), |(min, max), &(child_min, child_max)| (f32::max(min, child_min), f32::max(max, child_max)));
+        if keyword(&self.style, "flex-direction").starts_with("column") {
+            return largest;
+        }
+        let gaps = gap(&self.style, "column-gap", None) * contributions.len().saturating_sub(1) as f32;
+        let max = contributions.iter().map(|(_, max)| max).sum::<f32>() + gaps;
+        let min = match keyword(&self.style, "flex-wrap").starts_with("wrap") {
+            true => largest.0,
+            false => contributions.iter().map(|(min, _)| min).sum::<f32>() + gaps,
+        };
+        (min, max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::content;
+    use super::super::tests::layout;
+
+    const ITEMS: &str = "<html><body><div id=flex><div id=a></div><div id=b></div><div id=c></div></div></body></html>";
+
+    #[test]
+    fn items_wrap_onto_new_lines() {
+        let boxes = layout(ITEMS, "#flex { display: flex; flex-wrap: wrap; width: 300px }
+            #a, #b, #c { width: 120px; height: 10px } #b { height: 20px }");
+        assert_eq!((content(&boxes, "a").x, content(&boxes, "a").y), (0.0, 0.0));
+        assert_eq!((content(&boxes, "b").x, content(&boxes, "b").y), (120.0, 0.0));
+        //below the taller item of the first line
+        assert_eq!((content(&boxes, "c").x, content(&boxes, "c").y), (0.0, 20.0));
+        assert_eq!(content(&boxes, "flex").height, 30.0);
+    }
+
+    #[test]
+    fn justify_content_and_align_items() {
+        let boxes = layout(ITEMS, "#flex { display: flex; width: 300px; height: 100px; justify-content: space-between; align-items: center }
+            #a, #b, #c { width: 50px; height: 20px }");
+        let xs: Vec<f32> = ["a", "b", "c"].iter().map(|id| content(&boxes, id).x).collect();
+        assert_eq!(xs, [0.0, 125.0, 250.0]);
+        assert_eq!(content(&boxes, "a").y, 40.0);
+
+        let boxes = layout(ITEMS, "#flex { display: flex; width: 300px; height: 100px; justify-content: center; align-items: flex-end }
+            #a, #b, #c { width: 50px; height: 20px }");
+        let xs: Vec<f32> = ["a", "b", "c"].iter().map(|id| content(&boxes, id).x).collect();
+        assert_eq!(xs, [75.0, 125.0, 175.0]);
+        assert_eq!(content(&boxes, "a").y, 80.0);
+
+        //stretched to the height of the line
+        let boxes = layout(ITEMS, "#flex { display: flex; width: 300px; height: 100px } #a, #b, #c { width: 50px }");
+        assert_eq!(content(&boxes, "a").height, 100.0);
+    }
+
+    #[test]
+    fn items_dont_shrink_below_their_min_content_width() {
+        let boxes = layout("<html><body><div id=flex><div id=a><div id=wide></div></div><div id=b></div></div></body></html>",
+            "#flex { display: flex; width: 150px } #a, #b { flex-basis: 200px; height: 10px } #wide { width: 100px; height: 10px }");
+        //shrinking both by the same 125px would leave #a narrower than #wide
+        assert_eq!(content(&boxes, "a").width, 100.0);
+        assert_eq!(content(&boxes, "b").width, 50.0);
+        assert_eq!(content(&boxes, "b").x, 100.0);
+    }
+
+    #[test]
+    fn order_moves_items() {
+        let boxes = layout(ITEMS, "#flex { display: flex } #a, #b, #c { width: 50px; height: 10px } #a { order: 2 } #c { order: -1 }");
+        let xs: Vec<f32> = ["a", "b", "c"].iter().map(|id| content(&boxes, id).x).collect();
+        assert_eq!(xs, [100.0, 50.0, 0.0]);
+    }
+
+    #[test]
+    fn gaps_go_between_items_and_lines() {
+        let boxes = layout(ITEMS, "#flex { display: flex; flex-wrap: wrap; width: 130px; column-gap: 10px; row-gap: 5px }
+            #a, #b, #c { width: 60px; height: 10px }");
+        assert_eq!((content(&boxes, "a").x, content(&boxes, "a").y), (0.0, 0.0));
+        assert_eq!((content(&boxes, "b").x, content(&boxes, "b").y), (70.0, 0.0));
+        assert_eq!((content(&boxes, "c").x, content(&boxes, "c").y), (0.0, 15.0));
+
+        //and are left out of the space items grow into
+        let boxes = layout(ITEMS, "#flex { display: flex; width: 320px; gap: 10px } #a, #b, #c { flex-grow: 1; height: 10px }");
+        let xs: Vec<f32> = ["a", "b", "c"].iter().map(|id| content(&boxes, id).x).collect();
+        assert_eq!(xs, [0.0, 110.0, 220.0]);
+        assert_eq!(content(&boxes, "c").width, 100.0);
+    }
+}
@@ -0,0 +1,231 @@
+use super::keyword;
+use super::to_px;
+use super::FlowPosition;
+use super::LayoutBox;
+use super::Placement;
+use super::Rect;
+use crate::font::FontDatabase;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Side {
+    Left,
+    Right,
+}
+
+// The floats placed in a block formatting context so far, by their margin boxes in document
+// coordinates. Every box in the context flows around them, CSS 2.1 §9.5.
+#[derive(Clone, Default)]
+pub(super) struct FloatContext {
+    floats: Vec<(Side, Rect)>,
+}
+
+impl FloatContext {
+    fn overlapping(&self, y: f32, height: f32) -> impl Iterator<Item = &(Side, Rect)> {
+        //a band without height still runs into the floats beside it
+        self.floats.iter().filter(move |(_, rect)| rect.y + rect.height > y && (rect.y < y + height || rect.y <= y))
+    }
+
+    // The left and right of the room that the floats leave between `left` and `right`, from
+    // `y` down to `y + height`.
+    pub(super) fn band(&self, y: f32, height: f32, left: f32, right: f32) -> (f32, f32) {
+        self.overlapping(y, height).fold((left, right), |(left, right), (side, rect)| match side {
+            Side::Left => (left.max(rect.x + rect.width), right),
+            Side::Right => (left, right.min(rect.x)),
+        })
+    }
+
+    // The bottom of the highest float next to the band from `y` down to `y + height`, where
+    // the band gets wider. None if there are no floats next to it.
+    pub(super) fn next_bottom(&self, y: f32, height: f32) -> Option<f32> {
+        self.overlapping(y, height).map(|(_, rect)| rect.y + rect.height).reduce(f32::min)
+    }
+
+    // The bottom of the lowest float a box with `clear` has to go below, §9.5.2.
+    pub(super) fn clearance(&self, clear: &str) -> Option<f32> {
+        self.floats.iter()
+            .filter(|(side, _)| matches!((clear, side), ("left", Side::Left) | ("right", Side::Right) | ("both", _)))
+            .map(|(_, rect)| rect.y + rect.height)
+            .reduce(f32::max)
+    }
+
+    // How many floats have been placed.
+    pub(super) fn count(&self) -> usize {
+        self.floats.len()
+    }
+
+    // The bottom of the lowest float, which a formatting root's auto height takes in, §10.6.7.
+    pub(super) fn bottom(&self) -> Option<f32> {
+        self.clearance("both")
+    }
+
+    // Puts a float with a margin box of the given size as high as it goes but not above `y`,
+    // then as far to its side as it goes between `left` and `right`, following the rules of
+    // §9.5.1. Returns the top left corner of its margin box.
+    fn place(&mut self, side: Side, width: f32, height: f32, y: f32, left: f32, right: f32) -> (f32, f32) {
+        //no higher than a float before it
+        let mut y = self.floats.last().map_or(y, |(_, rect)| y.max(rect.y));
+        loop {
+            let (band_left, band_right) = self.band(y, height, left, right);
+            //a float too wide for the room left moves down past the floats in the way, unless
+            //there are none and it is simply too wide
+            match self.next_bottom(y, height) {
+                Some(bottom) if band_right - band_left < width => y = bottom,
+                _ => {
+                    let x = match side {
+                        Side::Left => band_left,
+                        Side::Right => band_right - width,
+                    };
+                    self.floats.push((side, Rect {x, y, width, height}));
+                    return (x, y);
+                },
+            }
+        }
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    // Whether the box floats, taking it out of flow to the left or right of its containing block.
+    pub(super) fn is_float(&self) -> bool {
+        matches!(keyword(&self.style, "float"), "left" | "right")
+    }
+
+    // Whether the box is the root of a formatting context of its own, whose floats stay inside
+    // it and whose margins don't collapse with its children's, CSS 2.1 §9.4.1.
+    pub(super) fn establishes_formatting_context(&self) -> bool {
+        //clip cuts off the content without making a scroll container, which keeps it in flow
+        let overflows = |name| !matches!(keyword(&self.style, name), "visible" | "clip" | "");
+        matches!(self.box_type, super::BoxType::InlineBlockNode(_))
+            || self.is_out_of_flow()
+            || self.is_flex_container()
+            || self.is_grid_container()
+            || self.is_table()
+            || keyword(&self.style, "display") == "flow-root"
+            || overflows("overflow-x")
+            || overflows("overflow-y")
+    }
+
+    // Moves a float laid out at the origin to its place in `containing_block`, the content box of
+    // the block container it is in, no higher than `y`.
+    pub(super) fn place_float(&mut self, containing_block: Rect, y: f32, floats: &mut FloatContext) {
+        let side = if keyword(&self.style, "float") == "right" { Side::Right } else { Side::Left };
+        let y = floats.clearance(keyword(&self.style, "clear")).map_or(y, |bottom| y.max(bottom));
+        let margin_box = self.dimensions.margin_box();
+        let (x, y) = floats.place(side, margin_box.width, margin_box.height, y, containing_block.x, containing_block.x + containing_block.width);
+        self.translate(x - margin_box.x, y - margin_box.y);
+    }
+
+    // Where a box with `clear` goes if that takes it below floats it would otherwise be next
+    // to. Clearance keeps its top margin from collapsing with the margins above it, §9.5.2.
+    pub(super) fn clear_floats(&self, position: FlowPosition, containing_width: f32, floats: &FloatContext) -> Option<FlowPosition> {
+        let below = floats.clearance(keyword(&self.style, "clear"))?;
+        let margin_top = to_px(self.style.get("margin-top"), containing_width).unwrap_or(0.0);
+        let top = position.y + position.margins.adjoin(margin_top).collapse();
+        (top < below).then(|| FlowPosition {y: below - margin_top, margins: Default::default()})
+    }
+
+    // Lays out a block-level formatting root next to the floats of its parent's formatting
+    // context. Its border box mustn't overlap them, so it gets the room they leave beside it
+    // and moves down past them until it fits, §9.5.
+    pub(super) fn layout_beside_floats(&mut self, containing_block: Rect, mut position: FlowPosition, containing_height: Option<f32>, floats: &FloatContext, fonts: &FontDatabase) -> Placement {
+        let (left, right) = (containing_block.x, containing_block.x + containing_block.width);
+        let margin_top = to_px(self.style.get("margin-top"), containing_block.width).unwrap_or(0.0);
+        loop {
+            let top = position.y + position.margins.adjoin(margin_top).collapse();
+            let (band_left, band_right) = floats.band(top, 0.0, left, right);
+            let placement = self.layout_block(band_left, position, band_right - band_left, containing_height, None, fonts);
+            let border_box = self.dimensions.border_box();
+            let (band_left, band_right) = floats.band(border_box.y, border_box.height, left, right);
+            let fits = border_box.x >= band_left - 0.01 && border_box.x + border_box.width <= band_right + 0.01;
+            match floats.next_bottom(border_box.y, border_box.height) {
+                Some(bottom) if !fits => position = FlowPosition {y: bottom - margin_top, margins: Default::default()},
+                _ => return placement,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::content;
+    use super::super::Dimensions;
+    use super::super::tests::layout;
+    use super::super::tests::lines;
+
+    const FLOATS: &str = "<html><body><div id=box><div id=a></div><div id=b></div><div id=c></div></div></body></html>";
+
+    // The top left corner of the content box of the element with `id`.
+    fn corner(boxes: &[(String, Dimensions)], id: &str) -> (f32, f32) {
+        let rect = content(boxes, id);
+        (rect.x, rect.y)
+    }
+
+    #[test]
+    fn floats_stack_to_the_left_and_right() {
+        let boxes = layout(FLOATS, "#box { width: 300px } #a, #b { float: left; width: 100px; height: 50px }
+            #c { float: right; width: 50px; height: 50px }");
+        assert_eq!(corner(&boxes, "a"), (0.0, 0.0));
+        assert_eq!(corner(&boxes, "b"), (100.0, 0.0));
+        assert_eq!(corner(&boxes, "c"), (250.0, 0.0));
+    }
+
+    #[test]
+    fn float_goes_below_earlier_floats_it_doesnt_fit_beside() {
+        let boxes = layout(FLOATS, "#box { width: 300px } #a { float: left; width: 200px; height: 50px }
+            #b { float: left; width: 150px; height: 30px } #c { float: right; width: 100px; height: 10px }");
+        assert_eq!(corner(&boxes, "a"), (0.0, 0.0));
+        assert_eq!(corner(&boxes, "b"), (0.0, 50.0));
+        //no higher than the float before it, even though there's room beside the first one
+        assert_eq!(corner(&boxes, "c"), (200.0, 50.0));
+    }
+
+    #[test]
+    fn clear_goes_below_floats_on_that_side() {
+        let css = "#a { float: left; width: 100px; height: 50px } #b { float: right; width: 100px; height: 80px } #c { height: 10px }";
+        let boxes = layout(FLOATS, &format!("{} #c {{ clear: left }}", css));
+        assert_eq!(content(&boxes, "c").y, 50.0);
+        let boxes = layout(FLOATS, &format!("{} #c {{ clear: right }}", css));
+        assert_eq!(content(&boxes, "c").y, 80.0);
+        let boxes = layout(FLOATS, &format!("{} #c {{ clear: both }}", css));
+        assert_eq!(content(&boxes, "c").y, 80.0);
+        let boxes = layout(FLOATS, css);
+        assert_eq!(content(&boxes, "c").y, 0.0);
+    }
+
+    #[test]
+    fn lines_wrap_around_floats() {
+        let text = "word ".repeat(300);
+        let boxes = layout(&format!("<html><body><div id=left></div><div id=right></div><p>{}</p></body></html>", text),
+            "#left { float: left; width: 100px; height: 40px } #right { float: right; width: 200px; height: 80px }");
+        let lines = lines(&boxes);
+        let beside_both = lines.iter().filter(|line| line.y < 40.0);
+        let beside_right = lines.iter().filter(|line| line.y >= 40.0 && line.y < 80.0);
+        let below = lines.iter().filter(|line| line.y >= 80.0);
+        assert!(beside_both.clone().count() > 0 && beside_right.clone().count() > 0 && below.clone().count() > 0);
+        for line in beside_both {
+            assert_eq!((line.x, line.width), (100.0, 500.0));
+        }
+        for line in beside_right {
+            assert_eq!((line.x, line.width), (0.0, 600.0));
+        }
+        for line in below {
+            assert_eq!((line.x, line.width), (0.0, 800.0));
+        }
+    }
+
+    #[test]
+    fn float_goes_below_the_margins_that_collapse_after_it() {
+        //the float's top is where the paragraph's margins end, next to its first line
+        let html = "<html><body><div id=a></div><div id=empty></div><div id=float></div><p id=p>text</p></body></html>";
+        let boxes = layout(html, "#a { height: 100px } #empty { margin-bottom: 10px }
+            #float { float: left; width: 50px; height: 50px } #p { margin-top: 16px }");
+        assert_eq!(corner(&boxes, "float"), (0.0, 116.0));
+        assert_eq!(lines(&boxes)[0].y, 116.0);
+        assert_eq!(lines(&boxes)[0].x, 50.0);
+
+        //or where the collapsed-through margin ends if that's larger
+        let boxes = layout(html, "#a { height: 100px } #empty { margin-bottom: 20px }
+            #float { float: left; width: 50px; height: 50px } #p { margin-top: 16px }");
+        assert_eq!(corner(&boxes, "float"), (0.0, 120.0));
+        assert_eq!(lines(&boxes)[0].y, 120.0);
+    }
+}
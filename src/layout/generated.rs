@@ -0,0 +1,275 @@
+// Counters and generated content, CSS Lists 3 §4 and CSS Generated Content 3: the counters
+// that elements create and change, followed in document order as the box tree is built, and
+// the text of the ::before, ::after and ::marker boxes they go into.
+
+use std::collections::HashMap;
+
+use super::keyword;
+use crate::css::PseudoElement;
+use crate::css::Unit;
+use crate::css::Value;
+use crate::dom::NodeType;
+use crate::style::Display;
+use crate::style::PropertyMap;
+use crate::style::StyledNode;
+
+#[derive(Clone, Copy)]
+struct Counter {
+    value: i32,
+    // whether list items count it down rather than up
+    reversed: bool,
+}
+
+// The counters in scope at a point in the tree. A counter is in scope for the element that
+// creates it, its descendants and its following siblings and their descendants, §4.5, so the
+// ones created among the children of an element go away at its end. A counter created inside
+// the scope of another of the same name hides it until then.
+pub(super) struct Counters {
+    counters: HashMap<String, Vec<Counter>>,
+    // the names of the counters created among the children of each element that is open,
+    // innermost last, starting with those created by the root element
+    levels: Vec<Vec<String>>,
+}
+
+impl Counters {
+    pub(super) fn new() -> Counters {
+        Counters {counters: HashMap::new(), levels: vec![vec![]]}
+    }
+
+    // Goes into the children of an element.
+    pub(super) fn enter(&mut self) {
+        self.levels.push(vec![]);
+    }
+
+    // Comes back out of them, taking the counters created there out of scope.
+    pub(super) fn leave(&mut self) {
+        for name in self.levels.pop().unwrap_or_default() {
+            if let Some(stack) = self.counters.get_mut(&name) {
+                stack.pop();
+            }
+        }
+    }
+
+    // Creates a counter, which replaces one of the same name that a sibling created.
+    fn reset(&mut self, name: &str, counter: Counter) {
+        let level = self.levels.last_mut().unwrap();
+        let stack = self.counters.entry(name.to_string()).or_default();
+        match level.iter().any(|created| created == name) {
+            true => *stack.last_mut().unwrap() = counter,
+            false => {
+                stack.push(counter);
+                level.push(name.to_string());
+            },
+        }
+    }
+
+    // The innermost counter of this name, which is created at 0 if there isn't one, §4.5.
+    fn counter_mut(&mut self, name: &str) -> &mut Counter {
+        if self.counters.get(name).is_none_or(Vec::is_empty) {
+            self.reset(name, Counter {value: 0, reversed: false});
+        }
+        self.counters.get_mut(name).unwrap().last_mut().unwrap()
+    }
+
+    fn value(&self, name: &str) -> i32 {
+        self.counters.get(name).and_then(|stack| stack.last()).map_or(0, |counter| counter.value)
+    }
+
+    // Applies the counter-reset, counter-increment and counter-set of `node`, in that order,
+    // with `style` its computed values, §4.3. A list item also increments list-item by one,
+    // or by minus one if the counter is reversed, unless counter-increment says how to.
+    pub(super) fn update(&mut self, node: &StyledNode, style: &PropertyMap) {
+        for (name, value, reversed) in counter_list(style.get("counter-reset")) {
+            let value = match value {
+                Some(value) => value,
+                None if reversed => reversed_start(node, &name),
+                None => 0,
+            };
+            self.reset(&name, Counter {value, reversed});
+        }
+        let increments = counter_list(style.get("counter-increment"));
+        let list_item = keyword(style, "display") == "list-item" && node.pseudo_element.is_none();
+        if list_item && !increments.iter().any(|(name, ..)| name == "list-item") {
+            let counter = self.counter_mut("list-item");
+            counter.value = counter.value.saturating_add(if counter.reversed { -1 } else { 1 });
+        }
+        for (name, value, _) in increments {
+            let counter = self.counter_mut(&name);
+            counter.value = counter.value.saturating_add(value.unwrap_or(1));
+        }
+        for (name, value, _) in counter_list(style.get("counter-set")) {
+            self.counter_mut(&name).value = value.unwrap_or(0);
+        }
+    }
+
+    // The text the pseudo-element `node` with computed values `style` generates, or None if it
+    // generates no box. A ::marker with content: normal shows its list item's list-style-type,
+    // CSS Lists 3 §3.1; for ::before and ::after normal is the same as none.
+    pub(super) fn content(&self, node: &StyledNode, style: &PropertyMap) -> Option<String> {
+        let content = match style.get("content") {
+            Some(Value::Keyword(keyword)) if keyword == "none" => return None,
+            Some(Value::Keyword(_)) | None if node.pseudo_element == Some(PseudoElement::Marker) => return self.marker_text(style),
+            Some(Value::Keyword(_)) | None => return None,
+            Some(content) => content,
+        };
+        let counter_style = |args: &[Value]| match args {
+            [Value::Keyword(style)] => style.to_ascii_lowercase(),
+            _ => "decimal".to_string(),
+        };
+        let text = content.components().iter().map(|component| match component {
+            Value::Str(text) => text.clone(),
+            Value::Function(name, args) => match (&**name, args.as_slice()) {
+                ("counter", [Value::Keyword(name), style @ ..]) => format_counter(self.value(name), &counter_style(style)),
+                ("counters", [Value::Keyword(name), Value::Str(separator), style @ ..]) => {
+                    let style = counter_style(style);
+                    match self.counters.get(name).filter(|stack| !stack.is_empty()) {
+                        Some(stack) => stack.iter().map(|counter| format_counter(counter.value, &style)).collect::<Vec<String>>().join(separator),
+                        None => format_counter(0, &style),
+                    }
+                },
+                ("attr", [Value::Keyword(name)]) => match &node.node.node_type {
+                    NodeType::Element(elem) => elem.attributes.get(name).cloned().unwrap_or_default(),
+                    _ => String::new(),
+                },
+                _ => String::new(),
+            },
+            _ => String::new(),
+        }).collect();
+        Some(text)
+    }
+
+    // The marker of a list item with this list-style-type: its list-item counter followed by
+    // a suffix, or the string it gives.
+    fn marker_text(&self, style: &PropertyMap) -> Option<String> {
+        match style.get("list-style-type") {
+            Some(Value::Str(text)) => Some(text.clone()),
+            Some(Value::Keyword(list_style_type)) if list_style_type != "none" => {
+                let suffix = match &**list_style_type {
+                    "disc" | "circle" | "square" => " ",
+                    _ => ". ",
+                };
+                Some(format_counter(self.value("list-item"), list_style_type) + suffix)
+            },
+            _ => None,
+        }
+    }
+}
+
+// The counters of a counter-reset, counter-increment or counter-set, with the integer after
+// each name if there is one and whether it is reversed().
+fn counter_list(value: Option<&Value>) -> Vec<(String, Option<i32>, bool)> {
+    let mut counters: Vec<(String, Option<i32>, bool)> = vec![];
+    for component in value.map_or(&[][..], Value::components) {
+        match component {
+            Value::Keyword(name) if name != "none" => counters.push((name.clone(), None, false)),
+            Value::Function(function, args) if function == "reversed" => {
+                if let [Value::Keyword(name)] = args.as_slice() {
+                    counters.push((name.clone(), None, true));
+                }
+            },
+            Value::Length(number, Unit::None) => {
+                if let Some(last) = counters.last_mut() {
+                    last.1 = Some(*number as i32);
+                }
+            },
+            _ => {},
+        }
+    }
+    counters
+}
+
+// The value a reversed counter starts at when `node` resets it without giving one, so that
+// counting down it ends at 1, §4.4.2: the sum of the increments it gets in its scope, up to
+// where it is set, with the first of them counted twice. Only `node` and its descendants are
+// looked at, and an element that resets the same counter begins a scope of its own.
+fn reversed_start(node: &StyledNode, name: &str) -> i32 {
+    let mut start = 0;
+    let mut first = true;
+    count_changes(node, name, true, &mut start, &mut first);
+    start
+}
+
+// Adds up the negated increments of the counter from `node` on in document order, returning
+// true once one sets it, which ends the count.
+fn count_changes(node: &StyledNode, name: &str, is_root: bool, start: &mut i32, first: &mut bool) -> bool {
+    if !matches!(node.node.node_type, NodeType::Element(_)) || node.display() == Display::None {
+        return false;
+    }
+    let style = &node.specified_values;
+    let is_named = |(counter, ..): &(String, Option<i32>, bool)| counter == name;
+    if !is_root && counter_list(style.get("counter-reset")).iter().any(is_named) {
+        return false;
+    }
+    let list_item = name == "list-item" && node.display() == Display::ListItem && node.pseudo_element.is_none();
+    let increment = match counter_list(style.get("counter-increment")).into_iter().find(is_named) {
+        Some((_, increment, _)) => Some(increment.unwrap_or(1)),
+        None if list_item => Some(-1),
+        None => None,
+    };
+    let set = counter_list(style.get("counter-set")).into_iter().find(is_named).map(|(_, value, _)| value.unwrap_or(0));
+    if increment.is_some() || set.is_some() {
+        let negated = -increment.unwrap_or(0);
+        if *first {
+            *start += negated;
+            *first = false;
+        }
+        if let Some(set) = set {
+            *start += set;
+            return true;
+        }
+        *start += negated;
+    }
+    node.pseudo_elements.iter().chain(&node.children).any(|child| count_changes(child, name, false, start, first))
+}
+
+const ROMAN_NUMERALS: &[(i32, &str)] = &[
+    (1000, "m"), (900, "cm"), (500, "d"), (400, "cd"), (100, "c"), (90, "xc"),
+    (50, "l"), (40, "xl"), (10, "x"), (9, "ix"), (5, "v"), (4, "iv"), (1, "i"),
+];
+
+const GREEK_LETTERS: &[char] = &[
+    'α', 'β', 'γ', 'δ', 'ε', 'ζ', 'η', 'θ', 'ι', 'κ', 'λ', 'μ',
+    'ν', 'ξ', 'ο', 'π', 'ρ', 'σ', 'τ', 'υ', 'φ', 'χ', 'ψ', 'ω',
+];
+
+// A counter value in one of the counter styles, CSS Counter Styles 3 §6. A value outside the
+// range of its style is shown in decimal instead, and the symbols of disc, circle and square
+// are the same whatever the value.
+fn format_counter(value: i32, style: &str) -> String {
+    let latin: Vec<char> = ('a'..='z').collect();
+    match style {
+        "none" => String::new(),
+        "disc" => "•".to_string(),
+        "circle" => "◦".to_string(),
+        "square" => "▪".to_string(),
+        "decimal-leading-zero" if value < 0 => format!("-{:02}", value.unsigned_abs()),
+        "decimal-leading-zero" => format!("{:02}", value),
+        "lower-roman" | "upper-roman" if (1..4000).contains(&value) => {
+            let mut rest = value;
+            let mut numeral = String::new();
+            for &(number, letters) in ROMAN_NUMERALS {
+                while rest >= number {
+                    numeral.push_str(letters);
+                    rest -= number;
+                }
+            }
+            if style == "upper-roman" { numeral.to_ascii_uppercase() } else { numeral }
+        },
+        "lower-alpha" | "lower-latin" if value > 0 => alphabetic(value, &latin),
+        "upper-alpha" | "upper-latin" if value > 0 => alphabetic(value, &latin).to_ascii_uppercase(),
+        "lower-greek" if value > 0 => alphabetic(value, GREEK_LETTERS),
+        _ => value.to_string(),
+    }
+}
+
+// A positive value in bijective base-n with these letters for digits, so a, b, ..., z, aa.
+fn alphabetic(mut value: i32, letters: &[char]) -> String {
+    let base = letters.len() as i32;
+    let mut digits = vec![];
+    while value > 0 {
+        value -= 1;
+        digits.push(letters[(value % base) as usize]);
+        value /= base;
+    }
+    digits.iter().rev().collect()
+}
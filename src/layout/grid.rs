@@ -0,0 +1,831 @@
+// Grid layout, CSS Grid Layout 1: the children of a grid container are placed in the areas
+// of a grid of rows and columns. The explicit grid comes from the container's templates, §7,
+// and items placed outside of it add implicit tracks, §8. The tracks are then sized to the
+// items in them, columns first, §12, and each item is laid out in its area with layout_block
+// and aligned in it.
+//
+// Items aligned by baseline are aligned to the start instead, and there are no subgrids.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use super::flex::alignment;
+use super::flex::axis_sum;
+use super::flex::distribute;
+use super::flex::gap;
+use super::flex::number;
+use super::flex::Alignment;
+use super::keyword;
+use super::to_px;
+use super::FlowPosition;
+use super::LayoutBox;
+use crate::css::Unit;
+use crate::css::Value;
+use crate::font::FontDatabase;
+
+// Indices of the two axes in the arrays below.
+const ROWS: usize = 0;
+const COLUMNS: usize = 1;
+
+// How big a track gets at least or at most, §7.2.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Sizing {
+    Fixed(f32),
+    // a share of the free space, only as a maximum
+    Flex(f32),
+    MinContent,
+    MaxContent,
+    Auto,
+    // fit-content(limit), only as a maximum
+    FitContent(f32),
+}
+
+impl Sizing {
+    fn is_intrinsic(self) -> bool {
+        matches!(self, Sizing::MinContent | Sizing::MaxContent | Sizing::Auto | Sizing::FitContent(_))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct TrackSize {
+    min: Sizing,
+    max: Sizing,
+}
+
+// A single track sizing function, where a percentage of a size that isn't known yet counts
+// as auto.
+fn sizing(value: &Value, space: Option<f32>) -> Sizing {
+    match value {
+        Value::Length(flex, Unit::Fr) => Sizing::Flex(*flex),
+        Value::Keyword(keyword) if keyword.eq_ignore_ascii_case("min-content") => Sizing::MinContent,
+        Value::Keyword(keyword) if keyword.eq_ignore_ascii_case("max-content") => Sizing::MaxContent,
+        Value::Percentage(percentage) => space.map_or(Sizing::Auto, |space| Sizing::Fixed(percentage / 100.0 * space)),
+        value => to_px(Some(value), 0.0).map_or(Sizing::Auto, Sizing::Fixed),
+    }
+}
+
+fn track_size(value: &Value, space: Option<f32>) -> TrackSize {
+    match value {
+        Value::Function(name, args) if name == "minmax" && args.len() == 2 => {
+            TrackSize {min: sizing(&args[0], space), max: sizing(&args[1], space)}
+        },
+        Value::Function(name, args) if name == "fit-content" && args.len() == 1 => {
+            let limit = to_px(Some(&args[0]), space.unwrap_or(0.0)).unwrap_or(0.0);
+            TrackSize {min: Sizing::Auto, max: Sizing::FitContent(limit)}
+        },
+        //a flexible track has an auto minimum
+        value => match sizing(value, space) {
+            Sizing::Flex(flex) => TrackSize {min: Sizing::Auto, max: Sizing::Flex(flex)},
+            sizing => TrackSize {min: sizing, max: sizing},
+        },
+    }
+}
+
+// The explicit tracks along one axis and the names of the lines around them, of which there
+// is one more than there are tracks.
+#[derive(Default)]
+struct TrackList {
+    sizes: Vec<TrackSize>,
+    names: Vec<Vec<String>>,
+    // the tracks repeat(auto-fit, ...) made, which collapse if no item is in them
+    auto_fit: Range<usize>,
+}
+
+impl TrackList {
+    fn push(&mut self, component: &Value, space: Option<f32>) {
+        match component {
+            Value::LineNames(names) => self.names.last_mut().unwrap().extend(names.iter().cloned()),
+            component => {
+                self.sizes.push(track_size(component, space));
+                self.names.push(vec![]);
+            },
+        }
+    }
+}
+
+// The tracks of a grid-template-rows or -columns value with repeat() expanded, §7.2.
+// auto-fill and auto-fit repeat as often as fits in `space`, the size of the content box
+// along the axis, going by the fixed sizes of the tracks, or once if that isn't known.
+fn track_list(value: Option<&Value>, space: Option<f32>, gap: f32) -> TrackList {
+    let mut list = TrackList {names: vec![vec![]], ..Default::default()};
+    let components = match value {
+        Some(Value::Keyword(keyword)) if keyword.eq_ignore_ascii_case("none") => return list,
+        Some(value) => value.components(),
+        None => return list,
+    };
+    let fixed_size = |component: &Value| match track_size(component, space) {
+        TrackSize {max: Sizing::Fixed(size), ..} | TrackSize {min: Sizing::Fixed(size), ..} => size,
+        _ => 0.0,
+    };
+    let is_track = |component: &&Value| !matches!(component, Value::LineNames(_));
+    let is_repeat = |component: &Value| matches!(component, Value::Function(name, _) if name == "repeat");
+
+    //the room the other tracks and the gaps after them leave for the repetitions
+    let others: Vec<&Value> = components.iter().filter(|component| !is_repeat(component)).filter(is_track).collect();
+    let room = space.map(|space| space + gap - others.iter().map(|component| fixed_size(component) + gap).sum::<f32>());
+
+    for component in components {
+        match component {
+            Value::Function(name, args) if name == "repeat" && !args.is_empty() => {
+                let count = match &args[0] {
+                    Value::Length(count, Unit::None) => *count as usize,
+                    _ => {
+                        let size: f32 = args[1..].iter().filter(is_track).map(|track| fixed_size(track) + gap).sum();
+                        match room {
+                            Some(room) if size > 0.0 => ((room / size).floor() as usize).max(1),
+                            _ => 1,
+                        }
+                    },
+                };
+                let first = list.sizes.len();
+                for _ in 0..count {
+                    for arg in &args[1..] {
+                        list.push(arg, space);
+                    }
+                }
+                if matches!(&args[0], Value::Keyword(keyword) if keyword.eq_ignore_ascii_case("auto-fit")) {
+                    list.auto_fit = first..list.sizes.len();
+                }
+            },
+            component => list.push(component, space),
+        }
+    }
+    list
+}
+
+// The named areas of grid-template-areas as the rows and columns they cover, and how many
+// rows and columns the template has, §7.3. A run of periods is a cell without a name.
+fn template_areas(value: Option<&Value>) -> (HashMap<String, [Range<usize>; 2]>, [usize; 2]) {
+    let mut areas: HashMap<String, [Range<usize>; 2]> = HashMap::new();
+    let mut size = [0, 0];
+    let rows = match value {
+        Some(value @ Value::Str(_)) | Some(value @ Value::List(_)) => value.components(),
+        _ => &[],
+    };
+    for (row, string) in rows.iter().enumerate() {
+        let cells = match string {
+            Value::Str(cells) => cells,
+            _ => continue,
+        };
+        size[ROWS] = row + 1;
+        for (column, name) in cells.split_whitespace().enumerate() {
+            size[COLUMNS] = size[COLUMNS].max(column + 1);
+            if name.chars().all(|c| c == '.') {
+                continue;
+            }
+            let area = areas.entry(name.to_string()).or_insert([row..row + 1, column..column + 1]);
+            for (axis, line) in [(ROWS, row), (COLUMNS, column)] {
+                area[axis] = area[axis].start.min(line)..area[axis].end.max(line + 1);
+            }
+        }
+    }
+    (areas, size)
+}
+
+// Where an item starts or ends along an axis, from a property like grid-row-start, §8.3.
+#[derive(Clone, PartialEq, Debug)]
+enum GridLine {
+    Auto,
+    // the nth line, or the nth with the name, counting back from the end if negative
+    Line(i32, Option<String>),
+    // as many tracks as it takes to get past n lines, or n lines with the name
+    Span(i32, Option<String>),
+}
+
+fn grid_line(value: Option<&Value>) -> GridLine {
+    let mut number = None;
+    let mut name = None;
+    let mut span = false;
+    for component in value.map_or(&[][..], Value::components) {
+        match component {
+            Value::Length(n, Unit::None) => number = Some(*n as i32),
+            Value::Keyword(keyword) if keyword.eq_ignore_ascii_case("span") => span = true,
+            Value::Keyword(keyword) if keyword.eq_ignore_ascii_case("auto") => return GridLine::Auto,
+            Value::Keyword(keyword) => name = Some(keyword.clone()),
+            _ => return GridLine::Auto,
+        }
+    }
+    match (span, number, name) {
+        (true, number, name) => GridLine::Span(number.unwrap_or(1), name),
+        (false, None, None) => GridLine::Auto,
+        (false, number, name) => GridLine::Line(number.unwrap_or(1), name),
+    }
+}
+
+// The lines of the explicit grid along an axis with the name, in order.
+fn named_lines(names: &[Vec<String>], name: &str) -> Vec<i32> {
+    names.iter().enumerate().filter(|(_, line)| line.iter().any(|line| line == name)).map(|(i, _)| i as i32).collect()
+}
+
+// The line `count` lines with the name on from `from`, going back if `count` is negative.
+// The implicit lines past the ends of the explicit grid all count as having every name.
+fn nth_named_line(names: &[Vec<String>], name: &str, from: i32, count: i32) -> i32 {
+    let lines = named_lines(names, name);
+    let last = names.len() as i32 - 1;
+    if count > 0 {
+        let after: Vec<&i32> = lines.iter().filter(|&&line| line > from).collect();
+        after.get(count as usize - 1).map_or(last.max(from) + count - after.len() as i32, |&&line| line)
+    } else {
+        let before: Vec<&i32> = lines.iter().rev().filter(|&&line| line < from).collect();
+        before.get((-count) as usize - 1).map_or(0.min(from) + count + before.len() as i32, |&&line| line)
+    }
+}
+
+// The line a grid-*-start (`side` "start") or grid-*-end placement is on, if it says.
+fn definite_line(line: &GridLine, names: &[Vec<String>], side: &str) -> Option<i32> {
+    match line {
+        GridLine::Line(number, None) if *number > 0 => Some(number - 1),
+        GridLine::Line(number, None) => Some(names.len() as i32 + number),
+        //the name of an area stands for the line at its start or end
+        GridLine::Line(1, Some(name)) if !named_lines(names, &format!("{}-{}", name, side)).is_empty() => {
+            named_lines(names, &format!("{}-{}", name, side)).first().copied()
+        },
+        GridLine::Line(number, Some(name)) if *number > 0 => Some(nth_named_line(names, name, -1, *number)),
+        GridLine::Line(number, Some(name)) => Some(nth_named_line(names, name, names.len() as i32, *number)),
+        _ => None,
+    }
+}
+
+// Where an item starts along an axis, or None if the auto-placement algorithm has to find
+// that, and how many tracks it spans, §8.3.1.
+fn resolve_lines(start: &GridLine, end: &GridLine, names: &[Vec<String>]) -> (Option<i32>, i32) {
+    //the line a span from `from` reaches, going back if it sets the start
+    let reach = |from: i32, span: &GridLine, forward: bool| {
+        let direction = if forward { 1 } else { -1 };
+        match span {
+            GridLine::Span(count, Some(name)) => nth_named_line(names, name, from, count * direction),
+            GridLine::Span(count, None) => from + count * direction,
+            _ => from + direction,
+        }
+    };
+    match (definite_line(start, names, "start"), definite_line(end, names, "end")) {
+        (Some(start), Some(end)) if start == end => (Some(start), 1),
+        (Some(start), Some(end)) => (Some(start.min(end)), (end - start).abs()),
+        (Some(start), None) => (Some(start), reach(start, end, true) - start),
+        (None, Some(end)) => {
+            let start = reach(end, start, false);
+            (Some(start), end - start)
+        },
+        (None, None) => match (start, end) {
+            (GridLine::Span(count, None), _) | (_, GridLine::Span(count, None)) => (None, *count),
+            _ => (None, 1),
+        },
+    }
+}
+
+fn overlaps(a: &[Range<i32>; 2], b: &[Range<i32>; 2]) -> bool {
+    (0..2).all(|axis| a[axis].start < b[axis].end && b[axis].start < a[axis].end)
+}
+
+// The auto-placement algorithm, §8.5: finds the lines between which each item goes from the
+// start line along each axis, if it has a definite one, and the number of tracks it spans.
+// Items flow along the rows, or the columns with `column_flow`, each going after the one
+// before unless `dense` packs them into the first hole they fit in.
+fn auto_place(positions: &[[(Option<i32>, i32); 2]], column_flow: bool, dense: bool, explicit: [i32; 2]) -> Vec<[Range<i32>; 2]> {
+    let (major, minor) = if column_flow { (COLUMNS, ROWS) } else { (ROWS, COLUMNS) };
+    let area = |starts: [i32; 2], position: &[(Option<i32>, i32); 2]| [starts[0]..starts[0] + position[0].1, starts[1]..starts[1] + position[1].1];
+    let at = |major_line: i32, minor_line: i32| {
+        let mut starts = [0; 2];
+        starts[major] = major_line;
+        starts[minor] = minor_line;
+        starts
+    };
+    let mut areas: Vec<Option<[Range<i32>; 2]>> = vec![None; positions.len()];
+    let mut placed: Vec<[Range<i32>; 2]> = vec![];
+    let fits = |placed: &[[Range<i32>; 2]], candidate: &[Range<i32>; 2]| !placed.iter().any(|area| overlaps(area, candidate));
+
+    //first the items with a definite position, then those locked to a row (or column)
+    for (i, position) in positions.iter().enumerate() {
+        if let [(Some(row), _), (Some(column), _)] = position {
+            areas[i] = Some(area([*row, *column], position));
+            placed.push(areas[i].clone().unwrap());
+        }
+    }
+    let mut row_cursors: HashMap<i32, i32> = HashMap::new();
+    for (i, position) in positions.iter().enumerate() {
+        if let (Some(major_line), None) = (position[major].0, position[minor].0) {
+            let mut minor_line = if dense { 0 } else { row_cursors.get(&major_line).copied().unwrap_or(0) };
+            while !fits(&placed, &area(at(major_line, minor_line), position)) {
+                minor_line += 1;
+            }
+            row_cursors.insert(major_line, minor_line + position[minor].1);
+            areas[i] = Some(area(at(major_line, minor_line), position));
+            placed.push(areas[i].clone().unwrap());
+        }
+    }
+
+    //the rest go where the cursor finds room for them, in as many tracks across as the
+    //explicit grid and the items need
+    let minor_start = placed.iter().map(|area| area[minor].start).fold(0, i32::min);
+    let minor_end = positions.iter().map(|position| minor_start + position[minor].1)
+        .chain(placed.iter().map(|area| area[minor].end))
+        .fold(explicit[minor], i32::max);
+    let major_start = placed.iter().map(|area| area[major].start).fold(0, i32::min);
+    let mut cursor = (major_start, minor_start);
+    for (i, position) in positions.iter().enumerate() {
+        if areas[i].is_some() {
+            continue;
+        }
+        if dense {
+            cursor = (major_start, minor_start);
+        }
+        match position[minor].0 {
+            Some(minor_line) => {
+                if minor_line < cursor.1 {
+                    cursor.0 += 1;
+                }
+                cursor.1 = minor_line;
+                while !fits(&placed, &area(at(cursor.0, cursor.1), position)) {
+                    cursor.0 += 1;
+                }
+            },
+            None => loop {
+                if cursor.1 + position[minor].1 > minor_end {
+                    cursor = (cursor.0 + 1, minor_start);
+                }
+                if fits(&placed, &area(at(cursor.0, cursor.1), position)) {
+                    break;
+                }
+                cursor.1 += 1;
+            },
+        }
+        areas[i] = Some(area(at(cursor.0, cursor.1), position));
+        placed.push(areas[i].clone().unwrap());
+        cursor.1 += position[minor].1;
+    }
+    areas.into_iter().map(Option::unwrap).collect()
+}
+
+// A grid container's grid with its items placed in it.
+struct Grid {
+    // the tracks along each axis, from the first implicit one on
+    tracks: [Vec<TrackSize>; 2],
+    // the auto-fit tracks no item is in, which collapse to nothing along with a gap
+    collapsed: [Vec<bool>; 2],
+    // the tracks each child spans along each axis
+    areas: Vec<[Range<usize>; 2]>,
+}
+
+// How much room there is for the tracks along an axis, §12.1: a size, or none at all when
+// the container is being sized to its content.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Space {
+    Definite(f32),
+    MinContent,
+    MaxContent,
+}
+
+// How big an item wants the tracks it spans to be along an axis, with its margins.
+struct Contribution {
+    tracks: Range<usize>,
+    min_content: f32,
+    max_content: f32,
+}
+
+// The gap after each track, none after the last one or one that collapsed.
+fn gaps_after(collapsed: &[bool], gap: f32) -> Vec<f32> {
+    (0..collapsed.len()).map(|i| if i + 1 < collapsed.len() && !collapsed[i] { gap } else { 0.0 }).collect()
+}
+
+// The size of one fr that makes the tracks in `tracks` fill `space`, §12.7.1. A flexible
+// track whose base size is more than its share is treated as inflexible.
+fn find_fr_size(sizes: &[TrackSize], base: &[f32], tracks: Range<usize>, space: f32) -> f32 {
+    let flex = |i: usize| match sizes[i].max {
+        Sizing::Flex(flex) => Some(flex),
+        _ => None,
+    };
+    let mut inflexible: Vec<bool> = tracks.clone().map(|i| flex(i).is_none()).collect();
+    loop {
+        let leftover = space - tracks.clone().zip(&inflexible).filter(|(_, &inflexible)| inflexible).map(|(i, _)| base[i]).sum::<f32>();
+        let flex_factors: f32 = tracks.clone().zip(&inflexible).filter(|(_, &inflexible)| !inflexible).filter_map(|(i, _)| flex(i)).sum();
+        let fr = leftover / flex_factors.max(1.0);
+        let mut changed = false;
+        for (i, inflexible) in tracks.clone().zip(&mut inflexible) {
+            if !*inflexible && fr * flex(i).unwrap() < base[i] {
+                *inflexible = true;
+                changed = true;
+            }
+        }
+        if !changed {
+            return fr.max(0.0);
+        }
+    }
+}
+
+// The track sizing algorithm, §12.3 to §12.8, which returns the size of each track along
+// an axis. `stretch` says whether tracks with an auto maximum share out the space left.
+fn size_tracks(sizes: &[TrackSize], collapsed: &[bool], gap: f32, space: Space, contributions: &[Contribution], stretch: bool) -> Vec<f32> {
+    let sizes: Vec<TrackSize> = sizes.iter().zip(collapsed).map(|(&size, &collapsed)| match collapsed {
+        true => TrackSize {min: Sizing::Fixed(0.0), max: Sizing::Fixed(0.0)},
+        false => size,
+    }).collect();
+    let gaps = gaps_after(collapsed, gap);
+    let span_gaps = |tracks: &Range<usize>| gaps[tracks.start..tracks.end - 1].iter().sum::<f32>();
+    let is_flexible = |i: usize| matches!(sizes[i].max, Sizing::Flex(_));
+
+    //§12.4, fixed sizes are where the tracks start out, and an infinite growth limit stands
+    //for one that isn't known yet
+    let mut base: Vec<f32> = sizes.iter().map(|size| match size.min {
+        Sizing::Fixed(size) => size,
+        _ => 0.0,
+    }).collect();
+    let mut limit: Vec<f32> = sizes.iter().zip(&base).map(|(size, &base)| match size.max {
+        Sizing::Fixed(size) => size.max(base),
+        _ => f32::INFINITY,
+    }).collect();
+
+    //§12.5, the intrinsic tracks grow to fit the items in them, those spanning one track
+    //first and the ones spanning flexible tracks last
+    let mut spanning: Vec<&Contribution> = contributions.iter().filter(|item| !item.tracks.clone().any(is_flexible)).collect();
+    spanning.sort_by_key(|item| item.tracks.len());
+    for item in spanning {
+        let tracks = item.tracks.clone();
+        let minimum = |track: usize| match sizes[track].min {
+            Sizing::MinContent => item.min_content,
+            Sizing::MaxContent => item.max_content,
+            Sizing::Auto if space == Space::MaxContent => item.max_content,
+            _ => item.min_content,
+        };
+        if tracks.len() == 1 {
+            let track = tracks.start;
+            if sizes[track].min.is_intrinsic() {
+                base[track] = base[track].max(minimum(track));
+            }
+            let maximum = match sizes[track].max {
+                Sizing::MinContent => item.min_content,
+                Sizing::MaxContent | Sizing::Auto => item.max_content,
+                Sizing::FitContent(fit) => item.max_content.min(fit),
+                _ => continue,
+            };
+            limit[track] = if limit[track].is_infinite() { maximum } else { limit[track].max(maximum) };
+            continue;
+        }
+
+        //an item spanning several tracks shares what it needs out evenly among them
+        let intrinsic_min: Vec<usize> = tracks.clone().filter(|&track| sizes[track].min.is_intrinsic()).collect();
+        let extra = minimum(tracks.start) - tracks.clone().map(|track| base[track]).sum::<f32>() - span_gaps(&tracks);
+        if extra > 0.0 && !intrinsic_min.is_empty() {
+            for &track in &intrinsic_min {
+                base[track] += extra / intrinsic_min.len() as f32;
+            }
+        }
+        let intrinsic_max: Vec<usize> = tracks.clone().filter(|&track| sizes[track].max.is_intrinsic()).collect();
+        let current: f32 = tracks.clone().map(|track| if limit[track].is_infinite() { base[track] } else { limit[track] }).sum();
+        let extra = item.max_content - current - span_gaps(&tracks);
+        for &track in &intrinsic_max {
+            let limit = &mut limit[track];
+            *limit = if limit.is_infinite() { base[track] } else { *limit } + extra.max(0.0) / intrinsic_max.len() as f32;
+        }
+    }
+    for item in contributions.iter().filter(|item| item.tracks.clone().any(is_flexible)) {
+        let flexible: Vec<usize> = item.tracks.clone().filter(|&track| is_flexible(track) && sizes[track].min.is_intrinsic()).collect();
+        let extra = item.min_content - item.tracks.clone().map(|track| base[track]).sum::<f32>() - span_gaps(&item.tracks);
+        if extra > 0.0 && !flexible.is_empty() {
+            for &track in &flexible {
+                base[track] += extra / flexible.len() as f32;
+            }
+        }
+    }
+    for (limit, &base) in limit.iter_mut().zip(&base) {
+        *limit = if limit.is_infinite() { base } else { limit.max(base) };
+    }
+
+    //§12.6, the free space goes to the tracks evenly until they reach their growth limits
+    let free_space = |base: &[f32], space: f32| space - base.iter().sum::<f32>() - gaps.iter().sum::<f32>();
+    match space {
+        Space::Definite(space) => loop {
+            let growing: Vec<usize> = (0..base.len()).filter(|&track| base[track] < limit[track]).collect();
+            let free = free_space(&base, space);
+            if growing.is_empty() || free <= 0.0 {
+                break;
+            }
+            let share = free / growing.len() as f32;
+            for track in growing {
+                base[track] = (base[track] + share).min(limit[track]);
+            }
+        },
+        Space::MaxContent => base.clone_from(&limit),
+        Space::MinContent => {},
+    }
+
+    //§12.7, then flexible tracks get their share of what's left, or without a size to
+    //fill, as much as the items in them need
+    if (0..sizes.len()).any(is_flexible) {
+        let fr = match space {
+            Space::Definite(space) => find_fr_size(&sizes, &base, 0..sizes.len(), space - gaps.iter().sum::<f32>()),
+            Space::MinContent => 0.0,
+            Space::MaxContent => {
+                let tracks = (0..sizes.len()).filter_map(|track| match sizes[track].max {
+                    Sizing::Flex(flex) if flex > 1.0 => Some(base[track] / flex),
+                    Sizing::Flex(_) => Some(base[track]),
+                    _ => None,
+                });
+                let items = contributions.iter().filter(|item| item.tracks.clone().any(is_flexible)).map(|item| {
+                    find_fr_size(&sizes, &base, item.tracks.clone(), item.max_content - span_gaps(&item.tracks))
+                });
+                tracks.chain(items).fold(0.0, f32::max)
+            },
+        };
+        for (track, size) in sizes.iter().enumerate() {
+            if let Sizing::Flex(flex) = size.max {
+                base[track] = base[track].max(fr * flex);
+            }
+        }
+    }
+
+    //§12.8, and tracks with an auto maximum stretch to fill the container
+    if let (Space::Definite(space), true) = (space, stretch) {
+        let auto: Vec<usize> = (0..sizes.len()).filter(|&track| sizes[track].max == Sizing::Auto).collect();
+        let free = free_space(&base, space);
+        if free > 0.0 && !auto.is_empty() {
+            for &track in &auto {
+                base[track] += free / auto.len() as f32;
+            }
+        }
+    }
+    base
+}
+
+// Where each track starts, going by the sizes of the tracks and gaps before it, with
+// `offset` before the first track and `spacing` added to each gap.
+fn track_positions(sizes: &[f32], gaps: &[f32], offset: f32, spacing: f32) -> Vec<f32> {
+    let mut position = offset;
+    sizes.iter().zip(gaps).map(|(size, gap)| {
+        let start = position;
+        position += size + gap + if *gap > 0.0 || spacing > 0.0 { spacing } else { 0.0 };
+        start
+    }).collect()
+}
+
+// The start and size of the tracks in `tracks`, given where each one starts and how big it is.
+fn extent(tracks: &Range<usize>, positions: &[f32], sizes: &[f32]) -> (f32, f32) {
+    let start = positions.get(tracks.start).copied().unwrap_or(0.0);
+    let end = positions.get(tracks.end - 1).map_or(start, |position| position + sizes[tracks.end - 1]);
+    (start, end - start)
+}
+
+impl<'a> LayoutBox<'a> {
+    // Sets up the grid of a grid container and places its children in it. `space` is the size
+    // of its content box along each axis, where it's known, and `gaps` the gaps between tracks.
+    fn grid(&self, space: [Option<f32>; 2], gaps: [f32; 2]) -> Grid {
+        let (named_areas, area_size) = template_areas(self.style.get("grid-template-areas"));
+        let mut templates = [("grid-template-rows", ROWS), ("grid-template-columns", COLUMNS)]
+            .map(|(name, axis)| track_list(self.style.get(name), space[axis], gaps[axis]));
+        for (axis, template) in templates.iter_mut().enumerate() {
+            //the areas add lines named after them, and tracks the template doesn't have
+            while template.names.len() <= area_size[axis] {
+                template.names.push(vec![]);
+            }
+            for (name, area) in &named_areas {
+                template.names[area[axis].start].push(format!("{}-start", name));
+                template.names[area[axis].end].push(format!("{}-end", name));
+            }
+        }
+
+        let sides = [("grid-row-start", "grid-row-end"), ("grid-column-start", "grid-column-end")];
+        let positions: Vec<[(Option<i32>, i32); 2]> = self.children.iter().map(|child| {
+            [ROWS, COLUMNS].map(|axis| {
+                let (start, end) = sides[axis];
+                resolve_lines(&grid_line(child.style.get(start)), &grid_line(child.style.get(end)), &templates[axis].names)
+            })
+        }).collect();
+        let flow = self.style.get("grid-auto-flow").map_or(&[][..], Value::components);
+        let has_keyword = |name: &str| flow.iter().any(|value| matches!(value, Value::Keyword(keyword) if keyword.eq_ignore_ascii_case(name)));
+        let explicit = templates.each_ref().map(|template| template.names.len() as i32 - 1);
+        let lines = auto_place(&positions, has_keyword("column"), has_keyword("dense"), explicit);
+
+        //the implicit grid reaches from the explicit one out to every item, with its extra
+        //tracks sized by grid-auto-rows and grid-auto-columns, counting back before the start
+        let mut grid = Grid {tracks: [vec![], vec![]], collapsed: [vec![], vec![]], areas: vec![Default::default(); lines.len()]};
+        for (axis, name) in [(ROWS, "grid-auto-rows"), (COLUMNS, "grid-auto-columns")] {
+            let auto = track_list(self.style.get(name), space[axis], gaps[axis]).sizes;
+            let auto_size = |index: i32| match auto.len() as i32 {
+                0 => TrackSize {min: Sizing::Auto, max: Sizing::Auto},
+                count => auto[index.rem_euclid(count) as usize],
+            };
+            let template = &templates[axis];
+            let first = lines.iter().map(|area| area[axis].start).fold(0, i32::min);
+            let last = lines.iter().map(|area| area[axis].end).fold(explicit[axis], i32::max);
+            for line in first..last {
+                let size = match template.sizes.get(line as usize) {
+                    Some(size) if line >= 0 => *size,
+                    _ if line < 0 => auto_size(line),
+                    _ => auto_size(line - template.sizes.len() as i32),
+                };
+                let empty = !lines.iter().any(|area| area[axis].contains(&line));
+                grid.tracks[axis].push(size);
+                grid.collapsed[axis].push(line >= 0 && template.auto_fit.contains(&(line as usize)) && empty);
+            }
+            for (area, lines) in grid.areas.iter_mut().zip(&lines) {
+                area[axis] = (lines[axis].start - first) as usize..(lines[axis].end - first) as usize;
+            }
+        }
+        grid
+    }
+
+    // Lays out the children of a grid container as grid items in its content box, which
+    // starts at `start` and is `height` high if that is known, with `clamp_height` keeping a
+    // height within its min-height and max-height. Returns where the content box ends.
+    pub(super) fn layout_grid_items(&mut self, start: FlowPosition, height: Option<f32>, clamp_height: impl Fn(f32) -> f32, fonts: &FontDatabase) -> (FlowPosition, Option<f32>) {
+        let content = self.dimensions.content;
+        let rtl = keyword(&self.style, "direction") == "rtl";
+        let gaps = [gap(&self.style, "row-gap", height), gap(&self.style, "column-gap", Some(content.width))];
+        let stretches = |name: &str| matches!(keyword(&self.style, name), "normal" | "stretch" | "");
+        let (stretch_columns, stretch_rows) = (stretches("justify-content"), stretches("align-content"));
+        let justify_content = alignment(keyword(&self.style, "justify-content"), false, !rtl);
+        let align_content = alignment(keyword(&self.style, "align-content"), false, true);
+        let justify_items = keyword(&self.style, "justify-items").to_string();
+        let align_items = keyword(&self.style, "align-items").to_string();
+
+        //items go in order-modified document order, §5.4 of CSS Flexbox
+        self.children.sort_by_key(|child| number(&child.style, "order", 0.0) as i32);
+        let unlaid = self.children.clone();
+        let grid = self.grid([height, Some(content.width)], gaps);
+
+        //the columns are sized first, to the widths of the items
+        let contributions: Vec<Contribution> = grid.areas.iter().zip(&self.children).map(|(area, child)| {
+            let (min_content, max_content) = child.intrinsic_contribution(fonts);
+            Contribution {tracks: area[COLUMNS].clone(), min_content, max_content}
+        }).collect();
+        let columns = size_tracks(&grid.tracks[COLUMNS], &grid.collapsed[COLUMNS], gaps[COLUMNS], Space::Definite(content.width), &contributions, stretch_columns);
+        let column_gaps = gaps_after(&grid.collapsed[COLUMNS], gaps[COLUMNS]);
+        let free = content.width - columns.iter().sum::<f32>() - column_gaps.iter().sum::<f32>();
+        let (offset, spacing) = distribute(justify_content, free, columns.len());
+        let column_positions = track_positions(&columns, &column_gaps, offset, spacing);
+
+        //then each item is laid out at the width it gets in its columns, and the rows are
+        //sized to its height
+        let self_alignment = |child: &LayoutBox, property: &str, items: &str, left_is_start: bool| {
+            let value = match keyword(&child.style, property) {
+                "auto" | "" => items,
+                value => value,
+            };
+            match value {
+                "normal" | "" => Alignment::Stretch,
+                value => alignment(value, false, left_is_start),
+            }
+        };
+        let mut widths = vec![];
+        let mut contributions = vec![];
+        for ((child, unlaid), area) in self.children.iter_mut().zip(&unlaid).zip(&grid.areas) {
+            let (_, area_width) = extent(&area[COLUMNS], &column_positions, &columns);
+            let (margin, auto_margins, edges) = child.item_edges(area_width);
+            let width = match to_px(child.style.get("width"), area_width) {
+                Some(_) => None,
+                None if self_alignment(child, "justify-self", &justify_items, !rtl) == Alignment::Stretch && !auto_margins[1] && !auto_margins[3] => {
+                    Some(area_width - axis_sum(margin, true) - axis_sum(edges, true))
+                },
+                None => {
+                    let (min_content, max_content) = child.intrinsic_widths(fonts);
+                    Some(max_content.min(min_content.max(area_width - axis_sum(margin, true) - axis_sum(edges, true))))
+                },
+            };
+            let width = width.map(|width| {
+                let edges = if child.border_box_sizing() { axis_sum(edges, true) } else { 0.0 };
+                Value::Length(width.max(0.0) + edges, Unit::Px)
+            });
+            *child = unlaid.clone();
+            child.layout_overriding(&width.iter().map(|width| ("width", width.clone())).collect::<Vec<_>>(), area_width, None, fonts);
+            let outer_height = child.dimensions.margin_box().height;
+            contributions.push(Contribution {tracks: area[ROWS].clone(), min_content: outer_height, max_content: outer_height});
+            widths.push(width);
+        }
+        let rows_space = height.map_or(Space::MaxContent, Space::Definite);
+        let rows = size_tracks(&grid.tracks[ROWS], &grid.collapsed[ROWS], gaps[ROWS], rows_space, &contributions, stretch_rows);
+        let row_gaps = gaps_after(&grid.collapsed[ROWS], gaps[ROWS]);
+        let rows_height = rows.iter().sum::<f32>() + row_gaps.iter().sum::<f32>();
+        let content_height = height.unwrap_or_else(|| clamp_height(rows_height));
+        let (offset, spacing) = distribute(align_content, content_height - rows_height, rows.len());
+        let row_positions = track_positions(&rows, &row_gaps, offset, spacing);
+
+        //last, each item is aligned in its area, §10
+        for (((child, unlaid), area), width) in self.children.iter_mut().zip(&unlaid).zip(&grid.areas).zip(widths) {
+            let (x, area_width) = extent(&area[COLUMNS], &column_positions, &columns);
+            let (y, area_height) = extent(&area[ROWS], &row_positions, &rows);
+            let (mut margin, auto_margins, edges) = child.item_edges(area_width);
+            let align_self = self_alignment(child, "align-self", &align_items, true);
+            let mut overrides: Vec<(&str, Value)> = width.into_iter().map(|width| ("width", width)).collect();
+            if align_self == Alignment::Stretch && to_px(child.style.get("height"), 0.0).is_none() && !auto_margins[0] && !auto_margins[2] {
+                let height = area_height - axis_sum(margin, false) - if child.border_box_sizing() { 0.0 } else { axis_sum(edges, false) };
+                overrides.push(("height", Value::Length(height.max(0.0), Unit::Px)));
+            }
+            *child = unlaid.clone();
+            child.layout_overriding(&overrides, area_width, Some(area_height), fonts);
+
+            //auto margins take the free space, or else it goes where justify-self and
+            //align-self say
+            let margin_box = child.dimensions.margin_box();
+            let border_box = child.dimensions.border_box();
+            let justify_self = self_alignment(child, "justify-self", &justify_items, !rtl);
+            let mut offsets = [0.0; 2];
+            for (axis, sides, free, alignment) in [
+                (ROWS, [0, 2], area_height - border_box.height - axis_sum(margin, false), align_self),
+                (COLUMNS, [3, 1], area_width - border_box.width - axis_sum(margin, true), justify_self),
+            ] {
+                let autos = sides.iter().filter(|&&side| auto_margins[side]).count();
+                if autos > 0 {
+                    for side in sides.iter().filter(|&&side| auto_margins[side]) {
+                        *super::flex::side(&mut margin, *side) = free.max(0.0) / autos as f32;
+                    }
+                    continue;
+                }
+                offsets[axis] = match alignment {
+                    Alignment::End => free,
+                    Alignment::Center => free / 2.0,
+                    _ => 0.0,
+                };
+            }
+            child.dimensions.margin = margin;
+            let margin_box_width = border_box.width + axis_sum(margin, true);
+            let x = x + offsets[COLUMNS];
+            let x = if rtl { content.width - x - margin_box_width } else { x };
+            let moved = child.dimensions.margin_box();
+            child.translate(content.x + x - moved.x, start.y + y + offsets[ROWS] - margin_box.y);
+        }
+
+        (FlowPosition {y: start.y + content_height, margins: Default::default()}, None)
+    }
+
+    // The min-content and max-content widths of a grid container's content box: the sum of
+    // its columns sized to fit the items' min-content or max-content contributions.
+    pub(super) fn grid_intrinsic_widths(&self, fonts: &FontDatabase) -> (f32, f32) {
+        let gap = gap(&self.style, "column-gap", None);
+        let mut children: Vec<&LayoutBox> = self.children.iter().filter(|child| !child.is_absolutely_positioned()).collect();
+        children.sort_by_key(|child| number(&child.style, "order", 0.0) as i32);
+        let container = LayoutBox {children: children.into_iter().cloned().collect(), ..LayoutBox::new(self.box_type.clone(), self.style.clone())};
+        let grid = container.grid([None, None], [0.0, gap]);
+        let contributions: Vec<Contribution> = grid.areas.iter().zip(&container.children).map(|(area, child)| {
+            let (min_content, max_content) = child.intrinsic_contribution(fonts);
+            Contribution {tracks: area[COLUMNS].clone(), min_content, max_content}
+        }).collect();
+        let width = |space| {
+            let columns = size_tracks(&grid.tracks[COLUMNS], &grid.collapsed[COLUMNS], gap, space, &contributions, false);
+            columns.iter().sum::<f32>() + gaps_after(&grid.collapsed[COLUMNS], gap).iter().sum::<f32>()
+        };
+        (width(Space::MinContent), width(Space::MaxContent))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::content;
+    use super::super::tests::layout;
+    use super::super::Dimensions;
+
+    const ITEMS: &str = "<html><body><div id=grid><div id=a></div><div id=b></div><div id=c></div><div id=d></div></div></body></html>";
+
+    // The top left corner of the content box of each of the items.
+    fn corners(boxes: &[(String, Dimensions)]) -> Vec<(f32, f32)> {
+        ["a", "b", "c", "d"].iter().map(|id| (content(boxes, id).x, content(boxes, id).y)).collect()
+    }
+
+    #[test]
+    fn auto_fill_repeats_as_many_tracks_as_fit() {
+        let boxes = layout(ITEMS, "#grid { display: grid; width: 350px; gap: 10px; grid-template-columns: repeat(auto-fill, 100px) }
+            #a, #b, #c, #d { height: 10px }");
+        assert_eq!(corners(&boxes), [(0.0, 0.0), (110.0, 0.0), (220.0, 0.0), (0.0, 20.0)]);
+
+        //and then the flexible ones share out what is left
+        let boxes = layout(ITEMS, "#grid { display: grid; width: 350px; gap: 10px; grid-template-columns: repeat(auto-fill, minmax(100px, 1fr)) }
+            #a, #b, #c, #d { height: 10px }");
+        assert_eq!(corners(&boxes), [(0.0, 0.0), (120.0, 0.0), (240.0, 0.0), (0.0, 20.0)]);
+        assert_eq!(content(&boxes, "a").width, 110.0);
+    }
+
+    #[test]
+    fn dense_packing_fills_earlier_holes() {
+        let css = "#grid { display: grid; width: 300px; grid-template-columns: 100px 100px 100px }
+            #a, #b, #c, #d { height: 10px } #a, #b { grid-column: span 2 }";
+        //#b doesn't fit beside #a, and the items after it don't go back
+        let boxes = layout(ITEMS, css);
+        assert_eq!(corners(&boxes), [(0.0, 0.0), (0.0, 10.0), (200.0, 10.0), (0.0, 20.0)]);
+        let boxes = layout(ITEMS, &format!("{} #grid {{ grid-auto-flow: dense }}", css));
+        assert_eq!(corners(&boxes), [(0.0, 0.0), (0.0, 10.0), (200.0, 0.0), (200.0, 10.0)]);
+    }
+
+    #[test]
+    fn items_go_in_their_named_areas() {
+        let boxes = layout(ITEMS, "#grid { display: grid; width: 300px; grid-template-columns: 100px 1fr;
+                grid-template-areas: \"head head\" \"side main\" \"foot foot\" }
+            #a { grid-area: foot; height: 10px } #b { grid-area: main; height: 30px }
+            #c { grid-area: side; height: 20px } #d { grid-area: head; height: 10px }");
+        assert_eq!(corners(&boxes), [(0.0, 40.0), (100.0, 10.0), (0.0, 10.0), (0.0, 0.0)]);
+        let widths: Vec<f32> = ["a", "b", "c", "d"].iter().map(|id| content(&boxes, id).width).collect();
+        assert_eq!(widths, [300.0, 200.0, 100.0, 300.0]);
+    }
+
+    #[test]
+    fn flexible_tracks_share_the_free_space() {
+        let boxes = layout(ITEMS, "#grid { display: grid; width: 400px; height: 100px; grid-template-columns: 100px 1fr 2fr;
+            grid-template-rows: 1fr 3fr }");
+        assert_eq!(corners(&boxes), [(0.0, 0.0), (100.0, 0.0), (200.0, 0.0), (0.0, 25.0)]);
+        let sizes: Vec<(f32, f32)> = ["a", "b", "c", "d"].iter().map(|id| (content(&boxes, id).width, content(&boxes, id).height)).collect();
+        assert_eq!(sizes, [(100.0, 25.0), (100.0, 25.0), (200.0, 25.0), (100.0, 75.0)]);
+
+        //a flexible track that would be smaller than its minimum keeps that
+        let boxes = layout(ITEMS, "#grid { display: grid; width: 200px; grid-template-columns: minmax(150px, 1fr) 1fr }
+            #a, #b, #c, #d { height: 10px }");
+        assert_eq!(corners(&boxes), [(0.0, 0.0), (150.0, 0.0), (0.0, 10.0), (150.0, 10.0)]);
+        assert_eq!(content(&boxes, "b").width, 50.0);
+    }
+}
@@ -0,0 +1,1186 @@
+// Inline formatting contexts, CSS 2.1 §9.4.2 and §10.8: the inline-level content of a block
+// container broken into line boxes, with the inline boxes split into a fragment per line.
+// Mixed direction text is put in visual order on each line with the bidi algorithm, CSS
+// Writing Modes 4 §2.
+
+use std::ops::Range;
+
+use super::keyword;
+use super::to_px;
+use super::BoxType;
+use super::Dimensions;
+use super::EdgeSizes;
+use super::FloatContext;
+use super::FlowPosition;
+use super::LayoutBox;
+use super::Rect;
+use crate::bidi;
+use crate::bidi::Level;
+use crate::css::Unit;
+use crate::css::Value;
+use crate::dom::NodeType;
+use crate::font::FontDatabase;
+use crate::font::FontMetrics;
+use crate::font::FontQuery;
+use crate::font::ShapedGlyph;
+use crate::font::ShapedText;
+use crate::line_break;
+use crate::line_break::Break;
+use crate::style::PropertyMap;
+
+fn font_size(style: &PropertyMap) -> f32 {
+    to_px(style.get("font-size"), 0.0).unwrap_or(16.0)
+}
+
+pub(super) fn font_metrics(style: &PropertyMap, fonts: &FontDatabase) -> FontMetrics {
+    fonts.metrics(&FontQuery::from_style(style), font_size(style))
+}
+
+pub(super) fn shape(text: &str, style: &PropertyMap, right_to_left: bool, fonts: &FontDatabase) -> ShapedText {
+    match right_to_left {
+        true => fonts.shape_right_to_left(text, &FontQuery::from_style(style), font_size(style)),
+        false => fonts.shape(text, &FontQuery::from_style(style), font_size(style)),
+    }
+}
+
+fn is_rtl(style: &PropertyMap) -> bool {
+    keyword(style, "direction") == "rtl"
+}
+
+fn line_height(style: &PropertyMap, fonts: &FontDatabase) -> f32 {
+    let metrics = font_metrics(style, fonts);
+    match style.get("line-height") {
+        Some(Value::Length(len, Unit::Px)) => *len,
+        Some(Value::Length(number, Unit::None)) => number * metrics.size,
+        //normal, which is what the font asks for
+        _ => metrics.ascent + metrics.descent + metrics.line_gap,
+    }
+}
+
+// How far above and below its baseline the inline box of text in this style reaches: the
+// font's ascent and descent, with the leading line-height adds split evenly between them.
+fn inline_extents(style: &PropertyMap, fonts: &FontDatabase) -> (f32, f32) {
+    let metrics = font_metrics(style, fonts);
+    let half_leading = (line_height(style, fonts) - metrics.ascent - metrics.descent) / 2.0;
+    (metrics.ascent + half_leading, metrics.descent + half_leading)
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum WhiteSpace {
+    Normal,
+    Pre,
+    Nowrap,
+    PreWrap,
+    PreLine,
+}
+
+impl WhiteSpace {
+    fn of(style: &PropertyMap) -> WhiteSpace {
+        match style.get("white-space") {
+            Some(Value::Keyword(keyword)) => match &**keyword {
+                "pre" => WhiteSpace::Pre,
+                "nowrap" => WhiteSpace::Nowrap,
+                "pre-wrap" => WhiteSpace::PreWrap,
+                "pre-line" => WhiteSpace::PreLine,
+                _ => WhiteSpace::Normal,
+            },
+            _ => WhiteSpace::Normal,
+        }
+    }
+
+    fn collapses_spaces(self) -> bool {
+        matches!(self, WhiteSpace::Normal | WhiteSpace::Nowrap | WhiteSpace::PreLine)
+    }
+
+    fn preserves_newlines(self) -> bool {
+        matches!(self, WhiteSpace::Pre | WhiteSpace::PreWrap | WhiteSpace::PreLine)
+    }
+
+    fn wraps(self) -> bool {
+        matches!(self, WhiteSpace::Normal | WhiteSpace::PreWrap | WhiteSpace::PreLine)
+    }
+}
+
+// Applies the white space processing rules of CSS Text 3 §4.1.1 to the text of a text node.
+// `space_before` says whether what came before ends with a collapsible space, or is the start
+// of the block, so spaces collapse across element boundaries too.
+fn process_white_space(text: &str, white_space: WhiteSpace, space_before: &mut bool) -> String {
+    let mut processed = String::new();
+    for c in text.replace("\r\n", "\n").replace('\r', "\n").chars() {
+        match c {
+            '\n' if white_space.preserves_newlines() => {
+                if white_space.collapses_spaces() {
+                    while processed.ends_with(' ') {
+                        processed.pop();
+                    }
+                }
+                processed.push('\n');
+                *space_before = white_space.collapses_spaces();
+            },
+            ' ' | '\t' | '\n' | '\x0C' if white_space.collapses_spaces() => {
+                if !*space_before {
+                    processed.push(' ');
+                    *space_before = true;
+                }
+            },
+            _ => {
+                processed.push(c);
+                *space_before = false;
+            },
+        }
+    }
+    processed
+}
+
+// The content of an inline formatting context in document order, with the inline boxes
+// flattened into their start and end.
+enum InlineItem<'b, 'a> {
+    Start(&'b LayoutBox<'a>),
+    End(&'b LayoutBox<'a>),
+    // a text box, with its text after white space processing
+    Text(&'b LayoutBox<'a>, String),
+    // an inline-block, or a block inside an inline box. The block really ought to split the
+    // inline box in two, instead it takes up a line of its own by being as wide as one.
+    Atomic(&'b LayoutBox<'a>),
+    // a float, which takes no room on the line but goes beside it, or an absolutely positioned
+    // box, which goes where it is on the line until it is laid out
+    OutOfFlow(&'b LayoutBox<'a>),
+}
+
+impl<'b, 'a> InlineItem<'b, 'a> {
+    fn layout_box(&self) -> &'b LayoutBox<'a> {
+        match self {
+            InlineItem::Start(layout_box) | InlineItem::End(layout_box) | InlineItem::Text(layout_box, _) | InlineItem::Atomic(layout_box) | InlineItem::OutOfFlow(layout_box) => layout_box,
+        }
+    }
+}
+
+fn collect_items<'b, 'a>(boxes: &'b [LayoutBox<'a>], items: &mut Vec<InlineItem<'b, 'a>>, space_before: &mut bool) {
+    for layout_box in boxes {
+        match &layout_box.box_type {
+            BoxType::GeneratedText {text, ..} => {
+                let text = process_white_space(text, WhiteSpace::of(&layout_box.style), space_before);
+                if !text.is_empty() {
+                    items.push(InlineItem::Text(layout_box, text));
+                }
+            },
+            BoxType::InlineNode(node) => match &node.node.node_type {
+                NodeType::Text(text) => {
+                    let text = process_white_space(text, WhiteSpace::of(&layout_box.style), space_before);
+                    if !text.is_empty() {
+                        items.push(InlineItem::Text(layout_box, text));
+                    }
+                },
+                _ => {
+                    items.push(InlineItem::Start(layout_box));
+                    collect_items(&layout_box.children, items, space_before);
+                    items.push(InlineItem::End(layout_box));
+                },
+            },
+            _ if layout_box.is_out_of_flow() => items.push(InlineItem::OutOfFlow(layout_box)),
+            _ => {
+                items.push(InlineItem::Atomic(layout_box));
+                *space_before = false;
+            },
+        }
+    }
+}
+
+// The level the paragraphs of a block container start at, from its direction, or None if
+// unicode-bidi: plaintext has each one take the direction of its text.
+fn base_level(style: &PropertyMap) -> Option<Level> {
+    match keyword(style, "unicode-bidi") {
+        "plaintext" => None,
+        _ => Some(is_rtl(style) as Level),
+    }
+}
+
+// The bidi controls that stand for the start and end of an inline box with this unicode-bidi
+// and direction, CSS Writing Modes 4 §2.4.2.
+fn bidi_controls(style: &PropertyMap) -> (&'static str, &'static str) {
+    let rtl = is_rtl(style);
+    match keyword(style, "unicode-bidi") {
+        "embed" if rtl => ("\u{202B}", "\u{202C}"),
+        "embed" => ("\u{202A}", "\u{202C}"),
+        "isolate" if rtl => ("\u{2067}", "\u{2069}"),
+        "isolate" => ("\u{2066}", "\u{2069}"),
+        "bidi-override" if rtl => ("\u{202E}", "\u{202C}"),
+        "bidi-override" => ("\u{202D}", "\u{202C}"),
+        "isolate-override" if rtl => ("\u{2067}\u{202E}", "\u{202C}\u{2069}"),
+        "isolate-override" => ("\u{2066}\u{202D}", "\u{202C}\u{2069}"),
+        "plaintext" => ("\u{2068}", "\u{2069}"),
+        _ => ("", ""),
+    }
+}
+
+// The embedding level of each item: one for each byte of a text, one for an atomic inline,
+// and for the start and end of an inline box the level of the content inside it next to them,
+// or the level it puts its content at if it has none. They come from the text of the
+// paragraph with the inline boxes turned into bidi controls, in a block container with style
+// `paragraph`.
+fn bidi_levels(items: &[InlineItem], paragraph: &PropertyMap) -> Vec<Vec<Level>> {
+    //an override on the block container applies to all of its inline content
+    let mut text = match keyword(paragraph, "unicode-bidi") {
+        "bidi-override" | "isolate-override" if is_rtl(paragraph) => "\u{202E}".to_string(),
+        "bidi-override" | "isolate-override" => "\u{202D}".to_string(),
+        _ => String::new(),
+    };
+    let mut starts = vec![];
+    for item in items {
+        starts.push(text.len());
+        match item {
+            InlineItem::Start(layout_box) => text.push_str(bidi_controls(&layout_box.style).0),
+            InlineItem::End(layout_box) => text.push_str(bidi_controls(&layout_box.style).1),
+            InlineItem::Text(_, item_text) => text.push_str(item_text),
+            InlineItem::Atomic(_) => text.push('\u{FFFC}'),
+            InlineItem::OutOfFlow(_) => {},
+        }
+    }
+    starts.push(text.len());
+    let base = base_level(paragraph);
+    let levels = bidi::resolve_levels(&text, base);
+
+    //the embedding levels of the inline boxes follow X1 to X5
+    let mut embedding = vec![base.unwrap_or_else(|| bidi::paragraph_level(&text).unwrap_or(0))];
+    let mut item_levels: Vec<Vec<Level>> = items.iter().enumerate().map(|(index, item)| match item {
+        InlineItem::Start(layout_box) => {
+            let outer = *embedding.last().unwrap();
+            let rtl = match keyword(&layout_box.style, "unicode-bidi") {
+                "normal" | "" => None,
+                "plaintext" => {
+                    let end = (index..items.len()).find(|&end| matches!(items[end], InlineItem::End(end_box) if std::ptr::eq(end_box, *layout_box))).unwrap_or(items.len());
+                    Some(bidi::paragraph_level(&text[starts[index]..starts[end]]) == Some(1))
+                },
+                _ => Some(is_rtl(&layout_box.style)),
+            };
+            let level = match rtl {
+                Some(true) => (outer + 1) | 1,
+                Some(false) => (outer + 2) & !1,
+                None => outer,
+            };
+            embedding.push(level);
+            vec![level]
+        },
+        InlineItem::End(_) => {
+            let level = *embedding.last().unwrap();
+            if embedding.len() > 1 {
+                embedding.pop();
+            }
+            vec![level]
+        },
+        InlineItem::OutOfFlow(_) => vec![*embedding.last().unwrap()],
+        InlineItem::Text(..) | InlineItem::Atomic(_) => levels[starts[index]..starts[index + 1]].to_vec(),
+    }).collect();
+
+    //the edges of a box stay beside its content, so that they don't split up a run of text
+    //the box isn't an embedding for
+    let content_level = |indices: &mut dyn Iterator<Item = usize>, first: bool| {
+        let mut depth = 0;
+        for i in indices {
+            match (&items[i], first) {
+                (InlineItem::Start(_), true) | (InlineItem::End(_), false) => depth += 1,
+                (InlineItem::End(_), true) | (InlineItem::Start(_), false) if depth == 0 => return None,
+                (InlineItem::End(_), true) | (InlineItem::Start(_), false) => depth -= 1,
+                (InlineItem::Text(..) | InlineItem::Atomic(_), _) if !item_levels[i].is_empty() => {
+                    return Some(if first { item_levels[i][0] } else { item_levels[i][item_levels[i].len() - 1] });
+                },
+                _ => {},
+            }
+        }
+        None
+    };
+    let edge_levels: Vec<Option<Level>> = items.iter().enumerate().map(|(index, item)| match item {
+        InlineItem::Start(_) => content_level(&mut (index + 1..items.len()), true),
+        InlineItem::End(_) => content_level(&mut (0..index).rev(), false),
+        _ => None,
+    }).collect();
+    for (levels, edge_level) in item_levels.iter_mut().zip(edge_levels) {
+        if let Some(level) = edge_level {
+            *levels = vec![level];
+        }
+    }
+    item_levels
+}
+
+// Splits a range of text at the changes of level, into ranges relative to its start. Empty text
+// is a single empty range.
+fn level_runs(levels: &[Level]) -> Vec<Range<usize>> {
+    let mut runs = vec![];
+    let mut start = 0;
+    for i in 1..=levels.len() {
+        if i == levels.len() || levels[i] != levels[start] {
+            runs.push(start..i);
+            start = i;
+        }
+    }
+    if runs.is_empty() {
+        runs.push(0..0);
+    }
+    runs
+}
+
+// The margins, borders and paddings of an inline box. Vertical margins don't do anything
+// for inline boxes, so they're left out.
+fn inline_edges(style: &PropertyMap, containing_width: f32) -> (EdgeSizes, EdgeSizes, EdgeSizes) {
+    let size = |name: &str| to_px(style.get(name), containing_width).unwrap_or(0.0);
+    let edges = |prefix: &str, suffix: &str| EdgeSizes {
+        top: size(&format!("{}-top{}", prefix, suffix)),
+        right: size(&format!("{}-right{}", prefix, suffix)),
+        bottom: size(&format!("{}-bottom{}", prefix, suffix)),
+        left: size(&format!("{}-left{}", prefix, suffix)),
+    };
+    let margin = EdgeSizes {left: size("margin-left"), right: size("margin-right"), ..Default::default()};
+    (margin, edges("border", "-width"), edges("padding", ""))
+}
+
+// The margin, border and padding of the start or end edge of an inline box, which is on its
+// left or right depending on its direction.
+fn inline_edge(style: &PropertyMap, containing_width: f32, end: bool) -> (f32, f32, f32) {
+    let (margin, border, padding) = inline_edges(style, containing_width);
+    match is_rtl(style) != end {
+        true => (margin.right, border.right, padding.right),
+        false => (margin.left, border.left, padding.left),
+    }
+}
+
+// What the line breaker places as a unit: content between two break opportunities, or the
+// start or end of an inline box.
+struct Piece {
+    kind: PieceKind,
+    // the embedding level of text and atomic inlines, for the start and end of an inline box
+    // that of the content beside them
+    level: Level,
+    width: f32,
+    // width of the spaces the piece ends with, which are removed or hang when it ends a line
+    trailing_space: f32,
+    break_before: Option<Break>,
+    // the shaped text of a text piece
+    glyphs: Vec<ShapedGlyph>,
+}
+
+enum PieceKind {
+    // indices into the items
+    Start(usize),
+    // of the innermost inline box still open
+    End,
+    // a range of the item's text
+    Text(usize, Range<usize>),
+    Atomic(usize),
+    OutOfFlow(usize),
+}
+
+// Splits the items into pieces at the break opportunities, and text further where its level
+// changes. `atomic_width` is how much room an atomic inline or a float takes up on a line,
+// `paragraph` is the style of the block container.
+fn pieces(items: &[InlineItem], containing_width: f32, atomic_width: &dyn Fn(&LayoutBox) -> f32, paragraph: &PropertyMap, fonts: &FontDatabase) -> Vec<Piece> {
+    //the break opportunities come from the text of the whole paragraph, with atomic inlines
+    //as object replacement characters, along with whether white-space lets each part wrap
+    let mut text = String::new();
+    let mut spans: Vec<(Range<usize>, bool)> = vec![];
+    for item in items {
+        let start = text.len();
+        match item {
+            InlineItem::Text(_, item_text) => text.push_str(item_text),
+            InlineItem::Atomic(_) => text.push('\u{FFFC}'),
+            _ => continue,
+        }
+        spans.push((start..text.len(), WhiteSpace::of(&item.layout_box().style).wraps()));
+    }
+    let wraps_before = |offset: usize| {
+        let span = spans.partition_point(|(range, _)| range.end < offset);
+        spans.get(span).is_none_or(|(_, wraps)| *wraps)
+    };
+    let opportunities: Vec<(usize, Break)> = line_break::break_opportunities(&text).into_iter()
+        .filter(|&(offset, opportunity)| opportunity == Break::Mandatory || wraps_before(offset))
+        .collect();
+
+    //each opportunity goes to the first piece after it that isn't the end of an inline box,
+    //so inline boxes that end at a break stay on the line before it
+    let mut next_opportunity = 0;
+    let mut take_break = |offset: usize| {
+        while opportunities.get(next_opportunity).is_some_and(|&(at, _)| at < offset) {
+            next_opportunity += 1;
+        }
+        match opportunities.get(next_opportunity) {
+            Some(&(at, opportunity)) if at == offset => {
+                next_opportunity += 1;
+                Some(opportunity)
+            },
+            _ => None,
+        }
+    };
+
+    let levels = bidi_levels(items, paragraph);
+    let mut pieces = vec![];
+    let mut offset = 0;
+    for (index, item) in items.iter().enumerate() {
+        let layout_box = item.layout_box();
+        let level = levels[index].first().copied().unwrap_or(0);
+        match item {
+            InlineItem::Start(_) => {
+                let (margin, border, padding) = inline_edge(&layout_box.style, containing_width, false);
+                let width = margin + border + padding;
+                pieces.push(Piece {kind: PieceKind::Start(index), level, width, trailing_space: 0.0, break_before: take_break(offset), glyphs: vec![]});
+            },
+            InlineItem::End(_) => {
+                let (margin, border, padding) = inline_edge(&layout_box.style, containing_width, true);
+                let width = padding + border + margin;
+                pieces.push(Piece {kind: PieceKind::End, level, width, trailing_space: 0.0, break_before: None, glyphs: vec![]});
+            },
+            InlineItem::Atomic(_) => {
+                let break_before = take_break(offset);
+                pieces.push(Piece {kind: PieceKind::Atomic(index), level, width: atomic_width(layout_box), trailing_space: 0.0, break_before, glyphs: vec![]});
+                offset += '\u{FFFC}'.len_utf8();
+            },
+            //a box out of flow is no break opportunity, it stays on the line of what comes
+            //before it
+            InlineItem::OutOfFlow(_) => {
+                let width = if layout_box.is_float() { atomic_width(layout_box) } else { 0.0 };
+                pieces.push(Piece {kind: PieceKind::OutOfFlow(index), level, width, trailing_space: 0.0, break_before: None, glyphs: vec![]});
+            },
+            InlineItem::Text(_, item_text) => {
+                let hangs = WhiteSpace::of(&layout_box.style) != WhiteSpace::Pre;
+                let ends = opportunities.iter()
+                    .map(|&(at, _)| at)
+                    .filter(|&at| at > offset && at < offset + item_text.len())
+                    .map(|at| at - offset)
+                    .chain(std::iter::once(item_text.len()));
+                let mut start = 0;
+                for end in ends.collect::<Vec<usize>>() {
+                    let mut break_before = take_break(offset + start);
+                    //a preserved newline ends the line, but isn't shown
+                    let chunk = &item_text[start..end];
+                    let shown = chunk.strip_suffix('\n').unwrap_or(chunk);
+                    for run in level_runs(&levels[index][start..start + shown.len()]) {
+                        let level = levels[index].get(start + run.start).copied().unwrap_or(0);
+                        let text = &shown[run.clone()];
+                        let shaped = shape(text, &layout_box.style, level % 2 == 1, fonts);
+                        let spaces_start = text.trim_end_matches(' ').len();
+                        let trailing_space = match hangs {
+                            true => shaped.glyphs.iter().filter(|glyph| glyph.cluster >= spaces_start).map(|glyph| glyph.advance).sum(),
+                            false => 0.0,
+                        };
+                        pieces.push(Piece {
+                            kind: PieceKind::Text(index, start + run.start..start + run.end),
+                            level,
+                            width: shaped.width,
+                            trailing_space,
+                            break_before: break_before.take(),
+                            glyphs: shaped.glyphs,
+                        });
+                    }
+                    start = end;
+                }
+                offset += item_text.len();
+            },
+        }
+    }
+    pieces
+}
+
+// The width of trailing spaces hanging off the end of a line that ends with `piece`, given the
+// hang before it.
+fn hang_after(piece: &Piece, hang: f32) -> f32 {
+    match piece.kind {
+        PieceKind::Text(..) => piece.trailing_space,
+        PieceKind::End | PieceKind::OutOfFlow(_) => hang,
+        _ => 0.0,
+    }
+}
+
+// Fills a line greedily with the pieces from `start` on, returning where the next line starts:
+// at the last break opportunity before its content would overflow `available`, or at a
+// mandatory break. Content that doesn't fit even on a line of its own overflows.
+fn break_line(pieces: &[Piece], start: usize, available: f32) -> usize {
+    let mut width = 0.0;
+    let mut hang = 0.0;
+    let mut last_break = None;
+    for (i, piece) in pieces.iter().enumerate().skip(start) {
+        match piece.break_before {
+            Some(Break::Mandatory) if i > start => return i,
+            Some(Break::Allowed) if i > start => last_break = Some(i),
+            _ => {},
+        }
+        width += piece.width;
+        hang = hang_after(piece, hang);
+        if width - hang > available {
+            if let Some(at) = last_break {
+                return at;
+            }
+        }
+    }
+    pieces.len()
+}
+
+// How wide the pieces are on a line, without the spaces that hang off its end.
+fn line_width(pieces: &[Piece]) -> f32 {
+    let (width, hang) = pieces.iter().fold((0.0, 0.0), |(width, hang), piece| (width + piece.width, hang_after(piece, hang)));
+    width - hang
+}
+
+// The min-content and max-content widths of inline content: its widest unbreakable run, and
+// its widest line when only breaking where it has to.
+pub(super) fn intrinsic_inline_widths(children: &[LayoutBox], paragraph: &PropertyMap, fonts: &FontDatabase) -> (f32, f32) {
+    let mut items = vec![];
+    collect_items(children, &mut items, &mut true);
+    let widest = |pieces: Vec<Piece>, breaks_at: &dyn Fn(Break) -> bool| {
+        let (mut widest, mut width, mut hang) = (0.0f32, 0.0, 0.0);
+        for piece in &pieces {
+            if piece.break_before.is_some_and(breaks_at) {
+                widest = widest.max(width - hang);
+                width = 0.0;
+            }
+            width += piece.width;
+            hang = hang_after(piece, hang);
+        }
+        widest.max(width - hang)
+    };
+    let min_content = widest(pieces(&items, 0.0, &|atomic| atomic.intrinsic_contribution(fonts).0, paragraph, fonts), &|_| true);
+    let max_content = widest(pieces(&items, 0.0, &|atomic| atomic.intrinsic_contribution(fonts).1, paragraph, fonts), &|opportunity| opportunity == Break::Mandatory);
+    (min_content, max_content)
+}
+
+// Lays out the atomic inlines at the origin, for their line to move them into place.
+// `paragraph` is the style of the block container they're in.
+fn layout_atomic_inlines(boxes: &mut [LayoutBox], containing_width: f32, paragraph: &PropertyMap, fonts: &FontDatabase) {
+    for layout_box in boxes {
+        match layout_box.box_type {
+            BoxType::InlineNode(_) => layout_atomic_inlines(&mut layout_box.children, containing_width, paragraph, fonts),
+            BoxType::GeneratedText {..} => {},
+            //absolutely positioned boxes wait for their containing block
+            _ if layout_box.is_absolutely_positioned() => {},
+            _ if layout_box.is_orthogonal_to(paragraph) => {
+                layout_box.layout_orthogonal(0.0, Default::default(), containing_width, None, fonts);
+            },
+            _ => {
+                layout_box.layout_block(0.0, Default::default(), containing_width, None, None, fonts);
+            },
+        }
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    // Lays out the inline-level children of a block container in line boxes, which then take
+    // their place as its children. Returns where a block after the lines would go and the top
+    // of the first line, if there are any lines at all.
+    pub(super) fn layout_inline_children(&mut self, start: FlowPosition, floats: &mut FloatContext, fonts: &FontDatabase) -> (FlowPosition, Option<f32>) {
+        let content = self.dimensions.content;
+        let mut children = std::mem::take(&mut self.children);
+        layout_atomic_inlines(&mut children, content.width, &self.style, fonts);
+        let mut items = vec![];
+        collect_items(&children, &mut items, &mut true);
+        let atomic_width = |atomic: &LayoutBox| if atomic.is_float() { 0.0 } else { atomic.dimensions.margin_box().width };
+        let pieces = pieces(&items, content.width, &atomic_width, &self.style, fonts);
+
+        //with unicode-bidi: plaintext the paragraph's direction is that of its text
+        let rtl = match base_level(&self.style) {
+            Some(level) => level == 1,
+            None => {
+                let text: String = items.iter().filter_map(|item| match item {
+                    InlineItem::Text(_, text) => Some(text.as_str()),
+                    _ => None,
+                }).collect();
+                bidi::paragraph_level(&text) == Some(1)
+            },
+        };
+
+        //each line gets the room the floats leave beside it, which is only known for sure
+        //once the line is as tall as its content, so it goes by the height of the strut
+        let strut = line_height(&self.style, fonts);
+        let (left, right) = (content.x, content.x + content.width);
+        let top = start.y + start.margins.collapse();
+        let mut y = top;
+        let mut open = vec![];
+        let mut lines = 0;
+        let mut placed = vec![false; pieces.len()];
+        let mut below = vec![];
+        let mut line_start = 0;
+        while line_start < pieces.len() {
+            let (band_left, band_right) = floats.band(y, strut, left, right);
+            let end = break_line(&pieces, line_start, band_right - band_left);
+
+            //a line shortened too much to hold any of its content moves down past the floats
+            //beside it, CSS 2.1 §9.5
+            if line_width(&pieces[line_start..end]) > band_right - band_left {
+                if let Some(bottom) = floats.next_bottom(y, strut) {
+                    y = bottom;
+                    continue;
+                }
+            }
+
+            //a float goes at the top of its line if it fits beside the content before it,
+            //which leaves less room for the line, otherwise it goes below the line
+            let mut shortened = false;
+            for i in line_start..end {
+                let item = match pieces[i].kind {
+                    PieceKind::OutOfFlow(item) if !placed[i] && items[item].layout_box().is_float() => item,
+                    _ => continue,
+                };
+                placed[i] = true;
+                let mut float = items[item].layout_box().clone();
+                let width = float.dimensions.margin_box().width;
+                if below.is_empty() && line_width(&pieces[line_start..i]) + width <= band_right - band_left {
+                    float.place_float(content, y, floats);
+                    self.children.push(float);
+                    shortened = true;
+                    break;
+                }
+                below.push(float);
+            }
+            if shortened {
+                continue;
+            }
+
+            //the last line and lines ended by a newline aren't justified
+            let justify = pieces.get(end).is_some_and(|next| next.break_before == Some(Break::Allowed));
+            let line = &pieces[line_start..end];
+            let mut line_box = self.build_line(&items, line, &mut open, justify, rtl, (band_left, band_right - band_left));
+            line_start = end;
+
+            //a line with nothing but empty inline boxes without margins, borders or padding
+            //isn't there at all
+            let phantom = line.iter().all(|piece| matches!(piece.kind, PieceKind::Start(_) | PieceKind::End | PieceKind::OutOfFlow(_)) && piece.width == 0.0);
+            if !phantom {
+                self.align_line(&mut line_box, y, fonts);
+                self.apply_text_overflow(&mut line_box, fonts);
+                y += line_box.dimensions.content.height;
+                self.children.push(line_box);
+                lines += 1;
+            } else {
+                //absolutely positioned boxes on it go where it would have been
+                for mut positioned in line_box.children.into_iter().filter(LayoutBox::is_absolutely_positioned) {
+                    positioned.dimensions.content.y = y;
+                    self.children.push(positioned);
+                }
+            }
+            for mut float in below.drain(..) {
+                float.place_float(content, y, floats);
+                self.children.push(float);
+            }
+        }
+
+        if lines == 0 {
+            return (start, None);
+        }
+        (FlowPosition {y, margins: Default::default()}, Some(top))
+    }
+
+    // The line box holding `pieces`, with its fragments placed horizontally. `open` has the
+    // inline boxes left open by the line before, and gets those left open by this one.
+    // Start and end are right and left in a right to left paragraph. The line goes across
+    // `band`, the x and width of the room the floats leave.
+    fn build_line(&self, items: &[InlineItem<'_, 'a>], pieces: &[Piece], open: &mut Vec<usize>, justify: bool, rtl: bool, (band_x, band_width): (f32, f32)) -> LayoutBox<'a> {
+        let content = self.dimensions.content;
+
+        //spaces at the end of the line are removed if collapsible and hang otherwise, either
+        //way they don't count for alignment
+        let last_text = pieces.iter().rposition(|piece| !matches!(piece.kind, PieceKind::End | PieceKind::OutOfFlow(_)))
+            .filter(|&i| matches!(pieces[i].kind, PieceKind::Text(..)));
+        let hang = last_text.map_or(0.0, |i| pieces[i].trailing_space);
+        let width = pieces.iter().map(|piece| piece.width).sum::<f32>() - hang;
+        let text_of = |i: usize| match (&pieces[i].kind, &items) {
+            (PieceKind::Text(item, range), items) => match &items[*item] {
+                InlineItem::Text(_, text) => &text[range.clone()],
+                _ => "",
+            },
+            _ => "",
+        };
+        let justifiable_spaces = |i: usize| {
+            let text = text_of(i);
+            let text = if Some(i) == last_text { text.trim_end_matches(' ') } else { text };
+            text.matches(' ').count()
+        };
+
+        let spaces: usize = (0..pieces.len()).map(justifiable_spaces).sum();
+        let free = (band_width - width).max(0.0);
+        let (offset, justification) = match keyword(&self.style, "text-align") {
+            "justify" if justify && spaces > 0 => (0.0, free / spaces as f32),
+            "right" => (free, 0.0),
+            "center" => (free / 2.0, 0.0),
+            "end" if !rtl => (free, 0.0),
+            "left" | "end" => (0.0, 0.0),
+            _ if rtl => (free, 0.0),
+            _ => (0.0, 0.0),
+        };
+
+        let mut line_box = LayoutBox::new(BoxType::LineBox {baseline: 0.0}, PropertyMap::new());
+        line_box.dimensions.content = Rect {x: band_x, y: 0.0, width: band_width, height: 0.0};
+
+        //the pieces go along the line in visual order, UAX #9 L2, which gives the edges of
+        //the inline boxes and the text and atomic inlines their places
+        let levels: Vec<Level> = pieces.iter().map(|piece| piece.level).collect();
+        let mut xs = vec![0.0; pieces.len()];
+        let mut leaves: Vec<Option<LayoutBox<'a>>> = pieces.iter().map(|_| None).collect();
+        let mut order = bidi::visual_order(&levels);
+        //the start edge of an inline box goes on the side its direction starts from, whichever
+        //side of its content the reordering put it on
+        let mut starts = vec![];
+        for (i, piece) in pieces.iter().enumerate() {
+            match piece.kind {
+                PieceKind::Start(item) => starts.push((i, item)),
+                PieceKind::End => if let Some((start, item)) = starts.pop() {
+                    let start_at = order.iter().position(|&j| j == start).unwrap();
+                    let end_at = order.iter().position(|&j| j == i).unwrap();
+                    if (start_at < end_at) == is_rtl(&items[item].layout_box().style) {
+                        order.swap(start_at, end_at);
+                    }
+                },
+                _ => {},
+            }
+        }
+        let mut x = band_x + offset;
+        for i in order {
+            let piece = &pieces[i];
+            xs[i] = x;
+            match &piece.kind {
+                PieceKind::Start(_) | PieceKind::End => x += piece.width,
+                PieceKind::OutOfFlow(item) => {
+                    let layout_box = items[*item].layout_box();
+                    if layout_box.is_absolutely_positioned() {
+                        let mut positioned = layout_box.clone();
+                        positioned.set_static_position(x, 0.0);
+                        leaves[i] = Some(positioned);
+                    }
+                },
+                PieceKind::Text(item, _) => {
+                    let layout_box = items[*item].layout_box();
+                    let white_space = WhiteSpace::of(&layout_box.style);
+                    let text = match text_of(i) {
+                        text if Some(i) == last_text && white_space.collapses_spaces() => text.trim_end_matches(' '),
+                        text => text,
+                    };
+                    let node = match layout_box.box_type {
+                        //nothing is left of a piece that was only spaces
+                        BoxType::InlineNode(node) | BoxType::GeneratedText {node, ..} if !text.is_empty() || text_of(i).is_empty() => node,
+                        _ => continue,
+                    };
+                    //justification widens the spaces
+                    let mut glyphs: Vec<ShapedGlyph> = piece.glyphs.iter().filter(|glyph| glyph.cluster < text.len()).copied().collect();
+                    let mut width = 0.0;
+                    for glyph in &mut glyphs {
+                        if text[glyph.cluster..].starts_with(' ') {
+                            glyph.advance += justification;
+                        }
+                        glyph.x = width;
+                        width += glyph.advance;
+                    }
+                    let mut fragment = LayoutBox::new(BoxType::TextRun {node, text: text.to_string(), justification, glyphs}, layout_box.style.clone());
+                    fragment.dimensions.content = Rect {x, y: 0.0, width, height: 0.0};
+                    leaves[i] = Some(fragment);
+                    x += width;
+                },
+                PieceKind::Atomic(item) => {
+                    let mut atomic = items[*item].layout_box().clone();
+                    let margin_box = atomic.dimensions.margin_box();
+                    atomic.translate(x - margin_box.x, 0.0);
+                    x += margin_box.width;
+                    leaves[i] = Some(atomic);
+                },
+            }
+        }
+
+        //then the fragments are nested in document order. Those of the inline boxes open at
+        //this point come outermost first, and the ones carried over from the line before
+        //have no start edge on this line.
+        let edge = |i: usize| (xs[i], pieces[i].width);
+        let mut stack: Vec<OpenFragment<'a>> = open.iter().map(|&item| {
+            (item, None, inline_fragment(items[item].layout_box(), content.width))
+        }).collect();
+        for (i, piece) in pieces.iter().enumerate() {
+            match &piece.kind {
+                PieceKind::Start(item) => stack.push((*item, Some(edge(i)), inline_fragment(items[*item].layout_box(), content.width))),
+                PieceKind::End => {
+                    let (_, start, mut fragment) = stack.pop().unwrap();
+                    finish_fragment(&mut fragment, start, Some(edge(i)), content.width);
+                    add_fragment(&mut stack, &mut line_box, fragment);
+                },
+                PieceKind::Text(item, _) => {
+                    let fragment = match leaves[i].take() {
+                        Some(fragment) => fragment,
+                        None => continue,
+                    };
+                    //the pieces of a text box at the same level on the same line make up one
+                    //run, the later one on the left of the earlier one in right to left text
+                    let continues_run = i > 0 && piece.level == pieces[i - 1].level
+                        && matches!(&pieces[i - 1].kind, PieceKind::Text(previous, _) if previous == item);
+                    let siblings = match stack.last_mut() {
+                        Some((_, _, parent)) => &mut parent.children,
+                        None => &mut line_box.children,
+                    };
+                    match (continues_run, siblings.last_mut()) {
+                        (true, Some(run)) if matches!(run.box_type, BoxType::TextRun {..}) => merge_runs(run, fragment),
+                        _ => add_fragment(&mut stack, &mut line_box, fragment),
+                    }
+                },
+                PieceKind::Atomic(_) => {
+                    if let Some(atomic) = leaves[i].take() {
+                        add_fragment(&mut stack, &mut line_box, atomic);
+                    }
+                },
+                //absolutely positioned boxes go straight in the line, at its top
+                PieceKind::OutOfFlow(_) => {
+                    if let Some(positioned) = leaves[i].take() {
+                        line_box.children.push(positioned);
+                    }
+                },
+            }
+        }
+
+        //the inline boxes still open carry on on the next line
+        open.clear();
+        while let Some((item, start, mut fragment)) = stack.pop() {
+            finish_fragment(&mut fragment, start, None, content.width);
+            open.insert(0, item);
+            add_fragment(&mut stack, &mut line_box, fragment);
+        }
+        line_box
+    }
+
+    // Puts the line box at `y`, as tall as its fragments need once they're aligned vertically,
+    // CSS 2.1 §10.8.
+    fn align_line(&self, line_box: &mut LayoutBox<'a>, y: f32, fonts: &FontDatabase) {
+        //the root inline box counts even when empty, as if the line started with a zero
+        //width character in the block's font
+        let metrics = font_metrics(&self.style, fonts);
+        let (above, below) = inline_extents(&self.style, fonts);
+        let mut extents = LineExtents {top: -above, bottom: below, aligned: vec![]};
+        for fragment in &line_box.children {
+            measure(fragment, 0.0, metrics, &mut extents, fonts);
+        }
+
+        //boxes aligned to the top or bottom of the line make it taller on the other side
+        let (mut top, mut bottom) = (extents.top, extents.bottom);
+        for (to_top, height) in extents.aligned {
+            if bottom - top < height {
+                if to_top {
+                    bottom = top + height;
+                } else {
+                    top = bottom - height;
+                }
+            }
+        }
+
+        let baseline = y - top;
+        for fragment in &mut line_box.children {
+            place(fragment, baseline, metrics, (y, y + bottom - top), fonts);
+        }
+        line_box.box_type = BoxType::LineBox {baseline};
+        line_box.dimensions.content.y = y;
+        line_box.dimensions.content.height = bottom - top;
+    }
+}
+
+// A fragment of an inline box, with its vertical edges. The horizontal ones depend on where
+// the fragment's content and edges end up on the line.
+fn inline_fragment<'a>(layout_box: &LayoutBox<'a>, containing_width: f32) -> LayoutBox<'a> {
+    let (_, border, padding) = inline_edges(&layout_box.style, containing_width);
+    let mut fragment = LayoutBox::new(layout_box.box_type.clone(), layout_box.style.clone());
+    fragment.dimensions = Dimensions {
+        content: Rect::default(),
+        padding: EdgeSizes {left: 0.0, right: 0.0, ..padding},
+        border: EdgeSizes {left: 0.0, right: 0.0, ..border},
+        margin: EdgeSizes::default(),
+    };
+    fragment
+}
+
+// An inline box open on a line: its item, the x and width of its start piece if it starts
+// on the line, and its fragment so far.
+type OpenFragment<'a> = (usize, Option<(f32, f32)>, LayoutBox<'a>);
+
+// Sizes a fragment of an inline box to its content, between the x and width of its start and
+// end edges if they are on this line. Each edge goes on the side of the content it landed on
+// when the line was put in visual order.
+fn finish_fragment(fragment: &mut LayoutBox, start: Option<(f32, f32)>, end: Option<(f32, f32)>, containing_width: f32) {
+    let extent = fragment.children.iter().map(|child| child.dimensions.margin_box())
+        .map(|margin_box| (margin_box.x, margin_box.x + margin_box.width))
+        .reduce(|(left, right), (child_left, child_right)| (left.min(child_left), right.max(child_right)));
+    let style = fragment.style.clone();
+    let d = &mut fragment.dimensions;
+    let (mut left, mut right) = extent.unwrap_or((f32::NEG_INFINITY, f32::INFINITY));
+    for (edge, is_end) in [(start, false), (end, true)] {
+        let (x, width) = match edge {
+            Some(edge) => edge,
+            None => continue,
+        };
+        let (margin, border, padding) = inline_edge(&style, containing_width, is_end);
+        //an empty fragment's edges are in the order the line put them in, or if there's only
+        //one the side its direction puts it on
+        let on_left = match (extent, start.zip(end)) {
+            (Some((content_left, content_right)), _) => x + width / 2.0 <= (content_left + content_right) / 2.0,
+            (None, Some(((start_x, _), (end_x, _)))) if start_x != end_x => x == start_x.min(end_x),
+            (None, _) => is_rtl(&style) == is_end,
+        };
+        if on_left {
+            left = left.max(x + width);
+            d.margin.left = margin;
+            d.border.left = border;
+            d.padding.left = padding;
+        } else {
+            right = right.min(x);
+            d.margin.right = margin;
+            d.border.right = border;
+            d.padding.right = padding;
+        }
+    }
+    match (left.is_finite(), right.is_finite()) {
+        (false, false) => (left, right) = (0.0, 0.0),
+        (false, true) => left = right,
+        (true, false) => right = left,
+        (true, true) => {},
+    }
+    d.content.x = left;
+    d.content.width = (right - left).max(0.0);
+}
+
+// Adds a text run to the one just before it on the line, which holds the text of the item
+// before it, on whichever side of it the new one is.
+fn merge_runs(run: &mut LayoutBox, next: LayoutBox) {
+    let (width, next_width) = (run.dimensions.content.width, next.dimensions.content.width);
+    let on_left = next.dimensions.content.x < run.dimensions.content.x;
+    if let (BoxType::TextRun {text, glyphs, ..}, BoxType::TextRun {text: next_text, glyphs: next_glyphs, ..}) = (&mut run.box_type, next.box_type) {
+        let next_glyphs = next_glyphs.into_iter().map(|glyph| ShapedGlyph {cluster: glyph.cluster + text.len(), ..glyph});
+        if on_left {
+            for glyph in glyphs.iter_mut() {
+                glyph.x += next_width;
+            }
+            glyphs.splice(0..0, next_glyphs);
+        } else {
+            glyphs.extend(next_glyphs.map(|glyph| ShapedGlyph {x: glyph.x + width, ..glyph}));
+        }
+        text.push_str(&next_text);
+    }
+    run.dimensions.content.x = run.dimensions.content.x.min(next.dimensions.content.x);
+    run.dimensions.content.width += next_width;
+}
+
+// Adds a finished fragment to the innermost open inline box, or to the line itself.
+fn add_fragment<'a>(stack: &mut [OpenFragment<'a>], line_box: &mut LayoutBox<'a>, fragment: LayoutBox<'a>) {
+    match stack.last_mut() {
+        Some((_, _, parent)) => parent.children.push(fragment),
+        None => line_box.children.push(fragment),
+    }
+}
+
+// How far the boxes on a line reach above and below its baseline, and the heights of those
+// aligned with the top (true) or bottom (false) of the line, which are placed afterwards.
+struct LineExtents {
+    top: f32,
+    bottom: f32,
+    aligned: Vec<(bool, f32)>,
+}
+
+// How far above and below its baseline a fragment reaches: its inline box for inline boxes
+// and text, its margin box for atomic inlines.
+fn fragment_extents(fragment: &LayoutBox, fonts: &FontDatabase) -> (f32, f32) {
+    match fragment.box_type {
+        BoxType::InlineNode(_) | BoxType::TextRun {..} => inline_extents(&fragment.style, fonts),
+        _ => {
+            let baseline = atomic_baseline(fragment);
+            let margin_box = fragment.dimensions.margin_box();
+            (baseline - margin_box.y, margin_box.y + margin_box.height - baseline)
+        },
+    }
+}
+
+// The baseline of an inline-block is that of its last line box, or its bottom margin edge if it
+// has none, §10.8.1. An inline flex or grid container's is that of its first item, CSS Flexbox 1
+// §8.5 and CSS Grid 1 §9, and an inline table's that of its first row, §17.5.3.
+pub(super) fn atomic_baseline(layout_box: &LayoutBox) -> f32 {
+    let baseline = match layout_box.is_flex_container() || layout_box.is_grid_container() || layout_box.is_table() {
+        true => layout_box.first_baseline(),
+        false => last_baseline(layout_box),
+    };
+    baseline.unwrap_or_else(|| {
+        let margin_box = layout_box.dimensions.margin_box();
+        margin_box.y + margin_box.height
+    })
+}
+
+fn last_baseline(layout_box: &LayoutBox) -> Option<f32> {
+    layout_box.children.iter().rev().find_map(|child| match child.box_type {
+        BoxType::LineBox {baseline} => Some(baseline),
+        BoxType::BlockNode(_) | BoxType::AnonymousBlock => last_baseline(child),
+        _ => None,
+    })
+}
+
+fn vertical_align(style: &PropertyMap) -> Option<&Value> {
+    style.get("vertical-align")
+}
+
+fn aligns_to_line(style: &PropertyMap) -> Option<bool> {
+    match vertical_align(style) {
+        Some(Value::Keyword(keyword)) if keyword == "top" => Some(true),
+        Some(Value::Keyword(keyword)) if keyword == "bottom" => Some(false),
+        _ => None,
+    }
+}
+
+// How far below its parent's baseline vertical-align puts a box's baseline, given the
+// parent's font and how far the box reaches above and below its baseline. Not for boxes
+// aligned with the line box instead.
+fn baseline_shift(style: &PropertyMap, parent: FontMetrics, (above, below): (f32, f32), fonts: &FontDatabase) -> f32 {
+    match vertical_align(style) {
+        Some(Value::Keyword(keyword)) => match &**keyword {
+            "sub" => 0.2 * parent.size,
+            "super" => -0.34 * parent.size,
+            "text-top" => above - parent.ascent,
+            "text-bottom" => parent.descent - below,
+            //the middle of the box goes half an x-height above the parent's baseline
+            "middle" => -parent.x_height / 2.0 - (below - above) / 2.0,
+            _ => 0.0,
+        },
+        Some(Value::Length(len, Unit::Px)) => -len,
+        Some(Value::Percentage(percentage)) => -percentage / 100.0 * line_height(style, fonts),
+        _ => 0.0,
+    }
+}
+
+fn measure(fragment: &LayoutBox, parent_baseline: f32, parent: FontMetrics, extents: &mut LineExtents, fonts: &FontDatabase) {
+    if fragment.is_absolutely_positioned() {
+        return;
+    }
+    let (above, below) = fragment_extents(fragment, fonts);
+    if let Some(to_top) = aligns_to_line(&fragment.style) {
+        extents.aligned.push((to_top, above + below));
+        return;
+    }
+    let baseline = parent_baseline + baseline_shift(&fragment.style, parent, (above, below), fonts);
+    extents.top = extents.top.min(baseline - above);
+    extents.bottom = extents.bottom.max(baseline + below);
+    if let BoxType::InlineNode(_) = fragment.box_type {
+        let metrics = font_metrics(&fragment.style, fonts);
+        for child in &fragment.children {
+            measure(child, baseline, metrics, extents, fonts);
+        }
+    }
+}
+
+// Moves a fragment to where vertical-align puts it, given the top and bottom of the line.
+fn place(fragment: &mut LayoutBox, parent_baseline: f32, parent: FontMetrics, (line_top, line_bottom): (f32, f32), fonts: &FontDatabase) {
+    if fragment.is_absolutely_positioned() {
+        fragment.dimensions.content.y = line_top;
+        return;
+    }
+    let (above, below) = fragment_extents(fragment, fonts);
+    let baseline = match aligns_to_line(&fragment.style) {
+        Some(true) => line_top + above,
+        Some(false) => line_bottom - below,
+        None => parent_baseline + baseline_shift(&fragment.style, parent, (above, below), fonts),
+    };
+    match fragment.box_type {
+        //the content area is the font's ascent and descent, whatever the line-height
+        BoxType::InlineNode(_) | BoxType::TextRun {..} => {
+            let metrics = font_metrics(&fragment.style, fonts);
+            fragment.dimensions.content.y = baseline - metrics.ascent;
+            fragment.dimensions.content.height = metrics.ascent + metrics.descent;
+            for child in &mut fragment.children {
+                place(child, baseline, metrics, (line_top, line_bottom), fonts);
+            }
+        },
+        _ => {
+            let dy = baseline - atomic_baseline(fragment);
+            fragment.translate(0.0, dy);
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::content;
+    use super::super::tests::layout;
+    use super::super::tests::lines;
+    use super::super::Dimensions;
+
+    // The top left corner of the content box of the element with `id`. In the bundled font at
+    // 16px a space is 5.09px wide, a 9.8px, b and d 10.16px, c 8.8px, and lines 18.625px high.
+    fn corner(boxes: &[(String, Dimensions)], id: &str) -> (f32, f32) {
+        let rect = content(boxes, id);
+        (rect.x, rect.y)
+    }
+
+    #[test]
+    fn white_space_collapses_unless_preserved() {
+        let html = "<html><body><div id=d>a  b\n c <span id=s>d</span></div></body></html>";
+        let boxes = layout(html, "#d { width: 300px }");
+        assert_eq!(lines(&boxes).len(), 1);
+        assert_eq!(corner(&boxes, "s"), (44.015625, 0.0));
+
+        //the newline ends the line, and the spaces after it are kept or collapsed away
+        let boxes = layout(html, "#d { width: 300px; white-space: pre }");
+        assert_eq!(lines(&boxes).len(), 2);
+        assert_eq!(corner(&boxes, "s"), (18.96875, 18.625));
+        let boxes = layout(html, "#d { width: 300px; white-space: pre-line }");
+        assert_eq!(lines(&boxes).len(), 2);
+        assert_eq!(corner(&boxes, "s"), (13.8828125, 18.625));
+    }
+
+    #[test]
+    fn lines_wrap_unless_white_space_says_not_to() {
+        let html = "<html><body><div id=d>aaaa         bbbb <span id=s>cccc</span></div></body></html>";
+        let boxes = layout(html, "#d { width: 60px }");
+        assert_eq!(lines(&boxes).iter().map(|line| line.y).collect::<Vec<f32>>(), [0.0, 18.625, 37.25]);
+        assert_eq!(corner(&boxes, "s"), (0.0, 37.25));
+        //preserved spaces hang off the end of the line, wider than it as they are
+        let boxes = layout(html, "#d { width: 60px; white-space: pre-wrap }");
+        assert_eq!(corner(&boxes, "s"), (0.0, 37.25));
+        let boxes = layout(html, "#d { width: 60px; white-space: nowrap }");
+        assert_eq!(lines(&boxes).len(), 1);
+        assert_eq!(corner(&boxes, "s"), (90.015625, 0.0));
+    }
+
+    #[test]
+    fn text_align_places_the_content_of_a_line() {
+        let html = "<html><body><div id=d>ab <span id=s>cd</span></div></body></html>";
+        let x = |css: &str| corner(&layout(html, &format!("#d {{ width: 300px; {} }}", css)), "s").0;
+        assert_eq!(x(""), 25.046875);
+        assert_eq!(x("text-align: center"), 153.04688);
+        assert_eq!(x("text-align: right"), 281.04688);
+        assert_eq!(x("text-align: end"), 281.04688);
+
+        //justified lines are widened at their spaces, all but the last one
+        let html = "<html><body><div id=d>aa bb <span id=s>cc</span>, <span id=t>dddddddd</span></div></body></html>";
+        let boxes = layout(html, "#d { width: 100px }");
+        assert_eq!((corner(&boxes, "s"), corner(&boxes, "t")), ((50.09375, 0.0), (0.0, 18.625)));
+        let boxes = layout(html, "#d { width: 100px; text-align: justify }");
+        assert_eq!((corner(&boxes, "s"), corner(&boxes, "t")), ((77.32031, 0.0), (0.0, 18.625)));
+    }
+
+    #[test]
+    fn inline_boxes_dont_reorder_their_text() {
+        //left to right text in a right to left paragraph keeps its order across inline boxes
+        //that go right to left too but don't embed it
+        let html = "<html><body><div id=d><span id=a>aa</span>, <span id=b>bb</span></div></body></html>";
+        let boxes = layout(html, "#d { width: 100px; direction: rtl }");
+        assert_eq!((corner(&boxes, "a"), corner(&boxes, "b")), ((49.90625, 0.0), (79.6875, 0.0)));
+        let boxes = layout(html, "#d { width: 100px; direction: rtl } #a { padding-left: 10px; padding-right: 3px }");
+        assert_eq!((corner(&boxes, "a"), corner(&boxes, "b")), ((46.90625, 0.0), (79.6875, 0.0)));
+
+        //and the edges of a box stay on their sides when its text goes the other way
+        let html = "<html><body><div id=d>a <span id=s>אב</span> c</div></body></html>";
+        let boxes = layout(html, "#d { width: 100px } #s { padding-left: 10px; padding-right: 3px }");
+        assert_eq!(corner(&boxes, "s"), (24.890625, 0.0));
+    }
+
+    #[test]
+    fn vertical_align_moves_boxes_from_the_baseline() {
+        let html = "<html><body><div id=d><span id=a>ab</span> <span id=s>cd</span></div></body></html>";
+        let aligned = |css: &str| {
+            let boxes = layout(html, &format!("#d {{ width: 300px }} {}", css));
+            (lines(&boxes)[0].height, content(&boxes, "a").y, content(&boxes, "s").y)
+        };
+        //the line grows to fit the shifted box
+        assert_eq!(aligned(""), (18.625, 0.0, 0.0));
+        assert_eq!(aligned("#s { vertical-align: super }"), (24.065, 5.4400005, 0.0));
+        assert_eq!(aligned("#s { vertical-align: sub }"), (21.825, 0.0, 3.2000008));
+        assert_eq!(aligned("#s { vertical-align: -4px }"), (22.625, 0.0, 4.0));
+
+        //beside a larger font the smaller text sits on the same baseline, or at the top, the
+        //bottom or the middle of the line
+        assert_eq!(aligned("#s { font-size: 32px }"), (37.25, 14.8515625, 0.0));
+        assert_eq!(aligned("#s { font-size: 32px } #a { vertical-align: top }"), (37.25, 0.0, 0.0));
+        assert_eq!(aligned("#s { font-size: 32px } #a { vertical-align: bottom }"), (37.25, 18.625, 0.0));
+        assert_eq!(aligned("#s { font-size: 32px } #a { vertical-align: middle }"), (37.25, 16.390625, 0.0));
+    }
+}
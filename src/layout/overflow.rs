@@ -0,0 +1,302 @@
+// Overflow, CSS Overflow 3: boxes that cut off the content that falls outside their padding
+// box, scroll containers whose content can be scrolled to show the rest of it, and the
+// ellipsis text-overflow puts at the end of a line that doesn't fit.
+
+use super::inline::font_metrics;
+use super::inline::shape;
+use super::inherited_values;
+use super::keyword;
+use super::BoxType;
+use super::LayoutBox;
+use super::Rect;
+use super::ScrollOffsets;
+use crate::font::FontDatabase;
+use crate::font::ShapedGlyph;
+use crate::style::StyledNode;
+
+// How far out the clip rect of a box reaches along an axis it doesn't clip, which is past
+// anything on a page.
+const UNCLIPPED: f32 = 1.0e9;
+
+const ELLIPSIS: &str = "\u{2026}";
+
+impl<'a> LayoutBox<'a> {
+    // Whether the box clips its content horizontally and vertically, which any overflow but
+    // visible does in a block container, §3.
+    pub fn clips_overflow(&self) -> (bool, bool) {
+        if !self.is_block_container() || self.is_table() {
+            return (false, false);
+        }
+        let clips = |name| !matches!(keyword(&self.style, name), "visible" | "");
+        (clips("overflow-x"), clips("overflow-y"))
+    }
+
+    // Whether the box is a scroll container, whose content can be scrolled within its padding
+    // box. With hidden only the host can scroll it, and clip doesn't let it scroll at all.
+    pub fn is_scroll_container(&self) -> bool {
+        let scrolls = |name| matches!(keyword(&self.style, name), "hidden" | "scroll" | "auto");
+        self.clips_overflow() != (false, false) && (scrolls("overflow-x") || scrolls("overflow-y"))
+    }
+
+    // The rect the box clips its descendants to, in document coordinates: its padding box
+    // along the axes it clips, and no limit along the others. None if it clips neither.
+    pub fn overflow_clip_rect(&self) -> Option<Rect> {
+        let (clip_x, clip_y) = self.clips_overflow();
+        if !clip_x && !clip_y {
+            return None;
+        }
+        let padding_box = self.dimensions.padding_box();
+        let (x, width) = if clip_x { (padding_box.x, padding_box.width) } else { (-UNCLIPPED, 2.0 * UNCLIPPED) };
+        let (y, height) = if clip_y { (padding_box.y, padding_box.height) } else { (-UNCLIPPED, 2.0 * UNCLIPPED) };
+        Some(Rect {x, y, width, height})
+    }
+
+    // The scrollable overflow rect of the box, §2.2, unscrolled: its padding box, grown to
+    // take in the boxes inside it and what overflows those that don't clip it, with the end
+    // padding after its content. Fixed boxes belong to the viewport rather than the boxes
+    // they're in.
+    pub fn scrollable_overflow(&self) -> Rect {
+        let padding_box = self.dimensions.padding_box();
+        let mut content: Option<Rect> = None;
+        for child in self.children.iter().filter(|child| keyword(&child.style, "position") != "fixed") {
+            let border_box = child.dimensions.border_box();
+            let inner = child.scrollable_overflow();
+            let (clip_x, clip_y) = child.clips_overflow();
+            let (left, right) = match clip_x {
+                true => (border_box.x, right(border_box)),
+                false => (border_box.x.min(inner.x), right(border_box).max(right(inner))),
+            };
+            let (top, bottom) = match clip_y {
+                true => (border_box.y, bottom(border_box)),
+                false => (border_box.y.min(inner.y), bottom(border_box).max(bottom(inner))),
+            };
+            let contribution = Rect {x: left, y: top, width: right - left, height: bottom - top};
+            content = Some(content.map_or(contribution, |content| union(content, contribution)));
+        }
+        match content {
+            Some(content) => {
+                let padding = self.dimensions.padding;
+                union(padding_box, Rect {width: content.width + padding.right, height: content.height + padding.bottom, ..content})
+            },
+            None => padding_box,
+        }
+    }
+
+    // Sets the scroll offset of a scroll container to the one `scroll` has for its element,
+    // kept within its scrollable overflow so that it can't scroll past the end of it.
+    pub(super) fn scroll_to(&mut self, scroll: &ScrollOffsets) {
+        let node = match self.box_type {
+            BoxType::BlockNode(node) | BoxType::InlineBlockNode(node) if node.pseudo_element.is_none() => node.node,
+            _ => return,
+        };
+        let (x, y) = scroll.get(node);
+        let padding_box = self.dimensions.padding_box();
+        let overflow = self.scrollable_overflow();
+        self.scroll_offset = (
+            x.clamp(0.0, (right(overflow) - right(padding_box)).max(0.0)),
+            y.clamp(0.0, (bottom(overflow) - bottom(padding_box)).max(0.0)),
+        );
+    }
+
+    // With text-overflow: ellipsis, cuts a line of a box that clips it short where its content
+    // would overflow the content box, and puts an ellipsis in the box's font after what is
+    // left, CSS Overflow 3 §4.1. Atomic inlines that don't fit are left out whole, and lines
+    // only get an ellipsis at their right end, in left to right text.
+    pub(super) fn apply_text_overflow(&self, line_box: &mut LayoutBox<'a>, fonts: &FontDatabase) {
+        if keyword(&self.style, "text-overflow") != "ellipsis" || !self.clips_overflow().0 || keyword(&self.style, "direction") == "rtl" {
+            return;
+        }
+        let content = self.dimensions.content;
+        if extent(line_box).is_none_or(|line_right| line_right <= right(content)) {
+            return;
+        }
+        let ellipsis = shape(ELLIPSIS, &self.style, false, fonts);
+        let limit = right(content) - ellipsis.width;
+        let cut_node = last_text_node(&line_box.children);
+        truncate(&mut line_box.children, limit);
+
+        //the ellipsis goes after what's left, as a run of text of the last text node left, or
+        //of one it cut if none is
+        let node = match last_text_node(&line_box.children).or(cut_node) {
+            Some(node) => node,
+            None => return,
+        };
+        let metrics = font_metrics(&self.style, fonts);
+        let baseline = match line_box.box_type {
+            BoxType::LineBox {baseline} => baseline,
+            _ => line_box.dimensions.content.y + metrics.ascent,
+        };
+        //right after what's left, which all ends inside the limit
+        let x = extent(line_box).unwrap_or(line_box.dimensions.content.x);
+        let mut run = LayoutBox::new(BoxType::TextRun {node, text: ELLIPSIS.to_string(), justification: 0.0, glyphs: ellipsis.glyphs}, inherited_values(&self.style));
+        run.dimensions.content = Rect {x, y: baseline - metrics.ascent, width: ellipsis.width, height: metrics.ascent + metrics.descent};
+        line_box.children.push(run);
+    }
+}
+
+fn right(rect: Rect) -> f32 {
+    rect.x + rect.width
+}
+
+fn bottom(rect: Rect) -> f32 {
+    rect.y + rect.height
+}
+
+fn union(a: Rect, b: Rect) -> Rect {
+    let (left, top) = (a.x.min(b.x), a.y.min(b.y));
+    let (right, bottom) = (right(a).max(right(b)), bottom(a).max(bottom(b)));
+    Rect {x: left, y: top, width: right - left, height: bottom - top}
+}
+
+// How far right the fragments on a line reach, None if there are none.
+fn extent(line_box: &LayoutBox) -> Option<f32> {
+    line_box.children.iter().filter(|fragment| !fragment.is_absolutely_positioned()).map(|fragment| {
+        let own = right(fragment.dimensions.margin_box());
+        match fragment.box_type {
+            BoxType::InlineNode(_) => extent(fragment).map_or(own, |inner| own.max(inner)),
+            _ => own,
+        }
+    }).reduce(f32::max)
+}
+
+// Takes what reaches past `limit` off the fragments of a line, going left to right: the
+// clusters of a text run from the first one that ends past it, an atomic inline that does as
+// a whole, and everything after that. Inline boxes shrink to what is left of their content,
+// keeping their end padding, border and margin inside the limit too, and go if nothing is
+// left. Returns whether anything was taken off.
+fn truncate(fragments: &mut Vec<LayoutBox>, limit: f32) -> bool {
+    let mut cut = false;
+    fragments.retain_mut(|fragment| {
+        let d = fragment.dimensions;
+        if fragment.is_absolutely_positioned() {
+            return true;
+        }
+        if cut {
+            return false;
+        }
+        if let BoxType::InlineNode(_) = fragment.box_type {
+            if extent(fragment).map_or(right(d.margin_box()), |inner| inner.max(right(d.margin_box()))) > limit {
+                let end = d.padding.right + d.border.right + d.margin.right;
+                cut = truncate(&mut fragment.children, limit - end) || right(d.margin_box()) > limit;
+                if !fragment.children.iter().any(|child| !child.is_absolutely_positioned()) {
+                    return false;
+                }
+                let inner = extent(fragment).unwrap_or(d.content.x);
+                fragment.dimensions.content.width = (inner - d.content.x).clamp(0.0, d.content.width);
+            }
+            return true;
+        }
+        let (text, glyphs) = match &mut fragment.box_type {
+            BoxType::TextRun {text, glyphs, ..} => (text, glyphs),
+            _ => {
+                cut = right(d.margin_box()) > limit;
+                return !cut;
+            },
+        };
+        if right(d.content) <= limit {
+            return true;
+        }
+        cut = true;
+        let first_cut = match glyphs.iter().position(|glyph| d.content.x + glyph.x + glyph.advance > limit) {
+            Some(first_cut) => first_cut,
+            None => return false,
+        };
+        //the whole cluster goes, along with the glyphs of it before the one that doesn't fit
+        let cut_cluster = glyphs[first_cut].cluster;
+        let kept: Vec<ShapedGlyph> = glyphs[..first_cut].iter().filter(|glyph| glyph.cluster != cut_cluster).cloned().collect();
+        if kept.is_empty() {
+            return false;
+        }
+        //the glyphs of right to left text run from the end of the text to its start
+        let kept_start = kept.iter().map(|glyph| glyph.cluster).min().unwrap_or(0);
+        *text = match kept_start > cut_cluster {
+            true => text[kept_start..].to_string(),
+            false => text[..cut_cluster].to_string(),
+        };
+        fragment.dimensions.content.width = kept.iter().map(|glyph| glyph.x + glyph.advance).fold(0.0, f32::max);
+        *glyphs = kept;
+        true
+    });
+    cut
+}
+
+// The node of the last text run on a line, where an ellipsis after it takes its place.
+fn last_text_node<'a>(fragments: &[LayoutBox<'a>]) -> Option<&'a StyledNode<'a>> {
+    fragments.iter().rev().find_map(|fragment| match fragment.box_type {
+        BoxType::TextRun {node, ..} => Some(node),
+        BoxType::InlineNode(_) => last_text_node(&fragment.children),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::layout_tree;
+    use super::*;
+    use crate::css_parser;
+    use crate::html_parser;
+    use crate::image::ImageStore;
+    use crate::media::MediaEnvironment;
+    use crate::style;
+
+    // The text runs on the first line of #box, which cuts off `html` with an ellipsis, as
+    // (text, x, width), and the right of its content box.
+    fn ellipsized(html: &str, css: &str) -> (Vec<(String, f32, f32)>, f32) {
+        let root = html_parser::parse(format!("<html><body><div id=box>{}</div></body></html>", html));
+        let stylesheet = css_parser::parse(format!("html, body, div {{ display: block }}
+            #box {{ overflow: hidden; white-space: nowrap; text-overflow: ellipsis }} {}", css));
+        let cascade = style::Cascade::new(&stylesheet, MediaEnvironment::default());
+        let styled = style::style_tree(&root, &cascade);
+        let root_box = layout_tree(&styled, 800.0, 600.0, &FontDatabase::new(), &ImageStore::new());
+
+        fn runs(fragments: &[LayoutBox], out: &mut Vec<(String, f32, f32)>) {
+            for fragment in fragments {
+                match &fragment.box_type {
+                    BoxType::TextRun {text, ..} => out.push((text.clone(), fragment.dimensions.content.x, fragment.dimensions.content.width)),
+                    _ => runs(&fragment.children, out),
+                }
+            }
+        }
+        let div = &root_box.children[0].children[0];
+        let mut out = vec![];
+        runs(&div.children[0].children, &mut out);
+        (out, right(div.dimensions.content))
+    }
+
+    #[test]
+    fn ellipsis_fits_in_the_box() {
+        let (runs, right) = ellipsized("This is a long line of text", "#box { width: 100px }");
+        assert_eq!(runs.iter().map(|(text, _, _)| &**text).collect::<Vec<&str>>(), ["This is a l", ELLIPSIS]);
+        let (_, text_x, text_width) = runs[0];
+        let (_, x, width) = runs[1];
+        assert_eq!(x, text_x + text_width);
+        assert!(x + width <= right);
+
+        //the next character, "o", wouldn't have fit with it
+        let fonts = FontDatabase::new();
+        let query = crate::font::FontQuery::from_style(&Default::default());
+        assert!(fonts.shape("This is a lo", &query, 16.0).width + width > right);
+    }
+
+    #[test]
+    fn ellipsis_never_overflows() {
+        let html = "This is a <span>long line</span> of text that overflows";
+        for css in ["", "#box { padding-left: 10px }", "span { padding: 0 7px; margin-right: 3px }", "span { font-size: 20px }"] {
+            for width in (20..200).step_by(3) {
+                let (runs, right) = ellipsized(html, &format!("#box {{ width: {}px }} {}", width, css));
+                let (ellipsis, x, ellipsis_width) = runs.last().unwrap();
+                assert_eq!(ellipsis, ELLIPSIS);
+                assert!(x + ellipsis_width <= right, "{} wide with {}: {:?}", width, css, runs);
+                //after the text that's left
+                let text_right = runs[..runs.len() - 1].iter().map(|&(_, x, width)| x + width).fold(0.0, f32::max);
+                assert!(*x >= text_right, "{} wide with {}: {:?}", width, css, runs);
+            }
+        }
+    }
+
+    #[test]
+    fn inline_box_left_empty_goes() {
+        let (runs, _) = ellipsized("This is a <span>long line</span> of text", "#box { width: 30px }");
+        assert_eq!(runs, [("T".to_string(), 0.0, runs[0].2), (ELLIPSIS.to_string(), runs[0].2, 16.0)]);
+    }
+}
@@ -0,0 +1,320 @@
+use super::keyword;
+use super::to_px;
+use super::Dimensions;
+use super::LayoutBox;
+use super::Rect;
+use super::ScrollOffsets;
+use crate::css::Unit;
+use crate::css::Value;
+use crate::font::FontDatabase;
+
+// The rects positioned boxes are placed against, in document coordinates.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct ContainingBlocks {
+    // the content box of the nearest block container, which relative offsets refer to
+    pub(super) block: Rect,
+    // the padding box of the nearest positioned ancestor, or the initial containing block
+    pub(super) absolute: Rect,
+    // the viewport, where it is scrolled to
+    pub(super) fixed: Rect,
+    // the padding box of the nearest scroll container, or the viewport, which sticky boxes
+    // stick inside of
+    pub(super) scrollport: Rect,
+}
+
+// What an absolutely positioned box asks for along one axis, None for auto: how far in from
+// the start and end edges of the containing block its margin box goes, its content size and
+// its margins.
+struct AxisConstraints {
+    start: Option<f32>,
+    size: Option<f32>,
+    end: Option<f32>,
+    margin_start: Option<f32>,
+    margin_end: Option<f32>,
+}
+
+// CSS 2.1 §10.3.7 and §10.6.4, the offset of an absolutely positioned box's margin box from the
+// start of its containing block, its content size and its margins along one axis. `edges` are
+// its borders and padding and `static_start` is where it would have started in flow.
+// `fit_content` is the size the content wants given the room there is, shrink-to-fit across
+// and the height of the content down.
+fn solve_absolute(axis: AxisConstraints, edges: f32, containing: f32, static_start: f32, fit_content: &dyn Fn(f32) -> f32) -> (f32, f32, f32, f32) {
+    let AxisConstraints {start, size, end, margin_start, margin_end} = axis;
+    let margins = margin_start.unwrap_or(0.0) + margin_end.unwrap_or(0.0);
+    let (margin_start, margin_end) = match (start, size, end) {
+        //with the insets and size all set, auto margins take up the rest, evenly if they can
+        (Some(start), Some(size), Some(end)) => {
+            let free = containing - start - margins - edges - size - end;
+            match (margin_start, margin_end) {
+                (None, None) if free >= 0.0 => (free / 2.0, free / 2.0),
+                (None, None) => (0.0, free),
+                (None, Some(margin_end)) => (free, margin_end),
+                (Some(margin_start), None) => (margin_start, free),
+                //overconstrained, the end inset gives way
+                (Some(margin_start), Some(margin_end)) => (margin_start, margin_end),
+            }
+        },
+        _ => (margin_start.unwrap_or(0.0), margin_end.unwrap_or(0.0)),
+    };
+    let margins = margin_start + margin_end;
+    let (start, size) = match (start, size, end) {
+        (Some(start), Some(size), _) => (start, size),
+        //without insets it stays where it would have been in flow
+        (None, size, None) => {
+            let size = size.unwrap_or_else(|| fit_content(containing - static_start - margins - edges));
+            (static_start, size)
+        },
+        (None, size, Some(end)) => {
+            let size = size.unwrap_or_else(|| fit_content(containing - end - margins - edges));
+            (containing - end - margins - edges - size, size)
+        },
+        (Some(start), None, None) => (start, fit_content(containing - start - margins - edges)),
+        (Some(start), None, Some(end)) => (start, (containing - start - margins - edges - end).max(0.0)),
+    };
+    (start, size, margin_start, margin_end)
+}
+
+impl<'a> LayoutBox<'a> {
+    // Whether the box is taken out of flow and placed against its containing block instead.
+    pub(super) fn is_absolutely_positioned(&self) -> bool {
+        matches!(keyword(&self.style, "position"), "absolute" | "fixed")
+    }
+
+    // Whether the box is positioned, which makes its padding box the containing block of the
+    // absolutely positioned boxes in it, CSS 2.1 §10.1.
+    fn is_positioned(&self) -> bool {
+        !matches!(keyword(&self.style, "position"), "static" | "")
+    }
+
+    // Marks where an absolutely positioned box would have gone in flow, the top left of its
+    // margin box, for it to fall back on when it has no insets.
+    pub(super) fn set_static_position(&mut self, x: f32, y: f32) {
+        self.dimensions = Dimensions {content: Rect {x, y, width: 0.0, height: 0.0}, ..Default::default()};
+    }
+
+    // Puts the positioned boxes in the box and in its descendants where they go now that the
+    // rest is laid out: relatively positioned and sticky boxes are offset from where they are,
+    // absolutely positioned ones are laid out in their containing block. Each box is done
+    // before the ones inside it, whose containing blocks it may be. Scroll containers are
+    // scrolled to where `scroll` has them, as far as their content lets them be.
+    pub(super) fn layout_positioned(&mut self, containing_blocks: ContainingBlocks, scroll: &ScrollOffsets, fonts: &FontDatabase) {
+        match keyword(&self.style, "position") {
+            "absolute" => self.layout_absolute(containing_blocks.absolute, fonts),
+            "fixed" => self.layout_absolute(containing_blocks.fixed, fonts),
+            "relative" => {
+                let (dx, dy) = self.relative_offset(containing_blocks.block);
+                self.translate(dx, dy);
+            },
+            "sticky" => {
+                let (dx, dy) = self.sticky_offset(containing_blocks.block, containing_blocks.scrollport);
+                self.translate(dx, dy);
+            },
+            _ => {},
+        }
+
+        let mut inner = containing_blocks;
+        if self.is_block_container() {
+            inner.block = self.dimensions.content;
+        }
+        if self.is_positioned() {
+            inner.absolute = self.dimensions.padding_box();
+        }
+        if self.is_scroll_container() {
+            self.scroll_to(scroll);
+            let (x, y) = self.scroll_offset;
+            let padding_box = self.dimensions.padding_box();
+            inner.scrollport = Rect {x: padding_box.x + x, y: padding_box.y + y, ..padding_box};
+        }
+        for child in &mut self.children {
+            child.layout_positioned(inner, scroll, fonts);
+        }
+    }
+
+    // How far left, right, top and bottom move a relatively positioned box, §9.4.3. When both
+    // of a pair are set, left wins in left to right text and right in right to left, and top
+    // always wins.
+    fn relative_offset(&self, containing_block: Rect) -> (f32, f32) {
+        let inset = |name, reference| to_px(self.style.get(name), reference);
+        let (left, right) = (inset("left", containing_block.width), inset("right", containing_block.width));
+        let dx = match (left, right) {
+            (Some(_), Some(right)) if keyword(&self.style, "direction") == "rtl" => -right,
+            (Some(left), _) => left,
+            (None, Some(right)) => -right,
+            (None, None) => 0.0,
+        };
+        let dy = match (inset("top", containing_block.height), inset("bottom", containing_block.height)) {
+            (Some(top), _) => top,
+            (None, Some(bottom)) => -bottom,
+            (None, None) => 0.0,
+        };
+        (dx, dy)
+    }
+
+    // How far a sticky box moves to keep its border box within its insets of the scrollport,
+    // without its margin box leaving its containing block, CSS Positioned Layout 3 §3.4.
+    fn sticky_offset(&self, containing_block: Rect, scrollport: Rect) -> (f32, f32) {
+        let inset = |name, reference| to_px(self.style.get(name), reference);
+        let border_box = self.dimensions.border_box();
+        let margin_box = self.dimensions.margin_box();
+        let axis = |start: Option<f32>, end: Option<f32>, [border_start, border_size]: [f32; 2], [margin_start, margin_size]: [f32; 2], [block_start, block_size]: [f32; 2], [port_start, port_size]: [f32; 2]| {
+            //it moves forward to stay clear of the start of the scrollport, as far as the end
+            //of the containing block lets it
+            let forward = start.map_or(0.0, |start| {
+                let room = block_start + block_size - (margin_start + margin_size);
+                (port_start + start - border_start).min(room).max(0.0)
+            });
+            if forward > 0.0 {
+                return forward;
+            }
+            let backward = end.map_or(0.0, |end| {
+                let room = margin_start - block_start;
+                (border_start + border_size - (port_start + port_size - end)).min(room).max(0.0)
+            });
+            -backward
+        };
+        let dx = axis(
+            inset("left", scrollport.width),
+            inset("right", scrollport.width),
+            [border_box.x, border_box.width],
+            [margin_box.x, margin_box.width],
+            [containing_block.x, containing_block.width],
+            [scrollport.x, scrollport.width],
+        );
+        let dy = axis(
+            inset("top", scrollport.height),
+            inset("bottom", scrollport.height),
+            [border_box.y, border_box.height],
+            [margin_box.y, margin_box.height],
+            [containing_block.y, containing_block.height],
+            [scrollport.y, scrollport.height],
+        );
+        (dx, dy)
+    }
+
+    // Lays out an absolutely positioned box in `containing_block`, sized and placed by its insets,
+    // or failing those where it would have been in flow.
+    fn layout_absolute(&mut self, containing_block: Rect, fonts: &FontDatabase) {
+        let static_x = self.dimensions.content.x - containing_block.x;
+        let static_y = self.dimensions.content.y - containing_block.y;
+        let unlaid = self.clone();
+
+        //across first, §10.3.7, kept between min-width and max-width like any other box
+        let width = containing_block.width;
+        let size = |name: &str| to_px(self.style.get(name), width);
+        let edges = ["border-left-width", "padding-left", "padding-right", "border-right-width"].iter().filter_map(|name| size(name)).sum::<f32>();
+        let content_size = |name| size(name).map(|size| if self.border_box_sizing() { (size - edges).max(0.0) } else { size });
+        let shrink_to_fit = |available: f32| {
+            let (min_content, max_content) = self.intrinsic_widths(fonts);
+            min_content.max(available).min(max_content)
+        };
+        let solve = |size: Option<f32>| {
+            let axis = AxisConstraints {
+                start: to_px(self.style.get("left"), width),
+                size,
+                end: to_px(self.style.get("right"), width),
+                margin_start: to_px(self.style.get("margin-left"), width),
+                margin_end: to_px(self.style.get("margin-right"), width),
+            };
+            solve_absolute(axis, edges, width, static_x, &shrink_to_fit)
+        };
+        //a replaced box's auto width comes from its image, §10.3.8
+        let specified_width = match self.is_replaced() {
+            true => Some(self.replaced_size(Some(width), Some(containing_block.height)).0),
+            false => content_size("width"),
+        };
+        let (mut left, mut content_width, mut margin_left, mut margin_right) = solve(specified_width);
+        if let Some(max_width) = content_size("max-width").filter(|max_width| content_width > *max_width) {
+            (left, content_width, margin_left, margin_right) = solve(Some(max_width));
+        }
+        if let Some(min_width) = content_size("min-width").filter(|min_width| content_width < *min_width) {
+            (left, content_width, margin_left, margin_right) = solve(Some(min_width));
+        }
+
+        //then down, §10.6.4, where an auto height is that of the content
+        let px = |size: f32| Value::Length(size, Unit::Px);
+        let mut overrides = vec![
+            ("box-sizing", Value::Keyword("content-box".to_string())),
+            ("width", px(content_width)),
+            ("margin-left", px(margin_left)),
+            ("margin-right", px(margin_right)),
+        ];
+        self.layout_overriding(&overrides, width, Some(containing_block.height), fonts);
+        let (height, min_height, max_height) = self.specified_heights(Some(containing_block.height));
+        let d = self.dimensions;
+        let content_height = d.content.height;
+        let axis = AxisConstraints {
+            start: to_px(self.style.get("top"), containing_block.height),
+            size: height.map(|height| max_height.map_or(height, |max| height.min(max)).max(min_height)),
+            end: to_px(self.style.get("bottom"), containing_block.height),
+            margin_start: to_px(self.style.get("margin-top"), width),
+            margin_end: to_px(self.style.get("margin-bottom"), width),
+        };
+        let edges = d.border.top + d.padding.top + d.padding.bottom + d.border.bottom;
+        let (top, content_height, margin_top, margin_bottom) = solve_absolute(axis, edges, containing_block.height, static_y, &|_| content_height);
+        if content_height != d.content.height || margin_top != d.margin.top {
+            *self = unlaid;
+            overrides.push(("height", px(content_height)));
+            overrides.push(("margin-top", px(margin_top)));
+            self.layout_overriding(&overrides, width, Some(containing_block.height), fonts);
+        }
+
+        let d = &mut self.dimensions;
+        d.margin.right = margin_right;
+        d.margin.bottom = margin_bottom;
+        let margin_box = d.margin_box();
+        self.translate(containing_block.x + left - margin_box.x, containing_block.y + top - margin_box.y);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::content;
+    use super::super::tests::layout;
+    use super::super::tests::layout_scrolled;
+
+    const NESTED: &str = "<html><body><div id=outer><div id=inner><div id=abs></div></div></div></body></html>";
+
+    #[test]
+    fn absolute_boxes_are_placed_in_the_nearest_positioned_ancestor() {
+        let css = "#outer { margin: 40px 0 0 30px; padding: 5px } #inner { padding: 4px; height: 100px }
+            #abs { position: absolute; left: 10px; top: 20px; width: 50px; height: 50px }";
+        //the initial containing block, which is where the viewport is
+        let boxes = layout(NESTED, css);
+        assert_eq!((content(&boxes, "abs").x, content(&boxes, "abs").y), (10.0, 20.0));
+        let boxes = layout(NESTED, &format!("{} #abs {{ left: auto; top: auto; right: 0; bottom: 0 }}", css));
+        assert_eq!((content(&boxes, "abs").x, content(&boxes, "abs").y), (750.0, 550.0));
+
+        //the padding box of a positioned ancestor, but not for a fixed box
+        let boxes = layout(NESTED, &format!("{} #inner {{ position: relative }}", css));
+        assert_eq!((content(&boxes, "abs").x, content(&boxes, "abs").y), (45.0, 65.0));
+        let boxes = layout(NESTED, &format!("{} #inner {{ position: relative }} #abs {{ position: fixed }}", css));
+        assert_eq!((content(&boxes, "abs").x, content(&boxes, "abs").y), (10.0, 20.0));
+    }
+
+    #[test]
+    fn auto_width_fills_between_left_and_right() {
+        let css = "#outer { margin: 40px 0 0 30px; padding: 5px } #inner { position: relative; padding: 4px; height: 100px }
+            #abs { position: absolute; left: 10px; right: 30px; height: 10px }";
+        let boxes = layout(NESTED, css);
+        assert_eq!((content(&boxes, "abs").x, content(&boxes, "abs").width), (45.0, 720.0));
+        //with a width, auto margins center it instead
+        let boxes = layout(NESTED, &format!("{} #abs {{ width: 100px; margin: 0 auto }}", css));
+        assert_eq!((content(&boxes, "abs").x, content(&boxes, "abs").width), (355.0, 100.0));
+        //and without either, it's as wide as its content
+        let boxes = layout(NESTED, &format!("{} #abs {{ right: auto }}", css));
+        assert_eq!((content(&boxes, "abs").x, content(&boxes, "abs").width), (45.0, 0.0));
+    }
+
+    #[test]
+    fn sticky_boxes_stay_in_their_containing_block() {
+        let html = "<html><body><div id=box><div id=sticky></div></div><div id=after></div></body></html>";
+        let css = "#box { margin-top: 100px; height: 300px } #sticky { position: sticky; top: 10px; height: 50px } #after { height: 2000px }";
+        let y = |scroll_y: f32| content(&layout_scrolled(html, css, (0.0, scroll_y)), "sticky").y;
+        assert_eq!(y(0.0), 100.0);
+        //10px below the top of the viewport
+        assert_eq!(y(150.0), 160.0);
+        //until it reaches the bottom of #box
+        assert_eq!(y(340.0), 350.0);
+        assert_eq!(y(1000.0), 350.0);
+    }
+}
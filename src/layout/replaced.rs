@@ -0,0 +1,137 @@
+// Replaced elements, CSS 2.1 §10.3.2 and §10.6.2: boxes like <img> whose content is an image
+// from outside the document, which has a natural size and ratio of its own. They are sized from
+// those where the style leaves width or height auto, and the image is fitted into the content
+// box by object-fit and object-position, CSS Images 3 §5.
+
+use super::to_px;
+use super::LayoutBox;
+use super::Rect;
+use crate::css::Unit;
+use crate::css::Value;
+
+impl LayoutBox<'_> {
+    pub(super) fn is_replaced(&self) -> bool {
+        self.image.is_some()
+    }
+
+    // The content width and height of a replaced box in a containing block of this size, where
+    // a percentage of a size that isn't given counts as auto. An auto size follows the other
+    // one by the ratio, or is the natural size if both are auto, and then min and max sizes are
+    // applied keeping to the ratio as far as they can, §10.4.
+    pub(super) fn replaced_size(&self, containing_width: Option<f32>, containing_height: Option<f32>) -> (f32, f32) {
+        let (natural_width, natural_height) = match &self.image {
+            Some(image) => (image.width as f32, image.height as f32),
+            None => (0.0, 0.0),
+        };
+        let ratio = self.preferred_ratio().unwrap_or(natural_width / natural_height);
+
+        //horizontal padding is a percentage of the width too, as is vertical padding
+        let edge = |name: &str| to_px(self.style.get(name), containing_width.unwrap_or(0.0)).unwrap_or(0.0);
+        let horizontal_edges = ["border-left-width", "padding-left", "padding-right", "border-right-width"].iter().map(|name| edge(name)).sum::<f32>();
+        let vertical_edges = ["border-top-width", "padding-top", "padding-bottom", "border-bottom-width"].iter().map(|name| edge(name)).sum::<f32>();
+        let size = |name: &str, reference: Option<f32>, edges: f32| {
+            let size = match self.style.get(name) {
+                Some(Value::Percentage(percentage)) => reference.map(|reference| percentage / 100.0 * reference),
+                value => to_px(value, 0.0),
+            };
+            size.map(|size| if self.border_box_sizing() { (size - edges).max(0.0) } else { size })
+        };
+        let width = size("width", containing_width, horizontal_edges);
+        let height = size("height", containing_height, vertical_edges);
+        let min_width = size("min-width", containing_width, horizontal_edges).unwrap_or(0.0);
+        let min_height = size("min-height", containing_height, vertical_edges).unwrap_or(0.0);
+        let max_width = size("max-width", containing_width, horizontal_edges).unwrap_or(f32::INFINITY).max(min_width);
+        let max_height = size("max-height", containing_height, vertical_edges).unwrap_or(f32::INFINITY).max(min_height);
+
+        let (width, height) = match (width, height) {
+            //with both sizes given there is no ratio to keep
+            (Some(width), Some(height)) => return (width.clamp(min_width, max_width), height.clamp(min_height, max_height)),
+            (Some(width), None) => (width, width / ratio),
+            (None, Some(height)) => (height * ratio, height),
+            (None, None) if self.preferred_ratio().is_some() => (natural_width, natural_width / ratio),
+            (None, None) => (natural_width, natural_height),
+        };
+        if !(width > 0.0 && height > 0.0 && width.is_finite() && height.is_finite()) {
+            return (width.clamp(min_width, max_width), height.clamp(min_height, max_height));
+        }
+
+        //the table of constraint violations in §10.4
+        match (width > max_width, width < min_width, height > max_height, height < min_height) {
+            (true, _, true, _) if max_width / width <= max_height / height => (max_width, min_height.max(max_width * height / width)),
+            (true, _, true, _) => (min_width.max(max_height * width / height), max_height),
+            (_, true, _, true) if min_width / width <= min_height / height => (max_width.min(min_height * width / height), min_height),
+            (_, true, _, true) => (min_width, max_height.min(min_width * height / width)),
+            (_, true, true, _) => (min_width, max_height),
+            (true, _, _, true) => (max_width, min_height),
+            (true, ..) => (max_width, min_height.max(max_width * height / width)),
+            (_, true, ..) => (min_width, max_height.min(min_width * height / width)),
+            (_, _, true, _) => (min_width.max(max_height * width / height), max_height),
+            (_, _, _, true) => (max_width.min(min_height * width / height), min_height),
+            _ => (width, height),
+        }
+    }
+
+    // The ratio aspect-ratio asks for, unless it is auto or lets the natural ratio go first,
+    // which an image always has, CSS Sizing 4 §7.1.
+    fn preferred_ratio(&self) -> Option<f32> {
+        let components = self.style.get("aspect-ratio")?.components();
+        let is_auto = |component: &Value| matches!(component, Value::Keyword(keyword) if keyword == "auto");
+        if components.iter().any(is_auto) {
+            return None;
+        }
+        match components {
+            [Value::Length(width, Unit::None)] => Some(*width),
+            [Value::Length(width, Unit::None), Value::Slash, Value::Length(height, Unit::None)] => Some(width / height),
+            _ => None,
+        }
+    }
+
+    // Where the image of a replaced box goes, in document coordinates: sized by object-fit and
+    // placed in the content box by object-position. Whatever falls outside the content box is
+    // clipped away. None if the box isn't replaced.
+    pub fn image_rect(&self) -> Option<Rect> {
+        let image = self.image.as_ref()?;
+        let content = self.dimensions.content;
+        let (natural_width, natural_height) = (image.width as f32, image.height as f32);
+        let contain = f32::min(content.width / natural_width, content.height / natural_height);
+        let scale = match super::keyword(&self.style, "object-fit") {
+            "contain" => contain,
+            "cover" => f32::max(content.width / natural_width, content.height / natural_height),
+            "none" => 1.0,
+            "scale-down" => contain.min(1.0),
+            _ => return Some(content),
+        };
+        let (width, height) = (natural_width * scale, natural_height * scale);
+        let (x, y) = object_position(self.style.get("object-position"));
+        let offset = |value: &Value, room: f32| to_px(Some(value), room).unwrap_or(room / 2.0);
+        Some(Rect {
+            x: content.x + offset(&x, content.width - width),
+            y: content.y + offset(&y, content.height - height),
+            width,
+            height,
+        })
+    }
+}
+
+// The horizontal and vertical offsets of an object-position, as lengths or percentages of the
+// room left over in the content box.
+fn object_position(value: Option<&Value>) -> (Value, Value) {
+    let offset = |component: &Value| match component {
+        Value::Keyword(keyword) => Value::Percentage(match &**keyword {
+            "left" | "top" => 0.0,
+            "right" | "bottom" => 100.0,
+            _ => 50.0,
+        }),
+        component => component.clone(),
+    };
+    let is_vertical = |component: &Value| matches!(component, Value::Keyword(keyword) if keyword == "top" || keyword == "bottom");
+    let is_horizontal = |component: &Value| matches!(component, Value::Keyword(keyword) if keyword == "left" || keyword == "right");
+    let center = Value::Percentage(50.0);
+    match value.map(Value::components) {
+        Some([component]) if is_vertical(component) => (center, offset(component)),
+        Some([component]) => (offset(component), center),
+        Some([first, second]) if is_vertical(first) || is_horizontal(second) => (offset(second), offset(first)),
+        Some([first, second]) => (offset(first), offset(second)),
+        _ => (center.clone(), center),
+    }
+}
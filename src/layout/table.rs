@@ -0,0 +1,835 @@
+// Table layout, CSS 2.1 §17: a table's rows and cells are laid out in a grid of rows and
+// columns. Missing parts of the table structure are made up with anonymous boxes when the
+// box tree is built, §17.2.1, and the collapsing border model is resolved then too, since
+// it only depends on the border widths and styles. Column widths come from the cells' min-content and
+// max-content widths with the automatic table layout or from the first row with the fixed
+// one, §17.5.2, and rows are as high as their cells, §17.5.3. Each cell is laid out with
+// layout_block and then aligned vertically in its row.
+//
+// Captions go inside the table's border box rather than in a table wrapper box around it,
+// and rows, row groups and columns get the rects they cover but no edges.
+
+use std::collections::HashSet;
+use std::ops::Range;
+
+use super::anonymous_box;
+use super::flex::axis_sum;
+use super::keyword;
+use super::to_px;
+use super::BoxType;
+use super::FlowPosition;
+use super::LayoutBox;
+use super::Rect;
+use crate::css::Unit;
+use crate::css::Value;
+use crate::dom::NodeType;
+use crate::font::FontDatabase;
+use crate::style::PropertyMap;
+
+const SIDES: [&str; 4] = ["top", "right", "bottom", "left"];
+
+// The border styles in the order they win conflicts between collapsing borders of the same
+// width, §17.6.2.1. hidden is last because it wins over every other border whatever its
+// width, and none first because it loses to all of them.
+const STYLE_PRIORITY: [&str; 10] = ["none", "inset", "groove", "outset", "ridge", "dotted", "dashed", "solid", "double", "hidden"];
+
+// A border that collapses with others along an edge: its width and where its style is in
+// STYLE_PRIORITY.
+type CollapsingBorder = (f32, usize);
+
+// The border that wins a conflict between two along the same edge, §17.6.2.1: a hidden one,
+// or else the wider one, or else the one with the style that comes later in STYLE_PRIORITY.
+// `a` wins a tie, its box comes before the other in the order the borders are gathered.
+fn stronger(a: CollapsingBorder, b: CollapsingBorder) -> CollapsingBorder {
+    let hidden = STYLE_PRIORITY.len() - 1;
+    match (a.1 == hidden, b.1 == hidden) {
+        (true, _) => a,
+        (_, true) => b,
+        _ if b.0 > a.0 || (b.0 == a.0 && b.1 > a.1) => b,
+        _ => a,
+    }
+}
+
+// What part of a table a box is, going by its display, or "" if it isn't one.
+fn part<'s>(layout_box: &'s LayoutBox) -> &'s str {
+    match layout_box.box_type {
+        BoxType::BlockNode(_) | BoxType::AnonymousBlock => match keyword(&layout_box.style, "display") {
+            part if part.starts_with("table-") => part,
+            _ => "",
+        },
+        _ => "",
+    }
+}
+
+fn is_row_group(layout_box: &LayoutBox) -> bool {
+    matches!(part(layout_box), "table-row-group" | "table-header-group" | "table-footer-group")
+}
+
+// Whether the box can be a child of a table box as it is.
+fn is_proper_table_child(layout_box: &LayoutBox) -> bool {
+    matches!(part(layout_box), "table-caption" | "table-column-group" | "table-column" | "table-row") || is_row_group(layout_box)
+}
+
+// The children a box with `style` gets once the anonymous table boxes are generated and the
+// ones that don't belong are gone, CSS 2.1 §17.2.1. Everything in a table goes in rows and
+// everything in a row in cells, and parts of a table outside of one get a table around them.
+// Rows right in a table go in an anonymous row group as well, so that every row is in one.
+pub(super) fn fix_up_table_parts<'a>(style: &PropertyMap, children: Vec<LayoutBox<'a>>) -> Vec<LayoutBox<'a>> {
+    match keyword(style, "display") {
+        "table" | "inline-table" => {
+            let children = wrap_runs(style, children, |child| !is_proper_table_child(child), "table-row");
+            wrap_runs(style, children, |child| part(child) == "table-row", "table-row-group")
+        },
+        "table-row-group" | "table-header-group" | "table-footer-group" => {
+            wrap_runs(style, children, |child| part(child) != "table-row", "table-row")
+        },
+        "table-row" => wrap_runs(style, children, |child| part(child) != "table-cell", "table-cell"),
+        "table-column-group" => children.into_iter().filter(|child| part(child) == "table-column").collect(),
+        "table-column" => vec![],
+        _ => {
+            let children = wrap_runs(style, children, |child| part(child) == "table-cell", "table-row");
+            wrap_runs(style, children, is_proper_table_child, "table")
+        },
+    }
+}
+
+// Puts each run of children that pass `test` in an anonymous box with `display`, which has
+// its own children fixed up in turn.
+fn wrap_runs<'a>(style: &PropertyMap, children: Vec<LayoutBox<'a>>, test: impl Fn(&LayoutBox) -> bool, display: &str) -> Vec<LayoutBox<'a>> {
+    let mut wrapped = vec![];
+    let mut run = vec![];
+    let flush = |run: &mut Vec<LayoutBox<'a>>, wrapped: &mut Vec<LayoutBox<'a>>| {
+        if run.is_empty() {
+            return;
+        }
+        let mut anonymous = anonymous_box(style, display);
+        let children = fix_up_table_parts(&anonymous.style, std::mem::take(run));
+        anonymous.adopt_children(children, false);
+        if anonymous.is_table() {
+            anonymous.prepare_table();
+        }
+        wrapped.push(anonymous);
+    };
+    for child in children {
+        if test(&child) {
+            run.push(child);
+            continue;
+        }
+        flush(&mut run, &mut wrapped);
+        wrapped.push(child);
+    }
+    flush(&mut run, &mut wrapped);
+    wrapped
+}
+
+// The number in an HTML attribute of the element a box is for, like colspan, if it has one.
+fn attribute(layout_box: &LayoutBox, name: &str) -> Option<usize> {
+    match layout_box.box_type {
+        BoxType::BlockNode(node) => match &node.node.node_type {
+            NodeType::Element(elem) => elem.attributes.get(name)?.trim().parse().ok(),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// A cell of a table and the slots of the table grid it covers.
+struct TableCell {
+    // the indices of the cell's row group among the table's children, its row in the row
+    // group and the cell in the row
+    path: [usize; 3],
+    row: usize,
+    column: usize,
+    rows: usize,
+    columns: usize,
+}
+
+// The table grid, §17.5.
+struct TableGrid {
+    // the row groups and the rows in each in the order they're laid out, with the first
+    // header group at the top and the first footer group at the bottom
+    groups: Vec<(usize, Range<usize>)>,
+    rows: Vec<[usize; 2]>,
+    // for each column, the child of the table that is its column or column group, and the
+    // column in that group if it has any
+    columns: Vec<(usize, Option<usize>)>,
+    column_count: usize,
+    cells: Vec<TableCell>,
+}
+
+impl TableGrid {
+    fn span(&self, cell: &TableCell) -> [Range<usize>; 2] {
+        [cell.row..cell.row + cell.rows, cell.column..cell.column + cell.columns]
+    }
+}
+
+// The horizontal and vertical spacing between the cells, which the collapsing border model
+// doesn't have.
+fn border_spacing(style: &PropertyMap) -> [f32; 2] {
+    if keyword(style, "border-collapse") == "collapse" {
+        return [0.0, 0.0];
+    }
+    match style.get("border-spacing").map(Value::components) {
+        Some([horizontal, vertical]) => [to_px(Some(horizontal), 0.0).unwrap_or(0.0), to_px(Some(vertical), 0.0).unwrap_or(0.0)],
+        Some([spacing]) => [to_px(Some(spacing), 0.0).unwrap_or(0.0); 2],
+        _ => [0.0, 0.0],
+    }
+}
+
+// How wide the columns want to be with the automatic table layout.
+#[derive(Default)]
+struct ColumnWidths {
+    min: Vec<f32>,
+    max: Vec<f32>,
+    // the largest percentage width of a column or cell in it
+    percent: Vec<f32>,
+    // whether a column or cell in it has a width in px
+    fixed: Vec<bool>,
+}
+
+// Spreads `extra` width over `columns`, in proportion to their `weights` or evenly if they
+// have none.
+fn spread(widths: &mut [f32], columns: &[usize], weights: &[f32], extra: f32) {
+    let total: f32 = columns.iter().map(|&column| weights[column]).sum();
+    for &column in columns {
+        widths[column] += match total > 0.0 {
+            true => extra * weights[column] / total,
+            false => extra / columns.len() as f32,
+        };
+    }
+}
+
+// The widths of the columns in `available` width, without the spacing. Percentage columns
+// get their share first, then the others grow from their min-content towards their
+// max-content widths, and whatever is left over goes to the columns with an auto width
+// before the fixed and percentage ones.
+fn distribute_widths(columns: &ColumnWidths, available: f32) -> Vec<f32> {
+    let mut widths = columns.min.clone();
+    if available <= widths.iter().sum::<f32>() {
+        return widths;
+    }
+    let count = widths.len();
+    for column in (0..count).filter(|&column| columns.percent[column] > 0.0) {
+        widths[column] = widths[column].max(columns.percent[column] / 100.0 * available);
+    }
+    let mut remaining = available - widths.iter().sum::<f32>();
+    if remaining <= 0.0 {
+        return widths;
+    }
+    let others: Vec<usize> = (0..count).filter(|&column| columns.percent[column] == 0.0).collect();
+    let growth: Vec<f32> = (0..count).map(|column| columns.max[column] - columns.min[column]).collect();
+    let total_growth: f32 = others.iter().map(|&column| growth[column]).sum();
+    if remaining <= total_growth {
+        spread(&mut widths, &others, &growth, remaining);
+        return widths;
+    }
+    for &column in &others {
+        widths[column] = columns.max[column];
+    }
+    remaining -= total_growth;
+    let auto: Vec<usize> = others.iter().copied().filter(|&column| !columns.fixed[column]).collect();
+    let fixed: Vec<usize> = others.iter().copied().filter(|&column| columns.fixed[column]).collect();
+    let percent: Vec<usize> = (0..count).filter(|&column| columns.percent[column] > 0.0).collect();
+    let takers = if !auto.is_empty() { auto } else if !fixed.is_empty() { fixed } else { percent };
+    spread(&mut widths, &takers, &columns.max, remaining);
+    widths
+}
+
+impl<'a> LayoutBox<'a> {
+    fn table_part(&self, path: &[usize]) -> &LayoutBox<'a> {
+        path.iter().fold(self, |part, &i| &part.children[i])
+    }
+
+    fn table_part_mut(&mut self, path: &[usize]) -> &mut LayoutBox<'a> {
+        path.iter().fold(self, |part, &i| &mut part.children[i])
+    }
+
+    // The table grid of a table whose children are fixed up, with its cells placed in the
+    // slots the ones above them don't already span, as in HTML's table processing model.
+    fn table_grid(&self) -> TableGrid {
+        let header = self.children.iter().position(|child| part(child) == "table-header-group");
+        let footer = self.children.iter().position(|child| part(child) == "table-footer-group");
+        let bodies = (0..self.children.len()).filter(|&i| is_row_group(&self.children[i]) && Some(i) != header && Some(i) != footer);
+        let order: Vec<usize> = header.into_iter().chain(bodies).chain(footer).collect();
+
+        let mut grid = TableGrid {groups: vec![], rows: vec![], columns: vec![], column_count: 0, cells: vec![]};
+        for group in order {
+            let start = grid.rows.len();
+            grid.rows.extend((0..self.children[group].children.len()).map(|row| [group, row]));
+            grid.groups.push((group, start..grid.rows.len()));
+        }
+        for (i, child) in self.children.iter().enumerate() {
+            let span = |column: &LayoutBox| attribute(column, "span").unwrap_or(1).clamp(1, 1000);
+            match part(child) {
+                "table-column-group" if !child.children.is_empty() => {
+                    for (j, column) in child.children.iter().enumerate() {
+                        grid.columns.extend(std::iter::repeat_n((i, Some(j)), span(column)));
+                    }
+                },
+                "table-column-group" | "table-column" => grid.columns.extend(std::iter::repeat_n((i, None), span(child))),
+                _ => {},
+            }
+        }
+
+        //a rowspan of 0 reaches to the end of the row group, and none reaches past it
+        let mut covered: HashSet<(usize, usize)> = HashSet::new();
+        for (group, rows) in &grid.groups {
+            for row in rows.clone() {
+                let mut column = 0;
+                for (i, cell) in self.children[*group].children[row - rows.start].children.iter().enumerate() {
+                    while covered.contains(&(row, column)) {
+                        column += 1;
+                    }
+                    let columns = attribute(cell, "colspan").unwrap_or(1).clamp(1, 1000);
+                    let cell_rows = match attribute(cell, "rowspan") {
+                        Some(0) => rows.end - row,
+                        span => span.unwrap_or(1).clamp(1, rows.end - row),
+                    };
+                    for slot_row in row..row + cell_rows {
+                        covered.extend((column..column + columns).map(|slot_column| (slot_row, slot_column)));
+                    }
+                    grid.cells.push(TableCell {path: [*group, row - rows.start, i], row, column, rows: cell_rows, columns});
+                    grid.column_count = grid.column_count.max(column + columns);
+                    column += columns;
+                }
+            }
+        }
+        grid.column_count = grid.column_count.max(grid.columns.len());
+        grid
+    }
+
+    // Gets a table that was just built ready for layout: the cells of an HTML table with a
+    // cellpadding attribute get it as their padding where no rule sets one, and the borders
+    // collapse if the table asks for that.
+    pub(super) fn prepare_table(&mut self) {
+        let grid = self.table_grid();
+        if let Some(padding) = attribute(self, "cellpadding") {
+            for cell in &grid.cells {
+                let cell = self.table_part_mut(&cell.path);
+                let node = match cell.box_type {
+                    BoxType::BlockNode(node) => node,
+                    _ => continue,
+                };
+                for side in SIDES {
+                    let name = format!("padding-{}", side);
+                    if node.value(&name).is_none() {
+                        cell.style.insert(name, Value::Length(padding as f32, Unit::Px));
+                    }
+                }
+            }
+        }
+        if keyword(&self.style, "border-collapse") == "collapse" {
+            self.collapse_borders(&grid);
+        }
+    }
+
+    // The collapsing border model, §17.6.2: the borders of the cells, rows, row groups,
+    // columns, column groups and the table that meet along each edge between two slots of the
+    // table grid become one border, the one that wins over the others by its style and width.
+    // Half of it goes to each cell it is between and the outer half of the table's outer
+    // borders to the table, which has no padding. A side of a cell that spans several slots
+    // gets the widest border along them that isn't hidden.
+    fn collapse_borders(&mut self, grid: &TableGrid) {
+        let (row_count, column_count) = (grid.rows.len(), grid.column_count);
+        if row_count == 0 || column_count == 0 {
+            return;
+        }
+        let border = |layout_box: &LayoutBox, side: usize| -> CollapsingBorder {
+            let width = to_px(layout_box.style.get(&format!("border-{}-width", SIDES[side])), 0.0).unwrap_or(0.0);
+            let style = keyword(&layout_box.style, &format!("border-{}-style", SIDES[side]));
+            (width, STYLE_PRIORITY.iter().position(|priority| priority.eq_ignore_ascii_case(style)).unwrap_or(0))
+        };
+        //the edges above each row of slots and the ones left of each column of slots
+        let mut horizontal = vec![vec![(0.0, 0); column_count]; row_count + 1];
+        let mut vertical = vec![vec![(0.0, 0); column_count + 1]; row_count];
+        let mut widen = |layout_box: &LayoutBox, rows: Range<usize>, columns: Range<usize>| {
+            for column in columns.clone() {
+                horizontal[rows.start][column] = stronger(horizontal[rows.start][column], border(layout_box, 0));
+                horizontal[rows.end][column] = stronger(horizontal[rows.end][column], border(layout_box, 2));
+            }
+            for row in rows {
+                vertical[row][columns.start] = stronger(vertical[row][columns.start], border(layout_box, 3));
+                vertical[row][columns.end] = stronger(vertical[row][columns.end], border(layout_box, 1));
+            }
+        };
+        widen(self, 0..row_count, 0..column_count);
+        for (group, rows) in grid.groups.iter().filter(|(_, rows)| !rows.is_empty()) {
+            widen(&self.children[*group], rows.clone(), 0..column_count);
+        }
+        for (row, path) in grid.rows.iter().enumerate() {
+            widen(self.table_part(path), row..row + 1, 0..column_count);
+        }
+        for (column, &(i, j)) in grid.columns.iter().enumerate() {
+            if let Some(j) = j {
+                widen(&self.children[i].children[j], 0..row_count, column..column + 1);
+            }
+            let group = grid.columns.iter().enumerate().filter(|(_, &(group, _))| group == i).map(|(column, _)| column);
+            let (first, last) = (group.clone().min().unwrap_or(column), group.max().unwrap_or(column));
+            widen(&self.children[i], 0..row_count, first..last + 1);
+        }
+        for cell in &grid.cells {
+            let [rows, columns] = grid.span(cell);
+            widen(self.table_part(&cell.path), rows, columns);
+        }
+
+        let set = |layout_box: &mut LayoutBox, borders: [CollapsingBorder; 4]| {
+            for (side, (width, style)) in SIDES.iter().zip(borders) {
+                layout_box.style.insert(format!("border-{}-width", side), Value::Length(width, Unit::Px));
+                layout_box.style.insert(format!("border-{}-style", side), Value::Keyword(STYLE_PRIORITY[style].to_string()));
+            }
+        };
+        let widest = |edges: &[CollapsingBorder]| {
+            let (width, style) = edges.iter().filter(|(_, style)| STYLE_PRIORITY[*style] != "hidden").fold((0.0, 0), |a, &b| stronger(a, b));
+            (width / 2.0, style)
+        };
+        for cell in &grid.cells {
+            let [rows, columns] = grid.span(cell);
+            let widths = [
+                widest(&horizontal[rows.start][columns.clone()]),
+                widest(&vertical[rows.clone()].iter().map(|edges| edges[columns.end]).collect::<Vec<CollapsingBorder>>()),
+                widest(&horizontal[rows.end][columns.clone()]),
+                widest(&vertical[rows].iter().map(|edges| edges[columns.start]).collect::<Vec<CollapsingBorder>>()),
+            ];
+            set(self.table_part_mut(&cell.path), widths);
+        }
+        for path in grid.rows.iter().map(|path| path.to_vec()).chain(grid.groups.iter().map(|(group, _)| vec![*group])) {
+            set(self.table_part_mut(&path), [(0.0, 0); 4]);
+        }
+        let widths = [widest(&horizontal[0]), widest(&vertical[0][column_count..]), widest(&horizontal[row_count]), widest(&vertical[0][..1])];
+        set(self, widths);
+        for side in SIDES {
+            self.style.insert(format!("padding-{}", side), Value::Length(0.0, Unit::Px));
+        }
+    }
+
+    // Whether the table uses the fixed table layout, §17.5.2.1, which needs a width.
+    fn is_fixed_layout(&self) -> bool {
+        keyword(&self.style, "table-layout") == "fixed" && to_px(self.style.get("width"), 0.0).is_some()
+    }
+
+    // The border box width or height a cell asks for, if it asks for one in px.
+    fn specified_border_box_size(&self, name: &str, reference: f32) -> Option<f32> {
+        let size = to_px(self.style.get(name), reference)?;
+        let (_, _, edges) = self.item_edges(reference);
+        Some(if self.border_box_sizing() { size } else { size + axis_sum(edges, name == "width") })
+    }
+
+    // How wide each column wants to be with the automatic table layout, §17.5.2.2: at least
+    // as wide as the min-content width of every cell in it and as the width a column or cell
+    // in it asks for, and at most as wide as their max-content widths. A cell that spans
+    // several columns spreads what it needs beyond them over them.
+    fn column_widths(&self, grid: &TableGrid, spacing: f32, fonts: &FontDatabase) -> ColumnWidths {
+        let count = grid.column_count;
+        let mut columns = ColumnWidths {min: vec![0.0; count], max: vec![0.0; count], percent: vec![0.0; count], fixed: vec![false; count]};
+        for (column, &(i, j)) in grid.columns.iter().enumerate() {
+            let column_box = match j {
+                Some(j) => &self.children[i].children[j],
+                None => &self.children[i],
+            };
+            match column_box.style.get("width") {
+                Some(Value::Percentage(percentage)) => columns.percent[column] = *percentage,
+                value => if let Some(width) = to_px(value, 0.0) {
+                    columns.max[column] = width;
+                    columns.fixed[column] = true;
+                },
+            }
+        }
+
+        let mut cells: Vec<&TableCell> = grid.cells.iter().collect();
+        cells.sort_by_key(|cell| cell.columns);
+        for cell in cells {
+            let cell_box = self.table_part(&cell.path);
+            let (_, _, edges) = cell_box.item_edges(0.0);
+            let (min_content, max_content) = cell_box.intrinsic_widths(fonts);
+            let (mut min, mut max) = (min_content + axis_sum(edges, true), max_content + axis_sum(edges, true));
+            let span = cell.column..cell.column + cell.columns;
+            match cell_box.style.get("width") {
+                Some(Value::Percentage(percentage)) if cell.columns == 1 => {
+                    columns.percent[cell.column] = columns.percent[cell.column].max(*percentage);
+                },
+                _ => if let Some(width) = cell_box.specified_border_box_size("width", 0.0) {
+                    min = min.max(width);
+                    max = width.max(min);
+                    for column in span.clone() {
+                        columns.fixed[column] = true;
+                    }
+                },
+            }
+            if cell.columns == 1 {
+                columns.min[cell.column] = columns.min[cell.column].max(min);
+                columns.max[cell.column] = columns.max[cell.column].max(max);
+                continue;
+            }
+            let gaps = spacing * (cell.columns - 1) as f32;
+            let span: Vec<usize> = span.collect();
+            let min_extra = min - gaps - span.iter().map(|&column| columns.min[column]).sum::<f32>();
+            if min_extra > 0.0 {
+                let weights = columns.max.clone();
+                spread(&mut columns.min, &span, &weights, min_extra);
+            }
+            let max_extra = max - gaps - span.iter().map(|&column| columns.max[column]).sum::<f32>();
+            if max_extra > 0.0 {
+                let weights = columns.max.clone();
+                spread(&mut columns.max, &span, &weights, max_extra);
+            }
+        }
+        for column in 0..count {
+            columns.max[column] = columns.max[column].max(columns.min[column]);
+        }
+        columns
+    }
+
+    // The widths of the columns with the fixed table layout, §17.5.2.1: what the columns ask
+    // for, or failing that the cells of the first row, with the rest shared equally by the
+    // columns that get nothing from either. If the table is wider than that, the columns
+    // grow in proportion.
+    fn fixed_column_widths(&self, grid: &TableGrid, available: f32, spacing: f32) -> Vec<f32> {
+        let mut widths: Vec<Option<f32>> = vec![None; grid.column_count];
+        for (column, &(i, j)) in grid.columns.iter().enumerate() {
+            let column_box = match j {
+                Some(j) => &self.children[i].children[j],
+                None => &self.children[i],
+            };
+            widths[column] = to_px(column_box.style.get("width"), available);
+        }
+        for cell in grid.cells.iter().filter(|cell| cell.row == 0) {
+            let span = cell.column..cell.column + cell.columns;
+            let unset: Vec<usize> = span.filter(|&column| widths[column].is_none()).collect();
+            if let Some(width) = self.table_part(&cell.path).specified_border_box_size("width", available) {
+                let width = (width - spacing * (cell.columns - 1) as f32).max(0.0);
+                for &column in &unset {
+                    widths[column] = Some(width / unset.len() as f32);
+                }
+            }
+        }
+        let set: f32 = widths.iter().flatten().sum();
+        let unset = widths.iter().filter(|width| width.is_none()).count();
+        let share = ((available - set) / unset.max(1) as f32).max(0.0);
+        let mut widths: Vec<f32> = widths.into_iter().map(|width| width.unwrap_or(share)).collect();
+        let extra = available - widths.iter().sum::<f32>();
+        if extra > 0.0 {
+            let weights = widths.clone();
+            let columns: Vec<usize> = (0..widths.len()).collect();
+            spread(&mut widths, &columns, &weights, extra);
+        }
+        widths
+    }
+
+    // Lays out a table's captions, rows and cells in its content box, which starts at `start`
+    // and is at least `height` high, and returns where the content box ends.
+    pub(super) fn layout_table(&mut self, start: FlowPosition, height: Option<f32>, fonts: &FontDatabase) -> (FlowPosition, Option<f32>) {
+        let content = self.dimensions.content;
+        let grid = self.table_grid();
+        let [spacing_x, spacing_y] = border_spacing(&self.style);
+        let rtl = keyword(&self.style, "direction") == "rtl";
+        let unlaid: Vec<LayoutBox<'a>> = grid.cells.iter().map(|cell| self.table_part(&cell.path).clone()).collect();
+
+        //captions go above or below the table grid as blocks as wide as the table
+        let layout_captions = |table: &mut LayoutBox<'a>, side: &str, mut y: f32| {
+            for caption in table.children.iter_mut().filter(|child| part(child) == "table-caption") {
+                if keyword(&caption.style, "caption-side") == side {
+                    let next = caption.layout_block(content.x, FlowPosition {y, margins: Default::default()}, content.width, None, None, fonts).next;
+                    y = next.y + next.margins.collapse();
+                }
+            }
+            y
+        };
+        let grid_top = layout_captions(self, "top", start.y);
+
+        let columns = grid.column_count;
+        let available = content.width - spacing_x * (columns + 1) as f32;
+        let widths = match self.is_fixed_layout() {
+            true => self.fixed_column_widths(&grid, available, spacing_x),
+            false => distribute_widths(&self.column_widths(&grid, spacing_x, fonts), available),
+        };
+        let mut column_x = Vec::with_capacity(columns);
+        let mut x = spacing_x;
+        for width in &widths {
+            column_x.push(x);
+            x += width + spacing_x;
+        }
+        let span_width = |span: &Range<usize>| widths[span.clone()].iter().sum::<f32>() + spacing_x * span.len().saturating_sub(1) as f32;
+        //the left of a span of columns, relative to the content box
+        let span_left = |span: &Range<usize>| {
+            let left = column_x.get(span.start).copied().unwrap_or(spacing_x);
+            if rtl { content.width - left - span_width(span) } else { left }
+        };
+
+        //the cells are laid out at the width of their columns to find out how high their
+        //content is, and the rows are as high as that or the height the cells ask for, with
+        //the cells that span rows making room for themselves last
+        let overrides = |cell: &LayoutBox, width: f32, height: Option<f32>| {
+            let (_, _, edges) = cell.item_edges(content.width);
+            let sizing = |size: f32, horizontal: bool| match cell.border_box_sizing() {
+                true => size,
+                false => (size - axis_sum(edges, horizontal)).max(0.0),
+            };
+            //margins don't apply to cells
+            let mut overrides: Vec<(&str, Value)> = ["margin-top", "margin-right", "margin-bottom", "margin-left"].iter()
+                .map(|&name| (name, Value::Length(0.0, Unit::Px)))
+                .collect();
+            overrides.push(("width", Value::Length(sizing(width, true), Unit::Px)));
+            overrides.push(("height", match height {
+                Some(height) => Value::Length(sizing(height, false), Unit::Px),
+                None => Value::Keyword("auto".to_string()),
+            }));
+            overrides
+        };
+        let rows = grid.rows.len();
+        let mut row_heights: Vec<f32> = grid.rows.iter().map(|path| {
+            to_px(self.table_part(path).style.get("height"), 0.0).unwrap_or(0.0)
+        }).collect();
+        let mut above_baseline = vec![0.0_f32; rows];
+        let mut below_baseline = vec![0.0_f32; rows];
+        let mut cell_heights = vec![];
+        let mut cell_baselines = vec![];
+        for cell in &grid.cells {
+            let [_, span] = grid.span(cell);
+            let cell_box = self.table_part_mut(&cell.path);
+            let overrides = overrides(cell_box, span_width(&span), None);
+            cell_box.layout_overriding(&overrides, content.width, None, fonts);
+            let border_box = cell_box.dimensions.border_box();
+            let baseline = cell_box.first_baseline().unwrap_or(cell_box.dimensions.content.y + cell_box.dimensions.content.height) - border_box.y;
+            let height = cell_box.specified_border_box_size("height", content.width).unwrap_or(0.0).max(border_box.height);
+            if cell.rows == 1 {
+                row_heights[cell.row] = row_heights[cell.row].max(height);
+                if !matches!(keyword(&cell_box.style, "vertical-align"), "top" | "middle" | "bottom") {
+                    above_baseline[cell.row] = above_baseline[cell.row].max(baseline);
+                    below_baseline[cell.row] = below_baseline[cell.row].max(border_box.height - baseline);
+                }
+            }
+            cell_heights.push((height, border_box.height));
+            cell_baselines.push(baseline);
+        }
+        for row in 0..rows {
+            row_heights[row] = row_heights[row].max(above_baseline[row] + below_baseline[row]);
+        }
+        let mut spanning: Vec<(&TableCell, f32)> = grid.cells.iter().zip(&cell_heights).filter(|(cell, _)| cell.rows > 1).map(|(cell, &(height, _))| (cell, height)).collect();
+        spanning.sort_by_key(|(cell, _)| cell.rows);
+        for (cell, height) in spanning {
+            let [span, _] = grid.span(cell);
+            let extra = height - row_heights[span.clone()].iter().sum::<f32>() - spacing_y * (cell.rows - 1) as f32;
+            if extra > 0.0 {
+                let spanned: Vec<usize> = span.collect();
+                let weights = row_heights.clone();
+                spread(&mut row_heights, &spanned, &weights, extra);
+            }
+        }
+
+        //a table that is higher than its rows makes them higher in proportion
+        let spacing_height = if rows > 0 { spacing_y * (rows + 1) as f32 } else { 0.0 };
+        let grid_height = row_heights.iter().sum::<f32>() + spacing_height;
+        let bottom_captions: f32 = self.children.iter()
+            .filter(|child| part(child) == "table-caption" && keyword(&child.style, "caption-side") == "bottom")
+            .map(|caption| caption.clone().layout_block(0.0, Default::default(), content.width, None, None, fonts).next)
+            .map(|next| next.y + next.margins.collapse())
+            .sum();
+        let extra = height.unwrap_or(0.0) - (grid_top - start.y) - grid_height - bottom_captions;
+        if extra > 0.0 && rows > 0 {
+            let all: Vec<usize> = (0..rows).collect();
+            let weights = row_heights.clone();
+            spread(&mut row_heights, &all, &weights, extra);
+        }
+        let mut row_y = Vec::with_capacity(rows);
+        let mut y = grid_top + if rows > 0 { spacing_y } else { 0.0 };
+        for height in &row_heights {
+            row_y.push(y);
+            y += height + spacing_y;
+        }
+        let grid_bottom = if rows > 0 { y } else { grid_top };
+
+        //then each cell is laid out again as high as its rows and its content moved down to
+        //where vertical-align says, §17.5.4
+        for ((cell, unlaid), ((_, natural_height), baseline)) in grid.cells.iter().zip(unlaid).zip(cell_heights.into_iter().zip(cell_baselines)) {
+            let [row_span, column_span] = grid.span(cell);
+            let height = row_heights[row_span.clone()].iter().sum::<f32>() + spacing_y * (cell.rows - 1) as f32;
+            let cell_box = self.table_part_mut(&cell.path);
+            *cell_box = unlaid;
+            let overrides = overrides(cell_box, span_width(&column_span), Some(height));
+            cell_box.layout_overriding(&overrides, content.width, None, fonts);
+            let offset = match keyword(&cell_box.style, "vertical-align") {
+                "top" => 0.0,
+                "middle" => (height - natural_height) / 2.0,
+                "bottom" => height - natural_height,
+                _ if cell.rows == 1 => above_baseline[cell.row] - baseline,
+                _ => 0.0,
+            };
+            for child in &mut cell_box.children {
+                child.translate(0.0, offset.max(0.0));
+            }
+            cell_box.dimensions.margin = Default::default();
+            cell_box.translate(content.x + span_left(&column_span), row_y[cell.row]);
+        }
+
+        //rows, row groups and columns cover the cells in them
+        let all_columns = 0..columns;
+        let (left, width) = (content.x + span_left(&all_columns), span_width(&all_columns));
+        for (row, path) in grid.rows.iter().enumerate() {
+            let row_box = self.table_part_mut(path);
+            row_box.dimensions = Default::default();
+            row_box.dimensions.content = Rect {x: left, y: row_y[row], width, height: row_heights[row]};
+        }
+        for (group, rows) in &grid.groups {
+            let top = rows.clone().next().map_or(grid_top, |row| row_y[row]);
+            let bottom = rows.clone().last().map_or(top, |row| row_y[row] + row_heights[row]);
+            self.children[*group].dimensions = Default::default();
+            self.children[*group].dimensions.content = Rect {x: left, y: top, width, height: bottom - top};
+        }
+        for (i, child) in self.children.iter_mut().enumerate() {
+            let span = |j: Option<usize>| {
+                let columns: Vec<usize> = grid.columns.iter().enumerate().filter(|(_, &column)| column.0 == i && (j.is_none() || column.1 == j)).map(|(column, _)| column).collect();
+                columns.first().map_or(0..0, |&first| first..columns.last().unwrap() + 1)
+            };
+            if !matches!(part(child), "table-column-group" | "table-column") {
+                continue;
+            }
+            let rect = |span: Range<usize>| Rect {x: content.x + span_left(&span), y: grid_top, width: span_width(&span), height: grid_bottom - grid_top};
+            child.dimensions = Default::default();
+            child.dimensions.content = rect(span(None));
+            for (j, column) in child.children.iter_mut().enumerate() {
+                column.dimensions = Default::default();
+                column.dimensions.content = rect(span(Some(j)));
+            }
+        }
+
+        let end = layout_captions(self, "bottom", grid_bottom);
+        let end = end.max(start.y + height.unwrap_or(0.0));
+        (FlowPosition {y: end, margins: Default::default()}, None)
+    }
+
+    // The min-content and max-content widths of a table's content box, which are those of
+    // its columns and the spacing around them, or just what its columns ask for with the
+    // fixed table layout. Captions can make it wider.
+    pub(super) fn table_intrinsic_widths(&self, fonts: &FontDatabase) -> (f32, f32) {
+        let grid = self.table_grid();
+        let [spacing, _] = border_spacing(&self.style);
+        let spacing_width = spacing * (grid.column_count + 1) as f32;
+        let (min, max) = match self.is_fixed_layout() {
+            true => {
+                let min = self.fixed_column_widths(&grid, 0.0, spacing).iter().sum::<f32>() + spacing_width;
+                (min, min)
+            },
+            false => {
+                let columns = self.column_widths(&grid, spacing, fonts);
+                let min = columns.min.iter().sum::<f32>();
+                let mut max = columns.max.iter().sum::<f32>();
+                //percentage columns need the table to be wide enough that their share of it
+                //fits them, and leave the rest to the other columns
+                let percent: f32 = columns.percent.iter().sum();
+                for (column, &percentage) in columns.percent.iter().enumerate().filter(|(_, &percentage)| percentage > 0.0) {
+                    max = max.max(columns.max[column] * 100.0 / percentage);
+                }
+                if percent > 0.0 && percent < 100.0 {
+                    let others: f32 = (0..grid.column_count).filter(|&column| columns.percent[column] == 0.0).map(|column| columns.max[column]).sum();
+                    max = max.max(others * 100.0 / (100.0 - percent));
+                }
+                (min + spacing_width, max + spacing_width)
+            },
+        };
+        let captions = self.children.iter().filter(|child| part(child) == "table-caption")
+            .map(|caption| caption.intrinsic_contribution(fonts).0)
+            .fold(0.0, f32::max);
+        (min.max(captions), max.max(captions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::tests::content;
+    use super::super::tests::layout;
+    use crate::css_parser;
+    use crate::html_parser;
+    use crate::image::ImageStore;
+    use crate::media::MediaEnvironment;
+    use crate::style;
+
+    // The border widths and styles the element with `id` has after the table it is in is laid
+    // out, from top to left.
+    fn borders(html: &str, css: &str, id: &str) -> [(f32, String); 4] {
+        let root = html_parser::parse(html.to_string());
+        let stylesheet = css_parser::parse(css.to_string());
+        let cascade = style::Cascade::new(&stylesheet, MediaEnvironment::default());
+        let styled = style::style_tree(&root, &cascade);
+        let root_box = super::super::layout_tree(&styled, 800.0, 600.0, &FontDatabase::new(), &ImageStore::new());
+
+        fn find<'b, 'a>(layout_box: &'b LayoutBox<'a>, id: &str) -> Option<&'b LayoutBox<'a>> {
+            let is_it = match &layout_box.box_type {
+                BoxType::BlockNode(node) => matches!(&node.node.node_type, NodeType::Element(elem) if elem.id().is_some_and(|own| own == id)),
+                _ => false,
+            };
+            match is_it {
+                true => Some(layout_box),
+                false => layout_box.children.iter().find_map(|child| find(child, id)),
+            }
+        }
+        let found = find(&root_box, id).unwrap_or_else(|| panic!("no box for #{}", id));
+        SIDES.map(|side| {
+            let width = to_px(found.style.get(&format!("border-{}-width", side)), 0.0).unwrap_or(0.0);
+            (width, keyword(&found.style, &format!("border-{}-style", side)).to_string())
+        })
+    }
+
+    #[test]
+    fn collapsing_borders_are_chosen_by_width_and_style() {
+        let html = "<table id=t><tr><td id=a></td><td id=b></td></tr></table>";
+        let css = "#t { border-collapse: collapse; border-width: 2px; border-style: hidden solid solid }
+            #a { border-width: 10px 4px 4px 6px; border-style: solid dashed dotted none } #b { border-width: 4px; border-style: double }";
+        let side = |width: f32, style: &str| (width, style.to_string());
+
+        //hidden wins over the wider border, none loses to the narrower one, the wider dotted
+        //border wins over solid, and double over dashed of the same width
+        assert_eq!(borders(html, css, "a"), [side(0.0, "none"), side(2.0, "double"), side(2.0, "dotted"), side(1.0, "solid")]);
+        assert_eq!(borders(html, css, "b"), [side(0.0, "none"), side(2.0, "double"), side(2.0, "double"), side(2.0, "double")]);
+        //the outer halves of the table's outer borders, the strongest along each side
+        assert_eq!(borders(html, css, "t"), [side(0.0, "none"), side(2.0, "double"), side(2.0, "double"), side(1.0, "solid")]);
+    }
+
+    // The content boxes of the cells #a to #d that are in the laid out tree.
+    fn cells(html: &str, css: &str) -> Vec<Rect> {
+        let boxes = layout(&format!("<html><body>{}</body></html>", html), css);
+        ["a", "b", "c", "d"].iter().filter(|id| boxes.iter().any(|(label, _)| label == *id)).map(|id| content(&boxes, id)).collect()
+    }
+
+    fn rect(x: f32, y: f32, width: f32, height: f32) -> Rect {
+        Rect {x, y, width, height}
+    }
+
+    #[test]
+    fn cells_span_rows_and_columns() {
+        let html = "<table><tr><td id=a colspan=2></td><td id=b rowspan=2></td></tr><tr><td id=c></td><td id=d></td></tr></table>";
+        assert_eq!(cells(html, "td { width: 50px; height: 10px }"), [
+            rect(0.0, 0.0, 100.0, 10.0), rect(100.0, 0.0, 50.0, 20.0), rect(0.0, 10.0, 50.0, 10.0), rect(50.0, 10.0, 50.0, 10.0),
+        ]);
+    }
+
+    #[test]
+    fn fixed_layout_ignores_the_content_of_cells() {
+        let html = "<table id=t><tr><td id=a>aaaa aaaa</td><td id=b>b</td></tr></table>";
+        //the automatic layout shares out the width by how wide the content wants to be
+        assert_eq!(cells(html, "#t { width: 200px }"), [rect(0.0, 0.0, 178.31708, 18.625), rect(178.31708, 0.0, 21.68293, 18.625)]);
+        assert_eq!(cells(html, "#t { width: 200px; table-layout: fixed }"), [rect(0.0, 0.0, 100.0, 18.625), rect(100.0, 0.0, 100.0, 18.625)]);
+        //or by the widths of the cells of the first row, narrow as they may be
+        assert_eq!(cells(html, "#t { width: 200px; table-layout: fixed } #a { width: 50px }"),
+            [rect(0.0, 0.0, 50.0, 37.25), rect(50.0, 0.0, 150.0, 37.25)]);
+    }
+
+    #[test]
+    fn borders_are_spaced_apart_or_collapsed() {
+        let html = "<table id=t><tr><td id=a></td><td id=b></td></tr></table>";
+        let css = "#t { width: 200px; border-width: 4px; border-style: solid } td { height: 10px; border-width: 2px; border-style: solid }";
+        assert_eq!(cells(html, &format!("{} #t {{ border-spacing: 5px }}", css)), [rect(11.0, 11.0, 88.5, 10.0), rect(108.5, 11.0, 88.5, 10.0)]);
+        //half of the table's wider border is outside of it, and the cells share the border
+        //between them
+        assert_eq!(cells(html, &format!("{} #t {{ border-collapse: collapse }}", css)), [rect(4.0, 4.0, 97.0, 10.0), rect(103.0, 4.0, 97.0, 10.0)]);
+    }
+
+    #[test]
+    fn cellspacing_and_cellpadding_attributes() {
+        let html = "<table cellspacing=4 cellpadding=3><tr><td id=a></td><td id=b></td></tr></table>";
+        assert_eq!(cells(html, "table { width: 200px } td { height: 10px }"), [rect(7.0, 7.0, 88.0, 10.0), rect(105.0, 7.0, 88.0, 10.0)]);
+        //padding set by a rule wins over cellpadding, and the cell is stretched to the row
+        assert_eq!(cells(html, "table { width: 200px; table-layout: fixed } td { height: 10px } #b { padding: 0px }"),
+            [rect(7.0, 7.0, 88.0, 10.0), rect(102.0, 4.0, 94.0, 16.0)]);
+    }
+}
@@ -0,0 +1,170 @@
+// Vertical writing modes, CSS Writing Modes 4 §3: a box whose writing-mode is vertical-rl or
+// vertical-lr in a horizontal parent starts an orthogonal flow, where lines run top to bottom
+// and stack right to left or left to right. It is laid out as if it were horizontal, with
+// its physical sides and sizes turned to the logical ones, and then turned back into place.
+// Text is set sideways, its glyphs turned clockwise with the over side of each line on the
+// right, as with text-orientation: sideways.
+//
+// Boxes inside a vertical one go along with its writing mode whatever their own, so a
+// horizontal box in a vertical one isn't an orthogonal flow of its own. A horizontal parent
+// sized to its content counts a vertical child as if it were horizontal.
+
+use super::BoxType;
+use super::CollapsibleMargins;
+use super::EdgeSizes;
+use super::FlowPosition;
+use super::LayoutBox;
+use super::Placement;
+use super::Rect;
+use crate::css::Value;
+use crate::font::FontDatabase;
+use crate::style::PropertyMap;
+
+const SIDES: [&str; 4] = ["top", "right", "bottom", "left"];
+
+// What the physical top, right, bottom and left of a box in the writing mode are in the
+// horizontal layout: the top becomes the line-left side and the block-start side the top.
+fn logical_sides(writing_mode: &str) -> [&'static str; 4] {
+    match writing_mode {
+        "vertical-rl" => ["left", "top", "right", "bottom"],
+        _ => ["left", "bottom", "right", "top"],
+    }
+}
+
+fn is_vertical(style: &PropertyMap) -> bool {
+    matches!(style.get("writing-mode"), Some(Value::Keyword(keyword)) if keyword.starts_with("vertical"))
+}
+
+// Renames the box properties of a style from one set of sides to another, and swaps the
+// widths and heights.
+fn rename_sides(style: &mut PropertyMap, from: [&str; 4], to: [&str; 4]) {
+    let mut renamed = vec![];
+    for (from, to) in from.iter().zip(to) {
+        for template in ["margin-{}", "padding-{}", "border-{}-width"] {
+            if let Some(value) = style.remove(&template.replace("{}", from)) {
+                renamed.push((template.replace("{}", to), value));
+            }
+        }
+    }
+    for (width, height) in [("width", "height"), ("min-width", "min-height"), ("max-width", "max-height")] {
+        let (width_value, height_value) = (style.remove(width), style.remove(height));
+        renamed.extend(width_value.map(|value| (height.to_string(), value)));
+        renamed.extend(height_value.map(|value| (width.to_string(), value)));
+    }
+    style.extend(renamed);
+}
+
+fn rename_subtree(layout_box: &mut LayoutBox, from: [&str; 4], to: [&str; 4]) {
+    rename_sides(&mut layout_box.style, from, to);
+    for child in &mut layout_box.children {
+        rename_subtree(child, from, to);
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    // Whether the box starts an orthogonal flow in a parent with style `parent`.
+    pub(super) fn is_orthogonal_to(&self, parent: &PropertyMap) -> bool {
+        is_vertical(&self.style) && !is_vertical(parent)
+    }
+
+    // Lays out a box that starts an orthogonal flow at `position` in its parent's block
+    // formatting context, like layout_block. It is as tall as the containing block's height
+    // if that is known, or else as its content without wrapping any lines, and as wide as its
+    // content. Its margins don't collapse with its children's.
+    pub(super) fn layout_orthogonal(&mut self, x: f32, position: FlowPosition, containing_width: f32, containing_height: Option<f32>, fonts: &FontDatabase) -> Placement {
+        let writing_mode = match self.style.get("writing-mode") {
+            Some(Value::Keyword(keyword)) => keyword.clone(),
+            _ => String::new(),
+        };
+        let sides = logical_sides(&writing_mode);
+        rename_subtree(self, SIDES, sides);
+        let inline_size = containing_height.unwrap_or_else(|| self.intrinsic_contribution(fonts).1);
+        self.layout_block(0.0, Default::default(), inline_size, Some(containing_width), None, fonts);
+        rename_subtree(self, sides, SIDES);
+
+        if writing_mode == "vertical-lr" {
+            self.flip_lines();
+        }
+
+        //the physical top margin is the logical left one, and adjoins the margins above it
+        //like any other
+        let logical = self.dimensions;
+        let margin_top = logical.margin.left;
+        let top = position.y + position.margins.adjoin(margin_top).collapse() - margin_top;
+        let extent = logical.margin_box().height;
+        self.turn(&writing_mode, x, top, extent);
+
+        let border_box = self.dimensions.border_box();
+        let margins = CollapsibleMargins::default().adjoin(self.dimensions.margin.bottom);
+        Placement {next: FlowPosition {y: border_box.y + border_box.height, margins}, collapsed_through: false}
+    }
+
+    // Flips the content of each line box top to bottom. Turning a vertical-lr box into place
+    // mirrors it where vertical-rl only rotates it, so this puts the over side of its lines
+    // on the right all the same.
+    fn flip_lines(&mut self) {
+        for child in &mut self.children {
+            match child.box_type {
+                BoxType::LineBox {..} => {
+                    let line = child.dimensions.content;
+                    child.flip(2.0 * line.y + line.height);
+                },
+                _ => child.flip_lines(),
+            }
+        }
+    }
+
+    // Mirrors a box of a line and its content across the line y = `axis` / 2. The lines
+    // inside an atomic inline are only moved, then flipped in their own right.
+    fn flip(&mut self, axis: f32) {
+        match self.box_type {
+            BoxType::LineBox {..} | BoxType::InlineNode(_) | BoxType::TextRun {..} => {
+                let d = &mut self.dimensions;
+                d.content.y = axis - d.content.y - d.content.height;
+                for edges in [&mut d.margin, &mut d.border, &mut d.padding] {
+                    std::mem::swap(&mut edges.top, &mut edges.bottom);
+                }
+                if let BoxType::LineBox {baseline} = &mut self.box_type {
+                    *baseline = axis - *baseline;
+                }
+                for child in &mut self.children {
+                    child.flip(axis);
+                }
+            },
+            _ => {
+                let margin_box = self.dimensions.margin_box();
+                self.translate(0.0, axis - 2.0 * margin_box.y - margin_box.height);
+                self.flip_lines();
+            },
+        }
+    }
+
+    // Moves a box laid out horizontally at the origin to where it goes in the writing mode,
+    // with the block-start edge of its margin box `extent` wide at `x` and `y`. The glyphs of
+    // text stay where they are relative to their run, so they go down the line; a line box's
+    // baseline becomes the x the baseline of its text runs along.
+    fn turn(&mut self, writing_mode: &str, x: f32, y: f32, extent: f32) {
+        let rl = writing_mode == "vertical-rl";
+        let d = &mut self.dimensions;
+        let content = d.content;
+        d.content = Rect {
+            x: if rl { x + extent - content.y - content.height } else { x + content.y },
+            y: y + content.x,
+            width: content.height,
+            height: content.width,
+        };
+        let turn_edges = |edges: EdgeSizes| match rl {
+            true => EdgeSizes {top: edges.left, right: edges.top, bottom: edges.right, left: edges.bottom},
+            false => EdgeSizes {top: edges.left, right: edges.bottom, bottom: edges.right, left: edges.top},
+        };
+        d.margin = turn_edges(d.margin);
+        d.border = turn_edges(d.border);
+        d.padding = turn_edges(d.padding);
+        if let BoxType::LineBox {baseline} = &mut self.box_type {
+            *baseline = if rl { x + extent - *baseline } else { x + *baseline };
+        }
+        for child in &mut self.children {
+            child.turn(writing_mode, x, y, extent);
+        }
+    }
+}
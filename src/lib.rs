@@ -0,0 +1,17 @@
+pub mod animation;
+pub mod bidi;
+pub mod dom;
+pub mod font;
+pub mod html_parser;
+pub mod image;
+pub mod layout;
+pub mod line_break;
+pub mod css;
+pub mod css_parser;
+pub mod media;
+pub mod painting;
+pub mod properties;
+pub mod raster;
+pub mod resource;
+pub mod selector_map;
+pub mod style;
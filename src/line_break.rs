@@ -0,0 +1,222 @@
+// Line break opportunities in text, following the Unicode line breaking algorithm (UAX #14).
+// Characters are sorted into the line breaking classes that matter for the scripts we can
+// show; Hangul is treated as ideographic and South East Asian scripts, which need a dictionary
+// to find word boundaries, as alphabetic.
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Break {
+    // the line has to end here, after a newline
+    Mandatory,
+    // the line may end here
+    Allowed,
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Class {
+    // mandatory break, carriage return, line feed, next line
+    BK, CR, LF, NL,
+    // space, zero width space, word joiner, non-breaking glue
+    SP, ZW, WJ, GL,
+    // combining mark, zero width joiner
+    CM, ZWJ,
+    // opening and closing punctuation, closing parenthesis, quotation
+    OP, CL, CP, QU,
+    // exclamation/interrogation, infix numeric separator, symbols allowing a break after
+    EX, IS, SY,
+    // numeric, prefix and postfix numeric
+    NU, PR, PO,
+    // alphabetic, ideographic, inseparable
+    AL, ID, IN,
+    // hyphen, break after, break before, break on either side, nonstarter
+    HY, BA, BB, B2, NS,
+    // contingent break, like the object replacement character standing in for an inline-block
+    CB,
+}
+
+use Class::*;
+
+fn class(c: char) -> Class {
+    match c {
+        '\u{0B}' | '\u{0C}' | '\u{2028}' | '\u{2029}' => BK,
+        '\r' => CR,
+        '\n' => LF,
+        '\u{85}' => NL,
+        ' ' => SP,
+        '\u{200B}' => ZW,
+        '\u{2060}' | '\u{FEFF}' => WJ,
+        '\u{A0}' | '\u{202F}' | '\u{2007}' | '\u{034F}' | '\u{2011}' | '\u{0F0C}' => GL,
+        '\u{200D}' => ZWJ,
+        '\u{0300}'..='\u{036F}' | '\u{0483}'..='\u{0489}' | '\u{0591}'..='\u{05BD}' | '\u{0610}'..='\u{061A}'
+        | '\u{064B}'..='\u{065F}' | '\u{1AB0}'..='\u{1AFF}' | '\u{1DC0}'..='\u{1DFF}' | '\u{20D0}'..='\u{20FF}'
+        | '\u{FE00}'..='\u{FE0F}' | '\u{FE20}'..='\u{FE2F}' | '\u{200C}' | '\u{200E}' | '\u{200F}' => CM,
+        '(' | '[' | '{' | '\u{A1}' | '\u{BF}' | '\u{2045}' | '\u{3008}' | '\u{300A}' | '\u{300C}' | '\u{300E}'
+        | '\u{3010}' | '\u{3014}' | '\u{3016}' | '\u{FF08}' | '\u{FF3B}' | '\u{FF5B}' => OP,
+        '}' | '\u{2046}' | '\u{3001}' | '\u{3002}' | '\u{3009}' | '\u{300B}' | '\u{300D}' | '\u{300F}' | '\u{3011}'
+        | '\u{3015}' | '\u{3017}' | '\u{FF0C}' | '\u{FF0E}' | '\u{FF5D}' => CL,
+        ')' | ']' | '\u{FF09}' | '\u{FF3D}' => CP,
+        '"' | '\'' | '\u{AB}' | '\u{BB}' | '\u{2018}' | '\u{2019}' | '\u{201C}' | '\u{201D}' | '\u{2039}' | '\u{203A}' => QU,
+        '!' | '?' | '\u{203C}' | '\u{FF01}' | '\u{FF1F}' => EX,
+        ',' | '.' | ':' | ';' | '\u{37E}' | '\u{589}' => IS,
+        '/' => SY,
+        '0'..='9' | '\u{660}'..='\u{669}' | '\u{6F0}'..='\u{6F9}' | '\u{966}'..='\u{96F}' => NU,
+        '$' | '+' | '\\' | '#' | '\u{A3}' | '\u{A5}' | '\u{20AC}' | '\u{2116}' => PR,
+        '%' | '\u{A2}' | '\u{B0}' | '\u{2030}' | '\u{2032}' | '\u{2033}' | '\u{2103}' => PO,
+        '\u{2024}' | '\u{2025}' | '\u{2026}' => IN,
+        '-' => HY,
+        '\t' | '\u{AD}' | '|' | '\u{2010}' | '\u{2012}' | '\u{2013}' | '\u{1680}' | '\u{2000}'..='\u{2006}'
+        | '\u{2008}'..='\u{200A}' | '\u{205F}' => BA,
+        '\u{B4}' | '\u{2C8}' | '\u{2CC}' | '\u{1FFD}' => BB,
+        '\u{2014}' => B2,
+        '\u{3005}' | '\u{301C}' | '\u{303B}' | '\u{309B}'..='\u{309E}' | '\u{30A0}' | '\u{30FB}'..='\u{30FE}'
+        | '\u{3041}' | '\u{3043}' | '\u{3045}' | '\u{3047}' | '\u{3049}' | '\u{3063}' | '\u{3083}' | '\u{3085}' | '\u{3087}'
+        | '\u{30A1}' | '\u{30A3}' | '\u{30A5}' | '\u{30A7}' | '\u{30A9}' | '\u{30C3}' | '\u{30E3}' | '\u{30E5}' | '\u{30E7}' => NS,
+        '\u{FFFC}' => CB,
+        '\u{2E80}'..='\u{2FFF}' | '\u{3000}'..='\u{303F}' | '\u{3040}'..='\u{30FF}' | '\u{3100}'..='\u{31FF}'
+        | '\u{3400}'..='\u{4DBF}' | '\u{4E00}'..='\u{9FFF}' | '\u{A960}'..='\u{A97F}' | '\u{AC00}'..='\u{D7FF}'
+        | '\u{F900}'..='\u{FAFF}' | '\u{FF00}'..='\u{FFEF}' | '\u{1F300}'..='\u{1FAFF}' | '\u{20000}'..='\u{3FFFD}' => ID,
+        _ => AL,
+    }
+}
+
+// The byte offsets in `text` a line may break before, and whether it has to. The start and
+// end of the text aren't included.
+pub fn break_opportunities(text: &str) -> Vec<(usize, Break)> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+
+    //combining marks and joiners take the class of the character they attach to (LB9), and
+    //are alphabetic when there is none (LB10)
+    let mut classes: Vec<Class> = Vec::with_capacity(chars.len());
+    let mut attached = vec![false; chars.len()];
+    for (i, &(_, c)) in chars.iter().enumerate() {
+        let class = class(c);
+        let base = classes.last().copied().filter(|base| !matches!(base, BK | CR | LF | NL | SP | ZW));
+        classes.push(match (class, base) {
+            (CM, Some(base)) | (ZWJ, Some(base)) => {
+                attached[i] = true;
+                base
+            },
+            (CM, None) | (ZWJ, None) => AL,
+            _ => class,
+        });
+    }
+
+    let mut opportunities = vec![];
+    for i in 1..chars.len() {
+        if attached[i] {
+            continue;
+        }
+        let (before, after) = (classes[i - 1], classes[i]);
+        //the class before the spaces ending at i - 1, for the rules that look through them
+        let mut j = i - 1;
+        while j > 0 && classes[j] == SP {
+            j -= 1;
+        }
+        let before_spaces = classes[j];
+        let joiner_before = chars[i - 1].1 == '\u{200D}';
+        if let Some(opportunity) = pair_break(before, after, before_spaces, joiner_before) {
+            opportunities.push((chars[i].0, opportunity));
+        }
+    }
+    opportunities
+}
+
+// The rules of UAX #14 from LB4 on, for a break between `before` and `after`.
+fn pair_break(before: Class, after: Class, before_spaces: Class, joiner_before: bool) -> Option<Break> {
+    let allowed = Some(Break::Allowed);
+    match (before, after) {
+        //LB4, LB5: break after hard line breaks, but not inside CR LF
+        (BK, _) | (LF, _) | (NL, _) => Some(Break::Mandatory),
+        (CR, LF) => None,
+        (CR, _) => Some(Break::Mandatory),
+        //LB6, LB7: not before hard line breaks, spaces or zero width spaces
+        (_, BK) | (_, CR) | (_, LF) | (_, NL) | (_, SP) | (_, ZW) => None,
+        //LB8: after a zero width space, even with spaces in between
+        _ if before_spaces == ZW => allowed,
+        //LB8a: not after a zero width joiner
+        _ if joiner_before => None,
+        //LB11, LB12, LB12a: around word joiners and after glue, before glue unless after a space or hyphen
+        (WJ, _) | (_, WJ) | (GL, _) => None,
+        (SP, GL) | (BA, GL) | (HY, GL) => allowed,
+        (_, GL) => None,
+        //LB13: not before closing punctuation, exclamation marks and separators
+        (_, CL) | (_, CP) | (_, EX) | (_, IS) | (_, SY) => None,
+        //LB14 to LB17: not after opening punctuation, and some pairs not even with spaces between
+        _ if before_spaces == OP => None,
+        (_, OP) if before_spaces == QU => None,
+        (_, NS) if matches!(before_spaces, CL | CP) => None,
+        (_, B2) if before_spaces == B2 => None,
+        //LB18: after spaces
+        (SP, _) => allowed,
+        //LB19, LB20: not around quotation marks, but around contingent breaks
+        (_, QU) | (QU, _) => None,
+        (_, CB) | (CB, _) => allowed,
+        //LB21, LB22: not before hyphens, nonstarters and ellipses, or after break-before characters
+        (_, BA) | (_, HY) | (_, NS) | (BB, _) | (_, IN) => None,
+        //LB23 to LB25: letters and numbers with their prefixes, postfixes and separators stay together
+        (AL, NU) | (NU, AL) | (PR, ID) | (ID, PO) | (PR, AL) | (PO, AL) | (AL, PR) | (AL, PO) => None,
+        (CL, PO) | (CP, PO) | (CL, PR) | (CP, PR) | (NU, PO) | (NU, PR) | (PO, OP) | (PO, NU) | (PR, OP) | (PR, NU)
+        | (HY, NU) | (IS, NU) | (NU, NU) | (SY, NU) => None,
+        //LB28 to LB30: within words, and words with the punctuation attached to them
+        (AL, AL) | (IS, AL) | (AL, OP) | (NU, OP) | (CP, AL) | (CP, NU) => None,
+        //LB31: everywhere else
+        _ => allowed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `text` with ÷ where a line may break and ! where it has to.
+    fn marked(text: &str) -> String {
+        let opportunities = break_opportunities(text);
+        let mut marked = String::new();
+        for (offset, c) in text.char_indices() {
+            match opportunities.iter().find(|(at, _)| *at == offset) {
+                Some((_, Break::Allowed)) => marked.push('÷'),
+                Some((_, Break::Mandatory)) => marked.push('!'),
+                None => {},
+            }
+            marked.push(c);
+        }
+        marked
+    }
+
+    #[test]
+    fn breaks_between_words_and_ideographs() {
+        assert_eq!(marked("one two  three"), "one ÷two  ÷three");
+        assert_eq!(marked("日本語の文"), "日÷本÷語÷の÷文");
+        //punctuation stays with the word before it, an opening bracket with the one after
+        assert_eq!(marked("(a), b. 日本。"), "(a), ÷b. ÷日÷本。");
+    }
+
+    #[test]
+    fn small_kana_dont_start_a_line() {
+        //conditional Japanese starters are nonstarters, as with line-break: strict
+        assert_eq!(marked("ちょっと"), "ちょっ÷と");
+        assert_eq!(marked("ケーキァ"), "ケー÷キァ");
+    }
+
+    #[test]
+    fn breaks_after_hyphens() {
+        assert_eq!(marked("well-known"), "well-÷known");
+        //but not in the middle of a number or before a minus sign starting one
+        assert_eq!(marked("1-2 x -5"), "1-2 ÷x ÷-5");
+        assert_eq!(marked("a\u{2010}b a\u{AD}b"), "a\u{2010}÷b ÷a\u{AD}÷b");
+    }
+
+    #[test]
+    fn no_breaks_around_glue() {
+        assert_eq!(marked("10\u{A0}km a\u{A0}b"), "10\u{A0}km ÷a\u{A0}b");
+        assert_eq!(marked("a \u{A0}b"), "a ÷\u{A0}b");
+        assert_eq!(marked("a\u{2060}b\u{200B}c"), "a\u{2060}b\u{200B}÷c");
+    }
+
+    #[test]
+    fn mandatory_breaks_after_newlines() {
+        assert_eq!(marked("a\u{2028}b\u{B}c"), "a\u{2028}!b\u{B}!c");
+        assert_eq!(marked("a \nb\r\nc\rd"), "a \n!b\r\n!c\r!d");
+        assert_eq!(marked("a\u{85}b"), "a\u{85}!b");
+    }
+}
@@ -3,13 +3,106 @@
 
 use std::fs;
 
-mod dom;
-mod html_parser;
-mod css;
-mod css_parser;
-mod style;
+use light_browser_engine::animation;
+use light_browser_engine::dom;
+use light_browser_engine::font;
+use light_browser_engine::html_parser;
+use light_browser_engine::image;
+use light_browser_engine::layout;
+use light_browser_engine::media;
+use light_browser_engine::painting;
+use light_browser_engine::raster;
+use light_browser_engine::resource;
+use light_browser_engine::style;
+
+// Enough of the user agent stylesheet of HTML, §15.3, for a page without a stylesheet of its
+// own to look like one.
+const USER_AGENT_STYLES: &str = "
+    head, link, meta, script, style, template, title { display: none }
+    html, body, address, article, aside, blockquote, dd, div, dl, dt, figcaption, figure, footer, form,
+    h1, h2, h3, h4, h5, h6, header, hr, main, nav, p, pre, section { display: block }
+    table { display: table } thead { display: table-header-group } tbody { display: table-row-group }
+    tfoot { display: table-footer-group } tr { display: table-row } td, th { display: table-cell }
+    caption { display: table-caption }
+    body { margin: 8px }
+    p, blockquote, dl, figure, pre { margin: 1em 0 }
+    h1 { font-size: 2em; margin: 0.67em 0 } h2 { font-size: 1.5em; margin: 0.83em 0 }
+    h3 { font-size: 1.17em; margin: 1em 0 } h4 { margin: 1.33em 0 }
+    h5 { font-size: 0.83em; margin: 1.67em 0 } h6 { font-size: 0.67em; margin: 2.33em 0 }
+    h1, h2, h3, h4, h5, h6, b, strong, th { font-weight: bold }
+    i, em, cite, var { font-style: italic }
+    pre, code, kbd, samp { font-family: monospace } pre { white-space: pre }
+";
+
+fn find_element<'a>(node: &'a dom::Node, tag_name: &str) -> Option<&'a dom::Node> {
+    match &node.node_type {
+        dom::NodeType::Element(elem) if elem.tag_name == tag_name => Some(node),
+        _ => node.children.iter().find_map(|child| find_element(child, tag_name)),
+    }
+}
+
+// Prints the rules behind each property of the first `tag_name` element, like the Styles part
+// of the DevTools Computed pane: the computed value, then every declaration that competed for
+// it, the winner first.
+fn explain(html_tree: &dom::Node, cascade: &style::Cascade, tag_name: &str) {
+    let node = match find_element(html_tree, tag_name) {
+        Some(node) => node,
+        None => return println!("No <{}> element", tag_name),
+    };
+    let style_root = style::style_tree(html_tree, cascade);
+    let computed = style::computed_style(&style_root, node).unwrap_or_default();
+    println!("CASCADE <{}>:", tag_name);
+    for (name, candidates) in style::explain_cascade(html_tree, node, cascade).unwrap_or_default() {
+        println!("  {}: {}", name, computed.get(&name).map_or("(invalid)", |value| value));
+        for candidate in candidates {
+            println!("    {}", candidate);
+        }
+    }
+}
+
+// Renders the page at `path` into a PNG file at `output`, as much of it as shows in the
+// viewport of the default media environment.
+fn render(path: &str, output: &str) -> Result<(), String> {
+    let source = fs::read_to_string(path).map_err(|err| format!("Couldn't read {}: {}", path, err))?;
+    let mut html_tree = html_parser::parse(source);
+    let mut images = image::ImageStore::new();
+    for error in images.load_document_images(&mut html_tree, path, &resource::FileLoader) {
+        eprintln!("{}", error);
+    }
+    let stylesheet = resource::load_document_stylesheet(&html_tree, path, USER_AGENT_STYLES, &resource::FileLoader);
+    let cascade = style::Cascade::new(&stylesheet, media::MediaEnvironment::default());
+    let style_root = style::style_tree(&html_tree, &cascade);
+
+    //only the bundled font and the page's own, so that a page looks the same everywhere
+    let mut fonts = font::FontDatabase::new();
+    for error in fonts.load_font_faces(&stylesheet, &resource::FileLoader) {
+        eprintln!("{}", error);
+    }
+    let (width, height) = (cascade.environment().width, cascade.environment().height);
+    let layout_root = layout::layout_tree(&style_root, width, height, &fonts, &images);
+    let viewport = layout::Rect {x: 0.0, y: 0.0, width, height};
+    let display_list = painting::build_display_list(&layout_root, viewport, &fonts);
+    let canvas = raster::rasterize(&display_list, viewport, &fonts);
+    fs::write(output, image::encode_png(&canvas)).map_err(|err| format!("Couldn't write {}: {}", output, err))
+}
 
 fn main() {
+    //`render page.html -o out.png` draws a page instead of the usual output
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).is_some_and(|command| command == "render") {
+        let output = args.iter().position(|arg| arg == "-o").and_then(|i| args.get(i + 1));
+        let page = args[2..].iter().enumerate().find(|&(i, arg)| arg != "-o" && args[i + 1] != "-o");
+        let result = match page {
+            Some((_, page)) => render(page, output.map_or("out.png", String::as_str)),
+            None => Err("Usage: render page.html [-o out.png]".to_string()),
+        };
+        if let Err(err) = result {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     // let mut attrs: HashMap<String, String> = HashMap::new();
     // attrs.insert("bgcolor".to_string(),"skyblue".to_string());
     // let tree1 = dom::elem("html".to_string(), HashMap::new(),
@@ -27,9 +120,71 @@ fn main() {
     
     // println!("{}", tree1);
 
-    let html_tree = html_parser::parse(fs::read_to_string("test.html").unwrap());
-    println!("HTML PARSER:\n{}",html_tree);
+    let mut html_tree = html_parser::parse(fs::read_to_string("test.html").unwrap());
+    let css_tree = resource::load_stylesheet("test.css", &resource::FileLoader).unwrap();
+    let mut cascade = style::Cascade::new(&css_tree, media::MediaEnvironment::default());
 
-    let css_tree = css_parser::parse(fs::read_to_string("test.css").unwrap());
+    //`--explain p` shows why the first <p> looks the way it does instead of the usual output
+    if let Some(i) = args.iter().position(|arg| arg == "--explain") {
+        return explain(&html_tree, &cascade, args.get(i + 1).map_or("body", |tag_name| tag_name));
+    }
+
+    //images go in before styling, one that fails leaves its alt text in the tree instead
+    let mut images = image::ImageStore::new();
+    for error in images.load_document_images(&mut html_tree, "test.html", &resource::FileLoader) {
+        eprintln!("{}", error);
+    }
+
+    println!("HTML PARSER:\n{}",html_tree);
     println!("CSS PARSER:\n{}",css_tree);
+
+    let style_root = style::style_tree(&html_tree, &cascade);
+    println!("STYLE TREE ({}x{}):\n{}", cascade.environment().width, cascade.environment().height, style_root);
+
+    let mut fonts = font::FontDatabase::new();
+    for error in fonts.load_font_faces(&css_tree, &resource::FileLoader) {
+        eprintln!("{}", error);
+    }
+    let layout_root = layout::layout_tree(&style_root, cascade.environment().width, cascade.environment().height, &fonts, &images);
+    println!("LAYOUT TREE ({}x{}):\n{}", cascade.environment().width, cascade.environment().height, layout_root);
+    let viewport = layout::Rect {x: 0.0, y: 0.0, width: cascade.environment().width, height: cascade.environment().height};
+    println!("DISPLAY LIST:\n{}", painting::build_display_list(&layout_root, viewport, &fonts));
+
+    if let Some(computed) = find_element(&html_tree, "h1").and_then(|h1| style::computed_style(&style_root, h1)) {
+        println!("COMPUTED STYLE <h1>:");
+        for (name, value) in computed {
+            println!("  {}: {}", name, value);
+        }
+    }
+
+    //snapshot the animations on a clock we advance ourselves
+    let mut timeline = animation::AnimationTimeline::new();
+    timeline.update(&style_root, &cascade);
+    for step in [0.0, 250.0, 250.0] {
+        timeline.advance(step);
+        println!("ANIMATED STYLE TREE (t={}ms):\n{}", timeline.current_time(), timeline.sample(&style_root));
+    }
+
+    //a phone sized viewport in dark mode, only restyle if some @media rule changed its mind
+    let phone = media::MediaEnvironment {
+        color_scheme: media::ColorScheme::Dark,
+        ..media::MediaEnvironment::new(375.0, 667.0)
+    };
+    if cascade.set_environment(phone) {
+        let style_root = style::style_tree(&html_tree, &cascade);
+        println!("STYLE TREE ({}x{}):\n{}", cascade.environment().width, cascade.environment().height, style_root);
+
+        //values the new rules changed transition over from what was showing
+        timeline.update(&style_root, &cascade);
+        timeline.advance(250.0);
+        println!("ANIMATED STYLE TREE (t={}ms):\n{}", timeline.current_time(), timeline.sample(&style_root));
+    }
+
+    //change the DOM afterwards, only restyling what the change affects
+    let mut document = style::StyledDocument::new(html_parser::parse(fs::read_to_string("test.html").unwrap()));
+    println!("RESTYLED {} ELEMENTS", document.restyle(&cascade));
+    document.set_attribute(&[1, 3], "class", "r-side-bar"); //the <p>
+    println!("RESTYLED {} ELEMENTS", document.restyle(&cascade));
+    document.insert_child(&[1], 1, dom::elem("h2".to_string(), dom::AttrMap::new(), vec![]));
+    println!("RESTYLED {} ELEMENTS:\n{}", document.restyle(&cascade), document.style_tree());
 }
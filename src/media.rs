@@ -0,0 +1,248 @@
+use crate::css::MediaComparison;
+use crate::css::MediaCondition;
+use crate::css::MediaFeature;
+use crate::css::MediaQualifier;
+use crate::css::MediaQuery;
+use crate::css::MediaQueryList;
+use crate::css::MediaType;
+use crate::css::MediaValue;
+use crate::css::Unit;
+
+// The font size `em` and `rem` resolve against inside media queries.
+const INITIAL_FONT_SIZE: f32 = 16.0;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ColorScheme {
+    Light,
+    Dark,
+}
+
+// Everything media queries can ask about the device the page is rendered on.
+#[derive(Clone, PartialEq, Debug)]
+pub struct MediaEnvironment {
+    pub media_type: MediaType,
+    // viewport size in px
+    pub width: f32,
+    pub height: f32,
+    // device pixels per css pixel
+    pub resolution: f32,
+    pub color_scheme: ColorScheme,
+    pub reduced_motion: bool,
+}
+
+impl Default for MediaEnvironment {
+    fn default() -> Self {
+        MediaEnvironment {
+            media_type: MediaType::Screen,
+            width: 1024.0,
+            height: 768.0,
+            resolution: 1.0,
+            color_scheme: ColorScheme::Light,
+            reduced_motion: false,
+        }
+    }
+}
+
+// The value of a media feature in this environment. Range features are numbers in the
+// feature's canonical unit (px, dppx or a plain number), discrete features are keywords.
+enum FeatureValue {
+    Length(f32),
+    Number(f32),
+    Ratio(f32),
+    Resolution(f32),
+    Discrete(&'static str),
+}
+
+impl MediaEnvironment {
+    pub fn new(width: f32, height: f32) -> MediaEnvironment {
+        MediaEnvironment {width, height, ..Default::default()}
+    }
+
+    pub fn matches(&self, list: &MediaQueryList) -> bool {
+        list.queries.is_empty() || list.queries.iter().any(|query| self.matches_query(query))
+    }
+
+    pub fn matches_query(&self, query: &MediaQuery) -> bool {
+        let type_matches = match &query.media_type {
+            MediaType::All => true,
+            MediaType::Other(_) => false,
+            media_type => *media_type == self.media_type,
+        };
+        let result = type_matches && query.condition.iter().all(|condition| self.evaluate(condition));
+
+        if query.qualifier == Some(MediaQualifier::Not) {
+            !result
+        } else {
+            result
+        }
+    }
+
+    pub fn evaluate(&self, condition: &MediaCondition) -> bool {
+        self.evaluate_kleene(condition).unwrap_or(false)
+    }
+
+    // Media conditions use three valued logic: features we don't know about are "unknown"
+    // (None) rather than false, so that `not (unknown-feature)` doesn't match either.
+    fn evaluate_kleene(&self, condition: &MediaCondition) -> Option<bool> {
+        match condition {
+            MediaCondition::Feature(feature) => self.evaluate_feature(feature),
+            MediaCondition::Not(condition) => self.evaluate_kleene(condition).map(|result| !result),
+            MediaCondition::And(conditions) => {
+                let results: Vec<Option<bool>> = conditions.iter().map(|condition| self.evaluate_kleene(condition)).collect();
+                if results.contains(&Some(false)) {
+                    Some(false)
+                } else if results.contains(&None) {
+                    None
+                } else {
+                    Some(true)
+                }
+            },
+            MediaCondition::Or(conditions) => {
+                let results: Vec<Option<bool>> = conditions.iter().map(|condition| self.evaluate_kleene(condition)).collect();
+                if results.contains(&Some(true)) {
+                    Some(true)
+                } else if results.contains(&None) {
+                    None
+                } else {
+                    Some(false)
+                }
+            },
+            MediaCondition::Unknown(_) => None,
+        }
+    }
+
+    fn evaluate_feature(&self, feature: &MediaFeature) -> Option<bool> {
+        Some(match feature {
+            MediaFeature::Boolean(name) => match self.feature_value(name)? {
+                FeatureValue::Discrete(value) => value != "none" && value != "no-preference",
+                FeatureValue::Length(value) | FeatureValue::Number(value)
+                    | FeatureValue::Ratio(value) | FeatureValue::Resolution(value) => value != 0.0,
+            },
+            MediaFeature::Plain(name, value) => {
+                let (name, op) = if let Some(name) = name.strip_prefix("min-") {
+                    (name, MediaComparison::Ge)
+                } else if let Some(name) = name.strip_prefix("max-") {
+                    (name, MediaComparison::Le)
+                } else {
+                    (&**name, MediaComparison::Eq)
+                };
+                match self.feature_value(name)? {
+                    FeatureValue::Discrete(actual) => {
+                        op == MediaComparison::Eq && *value == MediaValue::Ident(actual.to_string())
+                    },
+                    actual => self.compare(&actual, op, value),
+                }
+            },
+            MediaFeature::Range(name, comparisons) => match self.feature_value(name)? {
+                FeatureValue::Discrete(_) => false,
+                actual => comparisons.iter().all(|(op, value)| self.compare(&actual, *op, value)),
+            },
+        })
+    }
+
+    fn feature_value(&self, name: &str) -> Option<FeatureValue> {
+        Some(match name {
+            "width" | "device-width" => FeatureValue::Length(self.width),
+            "height" | "device-height" => FeatureValue::Length(self.height),
+            "aspect-ratio" | "device-aspect-ratio" => FeatureValue::Ratio(self.width / self.height),
+            "resolution" => FeatureValue::Resolution(self.resolution),
+            "orientation" => FeatureValue::Discrete(if self.height >= self.width { "portrait" } else { "landscape" }),
+            "prefers-color-scheme" => FeatureValue::Discrete(match self.color_scheme {
+                ColorScheme::Light => "light",
+                ColorScheme::Dark => "dark",
+            }),
+            "prefers-reduced-motion" => FeatureValue::Discrete(if self.reduced_motion { "reduce" } else { "no-preference" }),
+            "color" => FeatureValue::Number(8.0),
+            "color-index" | "monochrome" | "grid" => FeatureValue::Number(0.0),
+            "hover" | "any-hover" => FeatureValue::Discrete(if self.media_type == MediaType::Print { "none" } else { "hover" }),
+            "pointer" | "any-pointer" => FeatureValue::Discrete(if self.media_type == MediaType::Print { "none" } else { "fine" }),
+            "update" => FeatureValue::Discrete(if self.media_type == MediaType::Print { "none" } else { "fast" }),
+            _ => return None,
+        })
+    }
+
+    fn compare(&self, actual: &FeatureValue, op: MediaComparison, value: &MediaValue) -> bool {
+        let (actual, expected) = match (actual, value) {
+            (FeatureValue::Length(actual), MediaValue::Length(len, unit)) => (*actual, match unit {
+                Unit::Em | Unit::Rem => len * INITIAL_FONT_SIZE,
+                _ => *len,
+            }),
+            // unitless zero is a valid length
+            (FeatureValue::Length(actual), MediaValue::Number(number)) if *number == 0.0 => (*actual, 0.0),
+            (FeatureValue::Number(actual), MediaValue::Number(number)) => (*actual, *number),
+            (FeatureValue::Ratio(actual), MediaValue::Ratio(num, den)) => (*actual, num / den),
+            (FeatureValue::Ratio(actual), MediaValue::Number(number)) => (*actual, *number),
+            (FeatureValue::Resolution(actual), MediaValue::Resolution(dppx)) => (*actual, *dppx),
+            _ => return false,
+        };
+
+        match op {
+            MediaComparison::Lt => actual < expected,
+            MediaComparison::Le => actual <= expected,
+            MediaComparison::Eq => (actual - expected).abs() < 0.001,
+            MediaComparison::Ge => actual >= expected,
+            MediaComparison::Gt => actual > expected,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::css_parser::parse_media_query_list;
+
+    // Whether `queries` match an 800x600 screen.
+    fn matches(queries: &str) -> bool {
+        MediaEnvironment::new(800.0, 600.0).matches(&parse_media_query_list(queries))
+    }
+
+    #[test]
+    fn media_types_and_qualifiers() {
+        assert!(matches("screen") && matches("all") && !matches("print"));
+        assert!(matches("only screen") && !matches("only print"));
+        assert!(!matches("not screen") && matches("not print"));
+        //not applies to the whole query, not just the type
+        assert!(matches("not screen and (max-width: 500px)"));
+        assert!(!matches("tv"));
+    }
+
+    #[test]
+    fn and_and_not() {
+        assert!(matches("screen and (min-width: 500px) and (orientation: landscape)"));
+        assert!(!matches("screen and (min-width: 500px) and (orientation: portrait)"));
+        assert!(matches("(not (color)) or (width > 700px)"));
+        assert!(matches("not (max-width: 500px)"));
+        assert!(!matches("(width > 500px) and (not (height > 500px))"));
+    }
+
+    #[test]
+    fn any_query_of_a_list_can_match() {
+        assert!(matches("print, (max-width: 500px), screen"));
+        assert!(!matches("print, (max-width: 500px)"));
+        //an empty list matches everything, one that fails to parse nothing
+        assert!(matches(""));
+        assert!(!matches("screen and"));
+        assert!(matches("screen and, screen"));
+    }
+
+    #[test]
+    fn width_ranges() {
+        assert!(matches("(min-width: 800px)") && matches("(max-width: 800px)") && matches("(width: 800px)"));
+        assert!(!matches("(min-width: 801px)") && !matches("(max-width: 799px)"));
+        //ems are of the initial font size
+        assert!(matches("(max-width: 50em)") && !matches("(max-width: 49em)"));
+        assert!(matches("(width >= 800px)") && !matches("(width > 800px)") && matches("(900px > width)"));
+        assert!(matches("(400px < width <= 800px)") && !matches("(400px < width < 800px)"));
+        assert!(matches("(min-width: 0)"));
+    }
+
+    #[test]
+    fn unknown_features_are_false() {
+        assert!(!matches("(frobnicate)") && !matches("(frobnicate: 1)"));
+        //and so is their negation
+        assert!(!matches("not (frobnicate)") && !matches("(not (frobnicate))"));
+        assert!(matches("(frobnicate) or (width)") && !matches("(frobnicate) and (width)"));
+        //a known feature with a value of the wrong type doesn't match either
+        assert!(!matches("(width: landscape)"));
+    }
+}
@@ -0,0 +1,632 @@
+// Painting, CSS 2.1 Appendix E: the display list of a laid out box tree, which says what to
+// draw and in which order. The boxes are painted in stacking contexts, each of which paints
+// its own background first, then the stacking contexts inside it with a negative z-index,
+// the blocks in flow, the floats, the inline content, the positioned boxes and those with a
+// z-index of 0 or auto, and last the ones with a positive z-index. Floats, inline-blocks and
+// positioned boxes without a z-index are painted in one go like a stacking context, except
+// for the positioned boxes and stacking contexts inside them, which belong to the enclosing
+// stacking context.
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::css::Color;
+use crate::css::Unit;
+use crate::css::Value;
+use crate::dom::NodeType;
+use crate::font::FontDatabase;
+use crate::font::FontQuery;
+use crate::font::ShapedGlyph;
+use crate::image::Image;
+use crate::layout::BoxType;
+use crate::layout::EdgeSizes;
+use crate::layout::LayoutBox;
+use crate::layout::Rect;
+use crate::style::PropertyMap;
+
+// A 2D affine transform [a, b, c, d, e, f], which takes x, y to a x + c y + e, b x + d y + f.
+pub type Matrix = [f32; 6];
+
+pub const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+// The horizontal and vertical radii of the top left, top right, bottom right and bottom left
+// corners of a rect, which are quarters of ellipses.
+pub type Radii = [(f32, f32); 4];
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum DisplayItem {
+    // a rect filled with a color
+    SolidColor { rect: Rect, color: Color },
+    // the same with its corners rounded
+    RoundedRect { rect: Rect, radii: Radii, color: Color },
+    // the border of a box whose border box is `rect`, each side as wide as `widths` says, with
+    // its outer corners rounded by `radii` and its inner ones by what is left of them
+    Border { rect: Rect, widths: EdgeSizes, radii: Radii, color: Color },
+    // glyphs along a baseline from `origin`, each at its x from there, in fonts of `size` px.
+    // Sideways text runs down the page with its glyphs turned clockwise.
+    Text { origin: (f32, f32), text: String, glyphs: Vec<ShapedGlyph>, size: f32, color: Color, sideways: bool },
+    // an image scaled to fill `rect`
+    Image { rect: Rect, image: Arc<Image> },
+    // what comes before the matching PopClip only shows inside `rect`
+    PushClip { rect: Rect },
+    PopClip,
+    // what comes before the matching PopOpacity is drawn on its own, then blended in with
+    // this opacity
+    PushOpacity { opacity: f32 },
+    PopOpacity,
+    // what comes before the matching PopTransform is drawn transformed by `matrix`
+    PushTransform { matrix: Matrix },
+    PopTransform,
+}
+
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct DisplayList {
+    pub items: Vec<DisplayItem>,
+}
+
+// The display list as text, an item a line and what is inside a push indented, which stays
+// the same as long as the painting does, so that display lists can be compared with a diff.
+// It is a debug format that only goes one way: images are written as their size and a hash
+// of their pixels, and glyphs without their advances, so it can't be read back in.
+impl fmt::Display for DisplayList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rect = |rect: &Rect| format!("{} {} {}x{}", rect.x, rect.y, rect.width, rect.height);
+        let color = |color: &Color| format!("#{:02x}{:02x}{:02x}{:02x}", color.r, color.g, color.b, color.a);
+        let radii = |radii: &Radii| match radii.iter().all(|&(x, y)| x == 0.0 && y == 0.0) {
+            true => String::new(),
+            false => format!(" radii {}", radii.iter().map(|(x, y)| format!("{}/{}", x, y)).collect::<Vec<String>>().join(" ")),
+        };
+        let mut depth = 0;
+        for item in &self.items {
+            if matches!(item, DisplayItem::PopClip | DisplayItem::PopOpacity | DisplayItem::PopTransform) {
+                depth -= 1;
+            }
+            let line = match item {
+                DisplayItem::SolidColor {rect: r, color: c} => format!("rect {} {}", rect(r), color(c)),
+                DisplayItem::RoundedRect {rect: r, radii: rs, color: c} => format!("rect {}{} {}", rect(r), radii(rs), color(c)),
+                DisplayItem::Border {rect: r, widths, radii: rs, color: c} => format!("border {} {}{} {}", rect(r), widths, radii(rs), color(c)),
+                DisplayItem::Text {origin, text, glyphs, size, color: c, sideways} => {
+                    let glyphs: Vec<String> = glyphs.iter().map(|glyph| format!("{}:{}@{}", glyph.face, glyph.glyph, glyph.x)).collect();
+                    format!("{} {} {} {}px {} {:?} {}", if *sideways { "sideways-text" } else { "text" }, origin.0, origin.1, size, color(c), text, glyphs.join(" "))
+                },
+                DisplayItem::Image {rect: r, image} => format!("image {} {}x{} {:016x}", rect(r), image.width, image.height, fingerprint(&image.pixels)),
+                DisplayItem::PushClip {rect: r} => format!("clip {}", rect(r)),
+                DisplayItem::PushOpacity {opacity} => format!("opacity {}", opacity),
+                DisplayItem::PushTransform {matrix} => format!("transform {:?}", matrix),
+                DisplayItem::PopClip | DisplayItem::PopOpacity | DisplayItem::PopTransform => "end".to_string(),
+            };
+            writeln!(f, "{}{}", "  ".repeat(depth), line)?;
+            if matches!(item, DisplayItem::PushClip {..} | DisplayItem::PushOpacity {..} | DisplayItem::PushTransform {..}) {
+                depth += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+// A 64-bit FNV-1a hash of the pixels of an image, which stands in for them in the text of a
+// display list.
+fn fingerprint(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+// Paints the box tree of the root element, whose background covers the whole of `canvas`,
+// the part of the page in view in the coordinates of the boxes, or the background of <body>
+// does if it has none, CSS 2.1 §14.2. Text is set in `fonts`.
+pub fn build_display_list(root: &LayoutBox, canvas: Rect, fonts: &FontDatabase) -> DisplayList {
+    let mut painter = Painter {fonts, items: vec![], canvas_background_of: None};
+    let body = root.children.iter().find(|child| tag_name(child) == Some("body"));
+    let background_of = match body {
+        Some(body) if background_color(root).is_none() && tag_name(root) == Some("html") => Some(body),
+        _ => Some(root),
+    };
+    if let Some(color) = background_of.and_then(background_color) {
+        painter.items.push(DisplayItem::SolidColor {rect: canvas, color});
+        painter.canvas_background_of = background_of;
+    }
+    painter.paint_stacking_context(root);
+    DisplayList {items: painter.items}
+}
+
+struct Painter<'b, 'a> {
+    fonts: &'b FontDatabase,
+    items: Vec<DisplayItem>,
+    // the box whose background was painted over the canvas instead of its border box
+    canvas_background_of: Option<&'b LayoutBox<'a>>,
+}
+
+// A positioned box or a stacking context in a stacking context, painted in the layer its
+// z-index puts it in. `clips` are the boxes between them that clip it, outermost first.
+struct Layer<'b, 'a> {
+    layout_box: &'b LayoutBox<'a>,
+    clips: Vec<&'b LayoutBox<'a>>,
+    z_index: i32,
+}
+
+impl<'b, 'a> Painter<'b, 'a> {
+    // Paints a stacking context in the order of Appendix E.2, inside the opacity and
+    // transform of its root.
+    fn paint_stacking_context(&mut self, root: &'b LayoutBox<'a>) {
+        let mut pops = vec![];
+        if let Some(matrix) = transform_matrix(root) {
+            self.items.push(DisplayItem::PushTransform {matrix});
+            pops.push(DisplayItem::PopTransform);
+        }
+        let opacity = number(root.style.get("opacity")).unwrap_or(1.0);
+        if opacity < 1.0 {
+            self.items.push(DisplayItem::PushOpacity {opacity: opacity.max(0.0)});
+            pops.push(DisplayItem::PopOpacity);
+        }
+
+        let mut layers = vec![];
+        collect_layers(root, &mut vec![root], &mut layers);
+        //sorting is stable, so layers with the same z-index stay in tree order
+        layers.sort_by_key(|layer| layer.z_index);
+
+        self.paint_decorations(root);
+        for layer in layers.iter().filter(|layer| layer.z_index < 0) {
+            self.paint_layer(layer);
+        }
+        self.clipped(root, Painter::paint_descendants);
+        for layer in layers.iter().filter(|layer| layer.z_index >= 0) {
+            self.paint_layer(layer);
+        }
+        self.items.extend(pops.into_iter().rev());
+    }
+
+    // Paints a positioned box or stacking context inside the clips of the boxes around it.
+    fn paint_layer(&mut self, layer: &Layer<'b, 'a>) {
+        let mut pops = vec![];
+        for clip in &layer.clips {
+            pops.extend(self.push_clip(clip));
+        }
+        match creates_stacking_context(layer.layout_box) {
+            true => self.paint_stacking_context(layer.layout_box),
+            false => self.paint_atomically(layer.layout_box),
+        }
+        self.items.extend(pops.into_iter().rev());
+    }
+
+    // Paints a box and its descendants as if it were a stacking context, for a float, an
+    // inline-block or a positioned box with z-index: auto, Appendix E.2 steps 5 and 7.2.1.
+    fn paint_atomically(&mut self, layout_box: &'b LayoutBox<'a>) {
+        self.paint_decorations(layout_box);
+        if let BoxType::InlineNode(_) = layout_box.box_type {
+            return self.paint_inline_content(layout_box);
+        }
+        self.clipped(layout_box, Painter::paint_descendants);
+    }
+
+    // The steps of a stacking context for the content of a block container that are done
+    // in one pass over the tree each: the backgrounds of the blocks in flow, then the floats,
+    // then the inline content and the image of a replaced box.
+    fn paint_descendants(&mut self, layout_box: &'b LayoutBox<'a>) {
+        self.paint_block_backgrounds(layout_box);
+        self.paint_floats(layout_box);
+        self.paint_image(layout_box);
+        self.paint_inline_content(layout_box);
+    }
+
+    fn paint_block_backgrounds(&mut self, layout_box: &'b LayoutBox<'a>) {
+        for child in layout_box.children.iter().filter(|child| is_in_flow_block(child)) {
+            self.paint_decorations(child);
+            self.clipped(child, Painter::paint_block_backgrounds);
+        }
+    }
+
+    fn paint_floats(&mut self, layout_box: &'b LayoutBox<'a>) {
+        for child in layout_box.children.iter().filter(|child| !is_layer(child)) {
+            if is_float(child) {
+                self.paint_atomically(child);
+            } else if is_in_flow_block(child) || matches!(child.box_type, BoxType::LineBox {..} | BoxType::InlineNode(_)) {
+                self.clipped(child, Painter::paint_floats);
+            }
+        }
+    }
+
+    fn paint_inline_content(&mut self, layout_box: &'b LayoutBox<'a>) {
+        for child in layout_box.children.iter().filter(|child| !is_layer(child) && !is_float(child)) {
+            match child.box_type {
+                BoxType::TextRun {..} => self.paint_text(child),
+                BoxType::LineBox {..} => self.paint_inline_content(child),
+                BoxType::InlineNode(_) => {
+                    self.paint_decorations(child);
+                    self.paint_inline_content(child);
+                },
+                BoxType::InlineBlockNode(_) => self.paint_atomically(child),
+                BoxType::BlockNode(_) | BoxType::AnonymousBlock => {
+                    self.clipped(child, |painter, child| {
+                        painter.paint_image(child);
+                        painter.paint_inline_content(child);
+                    });
+                },
+                BoxType::GeneratedText {..} => {},
+            }
+        }
+    }
+
+    // Paints with the clip of `layout_box` and its scroll offset if it has them, for painting
+    // what is inside it.
+    fn clipped(&mut self, layout_box: &'b LayoutBox<'a>, paint: impl FnOnce(&mut Self, &'b LayoutBox<'a>)) {
+        let start = self.items.len();
+        let pops = self.push_clip(layout_box);
+        let pushed = self.items.len();
+        paint(self, layout_box);
+        //a clip around nothing is left out
+        match self.items.len() == pushed {
+            true => self.items.truncate(start),
+            false => self.items.extend(pops.into_iter().rev()),
+        }
+    }
+
+    // Pushes the clip of a box that clips its overflow, and the scroll offset of a scroll
+    // container, returning the items that pop them again.
+    fn push_clip(&mut self, layout_box: &LayoutBox) -> Vec<DisplayItem> {
+        let mut pops = vec![];
+        if let Some(rect) = layout_box.overflow_clip_rect() {
+            self.items.push(DisplayItem::PushClip {rect});
+            pops.push(DisplayItem::PopClip);
+        }
+        let (x, y) = layout_box.scroll_offset;
+        if x != 0.0 || y != 0.0 {
+            self.items.push(DisplayItem::PushTransform {matrix: [1.0, 0.0, 0.0, 1.0, -x, -y]});
+            pops.push(DisplayItem::PopTransform);
+        }
+        pops
+    }
+
+    // The background and border of a box.
+    fn paint_decorations(&mut self, layout_box: &LayoutBox) {
+        let rect = layout_box.dimensions.border_box();
+        let radii = border_radii(layout_box);
+        let on_canvas = self.canvas_background_of.is_some_and(|of| std::ptr::eq(of, layout_box));
+        if let Some(color) = background_color(layout_box).filter(|_| !on_canvas) {
+            self.items.push(match radii == Radii::default() {
+                true => DisplayItem::SolidColor {rect, color},
+                false => DisplayItem::RoundedRect {rect, radii, color},
+            });
+        }
+        let widths = layout_box.dimensions.border;
+        let color = match layout_box.style.get("border-color") {
+            Some(Value::Color(color)) if color.a > 0 => color.clone(),
+            _ => return,
+        };
+        //the sides without a style have no width by now, and the others are all painted solid:
+        //there are no dots, dashes, double lines or 3D effects
+        if widths != EdgeSizes::default() {
+            self.items.push(DisplayItem::Border {rect, widths, radii, color});
+        }
+    }
+
+    // The image of a replaced box, cut off at its content box.
+    fn paint_image(&mut self, layout_box: &LayoutBox) {
+        let (image, rect) = match (&layout_box.image, layout_box.image_rect()) {
+            (Some(image), Some(rect)) => (image.clone(), rect),
+            _ => return,
+        };
+        let content = layout_box.dimensions.content;
+        let inside = rect.x >= content.x && rect.y >= content.y
+            && rect.x + rect.width <= content.x + content.width && rect.y + rect.height <= content.y + content.height;
+        if !inside {
+            self.items.push(DisplayItem::PushClip {rect: content});
+        }
+        self.items.push(DisplayItem::Image {rect, image});
+        if !inside {
+            self.items.push(DisplayItem::PopClip);
+        }
+    }
+
+    // The glyphs of a text run on the baseline of its font. In a vertical writing mode the run
+    // is turned clockwise, with the ascent on the right.
+    fn paint_text(&mut self, run: &LayoutBox) {
+        let (text, glyphs) = match &run.box_type {
+            BoxType::TextRun {text, glyphs, ..} => (text, glyphs),
+            _ => return,
+        };
+        let color = match run.style.get("color") {
+            Some(Value::Color(color)) => color.clone(),
+            _ => Color {r: 0, g: 0, b: 0, a: 255},
+        };
+        if glyphs.is_empty() || color.a == 0 {
+            return;
+        }
+        let size = match run.style.get("font-size") {
+            Some(Value::Length(size, Unit::Px)) => *size,
+            _ => 16.0,
+        };
+        let metrics = self.fonts.metrics(&FontQuery::from_style(&run.style), size);
+        let content = run.dimensions.content;
+        let sideways = matches!(run.style.get("writing-mode"), Some(Value::Keyword(mode)) if mode.starts_with("vertical"));
+        let origin = match sideways {
+            true => (content.x + content.width - metrics.ascent, content.y),
+            false => (content.x, content.y + metrics.ascent),
+        };
+        self.items.push(DisplayItem::Text {origin, text: text.clone(), glyphs: glyphs.clone(), size, color, sideways});
+    }
+}
+
+// Finds the positioned boxes and stacking contexts that are painted in the layers of the
+// stacking context of `path[0]`, which aren't inside another stacking context. `path` has the
+// boxes from the root of the stacking context down to `layout_box`.
+fn collect_layers<'b, 'a>(layout_box: &'b LayoutBox<'a>, path: &mut Vec<&'b LayoutBox<'a>>, layers: &mut Vec<Layer<'b, 'a>>) {
+    for child in &layout_box.children {
+        if is_layer(child) {
+            layers.push(Layer {layout_box: child, clips: clips_of(child, path), z_index: z_index(child)});
+            if creates_stacking_context(child) {
+                continue;
+            }
+        }
+        path.push(child);
+        collect_layers(child, path, layers);
+        path.pop();
+    }
+}
+
+// The boxes on `path`, the ancestors of a box, that clip it. Those outside the containing
+// block of an absolutely positioned box don't, and nothing does a fixed box, which is in the
+// viewport.
+fn clips_of<'b, 'a>(layout_box: &LayoutBox, path: &[&'b LayoutBox<'a>]) -> Vec<&'b LayoutBox<'a>> {
+    let ancestors = match keyword(&layout_box.style, "position") {
+        "fixed" => &path[..0],
+        "absolute" => match path.iter().rposition(|ancestor| is_positioned(ancestor)) {
+            Some(containing_block) => &path[..=containing_block],
+            None => &path[..0],
+        },
+        _ => path,
+    };
+    ancestors.iter().copied().filter(|ancestor| ancestor.overflow_clip_rect().is_some() || ancestor.scroll_offset != (0.0, 0.0)).collect()
+}
+
+fn keyword<'s>(style: &'s PropertyMap, name: &str) -> &'s str {
+    match style.get(name) {
+        Some(Value::Keyword(keyword)) => keyword,
+        _ => "",
+    }
+}
+
+fn number(value: Option<&Value>) -> Option<f32> {
+    match value {
+        Some(Value::Length(number, Unit::None)) => Some(*number),
+        _ => None,
+    }
+}
+
+fn tag_name<'s>(layout_box: &LayoutBox<'s>) -> Option<&'s str> {
+    match layout_box.box_type {
+        BoxType::BlockNode(node) | BoxType::InlineNode(node) | BoxType::InlineBlockNode(node) => match &node.node.node_type {
+            NodeType::Element(elem) if node.pseudo_element.is_none() => Some(&elem.tag_name),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn background_color(layout_box: &LayoutBox) -> Option<Color> {
+    match layout_box.style.get("background-color") {
+        Some(Value::Color(color)) if color.a > 0 => Some(color.clone()),
+        _ => None,
+    }
+}
+
+// The radii of the corners of the border box of a box, percentages being of its width for the
+// horizontal ones and of its height for the vertical ones. Where those of two corners on a
+// side add up to more than its length all of them are scaled down to fit, CSS Backgrounds 3
+// §5.5.
+fn border_radii(layout_box: &LayoutBox) -> Radii {
+    let border_box = layout_box.dimensions.border_box();
+    let (width, height) = (border_box.width, border_box.height);
+    let corner = |name: &str| match layout_box.style.get(name) {
+        Some(Value::Length(radius, Unit::Px)) => (radius.max(0.0), radius.max(0.0)),
+        Some(Value::Percentage(percentage)) => (percentage.max(0.0) / 100.0 * width, percentage.max(0.0) / 100.0 * height),
+        _ => (0.0, 0.0),
+    };
+    let radii = ["border-top-left-radius", "border-top-right-radius", "border-bottom-right-radius", "border-bottom-left-radius"].map(corner);
+    let [top_left, top_right, bottom_right, bottom_left] = radii;
+    let fit = |length: f32, sum: f32| if sum > 0.0 { length / sum } else { 1.0 };
+    let factor = fit(width, top_left.0 + top_right.0)
+        .min(fit(height, top_right.1 + bottom_right.1))
+        .min(fit(width, bottom_right.0 + bottom_left.0))
+        .min(fit(height, bottom_left.1 + top_left.1));
+    match factor < 1.0 {
+        true => radii.map(|(x, y)| (x * factor, y * factor)),
+        false => radii,
+    }
+}
+
+fn is_positioned(layout_box: &LayoutBox) -> bool {
+    !matches!(keyword(&layout_box.style, "position"), "static" | "")
+}
+
+fn is_float(layout_box: &LayoutBox) -> bool {
+    matches!(keyword(&layout_box.style, "float"), "left" | "right")
+}
+
+fn is_in_flow_block(layout_box: &LayoutBox) -> bool {
+    matches!(layout_box.box_type, BoxType::BlockNode(_) | BoxType::AnonymousBlock) && !is_float(layout_box) && !is_layer(layout_box)
+}
+
+// Whether a box is painted in a layer of its stacking context rather than with the content
+// around it.
+fn is_layer(layout_box: &LayoutBox) -> bool {
+    is_positioned(layout_box) || creates_stacking_context(layout_box)
+}
+
+// Whether a box is the root of a stacking context of its own: a positioned box with a z-index,
+// a fixed one, and one with opacity, a transform or isolation: isolate, CSS 2.1 §9.9.1, CSS
+// Color 4 §14, CSS Transforms 1 §3 and Compositing 1 §3.2.
+fn creates_stacking_context(layout_box: &LayoutBox) -> bool {
+    let position = keyword(&layout_box.style, "position");
+    (is_positioned(layout_box) && number(layout_box.style.get("z-index")).is_some())
+        || position == "fixed"
+        || number(layout_box.style.get("opacity")).is_some_and(|opacity| opacity < 1.0)
+        || transform_matrix(layout_box).is_some()
+        || keyword(&layout_box.style, "isolation") == "isolate"
+}
+
+// The layer z-index puts a box in, where auto goes with 0.
+fn z_index(layout_box: &LayoutBox) -> i32 {
+    match number(layout_box.style.get("z-index")) {
+        Some(z_index) if is_positioned(layout_box) => z_index as i32,
+        _ => 0,
+    }
+}
+
+// The transform that does `b` and then `a`.
+pub(crate) fn multiply(a: Matrix, b: Matrix) -> Matrix {
+    [
+        a[0] * b[0] + a[2] * b[1],
+        a[1] * b[0] + a[3] * b[1],
+        a[0] * b[2] + a[2] * b[3],
+        a[1] * b[2] + a[3] * b[3],
+        a[0] * b[4] + a[2] * b[5] + a[4],
+        a[1] * b[4] + a[3] * b[5] + a[5],
+    ]
+}
+
+// The matrix of the transform of a box, CSS Transforms 1 §7, about the center of its border
+// box. None if it has none.
+fn transform_matrix(layout_box: &LayoutBox) -> Option<Matrix> {
+    let functions = match layout_box.style.get("transform") {
+        Some(Value::Keyword(_)) | None => return None,
+        Some(value) => value.components(),
+    };
+    let border_box = layout_box.dimensions.border_box();
+    let length = |value: &Value, reference: f32| match value {
+        Value::Length(length, _) => *length,
+        Value::Percentage(percentage) => percentage / 100.0 * reference,
+        _ => 0.0,
+    };
+    let angle = |value: &Value| match value {
+        Value::Angle(degrees) => degrees.to_radians(),
+        _ => 0.0,
+    };
+    let factor = |value: &Value| match value {
+        Value::Length(number, Unit::None) => *number,
+        Value::Percentage(percentage) => percentage / 100.0,
+        _ => 1.0,
+    };
+    let (width, height) = (border_box.width, border_box.height);
+    let mut matrix = IDENTITY;
+    for function in functions {
+        let (name, args) = match function {
+            Value::Function(name, args) => (name, args.as_slice()),
+            _ => continue,
+        };
+        let step = match (&**name, args) {
+            ("translate", [x]) | ("translatex", [x]) => [1.0, 0.0, 0.0, 1.0, length(x, width), 0.0],
+            ("translate", [x, y]) => [1.0, 0.0, 0.0, 1.0, length(x, width), length(y, height)],
+            ("translatey", [y]) => [1.0, 0.0, 0.0, 1.0, 0.0, length(y, height)],
+            ("scale", [scale]) => [factor(scale), 0.0, 0.0, factor(scale), 0.0, 0.0],
+            ("scale", [x, y]) => [factor(x), 0.0, 0.0, factor(y), 0.0, 0.0],
+            ("scalex", [x]) => [factor(x), 0.0, 0.0, 1.0, 0.0, 0.0],
+            ("scaley", [y]) => [1.0, 0.0, 0.0, factor(y), 0.0, 0.0],
+            ("rotate", [a]) => {
+                let (sin, cos) = angle(a).sin_cos();
+                [cos, sin, -sin, cos, 0.0, 0.0]
+            },
+            ("skew", [x]) | ("skewx", [x]) => [1.0, 0.0, angle(x).tan(), 1.0, 0.0, 0.0],
+            ("skew", [x, y]) => [1.0, angle(y).tan(), angle(x).tan(), 1.0, 0.0, 0.0],
+            ("skewy", [y]) => [1.0, angle(y).tan(), 0.0, 1.0, 0.0, 0.0],
+            ("matrix", [a, b, c, d, e, f]) => [factor(a), factor(b), factor(c), factor(d), length(e, 0.0), length(f, 0.0)],
+            _ => continue,
+        };
+        matrix = multiply(matrix, step);
+    }
+    let (x, y) = (border_box.x + width / 2.0, border_box.y + height / 2.0);
+    Some(multiply(multiply([1.0, 0.0, 0.0, 1.0, x, y], matrix), [1.0, 0.0, 0.0, 1.0, -x, -y]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::css_parser;
+    use crate::html_parser;
+    use crate::image::ImageStore;
+    use crate::layout;
+    use crate::media::MediaEnvironment;
+    use crate::style;
+
+    // The display list of `html` styled by `css` in an 800x600 viewport, as text.
+    fn display_list(html: &str, css: &str) -> String {
+        let root = html_parser::parse(html.to_string());
+        let stylesheet = css_parser::parse(format!("html, body, div, p {{ display: block }} {}", css));
+        let cascade = style::Cascade::new(&stylesheet, MediaEnvironment::default());
+        let styled = style::style_tree(&root, &cascade);
+        let fonts = FontDatabase::new();
+        let root_box = layout::layout_tree(&styled, 800.0, 600.0, &fonts, &ImageStore::new());
+        build_display_list(&root_box, Rect {x: 0.0, y: 0.0, width: 800.0, height: 600.0}, &fonts).to_string()
+    }
+
+    #[test]
+    fn borders_paint_the_sides_with_a_style() {
+        let html = "<html><body><div></div></body></html>";
+        let css = "div { width: 10px; height: 10px; border-width: 2px 4px; border-color: black }";
+        //without a style a side has no width, so there is nothing to paint
+        assert_eq!(display_list(html, css), "");
+        //a dashed side is painted like a solid one
+        assert_eq!(display_list(html, &format!("{} div {{ border-style: solid none dashed }}", css)), "border 0 0 10x14 2 0 2 0 #000000ff\n");
+    }
+
+    #[test]
+    fn stacking_context_paints_in_appendix_e_order() {
+        //in the tree the positioned box comes first and the one with a negative z-index last
+        let html = "<html><body><div id=pos></div><div id=float></div><div id=block><span id=inline>a</span></div><div id=neg></div></body></html>";
+        let css = "div { height: 10px } #pos { position: relative; background-color: #000001 } \
+            #float { float: left; width: 10px; background-color: #000002 } #block { background-color: #000003 } \
+            #inline { background-color: #000004 } #neg { position: absolute; z-index: -1; width: 10px; background-color: #000005 }";
+        assert_eq!(display_list(html, css), "\
+            rect 0 20 10x10 #000005ff\n\
+            rect 0 10 800x10 #000003ff\n\
+            rect 0 10 10x10 #000002ff\n\
+            rect 10 10 9.8046875x18.625 #000004ff\n\
+            text 10 24.851563 16px #000000ff \"a\" 0:68@0\n\
+            rect 0 0 800x10 #000001ff\n");
+    }
+
+    #[test]
+    fn opacity_makes_a_stacking_context() {
+        //the z-index of #b only orders it inside #a, which is painted below #c
+        let html = "<html><body><div id=a><div id=b></div></div><div id=c></div></body></html>";
+        let css = "div { height: 10px } #a { opacity: 0.5; background-color: #000001 } \
+            #b { position: relative; z-index: 10; background-color: #000002 } #c { position: relative; z-index: 1; background-color: #000003 }";
+        assert_eq!(display_list(html, css), "\
+            opacity 0.5\n  \
+            rect 0 0 800x10 #000001ff\n  \
+            rect 0 0 800x10 #000002ff\n\
+            end\n\
+            rect 0 10 800x10 #000003ff\n");
+
+        //without the opacity #b goes above #c
+        let css = "div { height: 10px } #a { background-color: #000001 } \
+            #b { position: relative; z-index: 10; background-color: #000002 } #c { position: relative; z-index: 1; background-color: #000003 }";
+        assert_eq!(display_list(html, css), "\
+            rect 0 0 800x10 #000001ff\n\
+            rect 0 10 800x10 #000003ff\n\
+            rect 0 0 800x10 #000002ff\n");
+    }
+
+    #[test]
+    fn display_list_as_text() {
+        let rect = Rect {x: 1.0, y: 2.0, width: 30.0, height: 40.5};
+        let red = Color {r: 255, g: 0, b: 0, a: 255};
+        let list = DisplayList {items: vec![
+            DisplayItem::SolidColor {rect, color: red.clone()},
+            DisplayItem::PushOpacity {opacity: 0.5},
+            DisplayItem::PushClip {rect},
+            DisplayItem::RoundedRect {rect, radii: [(4.0, 4.0), (0.0, 0.0), (0.0, 0.0), (0.0, 0.0)], color: Color {a: 128, ..red}},
+            DisplayItem::PopClip,
+            DisplayItem::Image {rect, image: Arc::new(Image::new(2, 1))},
+            DisplayItem::PopOpacity,
+        ]};
+        assert_eq!(list.to_string(), [
+            "rect 1 2 30x40.5 #ff0000ff",
+            "opacity 0.5",
+            "  clip 1 2 30x40.5",
+            "    rect 1 2 30x40.5 radii 4/4 0/0 0/0 0/0 #ff000080",
+            "  end",
+            &format!("  image 1 2 30x40.5 2x1 {:016x}", fingerprint(&[0; 8])),
+            "end",
+            "",
+        ].join("\n"));
+    }
+}
@@ -0,0 +1,745 @@
+use crate::css::Declaration;
+use crate::css::SupportsCondition;
+use crate::css::Unit;
+use crate::css::Value;
+use crate::css_parser;
+
+enum ValueType {
+    Length,
+    // a percentage of some other size, which one depends on the property
+    Percentage,
+    Number,
+    Color,
+    Time,
+    // a timing function like ease-in or cubic-bezier(...)
+    Easing,
+    // a list of transform functions
+    Transform,
+    // any identifier, like an animation name
+    Ident,
+    // a font family name, quoted or as one or more identifiers
+    FamilyName,
+    Keywords(&'static [&'static str]),
+    // one or more of the keywords, each at most once
+    KeywordSet(&'static [&'static str]),
+    // grid track sizes, minmax(), fit-content() and repeat(), with line names between them
+    TrackList,
+    // one or more strings
+    Strings,
+    // where a grid item starts or ends: auto, a line number or name, or a span
+    GridLine,
+    // one or two lengths, like a horizontal and a vertical spacing
+    LengthPair,
+    // a width to height ratio, a number or two numbers with a slash between them
+    Ratio,
+    // where something goes in a box: one or two lengths, percentages or side keywords
+    Position,
+    // counter names, each of which may be followed by an integer
+    CounterChanges,
+    // the same, where a name may also be reversed(name)
+    CounterResets,
+    // strings, counter(), counters() and attr(), the text generated content is made of
+    Content,
+}
+
+use ValueType::*;
+
+struct Property {
+    name: &'static str,
+    values: &'static [ValueType],
+    // parsed with css_parser::parse_value
+    initial: &'static str,
+    inherited: bool,
+}
+
+// Every style is painted as solid. Besides choosing between collapsing table borders, only
+// none and hidden make a difference to layout.
+const BORDER_STYLES: &[&str] = &["none", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset", "outset"];
+
+// The properties the engine actually does something with, and the values it understands
+// for each. This is what @supports is answered from, so a property only goes in here once
+// it is implemented. Border widths start at 0 rather than `medium`, since with the initial
+// border-style of none they compute to 0 anyway.
+const PROPERTIES: &[Property] = &[
+    Property {name: "display", values: &[Keywords(&["block", "inline", "inline-block", "flex", "inline-flex", "grid", "inline-grid", "table", "inline-table", "table-row-group", "table-header-group", "table-footer-group", "table-row", "table-cell", "table-column-group", "table-column", "table-caption", "flow-root", "list-item", "none"])], initial: "inline", inherited: false},
+    Property {name: "color", values: &[Color], initial: "black", inherited: true},
+    Property {name: "background-color", values: &[Color], initial: "transparent", inherited: false},
+    Property {name: "width", values: &[Length, Percentage, Keywords(&["auto"])], initial: "auto", inherited: false},
+    Property {name: "height", values: &[Length, Percentage, Keywords(&["auto"])], initial: "auto", inherited: false},
+    Property {name: "min-width", values: &[Length, Percentage, Keywords(&["auto"])], initial: "auto", inherited: false},
+    Property {name: "max-width", values: &[Length, Percentage, Keywords(&["none"])], initial: "none", inherited: false},
+    Property {name: "min-height", values: &[Length, Percentage, Keywords(&["auto"])], initial: "auto", inherited: false},
+    Property {name: "max-height", values: &[Length, Percentage, Keywords(&["none"])], initial: "none", inherited: false},
+    Property {name: "aspect-ratio", values: &[Ratio, Keywords(&["auto"])], initial: "auto", inherited: false},
+    Property {name: "object-fit", values: &[Keywords(&["fill", "contain", "cover", "none", "scale-down"])], initial: "fill", inherited: false},
+    Property {name: "object-position", values: &[Position], initial: "50% 50%", inherited: false},
+    Property {name: "box-sizing", values: &[Keywords(&["content-box", "border-box"])], initial: "content-box", inherited: false},
+    Property {name: "margin-top", values: &[Length, Percentage, Keywords(&["auto"])], initial: "0px", inherited: false},
+    Property {name: "margin-right", values: &[Length, Percentage, Keywords(&["auto"])], initial: "0px", inherited: false},
+    Property {name: "margin-bottom", values: &[Length, Percentage, Keywords(&["auto"])], initial: "0px", inherited: false},
+    Property {name: "margin-left", values: &[Length, Percentage, Keywords(&["auto"])], initial: "0px", inherited: false},
+    Property {name: "padding-top", values: &[Length, Percentage], initial: "0px", inherited: false},
+    Property {name: "padding-right", values: &[Length, Percentage], initial: "0px", inherited: false},
+    Property {name: "padding-bottom", values: &[Length, Percentage], initial: "0px", inherited: false},
+    Property {name: "padding-left", values: &[Length, Percentage], initial: "0px", inherited: false},
+    Property {name: "border-top-width", values: &[Length], initial: "0px", inherited: false},
+    Property {name: "border-right-width", values: &[Length], initial: "0px", inherited: false},
+    Property {name: "border-bottom-width", values: &[Length], initial: "0px", inherited: false},
+    Property {name: "border-left-width", values: &[Length], initial: "0px", inherited: false},
+    Property {name: "border-top-style", values: &[Keywords(BORDER_STYLES)], initial: "none", inherited: false},
+    Property {name: "border-right-style", values: &[Keywords(BORDER_STYLES)], initial: "none", inherited: false},
+    Property {name: "border-bottom-style", values: &[Keywords(BORDER_STYLES)], initial: "none", inherited: false},
+    Property {name: "border-left-style", values: &[Keywords(BORDER_STYLES)], initial: "none", inherited: false},
+    Property {name: "border-top-left-radius", values: &[Length, Percentage], initial: "0px", inherited: false},
+    Property {name: "border-top-right-radius", values: &[Length, Percentage], initial: "0px", inherited: false},
+    Property {name: "border-bottom-right-radius", values: &[Length, Percentage], initial: "0px", inherited: false},
+    Property {name: "border-bottom-left-radius", values: &[Length, Percentage], initial: "0px", inherited: false},
+    Property {name: "margin-block-start", values: &[Length, Percentage, Keywords(&["auto"])], initial: "0px", inherited: false},
+    Property {name: "margin-block-end", values: &[Length, Percentage, Keywords(&["auto"])], initial: "0px", inherited: false},
+    Property {name: "margin-inline-start", values: &[Length, Percentage, Keywords(&["auto"])], initial: "0px", inherited: false},
+    Property {name: "margin-inline-end", values: &[Length, Percentage, Keywords(&["auto"])], initial: "0px", inherited: false},
+    Property {name: "padding-block-start", values: &[Length, Percentage], initial: "0px", inherited: false},
+    Property {name: "padding-block-end", values: &[Length, Percentage], initial: "0px", inherited: false},
+    Property {name: "padding-inline-start", values: &[Length, Percentage], initial: "0px", inherited: false},
+    Property {name: "padding-inline-end", values: &[Length, Percentage], initial: "0px", inherited: false},
+    Property {name: "border-block-start-width", values: &[Length], initial: "0px", inherited: false},
+    Property {name: "border-block-end-width", values: &[Length], initial: "0px", inherited: false},
+    Property {name: "border-inline-start-width", values: &[Length], initial: "0px", inherited: false},
+    Property {name: "border-inline-end-width", values: &[Length], initial: "0px", inherited: false},
+    Property {name: "border-block-start-style", values: &[Keywords(BORDER_STYLES)], initial: "none", inherited: false},
+    Property {name: "border-block-end-style", values: &[Keywords(BORDER_STYLES)], initial: "none", inherited: false},
+    Property {name: "border-inline-start-style", values: &[Keywords(BORDER_STYLES)], initial: "none", inherited: false},
+    Property {name: "border-inline-end-style", values: &[Keywords(BORDER_STYLES)], initial: "none", inherited: false},
+    Property {name: "inline-size", values: &[Length, Percentage, Keywords(&["auto"])], initial: "auto", inherited: false},
+    Property {name: "block-size", values: &[Length, Percentage, Keywords(&["auto"])], initial: "auto", inherited: false},
+    Property {name: "min-inline-size", values: &[Length, Percentage, Keywords(&["auto"])], initial: "auto", inherited: false},
+    Property {name: "min-block-size", values: &[Length, Percentage, Keywords(&["auto"])], initial: "auto", inherited: false},
+    Property {name: "max-inline-size", values: &[Length, Percentage, Keywords(&["none"])], initial: "none", inherited: false},
+    Property {name: "max-block-size", values: &[Length, Percentage, Keywords(&["none"])], initial: "none", inherited: false},
+    Property {name: "flex-direction", values: &[Keywords(&["row", "row-reverse", "column", "column-reverse"])], initial: "row", inherited: false},
+    Property {name: "flex-wrap", values: &[Keywords(&["nowrap", "wrap", "wrap-reverse"])], initial: "nowrap", inherited: false},
+    Property {name: "flex-grow", values: &[Number], initial: "0", inherited: false},
+    Property {name: "flex-shrink", values: &[Number], initial: "1", inherited: false},
+    Property {name: "flex-basis", values: &[Length, Percentage, Keywords(&["auto", "content"])], initial: "auto", inherited: false},
+    Property {name: "order", values: &[Number], initial: "0", inherited: false},
+    Property {name: "justify-content", values: &[Keywords(&["normal", "flex-start", "flex-end", "start", "end", "left", "right", "center", "space-between", "space-around", "space-evenly"])], initial: "normal", inherited: false},
+    Property {name: "align-items", values: &[Keywords(&["normal", "stretch", "flex-start", "flex-end", "start", "end", "self-start", "self-end", "center", "baseline"])], initial: "normal", inherited: false},
+    Property {name: "align-self", values: &[Keywords(&["auto", "normal", "stretch", "flex-start", "flex-end", "start", "end", "self-start", "self-end", "center", "baseline"])], initial: "auto", inherited: false},
+    Property {name: "align-content", values: &[Keywords(&["normal", "stretch", "flex-start", "flex-end", "start", "end", "center", "space-between", "space-around", "space-evenly"])], initial: "normal", inherited: false},
+    Property {name: "row-gap", values: &[Length, Percentage, Keywords(&["normal"])], initial: "normal", inherited: false},
+    Property {name: "column-gap", values: &[Length, Percentage, Keywords(&["normal"])], initial: "normal", inherited: false},
+    Property {name: "grid-template-columns", values: &[TrackList, Keywords(&["none"])], initial: "none", inherited: false},
+    Property {name: "grid-template-rows", values: &[TrackList, Keywords(&["none"])], initial: "none", inherited: false},
+    Property {name: "grid-template-areas", values: &[Strings, Keywords(&["none"])], initial: "none", inherited: false},
+    Property {name: "grid-auto-columns", values: &[TrackList], initial: "auto", inherited: false},
+    Property {name: "grid-auto-rows", values: &[TrackList], initial: "auto", inherited: false},
+    Property {name: "grid-auto-flow", values: &[KeywordSet(&["row", "column", "dense"])], initial: "row", inherited: false},
+    Property {name: "grid-row-start", values: &[GridLine], initial: "auto", inherited: false},
+    Property {name: "grid-row-end", values: &[GridLine], initial: "auto", inherited: false},
+    Property {name: "grid-column-start", values: &[GridLine], initial: "auto", inherited: false},
+    Property {name: "grid-column-end", values: &[GridLine], initial: "auto", inherited: false},
+    Property {name: "justify-items", values: &[Keywords(&["normal", "stretch", "start", "end", "self-start", "self-end", "center", "left", "right", "baseline"])], initial: "normal", inherited: false},
+    Property {name: "justify-self", values: &[Keywords(&["auto", "normal", "stretch", "start", "end", "self-start", "self-end", "center", "left", "right", "baseline"])], initial: "auto", inherited: false},
+    Property {name: "position", values: &[Keywords(&["static", "relative", "absolute", "fixed", "sticky"])], initial: "static", inherited: false},
+    Property {name: "top", values: &[Length, Percentage, Keywords(&["auto"])], initial: "auto", inherited: false},
+    Property {name: "right", values: &[Length, Percentage, Keywords(&["auto"])], initial: "auto", inherited: false},
+    Property {name: "bottom", values: &[Length, Percentage, Keywords(&["auto"])], initial: "auto", inherited: false},
+    Property {name: "left", values: &[Length, Percentage, Keywords(&["auto"])], initial: "auto", inherited: false},
+    Property {name: "z-index", values: &[Number, Keywords(&["auto"])], initial: "auto", inherited: false},
+    Property {name: "float", values: &[Keywords(&["none", "left", "right"])], initial: "none", inherited: false},
+    Property {name: "clear", values: &[Keywords(&["none", "left", "right", "both"])], initial: "none", inherited: false},
+    Property {name: "overflow-x", values: &[Keywords(&["visible", "hidden", "clip", "scroll", "auto"])], initial: "visible", inherited: false},
+    Property {name: "overflow-y", values: &[Keywords(&["visible", "hidden", "clip", "scroll", "auto"])], initial: "visible", inherited: false},
+    Property {name: "text-overflow", values: &[Keywords(&["clip", "ellipsis"])], initial: "clip", inherited: false},
+    Property {name: "table-layout", values: &[Keywords(&["auto", "fixed"])], initial: "auto", inherited: false},
+    Property {name: "border-collapse", values: &[Keywords(&["separate", "collapse"])], initial: "separate", inherited: true},
+    Property {name: "border-spacing", values: &[LengthPair], initial: "0px", inherited: true},
+    Property {name: "caption-side", values: &[Keywords(&["top", "bottom"])], initial: "top", inherited: true},
+    Property {name: "border-color", values: &[Color], initial: "currentcolor", inherited: false},
+    Property {name: "font-family", values: &[FamilyName], initial: "sans-serif", inherited: true},
+    Property {name: "font-size", values: &[Length], initial: "16px", inherited: true},
+    Property {name: "font-weight", values: &[Number, Keywords(&["normal", "bold", "bolder", "lighter"])], initial: "normal", inherited: true},
+    Property {name: "font-style", values: &[Keywords(&["normal", "italic", "oblique"])], initial: "normal", inherited: true},
+    Property {name: "line-height", values: &[Length, Percentage, Number, Keywords(&["normal"])], initial: "normal", inherited: true},
+    Property {name: "vertical-align", values: &[Length, Percentage, Keywords(&["baseline", "sub", "super", "text-top", "text-bottom", "middle", "top", "bottom"])], initial: "baseline", inherited: false},
+    Property {name: "text-align", values: &[Keywords(&["start", "end", "left", "right", "center", "justify"])], initial: "start", inherited: true},
+    Property {name: "direction", values: &[Keywords(&["ltr", "rtl"])], initial: "ltr", inherited: true},
+    Property {name: "unicode-bidi", values: &[Keywords(&["normal", "embed", "isolate", "bidi-override", "isolate-override", "plaintext"])], initial: "normal", inherited: false},
+    Property {name: "writing-mode", values: &[Keywords(&["horizontal-tb", "vertical-rl", "vertical-lr"])], initial: "horizontal-tb", inherited: true},
+    Property {name: "white-space", values: &[Keywords(&["normal", "pre", "nowrap", "pre-wrap", "pre-line"])], initial: "normal", inherited: true},
+    Property {name: "list-style-type", values: &[Keywords(LIST_STYLE_TYPES), Strings], initial: "disc", inherited: true},
+    Property {name: "list-style-position", values: &[Keywords(&["inside", "outside"])], initial: "outside", inherited: true},
+    Property {name: "content", values: &[Content, Keywords(&["normal", "none"])], initial: "normal", inherited: false},
+    Property {name: "counter-reset", values: &[CounterResets, Keywords(&["none"])], initial: "none", inherited: false},
+    Property {name: "counter-increment", values: &[CounterChanges, Keywords(&["none"])], initial: "none", inherited: false},
+    Property {name: "counter-set", values: &[CounterChanges, Keywords(&["none"])], initial: "none", inherited: false},
+    Property {name: "opacity", values: &[Number], initial: "1", inherited: false},
+    Property {name: "transform", values: &[Transform, Keywords(&["none"])], initial: "none", inherited: false},
+    Property {name: "isolation", values: &[Keywords(&["auto", "isolate"])], initial: "auto", inherited: false},
+    Property {name: "animation-name", values: &[Ident], initial: "none", inherited: false},
+    Property {name: "animation-duration", values: &[Time], initial: "0s", inherited: false},
+    Property {name: "animation-timing-function", values: &[Easing], initial: "ease", inherited: false},
+    Property {name: "animation-delay", values: &[Time], initial: "0s", inherited: false},
+    Property {name: "animation-iteration-count", values: &[Number, Keywords(&["infinite"])], initial: "1", inherited: false},
+    Property {name: "animation-direction", values: &[Keywords(&["normal", "reverse", "alternate", "alternate-reverse"])], initial: "normal", inherited: false},
+    Property {name: "animation-fill-mode", values: &[Keywords(&["none", "forwards", "backwards", "both"])], initial: "none", inherited: false},
+    Property {name: "animation-play-state", values: &[Keywords(&["running", "paused"])], initial: "running", inherited: false},
+    Property {name: "transition-property", values: &[Ident], initial: "all", inherited: false},
+    Property {name: "transition-duration", values: &[Time], initial: "0s", inherited: false},
+    Property {name: "transition-timing-function", values: &[Easing], initial: "ease", inherited: false},
+    Property {name: "transition-delay", values: &[Time], initial: "0s", inherited: false},
+];
+
+// Properties that take a comma separated list, like one entry per animation or transition.
+const LIST_VALUED: &[&str] = &[
+    "font-family",
+    "animation-name", "animation-duration", "animation-timing-function", "animation-delay",
+    "animation-iteration-count", "animation-direction", "animation-fill-mode", "animation-play-state",
+    "transition-property", "transition-duration", "transition-timing-function", "transition-delay",
+];
+
+// The counter styles there are, CSS Counter Styles 3 §6, for list markers and counter().
+pub const LIST_STYLE_TYPES: &[&str] = &[
+    "none", "disc", "circle", "square", "decimal", "decimal-leading-zero", "lower-roman", "upper-roman",
+    "lower-alpha", "upper-alpha", "lower-latin", "upper-latin", "lower-greek",
+];
+
+const EASING_KEYWORDS: &[&str] = &["linear", "ease", "ease-in", "ease-out", "ease-in-out", "step-start", "step-end"];
+
+const TRANSFORM_FUNCTIONS: &[&str] = &[
+    "translate", "translatex", "translatey", "scale", "scalex", "scaley",
+    "rotate", "skew", "skewx", "skewy", "matrix",
+];
+
+// Valid for every property.
+const CSS_WIDE_KEYWORDS: &[&str] = &["inherit", "initial", "unset", "revert", "revert-layer"];
+
+pub fn is_supported(name: &str, value: &Value) -> bool {
+    if let Value::Keyword(keyword) = value {
+        if CSS_WIDE_KEYWORDS.contains(&&*keyword.to_ascii_lowercase()) {
+            return true;
+        }
+    }
+    if let Some(longhands) = expand_shorthand(name, value) {
+        return !longhands.is_empty() && longhands.iter().all(|(name, value)| is_supported(name, value));
+    }
+    let types = match property(name) {
+        Some(property) => property.values,
+        None => return false,
+    };
+
+    let values = if LIST_VALUED.contains(&name) { value.groups() } else { std::slice::from_ref(value) };
+    values.iter().all(|value| types.iter().any(|value_type| matches_type(value_type, value)))
+}
+
+fn matches_type(value_type: &ValueType, value: &Value) -> bool {
+    match (value_type, value) {
+        (Length, Value::Length(_, Unit::Px)) | (Length, Value::Length(_, Unit::Em)) | (Length, Value::Length(_, Unit::Rem)) => true,
+        (Length, Value::Length(len, Unit::None)) => *len == 0.0,
+        (Percentage, Value::Percentage(_)) => true,
+        (Number, Value::Length(_, Unit::None)) => true,
+        (Color, Value::Color(_)) => true,
+        (Color, Value::Keyword(keyword)) => crate::css::Color::from_name(keyword).is_some() || keyword.eq_ignore_ascii_case("currentcolor"),
+        (Time, Value::Time(_)) => true,
+        (Easing, Value::Keyword(keyword)) => EASING_KEYWORDS.contains(&&*keyword.to_ascii_lowercase()),
+        (Easing, Value::Function(name, _)) => name == "cubic-bezier" || name == "steps",
+        (Transform, Value::Function(name, _)) => TRANSFORM_FUNCTIONS.contains(&&**name),
+        (Transform, Value::List(functions)) => functions.iter().all(|function| matches_type(&Transform, function)),
+        (Ident, Value::Keyword(_)) => true,
+        (FamilyName, Value::Keyword(_)) | (FamilyName, Value::Str(_)) => true,
+        (FamilyName, Value::List(words)) => words.iter().all(|word| matches!(word, Value::Keyword(_))),
+        (Keywords(keywords), Value::Keyword(keyword)) => keywords.contains(&&*keyword.to_ascii_lowercase()),
+        (KeywordSet(keywords), value) => value.components().iter().enumerate().all(|(i, component)| {
+            matches_type(&Keywords(keywords), component) && !value.components()[..i].contains(component)
+        }),
+        (TrackList, value) => value.components().iter().all(|component| match component {
+            Value::LineNames(_) => true,
+            Value::Function(name, args) if name == "repeat" => is_repeat(args),
+            component => is_track_size(component),
+        }),
+        (Strings, Value::Str(_)) => true,
+        (Strings, Value::List(values)) => values.iter().all(|value| matches!(value, Value::Str(_))),
+        (GridLine, value) => is_grid_line(value.components()),
+        (LengthPair, value) => matches!(value.components().len(), 1 | 2) && value.components().iter().all(|length| {
+            matches_type(&Length, length) && !matches!(length, Value::Length(len, _) if *len < 0.0)
+        }),
+        (Ratio, value) => is_ratio(value.components()),
+        (CounterChanges, value) => is_counter_list(value.components(), false),
+        (CounterResets, value) => is_counter_list(value.components(), true),
+        (Content, value) => value.components().iter().all(is_content_item),
+        (Position, value) => is_position(value.components()),
+        _ => false,
+    }
+}
+
+// A ratio, optionally with `auto` before or after it: a positive number, or two numbers
+// separated by a slash of which the first is positive, CSS Sizing 4 §7.1.
+fn is_ratio(components: &[Value]) -> bool {
+    let is_auto = |component: &Value| matches!(component, Value::Keyword(keyword) if keyword.eq_ignore_ascii_case("auto"));
+    let ratio = match components {
+        [auto, ratio @ ..] if is_auto(auto) => ratio,
+        [ratio @ .., auto] if is_auto(auto) => ratio,
+        ratio => ratio,
+    };
+    match ratio {
+        [Value::Length(width, Unit::None)] => *width > 0.0,
+        [Value::Length(width, Unit::None), Value::Slash, Value::Length(height, Unit::None)] => *width > 0.0 && *height >= 0.0,
+        _ => false,
+    }
+}
+
+// A position of one or two components, CSS Backgrounds 3 §3.6. Lengths and percentages are
+// horizontal first and vertical second, two keywords can come in either order.
+fn is_position(components: &[Value]) -> bool {
+    let keyword = |component: &Value| match component {
+        Value::Keyword(keyword) => keyword.to_ascii_lowercase(),
+        _ => String::new(),
+    };
+    let is_offset = |component: &Value| matches_type(&Length, component) || matches_type(&Percentage, component);
+    let horizontal = |component: &Value| is_offset(component) || matches!(&*keyword(component), "left" | "center" | "right");
+    let vertical = |component: &Value| is_offset(component) || matches!(&*keyword(component), "top" | "center" | "bottom");
+    match components {
+        [component] => horizontal(component) || vertical(component),
+        [first, second] if horizontal(first) && vertical(second) => true,
+        [first, second] => !is_offset(first) && !is_offset(second) && vertical(first) && horizontal(second),
+        _ => false,
+    }
+}
+
+// Counter names, each optionally followed by an integer, CSS Lists 3 §4.2. `none` can't be a
+// name, and neither can the CSS wide keywords.
+fn is_counter_list(components: &[Value], reversible: bool) -> bool {
+    let is_name = |component: &Value| match component {
+        Value::Keyword(name) => name != "none" && !CSS_WIDE_KEYWORDS.contains(&&*name.to_ascii_lowercase()),
+        Value::Function(function, args) if reversible && function == "reversed" => matches!(args.as_slice(), [Value::Keyword(_)]),
+        _ => false,
+    };
+    let is_integer = |component: &Value| matches!(component, Value::Length(number, Unit::None) if number.fract() == 0.0);
+    !components.is_empty() && components.iter().enumerate().all(|(i, component)| {
+        is_name(component) || is_integer(component) && i > 0 && is_name(&components[i - 1])
+    })
+}
+
+// One part of the content of a ::before, ::after or ::marker, CSS Generated Content 3 §1.
+fn is_content_item(component: &Value) -> bool {
+    let is_style = |style: &Value| matches!(style, Value::Keyword(style) if LIST_STYLE_TYPES.contains(&&*style.to_ascii_lowercase()));
+    match component {
+        Value::Str(_) => true,
+        Value::Function(name, args) => match (&**name, args.as_slice()) {
+            ("counter", [Value::Keyword(_)]) | ("attr", [Value::Keyword(_)]) => true,
+            ("counter", [Value::Keyword(_), style]) => is_style(style),
+            ("counters", [Value::Keyword(_), Value::Str(_)]) => true,
+            ("counters", [Value::Keyword(_), Value::Str(_), style]) => is_style(style),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn is_track_size(value: &Value) -> bool {
+    match value {
+        Value::Length(_, Unit::Fr) => true,
+        Value::Keyword(keyword) => matches!(&*keyword.to_ascii_lowercase(), "auto" | "min-content" | "max-content"),
+        //the minimum can't be flexible
+        Value::Function(name, args) if name == "minmax" => match args.as_slice() {
+            [min, max] => !matches!(min, Value::Length(_, Unit::Fr)) && is_track_size(min) && is_track_size(max),
+            _ => false,
+        },
+        Value::Function(name, args) if name == "fit-content" => match args.as_slice() {
+            [limit] => matches_type(&Length, limit) || matches_type(&Percentage, limit),
+            _ => false,
+        },
+        value => matches_type(&Length, value) || matches_type(&Percentage, value),
+    }
+}
+
+// The arguments of repeat(): a number of repetitions, auto-fill or auto-fit, and then the
+// tracks and line names to repeat.
+fn is_repeat(args: &[Value]) -> bool {
+    let count = match args.first() {
+        Some(Value::Length(count, Unit::None)) => *count >= 1.0 && count.fract() == 0.0,
+        Some(Value::Keyword(keyword)) => matches!(&*keyword.to_ascii_lowercase(), "auto-fill" | "auto-fit"),
+        _ => false,
+    };
+    count && args[1..].iter().any(is_track_size) && args[1..].iter().all(|arg| matches!(arg, Value::LineNames(_)) || is_track_size(arg))
+}
+
+// auto, or some of a line number, a line name and `span`, each at most once. A spanned
+// number of lines has to be positive and a line number can't be 0.
+fn is_grid_line(components: &[Value]) -> bool {
+    let is_keyword = |component: &Value, name: &str| matches!(component, Value::Keyword(keyword) if keyword.eq_ignore_ascii_case(name));
+    if let [component] = components {
+        if is_keyword(component, "auto") {
+            return true;
+        }
+    }
+    let span = components.iter().filter(|component| is_keyword(component, "span")).count();
+    let mut numbers = 0;
+    let mut names = 0;
+    for component in components {
+        match component {
+            Value::Length(number, Unit::None) if number.fract() == 0.0 && *number != 0.0 && (span == 0 || *number > 0.0) => numbers += 1,
+            Value::Keyword(_) if is_keyword(component, "span") => {},
+            Value::Keyword(_) if !is_keyword(component, "auto") => names += 1,
+            _ => return false,
+        }
+    }
+    span <= 1 && numbers <= 1 && names <= 1 && numbers + names > 0
+}
+
+fn property(name: &str) -> Option<&'static Property> {
+    PROPERTIES.iter().find(|property| property.name == name)
+}
+
+// Every longhand property the engine knows, in no particular order.
+pub fn names() -> impl Iterator<Item = &'static str> {
+    PROPERTIES.iter().map(|property| property.name)
+}
+
+pub fn initial_value(name: &str) -> Option<Value> {
+    property(name).and_then(|property| css_parser::parse_value(property.initial))
+}
+
+pub fn is_inherited(name: &str) -> bool {
+    property(name).is_some_and(|property| property.inherited)
+}
+
+// Whether plain numbers are valid values, so a unitless 0 is a number rather than a length.
+pub fn takes_numbers(name: &str) -> bool {
+    property(name).is_some_and(|property| property.values.iter().any(|value_type| matches!(value_type, Number | Ratio | CounterChanges | CounterResets)))
+}
+
+pub fn takes_colors(name: &str) -> bool {
+    property(name).is_some_and(|property| property.values.iter().any(|value_type| matches!(value_type, Color)))
+}
+
+// Whether a property is a logical one, CSS Logical Properties 1, which stands for a physical
+// property that depends on the writing mode and direction.
+pub fn is_logical(name: &str) -> bool {
+    (name.contains("inline-") || name.contains("block-")) && property(name).is_some()
+}
+
+// The physical property a logical one stands for in an element with this writing-mode and
+// direction: the inline axis runs along the lines and the block axis across them, CSS Writing
+// Modes 4 §6.
+pub fn physical_property(name: &str, writing_mode: &str, direction: &str) -> Option<String> {
+    if !is_logical(name) {
+        return None;
+    }
+    let vertical = writing_mode.starts_with("vertical");
+    let (inline_size, block_size) = if vertical { ("height", "width") } else { ("width", "height") };
+    for (logical, physical) in [("inline-size", inline_size), ("block-size", block_size)] {
+        if let Some(prefix) = name.strip_suffix(logical) {
+            return Some(format!("{}{}", prefix, physical));
+        }
+    }
+
+    let (inline_start, inline_end) = match (vertical, direction == "rtl") {
+        (false, false) => ("left", "right"),
+        (false, true) => ("right", "left"),
+        (true, false) => ("top", "bottom"),
+        (true, true) => ("bottom", "top"),
+    };
+    let (block_start, block_end) = match writing_mode {
+        "vertical-rl" => ("right", "left"),
+        "vertical-lr" => ("left", "right"),
+        _ => ("top", "bottom"),
+    };
+    [("inline-start", inline_start), ("inline-end", inline_end), ("block-start", block_start), ("block-end", block_end)].iter()
+        .find(|(logical, _)| name.contains(logical))
+        .map(|(logical, physical)| name.replacen(logical, physical, 1))
+}
+
+pub fn supports(condition: &SupportsCondition) -> bool {
+    match condition {
+        SupportsCondition::Declaration(name, Some(value)) => is_supported(name, value),
+        SupportsCondition::Declaration(_, None) => false,
+        SupportsCondition::Selector(_, valid) => *valid,
+        SupportsCondition::Not(condition) => !supports(condition),
+        SupportsCondition::And(conditions) => conditions.iter().all(supports),
+        SupportsCondition::Or(conditions) => conditions.iter().any(supports),
+        SupportsCondition::Unknown(_) => false,
+    }
+}
+
+// Splits shorthand declarations into their longhands, everything else is passed through.
+// A shorthand whose value doesn't fit its grammar expands to nothing, dropping it like any
+// other invalid declaration.
+pub fn expand(declaration: Declaration) -> Vec<Declaration> {
+    match expand_shorthand(&declaration.name, &declaration.value) {
+        Some(longhands) => longhands.into_iter().map(|(name, value)| Declaration {
+            name: name.to_string(),
+            value,
+            important: declaration.important,
+            location: declaration.location,
+        }).collect(),
+        None => vec![declaration],
+    }
+}
+
+// None if `name` isn't a shorthand.
+fn expand_shorthand(name: &str, value: &Value) -> Option<Vec<(&'static str, Value)>> {
+    let longhands: &[&'static str] = match name {
+        "animation" => &[
+            "animation-name", "animation-duration", "animation-timing-function", "animation-delay",
+            "animation-iteration-count", "animation-direction", "animation-fill-mode", "animation-play-state",
+        ],
+        "transition" => &["transition-property", "transition-duration", "transition-timing-function", "transition-delay"],
+        "margin" => &["margin-top", "margin-right", "margin-bottom", "margin-left"],
+        "padding" => &["padding-top", "padding-right", "padding-bottom", "padding-left"],
+        "inset" => &["top", "right", "bottom", "left"],
+        "border-width" => &["border-top-width", "border-right-width", "border-bottom-width", "border-left-width"],
+        "border-style" => &["border-top-style", "border-right-style", "border-bottom-style", "border-left-style"],
+        "border-radius" => &["border-top-left-radius", "border-top-right-radius", "border-bottom-right-radius", "border-bottom-left-radius"],
+        "margin-block" => &["margin-block-start", "margin-block-end"],
+        "margin-inline" => &["margin-inline-start", "margin-inline-end"],
+        "padding-block" => &["padding-block-start", "padding-block-end"],
+        "padding-inline" => &["padding-inline-start", "padding-inline-end"],
+        "border-block-width" => &["border-block-start-width", "border-block-end-width"],
+        "border-inline-width" => &["border-inline-start-width", "border-inline-end-width"],
+        "border-block-style" => &["border-block-start-style", "border-block-end-style"],
+        "border-inline-style" => &["border-inline-start-style", "border-inline-end-style"],
+        "flex" => &["flex-grow", "flex-shrink", "flex-basis"],
+        "flex-flow" => &["flex-direction", "flex-wrap"],
+        "gap" => &["row-gap", "column-gap"],
+        "overflow" => &["overflow-x", "overflow-y"],
+        "list-style" => &["list-style-position", "list-style-type"],
+        "grid-row" => &["grid-row-start", "grid-row-end"],
+        "grid-column" => &["grid-column-start", "grid-column-end"],
+        "grid-area" => &["grid-row-start", "grid-column-start", "grid-row-end", "grid-column-end"],
+        _ => return None,
+    };
+
+    //a css wide keyword sets every longhand to itself
+    if let Value::Keyword(keyword) = value {
+        if CSS_WIDE_KEYWORDS.contains(&&*keyword.to_ascii_lowercase()) {
+            return Some(longhands.iter().map(|longhand| (*longhand, value.clone())).collect());
+        }
+    }
+
+    let groups: Option<Vec<Vec<Value>>> = value.groups().iter().map(|group| match name {
+        "animation" => expand_animation(group.components()),
+        "transition" => expand_transition(group.components()),
+        "flex" => expand_flex(group.components()),
+        "flex-flow" => expand_flex_flow(group.components()),
+        "list-style" => expand_list_style(group.components()),
+        "grid-row" | "grid-column" | "grid-area" => expand_grid_lines(group.components(), longhands.len()),
+        _ if longhands.len() == 2 => expand_ends(group.components()),
+        _ => expand_sides(group.components()),
+    }).collect();
+    let groups = match groups {
+        Some(groups) => groups,
+        None => return Some(vec![]),
+    };
+
+    Some(longhands.iter().enumerate().map(|(i, longhand)| {
+        let mut values: Vec<Value> = groups.iter().map(|group| group[i].clone()).collect();
+        let value = if values.len() == 1 { values.pop().unwrap() } else { Value::CommaList(values) };
+        (*longhand, value)
+    }).collect())
+}
+
+fn is_easing(value: &Value) -> bool {
+    matches_type(&Easing, value)
+}
+
+// One animation of the `animation` shorthand, in longhand order. The first time is the
+// duration and the second the delay, keywords are claimed by the first longhand that hasn't
+// been set yet and takes them, and what's left over is the name.
+fn expand_animation(components: &[Value]) -> Option<Vec<Value>> {
+    let keyword = |keyword: &str| Some(Value::Keyword(keyword.to_string()));
+    let mut name = None;
+    let mut duration = None;
+    let mut easing = None;
+    let mut delay = None;
+    let mut count = None;
+    let mut direction = None;
+    let mut fill = None;
+    let mut play_state = None;
+
+    for component in components {
+        let lowercase = match component {
+            Value::Keyword(keyword) => keyword.to_ascii_lowercase(),
+            _ => String::new(),
+        };
+        match component {
+            Value::Time(_) if duration.is_none() => duration = Some(component.clone()),
+            Value::Time(_) if delay.is_none() => delay = Some(component.clone()),
+            Value::Length(_, Unit::None) if count.is_none() => count = Some(component.clone()),
+            _ if is_easing(component) && easing.is_none() => easing = Some(component.clone()),
+            Value::Keyword(_) if lowercase == "infinite" && count.is_none() => count = Some(component.clone()),
+            Value::Keyword(_) if matches!(&*lowercase, "normal" | "reverse" | "alternate" | "alternate-reverse") && direction.is_none() => {
+                direction = Some(component.clone())
+            },
+            Value::Keyword(_) if matches!(&*lowercase, "none" | "forwards" | "backwards" | "both") && fill.is_none() => {
+                fill = Some(component.clone())
+            },
+            Value::Keyword(_) if matches!(&*lowercase, "running" | "paused") && play_state.is_none() => {
+                play_state = Some(component.clone())
+            },
+            Value::Keyword(_) if name.is_none() => name = Some(component.clone()),
+            _ => return None,
+        }
+    }
+
+    Some(vec![
+        name.or_else(|| keyword("none"))?,
+        duration.unwrap_or(Value::Time(0.0)),
+        easing.or_else(|| keyword("ease"))?,
+        delay.unwrap_or(Value::Time(0.0)),
+        count.unwrap_or(Value::Length(1.0, Unit::None)),
+        direction.or_else(|| keyword("normal"))?,
+        fill.or_else(|| keyword("none"))?,
+        play_state.or_else(|| keyword("running"))?,
+    ])
+}
+
+// One transition of the `transition` shorthand: property, duration, timing function, delay.
+fn expand_transition(components: &[Value]) -> Option<Vec<Value>> {
+    let mut property = None;
+    let mut duration = None;
+    let mut easing = None;
+    let mut delay = None;
+
+    for component in components {
+        match component {
+            Value::Time(_) if duration.is_none() => duration = Some(component.clone()),
+            Value::Time(_) if delay.is_none() => delay = Some(component.clone()),
+            _ if is_easing(component) && easing.is_none() => easing = Some(component.clone()),
+            Value::Keyword(_) if property.is_none() => property = Some(component.clone()),
+            _ => return None,
+        }
+    }
+
+    Some(vec![
+        property.unwrap_or_else(|| Value::Keyword("all".to_string())),
+        duration.unwrap_or(Value::Time(0.0)),
+        easing.unwrap_or_else(|| Value::Keyword("ease".to_string())),
+        delay.unwrap_or(Value::Time(0.0)),
+    ])
+}
+
+// The grow, shrink and basis of the `flex` shorthand. `none` is 0 0 auto and `auto` 1 1 auto;
+// otherwise the numbers are the grow and shrink factors, and a basis left out is 0%, CSS
+// Flexbox 1 §7.1.
+fn expand_flex(components: &[Value]) -> Option<Vec<Value>> {
+    let number = |number: f32| Value::Length(number, Unit::None);
+    let keyword = |components: &[Value], keyword: &str| matches!(components, [Value::Keyword(k)] if k.eq_ignore_ascii_case(keyword));
+    if keyword(components, "none") {
+        return Some(vec![number(0.0), number(0.0), Value::Keyword("auto".to_string())]);
+    }
+    if keyword(components, "auto") {
+        return Some(vec![number(1.0), number(1.0), Value::Keyword("auto".to_string())]);
+    }
+
+    let mut grow = None;
+    let mut shrink = None;
+    let mut basis = None;
+    let mut after_grow = false;
+    for component in components {
+        let is_basis = matches_type(&Length, component) || matches_type(&Percentage, component)
+            || matches_type(&Keywords(&["auto", "content"]), component);
+        match component {
+            Value::Length(_, Unit::None) if grow.is_none() => grow = Some(component.clone()),
+            //the shrink factor has to come right after the grow factor
+            Value::Length(_, Unit::None) if shrink.is_none() && after_grow => shrink = Some(component.clone()),
+            _ if is_basis && basis.is_none() => basis = Some(component.clone()),
+            _ => return None,
+        }
+        after_grow = matches!(component, Value::Length(_, Unit::None)) && shrink.is_none();
+    }
+    Some(vec![
+        grow.unwrap_or_else(|| number(1.0)),
+        shrink.unwrap_or_else(|| number(1.0)),
+        basis.unwrap_or(Value::Percentage(0.0)),
+    ])
+}
+
+// The direction and wrapping of the `flex-flow` shorthand, in either order.
+fn expand_flex_flow(components: &[Value]) -> Option<Vec<Value>> {
+    let mut direction = None;
+    let mut wrap = None;
+    for component in components {
+        let lowercase = match component {
+            Value::Keyword(keyword) => keyword.to_ascii_lowercase(),
+            _ => return None,
+        };
+        match &*lowercase {
+            "row" | "row-reverse" | "column" | "column-reverse" if direction.is_none() => direction = Some(component.clone()),
+            "nowrap" | "wrap" | "wrap-reverse" if wrap.is_none() => wrap = Some(component.clone()),
+            _ => return None,
+        }
+    }
+    Some(vec![
+        direction.unwrap_or_else(|| Value::Keyword("row".to_string())),
+        wrap.unwrap_or_else(|| Value::Keyword("nowrap".to_string())),
+    ])
+}
+
+// The position and type of the `list-style` shorthand, in either order. Marker images aren't
+// supported, so the only image it takes is `none`; a single `none` goes to the type, which
+// comes to the same thing.
+fn expand_list_style(components: &[Value]) -> Option<Vec<Value>> {
+    let mut position = None;
+    let mut list_style_type = None;
+    for component in components {
+        match component {
+            _ if matches_type(&Keywords(&["inside", "outside"]), component) && position.is_none() => position = Some(component.clone()),
+            _ if (matches_type(&Keywords(LIST_STYLE_TYPES), component) || matches!(component, Value::Str(_))) && list_style_type.is_none() => {
+                list_style_type = Some(component.clone())
+            },
+            //`none` for the image, once the type has one
+            Value::Keyword(keyword) if keyword.eq_ignore_ascii_case("none") => {},
+            _ => return None,
+        }
+    }
+    Some(vec![
+        position.unwrap_or_else(|| Value::Keyword("outside".to_string())),
+        list_style_type.unwrap_or_else(|| Value::Keyword("disc".to_string())),
+    ])
+}
+
+// The grid lines of a shorthand like `grid-area`, separated by slashes. A missing line is the
+// same as the one across from it, or for grid-area the column start as the row start, if that
+// is a line name, and auto otherwise.
+fn expand_grid_lines(components: &[Value], count: usize) -> Option<Vec<Value>> {
+    let mut lines: Vec<Value> = components.split(|component| *component == Value::Slash).map(|line| match line {
+        [line] => line.clone(),
+        line => Value::List(line.to_vec()),
+    }).collect();
+    if lines.len() > count || !lines.iter().all(|line| is_grid_line(line.components())) {
+        return None;
+    }
+    while lines.len() < count {
+        let from = if count == 4 && lines.len() == 1 { 0 } else { lines.len() - count / 2 };
+        let line = match &lines[from] {
+            Value::Keyword(name) if !name.eq_ignore_ascii_case("auto") => lines[from].clone(),
+            _ => Value::Keyword("auto".to_string()),
+        };
+        lines.push(line);
+    }
+    Some(lines)
+}
+
+// The top, right, bottom and left values of a shorthand like `margin`, from one to four
+// components: a missing left copies right, a missing bottom copies top, a missing right top.
+fn expand_sides(components: &[Value]) -> Option<Vec<Value>> {
+    let [top, right, bottom, left] = match components {
+        [all] => [all, all, all, all],
+        [vertical, horizontal] => [vertical, horizontal, vertical, horizontal],
+        [top, horizontal, bottom] => [top, horizontal, bottom, horizontal],
+        [top, right, bottom, left] => [top, right, bottom, left],
+        _ => return None,
+    };
+    Some(vec![top.clone(), right.clone(), bottom.clone(), left.clone()])
+}
+
+// The start and end values of a logical shorthand like `margin-inline`, where a missing end
+// copies the start.
+fn expand_ends(components: &[Value]) -> Option<Vec<Value>> {
+    match components {
+        [both] => Some(vec![both.clone(), both.clone()]),
+        [start, end] => Some(vec![start.clone(), end.clone()]),
+        _ => None,
+    }
+}
@@ -0,0 +1,573 @@
+// Rasterizing, drawing a display list into pixels on the CPU. Every shape, a rect, a rounded
+// rect, a border or a glyph, is turned into polygons and filled with the coverage of each
+// pixel worked out exactly from the signed area its edges sweep out, which anti-aliases them
+// and does the same thing on every machine. Pixels are blended premultiplied in floating
+// point, and opacity groups are drawn into layers of their own, blended into the one under
+// them when they end.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::css::Color;
+use crate::font::FaceId;
+use crate::font::FontDatabase;
+use crate::image::Image;
+use crate::layout::EdgeSizes;
+use crate::layout::Rect;
+use crate::painting::DisplayItem;
+use crate::painting::DisplayList;
+use crate::painting::Matrix;
+use crate::painting::Radii;
+use crate::painting::multiply;
+
+// A point in device pixels, from the top left of the canvas.
+type Point = (f32, f32);
+
+// The contours of a glyph, as Face::outline gives them.
+type Outline = Vec<Vec<(f32, f32, bool)>>;
+
+// A color with its alpha multiplied in, each channel from 0 to 1.
+type Premultiplied = [f32; 4];
+
+// How far a curve flattened into lines may stray from it, in pixels.
+const TOLERANCE: f32 = 0.1;
+
+// Draws a display list, as the part of the page in `viewport` seen through a canvas of its
+// size, which starts out white like the window of a browser. Text is drawn with the outlines
+// of the glyphs in `fonts`.
+pub fn rasterize(list: &DisplayList, viewport: Rect, fonts: &FontDatabase) -> Image {
+    let (width, height) = (viewport.width.ceil().max(0.0) as usize, viewport.height.ceil().max(0.0) as usize);
+    let mut rasterizer = Rasterizer {
+        width,
+        height,
+        fonts,
+        layers: vec![vec![[1.0; 4]; width * height]],
+        opacities: vec![],
+        clips: vec![Clip {bounds: (0, 0, width, height), mask: None}],
+        transforms: vec![[1.0, 0.0, 0.0, 1.0, -viewport.x, -viewport.y]],
+        outlines: HashMap::new(),
+    };
+    for item in &list.items {
+        rasterizer.draw(item);
+    }
+    //groups the list leaves open end where it does
+    while rasterizer.layers.len() > 1 {
+        rasterizer.pop_layer();
+    }
+
+    let mut image = Image::new(width as u32, height as u32);
+    for (pixel, rgba) in rasterizer.layers[0].iter().zip(image.pixels.chunks_exact_mut(4)) {
+        let alpha = pixel[3].clamp(0.0, 1.0);
+        let channel = |value: f32| match alpha > 0.0 {
+            true => ((value / alpha).clamp(0.0, 1.0) * 255.0).round() as u8,
+            false => 0,
+        };
+        rgba.copy_from_slice(&[channel(pixel[0]), channel(pixel[1]), channel(pixel[2]), (alpha * 255.0).round() as u8]);
+    }
+    image
+}
+
+// The area drawing is limited to: the pixels in `bounds`, from x0, y0 up to x1, y1, each
+// scaled by its coverage in `mask` if the edges of the clip don't fall on pixel boundaries.
+#[derive(Clone)]
+struct Clip {
+    bounds: (usize, usize, usize, usize),
+    // a coverage for every pixel of the canvas
+    mask: Option<Rc<Vec<f32>>>,
+}
+
+// What a shape is filled with.
+enum Paint<'i> {
+    Solid(Premultiplied),
+    // an image stretched over a rect in document coordinates
+    Image(&'i Image, Rect),
+}
+
+struct Rasterizer<'f> {
+    width: usize,
+    height: usize,
+    fonts: &'f FontDatabase,
+    // the pixels of the canvas, then those of each opacity group that is open
+    layers: Vec<Vec<Premultiplied>>,
+    opacities: Vec<f32>,
+    clips: Vec<Clip>,
+    // from document coordinates to device pixels, the innermost last
+    transforms: Vec<Matrix>,
+    outlines: HashMap<(FaceId, u16), Rc<Outline>>,
+}
+
+impl<'f> Rasterizer<'f> {
+    fn draw(&mut self, item: &DisplayItem) {
+        match item {
+            DisplayItem::SolidColor {rect, color} => {
+                let contours = vec![self.rect_contour(*rect)];
+                self.fill(&contours, &Paint::Solid(premultiply(color)));
+            },
+            DisplayItem::RoundedRect {rect, radii, color} => {
+                let contours = vec![self.rounded_rect_contour(*rect, radii)];
+                self.fill(&contours, &Paint::Solid(premultiply(color)));
+            },
+            DisplayItem::Border {rect, widths, radii, color} => {
+                let contours = self.border_contours(*rect, widths, radii);
+                self.fill(&contours, &Paint::Solid(premultiply(color)));
+            },
+            DisplayItem::Text {origin, glyphs, size, color, sideways, ..} => {
+                let paint = Paint::Solid(premultiply(color));
+                for glyph in glyphs {
+                    let outline = self.outline(glyph.face, glyph.glyph);
+                    let scale = size / self.fonts.face(glyph.face).units_per_em as f32;
+                    //font units go up from the baseline, and sideways text is turned clockwise
+                    let place = match sideways {
+                        true => [0.0, scale, scale, 0.0, origin.0, origin.1 + glyph.x],
+                        false => [scale, 0.0, 0.0, -scale, origin.0 + glyph.x, origin.1],
+                    };
+                    let matrix = multiply(self.transform(), place);
+                    let contours: Vec<Vec<Point>> = outline.iter().map(|contour| flatten(contour, matrix)).collect();
+                    self.fill(&contours, &paint);
+                }
+            },
+            DisplayItem::Image {rect, image} => {
+                let contours = vec![self.rect_contour(*rect)];
+                self.fill(&contours, &Paint::Image(image, *rect));
+            },
+            DisplayItem::PushClip {rect} => {
+                let clip = self.clip_to(*rect);
+                self.clips.push(clip);
+            },
+            DisplayItem::PopClip => {
+                if self.clips.len() > 1 {
+                    self.clips.pop();
+                }
+            },
+            DisplayItem::PushOpacity {opacity} => {
+                self.layers.push(vec![[0.0; 4]; self.width * self.height]);
+                self.opacities.push(opacity.clamp(0.0, 1.0));
+            },
+            DisplayItem::PopOpacity => {
+                if self.layers.len() > 1 {
+                    self.pop_layer();
+                }
+            },
+            DisplayItem::PushTransform {matrix} => {
+                let transform = multiply(self.transform(), *matrix);
+                self.transforms.push(transform);
+            },
+            DisplayItem::PopTransform => {
+                if self.transforms.len() > 1 {
+                    self.transforms.pop();
+                }
+            },
+        }
+    }
+
+    fn transform(&self) -> Matrix {
+        *self.transforms.last().unwrap()
+    }
+
+    fn clip(&self) -> &Clip {
+        self.clips.last().unwrap()
+    }
+
+    fn outline(&mut self, face: FaceId, glyph: u16) -> Rc<Outline> {
+        let fonts = self.fonts;
+        self.outlines.entry((face, glyph)).or_insert_with(|| Rc::new(fonts.face(face).outline(glyph))).clone()
+    }
+
+    // Blends the innermost opacity group into the layer under it.
+    fn pop_layer(&mut self) {
+        let layer = self.layers.pop().unwrap();
+        let opacity = self.opacities.pop().unwrap_or(1.0);
+        for (destination, source) in self.layers.last_mut().unwrap().iter_mut().zip(layer) {
+            blend(destination, source, opacity);
+        }
+    }
+
+    // The clip of a rect inside the current one. Its edges are only worked out pixel by pixel
+    // when they don't fall on pixel boundaries, which those of most boxes do.
+    fn clip_to(&self, rect: Rect) -> Clip {
+        let contour = self.rect_contour(rect);
+        let parent = self.clip().clone();
+        let aligned = contour.iter().all(|&(x, y)| x.fract() == 0.0 && y.fract() == 0.0)
+            && contour.windows(2).all(|edge| edge[0].0 == edge[1].0 || edge[0].1 == edge[1].1);
+        let bounds = intersect(parent.bounds, pixel_bounds(&contour));
+        if aligned {
+            return Clip {bounds, mask: parent.mask};
+        }
+        let mut mask = vec![0.0; self.width * self.height];
+        let (x0, y0, x1, y1) = bounds;
+        let coverage = coverage(&[contour], bounds);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let inherited = parent.mask.as_ref().map_or(1.0, |mask| mask[y * self.width + x]);
+                mask[y * self.width + x] = coverage[(y - y0) * (x1 - x0) + x - x0] * inherited;
+            }
+        }
+        Clip {bounds, mask: Some(Rc::new(mask))}
+    }
+
+    // Fills polygons in device pixels, by the nonzero rule, within the current clip.
+    fn fill(&mut self, contours: &[Vec<Point>], paint: &Paint) {
+        let bounds = intersect(self.clip().bounds, pixel_bounds(contours.iter().flatten()));
+        let (x0, y0, x1, y1) = bounds;
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+        let coverage = coverage(contours, bounds);
+        //images are looked up from device pixels back in document coordinates
+        let inverse = match paint {
+            Paint::Image(..) => match invert(self.transform()) {
+                Some(inverse) => inverse,
+                None => return,
+            },
+            Paint::Solid(_) => [1.0, 0.0, 0.0, 1.0, 0.0, 0.0],
+        };
+        let width = self.width;
+        let mask = self.clip().mask.clone();
+        let layer = self.layers.last_mut().unwrap();
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let mut alpha = coverage[(y - y0) * (x1 - x0) + x - x0];
+                if let Some(mask) = &mask {
+                    alpha *= mask[y * width + x];
+                }
+                if alpha <= 0.0 {
+                    continue;
+                }
+                let source = match paint {
+                    Paint::Solid(color) => *color,
+                    Paint::Image(image, rect) => {
+                        let (px, py) = apply(inverse, (x as f32 + 0.5, y as f32 + 0.5));
+                        sample(image, (px - rect.x) / rect.width * image.width as f32, (py - rect.y) / rect.height * image.height as f32)
+                    },
+                };
+                blend(&mut layer[y * width + x], source, alpha);
+            }
+        }
+    }
+
+    fn rect_contour(&self, rect: Rect) -> Vec<Point> {
+        let matrix = self.transform();
+        [(rect.x, rect.y), (rect.x + rect.width, rect.y), (rect.x + rect.width, rect.y + rect.height), (rect.x, rect.y + rect.height)]
+            .iter().map(|&point| apply(matrix, point)).collect()
+    }
+
+    // A rect with its corners rounded, clockwise from its top left corner, with enough points
+    // on each quarter ellipse for it to look round at the scale it is drawn at.
+    fn rounded_rect_contour(&self, rect: Rect, radii: &Radii) -> Vec<Point> {
+        let matrix = self.transform();
+        let scale = (matrix[0] * matrix[3] - matrix[1] * matrix[2]).abs().sqrt();
+        let (right, bottom) = (rect.x + rect.width, rect.y + rect.height);
+        let [top_left, top_right, bottom_right, bottom_left] = *radii;
+        let corners = [
+            ((rect.x + top_left.0, rect.y + top_left.1), top_left, 2.0),
+            ((right - top_right.0, rect.y + top_right.1), top_right, 3.0),
+            ((right - bottom_right.0, bottom - bottom_right.1), bottom_right, 0.0),
+            ((rect.x + bottom_left.0, bottom - bottom_left.1), bottom_left, 1.0),
+        ];
+        let mut contour = vec![];
+        for ((cx, cy), (rx, ry), quarter) in corners {
+            let radius = rx.max(ry) * scale;
+            let segments = if radius > 0.0 { ((radius / TOLERANCE).sqrt().ceil() as usize).clamp(1, 64) } else { 0 };
+            for i in 0..=segments {
+                let angle = std::f32::consts::FRAC_PI_2 * (quarter + i as f32 / segments.max(1) as f32);
+                contour.push(apply(matrix, (cx + rx * angle.cos(), cy + ry * angle.sin())));
+            }
+        }
+        contour
+    }
+
+    // The outer edge of a border and, the other way around so that it cuts a hole, its inner
+    // edge, whose corners are rounded by what is left of the radii past the border widths.
+    fn border_contours(&self, rect: Rect, widths: &EdgeSizes, radii: &Radii) -> Vec<Vec<Point>> {
+        let mut contours = vec![self.rounded_rect_contour(rect, radii)];
+        let inner = Rect {
+            x: rect.x + widths.left,
+            y: rect.y + widths.top,
+            width: rect.width - widths.left - widths.right,
+            height: rect.height - widths.top - widths.bottom,
+        };
+        if inner.width > 0.0 && inner.height > 0.0 {
+            let [top_left, top_right, bottom_right, bottom_left] = *radii;
+            let shrink = |(x, y): (f32, f32), horizontal: f32, vertical: f32| ((x - horizontal).max(0.0), (y - vertical).max(0.0));
+            let inner_radii = [
+                shrink(top_left, widths.left, widths.top),
+                shrink(top_right, widths.right, widths.top),
+                shrink(bottom_right, widths.right, widths.bottom),
+                shrink(bottom_left, widths.left, widths.bottom),
+            ];
+            let mut hole = self.rounded_rect_contour(inner, &inner_radii);
+            hole.reverse();
+            contours.push(hole);
+        }
+        contours
+    }
+}
+
+fn premultiply(color: &Color) -> Premultiplied {
+    let alpha = color.a as f32 / 255.0;
+    [color.r as f32 / 255.0 * alpha, color.g as f32 / 255.0 * alpha, color.b as f32 / 255.0 * alpha, alpha]
+}
+
+// Draws `source` over `destination`, with its alpha scaled by `alpha`.
+fn blend(destination: &mut Premultiplied, source: Premultiplied, alpha: f32) {
+    let keep = 1.0 - source[3] * alpha;
+    for channel in 0..4 {
+        destination[channel] = source[channel] * alpha + destination[channel] * keep;
+    }
+}
+
+// The color of an image at a point in its pixels, interpolated between the four nearest
+// pixel centers, with the pixels at its edges carrying on past them.
+fn sample(image: &Image, x: f32, y: f32) -> Premultiplied {
+    if image.width == 0 || image.height == 0 {
+        return [0.0; 4];
+    }
+    let (max_x, max_y) = (image.width as usize - 1, image.height as usize - 1);
+    let (x, y) = (x - 0.5, y - 0.5);
+    let (left, top) = (x.floor(), y.floor());
+    let (fx, fy) = (x - left, y - top);
+    let pixel = |column: f32, row: f32| {
+        let [r, g, b, a] = image.pixel((column.max(0.0) as usize).min(max_x), (row.max(0.0) as usize).min(max_y));
+        premultiply(&Color {r, g, b, a})
+    };
+    let (top_left, top_right) = (pixel(left, top), pixel(left + 1.0, top));
+    let (bottom_left, bottom_right) = (pixel(left, top + 1.0), pixel(left + 1.0, top + 1.0));
+    let mut color = [0.0; 4];
+    for channel in 0..4 {
+        let upper = top_left[channel] + (top_right[channel] - top_left[channel]) * fx;
+        let lower = bottom_left[channel] + (bottom_right[channel] - bottom_left[channel]) * fx;
+        color[channel] = upper + (lower - upper) * fy;
+    }
+    color
+}
+
+fn apply(matrix: Matrix, (x, y): Point) -> Point {
+    (matrix[0] * x + matrix[2] * y + matrix[4], matrix[1] * x + matrix[3] * y + matrix[5])
+}
+
+// None if the matrix flattens everything onto a line, so that nothing is drawn.
+fn invert(m: Matrix) -> Option<Matrix> {
+    let determinant = m[0] * m[3] - m[1] * m[2];
+    if determinant == 0.0 || !determinant.is_finite() {
+        return None;
+    }
+    let (a, b, c, d) = (m[3] / determinant, -m[1] / determinant, -m[2] / determinant, m[0] / determinant);
+    Some([a, b, c, d, -(a * m[4] + c * m[5]), -(b * m[4] + d * m[5])])
+}
+
+// The pixels the points are in, as x0, y0, x1, y1.
+fn pixel_bounds<'p>(points: impl IntoIterator<Item = &'p Point>) -> (usize, usize, usize, usize) {
+    let (mut left, mut top, mut right, mut bottom) = (f32::INFINITY, f32::INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY);
+    for &(x, y) in points {
+        left = left.min(x);
+        top = top.min(y);
+        right = right.max(x);
+        bottom = bottom.max(y);
+    }
+    if left > right || top > bottom {
+        return (0, 0, 0, 0);
+    }
+    //the casts saturate, at 0 for what is left of or above the canvas
+    (left.floor() as usize, top.floor() as usize, right.ceil() as usize, bottom.ceil() as usize)
+}
+
+fn intersect(a: (usize, usize, usize, usize), b: (usize, usize, usize, usize)) -> (usize, usize, usize, usize) {
+    let (x0, y0) = (a.0.max(b.0), a.1.max(b.1));
+    (x0, y0, a.2.min(b.2).max(x0), a.3.min(b.3).max(y0))
+}
+
+// A contour of a glyph outline as a polygon in device pixels, the quadratic Béziers between
+// its on curve points cut into lines. Between two off curve points there is an implied on
+// curve point halfway.
+fn flatten(contour: &[(f32, f32, bool)], matrix: Matrix) -> Vec<Point> {
+    let mut points: Vec<(Point, bool)> = vec![];
+    for (i, &(x, y, on_curve)) in contour.iter().enumerate() {
+        let previous = contour[(i + contour.len() - 1) % contour.len()];
+        if !on_curve && !previous.2 {
+            points.push((apply(matrix, ((x + previous.0) / 2.0, (y + previous.1) / 2.0)), true));
+        }
+        points.push((apply(matrix, (x, y)), on_curve));
+    }
+    let start = match points.iter().position(|&(_, on_curve)| on_curve) {
+        Some(start) => start,
+        None => return vec![],
+    };
+    points.rotate_left(start);
+
+    let mut polygon = vec![points[0].0];
+    let mut i = 1;
+    while i <= points.len() {
+        let (point, on_curve) = points[i % points.len()];
+        if on_curve {
+            polygon.push(point);
+            i += 1;
+            continue;
+        }
+        let from = *polygon.last().unwrap();
+        let to = points[(i + 1) % points.len()].0;
+        //the error of n lines is about a quarter of how far the control point bends the
+        //curve, over n squared
+        let bend = ((from.0 - 2.0 * point.0 + to.0).powi(2) + (from.1 - 2.0 * point.1 + to.1).powi(2)).sqrt();
+        let segments = ((bend / (4.0 * TOLERANCE)).sqrt().ceil() as usize).clamp(1, 32);
+        for step in 1..=segments {
+            let t = step as f32 / segments as f32;
+            let u = 1.0 - t;
+            polygon.push((
+                u * u * from.0 + 2.0 * u * t * point.0 + t * t * to.0,
+                u * u * from.1 + 2.0 * u * t * point.1 + t * t * to.1,
+            ));
+        }
+        i += 2;
+    }
+    polygon
+}
+
+// How much of each pixel within `bounds` the polygons cover by the nonzero rule, row by row,
+// from 0 to 1. Each edge adds the signed area it sweeps out to the left of it to the pixels it
+// crosses and what is left of a full pixel to the next one, so that adding them up along a row
+// gives the coverage, as in font-rs. Where polygons overlap the sum is cut off at 1.
+fn coverage(contours: &[Vec<Point>], bounds: (usize, usize, usize, usize)) -> Vec<f32> {
+    let (x0, y0, x1, y1) = bounds;
+    let (width, height) = (x1 - x0, y1 - y0);
+    let stride = width + 2;
+    let mut accumulation = vec![0.0f32; stride * height];
+    for contour in contours {
+        for (i, &from) in contour.iter().enumerate() {
+            let to = contour[(i + 1) % contour.len()];
+            let shift = |(x, y): Point| (x - x0 as f32, y - y0 as f32);
+            accumulate(&mut accumulation, stride, height, shift(from), shift(to));
+        }
+    }
+    let mut coverage = Vec::with_capacity(width * height);
+    for row in accumulation.chunks_exact(stride) {
+        let mut sum = 0.0;
+        for &area in &row[..width] {
+            sum += area;
+            coverage.push(sum.abs().min(1.0));
+        }
+    }
+    coverage
+}
+
+// Adds the areas of an edge to the rows it crosses. Past the left of the buffer it is taken to
+// be on its first column, and past the right on columns that aren't added up.
+fn accumulate(accumulation: &mut [f32], stride: usize, height: usize, from: Point, to: Point) {
+    if from.1 == to.1 || !(from.0.is_finite() && from.1.is_finite() && to.0.is_finite() && to.1.is_finite()) {
+        return;
+    }
+    let (direction, top, bottom) = if from.1 < to.1 { (1.0, from, to) } else { (-1.0, to, from) };
+    let dxdy = (bottom.0 - top.0) / (bottom.1 - top.1);
+    let (y_start, y_end) = (top.1.max(0.0), bottom.1.min(height as f32));
+    if y_start >= y_end {
+        return;
+    }
+    let max_x = (stride - 2) as f32;
+    let mut x = top.0 + (y_start - top.1) * dxdy;
+    let mut y = y_start;
+    for row in y_start as usize..y_end.ceil() as usize {
+        let next_y = ((row + 1) as f32).min(y_end);
+        let dy = next_y - y;
+        let next_x = x + dxdy * dy;
+        let d = dy * direction;
+        let (left, right) = if x < next_x { (x, next_x) } else { (next_x, x) };
+        let (left, right) = (left.clamp(0.0, max_x), right.clamp(0.0, max_x));
+        let line = &mut accumulation[row * stride..(row + 1) * stride];
+        let (left_floor, right_ceil) = (left.floor(), right.ceil());
+        let (first, last) = (left_floor as usize, right_ceil as usize);
+        if last <= first + 1 {
+            //within one pixel, split by where the middle of the edge is
+            let middle = 0.5 * (left + right) - left_floor;
+            line[first] += d - d * middle;
+            line[first + 1] += d * middle;
+        } else {
+            let slope = (right - left).recip();
+            let left_fraction = left - left_floor;
+            let first_area = 0.5 * slope * (1.0 - left_fraction) * (1.0 - left_fraction);
+            let right_fraction = right - right_ceil + 1.0;
+            let last_area = 0.5 * slope * right_fraction * right_fraction;
+            line[first] += d * first_area;
+            if last == first + 2 {
+                line[first + 1] += d * (1.0 - first_area - last_area);
+            } else {
+                let second_area = slope * (1.5 - left_fraction);
+                line[first + 1] += d * (second_area - first_area);
+                for area in &mut line[first + 2..last - 1] {
+                    *area += d * slope;
+                }
+                let before_last = second_area + (last - first - 3) as f32 * slope;
+                line[last - 1] += d * (1.0 - before_last - last_area);
+            }
+            line[last] += d * last_area;
+        }
+        x = next_x;
+        y = next_y;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: f32, y: f32, width: f32, height: f32) -> Rect {
+        Rect {x, y, width, height}
+    }
+
+    fn color(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color {r, g, b, a}
+    }
+
+    // A small scene with an opacity group, a clip, edges half across pixels and a
+    // transformed border, and what it has to come out as, a character a pixel.
+    #[test]
+    fn rasterizes_the_golden_scene() {
+        let list = DisplayList {items: vec![
+            DisplayItem::SolidColor {rect: rect(1.0, 1.0, 4.0, 3.0), color: color(255, 0, 0, 255)},
+            DisplayItem::PushOpacity {opacity: 0.5},
+            DisplayItem::SolidColor {rect: rect(3.0, 2.0, 4.0, 3.0), color: color(0, 0, 255, 255)},
+            DisplayItem::PopOpacity,
+            DisplayItem::PushClip {rect: rect(0.0, 0.0, 8.0, 1.0)},
+            DisplayItem::SolidColor {rect: rect(0.0, 0.0, 11.0, 6.0), color: color(0, 128, 0, 255)},
+            DisplayItem::PopClip,
+            DisplayItem::RoundedRect {rect: rect(0.5, 5.0, 3.0, 1.0), radii: [(0.0, 0.0); 4], color: color(0, 0, 0, 255)},
+            DisplayItem::PushTransform {matrix: [1.0, 0.0, 0.0, 1.0, 8.0, 3.0]},
+            DisplayItem::Border {rect: rect(0.0, 0.0, 3.0, 3.0), widths: EdgeSizes {top: 1.0, right: 1.0, bottom: 1.0, left: 1.0}, radii: [(0.0, 0.0); 4], color: color(0, 0, 0, 255)},
+            DisplayItem::PopTransform,
+        ]};
+        let golden = [
+            "GGGGGGGGWWW",
+            "WRRRRWWWWWW",
+            "WRRPPBBWWWW",
+            "WRRPPBBWKKK",
+            "WWWBBBBWKWK",
+            "gKKgWWWWKKK",
+        ];
+        let palette = |c: char| match c {
+            'W' => [255, 255, 255, 255],
+            'R' => [255, 0, 0, 255],
+            'G' => [0, 128, 0, 255],
+            'K' => [0, 0, 0, 255],
+            //half covered by black
+            'g' => [128, 128, 128, 255],
+            //half opaque blue over white and over red
+            'B' => [128, 128, 255, 255],
+            'P' => [128, 0, 128, 255],
+            _ => unreachable!(),
+        };
+
+        let image = rasterize(&list, rect(0.0, 0.0, 11.0, 6.0), &FontDatabase::new());
+        assert_eq!((image.width, image.height), (11, 6));
+        for (y, row) in golden.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                assert_eq!(image.pixel(x, y), palette(c), "pixel {}, {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn viewport_scrolls_the_list() {
+        let list = DisplayList {items: vec![DisplayItem::SolidColor {rect: rect(10.0, 20.0, 2.0, 2.0), color: color(0, 0, 255, 255)}]};
+        let image = rasterize(&list, rect(10.0, 20.0, 3.0, 3.0), &FontDatabase::new());
+        assert_eq!(image.pixel(1, 1), [0, 0, 255, 255]);
+        assert_eq!(image.pixel(2, 2), [255, 255, 255, 255]);
+    }
+}
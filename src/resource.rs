@@ -0,0 +1,111 @@
+use std::fs;
+
+use crate::css::CssRule;
+use crate::dom;
+use crate::dom::NodeType;
+use crate::css::Stylesheet;
+use crate::css_parser;
+
+// Where stylesheets (and later images and fonts) come from. Urls handed to a loader are
+// already resolved against the document or stylesheet that referenced them.
+pub trait ResourceLoader {
+    fn load(&self, url: &str) -> Result<Vec<u8>, String>;
+}
+
+// Treats urls as paths on the local filesystem, with an optional file:// scheme.
+pub struct FileLoader;
+
+impl ResourceLoader for FileLoader {
+    fn load(&self, url: &str) -> Result<Vec<u8>, String> {
+        let path = url.strip_prefix("file://").unwrap_or(url);
+        fs::read(path).map_err(|err| format!("Couldn't load {}: {}", url, err))
+    }
+}
+
+// Resolves `url` relative to the resource at `base`.
+pub fn resolve_url(base: &str, url: &str) -> String {
+    let has_scheme = url.find(':').is_some_and(|colon| {
+        colon > 1 && url[..colon].chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+    });
+    if has_scheme || url.starts_with('/') {
+        return url.to_string();
+    }
+
+    let mut segments: Vec<&str> = base.split('/').collect();
+    segments.pop(); //the file name of the base
+    for segment in url.split('/') {
+        match segment {
+            "." => {},
+            ".." if !segments.is_empty() && segments.last() != Some(&"..") && segments.last() != Some(&"") => {
+                segments.pop();
+            },
+            _ => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
+
+// Loads and parses the stylesheet at `url`, along with everything it @imports. An import that
+// can't be loaded, or that would import a stylesheet already being loaded further up the
+// chain, is left without a stylesheet and so contributes no rules.
+pub fn load_stylesheet(url: &str, loader: &dyn ResourceLoader) -> Result<Stylesheet, String> {
+    load_stylesheet_chain(url, loader, &mut vec![])
+}
+
+fn load_stylesheet_chain(url: &str, loader: &dyn ResourceLoader, chain: &mut Vec<String>) -> Result<Stylesheet, String> {
+    let source = String::from_utf8_lossy(&loader.load(url)?).into_owned();
+    let mut stylesheet = css_parser::parse(source);
+    stylesheet.url = url.to_string();
+
+    chain.push(url.to_string());
+    load_imports(&mut stylesheet, loader, chain);
+    chain.pop();
+
+    Ok(stylesheet)
+}
+
+fn load_imports(stylesheet: &mut Stylesheet, loader: &dyn ResourceLoader, chain: &mut Vec<String>) {
+    let url = stylesheet.url.clone();
+    for rule in &mut stylesheet.rules {
+        if let CssRule::Import(import) = rule {
+            let import_url = resolve_url(&url, &import.url);
+            if chain.contains(&import_url) {
+                continue;
+            }
+            import.stylesheet = load_stylesheet_chain(&import_url, loader, chain).ok();
+        }
+    }
+}
+
+// The stylesheets of the document at `url` as one: its <link rel="stylesheet">s, imported in
+// the order they come in, then the rules of its <style> elements, with `user_agent` rules in
+// a cascade layer under all of them. A linked stylesheet that can't be loaded is left out.
+pub fn load_document_stylesheet(root: &dom::Node, url: &str, user_agent: &str, loader: &dyn ResourceLoader) -> Stylesheet {
+    let (mut imports, mut styles) = (String::new(), String::new());
+    collect_stylesheets(root, &mut imports, &mut styles);
+    let mut stylesheet = css_parser::parse(format!("{}@layer user-agent {{\n{}\n}}\n{}", imports, user_agent, styles));
+    stylesheet.url = url.to_string();
+    load_imports(&mut stylesheet, loader, &mut vec![url.to_string()]);
+    stylesheet
+}
+
+fn collect_stylesheets(node: &dom::Node, imports: &mut String, styles: &mut String) {
+    if let NodeType::Element(elem) = &node.node_type {
+        let is_stylesheet = elem.attributes.get("rel").is_some_and(|rel| rel.split_ascii_whitespace().any(|rel| rel.eq_ignore_ascii_case("stylesheet")));
+        match (&*elem.tag_name, elem.attributes.get("href")) {
+            ("link", Some(href)) if is_stylesheet => imports.push_str(&format!("@import url(\"{}\");\n", href.replace('"', "%22"))),
+            ("style", _) => {
+                for child in &node.children {
+                    if let NodeType::Text(text) = &child.node_type {
+                        styles.push_str(text);
+                        styles.push('\n');
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+    for child in &node.children {
+        collect_stylesheets(child, imports, styles);
+    }
+}
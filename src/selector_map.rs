@@ -0,0 +1,413 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use crate::css::Combinator;
+use crate::css::PseudoClass;
+use crate::css::Selector;
+use crate::css::SimpleSelector;
+use crate::css::Specificity;
+use crate::dom::ElementData;
+
+// A selector of one of the cascade's rules, filed under its rightmost compound selector.
+pub struct SelectorEntry<'a> {
+    // index of the rule in the cascade
+    pub rule: usize,
+    pub selector: &'a Selector,
+    pub specificity: Specificity,
+    // hashes of the ids, classes and tags the element's ancestors must have for the selector
+    // to match, checked against an AncestorFilter before doing any real matching
+    pub ancestor_hashes: Vec<u32>,
+}
+
+// Selectors bucketed by the most specific thing their rightmost compound asks for: an id, else
+// a class, else a tag name. An element then only has to look at the buckets for its own id,
+// classes and tag, plus the selectors that could match anything.
+#[derive(Default)]
+pub struct SelectorMap<'a> {
+    ids: HashMap<&'a str, Vec<SelectorEntry<'a>>>,
+    classes: HashMap<&'a str, Vec<SelectorEntry<'a>>>,
+    tags: HashMap<&'a str, Vec<SelectorEntry<'a>>>,
+    universal: Vec<SelectorEntry<'a>>,
+    // selectors that look at siblings, which the style sharing cache has to check itself
+    revalidation: Vec<&'a Selector>,
+    invalidation: InvalidationMap<'a>,
+    // whether any selector is for a pseudo-element
+    pseudo_elements: bool,
+}
+
+impl<'a> SelectorMap<'a> {
+    pub fn new() -> SelectorMap<'a> {
+        Default::default()
+    }
+
+    pub fn insert(&mut self, rule: usize, selector: &'a Selector) {
+        let (subject, ancestor_hashes) = match selector {
+            Selector::Simple(simple) => (simple, vec![]),
+            Selector::Complex(complex) => {
+                //a part followed by a child or descendant combinator is an ancestor of the subject,
+                //whatever comes after it
+                let ancestors = complex.parts.iter().zip(&complex.combinators)
+                    .filter(|(_, combinator)| matches!(combinator, Combinator::Child | Combinator::Descendant))
+                    .flat_map(|(part, _)| compound_hashes(part));
+                (complex.parts.last().unwrap(), ancestors.collect())
+            },
+        };
+
+        if depends_on_siblings(selector) {
+            self.revalidation.push(selector);
+        }
+        self.pseudo_elements |= subject.pseudo_element.is_some();
+        self.invalidation.add_selector(selector, Scope::Subject);
+
+        let entry = SelectorEntry {rule, selector, specificity: selector.specificity(), ancestor_hashes};
+        if let Some(id) = &subject.id {
+            self.ids.entry(id).or_default().push(entry);
+        } else if let Some(class) = subject.class.first() {
+            self.classes.entry(class).or_default().push(entry);
+        } else if let Some(tag_name) = &subject.tag_name {
+            self.tags.entry(tag_name).or_default().push(entry);
+        } else {
+            self.universal.push(entry);
+        }
+    }
+
+    // The selectors that might match an element. Each one comes up at most once.
+    pub fn candidates<'m>(&'m self, elem: &'m ElementData, classes: &'m HashSet<&str>) -> impl Iterator<Item = &'m SelectorEntry<'a>> + 'm {
+        let ids = elem.id().and_then(|id| self.ids.get(&**id));
+        let classes = classes.iter().filter_map(move |class| self.classes.get(class));
+        let tags = self.tags.get(&*elem.tag_name);
+        ids.into_iter().chain(classes).chain(tags).flatten().chain(&self.universal)
+    }
+
+    // Selectors whose matching depends on more than an element's tag, id, classes and
+    // ancestors. Two elements those agree on only match the same rules if they also agree on
+    // every one of these.
+    pub fn revalidation_selectors(&self) -> &[&'a Selector] {
+        &self.revalidation
+    }
+
+    pub fn invalidation_map(&self) -> &InvalidationMap<'a> {
+        &self.invalidation
+    }
+
+    // Whether matching an element's pseudo-elements can find anything at all.
+    pub fn has_pseudo_elements(&self) -> bool {
+        self.pseudo_elements
+    }
+}
+
+// Where, relative to an element whose class or id changed, the elements are that a selector
+// mentioning that class or id might now match differently.
+#[derive(Clone, Copy)]
+enum Scope<'a> {
+    Subject,
+    // descendants that match the selector's rightmost compound
+    Descendants(&'a SimpleSelector),
+    // later siblings, and maybe everything below them
+    Siblings { subtrees: bool },
+    // anything nearby, for features inside the complex arguments of :is() and :not()
+    Everything,
+}
+
+// The descendants an invalidation reaches: those with any of these ids, classes or tags.
+#[derive(Default)]
+pub struct DescendantFeatures<'a> {
+    pub all: bool,
+    pub ids: HashSet<&'a str>,
+    pub classes: HashSet<&'a str>,
+    pub tags: HashSet<&'a str>,
+}
+
+impl DescendantFeatures<'_> {
+    pub fn is_empty(&self) -> bool {
+        !self.all && self.ids.is_empty() && self.classes.is_empty() && self.tags.is_empty()
+    }
+
+    pub fn matches(&self, elem: &ElementData, classes: &HashSet<&str>) -> bool {
+        self.all
+            || elem.id().is_some_and(|id| self.ids.contains(&**id))
+            || classes.iter().any(|class| self.classes.contains(class))
+            || self.tags.contains(&*elem.tag_name)
+    }
+}
+
+// What has to be restyled when an element gains or loses a particular class or id.
+#[derive(Default)]
+pub struct InvalidationSet<'a> {
+    pub invalidates_self: bool,
+    pub descendants: DescendantFeatures<'a>,
+    // later siblings, and with `sibling_subtrees` everything below them as well
+    pub siblings: bool,
+    pub sibling_subtrees: bool,
+}
+
+impl<'a> InvalidationSet<'a> {
+    fn add(&mut self, scope: Scope<'a>) {
+        match scope {
+            Scope::Subject => self.invalidates_self = true,
+            Scope::Descendants(subject) => {
+                self.descendants.ids.extend(subject.id.iter().map(|id| &**id));
+                self.descendants.classes.extend(subject.class.iter().map(|class| &**class));
+                self.descendants.tags.extend(subject.tag_name.iter().map(|tag_name| &**tag_name));
+                if subject.id.is_none() && subject.class.is_empty() && subject.tag_name.is_none() {
+                    self.descendants.all = true;
+                }
+            },
+            Scope::Siblings {subtrees} => {
+                self.siblings = true;
+                self.sibling_subtrees |= subtrees;
+            },
+            Scope::Everything => {
+                self.invalidates_self = true;
+                self.descendants.all = true;
+                self.siblings = true;
+                self.sibling_subtrees = true;
+            },
+        }
+    }
+}
+
+// Invalidation sets for every class and id the selectors of a cascade mention.
+#[derive(Default)]
+pub struct InvalidationMap<'a> {
+    classes: HashMap<&'a str, InvalidationSet<'a>>,
+    ids: HashMap<&'a str, InvalidationSet<'a>>,
+}
+
+impl<'a> InvalidationMap<'a> {
+    pub fn class(&self, class: &str) -> Option<&InvalidationSet<'a>> {
+        self.classes.get(class)
+    }
+
+    pub fn id(&self, id: &str) -> Option<&InvalidationSet<'a>> {
+        self.ids.get(id)
+    }
+
+    // `scope` is where the selector's subject is. Only for a top level selector does that
+    // tell us where its other compounds are.
+    fn add_selector(&mut self, selector: &'a Selector, scope: Scope<'a>) {
+        let complex = match selector {
+            Selector::Simple(simple) => return self.add_compound(simple, scope),
+            Selector::Complex(complex) => complex,
+        };
+        let subject = complex.parts.len() - 1;
+        let ancestral = |combinator: &Combinator| matches!(combinator, Combinator::Child | Combinator::Descendant);
+        for (i, part) in complex.parts.iter().enumerate() {
+            let part_scope = match scope {
+                _ if i == subject => scope,
+                Scope::Subject if ancestral(&complex.combinators[i]) => Scope::Descendants(&complex.parts[subject]),
+                Scope::Subject => Scope::Siblings {subtrees: complex.combinators[i + 1..].iter().any(ancestral)},
+                _ => Scope::Everything,
+            };
+            self.add_compound(part, part_scope);
+        }
+    }
+
+    fn add_compound(&mut self, compound: &'a SimpleSelector, scope: Scope<'a>) {
+        for class in &compound.class {
+            self.classes.entry(class).or_default().add(scope);
+        }
+        if let Some(id) = &compound.id {
+            self.ids.entry(id).or_default().add(scope);
+        }
+        //the arguments of pseudo classes are about the same element as the compound
+        for pseudo_class in &compound.pseudo_classes {
+            match pseudo_class {
+                PseudoClass::Is(selectors) | PseudoClass::Where(selectors) | PseudoClass::Not(selectors) => {
+                    for selector in selectors {
+                        self.add_selector(selector, scope);
+                    }
+                },
+            }
+        }
+    }
+}
+
+fn depends_on_siblings(selector: &Selector) -> bool {
+    let compound_depends = |compound: &SimpleSelector| compound.pseudo_classes.iter().any(|pseudo_class| match pseudo_class {
+        PseudoClass::Is(selectors) | PseudoClass::Where(selectors) | PseudoClass::Not(selectors) => {
+            selectors.iter().any(depends_on_siblings)
+        },
+    });
+    match selector {
+        Selector::Simple(simple) => compound_depends(simple),
+        Selector::Complex(complex) => {
+            complex.combinators.iter().any(|combinator| matches!(combinator, Combinator::NextSibling | Combinator::SubsequentSibling))
+                || complex.parts.iter().any(compound_depends)
+        },
+    }
+}
+
+fn compound_hashes(selector: &SimpleSelector) -> impl Iterator<Item = u32> + '_ {
+    let id = selector.id.iter().map(|id| hash(b'#', id));
+    let classes = selector.class.iter().map(|class| hash(b'.', class));
+    let tag = selector.tag_name.iter().map(|tag_name| hash(b't', tag_name));
+    id.chain(classes).chain(tag)
+}
+
+// FNV-1a, with a byte up front to keep ids, classes and tags with the same name apart.
+fn hash(kind: u8, name: &str) -> u32 {
+    std::iter::once(kind).chain(name.bytes()).fold(0x811c9dc5, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x01000193))
+}
+
+const FILTER_SIZE: usize = 4096;
+
+// A counting bloom filter over the ids, classes and tags of the elements above the one being
+// styled. If it says an ancestor hash is missing, no ancestor has it and selectors needing it
+// can't match; if it says it's there, it probably is.
+#[derive(Clone)]
+pub struct AncestorFilter {
+    counters: Box<[u8; FILTER_SIZE]>,
+}
+
+impl Default for AncestorFilter {
+    fn default() -> Self {
+        AncestorFilter {counters: Box::new([0; FILTER_SIZE])}
+    }
+}
+
+impl AncestorFilter {
+    pub fn new() -> AncestorFilter {
+        Default::default()
+    }
+
+    pub fn push(&mut self, elem: &ElementData, classes: &HashSet<&str>) {
+        for hash in element_hashes(elem, classes) {
+            for slot in slots(hash) {
+                //a saturated counter stays put, so it never drops back to zero while still in use
+                self.counters[slot] = self.counters[slot].saturating_add(1);
+            }
+        }
+    }
+
+    pub fn pop(&mut self, elem: &ElementData, classes: &HashSet<&str>) {
+        for hash in element_hashes(elem, classes) {
+            for slot in slots(hash) {
+                if self.counters[slot] != u8::MAX {
+                    self.counters[slot] -= 1;
+                }
+            }
+        }
+    }
+
+    pub fn might_have(&self, hash: u32) -> bool {
+        slots(hash).iter().all(|&slot| self.counters[slot] > 0)
+    }
+
+    // True if some hash the entry needs is definitely missing from the ancestors.
+    pub fn rejects(&self, entry: &SelectorEntry) -> bool {
+        !entry.ancestor_hashes.iter().all(|&hash| self.might_have(hash))
+    }
+}
+
+fn element_hashes<'e>(elem: &'e ElementData, classes: &'e HashSet<&str>) -> impl Iterator<Item = u32> + 'e {
+    let id = elem.id().map(|id| hash(b'#', id));
+    let classes = classes.iter().map(|class| hash(b'.', class));
+    id.into_iter().chain(classes).chain(std::iter::once(hash(b't', &elem.tag_name)))
+}
+
+// Two counters per hash, from its low and high bits.
+fn slots(hash: u32) -> [usize; 2] {
+    [hash as usize % FILTER_SIZE, (hash >> 16) as usize % FILTER_SIZE]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::css::CssRule;
+    use crate::css::Stylesheet;
+    use crate::css_parser;
+
+    fn element(tag_name: &str, attributes: &[(&str, &str)]) -> ElementData {
+        let attributes = attributes.iter().map(|&(name, value)| (name.to_string(), value.to_string())).collect();
+        ElementData {tag_name: tag_name.to_string(), attributes}
+    }
+
+    fn selector_map(stylesheet: &Stylesheet) -> SelectorMap<'_> {
+        let mut map = SelectorMap::new();
+        for (i, rule) in stylesheet.rules.iter().enumerate() {
+            if let CssRule::Style(rule) = rule {
+                for selector in &rule.selectors {
+                    map.insert(i, selector);
+                }
+            }
+        }
+        map
+    }
+
+    // The rules of the candidates for `elem`, in order.
+    fn candidates(map: &SelectorMap, elem: &ElementData) -> Vec<usize> {
+        let classes = elem.classes();
+        let mut rules: Vec<usize> = map.candidates(elem, &classes).map(|entry| entry.rule).collect();
+        rules.sort();
+        rules
+    }
+
+    #[test]
+    fn selectors_are_bucketed_by_id_class_and_tag() {
+        let stylesheet = css_parser::parse("#x {} .a {} .b {} p {} div {} * {} #y {} p.a {} #x.c {}".to_string());
+        let map = selector_map(&stylesheet);
+        //p.a is filed under its class, #x.c under its id, so it comes up without the class
+        assert_eq!(candidates(&map, &element("p", &[("id", "x"), ("class", "a b")])), [0, 1, 2, 3, 5, 7, 8]);
+        assert_eq!(candidates(&map, &element("div", &[])), [4, 5]);
+        assert_eq!(candidates(&map, &element("span", &[("class", "c")])), [5]);
+    }
+
+    #[test]
+    fn each_selector_is_a_candidate_once() {
+        //in more than one bucket the element is in, and repeated in the class attribute
+        let stylesheet = css_parser::parse(".a.b {} p#x.a {} .b, .a {}".to_string());
+        let map = selector_map(&stylesheet);
+        let elem = element("p", &[("id", "x"), ("class", "a b a")]);
+        //the last rule has two selectors
+        assert_eq!(candidates(&map, &elem), [0, 1, 2, 2]);
+    }
+
+    #[test]
+    fn popping_an_ancestor_undoes_pushing_it() {
+        let (outer, inner) = (element("div", &[("class", "a")]), element("p", &[("id", "x")]));
+        let mut filter = AncestorFilter::new();
+        filter.push(&outer, &outer.classes());
+        filter.push(&inner, &inner.classes());
+        assert!(filter.might_have(hash(b'.', "a")) && filter.might_have(hash(b'#', "x")) && filter.might_have(hash(b't', "p")));
+
+        filter.pop(&inner, &inner.classes());
+        assert!(filter.might_have(hash(b'.', "a")) && filter.might_have(hash(b't', "div")));
+        assert!(!filter.might_have(hash(b'#', "x")) && !filter.might_have(hash(b't', "p")));
+
+        filter.pop(&outer, &outer.classes());
+        assert!(filter.counters.iter().all(|&counter| counter == 0));
+    }
+
+    #[test]
+    fn saturated_counters_stay_put() {
+        let elem = element("div", &[]);
+        let mut filter = AncestorFilter::new();
+        for _ in 0..300 {
+            filter.push(&elem, &elem.classes());
+        }
+        for _ in 0..300 {
+            filter.pop(&elem, &elem.classes());
+        }
+        //it can't know how far past the maximum it went, so it has to keep saying maybe
+        assert!(filter.might_have(hash(b't', "div")));
+    }
+
+    #[test]
+    fn entries_whose_ancestors_are_missing_are_rejected() {
+        let stylesheet = css_parser::parse(".a > p {} #x p {} div + p {}".to_string());
+        let map = selector_map(&stylesheet);
+        let (ancestor, elem) = (element("div", &[("class", "a")]), element("p", &[]));
+        let classes = elem.classes();
+        let entry = |rule| map.candidates(&elem, &classes).find(|entry| entry.rule == rule).unwrap();
+
+        let mut filter = AncestorFilter::new();
+        assert!(filter.rejects(entry(0)) && filter.rejects(entry(1)));
+        //a sibling isn't an ancestor, so nothing is needed for it
+        assert!(!filter.rejects(entry(2)));
+
+        filter.push(&ancestor, &ancestor.classes());
+        assert!(!filter.rejects(entry(0)));
+        assert!(filter.rejects(entry(1)));
+    }
+}
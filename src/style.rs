@@ -1,31 +1,454 @@
-
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Arc;
 
 use crate::dom::ElementData;
 use crate::dom::Node;
 use crate::dom::NodeType;
+use crate::css::Color;
+use crate::css::CssRule;
+use crate::css::Declaration;
+use crate::css::KeyframesRule;
 use crate::css::Stylesheet;
+use crate::css::Combinator;
+use crate::css::ComplexSelector;
+use crate::css::PseudoClass;
+use crate::css::PseudoElement;
 use crate::css::Selector;
 use crate::css::SimpleSelector;
 use crate::css::Specificity;
+use crate::css::Unit;
 use crate::css::Rule;
 use crate::css::Value;
+use crate::media::MediaEnvironment;
+use crate::properties;
+use crate::selector_map::AncestorFilter;
+use crate::selector_map::DescendantFeatures;
+use crate::selector_map::SelectorMap;
+
+pub struct StyledNode<'a> {
+    pub node: &'a Node,
+    // set for a pseudo-element, whose node is the element it belongs to
+    pub pseudo_element: Option<PseudoElement>,
+    pub specified_values: Arc<PropertyMap>,
+    pub children: Vec<StyledNode<'a>>,
+    // the ::marker, ::before and ::after of an element, the ones any rules apply to along with
+    // the ::marker of a list item
+    pub pseudo_elements: Vec<StyledNode<'a>>,
+}
+
+pub type PropertyMap = HashMap<String, Value>;
+
+impl fmt::Display for StyledNode<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_indented(f, 0)
+    }
+}
+
+impl StyledNode<'_> {
+    fn write_indented(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        let label = match &self.node.node_type {
+            NodeType::Element(elem) => format!("<{}>{}", elem.tag_name, self.pseudo_element.map_or(String::new(), |pseudo_element| pseudo_element.to_string())),
+            NodeType::Text(_) => "#text".to_string(),
+            NodeType::Comment(_) => "#comment".to_string(),
+        };
+        let mut names: Vec<&String> = self.specified_values.keys().collect();
+        names.sort();
+        let values = names.iter()
+            .map(|name| format!(" {}: {};", name, self.specified_values[*name]))
+            .collect::<String>();
+        writeln!(f, "{}{}{}", "  ".repeat(depth), label, values)?;
+        for child in self.pseudo_elements.iter().chain(&self.children) {
+            child.write_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Display {
+    Inline,
+    Block,
+    FlowRoot,
+    InlineBlock,
+    Flex,
+    InlineFlex,
+    Grid,
+    InlineGrid,
+    Table,
+    InlineTable,
+    TableRowGroup,
+    TableHeaderGroup,
+    TableFooterGroup,
+    TableRow,
+    TableCell,
+    TableColumnGroup,
+    TableColumn,
+    TableCaption,
+    ListItem,
+    None,
+}
+
+impl StyledNode<'_> {
+    // The specified value of a property, if the cascade gave it one.
+    pub fn value(&self, name: &str) -> Option<Value> {
+        self.specified_values.get(name).cloned()
+    }
+
+    // The value of `name`, or failing that of `fallback_name`, or failing that `default`.
+    pub fn lookup(&self, name: &str, fallback_name: &str, default: &Value) -> Value {
+        self.value(name).unwrap_or_else(|| self.value(fallback_name).unwrap_or_else(|| default.clone()))
+    }
+
+    pub fn display(&self) -> Display {
+        match self.value("display") {
+            Some(Value::Keyword(keyword)) => match &*keyword {
+                "block" => Display::Block,
+                "flow-root" => Display::FlowRoot,
+                "inline-block" => Display::InlineBlock,
+                "flex" => Display::Flex,
+                "inline-flex" => Display::InlineFlex,
+                "grid" => Display::Grid,
+                "inline-grid" => Display::InlineGrid,
+                "table" => Display::Table,
+                "inline-table" => Display::InlineTable,
+                "table-row-group" => Display::TableRowGroup,
+                "table-header-group" => Display::TableHeaderGroup,
+                "table-footer-group" => Display::TableFooterGroup,
+                "table-row" => Display::TableRow,
+                "table-cell" => Display::TableCell,
+                "table-column-group" => Display::TableColumnGroup,
+                "table-column" => Display::TableColumn,
+                "table-caption" => Display::TableCaption,
+                "list-item" => Display::ListItem,
+                "none" => Display::None,
+                _ => Display::Inline,
+            },
+            _ => Display::Inline,
+        }
+    }
+}
+
+// The style rules of a stylesheet that apply in a media environment, in source order.
+// Rules inside @media, @supports and conditional @imports are only included while their
+// conditions hold.
+pub struct Cascade<'a> {
+    stylesheet: &'a Stylesheet,
+    environment: MediaEnvironment,
+    rules: Vec<CascadeRule<'a>>,
+    keyframes: HashMap<String, &'a KeyframesRule>,
+    // the dotted name of the layer with each rank, None for unlayered rules
+    layer_names: Vec<Option<String>>,
+    selector_map: SelectorMap<'a>,
+}
+
+// A rule along with the rank of its cascade layer; declarations in higher ranked layers win.
+// Unlayered rules get the highest rank of all.
+#[derive(Clone, Copy)]
+struct CascadeRule<'a> {
+    rule: &'a Rule,
+    layer: usize,
+    // url of the stylesheet the rule is in
+    stylesheet: &'a str,
+}
+
+// The rules and @keyframes that apply, and the names of the layers they're in.
+struct CollectedRules<'a> {
+    rules: Vec<CascadeRule<'a>>,
+    keyframes: HashMap<String, &'a KeyframesRule>,
+    layer_names: Vec<Option<String>>,
+}
+
+impl<'a> Cascade<'a> {
+    pub fn new(stylesheet: &'a Stylesheet, environment: MediaEnvironment) -> Cascade<'a> {
+        let CollectedRules {rules, keyframes, layer_names} = RuleCollector::collect(stylesheet, &environment);
+        let selector_map = build_selector_map(&rules);
+        Cascade {stylesheet, environment, rules, keyframes, layer_names, selector_map}
+    }
+
+    pub fn environment(&self) -> &MediaEnvironment {
+        &self.environment
+    }
+
+    // Re-evaluates the media queries, returns true if a different set of rules now applies
+    // and the style tree has to be rebuilt.
+    pub fn set_environment(&mut self, environment: MediaEnvironment) -> bool {
+        let CollectedRules {rules, keyframes, layer_names} = RuleCollector::collect(self.stylesheet, &environment);
+        let changed = rules.len() != self.rules.len()
+            || rules.iter().zip(&self.rules).any(|(a, b)| !std::ptr::eq(a.rule, b.rule) || a.layer != b.layer)
+            || keyframes.len() != self.keyframes.len()
+            || keyframes.iter().any(|(name, rule)| !self.keyframes.get(name).is_some_and(|other| std::ptr::eq(*rule, *other)));
+        self.environment = environment;
+        self.selector_map = build_selector_map(&rules);
+        self.rules = rules;
+        self.keyframes = keyframes;
+        self.layer_names = layer_names;
+        changed
+    }
+
+    // The @keyframes rule animations with this name use.
+    pub fn keyframes(&self, name: &str) -> Option<&'a KeyframesRule> {
+        self.keyframes.get(name).copied()
+    }
+}
+
+fn build_selector_map<'a>(rules: &[CascadeRule<'a>]) -> SelectorMap<'a> {
+    let mut selector_map = SelectorMap::new();
+    for (index, rule) in rules.iter().enumerate() {
+        for selector in &rule.rule.selectors {
+            selector_map.insert(index, selector);
+        }
+    }
+    selector_map
+}
+
+// Flattens the rule tree of a stylesheet into the rules that apply, and works out the order
+// of the cascade layers they were declared in.
+struct RuleCollector<'a, 'e> {
+    environment: &'e MediaEnvironment,
+    // layer tree, node 0 is the implicit outer layer holding unlayered rules
+    layer_children: Vec<Vec<(String, usize)>>,
+    anonymous_layers: usize,
+    rules: Vec<CascadeRule<'a>>,
+    keyframes: Vec<(usize, &'a KeyframesRule)>,
+}
+
+impl<'a, 'e> RuleCollector<'a, 'e> {
+    fn collect(stylesheet: &'a Stylesheet, environment: &'e MediaEnvironment) -> CollectedRules<'a> {
+        let mut collector = RuleCollector {
+            environment,
+            layer_children: vec![vec![]],
+            anonymous_layers: 0,
+            rules: vec![],
+            keyframes: vec![],
+        };
+        collector.collect_rules(&stylesheet.rules, 0, &stylesheet.url);
+
+        //layers are ordered by first declaration, with a layer's sublayers coming before the
+        //layer's own rules, so a post-order walk of the tree gives each its rank
+        let mut ranks = vec![0; collector.layer_children.len()];
+        let mut next_rank = 0;
+        collector.rank_layers(0, &mut ranks, &mut next_rank);
+        for rule in &mut collector.rules {
+            rule.layer = ranks[rule.layer];
+        }
+
+        //of several @keyframes with the same name the one in the highest layer wins, then the last
+        let mut keyframes: HashMap<String, (usize, &KeyframesRule)> = HashMap::new();
+        for &(layer, rule) in &collector.keyframes {
+            let rank = ranks[layer];
+            if keyframes.get(&rule.name).is_none_or(|&(other, _)| rank >= other) {
+                keyframes.insert(rule.name.clone(), (rank, rule));
+            }
+        }
 
-struct StyledNode<'a> {
-    node: &'a Node,
-    specified_values: PropertyMap,
-    children: Vec<StyledNode<'a>>,
+        let mut layer_names = vec![None; ranks.len()];
+        collector.name_layers(0, "", &ranks, &mut layer_names);
+        CollectedRules {
+            rules: collector.rules,
+            keyframes: keyframes.into_iter().map(|(name, (_, rule))| (name, rule)).collect(),
+            layer_names,
+        }
+    }
+
+    // Names every layer under `layer`, whose own name is `name`, by rank.
+    fn name_layers(&self, layer: usize, name: &str, ranks: &[usize], names: &mut Vec<Option<String>>) {
+        for (child_name, child) in &self.layer_children[layer] {
+            let child_name = if child_name.starts_with(' ') { "<anonymous>" } else { child_name };
+            let full_name = if name.is_empty() { child_name.to_string() } else { format!("{}.{}", name, child_name) };
+            self.name_layers(*child, &full_name, ranks, names);
+            names[ranks[*child]] = Some(full_name);
+        }
+    }
+
+    fn rank_layers(&self, layer: usize, ranks: &mut Vec<usize>, next_rank: &mut usize) {
+        for &(_, child) in &self.layer_children[layer] {
+            self.rank_layers(child, ranks, next_rank);
+        }
+        ranks[layer] = *next_rank;
+        *next_rank += 1;
+    }
+
+    fn sublayer(&mut self, parent: usize, name: &str) -> usize {
+        if let Some(&(_, child)) = self.layer_children[parent].iter().find(|(child_name, _)| child_name == name) {
+            return child;
+        }
+        let child = self.layer_children.len();
+        self.layer_children.push(vec![]);
+        self.layer_children[parent].push((name.to_string(), child));
+        child
+    }
+
+    fn declare_layer(&mut self, parent: usize, name: &[String]) -> usize {
+        if name.is_empty() {
+            //every anonymous layer is distinct, so give it a name no stylesheet can write
+            self.anonymous_layers += 1;
+            let name = format!(" anonymous {}", self.anonymous_layers);
+            return self.sublayer(parent, &name);
+        }
+        name.iter().fold(parent, |layer, part| self.sublayer(layer, part))
+    }
+
+    fn collect_rules(&mut self, rules: &'a [CssRule], layer: usize, stylesheet: &'a str) {
+        for rule in rules {
+            match rule {
+                CssRule::Style(rule) => {
+                    self.rules.push(CascadeRule {rule, layer, stylesheet});
+                    self.collect_rules(&rule.children, layer, stylesheet);
+                },
+                CssRule::Media(media) => {
+                    if self.environment.matches(&media.queries) {
+                        self.collect_rules(&media.rules, layer, stylesheet);
+                    }
+                },
+                CssRule::Supports(supports) => {
+                    if properties::supports(&supports.condition) {
+                        self.collect_rules(&supports.rules, layer, stylesheet);
+                    }
+                },
+                CssRule::Layer(layer_rule) => {
+                    let name = layer_rule.name.clone().unwrap_or_default();
+                    let sublayer = self.declare_layer(layer, &name);
+                    self.collect_rules(&layer_rule.rules, sublayer, stylesheet);
+                },
+                CssRule::LayerStatement(names) => {
+                    for name in names {
+                        self.declare_layer(layer, name);
+                    }
+                },
+                CssRule::Import(import) => {
+                    let applies = self.environment.matches(&import.queries)
+                        && import.supports.iter().all(properties::supports);
+                    if let (true, Some(imported)) = (applies, &import.stylesheet) {
+                        let layer = match &import.layer {
+                            Some(name) => self.declare_layer(layer, name),
+                            None => layer,
+                        };
+                        self.collect_rules(&imported.rules, layer, &imported.url);
+                    }
+                },
+                CssRule::Keyframes(keyframes) => self.keyframes.push((layer, keyframes)),
+                CssRule::FontFace(_) => {},
+            }
+        }
+    }
+}
+
+// An element with its class list split up front, since selectors ask for it over and over.
+struct ElementInfo<'a> {
+    elem: &'a ElementData,
+    classes: HashSet<&'a str>,
+}
+
+impl<'a> ElementInfo<'a> {
+    fn new(node: &'a Node) -> Option<ElementInfo<'a>> {
+        match &node.node_type {
+            NodeType::Element(elem) => Some(ElementInfo {elem, classes: elem.classes()}),
+            _ => None,
+        }
+    }
 }
 
-type PropertyMap = HashMap<String, Value>;
+// The element children of one node, and which of them is being styled right now. Cloning one
+// is cheap, so parallel traversals can each take a copy of the levels above them.
+#[derive(Clone)]
+struct Level<'a> {
+    elements: Arc<Vec<ElementInfo<'a>>>,
+    current: usize,
+}
 
-fn matches(elem: &ElementData, selector: &Selector) -> bool {
+impl<'a> Level<'a> {
+    fn new(nodes: &'a [Node]) -> Level<'a> {
+        Level {elements: Arc::new(nodes.iter().filter_map(ElementInfo::new).collect()), current: 0}
+    }
+}
+
+// An element together with the levels of the tree above it, root first, which is everything
+// combinators need to look at. The element is `index` in the last level, the ancestors are
+// the `current` element of the levels before.
+#[derive(Clone, Copy)]
+struct ElementPath<'a, 'p> {
+    levels: &'p [Level<'a>],
+    index: usize,
+}
+
+impl<'a, 'p> ElementPath<'a, 'p> {
+    fn info(&self) -> &'p ElementInfo<'a> {
+        &self.levels[self.levels.len() - 1].elements[self.index]
+    }
+
+    fn parent(&self) -> Option<ElementPath<'a, 'p>> {
+        let levels = &self.levels[..self.levels.len() - 1];
+        levels.last().map(|level| ElementPath {levels, index: level.current})
+    }
+
+    // element siblings before this one, closest first
+    fn previous_siblings(&self) -> impl Iterator<Item = ElementPath<'a, 'p>> + '_ {
+        (0..self.index).rev().map(move |index| ElementPath {levels: self.levels, index})
+    }
+}
+
+fn matches(elem: ElementPath, selector: &Selector) -> bool {
+    matches_pseudo_element(elem, selector, None)
+}
+
+// Whether the selector selects `pseudo_element` of `elem`, or `elem` itself for None.
+fn matches_pseudo_element(elem: ElementPath, selector: &Selector, pseudo_element: Option<PseudoElement>) -> bool {
+    if subject(selector).pseudo_element != pseudo_element {
+        return false;
+    }
     match *selector {
         Selector::Simple(ref sel) => matches_simple_selector(elem, sel),
+        Selector::Complex(ref sel) => matches_complex_selector(elem, sel, sel.parts.len() - 1),
     }
 }
 
-fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> bool {
+// Whether the selector selects `elem` or the pseudo-element of it that it is for.
+fn matches_element_or_pseudo_element(elem: ElementPath, selector: &Selector) -> bool {
+    matches_pseudo_element(elem, selector, subject(selector).pseudo_element)
+}
+
+// The rightmost compound of a selector, the one for the element it selects.
+fn subject(selector: &Selector) -> &SimpleSelector {
+    match selector {
+        Selector::Simple(simple) => simple,
+        Selector::Complex(complex) => &complex.parts[complex.parts.len() - 1],
+    }
+}
+
+// Matches parts[..=part] with parts[part] matching `elem`, walking right to left.
+fn matches_complex_selector(elem: ElementPath, selector: &ComplexSelector, part: usize) -> bool {
+    if !matches_simple_selector(elem, &selector.parts[part]) {
+        return false;
+    }
+    if part == 0 {
+        return true;
+    }
+
+    match selector.combinators[part - 1] {
+        Combinator::Child => elem.parent().is_some_and(|parent| matches_complex_selector(parent, selector, part - 1)),
+        Combinator::Descendant => {
+            let mut ancestor = elem.parent();
+            while let Some(elem) = ancestor {
+                if matches_complex_selector(elem, selector, part - 1) {
+                    return true;
+                }
+                ancestor = elem.parent();
+            }
+            false
+        },
+        Combinator::NextSibling => elem.previous_siblings().next().is_some_and(|sibling| matches_complex_selector(sibling, selector, part - 1)),
+        Combinator::SubsequentSibling => elem.previous_siblings().any(|sibling| matches_complex_selector(sibling, selector, part - 1)),
+    }
+}
+
+fn matches_simple_selector(path: ElementPath, selector: &SimpleSelector) -> bool {
+    let info = path.info();
+    let elem = info.elem;
 
     //check if tag doesnt match
     if selector.tag_name.iter().any(|name| elem.tag_name != *name) {
@@ -38,30 +461,196 @@ fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> boo
     }
 
     //check if class doesnt match
-    if selector.class.iter().any(|class| !elem.classes().contains(&**class)) {
+    if selector.class.iter().any(|class| !info.classes.contains(&**class)) {
+        return false;
+    }
+
+    //check if a pseudo class doesnt match
+    let pseudo_class_fails = |pseudo_class: &PseudoClass| match pseudo_class {
+        PseudoClass::Is(selectors) | PseudoClass::Where(selectors) => !selectors.iter().any(|sel| matches(path, sel)),
+        PseudoClass::Not(selectors) => selectors.iter().any(|sel| matches(path, sel)),
+    };
+    if selector.pseudo_classes.iter().any(pseudo_class_fails) {
         return false;
     }
 
     //no non-matching selectors found
-    return true;
+    true
+}
+
+// (layer rank, specificity, rule)
+type MatchedRule<'a> = (usize, Specificity, &'a Rule);
+
+fn match_rule<'a>(elem: ElementPath, rule: &CascadeRule<'a>, pseudo_element: Option<PseudoElement>) -> Option<MatchedRule<'a>> {
+    rule.rule.selectors.iter().rev().find(|sel| matches_pseudo_element(elem, sel, pseudo_element)).map(|sel| (rule.layer, sel.specificity(), rule.rule))
 }
 
-type MatchedRule<'a> = (Specificity, &'a Rule);
+// The rules for `pseudo_element` of the element, or the element itself for None. Only looks at
+// the selectors the selector map files under the element's id, classes and tag, skipping those
+// whose ancestors the filter says aren't there.
+fn matching_rules<'a>(elem: ElementPath, filter: &AncestorFilter, cascade: &Cascade<'a>, pseudo_element: Option<PseudoElement>) -> Vec<MatchedRule<'a>> {
+    let info = elem.info();
+    let mut matched: Vec<(usize, Specificity)> = cascade.selector_map.candidates(info.elem, &info.classes)
+        .filter(|entry| !filter.rejects(entry) && matches_pseudo_element(elem, entry.selector, pseudo_element))
+        .map(|entry| (entry.rule, entry.specificity))
+        .collect();
 
-fn match_rule<'a>(elem: &ElementData, rule: &'a Rule) -> Option<MatchedRule<'a>> {
-    rule.selectors.iter().find(|sel| matches(elem, sel)).map(|sel| (sel.specificity(), rule))
+    //back into source order, with only the most specific matching selector of each rule
+    matched.sort_by_key(|&(rule, specificity)| (rule, Reverse(specificity)));
+    matched.dedup_by_key(|&mut (rule, _)| rule);
+    matched.into_iter().map(|(rule, specificity)| {
+        let rule = &cascade.rules[rule];
+        (rule.layer, specificity, rule.rule)
+    }).collect()
 }
 
-fn matching_rules<'a>(elem: &ElementData, stylesheet: &'a Stylesheet) -> Vec<MatchedRule<'a>> {
-    stylesheet.rules.iter().filter_map(|rule| match_rule(elem, rule)).collect()
+fn matching_rules_linear<'a>(elem: ElementPath, cascade: &Cascade<'a>, pseudo_element: Option<PseudoElement>) -> Vec<MatchedRule<'a>> {
+    cascade.rules.iter().filter_map(|rule| match_rule(elem, rule, pseudo_element)).collect()
 }
 
-fn specified_values(elem: &ElementData, stylesheet: &Stylesheet) -> PropertyMap {
-    let mut values = HashMap::new();
-    let mut matched_rules = matching_rules(elem, stylesheet);
-    matched_rules.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
-    for (_, rule) in matched_rules {
-        for declaration in &rule.declarations {
+// HTML attributes that map to CSS properties, on the elements they apply to (all of them if
+// none are listed), with the keyword values they may take.
+const PRESENTATIONAL_HINTS: &[(&str, &str, &[&str], &[&str])] = &[
+    ("align", "text-align", &["div", "p", "h1", "h2", "h3", "h4", "h5", "h6"], &["left", "right", "center", "justify"]),
+    ("dir", "direction", &[], &["ltr", "rtl"]),
+];
+
+// HTML attributes that map to lengths, on the elements they apply to. Their values are a
+// number of pixels or a percentage.
+const DIMENSION_HINTS: &[(&str, &str, &[&str])] = &[
+    ("width", "width", &["table", "col", "td", "th", "img"]),
+    ("height", "height", &["table", "tr", "td", "th", "img"]),
+    ("cellspacing", "border-spacing", &["table"]),
+];
+
+// The display of each table element, from the rendering section of HTML. Without a user
+// agent style sheet to say so, these are presentational hints as well.
+const TABLE_DISPLAYS: &[(&str, &str)] = &[
+    ("table", "table"), ("caption", "table-caption"), ("colgroup", "table-column-group"), ("col", "table-column"),
+    ("thead", "table-header-group"), ("tbody", "table-row-group"), ("tfoot", "table-footer-group"),
+    ("tr", "table-row"), ("td", "table-cell"), ("th", "table-cell"),
+];
+
+// The attributes list_hints looks at.
+const LIST_HINT_ATTRIBUTES: &[&str] = &["type", "start", "reversed", "value"];
+
+// The hints of lists, from the rendering section of HTML: list items are list-items, and
+// lists are blocks indented to leave room for the markers, which reset the list-item counter.
+// An ol is numbered in the style its type asks for, from start and downwards if it is
+// reversed, and an li can change the number it gets with value.
+fn list_hints(elem: &ElementData) -> Vec<(String, Value)> {
+    let keyword = |keyword: &str| Value::Keyword(keyword.to_string());
+    let integer = |attribute: &str| elem.attributes.get(attribute).and_then(|value| value.trim().parse::<i32>().ok());
+    let counter = |name: Value, value: Option<i32>| match value {
+        Some(value) => Value::List(vec![name, Value::Length(value as f32, Unit::None)]),
+        None => name,
+    };
+    match &*elem.tag_name {
+        "li" => {
+            let mut hints = vec![("display".to_string(), keyword("list-item"))];
+            if let Some(value) = integer("value") {
+                hints.push(("counter-set".to_string(), counter(keyword("list-item"), Some(value))));
+            }
+            hints
+        },
+        "ul" | "ol" | "menu" => {
+            let is_ol = elem.tag_name == "ol";
+            let reversed = is_ol && elem.attributes.contains_key("reversed");
+            let name = match reversed {
+                true => Value::Function("reversed".to_string(), vec![keyword("list-item")]),
+                false => keyword("list-item"),
+            };
+            //the counter is incremented before the first item, so it starts one away
+            let start = integer("start").filter(|_| is_ol).map(|start| if reversed { start + 1 } else { start - 1 });
+            let mut hints = vec![
+                ("display".to_string(), keyword("block")),
+                ("padding-left".to_string(), Value::Length(40.0, Unit::Px)),
+                ("counter-reset".to_string(), counter(name, start)),
+            ];
+            if is_ol {
+                //unlike other attributes, type is case-sensitive
+                let list_style_type = match elem.attributes.get("type").map(|value| value.trim()) {
+                    Some("a") => "lower-alpha",
+                    Some("A") => "upper-alpha",
+                    Some("i") => "lower-roman",
+                    Some("I") => "upper-roman",
+                    _ => "decimal",
+                };
+                hints.push(("list-style-type".to_string(), keyword(list_style_type)));
+            }
+            hints
+        },
+        _ => vec![],
+    }
+}
+
+// The presentational hints of an element, which act like declarations of a rule that comes
+// before all author rules.
+fn presentational_hints(elem: &ElementData) -> impl Iterator<Item = (String, Value)> + '_ {
+    let hints = PRESENTATIONAL_HINTS.iter().filter_map(move |&(attribute, property, tag_names, keywords)| {
+        let value = elem.attributes.get(attribute)?.trim().to_ascii_lowercase();
+        if !(tag_names.is_empty() || tag_names.contains(&&*elem.tag_name)) || !keywords.contains(&&*value) {
+            return None;
+        }
+        Some((property.to_string(), Value::Keyword(value)))
+    });
+    let dimensions = DIMENSION_HINTS.iter().filter_map(move |&(attribute, property, tag_names)| {
+        let value = dimension(elem.attributes.get(attribute)?)?;
+        if !tag_names.contains(&&*elem.tag_name) || !properties::is_supported(property, &value) {
+            return None;
+        }
+        Some((property.to_string(), value))
+    });
+    let display = TABLE_DISPLAYS.iter().find(|(tag_name, _)| *tag_name == elem.tag_name)
+        .map(|(_, display)| ("display".to_string(), Value::Keyword(display.to_string())));
+    let unicode_bidi = unicode_bidi_hint(elem).map(|value| ("unicode-bidi".to_string(), Value::Keyword(value.to_string())));
+    hints.chain(dimensions).chain(display).chain(list_hints(elem)).chain(unicode_bidi)
+}
+
+// A length attribute like width="80%", by the rules for parsing dimension values: a number
+// of pixels, or a percentage if a % follows it. Anything after that is ignored.
+fn dimension(value: &str) -> Option<Value> {
+    let value = value.trim();
+    let end = value.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(value.len());
+    let number: f32 = value[..end].parse().ok()?;
+    match value[end..].starts_with('%') {
+        true => Some(Value::Percentage(number)),
+        false => Some(Value::Length(number, Unit::Px)),
+    }
+}
+
+// The unicode-bidi of elements with a dir attribute and of bdi and bdo, from the rendering
+// section of HTML. Their direction comes from the dir attribute, except that dir=auto and a
+// bdi without one ask for the direction of their content, which plaintext gets for each of
+// their paragraphs.
+fn unicode_bidi_hint(elem: &ElementData) -> Option<&'static str> {
+    let dir = elem.attributes.get("dir").map(|dir| dir.trim().to_ascii_lowercase());
+    match (&*elem.tag_name, dir.as_deref()) {
+        ("bdo", _) => Some("isolate-override"),
+        ("bdi", None) | (_, Some("auto")) => Some("plaintext"),
+        ("bdi", _) | (_, Some(_)) => Some("isolate"),
+        _ => None,
+    }
+}
+
+fn specified_values(elem: &ElementData, matched_rules: Vec<MatchedRule>) -> PropertyMap {
+    cascade_declarations(presentational_hints(elem).collect(), matched_rules)
+}
+
+// The declarations of the rules on top of `values`, in the order of the cascade.
+fn cascade_declarations(mut values: PropertyMap, mut matched_rules: Vec<MatchedRule>) -> PropertyMap {
+    //normal declarations: later layers win, then higher specificity, then later in the source
+    matched_rules.sort_by_key(|&(layer, specificity, _)| (layer, specificity));
+    for &(_, _, rule) in &matched_rules {
+        for declaration in rule.declarations.iter().filter(|declaration| !declaration.important) {
+            values.insert(declaration.name.clone() , declaration.value.clone());
+        }
+    }
+
+    //important declarations beat all normal ones, and the layer order is reversed for them
+    matched_rules.sort_by_key(|&(layer, specificity, _)| (Reverse(layer), specificity));
+    for &(_, _, rule) in &matched_rules {
+        for declaration in rule.declarations.iter().filter(|declaration| declaration.important) {
             values.insert(declaration.name.clone() , declaration.value.clone());
         }
     }
@@ -69,13 +658,1097 @@ fn specified_values(elem: &ElementData, stylesheet: &Stylesheet) -> PropertyMap
     values
 }
 
-fn style_tree<'a>(root: &'a Node, stylesheet: &Stylesheet) -> StyledNode<'a> {
+// The specified values of an element's pseudo-elements, see StyledNode::pseudo_elements.
+type PseudoStyles = Vec<(PseudoElement, Arc<PropertyMap>)>;
+
+// What a user agent style sheet would give a ::marker, CSS Lists 3 §3.2, which author rules
+// override like presentational hints.
+const MARKER_HINTS: &[(&str, &str)] = &[("white-space", "pre"), ("unicode-bidi", "isolate")];
+
+// The specified values of an element and its pseudo-elements, with `matching_rules` finding
+// the rules for the element (None) or one of its pseudo-elements.
+fn element_styles<'a>(elem: &ElementData, cascade: &Cascade<'a>, matching_rules: impl Fn(Option<PseudoElement>) -> Vec<MatchedRule<'a>>) -> (Arc<PropertyMap>, PseudoStyles) {
+    let style = specified_values(elem, matching_rules(None));
+    let list_item = matches!(style.get("display"), Some(Value::Keyword(display)) if display == "list-item");
+    let pseudo_styles = [PseudoElement::Marker, PseudoElement::Before, PseudoElement::After].iter().filter_map(|&pseudo_element| {
+        let rules = if cascade.selector_map.has_pseudo_elements() { matching_rules(Some(pseudo_element)) } else { vec![] };
+        let is_marker = pseudo_element == PseudoElement::Marker;
+        if rules.is_empty() && !(list_item && is_marker) {
+            return None;
+        }
+        let hints = MARKER_HINTS.iter().filter(|_| is_marker).map(|(name, value)| (name.to_string(), Value::Keyword(value.to_string())));
+        Some((pseudo_element, Arc::new(cascade_declarations(hints.collect(), rules))))
+    }).collect();
+    (Arc::new(style), pseudo_styles)
+}
+
+// The styled nodes of an element's pseudo-elements.
+fn pseudo_element_nodes<'a>(node: &'a Node, pseudo_styles: &PseudoStyles) -> Vec<StyledNode<'a>> {
+    pseudo_styles.iter().map(|(pseudo_element, specified_values)| StyledNode {
+        node,
+        pseudo_element: Some(*pseudo_element),
+        specified_values: specified_values.clone(),
+        children: vec![],
+        pseudo_elements: vec![],
+    }).collect()
+}
+
+const STYLE_SHARING_CACHE_SIZE: usize = 31;
+
+// Recently styled elements, whose styles later siblings and cousins can reuse instead of
+// going through selector matching again.
+#[derive(Default)]
+struct StyleSharingCache<'a> {
+    candidates: VecDeque<SharingCandidate<'a>>,
+}
+
+// Everything matching depends on for an element without an id: its tag and classes, its
+// ancestors (which are alike whenever the parent styles are the same Arc, since that is only
+// ever shared between alike elements) and the results of the revalidation selectors. There
+// are no attribute selectors, so the only other attributes that matter are the ones with
+// presentational hints.
+struct SharingCandidate<'a> {
+    tag_name: &'a str,
+    classes: Vec<&'a str>,
+    hint_attributes: Vec<Option<&'a String>>,
+    parent_style: Option<Arc<PropertyMap>>,
+    revalidation: Vec<bool>,
+    style: Arc<PropertyMap>,
+    pseudo_styles: PseudoStyles,
+}
+
+impl<'a> StyleSharingCache<'a> {
+    fn style_for(&mut self, elem: ElementPath<'a, '_>, parent_style: Option<&Arc<PropertyMap>>, filter: &AncestorFilter, cascade: &Cascade) -> (Arc<PropertyMap>, PseudoStyles) {
+        let info = elem.info();
+        let styles = || element_styles(info.elem, cascade, |pseudo_element| matching_rules(elem, filter, cascade, pseudo_element));
+        if info.elem.id().is_some() {
+            return styles();
+        }
+
+        let mut classes: Vec<&str> = info.classes.iter().copied().collect();
+        classes.sort_unstable();
+        let hint_attributes: Vec<Option<&String>> = PRESENTATIONAL_HINTS.iter().map(|(attribute, ..)| *attribute)
+            .chain(DIMENSION_HINTS.iter().map(|(attribute, ..)| *attribute))
+            .chain(LIST_HINT_ATTRIBUTES.iter().copied())
+            .map(|attribute| info.elem.attributes.get(attribute))
+            .collect();
+        let revalidation: Vec<bool> = cascade.selector_map.revalidation_selectors().iter()
+            .map(|selector| matches_element_or_pseudo_element(elem, selector))
+            .collect();
+        let shareable = |candidate: &&SharingCandidate| {
+            candidate.tag_name == info.elem.tag_name
+                && candidate.classes == classes
+                && candidate.hint_attributes == hint_attributes
+                && match (&candidate.parent_style, parent_style) {
+                    (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+                    (None, None) => true,
+                    _ => false,
+                }
+                && candidate.revalidation == revalidation
+        };
+        if let Some(candidate) = self.candidates.iter().find(shareable) {
+            return (candidate.style.clone(), candidate.pseudo_styles.clone());
+        }
+
+        let (style, pseudo_styles) = styles();
+        self.candidates.push_front(SharingCandidate {
+            tag_name: &info.elem.tag_name,
+            classes,
+            hint_attributes,
+            parent_style: parent_style.cloned(),
+            revalidation,
+            style: style.clone(),
+            pseudo_styles: pseudo_styles.clone(),
+        });
+        self.candidates.truncate(STYLE_SHARING_CACHE_SIZE);
+        (style, pseudo_styles)
+    }
+}
+
+// What a style traversal carries along from element to element.
+struct StyleContext<'a, 'c, 's> {
+    cascade: &'c Cascade<'s>,
+    filter: AncestorFilter,
+    // None when matching every rule against every element without any shortcuts
+    sharing: Option<StyleSharingCache<'a>>,
+}
+
+pub fn style_tree<'a>(root: &'a Node, cascade: &Cascade) -> StyledNode<'a> {
+    let mut levels = vec![Level::new(std::slice::from_ref(root))];
+    let mut context = StyleContext {cascade, filter: AncestorFilter::new(), sharing: Some(Default::default())};
+    style_subtree(root, &mut levels, None, &mut context)
+}
+
+// Matches every rule against every element, without the selector map, the ancestor filter or
+// the style sharing cache. This is how styling used to work, and is kept to check and
+// benchmark the fast path against.
+pub fn style_tree_linear<'a>(root: &'a Node, cascade: &Cascade) -> StyledNode<'a> {
+    let mut levels = vec![Level::new(std::slice::from_ref(root))];
+    let mut context = StyleContext {cascade, filter: AncestorFilter::new(), sharing: None};
+    style_subtree(root, &mut levels, None, &mut context)
+}
+
+// `node` is the current element of the last level, if it is an element at all.
+fn style_subtree<'a>(node: &'a Node, levels: &mut Vec<Level<'a>>, parent_style: Option<&Arc<PropertyMap>>, context: &mut StyleContext<'a, '_, '_>) -> StyledNode<'a> {
+    let is_element = matches!(node.node_type, NodeType::Element(_));
+    let (specified_values, pseudo_styles) = if is_element {
+        let elem = ElementPath {levels, index: levels[levels.len() - 1].current};
+        let cascade = context.cascade;
+        match &mut context.sharing {
+            Some(sharing) => sharing.style_for(elem, parent_style, &context.filter, cascade),
+            None => element_styles(elem.info().elem, cascade, |pseudo_element| matching_rules_linear(elem, cascade, pseudo_element)),
+        }
+    } else {
+        (Arc::new(HashMap::new()), vec![])
+    };
+
+    let info = ElementInfo::new(node);
+    if let Some(info) = &info {
+        context.filter.push(info.elem, &info.classes);
+    }
+    levels.push(Level::new(&node.children));
+    let children = style_children(node, levels, if is_element { Some(&specified_values) } else { parent_style }, context);
+    levels.pop();
+    if let Some(info) = &info {
+        context.filter.pop(info.elem, &info.classes);
+    }
+
     StyledNode {
-        node: root,
-        specified_values: match &root.node_type {
-            NodeType::Element(elem) => specified_values(&elem, stylesheet),
-            _ => HashMap::new()
+        node,
+        pseudo_element: None,
+        specified_values,
+        children,
+        pseudo_elements: pseudo_element_nodes(node, &pseudo_styles),
+    }
+}
+
+// Styles the children of `node`, whose level is the last one.
+fn style_children<'a>(node: &'a Node, levels: &mut Vec<Level<'a>>, parent_style: Option<&Arc<PropertyMap>>, context: &mut StyleContext<'a, '_, '_>) -> Vec<StyledNode<'a>> {
+    #[cfg(feature = "parallel")]
+    {
+        if context.sharing.is_some() && levels.len() <= PARALLEL_DEPTH {
+            return style_children_parallel(node, levels, parent_style, context);
+        }
+    }
+
+    let mut element_index = 0;
+    node.children.iter().map(|child| {
+        if matches!(child.node_type, NodeType::Element(_)) {
+            levels.last_mut().unwrap().current = element_index;
+            element_index += 1;
+        }
+        style_subtree(child, levels, parent_style, context)
+    }).collect()
+}
+
+// Up to this many levels down, the children are styled in parallel on rayon's pool. Deeper
+// than that the subtrees are too small to be worth the copies of the traversal state.
+#[cfg(feature = "parallel")]
+const PARALLEL_DEPTH: usize = 6;
+
+// The children are split into a contiguous run for each thread, styled as a task of its own
+// with a copy of the levels and the ancestor filter and a style sharing cache that the whole
+// run shares, so the runs don't share anything mutable. Styles come out the same as in a
+// serial traversal, only none are shared between runs.
+#[cfg(feature = "parallel")]
+fn style_children_parallel<'a>(node: &'a Node, levels: &[Level<'a>], parent_style: Option<&Arc<PropertyMap>>, context: &StyleContext<'a, '_, '_>) -> Vec<StyledNode<'a>> {
+    use rayon::prelude::*;
+
+    //where each child is in its level, text nodes don't look at theirs
+    let mut element_count: usize = 0;
+    let children: Vec<(&'a Node, usize)> = node.children.iter().map(|child| {
+        if matches!(child.node_type, NodeType::Element(_)) {
+            element_count += 1;
+        }
+        (child, element_count.saturating_sub(1))
+    }).collect();
+
+    let run_length = children.len().div_ceil(rayon::current_num_threads()).max(1);
+    children.par_chunks(run_length).flat_map_iter(|run| {
+        let mut levels = levels.to_vec();
+        let mut context = StyleContext {
+            cascade: context.cascade,
+            filter: context.filter.clone(),
+            sharing: Some(Default::default()),
+        };
+        run.iter().map(|&(child, index)| {
+            levels.last_mut().unwrap().current = index;
+            style_subtree(child, &mut levels, parent_style, &mut context)
+        }).collect::<Vec<StyledNode<'a>>>()
+    }).collect()
+}
+
+// A DOM that can change after it has been styled, together with its styles. Changes to it
+// record what they affect, and `restyle` then only runs selector matching again for the
+// elements that might match differently.
+pub struct StyledDocument {
+    root: Node,
+    styles: NodeStyle,
+    // the first styling, or after a stylesheet change
+    restyle_all: bool,
+    // rightmost compounds of the selectors of rules that were added or removed
+    changed_rules: Vec<SimpleSelector>,
+}
+
+// The style of one node of a StyledDocument, in a tree mirroring the DOM.
+struct NodeStyle {
+    style: Arc<PropertyMap>,
+    pseudo_styles: PseudoStyles,
+    children: Vec<NodeStyle>,
+    dirty: bool,
+    // classes and ids added to or removed from the element since the last restyle
+    changed_classes: Vec<String>,
+    changed_ids: Vec<String>,
+    // children from this index on were inserted or moved over by a removal
+    children_changed_from: Option<usize>,
+}
+
+impl NodeStyle {
+    fn new(node: &Node) -> NodeStyle {
+        NodeStyle {
+            style: Arc::new(HashMap::new()),
+            pseudo_styles: vec![],
+            children: node.children.iter().map(NodeStyle::new).collect(),
+            dirty: true,
+            changed_classes: vec![],
+            changed_ids: vec![],
+            children_changed_from: None,
+        }
+    }
+
+    fn mark_subtree(&mut self) {
+        self.dirty = true;
+        for child in &mut self.children {
+            child.mark_subtree();
+        }
+    }
+}
+
+impl StyledDocument {
+    pub fn new(root: Node) -> StyledDocument {
+        let styles = NodeStyle::new(&root);
+        StyledDocument {root, styles, restyle_all: true, changed_rules: vec![]}
+    }
+
+    pub fn root(&self) -> &Node {
+        &self.root
+    }
+
+    // The node at `path`, a list of child indices starting from the root.
+    fn node_at(&mut self, path: &[usize]) -> (&mut Node, &mut NodeStyle) {
+        path.iter().fold((&mut self.root, &mut self.styles), |(node, styles), &index| {
+            (&mut node.children[index], &mut styles.children[index])
+        })
+    }
+
+    pub fn set_attribute(&mut self, path: &[usize], name: &str, value: &str) {
+        self.change_attribute(path, name, Some(value));
+    }
+
+    pub fn remove_attribute(&mut self, path: &[usize], name: &str) {
+        self.change_attribute(path, name, None);
+    }
+
+    fn change_attribute(&mut self, path: &[usize], name: &str, value: Option<&str>) {
+        let (node, styles) = self.node_at(path);
+        let elem = match &mut node.node_type {
+            NodeType::Element(elem) => elem,
+            _ => return,
+        };
+        let old = match value {
+            Some(value) => elem.attributes.insert(name.to_string(), value.to_string()),
+            None => elem.attributes.remove(name),
+        };
+        let (old, new) = (old.unwrap_or_default(), value.unwrap_or_default());
+
+        //there are no attribute selectors, so only classes and ids can change what matches,
+        //and presentational hints the element's own style
+        match name {
+            "class" => {
+                let old: HashSet<&str> = old.split(' ').filter(|class| !class.is_empty()).collect();
+                let new: HashSet<&str> = new.split(' ').filter(|class| !class.is_empty()).collect();
+                styles.changed_classes.extend(old.symmetric_difference(&new).map(|class| class.to_string()));
+            },
+            "id" if old != new => {
+                styles.changed_ids.extend(vec![old, new.to_string()].into_iter().filter(|id| !id.is_empty()));
+            },
+            _ if PRESENTATIONAL_HINTS.iter().any(|(attribute, ..)| *attribute == name)
+                || DIMENSION_HINTS.iter().any(|(attribute, ..)| *attribute == name) => styles.dirty = true,
+            _ if LIST_HINT_ATTRIBUTES.contains(&name) => {
+                styles.dirty = true;
+                //the items of an ol are numbered from its start and in its direction
+                if elem.tag_name == "ol" && (name == "start" || name == "reversed") {
+                    styles.children.iter_mut().for_each(|child| child.dirty = true);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    pub fn insert_child(&mut self, path: &[usize], index: usize, child: Node) {
+        let (node, styles) = self.node_at(path);
+        styles.children.insert(index, NodeStyle::new(&child));
+        node.children.insert(index, child);
+        styles.children_changed_from = Some(styles.children_changed_from.map_or(index, |from| from.min(index)));
+    }
+
+    pub fn remove_child(&mut self, path: &[usize], index: usize) -> Node {
+        let (node, styles) = self.node_at(path);
+        styles.children.remove(index);
+        styles.children_changed_from = Some(styles.children_changed_from.map_or(index, |from| from.min(index)));
+        node.children.remove(index)
+    }
+
+    // Rules were added to or removed from the stylesheet, the cascade passed to the next
+    // restyle has to have them or not. Only the elements they match (or matched) are restyled.
+    pub fn rules_changed(&mut self, rules: &[&Rule]) {
+        for rule in rules {
+            for selector in &rule.selectors {
+                self.changed_rules.push(match selector {
+                    Selector::Simple(simple) => simple.clone(),
+                    Selector::Complex(complex) => complex.parts[complex.parts.len() - 1].clone(),
+                });
+            }
+        }
+    }
+
+    // Anything else about the cascade changed, like its environment, so everything is restyled.
+    pub fn stylesheet_changed(&mut self) {
+        self.restyle_all = true;
+    }
+
+    // Brings the styles up to date with the changes since the last restyle, returns how many
+    // elements went through selector matching again.
+    pub fn restyle(&mut self, cascade: &Cascade) -> usize {
+        if self.restyle_all {
+            self.styles.mark_subtree();
+            self.restyle_all = false;
+        }
+        invalidate(&self.root, &mut self.styles, cascade);
+
+        let mut levels = vec![Level::new(std::slice::from_ref(&self.root))];
+        let mut context = StyleContext {cascade, filter: AncestorFilter::new(), sharing: Some(Default::default())};
+        let restyled = restyle_subtree(&self.root, &mut self.styles, &mut levels, None, &self.changed_rules, &mut context);
+        self.changed_rules.clear();
+        restyled
+    }
+
+    pub fn style_tree(&self) -> StyledNode<'_> {
+        fn styled<'a>(node: &'a Node, styles: &NodeStyle) -> StyledNode<'a> {
+            StyledNode {
+                node,
+                pseudo_element: None,
+                specified_values: styles.style.clone(),
+                children: node.children.iter().zip(&styles.children).map(|(child, styles)| styled(child, styles)).collect(),
+                pseudo_elements: pseudo_element_nodes(node, &styles.pseudo_styles),
+            }
+        }
+        styled(&self.root, &self.styles)
+    }
+}
+
+// Turns the classes and ids that changed on the children of `node`, and children that were
+// inserted or removed, into dirty elements using the cascade's invalidation sets.
+fn invalidate(node: &Node, styles: &mut NodeStyle, cascade: &Cascade) {
+    let map = cascade.selector_map.invalidation_map();
+
+    //inserting or removing an element changes who the siblings after it are
+    if let Some(from) = styles.children_changed_from.take() {
+        if !cascade.selector_map.revalidation_selectors().is_empty() {
+            styles.children.iter_mut().skip(from).for_each(NodeStyle::mark_subtree);
+        }
+    }
+
+    for i in 0..node.children.len() {
+        let child = &node.children[i];
+        let changed_classes = std::mem::take(&mut styles.children[i].changed_classes);
+        let changed_ids = std::mem::take(&mut styles.children[i].changed_ids);
+        let sets = changed_classes.iter().filter_map(|class| map.class(class))
+            .chain(changed_ids.iter().filter_map(|id| map.id(id)));
+
+        for set in sets {
+            if set.invalidates_self {
+                styles.children[i].dirty = true;
+            }
+            if !set.descendants.is_empty() {
+                mark_descendants(child, &mut styles.children[i], &set.descendants);
+            }
+            if set.siblings {
+                for sibling in styles.children.iter_mut().skip(i + 1) {
+                    if set.sibling_subtrees {
+                        sibling.mark_subtree();
+                    } else {
+                        sibling.dirty = true;
+                    }
+                }
+            }
+        }
+
+        //an element that isn't like the ones it shares its style with any more gets a copy
+        //of its own, so the style sharing cache doesn't take them for alike
+        let child_styles = &mut styles.children[i];
+        if (!changed_classes.is_empty() || !changed_ids.is_empty()) && !child_styles.dirty {
+            child_styles.style = Arc::new((*child_styles.style).clone());
+        }
+        invalidate(child, child_styles, cascade);
+    }
+}
+
+fn mark_descendants(node: &Node, styles: &mut NodeStyle, features: &DescendantFeatures) {
+    for (child, child_styles) in node.children.iter().zip(&mut styles.children) {
+        if let NodeType::Element(elem) = &child.node_type {
+            if features.matches(elem, &elem.classes()) {
+                child_styles.dirty = true;
+            }
+        }
+        mark_descendants(child, child_styles, features);
+    }
+}
+
+// Like style_subtree, only elements that aren't dirty or matched by a changed rule keep
+// the style they have.
+fn restyle_subtree<'a>(node: &'a Node, styles: &mut NodeStyle, levels: &mut Vec<Level<'a>>, parent_style: Option<&Arc<PropertyMap>>,
+    changed_rules: &[SimpleSelector], context: &mut StyleContext<'a, '_, '_>) -> usize {
+    let mut restyled = 0;
+    let info = ElementInfo::new(node);
+    if info.is_some() {
+        let elem = ElementPath {levels, index: levels[levels.len() - 1].current};
+        if styles.dirty || changed_rules.iter().any(|compound| matches_simple_selector(elem, compound)) {
+            let cascade = context.cascade;
+            if let Some(sharing) = &mut context.sharing {
+                (styles.style, styles.pseudo_styles) = sharing.style_for(elem, parent_style, &context.filter, cascade);
+            }
+            restyled += 1;
+        }
+    }
+    styles.dirty = false;
+
+    if let Some(info) = &info {
+        context.filter.push(info.elem, &info.classes);
+    }
+    levels.push(Level::new(&node.children));
+    let parent_style = if info.is_some() { Some(&styles.style) } else { parent_style };
+    let mut element_index = 0;
+    for (child, child_styles) in node.children.iter().zip(&mut styles.children) {
+        if matches!(child.node_type, NodeType::Element(_)) {
+            levels.last_mut().unwrap().current = element_index;
+            element_index += 1;
+        }
+        restyled += restyle_subtree(child, child_styles, levels, parent_style, changed_rules, context);
+    }
+    levels.pop();
+    if let Some(info) = &info {
+        context.filter.pop(info.elem, &info.classes);
+    }
+    restyled
+}
+
+// The computed style of `node`, which has to be an element in the tree under `root`, serialized
+// the way window.getComputedStyle does: every property the engine knows, with inherited and
+// initial values filled in, lengths in px and colors as rgb()/rgba(). Pass a tree sampled from
+// an AnimationTimeline to get the animated values.
+pub fn computed_style(root: &StyledNode, node: &Node) -> Option<BTreeMap<String, String>> {
+    let mut path = vec![];
+    if !matches!(node.node_type, NodeType::Element(_)) || !find_path(root, node, &mut path) {
+        return None;
+    }
+
+    //inheritance means computing every element from the root down
+    let mut parent: Option<PropertyMap> = None;
+    let mut root_font_size = None;
+    for styled in path.into_iter().filter(|styled| matches!(styled.node.node_type, NodeType::Element(_))) {
+        let values = computed_values(styled, parent.as_ref(), root_font_size);
+        if root_font_size.is_none() {
+            root_font_size = values.get("font-size").and_then(px);
+        }
+        parent = Some(values);
+    }
+    Some(parent?.iter().map(|(name, value)| (name.clone(), serialize_computed(value))).collect())
+}
+
+fn find_path<'s, 'a>(styled: &'s StyledNode<'a>, node: &Node, path: &mut Vec<&'s StyledNode<'a>>) -> bool {
+    path.push(styled);
+    if std::ptr::eq(styled.node, node) || styled.children.iter().any(|child| find_path(child, node, path)) {
+        return true;
+    }
+    path.pop();
+    false
+}
+
+fn px(value: &Value) -> Option<f32> {
+    match value {
+        Value::Length(len, Unit::Px) => Some(*len),
+        _ => None,
+    }
+}
+
+// The computed values of one node from its parent's, which layout builds its boxes from.
+pub(crate) fn computed_values(styled: &StyledNode, parent: Option<&PropertyMap>, root_font_size: Option<f32>) -> PropertyMap {
+    let initial_font_size = properties::initial_value("font-size").as_ref().and_then(px).unwrap_or(16.0);
+    let parent_font_size = parent.and_then(|parent| parent.get("font-size")).and_then(px).unwrap_or(initial_font_size);
+    let root_font_size = root_font_size.unwrap_or(initial_font_size);
+
+    let cascaded = |name: &str| -> Option<Value> {
+        let specified = styled.specified_values.get(name);
+        let inherited = || parent.and_then(|parent| parent.get(name)).cloned();
+        let initial = || properties::initial_value(name);
+        let is_unset = |value: &Value| matches!(value, Value::Keyword(keyword) if matches!(&**keyword, "unset" | "revert" | "revert-layer"));
+        match specified {
+            Some(Value::Keyword(keyword)) if keyword == "inherit" => inherited().or_else(initial),
+            Some(Value::Keyword(keyword)) if keyword == "initial" => initial(),
+            Some(value) if !is_unset(value) => Some(value.clone()),
+            //unset, and the revert keywords since there are no user agent styles to revert to
+            _ if properties::is_inherited(name) => inherited().or_else(initial),
+            _ => initial(),
+        }
+    };
+
+    //font-size first, ems in every other property are relative to it
+    let mut computed = HashMap::new();
+    let font_size = cascaded("font-size")
+        .map(|value| absolutize("font-size", value, parent_font_size, root_font_size))
+        .unwrap_or(Value::Length(parent_font_size, Unit::Px));
+    let em = px(&font_size).unwrap_or(parent_font_size);
+    computed.insert("font-size".to_string(), font_size);
+
+    //then color, since currentcolor in other properties is its value
+    let parent_color = parent.and_then(|parent| parent.get("color")).cloned();
+    let mut color = cascaded("color").map(|value| absolutize("color", value, em, root_font_size));
+    if color.as_ref().is_some_and(is_current_color) {
+        color = parent_color.or_else(|| properties::initial_value("color"));
+    }
+    if let Some(color) = color.clone() {
+        computed.insert("color".to_string(), color);
+    }
+
+    //logical properties only count where they're set, they stand in for physical ones
+    let computes = |name: &&str| !properties::is_logical(name) || styled.specified_values.contains_key(*name);
+    for name in properties::names().filter(|name| *name != "font-size" && *name != "color").filter(computes) {
+        if let Some(value) = cascaded(name) {
+            let value = match absolutize(name, value, em, root_font_size) {
+                value if is_current_color(&value) => color.clone().unwrap_or(value),
+                value => value,
+            };
+            computed.insert(name.to_string(), value);
+        }
+    }
+
+    //font-weight keywords compute to numbers, bolder and lighter relative to the parent's weight
+    let parent_weight = match parent.and_then(|parent| parent.get("font-weight")) {
+        Some(Value::Length(weight, Unit::None)) => *weight,
+        _ => 400.0,
+    };
+    if let Some(weight) = computed.get_mut("font-weight") {
+        *weight = Value::Length(font_weight(weight, parent_weight), Unit::None);
+    }
+
+    //a box that scrolls along one axis can't let its content overflow along the other, so
+    //visible computes to auto and clip to hidden, CSS Overflow 3 §3.1
+    let scrolls = |name: &str| matches!(computed.get(name), Some(Value::Keyword(keyword)) if matches!(&**keyword, "hidden" | "scroll" | "auto"));
+    if scrolls("overflow-x") || scrolls("overflow-y") {
+        for name in ["overflow-x", "overflow-y"] {
+            if let Some(Value::Keyword(keyword)) = computed.get_mut(name) {
+                match &**keyword {
+                    "visible" => *keyword = "auto".to_string(),
+                    "clip" => *keyword = "hidden".to_string(),
+                    _ => {},
+                }
+            }
+        }
+    }
+
+    //then the logical properties become the physical ones for the element's writing mode and
+    //direction. The specified values don't keep the order of the declarations, so a logical
+    //property wins over a physical one set on the same element wherever it was declared.
+    let keyword = |name: &str| match computed.get(name) {
+        Some(Value::Keyword(keyword)) => keyword.clone(),
+        _ => String::new(),
+    };
+    let (writing_mode, direction) = (keyword("writing-mode"), keyword("direction"));
+    for name in properties::names().filter(|name| properties::is_logical(name)) {
+        if let Some(value) = computed.remove(name) {
+            computed.extend(properties::physical_property(name, &writing_mode, &direction).map(|physical| (physical, value)));
+        }
+    }
+
+    //a side without a border style has no border, whatever its width, CSS Backgrounds 3 §3.3
+    for side in ["top", "right", "bottom", "left"] {
+        let style = computed.get(&format!("border-{}-style", side));
+        if matches!(style, Some(Value::Keyword(style)) if style.eq_ignore_ascii_case("none") || style.eq_ignore_ascii_case("hidden")) {
+            computed.insert(format!("border-{}-width", side), Value::Length(0.0, Unit::Px));
+        }
+    }
+    computed
+}
+
+// The numeric weight of a font-weight value, CSS Fonts 4 §2.2.1.
+fn font_weight(value: &Value, parent_weight: f32) -> f32 {
+    match value {
+        Value::Length(weight, Unit::None) => *weight,
+        Value::Keyword(keyword) => match &*keyword.to_ascii_lowercase() {
+            "bold" => 700.0,
+            "bolder" if parent_weight < 350.0 => 400.0,
+            "bolder" if parent_weight < 550.0 => 700.0,
+            "bolder" => parent_weight.max(900.0),
+            "lighter" if parent_weight < 100.0 => parent_weight,
+            "lighter" if parent_weight < 550.0 => 100.0,
+            "lighter" if parent_weight < 750.0 => 400.0,
+            "lighter" => 700.0,
+            _ => 400.0,
         },
-        children: root.children.iter().map(|node| style_tree(node, stylesheet)).collect()
+        _ => 400.0,
     }
-}
\ No newline at end of file
+}
+
+fn is_current_color(value: &Value) -> bool {
+    matches!(value, Value::Keyword(keyword) if keyword.eq_ignore_ascii_case("currentcolor"))
+}
+
+// Resolves relative lengths to px and color keywords to colors.
+fn absolutize(name: &str, value: Value, em: f32, rem: f32) -> Value {
+    match value {
+        Value::Length(len, Unit::Em) => Value::Length(len * em, Unit::Px),
+        Value::Length(len, Unit::Rem) => Value::Length(len * rem, Unit::Px),
+        //inherits as a length, so children with another font size get the same line height
+        Value::Percentage(percentage) if name == "line-height" => Value::Length(percentage / 100.0 * em, Unit::Px),
+        Value::Length(len, Unit::None) if len == 0.0 && !properties::takes_numbers(name) => Value::Length(0.0, Unit::Px),
+        Value::Keyword(keyword) if properties::takes_colors(name) => match Color::from_name(&keyword) {
+            Some(color) => Value::Color(color),
+            None => Value::Keyword(keyword),
+        },
+        Value::Function(function, args) => {
+            Value::Function(function, args.into_iter().map(|arg| absolutize(name, arg, em, rem)).collect())
+        },
+        Value::List(values) => Value::List(values.into_iter().map(|value| absolutize(name, value, em, rem)).collect()),
+        Value::CommaList(values) => Value::CommaList(values.into_iter().map(|value| absolutize(name, value, em, rem)).collect()),
+        value => value,
+    }
+}
+
+fn serialize_computed(value: &Value) -> String {
+    let join = |values: &[Value], separator: &str| values.iter().map(serialize_computed).collect::<Vec<String>>().join(separator);
+    match value {
+        Value::Length(len, Unit::None) => format!("{}", len),
+        Value::Length(len, unit) => format!("{}{}", len, unit),
+        Value::Time(ms) => format!("{}s", ms / 1000.0),
+        Value::Color(color) if color.a == 255 => format!("rgb({}, {}, {})", color.r, color.g, color.b),
+        Value::Color(color) => {
+            format!("rgba({}, {}, {}, {})", color.r, color.g, color.b, (color.a as f32 / 255.0 * 1000.0).round() / 1000.0)
+        },
+        Value::Function(name, args) => format!("{}({})", name, join(args, ", ")),
+        Value::List(values) => join(values, " "),
+        Value::CommaList(values) => join(values, ", "),
+        value => format!("{}", value),
+    }
+}
+
+// Where a declaration comes from. There are no user agent or user stylesheets yet, so every
+// declaration is an author one.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Origin {
+    Author,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Author => write!(f, "author"),
+        }
+    }
+}
+
+// A declaration that applies to an element, along with everything the cascade looked at to
+// decide whether it wins.
+pub struct CascadeCandidate<'a> {
+    pub declaration: &'a Declaration,
+    // the most specific selector of the rule that matches the element
+    pub selector: &'a Selector,
+    pub origin: Origin,
+    // dotted name of the cascade layer, None if the declaration isn't in one
+    pub layer: Option<String>,
+    pub specificity: Specificity,
+    // url of the stylesheet the declaration is in
+    pub stylesheet: &'a str,
+    pub won: bool,
+}
+
+impl fmt::Display for CascadeCandidate<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let declaration = self.declaration;
+        write!(f, "{} {}{}  {}  {}:{}:{}  {}",
+            if self.won { "\u{2713}" } else { "\u{2717}" },
+            serialize_computed(&declaration.value),
+            if declaration.important { " !important" } else { "" },
+            self.selector.css_text(),
+            self.stylesheet, declaration.location.line, declaration.location.column,
+            self.origin)?;
+        if let Some(layer) = &self.layer {
+            write!(f, ", layer {}", layer)?;
+        }
+        let (ids, classes, tags) = self.specificity;
+        write!(f, ", specificity ({},{},{})", ids, classes, tags)
+    }
+}
+
+// Every declaration of `property` that applies to `node`, an element in the tree under `root`,
+// from the one that wins down to the one most overridden. Empty if no rule sets the property,
+// in which case it is inherited or has its initial value.
+pub fn explain_property<'a>(root: &Node, node: &Node, property: &str, cascade: &Cascade<'a>) -> Option<Vec<CascadeCandidate<'a>>> {
+    let mut candidates = explain_cascade(root, node, cascade)?;
+    Some(candidates.remove(property).unwrap_or_default())
+}
+
+// The candidates for every property some rule sets on `node`, see explain_property.
+pub fn explain_cascade<'a>(root: &Node, node: &Node, cascade: &Cascade<'a>) -> Option<BTreeMap<String, Vec<CascadeCandidate<'a>>>> {
+    let mut levels = vec![Level::new(std::slice::from_ref(root))];
+    if !matches!(node.node_type, NodeType::Element(_)) || !find_levels(root, node, &mut levels) {
+        return None;
+    }
+    let elem = ElementPath {levels: &levels, index: levels[levels.len() - 1].current};
+
+    //the same order specified_values applies declarations in: normal before important, layers
+    //ascending for normal and descending for important, then specificity, then source order
+    let mut candidates = vec![];
+    for (index, rule) in cascade.rules.iter().enumerate() {
+        let selector = match rule.rule.selectors.iter().filter(|sel| matches(elem, sel)).max_by_key(|sel| sel.specificity()) {
+            Some(selector) => selector,
+            None => continue,
+        };
+        for (position, declaration) in rule.rule.declarations.iter().enumerate() {
+            let layer = if declaration.important { usize::MAX - rule.layer } else { rule.layer };
+            let precedence = (declaration.important, layer, selector.specificity(), index, position);
+            candidates.push((precedence, CascadeCandidate {
+                declaration,
+                selector,
+                origin: Origin::Author,
+                layer: cascade.layer_names[rule.layer].clone(),
+                specificity: selector.specificity(),
+                stylesheet: rule.stylesheet,
+                won: false,
+            }));
+        }
+    }
+    candidates.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    let mut properties: BTreeMap<String, Vec<CascadeCandidate>> = BTreeMap::new();
+    for (_, mut candidate) in candidates {
+        let property = properties.entry(candidate.declaration.name.clone()).or_default();
+        candidate.won = property.is_empty();
+        property.push(candidate);
+    }
+    Some(properties)
+}
+
+// Pushes a level for each element from `node` down to `target`, with `current` pointing the way.
+fn find_levels<'a>(node: &'a Node, target: &Node, levels: &mut Vec<Level<'a>>) -> bool {
+    if std::ptr::eq(node, target) {
+        return true;
+    }
+    let mut level = Level::new(&node.children);
+    let elements = node.children.iter().filter(|child| matches!(child.node_type, NodeType::Element(_)));
+    for (index, child) in elements.enumerate() {
+        level.current = index;
+        levels.push(level.clone());
+        if find_levels(child, target, levels) {
+            return true;
+        }
+        levels.pop();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::css_parser;
+    use crate::html_parser;
+
+    #[test]
+    fn invalid_declaration_doesnt_win_the_cascade() {
+        let root = html_parser::parse("<p class=\"x\">text</p>".to_string());
+        let stylesheet = css_parser::parse("p { color: red } p.x { color: 12px }".to_string());
+        let cascade = Cascade::new(&stylesheet, MediaEnvironment::default());
+        let styled = style_tree(&root, &cascade);
+
+        let computed = computed_values(&styled, None, None);
+        assert_eq!(computed.get("color"), Some(&Value::Color(Color {r: 255, g: 0, b: 0, a: 255})));
+    }
+
+    #[test]
+    fn border_without_a_style_has_no_width() {
+        let root = html_parser::parse("<div></div>".to_string());
+        let border_widths = |css: &str| {
+            let stylesheet = css_parser::parse(css.to_string());
+            let cascade = Cascade::new(&stylesheet, MediaEnvironment::default());
+            let computed = computed_values(&style_tree(&root, &cascade), None, None);
+            ["top", "right", "bottom", "left"].map(|side| computed[&format!("border-{}-width", side)].clone())
+        };
+        let px = |len| Value::Length(len, Unit::Px);
+
+        assert_eq!(border_widths("div { border-width: 2px 3px 4px 5px }"), [px(0.0), px(0.0), px(0.0), px(0.0)]);
+        assert_eq!(border_widths("div { border-width: 2px 3px 4px 5px; border-style: solid none hidden }"), [px(2.0), px(0.0), px(0.0), px(0.0)]);
+        //the logical longhands count too, the inline end is on the right here
+        assert_eq!(border_widths("div { border-width: 2px 3px 4px 5px; border-style: solid none hidden; border-inline-end-style: dotted }"),
+            [px(2.0), px(3.0), px(0.0), px(0.0)]);
+        assert_eq!(border_widths("div { border-width: 2px; border-left-style: DASHED }"), [px(0.0), px(0.0), px(0.0), px(2.0)]);
+    }
+
+    #[test]
+    fn dimension_attributes_restyle_the_element() {
+        let mut document = StyledDocument::new(html_parser::parse("<div><table width=\"100\"></table><p></p><img></div>".to_string()));
+        let stylesheet = css_parser::parse("p { color: red }".to_string());
+        let cascade = Cascade::new(&stylesheet, MediaEnvironment::default());
+        assert_eq!(document.restyle(&cascade), 4);
+
+        document.set_attribute(&[0], "width", "120");
+        assert_eq!(document.restyle(&cascade), 1);
+        assert_eq!(document.style_tree().children[0].specified_values.get("width"), Some(&Value::Length(120.0, Unit::Px)));
+
+        document.remove_attribute(&[0], "width");
+        document.set_attribute(&[0], "cellspacing", "4");
+        assert_eq!(document.restyle(&cascade), 1);
+        let table = &document.style_tree().children[0].specified_values;
+        assert_eq!((table.get("width"), table.get("border-spacing")), (None, Some(&Value::Length(4.0, Unit::Px))));
+
+        document.set_attribute(&[2], "width", "50%");
+        document.set_attribute(&[2], "height", "30");
+        assert_eq!(document.restyle(&cascade), 1);
+        let img = &document.style_tree().children[2].specified_values;
+        assert_eq!((img.get("width"), img.get("height")), (Some(&Value::Percentage(50.0)), Some(&Value::Length(30.0, Unit::Px))));
+    }
+
+    #[test]
+    fn list_attributes_restyle_the_list() {
+        let mut document = StyledDocument::new(html_parser::parse("<div><ol><li></li><li></li></ol><p></p></div>".to_string()));
+        let stylesheet = css_parser::parse("p { color: red }".to_string());
+        let cascade = Cascade::new(&stylesheet, MediaEnvironment::default());
+        assert_eq!(document.restyle(&cascade), 5);
+        let keyword = |keyword: &str| Value::Keyword(keyword.to_string());
+        let counter = |name: Value, value: f32| Value::List(vec![name, Value::Length(value, Unit::None)]);
+
+        document.set_attribute(&[0], "type", "I");
+        assert_eq!(document.restyle(&cascade), 1);
+        assert_eq!(document.style_tree().children[0].specified_values.get("list-style-type"), Some(&keyword("upper-roman")));
+
+        //the list and its items
+        document.set_attribute(&[0], "start", "5");
+        assert_eq!(document.restyle(&cascade), 3);
+        assert_eq!(document.style_tree().children[0].specified_values.get("counter-reset"), Some(&counter(keyword("list-item"), 4.0)));
+
+        document.set_attribute(&[0], "reversed", "");
+        assert_eq!(document.restyle(&cascade), 3);
+        let reversed = Value::Function("reversed".to_string(), vec![keyword("list-item")]);
+        assert_eq!(document.style_tree().children[0].specified_values.get("counter-reset"), Some(&counter(reversed, 6.0)));
+
+        document.set_attribute(&[0, 1], "value", "9");
+        assert_eq!(document.restyle(&cascade), 1);
+        assert_eq!(document.style_tree().children[0].children[1].specified_values.get("counter-set"), Some(&counter(keyword("list-item"), 9.0)));
+    }
+
+    // A section with two paragraphs, the second with a span in it, followed by two divs.
+    const RESTYLED: &str = "<div><section><p></p><p><span></span></p></section><div></div><div></div></div>";
+
+    // The document of RESTYLED styled with `stylesheet`, and the cascade to restyle it with.
+    fn restyled(stylesheet: &Stylesheet) -> (StyledDocument, Cascade<'_>) {
+        let mut document = StyledDocument::new(html_parser::parse(RESTYLED.to_string()));
+        let cascade = Cascade::new(stylesheet, MediaEnvironment::default());
+        assert_eq!(document.restyle(&cascade), 7);
+        (document, cascade)
+    }
+
+    // The width of the element at `path`, in px if a rule sets it.
+    fn width_at(document: &StyledDocument, path: &[usize]) -> Option<f32> {
+        let tree = document.style_tree();
+        let node = path.iter().fold(&tree, |node, &index| &node.children[index]);
+        match node.specified_values.get("width") {
+            Some(Value::Length(width, Unit::Px)) => Some(*width),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn class_and_id_changes_restyle_what_they_can_match() {
+        let stylesheet = css_parser::parse("
+            .self { width: 1px } .x span { width: 2px } .y > p { width: 3px } #z + div { width: 4px } .w ~ div { width: 5px }
+            ".to_string());
+        let (mut document, cascade) = restyled(&stylesheet);
+
+        document.set_attribute(&[1], "class", "self");
+        assert_eq!(document.restyle(&cascade), 1);
+        assert_eq!(width_at(&document, &[1]), Some(1.0));
+
+        //only the descendants a selector can end at
+        document.set_attribute(&[0], "class", "x");
+        assert_eq!(document.restyle(&cascade), 1);
+        assert_eq!(width_at(&document, &[0, 1, 0]), Some(2.0));
+        document.set_attribute(&[0], "class", "x y");
+        assert_eq!(document.restyle(&cascade), 2);
+        assert_eq!((width_at(&document, &[0, 0]), width_at(&document, &[0, 1])), (Some(3.0), Some(3.0)));
+        document.remove_attribute(&[0], "class");
+        assert_eq!(document.restyle(&cascade), 3);
+        assert_eq!((width_at(&document, &[0, 0]), width_at(&document, &[0, 1, 0])), (None, None));
+
+        //the siblings after the element
+        document.set_attribute(&[1], "id", "z");
+        assert_eq!(document.restyle(&cascade), 1);
+        assert_eq!(width_at(&document, &[2]), Some(4.0));
+        document.set_attribute(&[0], "class", "w");
+        assert_eq!(document.restyle(&cascade), 2);
+        //#z + div is the more specific of the two
+        assert_eq!((width_at(&document, &[1]), width_at(&document, &[2])), (Some(5.0), Some(4.0)));
+
+        //nothing mentions the class
+        document.set_attribute(&[0, 0], "class", "unused");
+        assert_eq!(document.restyle(&cascade), 0);
+    }
+
+    #[test]
+    fn pseudo_class_arguments_restyle_what_they_can_match() {
+        let stylesheet = css_parser::parse("p:not(.x) { width: 1px } :is(.y > p) span { width: 2px }".to_string());
+        let (mut document, cascade) = restyled(&stylesheet);
+        assert_eq!(width_at(&document, &[0, 0]), Some(1.0));
+
+        //the argument of :not is about the element itself
+        document.set_attribute(&[0, 0], "class", "x");
+        assert_eq!(document.restyle(&cascade), 1);
+        assert_eq!(width_at(&document, &[0, 0]), None);
+
+        //an element inside the argument of a compound that isn't the subject could be
+        //anywhere, so the element, everything below it and its later siblings are restyled
+        document.set_attribute(&[0], "class", "y");
+        assert_eq!(document.restyle(&cascade), 6);
+        assert_eq!(width_at(&document, &[0, 1, 0]), Some(2.0));
+    }
+
+    #[test]
+    fn inserting_and_removing_children_restyles_them() {
+        use crate::dom;
+
+        let paragraph = || dom::elem("p".to_string(), dom::AttrMap::new(), vec![dom::text("new".to_string())]);
+        let stylesheet = css_parser::parse("p { width: 1px }".to_string());
+        let (mut document, cascade) = restyled(&stylesheet);
+        document.insert_child(&[0], 1, paragraph());
+        assert_eq!(document.restyle(&cascade), 1);
+        assert_eq!(width_at(&document, &[0, 1]), Some(1.0));
+        document.remove_child(&[0], 1);
+        assert_eq!(document.restyle(&cascade), 0);
+
+        //with sibling selectors, the children after it are restyled as well
+        let stylesheet = css_parser::parse("p + p { width: 2px }".to_string());
+        let (mut document, cascade) = restyled(&stylesheet);
+        assert_eq!(width_at(&document, &[0, 1]), Some(2.0));
+        document.insert_child(&[0], 0, paragraph());
+        assert_eq!(document.restyle(&cascade), 4);
+        assert_eq!((width_at(&document, &[0, 0]), width_at(&document, &[0, 1]), width_at(&document, &[0, 2])), (None, Some(2.0), Some(2.0)));
+        document.remove_child(&[0], 1);
+        assert_eq!(document.restyle(&cascade), 2);
+        assert_eq!((width_at(&document, &[0, 0]), width_at(&document, &[0, 1])), (None, Some(2.0)));
+    }
+
+    #[test]
+    fn changed_rules_restyle_the_elements_they_match() {
+        let stylesheet = css_parser::parse("p { width: 1px }".to_string());
+        let (mut document, _) = restyled(&stylesheet);
+
+        let stylesheet = css_parser::parse("p { width: 1px } section span { width: 2px }".to_string());
+        let cascade = Cascade::new(&stylesheet, MediaEnvironment::default());
+        let added = match &stylesheet.rules[1] {
+            CssRule::Style(rule) => rule,
+            _ => unreachable!(),
+        };
+        document.rules_changed(&[added]);
+        assert_eq!(document.restyle(&cascade), 1);
+        assert_eq!((width_at(&document, &[0, 0]), width_at(&document, &[0, 1, 0])), (Some(1.0), Some(2.0)));
+
+        let stylesheet = css_parser::parse("p { width: 3px } section span { width: 2px }".to_string());
+        let cascade = Cascade::new(&stylesheet, MediaEnvironment::default());
+        document.stylesheet_changed();
+        assert_eq!(document.restyle(&cascade), 7);
+        assert_eq!((width_at(&document, &[0, 0]), width_at(&document, &[0, 1])), (Some(3.0), Some(3.0)));
+        assert_eq!(document.restyle(&cascade), 0);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_styles_match_the_serial_ones() {
+        use crate::dom;
+
+        //deeper than the parallel traversal goes, with classes that repeat so styles get
+        //shared, and text between the elements
+        fn build(next: &mut usize, depth: usize) -> Node {
+            let i = *next;
+            *next += 1;
+            let mut attributes = dom::AttrMap::new();
+            attributes.insert("class".to_string(), format!("c{} c{}", i % 7, i % 11));
+            if i.is_multiple_of(13) {
+                attributes.insert("id".to_string(), format!("id{}", i));
+            }
+            let children = match depth {
+                9 => vec![dom::text(format!("text {}", i))],
+                _ => (0..(i % 3 + 1)).flat_map(|_| vec![build(next, depth + 1), dom::text(" ".to_string())]).collect(),
+            };
+            dom::elem(["div", "p", "span", "section"][i % 4].to_string(), attributes, children)
+        }
+        let root = build(&mut 0, 0);
+
+        let stylesheet = css_parser::parse("
+            .c1 { width: 1px } #id13 { width: 2px } div.c2 { height: 3px } .c3 span { color: red }
+            .c4 > .c5 { color: blue } section .c6 p.c0 { margin-top: 4px } .c7 + .c8 { padding-top: 5px }
+            .c9 ~ span { padding-left: 6px } :not(.c10) > .c1 { margin-left: 7px } :is(p, span).c2 .c3 { opacity: 0.5 }
+            div { display: block } span::before { content: \"x\" } .c5:not(#id26) { font-size: 20px }
+            ".to_string());
+        let cascade = Cascade::new(&stylesheet, MediaEnvironment::default());
+        assert_eq!(style_tree(&root, &cascade).to_string(), style_tree_linear(&root, &cascade).to_string());
+    }
+
+    // How deep `shared` puts its elements, below where a parallel traversal splits children
+    // into runs that have caches of their own.
+    const SHARING_DEPTH: usize = 8;
+
+    // Whether the elements at paths `a` and `b` of `html` styled by `css` got the very same
+    // specified values from the style sharing cache.
+    fn shared(html: &str, css: &str, a: &[usize], b: &[usize]) -> bool {
+        let html = format!("{}{}{}", "<div>".repeat(SHARING_DEPTH), html, "</div>".repeat(SHARING_DEPTH));
+        let root = html_parser::parse(html);
+        let stylesheet = css_parser::parse(css.to_string());
+        let cascade = Cascade::new(&stylesheet, MediaEnvironment::default());
+        let styled = style_tree(&root, &cascade);
+        //sharing or not, they are what matching every rule gives
+        assert_eq!(styled.to_string(), style_tree_linear(&root, &cascade).to_string());
+        let values = |path: &[usize]| {
+            let node = std::iter::repeat_n(&0, SHARING_DEPTH).chain(path).fold(&styled, |node, &index| &node.children[index]);
+            node.specified_values.clone()
+        };
+        Arc::ptr_eq(&values(a), &values(b))
+    }
+
+    #[test]
+    fn alike_siblings_share_their_style() {
+        assert!(shared("<div><p class=\"a b\"></p><p class=\"b a\"></p></div>", ".a { color: red }", &[0], &[1]));
+        //cousins too, when their parents share
+        assert!(shared("<div><div><p></p></div><div><p></p></div></div>", "p { color: red }", &[0, 0], &[1, 0]));
+        //but not with an id
+        assert!(!shared("<div><p id=x></p><p id=y></p></div>", "p { color: red }", &[0], &[1]));
+    }
+
+    #[test]
+    fn presentational_hints_keep_styles_apart() {
+        assert!(!shared("<div><p align=center></p><p></p></div>", "", &[0], &[1]));
+        assert!(!shared("<div><p align=center></p><p align=right></p></div>", "", &[0], &[1]));
+        assert!(shared("<div><p align=center></p><p align=center></p></div>", "", &[0], &[1]));
+    }
+
+    #[test]
+    fn sibling_selectors_keep_styles_apart() {
+        let css = "p + p { color: red }";
+        assert!(!shared("<div><p></p><p></p><p></p></div>", css, &[0], &[1]));
+        assert!(shared("<div><p></p><p></p><p></p></div>", css, &[1], &[2]));
+        assert!(!shared("<div><p></p><p></p><p></p></div>", ":is(p + p) { color: red }", &[0], &[1]));
+    }
+
+    #[test]
+    fn different_parents_keep_styles_apart() {
+        //the cousins match the same rules, but what they inherit differs
+        assert!(!shared("<div><div class=a><p></p></div><div><p></p></div></div>", ".a { color: red }", &[0, 0], &[1, 0]));
+    }
+}